@@ -0,0 +1,264 @@
+//! Timeout and retry policy shared by the backend API clients.
+
+use std::time::Duration;
+
+/// Delay before the first retry, doubled before each further retry.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Longest delay between retries.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How a client sends its HTTP requests.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// How long to wait for a response.
+    pub timeout: Option<Duration>,
+    /// How often to retry requests that failed to connect, or idempotent requests that got a
+    /// server error.
+    pub retries: u32,
+    /// Delay before the first retry.
+    backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            retries: 0,
+            backoff: INITIAL_BACKOFF,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Sends `request`, retrying it with exponential backoff after transient failures.
+    ///
+    /// Requests that failed to connect never reached the backend, so they're always retried.
+    /// Server errors are only retried for idempotent methods, since a `POST` may already have
+    /// been processed. Requests that time out aren't retried, since the backend may still be
+    /// working on them.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request to send.
+    ///
+    /// # Returns
+    ///
+    /// The first response that isn't retried, or the error of the last attempt.
+    pub async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> reqwest::Result<reqwest::Response> {
+        let request = match self.timeout {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        };
+        let (client, request) = request.build_split();
+        let request = request?;
+        let idempotent = request.method().is_idempotent();
+        let mut backoff = self.backoff;
+        for _ in 0..self.retries {
+            // Requests with streaming bodies, like multipart uploads, can't be cloned, so
+            // they're only sent once.
+            let Some(attempt) = request.try_clone() else {
+                break;
+            };
+            match client.execute(attempt).await {
+                Ok(response) if !(idempotent && response.status().is_server_error()) => {
+                    return Ok(response)
+                }
+                Err(e) if !e.is_connect() => return Err(e),
+                _ => {}
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = next_backoff(backoff);
+        }
+        client.execute(request).await
+    }
+}
+
+/// Returns the delay before the retry after one that waited `backoff`.
+fn next_backoff(backoff: Duration) -> Duration {
+    (backoff * 2).min(MAX_BACKOFF)
+}
+
+/// Adds `with_timeout` and `with_retries` builder methods to a client, setting the
+/// [`RetryPolicy`] at the given field path.
+#[macro_export]
+macro_rules! retry_builders {
+    ($($field:ident).+) => {
+        /// Sets how long the client waits for a response before giving up.
+        ///
+        /// # Arguments
+        ///
+        /// * `timeout` - The time to wait, or `None` to wait indefinitely.
+        pub fn with_timeout(mut self, timeout: Option<std::time::Duration>) -> Self {
+            self.$($field).+.timeout = timeout;
+            self
+        }
+
+        /// Sets how often the client retries requests that failed to connect, or idempotent
+        /// requests that got a server error, waiting longer before each retry.
+        ///
+        /// # Arguments
+        ///
+        /// * `retries` - The number of retries, or 0 to not retry.
+        pub fn with_retries(mut self, retries: u32) -> Self {
+            self.$($field).+.retries = retries;
+            self
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    fn policy(retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            timeout: Some(Duration::from_secs(5)),
+            retries,
+            backoff: Duration::from_millis(10),
+        }
+    }
+
+    /// Serves requests on `listener`, answering with the status returned by `status` for the
+    /// number of requests seen so far, or not at all if it returns `None`. Returns the count.
+    fn serve(
+        listener: TcpListener,
+        status: impl Fn(usize) -> Option<u16> + Send + 'static,
+    ) -> Arc<AtomicUsize> {
+        let count = Arc::new(AtomicUsize::new(0));
+        let requests = count.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let n = requests.fetch_add(1, Ordering::SeqCst);
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let read = stream.read(&mut buf).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..read]);
+                }
+                let Some(status) = status(n) else {
+                    // Keep the connection open without answering.
+                    tokio::spawn(async move {
+                        tokio::time::sleep(Duration::from_secs(60)).await;
+                        drop(stream);
+                    });
+                    continue;
+                };
+                let response = format!(
+                    "HTTP/1.1 {status} Status\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        count
+    }
+
+    async fn listener() -> (TcpListener, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        (listener, url)
+    }
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut delays = vec![backoff];
+        for _ in 0..10 {
+            backoff = next_backoff(backoff);
+            delays.push(backoff);
+        }
+        assert_eq!(delays[1], Duration::from_secs(1));
+        assert_eq!(delays[6], Duration::from_secs(30));
+        assert!(delays.iter().all(|delay| *delay <= MAX_BACKOFF));
+        assert_eq!(next_backoff(MAX_BACKOFF), MAX_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn test_server_error_retried_for_idempotent_requests() {
+        let (listener, url) = listener().await;
+        let count = serve(listener, |n| Some(if n < 2 { 503 } else { 200 }));
+        let client = reqwest::Client::new();
+
+        let response = policy(3).send(client.get(&url)).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_server_error_returned_after_last_retry() {
+        let (listener, url) = listener().await;
+        let count = serve(listener, |_| Some(500));
+        let client = reqwest::Client::new();
+
+        let response = policy(2).send(client.get(&url)).await.unwrap();
+        assert_eq!(response.status(), 500);
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_server_error_not_retried_for_post() {
+        let (listener, url) = listener().await;
+        let count = serve(listener, |_| Some(500));
+        let client = reqwest::Client::new();
+
+        let response = policy(3).send(client.post(&url).body("{}")).await.unwrap();
+        assert_eq!(response.status(), 500);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_not_retried() {
+        let (listener, url) = listener().await;
+        let count = serve(listener, |_| None);
+        let client = reqwest::Client::new();
+        let policy = RetryPolicy {
+            timeout: Some(Duration::from_millis(100)),
+            ..policy(3)
+        };
+
+        let error = policy.send(client.get(&url)).await.unwrap_err();
+        assert!(error.is_timeout());
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_connect_failure_retried() {
+        // Reserve a port, then start listening on it only after the first attempts failed.
+        let (listener, url) = listener().await;
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let client = reqwest::Client::new();
+
+        let error = policy(1).send(client.post(&url)).await.unwrap_err();
+        assert!(error.is_connect());
+
+        let server = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            serve(TcpListener::bind(addr).await.unwrap(), |_| Some(200))
+        });
+        let policy = RetryPolicy {
+            backoff: Duration::from_millis(20),
+            ..policy(8)
+        };
+        let response = policy.send(client.post(&url).body("{}")).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(server.await.unwrap().load(Ordering::SeqCst), 1);
+    }
+}
@@ -1,7 +1,9 @@
-use reqwest::Url;
+use reqwest::{header::HeaderMap, Url};
 
 use crate::models::{History, Task};
 
+use backend_request::RetryPolicy;
+
 /// Errors that can occur when interacting with `HistoryApi`.
 #[derive(thiserror::Error, Debug)]
 #[non_exhaustive]
@@ -43,6 +45,8 @@ type Result<T> = std::result::Result<T, HistoryApiError>;
 pub struct HistoryApi {
     client: reqwest::Client,
     endpoint: Url,
+    headers: HeaderMap,
+    request_options: RetryPolicy,
 }
 
 impl HistoryApi {
@@ -75,7 +79,29 @@ impl HistoryApi {
     ///
     /// A new `HistoryApi` instance.
     pub fn new_with_url(client: reqwest::Client, endpoint: Url) -> Self {
-        Self { client, endpoint }
+        Self {
+            client,
+            endpoint,
+            headers: HeaderMap::new(),
+            request_options: RetryPolicy::default(),
+        }
+    }
+
+    /// Sets headers sent with every request, e.g. for authenticating with a proxy.
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - A `HeaderMap` of headers to send.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    backend_request::retry_builders!(request_options);
+
+    pub(crate) fn with_request_options(mut self, request_options: RetryPolicy) -> Self {
+        self.request_options = request_options;
+        self
     }
 
     /// Sends a history request using the HistoryApi client.
@@ -84,7 +110,11 @@ impl HistoryApi {
     ///
     /// A `Result` containing a `History` on success, or an error if the request failed.
     pub async fn get(&self) -> Result<History> {
-        let response = self.client.get(self.endpoint.clone()).send().await?;
+        let request = self
+            .client
+            .get(self.endpoint.clone())
+            .headers(self.headers.clone());
+        let response = self.request_options.send(request).await?;
         if response.status().is_success() {
             return response
                 .json()
@@ -112,11 +142,11 @@ impl HistoryApi {
     ///
     /// A `Result` containing a `Task` on success, or an error if the request failed.
     pub async fn get_prompt(&self, prompt_id: &uuid::Uuid) -> Result<Task> {
-        let response = self
+        let request = self
             .client
             .get(self.endpoint.clone().join(prompt_id.to_string().as_str())?)
-            .send()
-            .await?;
+            .headers(self.headers.clone());
+        let response = self.request_options.send(request).await?;
         if response.status().is_success() {
             let mut history: History = response
                 .json()
@@ -138,4 +168,43 @@ impl HistoryApi {
             error: text,
         })
     }
+
+    /// Sends a history request for the most recent tasks using the HistoryApi client.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_items` - The maximum number of tasks to return.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Vec<Task>` ordered from most to least recent on success, or an
+    /// error if the request failed.
+    pub async fn get_all(&self, max_items: u32) -> Result<Vec<Task>> {
+        let mut url = self.endpoint.clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty();
+        }
+        url.query_pairs_mut()
+            .append_pair("max_items", max_items.to_string().as_str());
+        let request = self.client.get(url).headers(self.headers.clone());
+        let response = self.request_options.send(request).await?;
+        if response.status().is_success() {
+            let history: History = response
+                .json()
+                .await
+                .map_err(HistoryApiError::InvalidResponse)?;
+            let mut tasks = history.tasks.into_values().collect::<Vec<_>>();
+            tasks.sort_by_key(|task| std::cmp::Reverse(task.prompt.num));
+            return Ok(tasks);
+        }
+        let status = response.status();
+        let text = response
+            .text()
+            .await
+            .map_err(HistoryApiError::GetDataFailed)?;
+        Err(HistoryApiError::GetHistoryFailed {
+            status,
+            error: text,
+        })
+    }
 }
@@ -0,0 +1,117 @@
+use reqwest::{header::HeaderMap, Url};
+
+use backend_request::RetryPolicy;
+
+/// Errors that can occur when interacting with `InterruptApi`.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum InterruptApiError {
+    /// Error parsing endpoint URL
+    #[error("Failed to parse endpoint URL")]
+    ParseError(#[from] url::ParseError),
+    /// Error sending request
+    #[error("Failed to send request")]
+    RequestFailed(#[from] reqwest::Error),
+    /// An error occurred getting response data.
+    #[error("Failed to get response data")]
+    GetDataFailed(#[source] reqwest::Error),
+    /// Server returned an error interrupting execution
+    #[error("Failed to interrupt execution: {status}: {error}")]
+    InterruptFailed {
+        status: reqwest::StatusCode,
+        error: String,
+    },
+}
+
+type Result<T> = std::result::Result<T, InterruptApiError>;
+
+/// Struct representing a connection to the ComfyUI API `interrupt` endpoint.
+#[derive(Clone, Debug)]
+pub struct InterruptApi {
+    client: reqwest::Client,
+    endpoint: Url,
+    headers: HeaderMap,
+    request_options: RetryPolicy,
+}
+
+impl InterruptApi {
+    /// Constructs a new `InterruptApi` client with a given `reqwest::Client` and ComfyUI API
+    /// endpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `str` representation of the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a new `InterruptApi` instance on success, or an error if url parsing failed.
+    pub fn new<S>(client: reqwest::Client, endpoint: S) -> Result<Self>
+    where
+        S: AsRef<str>,
+    {
+        Ok(Self::new_with_url(client, Url::parse(endpoint.as_ref())?))
+    }
+
+    /// Constructs a new `InterruptApi` client with a given `reqwest::Client` and endpoint `Url`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `Url` representing the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A new `InterruptApi` instance.
+    pub fn new_with_url(client: reqwest::Client, endpoint: Url) -> Self {
+        Self {
+            client,
+            endpoint,
+            headers: HeaderMap::new(),
+            request_options: RetryPolicy::default(),
+        }
+    }
+
+    /// Sets headers sent with every request, e.g. for authenticating with a proxy.
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - A `HeaderMap` of headers to send.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    backend_request::retry_builders!(request_options);
+
+    pub(crate) fn with_request_options(mut self, request_options: RetryPolicy) -> Self {
+        self.request_options = request_options;
+        self
+    }
+
+    /// Interrupts the prompt that's currently executing. Prompts waiting in the queue are
+    /// not affected.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `()` on success, or an error if the request failed.
+    pub async fn interrupt(&self) -> Result<()> {
+        let request = self
+            .client
+            .post(self.endpoint.clone())
+            .headers(self.headers.clone());
+        let response = self.request_options.send(request).await?;
+        if response.status().is_success() {
+            return Ok(());
+        }
+        let status = response.status();
+        let text = response
+            .text()
+            .await
+            .map_err(InterruptApiError::GetDataFailed)?;
+        Err(InterruptApiError::InterruptFailed {
+            status,
+            error: text,
+        })
+    }
+}
@@ -1,13 +1,27 @@
-use reqwest::Url;
+use std::time::Duration;
+
+use backend_request::RetryPolicy;
+use reqwest::{
+    header::{self, HeaderMap, HeaderValue},
+    Url,
+};
 
 pub mod history;
+pub mod interrupt;
+pub mod object_info;
 pub mod prompt;
+pub mod queue;
+pub mod system_stats;
 pub mod upload;
 pub mod view;
 pub mod websocket;
 
 pub use history::*;
+pub use interrupt::*;
+pub use object_info::*;
 pub use prompt::*;
+pub use queue::*;
+pub use system_stats::*;
 pub use upload::*;
 pub use view::*;
 pub use websocket::*;
@@ -37,6 +51,9 @@ pub enum ApiError {
     /// Error setting WebSocket scheme
     #[error("Failed to set scheme: ws://{url}")]
     SetWebSocketSchemeFailed { url: url::Url },
+    /// Error building an authentication header
+    #[error("Invalid header value")]
+    InvalidHeaderValue(#[from] header::InvalidHeaderValue),
 }
 
 type Result<T> = std::result::Result<T, ApiError>;
@@ -47,6 +64,8 @@ pub struct Api {
     client: reqwest::Client,
     url: Url,
     client_id: uuid::Uuid,
+    headers: HeaderMap,
+    request_options: RetryPolicy,
 }
 
 impl Default for Api {
@@ -62,6 +81,8 @@ impl Api {
             client: reqwest::Client::new(),
             url: Url::parse("http://localhost:8188")?,
             client_id: uuid::Uuid::new_v4(),
+            headers: HeaderMap::new(),
+            request_options: RetryPolicy::default(),
         })
     }
 
@@ -105,6 +126,84 @@ impl Api {
         })
     }
 
+    /// Sets headers sent with every HTTP request and websocket handshake, e.g. for ComfyUI
+    /// instances behind an authenticating proxy.
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - A `HeaderMap` of headers to send.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Sets a bearer token sent in the `Authorization` header.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The bearer token.
+    ///
+    /// # Errors
+    ///
+    /// If the token is not a valid header value, an error will be returned.
+    pub fn with_bearer_auth<S>(mut self, token: S) -> Result<Self>
+    where
+        S: AsRef<str>,
+    {
+        let mut value = HeaderValue::from_str(&format!("Bearer {}", token.as_ref()))?;
+        value.set_sensitive(true);
+        self.headers.insert(header::AUTHORIZATION, value);
+        Ok(self)
+    }
+
+    /// Sets cookies sent in the `Cookie` header.
+    ///
+    /// # Arguments
+    ///
+    /// * `cookie` - The cookies, e.g. `session=abc123`.
+    ///
+    /// # Errors
+    ///
+    /// If the cookie is not a valid header value, an error will be returned.
+    pub fn with_cookie<S>(mut self, cookie: S) -> Result<Self>
+    where
+        S: AsRef<str>,
+    {
+        let mut value = HeaderValue::from_str(cookie.as_ref())?;
+        value.set_sensitive(true);
+        self.headers.insert(header::COOKIE, value);
+        Ok(self)
+    }
+
+    /// Sets how long to wait for a response to any HTTP request before giving up. Requests wait
+    /// indefinitely by default, since large workflows can take a long time to queue.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The time to wait for each response.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.request_options.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how often HTTP requests that failed to connect, or idempotent requests that got a
+    /// server error, are retried. Queued prompts are `POST`s, so they're only retried if they
+    /// never reached ComfyUI. The delay before each retry doubles, starting at half a second.
+    /// Requests aren't retried by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `retries` - The number of retries after the first attempt.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.request_options.retries = retries;
+        self
+    }
+
+    /// Returns the headers sent with every request.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
     /// Returns a new instance of `PromptApi` with the API's cloned
     /// `reqwest::Client` and the URL for the `prompt` endpoint.
     ///
@@ -127,11 +226,11 @@ impl Api {
     ///
     /// If the URL fails to parse, an error will be returned.
     pub fn prompt_with_client(&self, client_id: uuid::Uuid) -> Result<PromptApi> {
-        Ok(PromptApi::new_with_url(
-            self.client.clone(),
-            self.url.join("prompt")?,
-            client_id,
-        ))
+        Ok(
+            PromptApi::new_with_url(self.client.clone(), self.url.join("prompt")?, client_id)
+                .with_headers(self.headers.clone())
+                .with_request_options(self.request_options.clone()),
+        )
     }
 
     /// Returns a new instance of `HistoryApi` with the API's cloned
@@ -141,10 +240,67 @@ impl Api {
     ///
     /// If the URL fails to parse, an error will be returned.
     pub fn history(&self) -> Result<HistoryApi> {
-        Ok(HistoryApi::new_with_url(
-            self.client.clone(),
-            self.url.join("history/")?,
-        ))
+        Ok(
+            HistoryApi::new_with_url(self.client.clone(), self.url.join("history/")?)
+                .with_headers(self.headers.clone())
+                .with_request_options(self.request_options.clone()),
+        )
+    }
+
+    /// Returns a new instance of `InterruptApi` with the API's cloned
+    /// `reqwest::Client` and the URL for the `interrupt` endpoint.
+    ///
+    /// # Errors
+    ///
+    /// If the URL fails to parse, an error will be returned.
+    pub fn interrupt(&self) -> Result<InterruptApi> {
+        Ok(
+            InterruptApi::new_with_url(self.client.clone(), self.url.join("interrupt")?)
+                .with_headers(self.headers.clone())
+                .with_request_options(self.request_options.clone()),
+        )
+    }
+
+    /// Returns a new instance of `QueueApi` with the API's cloned
+    /// `reqwest::Client` and the URL for the `queue` endpoint.
+    ///
+    /// # Errors
+    ///
+    /// If the URL fails to parse, an error will be returned.
+    pub fn queue(&self) -> Result<QueueApi> {
+        Ok(
+            QueueApi::new_with_url(self.client.clone(), self.url.join("queue")?)
+                .with_headers(self.headers.clone())
+                .with_request_options(self.request_options.clone()),
+        )
+    }
+
+    /// Returns a new instance of `ObjectInfoApi` with the API's cloned
+    /// `reqwest::Client` and the URL for the `object_info` endpoint.
+    ///
+    /// # Errors
+    ///
+    /// If the URL fails to parse, an error will be returned.
+    pub fn object_info(&self) -> Result<ObjectInfoApi> {
+        Ok(
+            ObjectInfoApi::new_with_url(self.client.clone(), self.url.join("object_info/")?)
+                .with_headers(self.headers.clone())
+                .with_request_options(self.request_options.clone()),
+        )
+    }
+
+    /// Returns a new instance of `SystemStatsApi` with the API's cloned
+    /// `reqwest::Client` and the URL for the `system_stats` endpoint.
+    ///
+    /// # Errors
+    ///
+    /// If the URL fails to parse, an error will be returned.
+    pub fn system_stats(&self) -> Result<SystemStatsApi> {
+        Ok(
+            SystemStatsApi::new_with_url(self.client.clone(), self.url.join("system_stats")?)
+                .with_headers(self.headers.clone())
+                .with_request_options(self.request_options.clone()),
+        )
     }
 
     /// Returns a new instance of `UploadApi` with the API's cloned
@@ -154,10 +310,11 @@ impl Api {
     ///
     /// If the URL fails to parse, an error will be returned.
     pub fn upload(&self) -> Result<UploadApi> {
-        Ok(UploadApi::new_with_url(
-            self.client.clone(),
-            self.url.join("upload/")?,
-        ))
+        Ok(
+            UploadApi::new_with_url(self.client.clone(), self.url.join("upload/")?)
+                .with_headers(self.headers.clone())
+                .with_request_options(self.request_options.clone()),
+        )
     }
 
     /// Returns a new instance of `ViewApi` with the API's cloned
@@ -167,10 +324,11 @@ impl Api {
     ///
     /// If the URL fails to parse, an error will be returned.
     pub fn view(&self) -> Result<ViewApi> {
-        Ok(ViewApi::new_with_url(
-            self.client.clone(),
-            self.url.join("view")?,
-        ))
+        Ok(
+            ViewApi::new_with_url(self.client.clone(), self.url.join("view")?)
+                .with_headers(self.headers.clone())
+                .with_request_options(self.request_options.clone()),
+        )
     }
 
     /// Returns a new instance of `WebsocketApi` with the API's cloned
@@ -205,6 +363,6 @@ impl Api {
         url.set_scheme("ws")
             .map_err(|_| ApiError::SetWebSocketSchemeFailed { url: url.clone() })?;
         url.set_query(Some(format!("clientId={}", client_id).as_str()));
-        Ok(WebsocketApi::new_with_url(url))
+        Ok(WebsocketApi::new_with_url(url).with_headers(self.headers.clone()))
     }
 }
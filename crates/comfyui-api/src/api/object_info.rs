@@ -0,0 +1,143 @@
+use reqwest::{header::HeaderMap, Url};
+
+use crate::models::ObjectInfo;
+
+use backend_request::RetryPolicy;
+
+/// Errors that can occur when interacting with `ObjectInfoApi`.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum ObjectInfoApiError {
+    /// Error parsing endpoint URL
+    #[error("Failed to parse endpoint URL")]
+    ParseError(#[from] url::ParseError),
+    /// Error sending request
+    #[error("Failed to send request")]
+    RequestFailed(#[from] reqwest::Error),
+    /// An error occurred while parsing the response from the API.
+    #[error("Parsing response failed")]
+    InvalidResponse(#[source] reqwest::Error),
+    /// An error occurred getting response data.
+    #[error("Failed to get response data")]
+    GetDataFailed(#[source] reqwest::Error),
+    /// Server returned an error getting object info
+    #[error("Failed to get object info: {status}: {error}")]
+    GetObjectInfoFailed {
+        status: reqwest::StatusCode,
+        error: String,
+    },
+}
+
+type Result<T> = std::result::Result<T, ObjectInfoApiError>;
+
+/// Struct representing a connection to the ComfyUI API `object_info` endpoint.
+#[derive(Clone, Debug)]
+pub struct ObjectInfoApi {
+    client: reqwest::Client,
+    endpoint: Url,
+    headers: HeaderMap,
+    request_options: RetryPolicy,
+}
+
+impl ObjectInfoApi {
+    /// Constructs a new `ObjectInfoApi` client with a given `reqwest::Client` and ComfyUI API
+    /// endpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `str` representation of the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a new `ObjectInfoApi` instance on success, or an error if url parsing failed.
+    pub fn new<S>(client: reqwest::Client, endpoint: S) -> Result<Self>
+    where
+        S: AsRef<str>,
+    {
+        Ok(Self::new_with_url(client, Url::parse(endpoint.as_ref())?))
+    }
+
+    /// Constructs a new `ObjectInfoApi` client with a given `reqwest::Client` and endpoint `Url`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `Url` representing the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A new `ObjectInfoApi` instance.
+    pub fn new_with_url(client: reqwest::Client, endpoint: Url) -> Self {
+        Self {
+            client,
+            endpoint,
+            headers: HeaderMap::new(),
+            request_options: RetryPolicy::default(),
+        }
+    }
+
+    /// Sets headers sent with every request, e.g. for authenticating with a proxy.
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - A `HeaderMap` of headers to send.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    backend_request::retry_builders!(request_options);
+
+    pub(crate) fn with_request_options(mut self, request_options: RetryPolicy) -> Self {
+        self.request_options = request_options;
+        self
+    }
+
+    /// Gets information about every node class using the `ObjectInfoApi` client, e.g. to find
+    /// the samplers and models ComfyUI accepts.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `ObjectInfo` on success, or an error if the request failed.
+    pub async fn get_all(&self) -> Result<ObjectInfo> {
+        // ComfyUI serves every node class without the trailing slash of the endpoint.
+        let mut url = self.endpoint.clone();
+        let path = url.path().trim_end_matches('/').to_owned();
+        url.set_path(&path);
+        self.send(url).await
+    }
+
+    /// Gets information about a node class using the `ObjectInfoApi` client.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_class` - The class of the node, e.g. `CheckpointLoaderSimple`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `ObjectInfo` on success, or an error if the request failed.
+    pub async fn get(&self, node_class: &str) -> Result<ObjectInfo> {
+        self.send(self.endpoint.join(node_class)?).await
+    }
+
+    async fn send(&self, url: Url) -> Result<ObjectInfo> {
+        let request = self.client.get(url).headers(self.headers.clone());
+        let response = self.request_options.send(request).await?;
+        if response.status().is_success() {
+            return response
+                .json()
+                .await
+                .map_err(ObjectInfoApiError::InvalidResponse);
+        }
+        let status = response.status();
+        let text = response
+            .text()
+            .await
+            .map_err(ObjectInfoApiError::GetDataFailed)?;
+        Err(ObjectInfoApiError::GetObjectInfoFailed {
+            status,
+            error: text,
+        })
+    }
+}
@@ -1,8 +1,10 @@
-use reqwest::Url;
+use reqwest::{header::HeaderMap, Url};
 use serde::Serialize;
 use serde_with::skip_serializing_none;
 
-use crate::models::{Prompt, Response};
+use crate::models::{Prompt, Response, Status};
+
+use backend_request::RetryPolicy;
 
 /// Errors that can occur when interacting with `PromptApi`.
 #[derive(thiserror::Error, Debug)]
@@ -26,6 +28,12 @@ pub enum PromptApiError {
         status: reqwest::StatusCode,
         error: String,
     },
+    /// Server returned an error when getting the queue state
+    #[error("Failed to get queue state: {status}: {error}")]
+    GetQueueFailed {
+        status: reqwest::StatusCode,
+        error: String,
+    },
 }
 
 type Result<T> = std::result::Result<T, PromptApiError>;
@@ -43,6 +51,8 @@ pub struct PromptApi {
     client: reqwest::Client,
     endpoint: Url,
     client_id: uuid::Uuid,
+    headers: HeaderMap,
+    request_options: RetryPolicy,
 }
 
 impl PromptApi {
@@ -83,9 +93,28 @@ impl PromptApi {
             client,
             endpoint,
             client_id,
+            headers: HeaderMap::new(),
+            request_options: RetryPolicy::default(),
         }
     }
 
+    /// Sets headers sent with every request, e.g. for authenticating with a proxy.
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - A `HeaderMap` of headers to send.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    backend_request::retry_builders!(request_options);
+
+    pub(crate) fn with_request_options(mut self, request_options: RetryPolicy) -> Self {
+        self.request_options = request_options;
+        self
+    }
+
     /// Sends a prompt request using the `PromptApi` client.
     ///
     /// # Arguments
@@ -100,15 +129,15 @@ impl PromptApi {
     }
 
     async fn send_as_client(&self, prompt: &Prompt, client_id: uuid::Uuid) -> Result<Response> {
-        let response = self
+        let request = self
             .client
             .post(self.endpoint.clone())
+            .headers(self.headers.clone())
             .json(&PromptWrapper {
                 prompt,
                 client_id: Some(client_id),
-            })
-            .send()
-            .await?;
+            });
+        let response = self.request_options.send(request).await?;
         if response.status().is_success() {
             return response
                 .json()
@@ -126,6 +155,34 @@ impl PromptApi {
         })
     }
 
+    /// Gets the state of the prompt queue using the `PromptApi` client.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Status` on success, or an error if the request failed.
+    pub async fn status(&self) -> Result<Status> {
+        let request = self
+            .client
+            .get(self.endpoint.clone())
+            .headers(self.headers.clone());
+        let response = self.request_options.send(request).await?;
+        if response.status().is_success() {
+            return response
+                .json()
+                .await
+                .map_err(PromptApiError::InvalidResponse);
+        }
+        let status = response.status();
+        let text = response
+            .text()
+            .await
+            .map_err(PromptApiError::GetDataFailed)?;
+        Err(PromptApiError::GetQueueFailed {
+            status,
+            error: text,
+        })
+    }
+
     /// Returns the client id used for requests.
     pub fn client_id(&self) -> uuid::Uuid {
         self.client_id
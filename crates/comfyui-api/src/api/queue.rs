@@ -0,0 +1,195 @@
+use reqwest::{header::HeaderMap, Url};
+use serde::Serialize;
+
+use crate::models::Queue;
+
+use backend_request::RetryPolicy;
+
+/// Errors that can occur when interacting with `QueueApi`.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum QueueApiError {
+    /// Error parsing endpoint URL
+    #[error("Failed to parse endpoint URL")]
+    ParseError(#[from] url::ParseError),
+    /// Error sending request
+    #[error("Failed to send request")]
+    RequestFailed(#[from] reqwest::Error),
+    /// An error occurred while parsing the response from the API.
+    #[error("Parsing response failed")]
+    InvalidResponse(#[source] reqwest::Error),
+    /// An error occurred getting response data.
+    #[error("Failed to get response data")]
+    GetDataFailed(#[source] reqwest::Error),
+    /// Server returned an error getting the queue
+    #[error("Failed to get queue: {status}: {error}")]
+    GetQueueFailed {
+        status: reqwest::StatusCode,
+        error: String,
+    },
+    /// Server returned an error deleting prompts from the queue
+    #[error("Failed to update queue: {status}: {error}")]
+    UpdateQueueFailed {
+        status: reqwest::StatusCode,
+        error: String,
+    },
+}
+
+type Result<T> = std::result::Result<T, QueueApiError>;
+
+/// Struct representing a request to delete prompts from the queue.
+#[derive(Serialize, Debug, Default)]
+struct QueueUpdate<'a> {
+    /// Ids of the pending prompts to delete.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delete: Option<&'a [uuid::Uuid]>,
+    /// Whether to delete every pending prompt.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    clear: bool,
+}
+
+/// Struct representing a connection to the ComfyUI API `queue` endpoint.
+#[derive(Clone, Debug)]
+pub struct QueueApi {
+    client: reqwest::Client,
+    endpoint: Url,
+    headers: HeaderMap,
+    request_options: RetryPolicy,
+}
+
+impl QueueApi {
+    /// Constructs a new `QueueApi` client with a given `reqwest::Client` and ComfyUI API
+    /// endpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `str` representation of the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a new `QueueApi` instance on success, or an error if url parsing failed.
+    pub fn new<S>(client: reqwest::Client, endpoint: S) -> Result<Self>
+    where
+        S: AsRef<str>,
+    {
+        Ok(Self::new_with_url(client, Url::parse(endpoint.as_ref())?))
+    }
+
+    /// Constructs a new `QueueApi` client with a given `reqwest::Client` and endpoint `Url`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `Url` representing the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A new `QueueApi` instance.
+    pub fn new_with_url(client: reqwest::Client, endpoint: Url) -> Self {
+        Self {
+            client,
+            endpoint,
+            headers: HeaderMap::new(),
+            request_options: RetryPolicy::default(),
+        }
+    }
+
+    /// Sets headers sent with every request, e.g. for authenticating with a proxy.
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - A `HeaderMap` of headers to send.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    backend_request::retry_builders!(request_options);
+
+    pub(crate) fn with_request_options(mut self, request_options: RetryPolicy) -> Self {
+        self.request_options = request_options;
+        self
+    }
+
+    /// Gets the prompts that are executing or waiting to execute, from every client.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `Queue` on success, or an error if the request failed.
+    pub async fn get(&self) -> Result<Queue> {
+        let request = self
+            .client
+            .get(self.endpoint.clone())
+            .headers(self.headers.clone());
+        let response = self.request_options.send(request).await?;
+        if response.status().is_success() {
+            return response
+                .json()
+                .await
+                .map_err(QueueApiError::InvalidResponse);
+        }
+        let status = response.status();
+        let text = response
+            .text()
+            .await
+            .map_err(QueueApiError::GetDataFailed)?;
+        Err(QueueApiError::GetQueueFailed {
+            status,
+            error: text,
+        })
+    }
+
+    /// Deletes prompts that are waiting to execute. Ids of prompts that aren't pending are
+    /// ignored, so this can't interrupt the executing prompt.
+    ///
+    /// # Arguments
+    ///
+    /// * `prompt_ids` - The ids of the prompts to delete.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `()` on success, or an error if the request failed.
+    pub async fn delete(&self, prompt_ids: &[uuid::Uuid]) -> Result<()> {
+        self.update(&QueueUpdate {
+            delete: Some(prompt_ids),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Deletes every prompt that's waiting to execute, from every client. The executing prompt
+    /// is not affected.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `()` on success, or an error if the request failed.
+    pub async fn clear(&self) -> Result<()> {
+        self.update(&QueueUpdate {
+            clear: true,
+            ..Default::default()
+        })
+        .await
+    }
+
+    async fn update(&self, update: &QueueUpdate<'_>) -> Result<()> {
+        let request = self
+            .client
+            .post(self.endpoint.clone())
+            .headers(self.headers.clone())
+            .json(update);
+        let response = self.request_options.send(request).await?;
+        if response.status().is_success() {
+            return Ok(());
+        }
+        let status = response.status();
+        let text = response
+            .text()
+            .await
+            .map_err(QueueApiError::GetDataFailed)?;
+        Err(QueueApiError::UpdateQueueFailed {
+            status,
+            error: text,
+        })
+    }
+}
@@ -0,0 +1,124 @@
+use reqwest::{header::HeaderMap, Url};
+
+use crate::models::SystemStats;
+
+use backend_request::RetryPolicy;
+
+/// Errors that can occur when interacting with `SystemStatsApi`.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum SystemStatsApiError {
+    /// Error parsing endpoint URL
+    #[error("Failed to parse endpoint URL")]
+    ParseError(#[from] url::ParseError),
+    /// Error sending request
+    #[error("Failed to send request")]
+    RequestFailed(#[from] reqwest::Error),
+    /// An error occurred while parsing the response from the API.
+    #[error("Parsing response failed")]
+    InvalidResponse(#[source] reqwest::Error),
+    /// An error occurred getting response data.
+    #[error("Failed to get response data")]
+    GetDataFailed(#[source] reqwest::Error),
+    /// Server returned an error getting system stats
+    #[error("Failed to get system stats: {status}: {error}")]
+    GetSystemStatsFailed {
+        status: reqwest::StatusCode,
+        error: String,
+    },
+}
+
+type Result<T> = std::result::Result<T, SystemStatsApiError>;
+
+/// Struct representing a connection to the ComfyUI API `system_stats` endpoint.
+#[derive(Clone, Debug)]
+pub struct SystemStatsApi {
+    client: reqwest::Client,
+    endpoint: Url,
+    headers: HeaderMap,
+    request_options: RetryPolicy,
+}
+
+impl SystemStatsApi {
+    /// Constructs a new `SystemStatsApi` client with a given `reqwest::Client` and ComfyUI API
+    /// endpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `str` representation of the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a new `SystemStatsApi` instance on success, or an error if url parsing failed.
+    pub fn new<S>(client: reqwest::Client, endpoint: S) -> Result<Self>
+    where
+        S: AsRef<str>,
+    {
+        Ok(Self::new_with_url(client, Url::parse(endpoint.as_ref())?))
+    }
+
+    /// Constructs a new `SystemStatsApi` client with a given `reqwest::Client` and endpoint `Url`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `Url` representing the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A new `SystemStatsApi` instance.
+    pub fn new_with_url(client: reqwest::Client, endpoint: Url) -> Self {
+        Self {
+            client,
+            endpoint,
+            headers: HeaderMap::new(),
+            request_options: RetryPolicy::default(),
+        }
+    }
+
+    /// Sets headers sent with every request, e.g. for authenticating with a proxy.
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - A `HeaderMap` of headers to send.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    backend_request::retry_builders!(request_options);
+
+    pub(crate) fn with_request_options(mut self, request_options: RetryPolicy) -> Self {
+        self.request_options = request_options;
+        self
+    }
+
+    /// Sends a system stats request using the SystemStatsApi client.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `SystemStats` on success, or an error if the request failed.
+    pub async fn get(&self) -> Result<SystemStats> {
+        let request = self
+            .client
+            .get(self.endpoint.clone())
+            .headers(self.headers.clone());
+        let response = self.request_options.send(request).await?;
+        if response.status().is_success() {
+            return response
+                .json()
+                .await
+                .map_err(SystemStatsApiError::InvalidResponse);
+        }
+        let status = response.status();
+        let text = response
+            .text()
+            .await
+            .map_err(SystemStatsApiError::GetDataFailed)?;
+        Err(SystemStatsApiError::GetSystemStatsFailed {
+            status,
+            error: text,
+        })
+    }
+}
@@ -1,5 +1,10 @@
-use reqwest::{multipart, Url};
+use std::time::Duration;
+
+use reqwest::{header::HeaderMap, multipart, Url};
 use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use backend_request::RetryPolicy;
 
 /// Errors that can occur when interacting with `UploadApi`.
 #[derive(thiserror::Error, Debug)]
@@ -28,8 +33,105 @@ pub enum UploadApiError {
     },
 }
 
+impl UploadApiError {
+    /// Returns whether the error is likely transient and the upload can be retried.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            UploadApiError::RequestFailed(e) => e.is_connect() || e.is_timeout(),
+            UploadApiError::UploadImageFailed { status, .. } => {
+                status.is_server_error() || *status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            }
+            _ => false,
+        }
+    }
+}
+
 type Result<T> = std::result::Result<T, UploadApiError>;
 
+/// Base delay between upload retries, multiplied by the attempt number.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Enumeration of image formats supported for upload.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageType {
+    /// PNG image.
+    #[default]
+    Png,
+    /// JPEG image.
+    Jpeg,
+    /// WebP image.
+    Webp,
+}
+
+impl ImageType {
+    /// Detects the image format from the leading bytes of the image data.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The image data.
+    ///
+    /// # Returns
+    ///
+    /// The detected `ImageType`, or `None` if the format is not recognized.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(ImageType::Png)
+        } else if data.starts_with(&[0xff, 0xd8, 0xff]) {
+            Some(ImageType::Jpeg)
+        } else if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+            Some(ImageType::Webp)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the MIME type of the image format.
+    pub fn mime(&self) -> &'static str {
+        match self {
+            ImageType::Png => "image/png",
+            ImageType::Jpeg => "image/jpeg",
+            ImageType::Webp => "image/webp",
+        }
+    }
+
+    /// Returns the file extension of the image format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageType::Png => "png",
+            ImageType::Jpeg => "jpg",
+            ImageType::Webp => "webp",
+        }
+    }
+}
+
+/// Options controlling how an image is uploaded.
+#[derive(Debug, Clone)]
+pub struct UploadOptions {
+    /// The filename to store the image as. Defaults to `image.<extension>`.
+    pub filename: Option<String>,
+    /// The subfolder to store the image in.
+    pub subfolder: Option<String>,
+    /// The format of the image.
+    pub image_type: ImageType,
+    /// Whether to overwrite an existing file with the same name.
+    pub overwrite: bool,
+    /// Number of times to retry the upload on transient failures.
+    pub retries: u32,
+}
+
+impl Default for UploadOptions {
+    fn default() -> Self {
+        Self {
+            filename: None,
+            subfolder: None,
+            image_type: ImageType::default(),
+            overwrite: false,
+            retries: 3,
+        }
+    }
+}
+
 /// Struct representing an image.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ImageUpload {
@@ -47,6 +149,8 @@ pub struct ImageUpload {
 pub struct UploadApi {
     client: reqwest::Client,
     endpoint: Url,
+    headers: HeaderMap,
+    request_options: RetryPolicy,
 }
 
 impl UploadApi {
@@ -79,7 +183,29 @@ impl UploadApi {
     ///
     /// A new `UploadApi` instance.
     pub fn new_with_url(client: reqwest::Client, endpoint: Url) -> Self {
-        Self { client, endpoint }
+        Self {
+            client,
+            endpoint,
+            headers: HeaderMap::new(),
+            request_options: RetryPolicy::default(),
+        }
+    }
+
+    /// Sets headers sent with every request, e.g. for authenticating with a proxy.
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - A `HeaderMap` of headers to send.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    backend_request::retry_builders!(request_options);
+
+    pub(crate) fn with_request_options(mut self, request_options: RetryPolicy) -> Self {
+        self.request_options = request_options;
+        self
     }
 
     /// Uploads an image using the `UploadApi` client.
@@ -93,16 +219,72 @@ impl UploadApi {
     /// A `Result` containing an `Image` struct containing information about the image.
     /// success, or an error if the request failed.
     pub async fn image(&self, image: Vec<u8>) -> Result<ImageUpload> {
+        self.image_with_options(image, &UploadOptions::default())
+            .await
+    }
+
+    /// Uploads an image with the given options using the `UploadApi` client.
+    ///
+    /// Transient failures (connection errors, timeouts and server errors) are retried up to
+    /// `options.retries` times. Retries are idempotent when a stable `filename` is given together
+    /// with `overwrite`.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - A `Vec<u8>` containing the image to upload.
+    /// * `options` - An `UploadOptions` describing how the image should be stored.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an `ImageUpload` describing the stored image on success, or an
+    /// error if the request failed.
+    pub async fn image_with_options(
+        &self,
+        image: Vec<u8>,
+        options: &UploadOptions,
+    ) -> Result<ImageUpload> {
+        let endpoint = self.endpoint.clone().join("image")?;
+        let mut attempt = 0;
+        loop {
+            match self.send_image(&endpoint, image.clone(), options).await {
+                Err(e) if attempt < options.retries && e.is_transient() => {
+                    attempt += 1;
+                    warn!("Upload attempt {} failed, retrying: {}", attempt, e);
+                    tokio::time::sleep(RETRY_DELAY * attempt).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn send_image(
+        &self,
+        endpoint: &Url,
+        image: Vec<u8>,
+        options: &UploadOptions,
+    ) -> Result<ImageUpload> {
+        let filename = options
+            .filename
+            .clone()
+            .unwrap_or_else(|| format!("image.{}", options.image_type.extension()));
         let file = multipart::Part::bytes(image)
-            .file_name("image.png")
-            .mime_str("image/png")
+            .file_name(filename)
+            .mime_str(options.image_type.mime())
             .map_err(UploadApiError::SetMimeStrFailed)?;
-        let form = multipart::Form::new().part("image", file);
-        let response = self
+        let mut form = multipart::Form::new()
+            .part("image", file)
+            .text("overwrite", options.overwrite.to_string());
+        if let Some(subfolder) = &options.subfolder {
+            form = form.text("subfolder", subfolder.clone());
+        }
+        let request = self
             .client
-            .post(self.endpoint.clone().join("image")?)
-            .multipart(form)
-            .send()
+            .post(endpoint.clone())
+            .headers(self.headers.clone())
+            .multipart(form);
+        let response = self
+            .request_options
+            .send(request)
             .await
             .map_err(UploadApiError::RequestFailed)?;
         if response.status().is_success() {
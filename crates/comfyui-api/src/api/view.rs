@@ -1,7 +1,9 @@
-use reqwest::Url;
+use reqwest::{header::HeaderMap, Url};
 
 use crate::models::Image;
 
+use backend_request::RetryPolicy;
+
 /// Errors that can occur when interacting with `ViewApi`.
 #[derive(thiserror::Error, Debug)]
 #[non_exhaustive]
@@ -33,6 +35,8 @@ type Result<T> = std::result::Result<T, ViewApiError>;
 pub struct ViewApi {
     client: reqwest::Client,
     endpoint: Url,
+    headers: HeaderMap,
+    request_options: RetryPolicy,
 }
 
 impl ViewApi {
@@ -65,7 +69,29 @@ impl ViewApi {
     ///
     /// A new `ViewApi` instance.
     pub fn new_with_url(client: reqwest::Client, endpoint: Url) -> Self {
-        Self { client, endpoint }
+        Self {
+            client,
+            endpoint,
+            headers: HeaderMap::new(),
+            request_options: RetryPolicy::default(),
+        }
+    }
+
+    /// Sets headers sent with every request, e.g. for authenticating with a proxy.
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - A `HeaderMap` of headers to send.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    backend_request::retry_builders!(request_options);
+
+    pub(crate) fn with_request_options(mut self, request_options: RetryPolicy) -> Self {
+        self.request_options = request_options;
+        self
     }
 
     /// Sends a view request using the `ViewApi` client.
@@ -79,12 +105,12 @@ impl ViewApi {
     /// A `Result` containing a `Vec<u8>` representation of the image on
     /// success, or an error if the request failed.
     pub async fn get(&self, image: &Image) -> Result<Vec<u8>> {
-        let response = self
+        let request = self
             .client
             .get(self.endpoint.clone())
-            .query(&image)
-            .send()
-            .await?;
+            .headers(self.headers.clone())
+            .query(&image);
+        let response = self.request_options.send(request).await?;
         if response.status().is_success() {
             return Ok(response
                 .bytes()
@@ -1,6 +1,9 @@
 use futures_util::{stream::FusedStream, StreamExt};
-use reqwest::Url;
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use reqwest::{header::HeaderMap, Url};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{client::IntoClientRequest, Message},
+};
 use tracing::warn;
 
 use crate::models::{Preview, PreviewOrUpdate, Update};
@@ -14,13 +17,13 @@ pub enum WebSocketApiError {
     ParseError(#[from] url::ParseError),
     /// Error parsing endpoint URL
     #[error("Failed to parse endpoint URL")]
-    ConnectFailed(#[from] tokio_tungstenite::tungstenite::Error),
+    ConnectFailed(#[from] Box<tokio_tungstenite::tungstenite::Error>),
     /// An error occurred while parsing the response from the API.
     #[error("Parsing response failed")]
     InvalidResponse(#[from] serde_json::Error),
     /// An error occurred while reading websocket message.
     #[error("Error occurred while reading websocket message")]
-    ReadFailed(#[source] tokio_tungstenite::tungstenite::Error),
+    ReadFailed(#[source] Box<tokio_tungstenite::tungstenite::Error>),
 }
 
 type Result<T> = std::result::Result<T, WebSocketApiError>;
@@ -29,6 +32,7 @@ type Result<T> = std::result::Result<T, WebSocketApiError>;
 #[derive(Clone, Debug)]
 pub struct WebsocketApi {
     endpoint: Url,
+    headers: HeaderMap,
 }
 
 impl WebsocketApi {
@@ -58,14 +62,29 @@ impl WebsocketApi {
     ///
     /// A new `WebsocketApi` instance.
     pub fn new_with_url(endpoint: Url) -> Self {
-        Self { endpoint }
+        Self {
+            endpoint,
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Sets headers sent with the websocket handshake, e.g. for authenticating with a proxy.
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - A `HeaderMap` of headers to send.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
     }
 
     async fn connect_to_endpoint(
         &self,
         endpoint: &Url,
     ) -> Result<impl FusedStream<Item = Result<PreviewOrUpdate>>> {
-        let (connection, _) = connect_async(endpoint).await?;
+        let mut request = endpoint.as_str().into_client_request().map_err(Box::new)?;
+        request.headers_mut().extend(self.headers.clone());
+        let (connection, _) = connect_async(request).await.map_err(Box::new)?;
         Ok(connection.filter_map(|m| async {
             match m {
                 Ok(m) => match m {
@@ -82,7 +101,7 @@ impl WebsocketApi {
                         None
                     }
                 },
-                Err(e) => Some(Err(WebSocketApiError::ReadFailed(e))),
+                Err(e) => Some(Err(WebSocketApiError::ReadFailed(Box::new(e)))),
             }
         }))
     }
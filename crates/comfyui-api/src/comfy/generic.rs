@@ -0,0 +1,194 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context};
+
+use crate::models::{NodeOrUnknown, Prompt};
+
+/// Accessor for reading and writing arbitrary node inputs, addressed as `node.input`.
+///
+/// Unlike the typed `Getter`s and `Setter`s, this works on any node in the workflow, including
+/// node types without a typed model, by round-tripping the node through JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GenericAccessor {
+    /// The id of the node.
+    pub node: String,
+    /// The name of the input.
+    pub input: String,
+}
+
+impl GenericAccessor {
+    /// Constructs a new `GenericAccessor` for the given node and input.
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The id of the node.
+    /// * `input` - The name of the input.
+    pub fn new<S, T>(node: S, input: T) -> Self
+    where
+        S: Into<String>,
+        T: Into<String>,
+    {
+        Self {
+            node: node.into(),
+            input: input.into(),
+        }
+    }
+
+    /// Gets the current value of the input.
+    ///
+    /// # Arguments
+    ///
+    /// * `prompt` - The `Prompt` to read from.
+    ///
+    /// # Returns
+    ///
+    /// The value of the input on success, or an error if the node or input does not exist.
+    pub fn get(&self, prompt: &Prompt) -> anyhow::Result<serde_json::Value> {
+        let node = self.node_json(prompt)?;
+        node.get("inputs")
+            .and_then(|inputs| inputs.get(&self.input))
+            .cloned()
+            .ok_or_else(|| anyhow!("Node {} has no input {}", self.node, self.input))
+    }
+
+    /// Sets the value of the input.
+    ///
+    /// # Arguments
+    ///
+    /// * `prompt` - The `Prompt` to modify.
+    /// * `value` - The new value of the input.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` on success, or an error if the node or input does not exist, or the value is not
+    /// valid for the input.
+    pub fn set(&self, prompt: &mut Prompt, value: serde_json::Value) -> anyhow::Result<()> {
+        let mut node = self.node_json(prompt)?;
+        let input = node
+            .get_mut("inputs")
+            .and_then(|inputs| inputs.get_mut(&self.input))
+            .ok_or_else(|| anyhow!("Node {} has no input {}", self.node, self.input))?;
+        *input = value;
+        let new_node: NodeOrUnknown =
+            serde_json::from_value(node).context("Failed to deserialize node")?;
+        let entry = prompt
+            .workflow
+            .get_mut(&self.node)
+            .ok_or_else(|| anyhow!("Node {} not found", self.node))?;
+        // A typed node that no longer deserializes would silently fall back to a generic node.
+        if matches!(entry, NodeOrUnknown::Node(_))
+            && matches!(new_node, NodeOrUnknown::GenericNode(_))
+        {
+            return Err(anyhow!(
+                "Invalid value for input {} of node {}",
+                self.input,
+                self.node
+            ));
+        }
+        *entry = new_node;
+        Ok(())
+    }
+
+    fn node_json(&self, prompt: &Prompt) -> anyhow::Result<serde_json::Value> {
+        let node = prompt
+            .workflow
+            .get(&self.node)
+            .ok_or_else(|| anyhow!("Node {} not found", self.node))?;
+        serde_json::to_value(node).context("Failed to serialize node")
+    }
+}
+
+impl FromStr for GenericAccessor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (node, input) = s
+            .split_once('.')
+            .filter(|(node, input)| !node.is_empty() && !input.is_empty())
+            .ok_or_else(|| anyhow!("Expected node.input, got {}", s))?;
+        Ok(Self::new(node, input))
+    }
+}
+
+impl std::fmt::Display for GenericAccessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.node, self.input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{comfy::getter::GetExt as _, models::KSampler};
+
+    fn prompt() -> Prompt {
+        serde_json::from_str(
+            r#"{
+                "3": {"class_type": "KSampler", "inputs": {
+                    "cfg": 7.0, "denoise": 1.0, "sampler_name": "euler", "scheduler": "normal",
+                    "seed": 1, "steps": 20, "positive": ["6", 0], "negative": ["7", 0],
+                    "model": ["4", 0], "latent_image": ["5", 0]
+                }},
+                "10": {"class_type": "FaceDetailer", "inputs": {
+                    "guide_size": 384, "image": ["8", 0]
+                }}
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_get_and_set() {
+        let mut prompt = prompt();
+        let steps = GenericAccessor::new("3", "steps");
+        assert_eq!(steps.get(&prompt).unwrap(), json!(20));
+        steps.set(&mut prompt, json!(30)).unwrap();
+        assert_eq!(steps.get(&prompt).unwrap(), json!(30));
+        let sampler: &KSampler = prompt.get_typed_node("3").unwrap();
+        assert_eq!(sampler.steps.value(), Some(&30));
+
+        // Nodes without a typed model are accessed the same way.
+        let guide_size = GenericAccessor::new("10", "guide_size");
+        assert_eq!(guide_size.get(&prompt).unwrap(), json!(384));
+        guide_size.set(&mut prompt, json!(512)).unwrap();
+        assert_eq!(guide_size.get(&prompt).unwrap(), json!(512));
+    }
+
+    #[test]
+    fn test_missing_node_or_input() {
+        let mut prompt = prompt();
+        let missing_node = GenericAccessor::new("99", "steps");
+        assert!(missing_node.get(&prompt).is_err());
+        assert!(missing_node.set(&mut prompt, json!(30)).is_err());
+
+        let missing_input = GenericAccessor::new("3", "strength");
+        assert!(missing_input.get(&prompt).is_err());
+        assert!(missing_input.set(&mut prompt, json!(0.5)).is_err());
+        assert_eq!(
+            serde_json::to_value(&prompt).unwrap(),
+            serde_json::to_value(self::prompt()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_set_mismatched_type() {
+        let mut prompt = prompt();
+        let steps = GenericAccessor::new("3", "steps");
+        assert!(steps.set(&mut prompt, json!("many")).is_err());
+        // The typed node is left as it was instead of turning into a generic node.
+        let sampler: &KSampler = prompt.get_typed_node("3").unwrap();
+        assert_eq!(sampler.steps.value(), Some(&20));
+    }
+
+    #[test]
+    fn test_parse() {
+        let accessor: GenericAccessor = "3.steps".parse().unwrap();
+        assert_eq!(accessor, GenericAccessor::new("3", "steps"));
+        assert_eq!(accessor.to_string(), "3.steps");
+        for invalid in ["3", ".steps", "3.", ""] {
+            assert!(invalid.parse::<GenericAccessor>().is_err(), "{invalid}");
+        }
+    }
+}
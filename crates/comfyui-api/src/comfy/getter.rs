@@ -159,6 +159,20 @@ where
     /// A mutable reference to the value on success, or an error if the node could not be found.
     fn get_value_mut<'a>(&self, node: &'a mut dyn Node) -> anyhow::Result<&'a mut T>;
 
+    /// Gets every copy of the value from the given `Node`. Implementations for nodes that
+    /// store the value in several inputs should override this so setters update all of them.
+    ///
+    /// # Inputs
+    ///
+    /// * `node` - A mutable reference to a `Node`.
+    ///
+    /// # Returns
+    ///
+    /// Mutable references to the values on success, or an error if the node could not be found.
+    fn get_values_mut<'a>(&self, node: &'a mut dyn Node) -> anyhow::Result<Vec<&'a mut T>> {
+        Ok(vec![self.get_value_mut(node)?])
+    }
+
     /// Finds a `Node` leading into the given `output_node`.
     ///
     /// # Inputs
@@ -258,6 +272,7 @@ impl<N: Node + 'static> GetExt<N> for Prompt {
     }
 }
 
+#[allow(dead_code)]
 trait GetterExt<T, N>
 where
     N: Node + 'static,
@@ -468,8 +483,14 @@ macro_rules! create_ext_trait {
     };
 }
 
+/// Gets the prompt text from a `CLIPTextEncode` node, or the CLIP-G text from a
+/// `CLIPTextEncodeSDXL` node. Setting the prompt on a `CLIPTextEncodeSDXL` node sets both its
+/// CLIP-G and CLIP-L text.
 impl Getter<String, CLIPTextEncode> for accessors::Prompt {
     fn get_value<'a>(&self, node: &'a dyn Node) -> anyhow::Result<&'a String> {
+        if let Some(node) = as_node::<CLIPTextEncodeSDXL>(node) {
+            return node.text_g.value().context("Failed to get text_g value");
+        }
         as_node::<CLIPTextEncode>(node)
             .context("Failed to cast node")?
             .text
@@ -478,6 +499,13 @@ impl Getter<String, CLIPTextEncode> for accessors::Prompt {
     }
 
     fn get_value_mut<'a>(&self, node: &'a mut dyn Node) -> anyhow::Result<&'a mut String> {
+        if as_node::<CLIPTextEncodeSDXL>(node).is_some() {
+            return as_node_mut::<CLIPTextEncodeSDXL>(node)
+                .context("Failed to cast node")?
+                .text_g
+                .value_mut()
+                .context("Failed to get text_g value");
+        }
         as_node_mut::<CLIPTextEncode>(node)
             .context("Failed to cast node")?
             .text
@@ -485,6 +513,21 @@ impl Getter<String, CLIPTextEncode> for accessors::Prompt {
             .context("Failed to get text value")
     }
 
+    fn get_values_mut<'a>(&self, node: &'a mut dyn Node) -> anyhow::Result<Vec<&'a mut String>> {
+        if as_node::<CLIPTextEncodeSDXL>(node).is_some() {
+            let node = as_node_mut::<CLIPTextEncodeSDXL>(node).context("Failed to cast node")?;
+            return Ok(vec![
+                node.text_g
+                    .value_mut()
+                    .context("Failed to get text_g value")?,
+                node.text_l
+                    .value_mut()
+                    .context("Failed to get text_l value")?,
+            ]);
+        }
+        Ok(vec![self.get_value_mut(node)?])
+    }
+
     fn find_node(prompt: &Prompt, output_node: Option<&str>) -> Option<String> {
         if let Some(node) = find_node::<KSampler>(prompt, output_node) {
             if let Ok(node) = prompt.get_typed_node(&node) as anyhow::Result<&KSampler> {
@@ -511,6 +554,10 @@ impl Getter<String, CLIPTextEncode> for accessors::NegativePrompt {
         accessors::Prompt.get_value_mut(node)
     }
 
+    fn get_values_mut<'a>(&self, node: &'a mut dyn Node) -> anyhow::Result<Vec<&'a mut String>> {
+        accessors::Prompt.get_values_mut(node)
+    }
+
     fn find_node(prompt: &Prompt, output_node: Option<&str>) -> Option<String> {
         if let Some(node) = find_node::<KSampler>(prompt, output_node) {
             if let Ok(node) = prompt.get_typed_node(&node) as anyhow::Result<&KSampler> {
@@ -641,6 +688,16 @@ where
             .context("Failed to set value")
     }
 
+    fn get_values_mut<'a>(&self, node: &'a mut dyn Node) -> anyhow::Result<Vec<&'a mut T>> {
+        let s1 = S1::default();
+        if s1.get_value(node).is_ok() {
+            return s1.get_values_mut(node);
+        }
+        S2::default()
+            .get_values_mut(node)
+            .context("Failed to set value")
+    }
+
     fn find_node(prompt: &Prompt, output_node: Option<&str>) -> Option<String> {
         find_node::<N1>(prompt, output_node).or_else(|| find_node::<N2>(prompt, output_node))
     }
@@ -4,9 +4,10 @@ use std::pin::pin;
 use anyhow::{anyhow, Context};
 use async_stream::stream;
 use futures_util::{
-    stream::{FusedStream, FuturesOrdered},
+    stream::{self, FusedStream},
     Stream, StreamExt,
 };
+use tracing::{debug, warn};
 use uuid::Uuid;
 
 use crate::{
@@ -17,6 +18,9 @@ use crate::{
 pub mod visitor;
 pub use visitor::Visitor;
 
+pub mod generic;
+pub use generic::GenericAccessor;
+
 pub mod setter;
 
 pub mod getter;
@@ -27,6 +31,7 @@ mod accessors;
 use self::setter::SetterExt as _;
 
 enum State {
+    Progress(Progress),
     Executing(String, Vec<Image>),
     Finished(Vec<(String, Vec<Image>)>),
 }
@@ -34,12 +39,28 @@ enum State {
 /// Output from a node.
 #[derive(Debug, Clone)]
 pub struct NodeOutput {
+    /// The id of the prompt that produced the output.
+    pub prompt_id: Uuid,
     /// The identifier of the node.
     pub node: String,
     /// The image generated by the node.
     pub image: Vec<u8>,
 }
 
+/// An event that occurs while a prompt executes.
+#[derive(Debug, Clone)]
+pub enum PromptEvent {
+    /// Progress of the executing node, such as the number of sampling steps completed.
+    Progress {
+        /// The current progress value.
+        value: u64,
+        /// The maximum progress value.
+        max: u64,
+    },
+    /// An image generated by a node.
+    Output(NodeOutput),
+}
+
 /// Errors that can occur opening API endpoints.
 #[derive(thiserror::Error, Debug)]
 #[non_exhaustive]
@@ -62,9 +83,27 @@ pub enum ComfyApiError {
     /// Prompt task not found
     #[error("Failed to get task for prompt")]
     PromptTaskNotFound(#[source] api::HistoryApiError),
+    /// Error getting history from API
+    #[error("Failed to get history from API")]
+    GetHistoryFailed(#[source] api::HistoryApiError),
+    /// Error getting object info
+    #[error("Failed to get object info from API")]
+    GetObjectInfoFailed(#[from] api::ObjectInfoApiError),
+    /// Error getting system stats
+    #[error("Failed to get system stats from API")]
+    GetSystemStatsFailed(#[from] api::SystemStatsApiError),
+    /// Error interrupting execution
+    #[error("Failed to interrupt execution")]
+    InterruptFailed(#[from] api::InterruptApiError),
     /// Error sending prompt to API
     #[error("Failed to send prompt to API")]
     SendPromptFailed(#[from] PromptApiError),
+    /// Error getting the queue state from API
+    #[error("Failed to get queue state from API")]
+    GetQueueFailed(#[source] PromptApiError),
+    /// Error getting or updating the queue's prompts
+    #[error("Failed to get or update the queue")]
+    QueueFailed(#[from] api::QueueApiError),
     /// Error getting image from API
     #[error("Failed to get image from API")]
     GetImageFailed(#[from] ViewApiError),
@@ -75,6 +114,9 @@ pub enum ComfyApiError {
 
 type Result<T> = std::result::Result<T, ComfyApiError>;
 
+/// The default maximum number of images fetched concurrently.
+pub const DEFAULT_FETCH_CONCURRENCY: usize = 4;
+
 /// Higher-level API for interacting with the ComfyUI API.
 #[derive(Clone, Debug)]
 pub struct Comfy {
@@ -82,6 +124,7 @@ pub struct Comfy {
     history: HistoryApi,
     upload: UploadApi,
     view: ViewApi,
+    fetch_concurrency: usize,
 }
 
 impl Default for Comfy {
@@ -91,6 +134,7 @@ impl Default for Comfy {
             history: api.history().expect("failed to create history api"),
             upload: api.upload().expect("failed to create upload api"),
             view: api.view().expect("failed to create view api"),
+            fetch_concurrency: DEFAULT_FETCH_CONCURRENCY,
             api,
         }
     }
@@ -104,6 +148,7 @@ impl Comfy {
             history: api.history()?,
             upload: api.upload()?,
             view: api.view()?,
+            fetch_concurrency: DEFAULT_FETCH_CONCURRENCY,
             api,
         })
     }
@@ -126,6 +171,7 @@ impl Comfy {
             history: api.history()?,
             upload: api.upload()?,
             view: api.view()?,
+            fetch_concurrency: DEFAULT_FETCH_CONCURRENCY,
             api,
         })
     }
@@ -144,17 +190,75 @@ impl Comfy {
     where
         S: AsRef<str>,
     {
-        let api = Api::new_with_client_and_url(client, url.as_ref())?;
+        Self::new_with_api(Api::new_with_client_and_url(client, url.as_ref())?)
+    }
+
+    /// Returns a new `Comfy` instance using the given `Api`, e.g. one configured with
+    /// authentication headers.
+    ///
+    /// # Arguments
+    ///
+    /// * `api` - The `Api` to use for all requests.
+    ///
+    /// # Errors
+    ///
+    /// If the endpoint URLs fail to parse, an error will be returned.
+    pub fn new_with_api(api: Api) -> Result<Self> {
         Ok(Self {
             history: api.history()?,
             upload: api.upload()?,
             view: api.view()?,
+            fetch_concurrency: DEFAULT_FETCH_CONCURRENCY,
             api,
         })
     }
 
+    /// Sets the maximum number of images fetched concurrently while streaming outputs.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The maximum number of concurrent image downloads. Values below 1 are treated as 1.
+    ///
+    /// # Returns
+    ///
+    /// The `Comfy` instance with the new limit applied.
+    pub fn with_fetch_concurrency(mut self, limit: usize) -> Self {
+        self.fetch_concurrency = limit.max(1);
+        self
+    }
+
+    /// Returns the maximum number of images fetched concurrently.
+    pub fn fetch_concurrency(&self) -> usize {
+        self.fetch_concurrency
+    }
+
+    /// Returns the underlying `Api`.
+    pub fn api(&self) -> &Api {
+        &self.api
+    }
+
+    fn fetch_images(
+        &self,
+        images: Vec<Image>,
+    ) -> impl Stream<Item = std::result::Result<Vec<u8>, ViewApiError>> + '_ {
+        // Downloads share the view client's connection pool; `buffered` keeps them in order
+        // while bounding how many are in flight at once.
+        stream::iter(images)
+            .map(move |image| async move { self.view.get(&image).await })
+            .buffered(self.fetch_concurrency)
+    }
+
     async fn filter_update(&self, update: Update, target_prompt_id: Uuid) -> Result<Option<State>> {
         match update {
+            // Progress is only sent to the client that queued the executing prompt, and each
+            // prompt is sent with its own client id.
+            Update::Progress(data) => Ok(Some(State::Progress(data))),
+            Update::ExecutionStart(data) => {
+                if data.prompt_id == target_prompt_id {
+                    debug!(prompt_id = %target_prompt_id, "Prompt dequeued");
+                }
+                Ok(None)
+            }
             Update::Executing(data) => {
                 if data.node.is_none() {
                     if let Some(prompt_id) = data.prompt_id {
@@ -166,19 +270,7 @@ impl Comfy {
                             .get_prompt(&prompt_id)
                             .await
                             .map_err(ComfyApiError::PromptTaskNotFound)?;
-                        let images = task
-                            .outputs
-                            .nodes
-                            .into_iter()
-                            .filter_map(|(key, value)| {
-                                if let NodeOutputOrUnknown::NodeOutput(output) = value {
-                                    Some((key, output.images))
-                                } else {
-                                    None
-                                }
-                            })
-                            .collect::<Vec<(String, Vec<Image>)>>();
-                        return Ok(Some(State::Finished(images)));
+                        return Ok(Some(State::Finished(task_images(task))));
                     }
                 }
                 Ok(None)
@@ -189,6 +281,18 @@ impl Comfy {
                 }
                 Ok(Some(State::Executing(data.node, data.output.images)))
             }
+            Update::ExecutionCached(data) => {
+                if data.prompt_id != target_prompt_id {
+                    return Ok(None);
+                }
+                // Fully cached prompts may finish without any further updates, so check
+                // whether the task has already been recorded as completed.
+                match self.history.get_prompt(&data.prompt_id).await {
+                    Ok(task) => Ok(Some(State::Finished(task_images(task)))),
+                    Err(HistoryApiError::TaskNotFound(_)) => Ok(None),
+                    Err(e) => Err(ComfyApiError::PromptTaskNotFound(e)),
+                }
+            }
             Update::ExecutionInterrupted(data) => {
                 if data.prompt_id != target_prompt_id {
                     return Ok(None);
@@ -211,7 +315,7 @@ impl Comfy {
     async fn prompt_impl<'a>(
         &'a self,
         prompt: &Prompt,
-    ) -> Result<impl Stream<Item = Result<State>> + 'a> {
+    ) -> Result<(Uuid, impl Stream<Item = Result<State>> + 'a)> {
         let client_id = Uuid::new_v4();
         let prompt_api = self.api.prompt_with_client(client_id)?;
         let websocket_api = self.api.websocket_with_client(client_id)?;
@@ -221,7 +325,11 @@ impl Comfy {
             .map_err(ComfyApiError::ReceiveUpdateFailure)?;
         let response = prompt_api.send(prompt).await?;
         let prompt_id = response.prompt_id;
-        Ok(stream.filter_map(move |msg| async move {
+        debug!(%prompt_id, number = response.number, "Prompt enqueued");
+        // Updates may have been emitted before the websocket was attached, e.g. when the
+        // prompt completes instantly, so check whether the task has already finished.
+        let completed = self.completed_task(prompt_id).await;
+        let stream = stream.filter_map(move |msg| async move {
             match msg {
                 Ok(msg) => match self.filter_update(msg, prompt_id).await {
                     Ok(Some(images)) => Some(Ok(images)),
@@ -230,10 +338,46 @@ impl Comfy {
                 },
                 Err(e) => Some(Err(ComfyApiError::ReceiveUpdateFailure(e))),
             }
-        }))
+        });
+        Ok((prompt_id, stream::iter(completed).chain(stream)))
     }
 
-    /// Executes a prompt and returns a stream of generated images.
+    async fn completed_task(&self, prompt_id: Uuid) -> Option<Result<State>> {
+        let task = match self.history.get_prompt(&prompt_id).await {
+            Ok(task) => task,
+            Err(HistoryApiError::TaskNotFound(_)) => return None,
+            Err(e) => {
+                warn!("Failed to check history for prompt {}: {:?}", prompt_id, e);
+                return None;
+            }
+        };
+        if let Some(TaskStatus {
+            status: TaskStatusKind::Error,
+            messages,
+            ..
+        }) = &task.status
+        {
+            let error = messages
+                .iter()
+                .find(|(event, _)| event == "execution_error")
+                .map(|(_, data)| data);
+            let field = |name: &str| {
+                error
+                    .and_then(|data| data.get(name))
+                    .and_then(|value| value.as_str())
+                    .unwrap_or("unknown")
+                    .to_owned()
+            };
+            return Some(Err(ComfyApiError::ExecutionError {
+                exception_type: field("exception_type"),
+                exception_message: field("exception_message"),
+            }));
+        }
+        Some(Ok(State::Finished(task_images(task))))
+    }
+
+    /// Executes a prompt and returns a stream of events, reporting progress as well as the
+    /// generated images.
     ///
     /// # Arguments
     ///
@@ -241,23 +385,23 @@ impl Comfy {
     ///
     /// # Returns
     ///
-    /// A `Result` containing a `Stream` of `Result<NodeOutput>` values on success, or an error if the request failed.
-    pub async fn stream_prompt<'a>(
+    /// A `Result` containing a `Stream` of `Result<PromptEvent>` values on success, or an error if the request failed.
+    pub async fn stream_prompt_events<'a>(
         &'a self,
         prompt: &Prompt,
-    ) -> Result<impl FusedStream<Item = Result<NodeOutput>> + 'a> {
-        let stream = self.prompt_impl(prompt).await?;
+    ) -> Result<impl FusedStream<Item = Result<PromptEvent>> + 'a> {
+        let (prompt_id, stream) = self.prompt_impl(prompt).await?;
         Ok(stream! {
             let mut executed = HashSet::new();
             for await msg in stream {
                 match msg {
+                    Ok(State::Progress(progress)) => {
+                        yield Ok(PromptEvent::Progress { value: progress.value, max: progress.max });
+                    }
                     Ok(State::Executing(node, images)) => {
                         executed.insert(node.clone());
-                        let fut = images.into_iter().map(|image| async move {
-                            self.view.get(&image).await
-                        }).collect::<FuturesOrdered<_>>();
-                        for await image in fut {
-                            yield Ok(NodeOutput { node: node.clone(), image: image? });
+                        for await image in self.fetch_images(images) {
+                            yield Ok(PromptEvent::Output(NodeOutput { prompt_id, node: node.clone(), image: image? }));
                         }
                     }
                     Ok(State::Finished(images)) => {
@@ -265,11 +409,8 @@ impl Comfy {
                             if executed.contains(&node) {
                                 continue;
                             }
-                            let fut = images.into_iter().map(|image| async move {
-                                self.view.get(&image).await
-                            }).collect::<FuturesOrdered<_>>();
-                            for await image in fut {
-                                yield Ok(NodeOutput { node: node.clone(), image: image? });
+                            for await image in self.fetch_images(images) {
+                                yield Ok(PromptEvent::Output(NodeOutput { prompt_id, node: node.clone(), image: image? }));
                             }
                         }
                         return;
@@ -280,6 +421,31 @@ impl Comfy {
         })
     }
 
+    /// Executes a prompt and returns a stream of generated images.
+    ///
+    /// # Arguments
+    ///
+    /// * `prompt` - A `Prompt` to send to the ComfyUI API.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Stream` of `Result<NodeOutput>` values on success, or an error if the request failed.
+    pub async fn stream_prompt<'a>(
+        &'a self,
+        prompt: &Prompt,
+    ) -> Result<impl FusedStream<Item = Result<NodeOutput>> + 'a> {
+        Ok(self
+            .stream_prompt_events(prompt)
+            .await?
+            .filter_map(|event| async move {
+                match event {
+                    Ok(PromptEvent::Progress { .. }) => None,
+                    Ok(PromptEvent::Output(output)) => Some(Ok(output)),
+                    Err(e) => Some(Err(e)),
+                }
+            }))
+    }
+
     /// Executes a prompt and returns the generated images.
     ///
     /// # Arguments
@@ -290,11 +456,34 @@ impl Comfy {
     ///
     /// A `Result` containing a `Vec<NodeOutput>` on success, or an error if the request failed.
     pub async fn execute_prompt(&self, prompt: &Prompt) -> Result<Vec<NodeOutput>> {
+        self.execute_prompt_with_progress(prompt, |_, _| {}).await
+    }
+
+    /// Executes a prompt and returns the generated images, calling `on_progress` with the
+    /// current and maximum progress values as the prompt executes.
+    ///
+    /// # Arguments
+    ///
+    /// * `prompt` - A `Prompt` to send to the ComfyUI API.
+    /// * `on_progress` - A function called with each progress update.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Vec<NodeOutput>` on success, or an error if the request failed.
+    pub async fn execute_prompt_with_progress<F>(
+        &self,
+        prompt: &Prompt,
+        mut on_progress: F,
+    ) -> Result<Vec<NodeOutput>>
+    where
+        F: FnMut(u64, u64),
+    {
         let mut images = vec![];
-        let mut stream = pin!(self.stream_prompt(prompt).await?);
-        while let Some(image) = stream.next().await {
-            match image {
-                Ok(image) => images.push(image),
+        let mut stream = pin!(self.stream_prompt_events(prompt).await?);
+        while let Some(event) = stream.next().await {
+            match event {
+                Ok(PromptEvent::Progress { value, max }) => on_progress(value, max),
+                Ok(PromptEvent::Output(image)) => images.push(image),
                 Err(e) => return Err(e),
             }
         }
@@ -313,6 +502,191 @@ impl Comfy {
     pub async fn upload_file(&self, file: Vec<u8>) -> Result<ImageUpload> {
         Ok(self.upload.image(file).await?)
     }
+
+    /// Uploads a file with the given options to the ComfyUI API and returns information about
+    /// the uploaded image.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - A `Vec<u8>` containing the file data to upload.
+    /// * `options` - An `UploadOptions` describing how the file should be stored.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an `ImageUpload` on success, or an error if the request failed.
+    pub async fn upload_file_with_options(
+        &self,
+        file: Vec<u8>,
+        options: &UploadOptions,
+    ) -> Result<ImageUpload> {
+        Ok(self.upload.image_with_options(file, options).await?)
+    }
+
+    /// Returns information about an image as generated by the executed workflow, as recorded
+    /// in the ComfyUI history.
+    ///
+    /// # Arguments
+    ///
+    /// * `prompt_id` - The id of the executed prompt.
+    /// * `output_node` - The output node that produced the image.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an `ImageInfo` on success, or an error if the task or output node was not found.
+    pub async fn image_info(
+        &self,
+        prompt_id: &Uuid,
+        output_node: &str,
+    ) -> anyhow::Result<ImageInfo> {
+        let task = self
+            .history
+            .get_prompt(prompt_id)
+            .await
+            .map_err(ComfyApiError::PromptTaskNotFound)?;
+        ImageInfo::new_from_prompt(&task.prompt.prompt, output_node)
+    }
+
+    /// Returns the most recent tasks from the ComfyUI history.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_items` - The maximum number of tasks to return.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Vec<Task>` ordered from most to least recent on success, or an error if the request failed.
+    pub async fn history(&self, max_items: u32) -> Result<Vec<Task>> {
+        self.history
+            .get_all(max_items)
+            .await
+            .map_err(ComfyApiError::GetHistoryFailed)
+    }
+
+    /// Returns information about the system running ComfyUI, including its version.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `SystemStats` on success, or an error if the request failed.
+    pub async fn system_stats(&self) -> Result<SystemStats> {
+        Ok(self.api.system_stats()?.get().await?)
+    }
+
+    /// Returns information about every node class, including the values their choice inputs
+    /// accept. Fetch it once to validate several names, e.g. before submitting a prompt.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `ObjectInfo` on success, or an error if the request failed.
+    pub async fn object_info(&self) -> Result<ObjectInfo> {
+        Ok(self.api.object_info()?.get_all().await?)
+    }
+
+    /// Returns the values that a choice input of a node class accepts.
+    async fn node_choices(&self, node_class: &str, input: &str) -> Result<Vec<String>> {
+        let info = self.api.object_info()?.get(node_class).await?;
+        Ok(info
+            .input_choices(node_class, input)
+            .map(<[String]>::to_vec)
+            .unwrap_or_default())
+    }
+
+    /// Returns the names of the checkpoints available to the `CheckpointLoaderSimple` node.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the checkpoint names on success, or an error if the request failed.
+    pub async fn checkpoints(&self) -> Result<Vec<String>> {
+        self.node_choices("CheckpointLoaderSimple", "ckpt_name")
+            .await
+    }
+
+    /// Returns the names of the LoRAs available to the `LoraLoader` node.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the LoRA names on success, or an error if the request failed.
+    pub async fn loras(&self) -> Result<Vec<String>> {
+        self.node_choices("LoraLoader", "lora_name").await
+    }
+
+    /// Returns the names of the samplers accepted by the `KSampler` node.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the sampler names on success, or an error if the request failed.
+    pub async fn samplers(&self) -> Result<Vec<String>> {
+        self.node_choices("KSampler", "sampler_name").await
+    }
+
+    /// Returns the names of the schedulers accepted by the `KSampler` node.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the scheduler names on success, or an error if the request failed.
+    pub async fn schedulers(&self) -> Result<Vec<String>> {
+        self.node_choices("KSampler", "scheduler").await
+    }
+
+    /// Returns the number of prompts that are running or waiting to run.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the length of the queue on success, or an error if the request failed.
+    pub async fn queue_remaining(&self) -> Result<u64> {
+        let status = self
+            .api
+            .prompt()?
+            .status()
+            .await
+            .map_err(ComfyApiError::GetQueueFailed)?;
+        Ok(status.exec_info.queue_remaining)
+    }
+
+    /// Returns the prompts that are executing or waiting to execute, from every client.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `Queue` on success, or an error if the request failed.
+    pub async fn queue(&self) -> Result<Queue> {
+        Ok(self.api.queue()?.get().await?)
+    }
+
+    /// Deletes prompts that are waiting to execute, e.g. ones whose results are no longer
+    /// needed. The executing prompt is not affected; use `interrupt` to stop it.
+    ///
+    /// # Arguments
+    ///
+    /// * `prompt_ids` - The ids of the prompts to delete.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `()` on success, or an error if the request failed.
+    pub async fn delete_queued(&self, prompt_ids: &[Uuid]) -> Result<()> {
+        Ok(self.api.queue()?.delete(prompt_ids).await?)
+    }
+
+    /// Interrupts the prompt that's currently executing, whichever client queued it.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `()` on success, or an error if the request failed.
+    pub async fn interrupt(&self) -> Result<()> {
+        Ok(self.api.interrupt()?.interrupt().await?)
+    }
+}
+
+fn task_images(task: Task) -> Vec<(String, Vec<Image>)> {
+    task.outputs
+        .nodes
+        .into_iter()
+        .filter_map(|(key, value)| {
+            if let NodeOutputOrUnknown::NodeOutput(output) = value {
+                Some((key, output.images))
+            } else {
+                None
+            }
+        })
+        .collect()
 }
 
 /// Information about the generated image.
@@ -330,6 +704,14 @@ pub struct ImageInfo {
     pub height: Option<u32>,
     /// The seed used to generate the image.
     pub seed: Option<i64>,
+    /// The number of steps used to generate the image.
+    pub steps: Option<u32>,
+    /// The CFG scale used to generate the image.
+    pub cfg: Option<f32>,
+    /// The denoising strength used to generate the image.
+    pub denoise: Option<f32>,
+    /// The sampler used to generate the image.
+    pub sampler: Option<String>,
 }
 
 impl ImageInfo {
@@ -529,6 +911,7 @@ impl PromptBuilder {
                     width.value,
                 )?;
             }
+            setter::set_size_conditioning(&mut new_prompt, Some(width.value), None);
         }
         if let Some(height) = self.height {
             if let Some(ref node) = height.node {
@@ -539,6 +922,7 @@ impl PromptBuilder {
                     height.value,
                 )?;
             }
+            setter::set_size_conditioning(&mut new_prompt, None, Some(height.value));
         }
         if let Some(ref seed) = self.seed {
             if let Some(ref node) = seed.node {
@@ -551,3 +935,140 @@ impl PromptBuilder {
         Ok(new_prompt)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use futures_util::SinkExt as _;
+    use tokio::{
+        io::{AsyncReadExt as _, AsyncWriteExt as _},
+        net::{TcpListener, TcpStream},
+    };
+    use tokio_tungstenite::tungstenite::Message;
+
+    use super::*;
+
+    const PROMPT_ID: &str = "1c6cf6b1-1b3c-4a5c-9b7e-7f3c3e9e4b1a";
+    const IMAGE: &[u8] = b"image data";
+
+    fn history_json() -> String {
+        format!(
+            r#"{{"{PROMPT_ID}": {{
+                "prompt": [0, "{PROMPT_ID}", {{}}, {{"client_id": "{PROMPT_ID}"}}, ["9"]],
+                "outputs": {{"9": {{"images": [{{"filename": "out.png", "subfolder": "", "type": "output"}}]}}}},
+                "status": {{"status_str": "success", "completed": true, "messages": []}}
+            }}}}"#
+        )
+    }
+
+    async fn read_request(stream: &mut TcpStream) -> Option<String> {
+        let mut buf = Vec::new();
+        let mut byte = [0u8; 1];
+        while !buf.ends_with(b"\r\n\r\n") {
+            if stream.read(&mut byte).await.ok()? == 0 {
+                return None;
+            }
+            buf.push(byte[0]);
+        }
+        let head = String::from_utf8(buf).ok()?;
+        let length = head
+            .lines()
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.eq_ignore_ascii_case("content-length")
+                    .then(|| value.trim().parse::<usize>().ok())
+                    .flatten()
+            })
+            .unwrap_or_default();
+        let mut body = vec![0u8; length];
+        stream.read_exact(&mut body).await.ok()?;
+        head.lines().next().map(str::to_owned)
+    }
+
+    async fn respond(stream: &mut TcpStream, content_type: &str, body: &[u8]) {
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+            content_type,
+            body.len()
+        );
+        stream.write_all(head.as_bytes()).await.unwrap();
+        stream.write_all(body).await.unwrap();
+    }
+
+    async fn serve_http(mut stream: TcpStream, history_requests: Arc<AtomicUsize>) {
+        while let Some(request_line) = read_request(&mut stream).await {
+            let path = request_line.split(' ').nth(1).unwrap_or_default();
+            if path.starts_with("/prompt") {
+                let body =
+                    format!(r#"{{"prompt_id": "{PROMPT_ID}", "number": 0, "node_errors": {{}}}}"#);
+                respond(&mut stream, "application/json", body.as_bytes()).await;
+            } else if path.starts_with("/history") {
+                // The task is only recorded in history after the cached execution completes.
+                let body = if history_requests.fetch_add(1, Ordering::SeqCst) == 0 {
+                    "{}".to_owned()
+                } else {
+                    history_json()
+                };
+                respond(&mut stream, "application/json", body.as_bytes()).await;
+            } else if path.starts_with("/view") {
+                respond(&mut stream, "image/png", IMAGE).await;
+            } else {
+                panic!("unexpected request: {}", request_line);
+            }
+        }
+    }
+
+    async fn serve_websocket(stream: TcpStream) {
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        let update = format!(
+            r#"{{"type": "execution_cached", "data": {{"prompt_id": "{PROMPT_ID}", "nodes": ["3", "9"]}}}}"#
+        );
+        ws.send(Message::Text(update)).await.unwrap();
+        // Keep the connection open without sending any further updates.
+        while ws.next().await.is_some() {}
+    }
+
+    async fn spawn_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let history_requests = Arc::new(AtomicUsize::new(0));
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut peek = [0u8; 8];
+                let n = stream.peek(&mut peek).await.unwrap();
+                if peek[..n].starts_with(b"GET /ws") {
+                    tokio::spawn(serve_websocket(stream));
+                } else {
+                    tokio::spawn(serve_http(stream, history_requests.clone()));
+                }
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_execute_prompt_cached() {
+        let url = spawn_server().await;
+        let comfy = Comfy::new_with_url(url).unwrap();
+        let prompt = Prompt {
+            workflow: Default::default(),
+        };
+
+        let images = tokio::time::timeout(
+            std::time::Duration::from_secs(10),
+            comfy.execute_prompt(&prompt),
+        )
+        .await
+        .expect("execute_prompt hung on cached execution")
+        .unwrap();
+
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].node, "9");
+        assert_eq!(images[0].image, IMAGE);
+    }
+}
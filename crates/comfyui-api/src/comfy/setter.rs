@@ -1,7 +1,10 @@
 use anyhow::{anyhow, Context};
 
 use crate::{
-    comfy::getter::{guess_node_mut, GetExt, Getter},
+    comfy::{
+        accessors,
+        getter::{guess_node_mut, GetExt, Getter, HeightExt as _, WidthExt as _},
+    },
     models::*,
 };
 
@@ -9,6 +12,7 @@ use crate::{
 pub trait SetterExt<T, N>
 where
     N: Node + 'static,
+    T: Clone,
 {
     /// Uses a heuristic to find a `Node` and set the value on it.
     ///
@@ -83,7 +87,7 @@ where
         F: FnOnce(&mut N) -> anyhow::Result<()>;
 }
 
-impl<T, N: Node + 'static> SetterExt<T, N> for crate::models::Prompt {
+impl<T: Clone, N: Node + 'static> SetterExt<T, N> for crate::models::Prompt {
     fn set<S>(&mut self, value: T) -> anyhow::Result<()>
     where
         S: Setter<T, N>,
@@ -126,6 +130,103 @@ impl<N: Node + 'static> SetExt<N> for crate::models::Prompt {
     }
 }
 
+/// Extension methods for `Prompt` to set generation parameters.
+///
+/// Unlike the mutable references returned by the `Getter` extension traits, these update every
+/// input a parameter is stored in, such as both text inputs and the size conditioning of
+/// `CLIPTextEncodeSDXL` nodes.
+pub trait ParamsSetExt {
+    /// Sets the prompt text.
+    ///
+    /// # Inputs
+    ///
+    /// * `value` - The prompt text to set.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` on success, or an error if the node could not be found.
+    fn set_prompt(&mut self, value: String) -> anyhow::Result<()>;
+
+    /// Sets the negative prompt text.
+    ///
+    /// # Inputs
+    ///
+    /// * `value` - The negative prompt text to set.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` on success, or an error if the node could not be found.
+    fn set_negative_prompt(&mut self, value: String) -> anyhow::Result<()>;
+
+    /// Sets the image width.
+    ///
+    /// # Inputs
+    ///
+    /// * `value` - The image width to set.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` on success, or an error if the node could not be found.
+    fn set_width(&mut self, value: u32) -> anyhow::Result<()>;
+
+    /// Sets the image height.
+    ///
+    /// # Inputs
+    ///
+    /// * `value` - The image height to set.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` on success, or an error if the node could not be found.
+    fn set_height(&mut self, value: u32) -> anyhow::Result<()>;
+}
+
+impl ParamsSetExt for crate::models::Prompt {
+    fn set_prompt(&mut self, value: String) -> anyhow::Result<()> {
+        let node = accessors::Prompt::guess_node_mut(self, None).context("Failed to find node")?;
+        accessors::Prompt.set_value(node, value)
+    }
+
+    fn set_negative_prompt(&mut self, value: String) -> anyhow::Result<()> {
+        let node =
+            accessors::NegativePrompt::guess_node_mut(self, None).context("Failed to find node")?;
+        accessors::NegativePrompt.set_value(node, value)
+    }
+
+    fn set_width(&mut self, value: u32) -> anyhow::Result<()> {
+        let result = self.width_mut().map(|width| *width = value);
+        set_size_conditioning(self, Some(value), None);
+        result
+    }
+
+    fn set_height(&mut self, value: u32) -> anyhow::Result<()> {
+        let result = self.height_mut().map(|height| *height = value);
+        set_size_conditioning(self, None, Some(value));
+        result
+    }
+}
+
+/// Sets the original and target size used for conditioning by every `CLIPTextEncodeSDXL` node
+/// in `prompt`, so it matches the size of the generated image.
+pub(crate) fn set_size_conditioning(
+    prompt: &mut crate::models::Prompt,
+    width: Option<u32>,
+    height: Option<u32>,
+) {
+    for (_, node) in prompt.get_nodes_by_type_mut::<CLIPTextEncodeSDXL>() {
+        if let Some(width) = width {
+            for input in [&mut node.width, &mut node.target_width] {
+                _ = input.value_mut().map(|value| *value = width);
+            }
+        }
+        if let Some(height) = height {
+            for input in [&mut node.height, &mut node.target_height] {
+                _ = input.value_mut().map(|value| *value = height);
+            }
+        }
+    }
+}
+
 /// A trait for setting values on nodes.
 ///
 /// This trait is implemented for types that can be used to set values on nodes.
@@ -135,6 +236,7 @@ pub trait Setter<T, N>
 where
     N: Node + 'static,
     Self: Getter<T, N>,
+    T: Clone,
 {
     /// Uses a heuristic to find a `Node` and set the value on it.
     ///
@@ -209,7 +311,9 @@ where
     ///
     /// `Ok(())` on success, or an error if the node could not be found.
     fn set_value(&self, node: &mut dyn Node, value: T) -> anyhow::Result<()> {
-        *self.get_value_mut(node)? = value;
+        for v in self.get_values_mut(node)? {
+            *v = value.clone();
+        }
         Ok(())
     }
 }
@@ -218,5 +322,81 @@ impl<G, T, N> Setter<T, N> for G
 where
     G: Getter<T, N>,
     N: Node + 'static,
+    T: Clone,
 {
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::comfy::getter::{NegativePromptExt as _, PromptExt as _};
+
+    use super::*;
+
+    fn sdxl_prompt() -> crate::models::Prompt {
+        let encode = |text: &str| {
+            format!(
+                r#"{{"class_type": "CLIPTextEncodeSDXL", "inputs": {{
+                    "clip": ["4", 1], "text_g": "{text}", "text_l": "{text}",
+                    "width": 1024, "height": 1024, "crop_w": 0, "crop_h": 0,
+                    "target_width": 1024, "target_height": 1024
+                }}}}"#
+            )
+        };
+        serde_json::from_str(&format!(
+            r#"{{
+                "3": {{"class_type": "KSampler", "inputs": {{
+                    "cfg": 7.0, "denoise": 1.0, "sampler_name": "euler", "scheduler": "normal",
+                    "seed": 1, "steps": 20, "positive": ["6", 0], "negative": ["7", 0],
+                    "model": ["4", 0], "latent_image": ["5", 0]
+                }}}},
+                "4": {{"class_type": "CheckpointLoaderSimple", "inputs": {{
+                    "ckpt_name": "sd_xl_base_1.0.safetensors"
+                }}}},
+                "5": {{"class_type": "EmptyLatentImage", "inputs": {{
+                    "batch_size": 1, "width": 1024, "height": 1024
+                }}}},
+                "6": {},
+                "7": {},
+                "8": {{"class_type": "VAEDecode", "inputs": {{"samples": ["3", 0], "vae": ["4", 2]}}}},
+                "9": {{"class_type": "SaveImage", "inputs": {{"images": ["8", 0]}}}}
+            }}"#,
+            encode("a cat"),
+            encode("blurry"),
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_set_sdxl_prompts() {
+        let mut prompt = sdxl_prompt();
+        assert_eq!(prompt.prompt().unwrap(), "a cat");
+        assert_eq!(prompt.negative_prompt().unwrap(), "blurry");
+
+        prompt.set_prompt("a dog".to_owned()).unwrap();
+        prompt.set_negative_prompt("lowres".to_owned()).unwrap();
+        let positive: &CLIPTextEncodeSDXL = prompt.get_typed_node("6").unwrap();
+        assert_eq!(positive.text_g.value().unwrap(), "a dog");
+        assert_eq!(positive.text_l.value().unwrap(), "a dog");
+        let negative: &CLIPTextEncodeSDXL = prompt.get_typed_node("7").unwrap();
+        assert_eq!(negative.text_g.value().unwrap(), "lowres");
+        assert_eq!(negative.text_l.value().unwrap(), "lowres");
+    }
+
+    #[test]
+    fn test_set_sdxl_size() {
+        let mut prompt = sdxl_prompt();
+        prompt.set_width(832).unwrap();
+        prompt.set_height(1216).unwrap();
+        let latent: &EmptyLatentImage = prompt.get_typed_node("5").unwrap();
+        assert_eq!(latent.width.value(), Some(&832));
+        assert_eq!(latent.height.value(), Some(&1216));
+        for id in ["6", "7"] {
+            let node: &CLIPTextEncodeSDXL = prompt.get_typed_node(id).unwrap();
+            assert_eq!(node.width.value(), Some(&832));
+            assert_eq!(node.target_width.value(), Some(&832));
+            assert_eq!(node.height.value(), Some(&1216));
+            assert_eq!(node.target_height.value(), Some(&1216));
+            assert_eq!(node.crop_w.value(), Some(&0));
+        }
+    }
+}
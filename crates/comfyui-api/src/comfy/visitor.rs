@@ -30,14 +30,25 @@ impl Visitor for ImageInfo {
             self.height = node.height.value().cloned();
         } else if let Some(node) = as_node::<KSampler>(node) {
             self.seed = node.seed.value().cloned();
+            self.steps = node.steps.value().cloned();
+            self.cfg = node.cfg.value().cloned();
+            self.denoise = node.denoise.value().cloned();
+            self.sampler = node.sampler_name.value().cloned();
         } else if let Some(node) = as_node::<SamplerCustom>(node) {
             self.seed = node.noise_seed.value().cloned();
+            self.cfg = node.cfg.value().cloned();
         } else if let Some(node) = as_node::<CLIPTextEncode>(node) {
             if self.prompt.is_none() {
                 self.prompt = node.text.value().cloned();
             } else if self.negative_prompt.is_none() {
                 self.negative_prompt = node.text.value().cloned();
             }
+        } else if let Some(node) = as_node::<CLIPTextEncodeSDXL>(node) {
+            if self.prompt.is_none() {
+                self.prompt = node.text_g.value().cloned();
+            } else if self.negative_prompt.is_none() {
+                self.negative_prompt = node.text_g.value().cloned();
+            }
         }
         for c in node.connections() {
             if let Some(node) = prompt.get_node_by_id(c) {
@@ -1,3 +1,6 @@
 pub mod api;
 pub mod comfy;
 pub mod models;
+
+/// The version of this crate.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
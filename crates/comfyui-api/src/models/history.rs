@@ -19,6 +19,52 @@ pub struct Task {
     pub outputs: Outputs,
     /// Information about prompt execution.
     pub prompt: PromptResult,
+    /// Execution status of the task.
+    #[serde(default)]
+    pub status: Option<TaskStatus>,
+}
+
+impl Task {
+    /// Returns the number of nodes in the task's workflow.
+    pub fn node_count(&self) -> usize {
+        self.prompt.prompt.workflow.len()
+    }
+}
+
+/// Struct representing the execution status of a task.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TaskStatus {
+    /// The status of the task.
+    #[serde(rename = "status_str")]
+    pub status: TaskStatusKind,
+    /// Whether the task completed.
+    pub completed: bool,
+    /// Messages emitted during execution, as `(event, data)` pairs.
+    #[serde(default)]
+    pub messages: Vec<(String, serde_json::Value)>,
+}
+
+/// Enumeration of the possible task statuses.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskStatusKind {
+    /// The task executed successfully.
+    Success,
+    /// The task failed with an error.
+    Error,
+    /// An unrecognized status.
+    #[serde(other)]
+    Unknown,
+}
+
+impl std::fmt::Display for TaskStatusKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskStatusKind::Success => write!(f, "success"),
+            TaskStatusKind::Error => write!(f, "error"),
+            TaskStatusKind::Unknown => write!(f, "unknown"),
+        }
+    }
 }
 
 /// Struct representing outputs from a task.
@@ -100,6 +146,7 @@ impl From<PromptResult> for (u64, uuid::Uuid, Prompt, ExtraData, OutputsToExecut
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ExtraData {
     /// The client id that performed the request.
+    #[serde(default)]
     pub client_id: uuid::Uuid,
 }
 
@@ -1,7 +1,13 @@
 pub mod history;
+pub mod object_info;
 pub mod prompt;
+pub mod queue;
+pub mod system_stats;
 pub mod websocket;
 
 pub use history::*;
+pub use object_info::*;
 pub use prompt::*;
+pub use queue::*;
+pub use system_stats::*;
 pub use websocket::*;
@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Struct representing a response from the ComfyUI API `object_info` endpoint.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(transparent)]
+pub struct ObjectInfo {
+    /// Information about each node class, indexed by class.
+    pub nodes: HashMap<String, NodeInfo>,
+}
+
+impl ObjectInfo {
+    /// Returns information about a node class.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_class` - The class of the node, e.g. `KSampler`.
+    ///
+    /// # Returns
+    ///
+    /// The `NodeInfo`, or `None` if ComfyUI has no such node class.
+    pub fn node(&self, node_class: &str) -> Option<&NodeInfo> {
+        self.nodes.get(node_class)
+    }
+
+    /// Returns the values that a choice input of a node class accepts.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_class` - The class of the node, e.g. `KSampler`.
+    /// * `input` - The name of the input, e.g. `sampler_name`.
+    ///
+    /// # Returns
+    ///
+    /// The accepted values, or `None` if there is no such node class or input, or the input
+    /// isn't a choice.
+    pub fn input_choices(&self, node_class: &str, input: &str) -> Option<&[String]> {
+        self.node(node_class)?.input(input)?.choices()
+    }
+
+    /// Returns the samplers accepted by the `KSampler` node.
+    pub fn samplers(&self) -> Option<&[String]> {
+        self.input_choices("KSampler", "sampler_name")
+    }
+
+    /// Returns the schedulers accepted by the `KSampler` node.
+    pub fn schedulers(&self) -> Option<&[String]> {
+        self.input_choices("KSampler", "scheduler")
+    }
+
+    /// Returns the checkpoints available to the `CheckpointLoaderSimple` node.
+    pub fn checkpoints(&self) -> Option<&[String]> {
+        self.input_choices("CheckpointLoaderSimple", "ckpt_name")
+    }
+
+    /// Returns the LoRAs available to the `LoraLoader` node.
+    pub fn loras(&self) -> Option<&[String]> {
+        self.input_choices("LoraLoader", "lora_name")
+    }
+}
+
+/// Struct representing information about a node class.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NodeInfo {
+    /// The inputs of the node.
+    pub input: NodeInputs,
+    /// The types of the node's outputs.
+    #[serde(default)]
+    pub output: Vec<String>,
+    /// The names of the node's outputs.
+    #[serde(default)]
+    pub output_name: Vec<String>,
+    /// Whether the node is an output node, such as `SaveImage`.
+    #[serde(default)]
+    pub output_node: bool,
+    /// The class of the node.
+    pub name: Option<String>,
+    /// The name of the node shown in the UI.
+    pub display_name: Option<String>,
+    /// The description of the node.
+    pub description: Option<String>,
+    /// The category of the node.
+    pub category: Option<String>,
+}
+
+/// Struct representing the inputs of a node class.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NodeInputs {
+    /// Required inputs, indexed by name.
+    #[serde(default)]
+    pub required: HashMap<String, InputSpec>,
+    /// Optional inputs, indexed by name.
+    #[serde(default)]
+    pub optional: HashMap<String, InputSpec>,
+}
+
+impl NodeInfo {
+    /// Returns an input of the node, whether it's required or optional.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The name of the input.
+    ///
+    /// # Returns
+    ///
+    /// The `InputSpec`, or `None` if the node has no such input.
+    pub fn input(&self, input: &str) -> Option<&InputSpec> {
+        self.input
+            .required
+            .get(input)
+            .or_else(|| self.input.optional.get(input))
+    }
+
+    /// Returns the names of the node's inputs, required inputs first.
+    pub fn input_names(&self) -> impl Iterator<Item = &str> {
+        self.input
+            .required
+            .keys()
+            .chain(self.input.optional.keys())
+            .map(String::as_str)
+    }
+
+    /// Returns the values that a choice input accepts, such as the available checkpoints.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The name of the input.
+    ///
+    /// # Returns
+    ///
+    /// The accepted values, or `None` if the node has no such input or it isn't a choice.
+    pub fn input_choices(&self, input: &str) -> Option<Vec<String>> {
+        self.input(input)?.choices().map(<[String]>::to_vec)
+    }
+}
+
+/// Enum representing the type of a node input.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputType {
+    /// An input that accepts one of a list of values, such as a sampler or checkpoint name.
+    Choice(Vec<String>),
+    /// An input of a named type, e.g. `INT`, `STRING` or `MODEL`.
+    Type(String),
+    /// An input described in a way this crate doesn't understand.
+    Unknown(Value),
+}
+
+/// Struct representing the specification of a node input.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(from = "Value", into = "Value")]
+pub struct InputSpec {
+    /// The type of the input.
+    pub input_type: InputType,
+    /// Options of the input, such as its default, minimum and maximum.
+    pub options: Map<String, Value>,
+}
+
+impl InputSpec {
+    /// Returns the values that the input accepts, if it's a choice.
+    pub fn choices(&self) -> Option<&[String]> {
+        match &self.input_type {
+            InputType::Choice(choices) => Some(choices),
+            _ => None,
+        }
+    }
+
+    /// Returns the default value of the input, if it has one.
+    pub fn default_value(&self) -> Option<&Value> {
+        self.options.get("default")
+    }
+}
+
+fn choice_values(values: &[Value]) -> Vec<String> {
+    values
+        .iter()
+        .map(|value| match value {
+            Value::String(s) => s.clone(),
+            value => value.to_string(),
+        })
+        .collect()
+}
+
+impl From<Value> for InputSpec {
+    /// Parses an input described as `[type, {options}]`, where the type is either the name of
+    /// a type or a list of choices. Newer versions of ComfyUI describe choices as
+    /// `["COMBO", {"options": [choice, ...]}]`.
+    fn from(value: Value) -> Self {
+        let (input_type, options) = match &value {
+            Value::Array(spec) => {
+                let options = match spec.get(1) {
+                    Some(Value::Object(options)) => options.clone(),
+                    _ => Map::new(),
+                };
+                let input_type = match spec.first() {
+                    Some(Value::Array(choices)) => InputType::Choice(choice_values(choices)),
+                    Some(Value::String(name)) if name == "COMBO" => match options.get("options") {
+                        Some(Value::Array(choices)) => InputType::Choice(choice_values(choices)),
+                        _ => InputType::Type(name.clone()),
+                    },
+                    Some(Value::String(name)) => InputType::Type(name.clone()),
+                    _ => InputType::Unknown(value.clone()),
+                };
+                (input_type, options)
+            }
+            _ => (InputType::Unknown(value), Map::new()),
+        };
+        Self {
+            input_type,
+            options,
+        }
+    }
+}
+
+impl From<InputSpec> for Value {
+    fn from(spec: InputSpec) -> Self {
+        let input_type = match spec.input_type {
+            InputType::Choice(choices) => Value::from(choices),
+            InputType::Type(name) => Value::String(name),
+            InputType::Unknown(value) => return value,
+        };
+        if spec.options.is_empty() {
+            Value::Array(vec![input_type])
+        } else {
+            Value::Array(vec![input_type, Value::Object(spec.options)])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_choices() {
+        let info: ObjectInfo = serde_json::from_str(
+            r#"{"CheckpointLoaderSimple": {
+                "input": {"required": {"ckpt_name": [["a.safetensors", "b.ckpt"]]}},
+                "output": ["MODEL", "CLIP", "VAE"],
+                "name": "CheckpointLoaderSimple",
+                "display_name": "Load Checkpoint",
+                "category": "loaders"
+            }}"#,
+        )
+        .unwrap();
+        let node = info.node("CheckpointLoaderSimple").unwrap();
+        assert_eq!(
+            node.input_choices("ckpt_name"),
+            Some(vec!["a.safetensors".to_owned(), "b.ckpt".to_owned()])
+        );
+        assert_eq!(node.input_choices("vae_name"), None);
+        assert_eq!(node.output, vec!["MODEL", "CLIP", "VAE"]);
+        assert_eq!(
+            info.checkpoints(),
+            Some(&["a.safetensors".to_owned(), "b.ckpt".to_owned()][..])
+        );
+    }
+
+    #[test]
+    fn test_input_spec() {
+        let info: ObjectInfo = serde_json::from_str(
+            r#"{"KSampler": {
+                "input": {
+                    "required": {
+                        "model": ["MODEL"],
+                        "seed": ["INT", {"default": 0, "min": 0}],
+                        "sampler_name": [["euler", "dpmpp_2m"]],
+                        "scheduler": ["COMBO", {"options": ["normal", "karras"]}]
+                    },
+                    "optional": {"custom": "unexpected"}
+                }
+            }}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            info.samplers(),
+            Some(&["euler".to_owned(), "dpmpp_2m".to_owned()][..])
+        );
+        assert_eq!(
+            info.schedulers(),
+            Some(&["normal".to_owned(), "karras".to_owned()][..])
+        );
+        assert_eq!(info.loras(), None);
+
+        let node = info.node("KSampler").unwrap();
+        let seed = node.input("seed").unwrap();
+        assert_eq!(seed.input_type, InputType::Type("INT".to_owned()));
+        assert_eq!(seed.default_value(), Some(&Value::from(0)));
+        assert!(matches!(
+            node.input("custom").unwrap().input_type,
+            InputType::Unknown(_)
+        ));
+        let mut names = node.input_names().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            vec!["custom", "model", "sampler_name", "scheduler", "seed"]
+        );
+
+        let value = serde_json::to_value(seed).unwrap();
+        assert_eq!(value, serde_json::json!(["INT", {"default": 0, "min": 0}]));
+    }
+}
@@ -310,6 +310,50 @@ impl Node for CLIPTextEncode {
     }
 }
 
+/// Struct representing a CLIPTextEncodeSDXL node.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CLIPTextEncodeSDXL {
+    /// The CLIP model input connection.
+    pub clip: NodeConnection,
+    /// The text to encode with the CLIP-G model.
+    pub text_g: Input<String>,
+    /// The text to encode with the CLIP-L model.
+    pub text_l: Input<String>,
+    /// The original image width used for size conditioning.
+    pub width: Input<u32>,
+    /// The original image height used for size conditioning.
+    pub height: Input<u32>,
+    /// The horizontal crop offset used for conditioning.
+    pub crop_w: Input<u32>,
+    /// The vertical crop offset used for conditioning.
+    pub crop_h: Input<u32>,
+    /// The target image width used for size conditioning.
+    pub target_width: Input<u32>,
+    /// The target image height used for size conditioning.
+    pub target_height: Input<u32>,
+}
+
+#[typetag::serde]
+impl Node for CLIPTextEncodeSDXL {
+    fn connections(&'_ self) -> Box<dyn Iterator<Item = &str> + '_> {
+        Box::new(
+            [
+                Some(self.clip.node_id.as_str()),
+                self.text_g.node_id(),
+                self.text_l.node_id(),
+                self.width.node_id(),
+                self.height.node_id(),
+                self.crop_w.node_id(),
+                self.crop_h.node_id(),
+                self.target_width.node_id(),
+                self.target_height.node_id(),
+            ]
+            .into_iter()
+            .flatten(),
+        )
+    }
+}
+
 /// Struct representing an EmptyLatentImage node.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct EmptyLatentImage {
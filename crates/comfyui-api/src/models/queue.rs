@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+
+use super::PromptResult;
+
+/// Struct representing a response from the ComfyUI API `queue` endpoint.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Queue {
+    /// Prompts that are executing.
+    pub queue_running: Vec<PromptResult>,
+    /// Prompts waiting to execute.
+    pub queue_pending: Vec<PromptResult>,
+}
+
+impl Queue {
+    /// Returns the ids of the prompts that are executing.
+    pub fn running_ids(&self) -> Vec<uuid::Uuid> {
+        self.queue_running.iter().map(|prompt| prompt.id).collect()
+    }
+
+    /// Returns the ids of the prompts waiting to execute, in the order they will execute.
+    pub fn pending_ids(&self) -> Vec<uuid::Uuid> {
+        let mut pending = self.queue_pending.iter().collect::<Vec<_>>();
+        pending.sort_by_key(|prompt| prompt.num);
+        pending.into_iter().map(|prompt| prompt.id).collect()
+    }
+
+    /// Returns the number of prompts that are executing or waiting to execute.
+    pub fn len(&self) -> usize {
+        self.queue_running.len() + self.queue_pending.len()
+    }
+
+    /// Returns whether no prompts are executing or waiting to execute.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue() {
+        let prompt = |num: u64, id: &str| format!(r#"[{num}, "{id}", {{}}, {{}}, ["9"]]"#);
+        let running = "11111111-1111-1111-1111-111111111111";
+        let first = "22222222-2222-2222-2222-222222222222";
+        let second = "33333333-3333-3333-3333-333333333333";
+        let queue: Queue = serde_json::from_str(&format!(
+            r#"{{"queue_running": [{}], "queue_pending": [{}, {}]}}"#,
+            prompt(1, running),
+            prompt(3, second),
+            prompt(2, first)
+        ))
+        .unwrap();
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.running_ids(), vec![running.parse().unwrap()]);
+        assert_eq!(
+            queue.pending_ids(),
+            vec![
+                first.parse().unwrap(),
+                second.parse::<uuid::Uuid>().unwrap()
+            ]
+        );
+    }
+}
@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+/// Struct representing a response from the ComfyUI API `system_stats` endpoint.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SystemStats {
+    /// Information about the system running ComfyUI.
+    pub system: SystemInfo,
+    /// Devices available to ComfyUI.
+    #[serde(default)]
+    pub devices: Vec<Device>,
+}
+
+/// Struct representing information about the system running ComfyUI.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SystemInfo {
+    /// The operating system.
+    pub os: String,
+    /// The version of Python.
+    pub python_version: String,
+    /// The version of ComfyUI. Only reported by recent versions.
+    #[serde(default)]
+    pub comfyui_version: Option<String>,
+    /// The version of PyTorch. Only reported by recent versions.
+    #[serde(default)]
+    pub pytorch_version: Option<String>,
+}
+
+/// Struct representing a device available to ComfyUI.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Device {
+    /// The name of the device.
+    pub name: String,
+    /// The type of the device, e.g. `cuda`.
+    #[serde(rename = "type")]
+    pub device_type: String,
+    /// Total VRAM in bytes.
+    #[serde(default)]
+    pub vram_total: u64,
+    /// Free VRAM in bytes.
+    #[serde(default)]
+    pub vram_free: u64,
+}
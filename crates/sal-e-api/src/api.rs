@@ -1,22 +1,199 @@
-use anyhow::Context;
+use std::{future::Future, time::Duration};
+
+use anyhow::{anyhow, Context};
+use async_stream::try_stream;
 use async_trait::async_trait;
-use comfyui_api::{comfy::getter::*, models::AsAny};
+use comfyui_api::{
+    api::{ImageType, UploadOptions},
+    comfy::getter::*,
+    models::AsAny,
+};
 use dyn_clone::DynClone;
-use stable_diffusion_api::{Img2ImgRequest, Txt2ImgRequest};
+use futures_util::stream::BoxStream;
+use stable_diffusion_api::{Img2ImgRequest, Txt2ImgRequest, UpscaleRequest};
+use tracing::{instrument, warn};
 
-use crate::{ComfyParams, Img2ImgParams, Txt2ImgParams};
+use crate::{error::BackendFailure, ComfyParams, Img2ImgParams, Txt2ImgParams};
+
+/// Struct representing a single generated image.
+#[derive(Debug, Clone, Default)]
+pub struct GeneratedImage {
+    /// The encoded image data.
+    pub data: Vec<u8>,
+    /// The seed used to generate this image, if known.
+    pub seed: Option<i64>,
+    /// The name of the node or step that produced the image, if known.
+    pub node: Option<String>,
+    /// The MIME type of the image, if recognized.
+    pub mime_type: Option<&'static str>,
+}
+
+impl GeneratedImage {
+    /// Constructs a new `GeneratedImage`, detecting the MIME type from the image data.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The encoded image data.
+    pub fn new(data: Vec<u8>) -> Self {
+        Self {
+            mime_type: crate::image_header::image_mime_type(&data),
+            data,
+            ..Default::default()
+        }
+    }
+
+    /// Sets the seed used to generate the image.
+    pub fn with_seed(mut self, seed: Option<i64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the name of the node that produced the image.
+    pub fn with_node(mut self, node: Option<String>) -> Self {
+        self.node = node;
+        self
+    }
+
+    /// Returns the `(width, height)` of the image, read from the image data.
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        crate::image_header::image_dimensions(&self.data)
+    }
+}
 
 /// Struct representing a response from a Stable Diffusion API image generation endpoint.
 #[derive(Debug, Clone)]
 pub struct Response {
     /// A vector of images.
-    pub images: Vec<Vec<u8>>,
+    pub images: Vec<GeneratedImage>,
     /// The parameters describing the generated image.
     pub params: Box<dyn crate::image_params::ImageParams>,
     /// The parameters that were provided for the generation request.
     pub gen_params: Box<dyn crate::gen_params::GenParams>,
 }
 
+impl Response {
+    /// Returns the dimensions of each image, read from the image data.
+    ///
+    /// # Returns
+    ///
+    /// A vector with the `(width, height)` of each image, or `None` for images whose format
+    /// was not recognized.
+    pub fn image_dimensions(&self) -> Vec<Option<(u32, u32)>> {
+        self.images.iter().map(GeneratedImage::dimensions).collect()
+    }
+}
+
+/// Struct representing an image yielded by `Txt2ImgApi::stream_txt2img` as soon as it's
+/// generated.
+#[derive(Debug, Clone)]
+pub struct ImageChunk {
+    /// The position of the image among the images generated by the request, starting at 0.
+    pub index: usize,
+    /// The generated image.
+    pub image: GeneratedImage,
+}
+
+/// A stream of the images generated by a request, ending after the last image or the first
+/// error.
+pub type ImageStream<'a> = BoxStream<'a, Result<ImageChunk, Txt2ImgApiError>>;
+
+/// Struct representing the progress of a running generation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenerationProgress {
+    /// The number of steps completed, e.g. sampling steps.
+    pub value: u64,
+    /// The total number of steps.
+    pub max: u64,
+    /// The estimated time until the generation completes, if the backend reports it.
+    pub eta: Option<Duration>,
+}
+
+impl GenerationProgress {
+    /// Returns the progress as a percentage, or `None` if the total number of steps is unknown.
+    pub fn percent(&self) -> Option<u64> {
+        (self.max > 0).then(|| self.value.min(self.max) * 100 / self.max)
+    }
+}
+
+/// A function that's called with the progress of a generation as it runs.
+pub type OnProgress<'a> = dyn Fn(GenerationProgress) + Send + Sync + 'a;
+
+/// Struct representing a generation a backend is running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunningJob {
+    /// Identifies the generation, e.g. by when it started.
+    pub id: String,
+    /// The progress of the generation.
+    pub progress: GenerationProgress,
+}
+
+dyn_clone::clone_trait_object!(ProgressApi);
+
+/// Struct representing how much of a device's memory is in use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VramUsage {
+    /// Used memory in bytes.
+    pub used: u64,
+    /// Total memory in bytes.
+    pub total: u64,
+}
+
+/// Struct representing what a backend reported about its state when it was pinged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackendStatus {
+    /// The model the backend generates with by default, if it reports it.
+    pub model: Option<String>,
+    /// The VRAM usage of the backend's GPU, if it reports it.
+    pub vram: Option<VramUsage>,
+}
+
+/// Trait representing an endpoint that can be polled for the progress of the generation a
+/// backend is running, for backends that don't report progress while a request runs.
+#[async_trait]
+pub trait ProgressApi: std::fmt::Debug + DynClone + Send + Sync + AsAny {
+    /// Returns the generation the backend is currently running, which may have been requested
+    /// by another client.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the running generation, or `None` if the backend is idle, on
+    /// success, or an error if the request failed.
+    async fn progress(&self) -> anyhow::Result<Option<RunningJob>>;
+}
+
+/// How often to poll for the progress of a generation.
+const PROGRESS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Runs `request`, polling `api` for progress and reporting it to `on_progress` until the
+/// request completes.
+///
+/// Backends run generations one at a time and only report the progress of the one that's
+/// running, so generations that were running before `request` was sent are skipped. If other
+/// clients have generations queued, their progress may still be reported.
+async fn with_polled_progress<T>(
+    api: &dyn ProgressApi,
+    on_progress: &OnProgress<'_>,
+    request: impl Future<Output = T>,
+) -> T {
+    let previous = api.progress().await.ok().flatten().map(|job| job.id);
+    let poll = async {
+        let mut interval = tokio::time::interval(PROGRESS_POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            // Progress is only informational, so failures here aren't worth logging.
+            if let Ok(Some(job)) = api.progress().await {
+                if Some(&job.id) != previous.as_ref() {
+                    on_progress(job.progress);
+                }
+            }
+        }
+    };
+    tokio::select! {
+        result = request => result,
+        () = poll => unreachable!("Polling for progress never completes"),
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 #[non_exhaustive]
 pub enum ComfyPromptApiError {
@@ -30,7 +207,8 @@ pub enum ComfyPromptApiError {
 pub struct ComfyPromptApi {
     /// The ComfyUI client.
     pub client: comfyui_api::comfy::Comfy,
-    /// Default parameters for the ComfyUI API.
+    /// Default parameters for the ComfyUI API. Their seed mode applies to users whose settings
+    /// don't have one yet.
     pub params: crate::gen_params::ComfyParams,
     /// The output node.
     pub output_node: Option<String>,
@@ -122,20 +300,67 @@ impl ComfyPromptApi {
     }
 }
 
+impl ComfyPromptApi {
+    /// Returns the parameters of the workflow as executed by the server for the given outputs,
+    /// falling back to the submitted prompt if they can't be retrieved.
+    async fn executed_params(
+        &self,
+        prompt: &comfyui_api::models::Prompt,
+        outputs: &[comfyui_api::comfy::NodeOutput],
+    ) -> Box<dyn crate::image_params::ImageParams> {
+        if let Some(output) = outputs.first() {
+            match self
+                .client
+                .image_info(&output.prompt_id, &output.node)
+                .await
+            {
+                Ok(info) => return Box::new(info),
+                Err(e) => warn!("Failed to get executed image info: {:?}", e),
+            }
+        }
+        Box::new(prompt.clone())
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 #[non_exhaustive]
 pub enum Txt2ImgApiError {
     /// Prompt was empty.
     #[error("Prompt was empty.")]
     EmptyPrompt,
+    /// The backend couldn't be reached or didn't respond in time.
+    #[error("Backend is unreachable.")]
+    Unreachable(#[source] anyhow::Error),
+    /// The backend rejected the parameters, e.g. an unknown sampler or model.
+    #[error("Backend rejected the parameters.")]
+    InvalidParameters(#[source] anyhow::Error),
+    /// The backend accepted the request but failed to generate.
+    #[error("Backend failed to generate.")]
+    Server(#[source] anyhow::Error),
     /// Error running txt2img.
     #[error("Error running txt2img.")]
-    Txt2Img(#[from] anyhow::Error),
+    Txt2Img(#[source] anyhow::Error),
     /// Error parsing response.
     #[error("Error parsing response.")]
     ParseResponse(#[source] anyhow::Error),
 }
 
+impl From<anyhow::Error> for Txt2ImgApiError {
+    /// Classifies an error from a backend by its cause, falling back to `Txt2Img`.
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<Self>() {
+            Ok(e) => return e,
+            Err(error) => error,
+        };
+        match BackendFailure::classify(&error) {
+            Some(BackendFailure::Unreachable) => Self::Unreachable(error),
+            Some(BackendFailure::InvalidParameters) => Self::InvalidParameters(error),
+            Some(BackendFailure::Server) => Self::Server(error),
+            None => Self::Txt2Img(error),
+        }
+    }
+}
+
 dyn_clone::clone_trait_object!(Txt2ImgApi);
 
 /// Trait representing a Txt2Img endpoint.
@@ -155,6 +380,49 @@ pub trait Txt2ImgApi: std::fmt::Debug + DynClone + Send + Sync + AsAny {
         config: &dyn crate::gen_params::GenParams,
     ) -> Result<Response, Txt2ImgApiError>;
 
+    /// Generates an image using text-to-image, calling `on_progress` as the generation
+    /// runs. Backends that don't report progress never call it.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration to use for the generation.
+    /// * `on_progress` - A function called with each progress update.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Response` on success, or an error if the request failed.
+    async fn txt2img_with_progress(
+        &self,
+        config: &dyn crate::gen_params::GenParams,
+        _on_progress: &OnProgress<'_>,
+    ) -> Result<Response, Txt2ImgApiError> {
+        self.txt2img(config).await
+    }
+
+    /// Generates images using text-to-image, yielding each image as soon as it's generated
+    /// rather than waiting for the whole batch. Backends that can't send images early yield
+    /// them all once the generation completes.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration to use for the generation.
+    ///
+    /// # Returns
+    ///
+    /// An `ImageStream` of the generated images, which ends with an error if the request
+    /// failed.
+    fn stream_txt2img<'a>(
+        &'a self,
+        config: &'a dyn crate::gen_params::GenParams,
+    ) -> ImageStream<'a> {
+        Box::pin(try_stream! {
+            let resp = self.txt2img(config).await?;
+            for (index, image) in resp.images.into_iter().enumerate() {
+                yield ImageChunk { index, image };
+            }
+        })
+    }
+
     /// Returns the default generation parameters for this endpoint.
     ///
     /// # Arguments
@@ -168,6 +436,81 @@ pub trait Txt2ImgApi: std::fmt::Debug + DynClone + Send + Sync + AsAny {
         &self,
         user_settings: Option<&dyn crate::gen_params::GenParams>,
     ) -> Box<dyn crate::gen_params::GenParams>;
+
+    /// Returns the name and version of the backend serving this endpoint, for diagnostics.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a description of the backend on success, or an error if it couldn't
+    /// be detected.
+    async fn backend_version(&self) -> anyhow::Result<String> {
+        Err(anyhow!("Backend version detection is not supported"))
+    }
+
+    /// Returns the number of jobs that are running or waiting to run on the backend.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the length of the queue on success, or an error if the backend
+    /// doesn't report it.
+    async fn queue_length(&self) -> anyhow::Result<u64> {
+        Err(anyhow!("Queue length is not supported"))
+    }
+
+    /// Checks that the backend is reachable. Backends that can't report their state are
+    /// reachable if they report their version.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing what the backend reported about its state on success, or an error
+    /// if it couldn't be reached.
+    async fn ping(&self) -> anyhow::Result<BackendStatus> {
+        self.backend_version()
+            .await
+            .map(|_| BackendStatus::default())
+    }
+
+    /// Returns the models that can be selected with `GenParams::set_model`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the model names on success, or an error if the backend doesn't
+    /// support listing models.
+    async fn models(&self) -> anyhow::Result<Vec<String>> {
+        Err(anyhow!("Listing models is not supported"))
+    }
+
+    /// Returns the LoRAs that can be applied with `<lora:name:weight>` in a prompt.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the LoRA names on success, or an error if the backend doesn't
+    /// support listing LoRAs.
+    async fn loras(&self) -> anyhow::Result<Vec<String>> {
+        Err(anyhow!("Listing LoRAs is not supported"))
+    }
+
+    /// Returns the VAEs that can be selected with `GenParams::set_vae`, not including
+    /// `AUTOMATIC_VAE`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the VAE names on success, or an error if the backend doesn't
+    /// support listing VAEs.
+    async fn vaes(&self) -> anyhow::Result<Vec<String>> {
+        Err(anyhow!("Listing VAEs is not supported"))
+    }
+
+    /// Interrupts the generation the backend is currently running, which may have been
+    /// requested by another client.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `()` on success, or an error if the backend doesn't support
+    /// interrupting generations.
+    async fn interrupt(&self) -> anyhow::Result<()> {
+        Err(anyhow!("Interrupting generations is not supported"))
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -176,9 +519,18 @@ pub enum Img2ImgApiError {
     /// Prompt was empty.
     #[error("Prompt was empty.")]
     EmptyPrompt,
-    /// Error running txt2img.
+    /// The backend couldn't be reached or didn't respond in time.
+    #[error("Backend is unreachable.")]
+    Unreachable(#[source] anyhow::Error),
+    /// The backend rejected the parameters, e.g. an unknown sampler or model.
+    #[error("Backend rejected the parameters.")]
+    InvalidParameters(#[source] anyhow::Error),
+    /// The backend accepted the request but failed to generate.
+    #[error("Backend failed to generate.")]
+    Server(#[source] anyhow::Error),
+    /// Error running img2img.
     #[error("Error running img2img.")]
-    Img2Img(#[from] anyhow::Error),
+    Img2Img(#[source] anyhow::Error),
     /// Error parsing response.
     #[error("Error parsing response.")]
     ParseResponse(#[source] anyhow::Error),
@@ -190,6 +542,22 @@ pub enum Img2ImgApiError {
     UploadImage(#[source] anyhow::Error),
 }
 
+impl From<anyhow::Error> for Img2ImgApiError {
+    /// Classifies an error from a backend by its cause, falling back to `Img2Img`.
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<Self>() {
+            Ok(e) => return e,
+            Err(error) => error,
+        };
+        match BackendFailure::classify(&error) {
+            Some(BackendFailure::Unreachable) => Self::Unreachable(error),
+            Some(BackendFailure::InvalidParameters) => Self::InvalidParameters(error),
+            Some(BackendFailure::Server) => Self::Server(error),
+            None => Self::Img2Img(error),
+        }
+    }
+}
+
 dyn_clone::clone_trait_object!(Img2ImgApi);
 
 /// Trait representing an Img2Img endpoint.
@@ -209,6 +577,25 @@ pub trait Img2ImgApi: std::fmt::Debug + DynClone + Send + Sync + AsAny {
         config: &dyn crate::gen_params::GenParams,
     ) -> Result<Response, Img2ImgApiError>;
 
+    /// Generates an image using image-to-image, calling `on_progress` as the generation
+    /// runs. Backends that don't report progress never call it.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration to use for the generation.
+    /// * `on_progress` - A function called with each progress update.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Response` on success, or an error if the request failed.
+    async fn img2img_with_progress(
+        &self,
+        config: &dyn crate::gen_params::GenParams,
+        _on_progress: &OnProgress<'_>,
+    ) -> Result<Response, Img2ImgApiError> {
+        self.img2img(config).await
+    }
+
     /// Returns the default generation parameters for this endpoint.
     ///
     /// # Arguments
@@ -222,6 +609,73 @@ pub trait Img2ImgApi: std::fmt::Debug + DynClone + Send + Sync + AsAny {
         &self,
         user_settings: Option<&dyn crate::gen_params::GenParams>,
     ) -> Box<dyn crate::gen_params::GenParams>;
+
+    /// Returns the name and version of the backend serving this endpoint, for diagnostics.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a description of the backend on success, or an error if it couldn't
+    /// be detected.
+    async fn backend_version(&self) -> anyhow::Result<String> {
+        Err(anyhow!("Backend version detection is not supported"))
+    }
+
+    /// Returns the number of jobs that are running or waiting to run on the backend.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the length of the queue on success, or an error if the backend
+    /// doesn't report it.
+    async fn queue_length(&self) -> anyhow::Result<u64> {
+        Err(anyhow!("Queue length is not supported"))
+    }
+
+    /// Interrupts the generation the backend is currently running, which may have been
+    /// requested by another client.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `()` on success, or an error if the backend doesn't support
+    /// interrupting generations.
+    async fn interrupt(&self) -> anyhow::Result<()> {
+        Err(anyhow!("Interrupting generations is not supported"))
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum UpscaleApiError {
+    /// Error running upscale.
+    #[error("Error running upscale.")]
+    Upscale(#[from] anyhow::Error),
+    /// Error parsing response.
+    #[error("Error parsing response.")]
+    ParseResponse(#[source] anyhow::Error),
+}
+
+dyn_clone::clone_trait_object!(UpscaleApi);
+
+/// Trait representing an endpoint for upscaling images.
+#[async_trait]
+pub trait UpscaleApi: std::fmt::Debug + DynClone + Send + Sync + AsAny {
+    /// Upscales an image.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - The encoded image to upscale.
+    /// * `scale` - The factor to scale the image by.
+    /// * `upscaler` - The name of the upscaler model to use.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the encoded upscaled image on success, or an error if the request
+    /// failed.
+    async fn upscale(
+        &self,
+        image: &[u8],
+        scale: f32,
+        upscaler: &str,
+    ) -> Result<Vec<u8>, UpscaleApiError>;
 }
 
 #[async_trait]
@@ -229,28 +683,76 @@ impl Txt2ImgApi for ComfyPromptApi {
     async fn txt2img(
         &self,
         config: &dyn crate::gen_params::GenParams,
+    ) -> Result<Response, Txt2ImgApiError> {
+        self.txt2img_with_progress(config, &|_| {}).await
+    }
+
+    #[instrument(skip_all, fields(backend = "comfyui"))]
+    async fn txt2img_with_progress(
+        &self,
+        config: &dyn crate::gen_params::GenParams,
+        on_progress: &OnProgress<'_>,
     ) -> Result<Response, Txt2ImgApiError> {
         let base_prompt = config.as_any().downcast_ref().unwrap_or(&self.params);
 
         let mut new_prompt = base_prompt.clone();
-        if let Some(-1) = new_prompt.seed {
-            new_prompt.seed = Some(rand::random::<i64>().abs());
-        }
+        new_prompt.seed = new_prompt.seed.map(crate::resolve_seed);
 
         let prompt = new_prompt.apply().context(Txt2ImgApiError::EmptyPrompt)?;
 
         let images = self
             .client
-            .execute_prompt(&prompt)
+            .execute_prompt_with_progress(&prompt, |value, max| {
+                on_progress(GenerationProgress {
+                    value,
+                    max,
+                    eta: None,
+                })
+            })
             .await
             .context("Failed to execute prompt")?;
+        let params = self.executed_params(&prompt, &images).await;
         Ok(Response {
-            images: images.into_iter().map(|image| image.image).collect(),
-            params: Box::new(prompt),
+            images: comfy_images(images, params.seed()),
+            params,
             gen_params: Box::new(base_prompt.clone()),
         })
     }
 
+    /// Yields each image as soon as the node that generated it has executed.
+    fn stream_txt2img<'a>(
+        &'a self,
+        config: &'a dyn crate::gen_params::GenParams,
+    ) -> ImageStream<'a> {
+        Box::pin(try_stream! {
+            let mut new_prompt = config
+                .as_any()
+                .downcast_ref()
+                .unwrap_or(&self.params)
+                .clone();
+            new_prompt.seed = new_prompt.seed.map(crate::resolve_seed);
+            let seed = new_prompt.seed;
+
+            let prompt = new_prompt.apply().context(Txt2ImgApiError::EmptyPrompt)?;
+            let outputs = self
+                .client
+                .stream_prompt(&prompt)
+                .await
+                .context("Failed to execute prompt")?;
+            let mut index = 0;
+            for await output in outputs {
+                let output = output.context("Failed to execute prompt")?;
+                yield ImageChunk {
+                    index,
+                    image: GeneratedImage::new(output.image)
+                        .with_seed(seed)
+                        .with_node(Some(output.node)),
+                };
+                index += 1;
+            }
+        })
+    }
+
     fn gen_params(
         &self,
         user_settings: Option<&dyn crate::gen_params::GenParams>,
@@ -258,11 +760,128 @@ impl Txt2ImgApi for ComfyPromptApi {
         if let Some(user_settings) = user_settings {
             let mut params = ComfyParams::from(user_settings);
             params.prompt = self.params.prompt.clone();
+            if user_settings.seed_mode().is_none() {
+                params.seed_mode = self.params.seed_mode;
+            }
             Box::new(params)
         } else {
             Box::new(self.params.clone())
         }
     }
+
+    #[instrument(skip_all, fields(backend = "comfyui"))]
+    async fn backend_version(&self) -> anyhow::Result<String> {
+        let stats = self
+            .client
+            .system_stats()
+            .await
+            .context("Failed to get system stats")?;
+        let mut version = format!(
+            "ComfyUI {}",
+            stats
+                .system
+                .comfyui_version
+                .as_deref()
+                .unwrap_or("(unknown version)")
+        );
+        if let Some(device) = stats.devices.first() {
+            version.push_str(&format!(" on {}", device.name));
+        }
+        Ok(version)
+    }
+
+    #[instrument(skip_all, fields(backend = "comfyui"))]
+    async fn queue_length(&self) -> anyhow::Result<u64> {
+        self.client
+            .queue_remaining()
+            .await
+            .context("Failed to get queue length")
+    }
+
+    #[instrument(skip_all, fields(backend = "comfyui"))]
+    async fn ping(&self) -> anyhow::Result<BackendStatus> {
+        let stats = self
+            .client
+            .system_stats()
+            .await
+            .context("Failed to get system stats")?;
+        // ComfyUI loads models as workflows need them, so report the workflow's.
+        Ok(BackendStatus {
+            model: crate::gen_params::GenParams::model(&self.params),
+            vram: stats
+                .devices
+                .first()
+                .filter(|device| device.vram_total > 0)
+                .map(|device| VramUsage {
+                    used: device.vram_total.saturating_sub(device.vram_free),
+                    total: device.vram_total,
+                }),
+        })
+    }
+
+    #[instrument(skip_all, fields(backend = "comfyui"))]
+    async fn interrupt(&self) -> anyhow::Result<()> {
+        self.client
+            .interrupt()
+            .await
+            .context("Failed to interrupt execution")
+    }
+
+    #[instrument(skip_all, fields(backend = "comfyui"))]
+    async fn models(&self) -> anyhow::Result<Vec<String>> {
+        self.client
+            .checkpoints()
+            .await
+            .context("Failed to get checkpoints")
+    }
+
+    #[instrument(skip_all, fields(backend = "comfyui"))]
+    async fn loras(&self) -> anyhow::Result<Vec<String>> {
+        let loras = self.client.loras().await.context("Failed to get LoRAs")?;
+        // Prompts refer to LoRAs by name, without the folder or extension of the file.
+        Ok(loras
+            .iter()
+            .map(|lora| lora_name(lora).to_owned())
+            .collect())
+    }
+}
+
+/// Returns the name of the LoRA in `file`, e.g. `detail` for `styles/detail.safetensors`.
+fn lora_name(file: &str) -> &str {
+    let name = file.rsplit(['/', '\\']).next().unwrap_or(file);
+    name.rsplit_once('.').map_or(name, |(stem, _)| stem)
+}
+
+fn comfy_images(
+    outputs: Vec<comfyui_api::comfy::NodeOutput>,
+    seed: Option<i64>,
+) -> Vec<GeneratedImage> {
+    outputs
+        .into_iter()
+        .map(|output| {
+            GeneratedImage::new(output.image)
+                .with_seed(seed)
+                .with_node(Some(output.node))
+        })
+        .collect()
+}
+
+/// Returns upload options with a filename derived from the image contents, so that repeated
+/// uploads of the same image map to the same file on the backend.
+fn upload_options(image: &[u8]) -> UploadOptions {
+    use sha2::{Digest, Sha256};
+
+    let image_type = ImageType::from_bytes(image).unwrap_or_default();
+    UploadOptions {
+        filename: Some(format!(
+            "{:x}.{}",
+            Sha256::digest(image),
+            image_type.extension()
+        )),
+        image_type,
+        overwrite: true,
+        ..Default::default()
+    }
 }
 
 #[async_trait]
@@ -270,12 +889,21 @@ impl Img2ImgApi for ComfyPromptApi {
     async fn img2img(
         &self,
         config: &dyn crate::gen_params::GenParams,
+    ) -> Result<Response, Img2ImgApiError> {
+        self.img2img_with_progress(config, &|_| {}).await
+    }
+
+    #[instrument(skip_all, fields(backend = "comfyui"))]
+    async fn img2img_with_progress(
+        &self,
+        config: &dyn crate::gen_params::GenParams,
+        on_progress: &OnProgress<'_>,
     ) -> Result<Response, Img2ImgApiError> {
         let base_prompt = config.as_any().downcast_ref().unwrap_or(&self.params);
 
         let resp = if let Some(image) = &base_prompt.image {
             self.client
-                .upload_file(image.clone())
+                .upload_file_with_options(image.clone(), &upload_options(image))
                 .await
                 .context("Failed to upload image")
                 .map_err(Img2ImgApiError::UploadImage)?
@@ -284,9 +912,7 @@ impl Img2ImgApi for ComfyPromptApi {
         };
 
         let mut new_prompt = base_prompt.clone();
-        if let Some(-1) = new_prompt.seed {
-            new_prompt.seed = Some(rand::random::<i64>().abs());
-        }
+        new_prompt.seed = new_prompt.seed.map(crate::resolve_seed);
 
         let mut prompt = new_prompt.apply().context(Img2ImgApiError::EmptyPrompt)?;
 
@@ -294,12 +920,19 @@ impl Img2ImgApi for ComfyPromptApi {
 
         let images = self
             .client
-            .execute_prompt(&prompt)
+            .execute_prompt_with_progress(&prompt, |value, max| {
+                on_progress(GenerationProgress {
+                    value,
+                    max,
+                    eta: None,
+                })
+            })
             .await
             .context("Failed to execute prompt")?;
+        let params = self.executed_params(&prompt, &images).await;
         Ok(Response {
-            images: images.into_iter().map(|image| image.image).collect(),
-            params: Box::new(prompt.clone()),
+            images: comfy_images(images, params.seed()),
+            params,
             gen_params: Box::new(base_prompt.clone()),
         })
     }
@@ -311,11 +944,42 @@ impl Img2ImgApi for ComfyPromptApi {
         if let Some(user_settings) = user_settings {
             let mut params = ComfyParams::from(user_settings);
             params.prompt = self.params.prompt.clone();
+            if user_settings.seed_mode().is_none() {
+                params.seed_mode = self.params.seed_mode;
+            }
             Box::new(params)
         } else {
             Box::new(self.params.clone())
         }
     }
+
+    async fn backend_version(&self) -> anyhow::Result<String> {
+        Txt2ImgApi::backend_version(self).await
+    }
+
+    async fn queue_length(&self) -> anyhow::Result<u64> {
+        Txt2ImgApi::queue_length(self).await
+    }
+
+    async fn interrupt(&self) -> anyhow::Result<()> {
+        Txt2ImgApi::interrupt(self).await
+    }
+}
+
+fn webui_images(images: Vec<Vec<u8>>, info: &stable_diffusion_api::ImgInfo) -> Vec<GeneratedImage> {
+    // Batches may be preceded by a grid image, which has no seed of its own.
+    let first = info.index_of_first_image.unwrap_or_default() as usize;
+    images
+        .into_iter()
+        .enumerate()
+        .map(|(index, data)| {
+            let seed = index
+                .checked_sub(first)
+                .and_then(|index| info.all_seeds.as_ref()?.get(index).copied())
+                .or(if index >= first { info.seed } else { None });
+            GeneratedImage::new(data).with_seed(seed)
+        })
+        .collect()
 }
 
 /// Struct wrapping a connection to the Stable Diffusion WebUI API.
@@ -341,14 +1005,22 @@ impl Txt2ImgApi for StableDiffusionWebUiApi {
     async fn txt2img(
         &self,
         config: &dyn crate::gen_params::GenParams,
+    ) -> Result<Response, Txt2ImgApiError> {
+        self.txt2img_with_progress(config, &|_| {}).await
+    }
+
+    #[instrument(skip_all, fields(backend = "webui"))]
+    async fn txt2img_with_progress(
+        &self,
+        config: &dyn crate::gen_params::GenParams,
+        on_progress: &OnProgress<'_>,
     ) -> Result<Response, Txt2ImgApiError> {
         let config = Txt2ImgParams::from(config);
         let txt2img = self
             .client
             .txt2img()
             .context("Failed to open txt2img API")?;
-        let resp = txt2img
-            .send(&config.user_params)
+        let resp = with_polled_progress(self, on_progress, txt2img.send(&config.user_params))
             .await
             .context("Failed to send request")?;
         let params = Box::new(
@@ -356,11 +1028,12 @@ impl Txt2ImgApi for StableDiffusionWebUiApi {
                 .context("Failed to parse info from response")
                 .map_err(Txt2ImgApiError::ParseResponse)?,
         );
+        let images = resp
+            .images()
+            .context("Failed to parse image from response")
+            .map_err(Txt2ImgApiError::ParseResponse)?;
         Ok(Response {
-            images: resp
-                .images()
-                .context("Failed to parse image from response")
-                .map_err(Txt2ImgApiError::ParseResponse)?,
+            images: webui_images(images, &params),
             params: params.clone(),
             gen_params: Box::new(Txt2ImgParams {
                 user_params: resp.parameters.clone(),
@@ -369,6 +1042,33 @@ impl Txt2ImgApi for StableDiffusionWebUiApi {
         })
     }
 
+    /// The WebUI only returns images once a request completes, so the batches of the request
+    /// are generated one request at a time, yielding the images of each batch when it's done.
+    fn stream_txt2img<'a>(
+        &'a self,
+        config: &'a dyn crate::gen_params::GenParams,
+    ) -> ImageStream<'a> {
+        Box::pin(try_stream! {
+            let count = config.count().unwrap_or(1).max(1);
+            let batch_size = config.batch_size().unwrap_or(1).max(1);
+            // Batches after the first continue from the seed, as they would in one request.
+            let seed = config.seed().filter(|seed| !crate::is_random_seed(*seed));
+            let mut batch = dyn_clone::clone_box(config);
+            batch.set_count(1);
+            let mut index = 0;
+            for n in 0..count {
+                if let Some(seed) = seed {
+                    batch.set_seed(seed.saturating_add(i64::from(n) * i64::from(batch_size)));
+                }
+                let resp = self.txt2img(batch.as_ref()).await?;
+                for image in resp.images {
+                    yield ImageChunk { index, image };
+                    index += 1;
+                }
+            }
+        })
+    }
+
     fn gen_params(
         &self,
         user_settings: Option<&dyn crate::gen_params::GenParams>,
@@ -385,6 +1085,90 @@ impl Txt2ImgApi for StableDiffusionWebUiApi {
             })
         }
     }
+
+    #[instrument(skip_all, fields(backend = "webui"))]
+    async fn backend_version(&self) -> anyhow::Result<String> {
+        let version = self
+            .client
+            .version()
+            .context("Failed to open version API")?
+            .get()
+            .await
+            .context("Failed to get version")?;
+        Ok(format!("Stable Diffusion WebUI {version}"))
+    }
+
+    #[instrument(skip_all, fields(backend = "webui"))]
+    async fn ping(&self) -> anyhow::Result<BackendStatus> {
+        let options = self
+            .client
+            .options()
+            .context("Failed to open options API")?
+            .get()
+            .await
+            .context("Failed to get options")?;
+        // Not every WebUI version reports memory usage, so it's optional.
+        let memory = match self.client.memory() {
+            Ok(memory) => memory.get().await.ok(),
+            Err(_) => None,
+        };
+        Ok(BackendStatus {
+            model: options.sd_model_checkpoint,
+            vram: memory
+                .and_then(|memory| memory.cuda?.system)
+                .filter(|usage| usage.total > 0)
+                .map(|usage| VramUsage {
+                    used: usage.used,
+                    total: usage.total,
+                }),
+        })
+    }
+
+    #[instrument(skip_all, fields(backend = "webui"))]
+    async fn interrupt(&self) -> anyhow::Result<()> {
+        self.client
+            .interrupter()
+            .context("Failed to open interrupt API")?
+            .interrupt()
+            .await
+            .context("Failed to interrupt generation")
+    }
+
+    #[instrument(skip_all, fields(backend = "webui"))]
+    async fn models(&self) -> anyhow::Result<Vec<String>> {
+        let models = self
+            .client
+            .models()
+            .context("Failed to open models API")?
+            .get()
+            .await
+            .context("Failed to get models")?;
+        Ok(models.into_iter().map(|model| model.title).collect())
+    }
+
+    #[instrument(skip_all, fields(backend = "webui"))]
+    async fn loras(&self) -> anyhow::Result<Vec<String>> {
+        let loras = self
+            .client
+            .loras()
+            .context("Failed to open LoRAs API")?
+            .get()
+            .await
+            .context("Failed to get LoRAs")?;
+        Ok(loras.into_iter().map(|lora| lora.name).collect())
+    }
+
+    #[instrument(skip_all, fields(backend = "webui"))]
+    async fn vaes(&self) -> anyhow::Result<Vec<String>> {
+        let vaes = self
+            .client
+            .vaes()
+            .context("Failed to open VAEs API")?
+            .get()
+            .await
+            .context("Failed to get VAEs")?;
+        Ok(vaes.into_iter().map(|vae| vae.model_name).collect())
+    }
 }
 
 #[async_trait]
@@ -392,14 +1176,22 @@ impl Img2ImgApi for StableDiffusionWebUiApi {
     async fn img2img(
         &self,
         config: &dyn crate::gen_params::GenParams,
+    ) -> Result<Response, Img2ImgApiError> {
+        self.img2img_with_progress(config, &|_| {}).await
+    }
+
+    #[instrument(skip_all, fields(backend = "webui"))]
+    async fn img2img_with_progress(
+        &self,
+        config: &dyn crate::gen_params::GenParams,
+        on_progress: &OnProgress<'_>,
     ) -> Result<Response, Img2ImgApiError> {
         let config = Img2ImgParams::from(config);
         let img2img = self
             .client
             .img2img()
             .context("Failed to open img2img API")?;
-        let resp = img2img
-            .send(&config.user_params)
+        let resp = with_polled_progress(self, on_progress, img2img.send(&config.user_params))
             .await
             .context("Failed to send request")?;
         let params = Box::new(
@@ -407,11 +1199,12 @@ impl Img2ImgApi for StableDiffusionWebUiApi {
                 .context("Failed to parse info from response")
                 .map_err(Img2ImgApiError::ParseResponse)?,
         );
+        let images = resp
+            .images()
+            .context("Failed to parse image from response")
+            .map_err(Img2ImgApiError::ParseResponse)?;
         Ok(Response {
-            images: resp
-                .images()
-                .context("Failed to parse image from response")
-                .map_err(Img2ImgApiError::ParseResponse)?,
+            images: webui_images(images, &params),
             params: params.clone(),
             gen_params: Box::new(Img2ImgParams {
                 user_params: resp.parameters.clone(),
@@ -436,4 +1229,67 @@ impl Img2ImgApi for StableDiffusionWebUiApi {
             })
         }
     }
+
+    async fn backend_version(&self) -> anyhow::Result<String> {
+        Txt2ImgApi::backend_version(self).await
+    }
+
+    async fn interrupt(&self) -> anyhow::Result<()> {
+        Txt2ImgApi::interrupt(self).await
+    }
+}
+
+#[async_trait]
+impl ProgressApi for StableDiffusionWebUiApi {
+    #[instrument(skip_all, fields(backend = "webui"))]
+    async fn progress(&self) -> anyhow::Result<Option<RunningJob>> {
+        let resp = self
+            .client
+            .progress()
+            .context("Failed to open progress API")?
+            .get()
+            .await
+            .context("Failed to get progress")?;
+        if !resp.is_running() {
+            return Ok(None);
+        }
+        // The WebUI reports progress over all of the batches in the job, which is more useful
+        // than the steps of the current batch.
+        Ok(Some(RunningJob {
+            id: resp.state.job_timestamp,
+            progress: GenerationProgress {
+                value: (resp.progress.clamp(0.0, 1.0) * 100.0).round() as u64,
+                max: 100,
+                eta: (resp.progress > 0.0 && resp.eta_relative.is_finite())
+                    .then(|| Duration::from_secs_f64(resp.eta_relative.max(0.0))),
+            },
+        }))
+    }
+}
+
+#[async_trait]
+impl UpscaleApi for StableDiffusionWebUiApi {
+    #[instrument(skip_all, fields(backend = "webui"))]
+    async fn upscale(
+        &self,
+        image: &[u8],
+        scale: f32,
+        upscaler: &str,
+    ) -> Result<Vec<u8>, UpscaleApiError> {
+        let mut request = UpscaleRequest::default();
+        request
+            .with_image(image)
+            .with_scale(scale.into())
+            .with_upscaler(upscaler.to_owned());
+        let resp = self
+            .client
+            .upscaler()
+            .context("Failed to open upscale API")?
+            .send(&request)
+            .await
+            .context("Failed to send request")?;
+        resp.image()
+            .context("Failed to parse image from response")
+            .map_err(UpscaleApiError::ParseResponse)
+    }
 }
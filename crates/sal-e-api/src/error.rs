@@ -0,0 +1,106 @@
+use comfyui_api::{api::PromptApiError, comfy::ComfyApiError};
+use reqwest::StatusCode;
+use stable_diffusion_api::{Img2ImgError, Txt2ImgError};
+
+/// Why a backend failed to generate, as far as can be told from its error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BackendFailure {
+    /// The backend couldn't be reached or didn't respond in time.
+    Unreachable,
+    /// The backend rejected the parameters of the request.
+    InvalidParameters,
+    /// The backend accepted the request but failed to carry it out.
+    Server,
+}
+
+impl BackendFailure {
+    fn from_status(status: StatusCode) -> Option<Self> {
+        if status.is_client_error() {
+            Some(Self::InvalidParameters)
+        } else if status.is_server_error() {
+            Some(Self::Server)
+        } else {
+            None
+        }
+    }
+
+    /// Classifies `error` by the first error in its chain that tells why the request failed.
+    pub fn classify(error: &anyhow::Error) -> Option<Self> {
+        error.chain().find_map(|cause| {
+            if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+                if e.is_connect() || e.is_timeout() {
+                    return Some(Self::Unreachable);
+                }
+                return e.status().and_then(Self::from_status);
+            }
+            match cause.downcast_ref::<Txt2ImgError>() {
+                Some(Txt2ImgError::Txt2ImgFailed { status, .. }) => {
+                    return Self::from_status(*status)
+                }
+                Some(_) => return None,
+                None => {}
+            }
+            match cause.downcast_ref::<Img2ImgError>() {
+                Some(Img2ImgError::Img2ImgFailed { status, .. }) => {
+                    return Self::from_status(*status)
+                }
+                Some(_) => return None,
+                None => {}
+            }
+            // ComfyUI validates prompts when they're queued, rejecting invalid ones with a 400.
+            if let Some(PromptApiError::SendPromptFailed { status, .. }) =
+                cause.downcast_ref::<PromptApiError>()
+            {
+                return Self::from_status(*status);
+            }
+            match cause.downcast_ref::<ComfyApiError>() {
+                Some(ComfyApiError::ExecutionError { .. }) => Some(Self::Server),
+                Some(ComfyApiError::ReceiveUpdateFailure(_)) => Some(Self::Unreachable),
+                _ => None,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let rejected: anyhow::Result<()> = Err(Txt2ImgError::Txt2ImgFailed {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            error: "invalid sampler".to_owned(),
+        })
+        .context("Failed to send request");
+        assert_eq!(
+            BackendFailure::classify(&rejected.unwrap_err()),
+            Some(BackendFailure::InvalidParameters)
+        );
+
+        let failed = anyhow::Error::new(ComfyApiError::ExecutionError {
+            exception_type: "torch.OutOfMemoryError".to_owned(),
+            exception_message: "CUDA out of memory".to_owned(),
+        });
+        assert_eq!(
+            BackendFailure::classify(&failed),
+            Some(BackendFailure::Server)
+        );
+
+        let queue_error = anyhow::Error::new(PromptApiError::SendPromptFailed {
+            status: StatusCode::BAD_REQUEST,
+            error: "Prompt outputs failed validation".to_owned(),
+        });
+        assert_eq!(
+            BackendFailure::classify(&queue_error),
+            Some(BackendFailure::InvalidParameters)
+        );
+
+        assert_eq!(
+            BackendFailure::classify(&anyhow::anyhow!("Failed to parse image")),
+            None
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::{gen_params::GenParams, ComfyParams};
+
+/// Version of the canonical form, bumped whenever it changes so old fingerprints don't match
+/// new ones by accident.
+const FINGERPRINT_VERSION: &str = "v2";
+
+/// Returns a float in a form that's the same whichever backend it came from: the shortest
+/// decimal that reads back as the same `f32`, with `-0` as `0`.
+fn canonical_float(value: f32) -> Value {
+    if !value.is_finite() {
+        return Value::Null;
+    }
+    let value = if value == 0.0 { 0.0 } else { value };
+    Value::String(value.to_string())
+}
+
+fn canonical_bytes(bytes: &[u8]) -> Value {
+    Value::String(format!("{:x}", Sha256::digest(bytes)))
+}
+
+/// Returns the settings of `params` that affect the generated images, keyed by name. Settings
+/// that aren't set are left out, and the count and batch size default to 1.
+fn canonical_params<P: GenParams + ?Sized>(params: &P) -> BTreeMap<&'static str, Value> {
+    let mut fields = BTreeMap::new();
+    let mut insert = |name, value: Option<Value>| {
+        if let Some(value) = value.filter(|value| !value.is_null()) {
+            fields.insert(name, value);
+        }
+    };
+
+    insert("prompt", params.prompt().map(Value::from));
+    insert("negative_prompt", params.negative_prompt().map(Value::from));
+    insert(
+        "seed",
+        params.seed().map(crate::normalize_seed).map(Value::from),
+    );
+    insert("steps", params.steps().map(Value::from));
+    insert("count", Some(params.count().unwrap_or(1).max(1).into()));
+    insert(
+        "batch_size",
+        Some(params.batch_size().unwrap_or(1).max(1).into()),
+    );
+    insert("cfg", params.cfg().map(canonical_float));
+    insert("width", params.width().map(Value::from));
+    insert("height", params.height().map(Value::from));
+    insert("denoising", params.denoising().map(canonical_float));
+    insert("sampler", params.sampler().map(Value::from));
+    insert("model", params.model().map(Value::from));
+    insert("image", params.image().as_deref().map(canonical_bytes));
+    insert("eta", params.eta().map(canonical_float));
+    insert("s_churn", params.s_churn().map(canonical_float));
+    insert("s_tmin", params.s_tmin().map(canonical_float));
+    insert("s_tmax", params.s_tmax().map(canonical_float));
+    insert("s_noise", params.s_noise().map(canonical_float));
+    insert("restore_faces", params.restore_faces().map(Value::from));
+    insert(
+        "face_restoration_model",
+        params.face_restoration_model().map(Value::from),
+    );
+    insert(
+        "codeformer_weight",
+        params.codeformer_weight().map(canonical_float),
+    );
+    insert("vae", params.vae().map(Value::from));
+    insert(
+        "subseed",
+        params.subseed().map(crate::normalize_seed).map(Value::from),
+    );
+    insert(
+        "subseed_strength",
+        params.subseed_strength().map(canonical_float),
+    );
+    insert("hires_fix", params.hires_fix().map(Value::from));
+    insert("hr_scale", params.hr_scale().map(canonical_float));
+    insert("hr_upscaler", params.hr_upscaler().map(Value::from));
+    insert(
+        "hr_second_pass_steps",
+        params.hr_second_pass_steps().map(Value::from),
+    );
+    insert(
+        "firstphase_width",
+        params.firstphase_width().map(Value::from),
+    );
+    insert(
+        "firstphase_height",
+        params.firstphase_height().map(Value::from),
+    );
+    insert(
+        "refiner_checkpoint",
+        params.refiner_checkpoint().map(Value::from),
+    );
+    insert(
+        "refiner_switch_at",
+        params.refiner_switch_at().map(canonical_float),
+    );
+    insert("mask", params.mask().as_deref().map(canonical_bytes));
+    let comfy = params.as_any().downcast_ref::<ComfyParams>();
+    // Node input overrides change the workflow itself, so they're part of the request. The
+    // map is sorted, so they serialize in a stable order.
+    insert(
+        "overrides",
+        comfy
+            .filter(|params| !params.overrides.is_empty())
+            .and_then(|params| serde_json::to_value(&params.overrides).ok()),
+    );
+    // So is the base workflow. Its nodes are kept in a hash map, so they're sorted by going
+    // through a JSON value before hashing.
+    insert(
+        "workflow",
+        comfy
+            .and_then(|params| params.prompt.as_ref())
+            .and_then(|workflow| serde_json::to_value(workflow).ok())
+            .map(|workflow| canonical_bytes(workflow.to_string().as_bytes())),
+    );
+    fields
+}
+
+/// Returns the fingerprint of `params`. See `GenParams::fingerprint`.
+pub(crate) fn fingerprint<P: GenParams + ?Sized>(params: &P) -> String {
+    let canonical = serde_json::to_string(&canonical_params(params))
+        .expect("A map of JSON values always serializes");
+    let mut hasher = Sha256::new();
+    hasher.update(FINGERPRINT_VERSION);
+    hasher.update(canonical);
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use stable_diffusion_api::Txt2ImgRequest;
+
+    use super::*;
+    use crate::Txt2ImgParams;
+
+    #[test]
+    fn test_fingerprint() {
+        let mut comfy = ComfyParams::default();
+        comfy.set_prompt("a red fox".to_owned());
+        comfy.set_seed(42);
+        comfy.set_cfg(7.5);
+
+        let mut webui = Txt2ImgParams {
+            user_params: Txt2ImgRequest::default(),
+            defaults: None,
+        };
+        webui.set_prompt("a red fox".to_owned());
+        webui.set_seed(42);
+        webui.set_cfg(7.5);
+
+        // The same settings match across backends, however the defaults are stored.
+        assert_eq!(comfy.fingerprint(), webui.fingerprint());
+        assert_eq!(comfy.fingerprint().len(), 64);
+
+        let mut other = comfy.clone();
+        other.set_cfg(7.0);
+        assert_ne!(other.fingerprint(), comfy.fingerprint());
+
+        // Negative seeds all ask for a random seed.
+        let mut random = comfy.clone();
+        random.set_seed(-1);
+        let mut also_random = comfy.clone();
+        also_random.set_seed(-5);
+        assert_eq!(random.fingerprint(), also_random.fingerprint());
+
+        let mut overridden = comfy.clone();
+        overridden
+            .overrides
+            .insert("12.denoise".to_owned(), serde_json::json!(0.55));
+        assert_ne!(overridden.fingerprint(), comfy.fingerprint());
+
+        let workflow = |class_type: &str| {
+            serde_json::from_value(serde_json::json!({
+                "3": {"class_type": class_type, "inputs": {"seed": 1}},
+                "4": {"class_type": "CheckpointLoaderSimple", "inputs": {"ckpt_name": "a"}},
+            }))
+            .unwrap()
+        };
+        let mut with_workflow = comfy.clone();
+        with_workflow.prompt = Some(workflow("KSampler"));
+        let mut same_workflow = comfy.clone();
+        same_workflow.prompt = Some(workflow("KSampler"));
+        let mut other_workflow = comfy.clone();
+        other_workflow.prompt = Some(workflow("KSamplerAdvanced"));
+        assert_ne!(with_workflow.fingerprint(), comfy.fingerprint());
+        assert_eq!(with_workflow.fingerprint(), same_workflow.fingerprint());
+        assert_ne!(with_workflow.fingerprint(), other_workflow.fingerprint());
+    }
+
+    #[test]
+    fn test_canonical_float() {
+        assert_eq!(canonical_float(7.0), Value::from("7"));
+        assert_eq!(canonical_float(-0.0), canonical_float(0.0));
+        assert_eq!(canonical_float(0.1_f64 as f32), Value::from("0.1"));
+        assert_eq!(canonical_float(f32::NAN), Value::Null);
+    }
+}
@@ -1,11 +1,14 @@
+use std::collections::{BTreeMap, HashMap};
+
 use anyhow::Context as _;
 use comfyui_api::{
-    comfy::getter::*,
+    comfy::{getter::*, setter::ParamsSetExt as _, GenericAccessor},
     models::{AsAny, Prompt},
 };
 use dyn_clone::DynClone;
 use serde::{Deserialize, Serialize};
 use stable_diffusion_api::{Img2ImgRequest, Txt2ImgRequest};
+use tracing::warn;
 
 dyn_clone::clone_trait_object!(GenParams);
 
@@ -14,7 +17,7 @@ dyn_clone::clone_trait_object!(GenParams);
 pub trait GenParams: std::fmt::Debug + AsAny + Send + Sync + DynClone {
     /// Gets the seed.
     fn seed(&self) -> Option<i64>;
-    /// Sets the seed.
+    /// Sets the seed. Negative seeds ask for a random seed and are stored as `RANDOM_SEED`.
     fn set_seed(&mut self, seed: i64);
 
     /// Gets the number of steps.
@@ -62,6 +65,11 @@ pub trait GenParams: std::fmt::Debug + AsAny + Send + Sync + DynClone {
     /// Sets the sampler.
     fn set_sampler(&mut self, sampler: String);
 
+    /// Gets the model, i.e. the checkpoint to generate with.
+    fn model(&self) -> Option<String>;
+    /// Sets the model.
+    fn set_model(&mut self, model: String);
+
     /// Gets the batch size.
     fn batch_size(&self) -> Option<u32>;
     /// Sets the batch size.
@@ -71,6 +79,281 @@ pub trait GenParams: std::fmt::Debug + AsAny + Send + Sync + DynClone {
     fn image(&self) -> Option<Vec<u8>>;
     /// Sets the image.
     fn set_image(&mut self, image: Option<Vec<u8>>);
+
+    /// Returns whether the backend supports the advanced sampler settings: eta, s_churn,
+    /// s_tmin, s_tmax and s_noise.
+    fn supports_advanced_sampler(&self) -> bool {
+        false
+    }
+
+    /// Gets the sampler eta.
+    fn eta(&self) -> Option<f32> {
+        None
+    }
+    /// Sets the sampler eta. Ignored if unsupported by the backend.
+    fn set_eta(&mut self, _eta: f32) {}
+
+    /// Gets the sampler churn.
+    fn s_churn(&self) -> Option<f32> {
+        None
+    }
+    /// Sets the sampler churn. Ignored if unsupported by the backend.
+    fn set_s_churn(&mut self, _s_churn: f32) {}
+
+    /// Gets the sampler minimum sigma.
+    fn s_tmin(&self) -> Option<f32> {
+        None
+    }
+    /// Sets the sampler minimum sigma. Ignored if unsupported by the backend.
+    fn set_s_tmin(&mut self, _s_tmin: f32) {}
+
+    /// Gets the sampler maximum sigma.
+    fn s_tmax(&self) -> Option<f32> {
+        None
+    }
+    /// Sets the sampler maximum sigma. Ignored if unsupported by the backend.
+    fn set_s_tmax(&mut self, _s_tmax: f32) {}
+
+    /// Gets the sampler noise multiplier.
+    fn s_noise(&self) -> Option<f32> {
+        None
+    }
+    /// Sets the sampler noise multiplier. Ignored if unsupported by the backend.
+    fn set_s_noise(&mut self, _s_noise: f32) {}
+
+    /// Returns whether the backend supports the face restoration settings: restore_faces,
+    /// face_restoration_model and codeformer_weight.
+    fn supports_face_restoration(&self) -> bool {
+        false
+    }
+
+    /// Gets whether faces are restored.
+    fn restore_faces(&self) -> Option<bool> {
+        None
+    }
+    /// Sets whether faces are restored. Ignored if unsupported by the backend.
+    fn set_restore_faces(&mut self, _restore_faces: bool) {}
+
+    /// Gets the face restoration model, e.g. `CodeFormer` or `GFPGAN`.
+    fn face_restoration_model(&self) -> Option<String> {
+        None
+    }
+    /// Sets the face restoration model. Ignored if unsupported by the backend.
+    fn set_face_restoration_model(&mut self, _model: String) {}
+
+    /// Gets the CodeFormer weight, from 0 for the strongest effect to 1 for the weakest.
+    fn codeformer_weight(&self) -> Option<f32> {
+        None
+    }
+    /// Sets the CodeFormer weight. Ignored if unsupported by the backend.
+    fn set_codeformer_weight(&mut self, _weight: f32) {}
+
+    /// Returns whether the backend supports choosing the VAE.
+    fn supports_vae(&self) -> bool {
+        false
+    }
+
+    /// Gets the VAE that decodes the image, or `AUTOMATIC_VAE` to let the backend pick the one
+    /// matching the model.
+    fn vae(&self) -> Option<String> {
+        None
+    }
+    /// Sets the VAE that decodes the image. Ignored if unsupported by the backend.
+    fn set_vae(&mut self, _vae: String) {}
+
+    /// Returns whether the backend supports variation seeds: subseed and subseed_strength.
+    fn supports_subseed(&self) -> bool {
+        false
+    }
+
+    /// Gets the variation seed, whose noise is blended into the noise of the seed.
+    fn subseed(&self) -> Option<i64> {
+        None
+    }
+    /// Sets the variation seed. Negative seeds ask for a random seed. Ignored if unsupported by
+    /// the backend.
+    fn set_subseed(&mut self, _subseed: i64) {}
+
+    /// Gets the variation strength, from 0 for only the noise of the seed to 1 for only the
+    /// noise of the subseed.
+    fn subseed_strength(&self) -> Option<f32> {
+        None
+    }
+    /// Sets the variation strength. Ignored if unsupported by the backend.
+    fn set_subseed_strength(&mut self, _strength: f32) {}
+
+    /// Gets how a fixed seed changes after each generation, if supported by the backend.
+    fn seed_mode(&self) -> Option<crate::SeedMode> {
+        None
+    }
+    /// Sets how a fixed seed changes after each generation. Ignored if unsupported by the
+    /// backend.
+    fn set_seed_mode(&mut self, _mode: crate::SeedMode) {}
+
+    /// Advances the seed after a generation with `seed`, as the seed mode asks.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - The seed the generation used.
+    fn advance_seed(&mut self, seed: i64) {
+        // A random seed is resolved by the backend, so only a fixed one is advanced.
+        if self.seed().is_some_and(crate::is_random_seed) {
+            return;
+        }
+        if let Some(next) = self.seed_mode().and_then(|mode| mode.next(seed)) {
+            self.set_seed(next);
+        }
+    }
+
+    /// Returns whether the backend supports the hires fix settings: hires_fix, hr_scale,
+    /// hr_upscaler, hr_second_pass_steps, firstphase_width and firstphase_height.
+    fn supports_hires_fix(&self) -> bool {
+        false
+    }
+
+    /// Gets whether the hires fix is enabled, which upscales the image and refines it in a
+    /// second pass.
+    fn hires_fix(&self) -> Option<bool> {
+        None
+    }
+    /// Sets whether the hires fix is enabled. Ignored if unsupported by the backend.
+    fn set_hires_fix(&mut self, _enabled: bool) {}
+
+    /// Gets the factor the hires fix upscales the image by.
+    fn hr_scale(&self) -> Option<f32> {
+        None
+    }
+    /// Sets the factor the hires fix upscales the image by. Ignored if unsupported by the
+    /// backend.
+    fn set_hr_scale(&mut self, _scale: f32) {}
+
+    /// Gets the upscaler used by the hires fix, e.g. `Latent` or `R-ESRGAN 4x+`.
+    fn hr_upscaler(&self) -> Option<String> {
+        None
+    }
+    /// Sets the upscaler used by the hires fix. Ignored if unsupported by the backend.
+    fn set_hr_upscaler(&mut self, _upscaler: String) {}
+
+    /// Gets the number of steps of the hires fix's second pass, where 0 uses the same number as
+    /// the first pass.
+    fn hr_second_pass_steps(&self) -> Option<u32> {
+        None
+    }
+    /// Sets the number of steps of the hires fix's second pass. Ignored if unsupported by the
+    /// backend.
+    fn set_hr_second_pass_steps(&mut self, _steps: u32) {}
+
+    /// Gets the width of the image generated in the hires fix's first pass.
+    fn firstphase_width(&self) -> Option<u32> {
+        None
+    }
+    /// Sets the width of the image generated in the hires fix's first pass. Ignored if
+    /// unsupported by the backend.
+    fn set_firstphase_width(&mut self, _width: u32) {}
+
+    /// Gets the height of the image generated in the hires fix's first pass.
+    fn firstphase_height(&self) -> Option<u32> {
+        None
+    }
+    /// Sets the height of the image generated in the hires fix's first pass. Ignored if
+    /// unsupported by the backend.
+    fn set_firstphase_height(&mut self, _height: u32) {}
+
+    /// Returns whether the backend supports the refiner settings: refiner_checkpoint and
+    /// refiner_switch_at.
+    fn supports_refiner(&self) -> bool {
+        false
+    }
+
+    /// Gets the checkpoint the generation switches to for its last steps, if any.
+    fn refiner_checkpoint(&self) -> Option<String> {
+        None
+    }
+    /// Sets the checkpoint the generation switches to for its last steps, or disables the
+    /// refiner if `None`. Ignored if unsupported by the backend.
+    fn set_refiner_checkpoint(&mut self, _checkpoint: Option<String>) {}
+
+    /// Gets the fraction of the steps, from 0 to 1, after which the refiner is used.
+    fn refiner_switch_at(&self) -> Option<f32> {
+        None
+    }
+    /// Sets the fraction of the steps after which the refiner is used. Ignored if unsupported
+    /// by the backend.
+    fn set_refiner_switch_at(&mut self, _switch_at: f32) {}
+
+    /// Returns whether the backend supports inpainting with a mask.
+    fn supports_mask(&self) -> bool {
+        false
+    }
+
+    /// Gets the inpainting mask, which is white where the image is inpainted.
+    fn mask(&self) -> Option<Vec<u8>> {
+        None
+    }
+    /// Sets the inpainting mask. Ignored if unsupported by the backend.
+    fn set_mask(&mut self, _mask: Option<Vec<u8>>) {}
+
+    /// Returns a fingerprint of the settings that affect the generated images, as a hex
+    /// encoded SHA-256 hash. Parameters with the same settings have the same fingerprint,
+    /// whichever backend they're for, so caches and statistics can recognize repeated requests.
+    /// Settings that ask for a random seed share a fingerprint, though their images differ.
+    fn fingerprint(&self) -> String {
+        crate::fingerprint::fingerprint(self)
+    }
+}
+
+/// The WebUI setting that selects the checkpoint.
+const MODEL_SETTING: &str = "sd_model_checkpoint";
+/// The WebUI setting that selects the face restoration model.
+const FACE_RESTORATION_MODEL_SETTING: &str = "face_restoration_model";
+/// The WebUI setting for the CodeFormer weight.
+const CODEFORMER_WEIGHT_SETTING: &str = "code_former_weight";
+/// The WebUI setting that selects the VAE.
+const VAE_SETTING: &str = "sd_vae";
+
+/// The VAE that makes the WebUI use the VAE matching the model, if there is one.
+pub const AUTOMATIC_VAE: &str = "Automatic";
+
+/// WebUI settings overridden for a single request, keyed by setting name.
+type OverrideSettings = Option<HashMap<String, serde_json::Value>>;
+
+/// Returns a WebUI override setting, preferring the user's value over the default.
+fn override_setting<'a>(
+    user: &'a OverrideSettings,
+    defaults: Option<&'a OverrideSettings>,
+    name: &str,
+) -> Option<&'a serde_json::Value> {
+    user.as_ref()
+        .and_then(|settings| settings.get(name))
+        .or_else(|| defaults?.as_ref()?.get(name))
+}
+
+/// Sets a WebUI override setting.
+fn set_override_setting(settings: &mut OverrideSettings, name: &str, value: serde_json::Value) {
+    settings
+        .get_or_insert_with(Default::default)
+        .insert(name.to_owned(), value);
+}
+
+/// Returns the WebUI override settings for the model, VAE and face restoration settings of
+/// `params`.
+fn override_settings(params: &dyn GenParams) -> OverrideSettings {
+    let settings: HashMap<_, _> = [
+        (MODEL_SETTING, params.model().map(Into::into)),
+        (VAE_SETTING, params.vae().map(Into::into)),
+        (
+            FACE_RESTORATION_MODEL_SETTING,
+            params.face_restoration_model().map(Into::into),
+        ),
+        (
+            CODEFORMER_WEIGHT_SETTING,
+            params.codeformer_weight().map(Into::into),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| Some((name.to_owned(), value?)))
+    .collect();
+    (!settings.is_empty()).then_some(settings)
 }
 
 /// A struct representing the parameters for ComfyUI image generation.
@@ -99,10 +382,19 @@ pub struct ComfyParams {
     pub denoising: Option<f32>,
     /// The sampler to use for generation.
     pub sampler: Option<String>,
+    /// The checkpoint to use for generation.
+    #[serde(default)]
+    pub model: Option<String>,
     /// The batch size to use for generation.
     pub batch_size: Option<u32>,
     /// The image to use for generation.
     pub image: Option<Vec<u8>>,
+    /// Raw node input overrides, keyed by `node.input`, applied after all other parameters.
+    #[serde(default)]
+    pub overrides: BTreeMap<String, serde_json::Value>,
+    /// How a fixed seed changes after each generation, if set.
+    #[serde(default)]
+    pub seed_mode: Option<crate::SeedMode>,
 }
 
 impl ComfyParams {
@@ -131,21 +423,19 @@ impl ComfyParams {
         }
 
         if let Some(width) = self.width {
-            _ = prompt.width_mut().map(|w| *w = width);
+            _ = prompt.set_width(width);
         }
 
         if let Some(height) = self.height {
-            _ = prompt.height_mut().map(|h| *h = height);
+            _ = prompt.set_height(height);
         }
 
         if let Some(prompt_text) = &self.prompt_text {
-            _ = prompt.prompt_mut().map(|p| *p = prompt_text.clone());
+            _ = prompt.set_prompt(prompt_text.clone());
         }
 
         if let Some(negative_prompt_text) = &self.negative_prompt_text {
-            _ = prompt
-                .negative_prompt_mut()
-                .map(|p| *p = negative_prompt_text.clone());
+            _ = prompt.set_negative_prompt(negative_prompt_text.clone());
         }
 
         if let Some(denoising) = self.denoising {
@@ -160,6 +450,19 @@ impl ComfyParams {
             _ = prompt.batch_size_mut().map(|b| *b = batch_size);
         }
 
+        if let Some(model) = &self.model {
+            _ = prompt.ckpt_name_mut().map(|m| *m = model.clone());
+        }
+
+        for (path, value) in &self.overrides {
+            if let Err(e) = path
+                .parse::<GenericAccessor>()
+                .and_then(|accessor| accessor.set(&mut prompt, value.clone()))
+            {
+                warn!("Failed to apply override {}: {:?}", path, e);
+            }
+        }
+
         prompt
     }
 
@@ -186,8 +489,15 @@ impl From<&dyn GenParams> for ComfyParams {
             negative_prompt_text: params.negative_prompt(),
             denoising: params.denoising(),
             sampler: params.sampler(),
+            model: params.model(),
             batch_size: params.batch_size(),
             image: params.image(),
+            overrides: params
+                .as_any()
+                .downcast_ref::<ComfyParams>()
+                .map(|params| params.overrides.clone())
+                .unwrap_or_default(),
+            seed_mode: params.seed_mode(),
             ..Default::default()
         }
     }
@@ -201,7 +511,15 @@ impl GenParams for ComfyParams {
     }
 
     fn set_seed(&mut self, seed: i64) {
-        self.seed = Some(seed);
+        self.seed = Some(crate::normalize_seed(seed));
+    }
+
+    fn seed_mode(&self) -> Option<crate::SeedMode> {
+        self.seed_mode
+    }
+
+    fn set_seed_mode(&mut self, mode: crate::SeedMode) {
+        self.seed_mode = Some(mode);
     }
 
     fn steps(&self) -> Option<u32> {
@@ -287,6 +605,16 @@ impl GenParams for ComfyParams {
         self.sampler = Some(sampler);
     }
 
+    fn model(&self) -> Option<String> {
+        self.model
+            .clone()
+            .or_else(|| self.prompt.as_ref()?.ckpt_name().ok().cloned())
+    }
+
+    fn set_model(&mut self, model: String) {
+        self.model = Some(model);
+    }
+
     fn batch_size(&self) -> Option<u32> {
         self.batch_size
             .or_else(|| self.prompt.as_ref()?.batch_size().ok().copied())
@@ -330,6 +658,23 @@ impl From<&dyn GenParams> for Txt2ImgParams {
                 denoising_strength: params.denoising().map(|d| d as f64),
                 sampler_index: params.sampler(),
                 batch_size: params.batch_size(),
+                restore_faces: params.restore_faces(),
+                override_settings: override_settings(params),
+                eta: params.eta().map(|e| e as f64),
+                s_churn: params.s_churn().map(|s| s as f64),
+                s_tmin: params.s_tmin().map(|s| s as f64),
+                s_tmax: params.s_tmax().map(|s| s as f64),
+                s_noise: params.s_noise().map(|s| s as f64),
+                subseed: params.subseed(),
+                subseed_strength: params.subseed_strength().map(|s| s as f64),
+                enable_hr: params.hires_fix(),
+                hr_scale: params.hr_scale().map(|s| s as f64),
+                hr_upscaler: params.hr_upscaler(),
+                hr_second_pass_steps: params.hr_second_pass_steps(),
+                firstphase_width: params.firstphase_width(),
+                firstphase_height: params.firstphase_height(),
+                refiner_checkpoint: params.refiner_checkpoint(),
+                refiner_switch_at: params.refiner_switch_at().map(|s| s as f64),
                 ..Default::default()
             },
             defaults: None,
@@ -346,7 +691,7 @@ impl GenParams for Txt2ImgParams {
     }
 
     fn set_seed(&mut self, seed: i64) {
-        self.user_params.seed = Some(seed);
+        self.user_params.seed = Some(crate::normalize_seed(seed));
     }
 
     fn steps(&self) -> Option<u32> {
@@ -444,6 +789,24 @@ impl GenParams for Txt2ImgParams {
         self.user_params.sampler_index = Some(sampler);
     }
 
+    fn model(&self) -> Option<String> {
+        override_setting(
+            &self.user_params.override_settings,
+            self.defaults.as_ref().map(|d| &d.override_settings),
+            MODEL_SETTING,
+        )?
+        .as_str()
+        .map(str::to_owned)
+    }
+
+    fn set_model(&mut self, model: String) {
+        set_override_setting(
+            &mut self.user_params.override_settings,
+            MODEL_SETTING,
+            model.into(),
+        );
+    }
+
     fn batch_size(&self) -> Option<u32> {
         self.user_params
             .batch_size
@@ -459,6 +822,256 @@ impl GenParams for Txt2ImgParams {
     }
 
     fn set_image(&mut self, _image: Option<Vec<u8>>) {}
+
+    fn supports_advanced_sampler(&self) -> bool {
+        true
+    }
+
+    fn eta(&self) -> Option<f32> {
+        self.user_params
+            .eta
+            .or_else(|| self.defaults.as_ref()?.eta)
+            .map(|v| v as f32)
+    }
+
+    fn set_eta(&mut self, eta: f32) {
+        self.user_params.eta = Some(eta as f64);
+    }
+
+    fn s_churn(&self) -> Option<f32> {
+        self.user_params
+            .s_churn
+            .or_else(|| self.defaults.as_ref()?.s_churn)
+            .map(|v| v as f32)
+    }
+
+    fn set_s_churn(&mut self, s_churn: f32) {
+        self.user_params.s_churn = Some(s_churn as f64);
+    }
+
+    fn s_tmin(&self) -> Option<f32> {
+        self.user_params
+            .s_tmin
+            .or_else(|| self.defaults.as_ref()?.s_tmin)
+            .map(|v| v as f32)
+    }
+
+    fn set_s_tmin(&mut self, s_tmin: f32) {
+        self.user_params.s_tmin = Some(s_tmin as f64);
+    }
+
+    fn s_tmax(&self) -> Option<f32> {
+        self.user_params
+            .s_tmax
+            .or_else(|| self.defaults.as_ref()?.s_tmax)
+            .map(|v| v as f32)
+    }
+
+    fn set_s_tmax(&mut self, s_tmax: f32) {
+        self.user_params.s_tmax = Some(s_tmax as f64);
+    }
+
+    fn s_noise(&self) -> Option<f32> {
+        self.user_params
+            .s_noise
+            .or_else(|| self.defaults.as_ref()?.s_noise)
+            .map(|v| v as f32)
+    }
+
+    fn set_s_noise(&mut self, s_noise: f32) {
+        self.user_params.s_noise = Some(s_noise as f64);
+    }
+
+    fn supports_face_restoration(&self) -> bool {
+        true
+    }
+
+    fn restore_faces(&self) -> Option<bool> {
+        self.user_params
+            .restore_faces
+            .or_else(|| self.defaults.as_ref()?.restore_faces)
+    }
+
+    fn set_restore_faces(&mut self, restore_faces: bool) {
+        self.user_params.restore_faces = Some(restore_faces);
+    }
+
+    fn face_restoration_model(&self) -> Option<String> {
+        override_setting(
+            &self.user_params.override_settings,
+            self.defaults.as_ref().map(|d| &d.override_settings),
+            FACE_RESTORATION_MODEL_SETTING,
+        )?
+        .as_str()
+        .map(str::to_owned)
+    }
+
+    fn set_face_restoration_model(&mut self, model: String) {
+        set_override_setting(
+            &mut self.user_params.override_settings,
+            FACE_RESTORATION_MODEL_SETTING,
+            model.into(),
+        );
+    }
+
+    fn codeformer_weight(&self) -> Option<f32> {
+        override_setting(
+            &self.user_params.override_settings,
+            self.defaults.as_ref().map(|d| &d.override_settings),
+            CODEFORMER_WEIGHT_SETTING,
+        )?
+        .as_f64()
+        .map(|v| v as f32)
+    }
+
+    fn set_codeformer_weight(&mut self, weight: f32) {
+        set_override_setting(
+            &mut self.user_params.override_settings,
+            CODEFORMER_WEIGHT_SETTING,
+            weight.into(),
+        );
+    }
+
+    fn supports_vae(&self) -> bool {
+        true
+    }
+
+    fn vae(&self) -> Option<String> {
+        override_setting(
+            &self.user_params.override_settings,
+            self.defaults.as_ref().map(|d| &d.override_settings),
+            VAE_SETTING,
+        )?
+        .as_str()
+        .map(str::to_owned)
+    }
+
+    fn set_vae(&mut self, vae: String) {
+        set_override_setting(
+            &mut self.user_params.override_settings,
+            VAE_SETTING,
+            vae.into(),
+        );
+    }
+
+    fn supports_subseed(&self) -> bool {
+        true
+    }
+
+    fn subseed(&self) -> Option<i64> {
+        self.user_params
+            .subseed
+            .or_else(|| self.defaults.as_ref()?.subseed)
+    }
+
+    fn set_subseed(&mut self, subseed: i64) {
+        self.user_params.subseed = Some(subseed);
+    }
+
+    fn subseed_strength(&self) -> Option<f32> {
+        self.user_params
+            .subseed_strength
+            .or_else(|| self.defaults.as_ref()?.subseed_strength)
+            .map(|v| v as f32)
+    }
+
+    fn set_subseed_strength(&mut self, strength: f32) {
+        self.user_params.subseed_strength = Some(strength as f64);
+    }
+
+    fn supports_hires_fix(&self) -> bool {
+        true
+    }
+
+    fn hires_fix(&self) -> Option<bool> {
+        self.user_params
+            .enable_hr
+            .or_else(|| self.defaults.as_ref()?.enable_hr)
+    }
+
+    fn set_hires_fix(&mut self, enabled: bool) {
+        self.user_params.enable_hr = Some(enabled);
+    }
+
+    fn hr_scale(&self) -> Option<f32> {
+        self.user_params
+            .hr_scale
+            .or_else(|| self.defaults.as_ref()?.hr_scale)
+            .map(|v| v as f32)
+    }
+
+    fn set_hr_scale(&mut self, scale: f32) {
+        self.user_params.hr_scale = Some(scale as f64);
+    }
+
+    fn hr_upscaler(&self) -> Option<String> {
+        self.user_params
+            .hr_upscaler
+            .clone()
+            .or_else(|| self.defaults.as_ref()?.hr_upscaler.clone())
+    }
+
+    fn set_hr_upscaler(&mut self, upscaler: String) {
+        self.user_params.hr_upscaler = Some(upscaler);
+    }
+
+    fn hr_second_pass_steps(&self) -> Option<u32> {
+        self.user_params
+            .hr_second_pass_steps
+            .or_else(|| self.defaults.as_ref()?.hr_second_pass_steps)
+    }
+
+    fn set_hr_second_pass_steps(&mut self, steps: u32) {
+        self.user_params.hr_second_pass_steps = Some(steps);
+    }
+
+    fn firstphase_width(&self) -> Option<u32> {
+        self.user_params
+            .firstphase_width
+            .or_else(|| self.defaults.as_ref()?.firstphase_width)
+    }
+
+    fn set_firstphase_width(&mut self, width: u32) {
+        self.user_params.firstphase_width = Some(width);
+    }
+
+    fn firstphase_height(&self) -> Option<u32> {
+        self.user_params
+            .firstphase_height
+            .or_else(|| self.defaults.as_ref()?.firstphase_height)
+    }
+
+    fn set_firstphase_height(&mut self, height: u32) {
+        self.user_params.firstphase_height = Some(height);
+    }
+
+    fn supports_refiner(&self) -> bool {
+        true
+    }
+
+    fn refiner_checkpoint(&self) -> Option<String> {
+        self.user_params
+            .refiner_checkpoint
+            .clone()
+            .or_else(|| self.defaults.as_ref()?.refiner_checkpoint.clone())
+            .filter(|checkpoint| !checkpoint.is_empty())
+    }
+
+    fn set_refiner_checkpoint(&mut self, checkpoint: Option<String>) {
+        // An empty name disables a refiner set in the defaults, which the WebUI treats as none.
+        self.user_params.refiner_checkpoint = Some(checkpoint.unwrap_or_default());
+    }
+
+    fn refiner_switch_at(&self) -> Option<f32> {
+        self.user_params
+            .refiner_switch_at
+            .or_else(|| self.defaults.as_ref()?.refiner_switch_at)
+            .map(|v| v as f32)
+    }
+
+    fn set_refiner_switch_at(&mut self, switch_at: f32) {
+        self.user_params.refiner_switch_at = Some(switch_at as f64);
+    }
 }
 
 /// A struct representing the parameters for image generation in the Stable Diffusion WebUI API.
@@ -486,6 +1099,15 @@ impl From<&dyn GenParams> for Img2ImgParams {
                 denoising_strength: params.denoising().map(|d| d as f64),
                 sampler_index: params.sampler(),
                 batch_size: params.batch_size(),
+                restore_faces: params.restore_faces(),
+                override_settings: override_settings(params),
+                eta: params.eta().map(|e| e as f64),
+                s_churn: params.s_churn().map(|s| s as f64),
+                s_tmin: params.s_tmin().map(|s| s as f64),
+                s_tmax: params.s_tmax().map(|s| s as f64),
+                s_noise: params.s_noise().map(|s| s as f64),
+                subseed: params.subseed(),
+                subseed_strength: params.subseed_strength().map(|s| s as f64),
                 ..Default::default()
             },
             defaults: None,
@@ -502,7 +1124,7 @@ impl GenParams for Img2ImgParams {
     }
 
     fn set_seed(&mut self, seed: i64) {
-        self.user_params.seed = Some(seed);
+        self.user_params.seed = Some(crate::normalize_seed(seed));
     }
 
     fn steps(&self) -> Option<u32> {
@@ -600,6 +1222,24 @@ impl GenParams for Img2ImgParams {
         self.user_params.sampler_index = Some(sampler);
     }
 
+    fn model(&self) -> Option<String> {
+        override_setting(
+            &self.user_params.override_settings,
+            self.defaults.as_ref().map(|d| &d.override_settings),
+            MODEL_SETTING,
+        )?
+        .as_str()
+        .map(str::to_owned)
+    }
+
+    fn set_model(&mut self, model: String) {
+        set_override_setting(
+            &mut self.user_params.override_settings,
+            MODEL_SETTING,
+            model.into(),
+        );
+    }
+
     fn batch_size(&self) -> Option<u32> {
         self.user_params
             .batch_size
@@ -635,4 +1275,179 @@ impl GenParams for Img2ImgParams {
             _ = self.user_params.init_images.take()
         }
     }
+
+    fn supports_advanced_sampler(&self) -> bool {
+        true
+    }
+
+    fn eta(&self) -> Option<f32> {
+        self.user_params
+            .eta
+            .or_else(|| self.defaults.as_ref()?.eta)
+            .map(|v| v as f32)
+    }
+
+    fn set_eta(&mut self, eta: f32) {
+        self.user_params.eta = Some(eta as f64);
+    }
+
+    fn s_churn(&self) -> Option<f32> {
+        self.user_params
+            .s_churn
+            .or_else(|| self.defaults.as_ref()?.s_churn)
+            .map(|v| v as f32)
+    }
+
+    fn set_s_churn(&mut self, s_churn: f32) {
+        self.user_params.s_churn = Some(s_churn as f64);
+    }
+
+    fn s_tmin(&self) -> Option<f32> {
+        self.user_params
+            .s_tmin
+            .or_else(|| self.defaults.as_ref()?.s_tmin)
+            .map(|v| v as f32)
+    }
+
+    fn set_s_tmin(&mut self, s_tmin: f32) {
+        self.user_params.s_tmin = Some(s_tmin as f64);
+    }
+
+    fn s_tmax(&self) -> Option<f32> {
+        self.user_params
+            .s_tmax
+            .or_else(|| self.defaults.as_ref()?.s_tmax)
+            .map(|v| v as f32)
+    }
+
+    fn set_s_tmax(&mut self, s_tmax: f32) {
+        self.user_params.s_tmax = Some(s_tmax as f64);
+    }
+
+    fn s_noise(&self) -> Option<f32> {
+        self.user_params
+            .s_noise
+            .or_else(|| self.defaults.as_ref()?.s_noise)
+            .map(|v| v as f32)
+    }
+
+    fn set_s_noise(&mut self, s_noise: f32) {
+        self.user_params.s_noise = Some(s_noise as f64);
+    }
+
+    fn supports_face_restoration(&self) -> bool {
+        true
+    }
+
+    fn restore_faces(&self) -> Option<bool> {
+        self.user_params
+            .restore_faces
+            .or_else(|| self.defaults.as_ref()?.restore_faces)
+    }
+
+    fn set_restore_faces(&mut self, restore_faces: bool) {
+        self.user_params.restore_faces = Some(restore_faces);
+    }
+
+    fn face_restoration_model(&self) -> Option<String> {
+        override_setting(
+            &self.user_params.override_settings,
+            self.defaults.as_ref().map(|d| &d.override_settings),
+            FACE_RESTORATION_MODEL_SETTING,
+        )?
+        .as_str()
+        .map(str::to_owned)
+    }
+
+    fn set_face_restoration_model(&mut self, model: String) {
+        set_override_setting(
+            &mut self.user_params.override_settings,
+            FACE_RESTORATION_MODEL_SETTING,
+            model.into(),
+        );
+    }
+
+    fn codeformer_weight(&self) -> Option<f32> {
+        override_setting(
+            &self.user_params.override_settings,
+            self.defaults.as_ref().map(|d| &d.override_settings),
+            CODEFORMER_WEIGHT_SETTING,
+        )?
+        .as_f64()
+        .map(|v| v as f32)
+    }
+
+    fn set_codeformer_weight(&mut self, weight: f32) {
+        set_override_setting(
+            &mut self.user_params.override_settings,
+            CODEFORMER_WEIGHT_SETTING,
+            weight.into(),
+        );
+    }
+
+    fn supports_vae(&self) -> bool {
+        true
+    }
+
+    fn vae(&self) -> Option<String> {
+        override_setting(
+            &self.user_params.override_settings,
+            self.defaults.as_ref().map(|d| &d.override_settings),
+            VAE_SETTING,
+        )?
+        .as_str()
+        .map(str::to_owned)
+    }
+
+    fn set_vae(&mut self, vae: String) {
+        set_override_setting(
+            &mut self.user_params.override_settings,
+            VAE_SETTING,
+            vae.into(),
+        );
+    }
+
+    fn supports_subseed(&self) -> bool {
+        true
+    }
+
+    fn subseed(&self) -> Option<i64> {
+        self.user_params
+            .subseed
+            .or_else(|| self.defaults.as_ref()?.subseed)
+    }
+
+    fn set_subseed(&mut self, subseed: i64) {
+        self.user_params.subseed = Some(subseed);
+    }
+
+    fn subseed_strength(&self) -> Option<f32> {
+        self.user_params
+            .subseed_strength
+            .or_else(|| self.defaults.as_ref()?.subseed_strength)
+            .map(|v| v as f32)
+    }
+
+    fn set_subseed_strength(&mut self, strength: f32) {
+        self.user_params.subseed_strength = Some(strength as f64);
+    }
+
+    fn supports_mask(&self) -> bool {
+        true
+    }
+
+    fn mask(&self) -> Option<Vec<u8>> {
+        use base64::{engine::general_purpose, Engine as _};
+        general_purpose::STANDARD
+            .decode(self.user_params.mask.as_ref()?)
+            .ok()
+    }
+
+    fn set_mask(&mut self, mask: Option<Vec<u8>>) {
+        if let Some(mask) = mask {
+            self.user_params.with_mask(mask);
+        } else {
+            self.user_params.mask = None;
+        }
+    }
 }
@@ -0,0 +1,200 @@
+use comfyui_api::api::ImageType;
+
+/// Reads the dimensions of an encoded image from its header, without decoding it.
+///
+/// Supports PNG, JPEG and WebP images.
+///
+/// # Arguments
+///
+/// * `data` - The encoded image data.
+///
+/// # Returns
+///
+/// The `(width, height)` of the image, or `None` if the format is not recognized or the header
+/// is malformed.
+pub fn image_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    match ImageType::from_bytes(data)? {
+        ImageType::Png => png_dimensions(data),
+        ImageType::Jpeg => jpeg_dimensions(data),
+        ImageType::Webp => webp_dimensions(data),
+    }
+}
+
+/// Detects the MIME type of an encoded image from its header.
+///
+/// # Arguments
+///
+/// * `data` - The encoded image data.
+///
+/// # Returns
+///
+/// The MIME type of the image, or `None` if the format is not recognized.
+pub fn image_mime_type(data: &[u8]) -> Option<&'static str> {
+    ImageType::from_bytes(data).map(|image_type| image_type.mime())
+}
+
+fn be_u16(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]) as u32)
+}
+
+fn be_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn le_u16(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]) as u32)
+}
+
+fn le_u24(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 3)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]))
+}
+
+fn png_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    // The IHDR chunk always comes first, directly after the signature.
+    if data.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    Some((be_u32(data, 16)?, be_u32(data, 20)?))
+}
+
+fn jpeg_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let mut offset = 2;
+    loop {
+        if *data.get(offset)? != 0xff {
+            return None;
+        }
+        let marker = *data.get(offset + 1)?;
+        match marker {
+            // Fill bytes.
+            0xff => offset += 1,
+            // Standalone markers without a length.
+            0x01 | 0xd0..=0xd7 => offset += 2,
+            // Start of frame markers, excluding DHT, JPG and DAC.
+            0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                return Some((be_u16(data, offset + 7)?, be_u16(data, offset + 5)?));
+            }
+            _ => offset += 2 + be_u16(data, offset + 2)? as usize,
+        }
+    }
+}
+
+fn webp_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    match data.get(12..16)? {
+        b"VP8 " => {
+            // Key frames start with a start code after the 3 byte frame tag.
+            if data.get(23..26)? != [0x9d, 0x01, 0x2a] {
+                return None;
+            }
+            Some((le_u16(data, 26)? & 0x3fff, le_u16(data, 28)? & 0x3fff))
+        }
+        b"VP8L" => {
+            if *data.get(20)? != 0x2f {
+                return None;
+            }
+            let bits = u32::from_le_bytes(data.get(21..25)?.try_into().ok()?);
+            Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+        }
+        b"VP8X" => Some((le_u24(data, 24)? + 1, le_u24(data, 27)? + 1)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG: &[u8] = include_bytes!("../tests/fixtures/5x3.png");
+    const JPEG: &[u8] = include_bytes!("../tests/fixtures/5x3.jpg");
+    const WEBP_VP8L: &[u8] = include_bytes!("../tests/fixtures/5x3-vp8l.webp");
+    const WEBP_VP8X: &[u8] = include_bytes!("../tests/fixtures/5x3-vp8x.webp");
+
+    /// Header of a lossy WebP image, up to and including its dimensions.
+    fn webp_vp8() -> Vec<u8> {
+        let mut data = b"RIFF\x24\0\0\0WEBPVP8 \x18\0\0\0".to_vec();
+        // Frame tag of a key frame, start code, then the width and height.
+        data.extend_from_slice(&[0x30, 0x01, 0x00, 0x9d, 0x01, 0x2a, 5, 0, 3, 0]);
+        data
+    }
+
+    #[test]
+    fn test_image_dimensions() {
+        for data in [PNG, JPEG, WEBP_VP8L, WEBP_VP8X, &webp_vp8()] {
+            assert_eq!(image_dimensions(data), Some((5, 3)));
+        }
+    }
+
+    #[test]
+    fn test_image_mime_type() {
+        assert_eq!(image_mime_type(PNG), Some("image/png"));
+        assert_eq!(image_mime_type(JPEG), Some("image/jpeg"));
+        assert_eq!(image_mime_type(WEBP_VP8X), Some("image/webp"));
+        assert_eq!(image_mime_type(b"GIF89a"), None);
+        assert_eq!(image_mime_type(&[]), None);
+    }
+
+    #[test]
+    fn test_truncated_headers() {
+        let vp8 = webp_vp8();
+        for data in [PNG, JPEG, WEBP_VP8L, WEBP_VP8X, &vp8] {
+            // No prefix may panic or report wrong dimensions.
+            let end = data.len().min(200);
+            for len in 0..end {
+                let dimensions = image_dimensions(&data[..len]);
+                assert!(
+                    dimensions.is_none() || dimensions == Some((5, 3)),
+                    "{len} bytes"
+                );
+            }
+        }
+        assert_eq!(image_dimensions(&PNG[..20]), None);
+        assert_eq!(image_dimensions(&WEBP_VP8L[..22]), None);
+        assert_eq!(image_dimensions(&vp8[..28]), None);
+    }
+
+    #[test]
+    fn test_malformed_headers() {
+        // PNG without an IHDR chunk first.
+        let mut png = PNG.to_vec();
+        png[12..16].copy_from_slice(b"IDAT");
+        assert_eq!(image_dimensions(&png), None);
+
+        // JPEG with a segment that doesn't start with a marker.
+        let mut jpeg = JPEG.to_vec();
+        jpeg[2] = 0x00;
+        assert_eq!(image_dimensions(&jpeg), None);
+
+        // JPEG whose segment length points past the end of the data.
+        assert_eq!(
+            image_dimensions(&[0xff, 0xd8, 0xff, 0xe0, 0xff, 0xff]),
+            None
+        );
+
+        // JPEG with only fill bytes.
+        assert_eq!(
+            image_dimensions(&[0xff, 0xd8, 0xff, 0xff, 0xff, 0xff]),
+            None
+        );
+
+        // Lossless WebP without its signature byte.
+        let mut vp8l = WEBP_VP8L.to_vec();
+        vp8l[20] = 0x00;
+        assert_eq!(image_dimensions(&vp8l), None);
+
+        // Lossy WebP without its start code.
+        let mut vp8 = webp_vp8();
+        vp8[23] = 0x00;
+        assert_eq!(image_dimensions(&vp8), None);
+
+        // WebP with an unknown chunk.
+        let mut webp = WEBP_VP8X.to_vec();
+        webp[12..16].copy_from_slice(b"ALPH");
+        assert_eq!(image_dimensions(&webp), None);
+
+        assert_eq!(image_dimensions(b"RIFF\0\0\0\0WEBP"), None);
+        assert_eq!(image_dimensions(b"GIF89a\x05\0\x03\0"), None);
+    }
+}
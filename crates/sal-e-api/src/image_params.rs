@@ -26,6 +26,34 @@ pub trait ImageParams: std::fmt::Debug + AsAny + Send + Sync + DynClone {
     fn model(&self) -> Option<String>;
     /// Returns the sampler.
     fn sampler(&self) -> Option<String>;
+    /// Returns the VAE, if the backend reports it.
+    fn vae(&self) -> Option<String> {
+        None
+    }
+    /// Returns whether a censor extension on the backend replaced the images, usually with
+    /// blank ones.
+    fn censored(&self) -> bool {
+        false
+    }
+}
+
+/// Returns whether an extra generation parameter named `name` with `value` is a censor
+/// extension's marker that it blocked the images.
+fn is_censor_marker(name: &str, value: &serde_json::Value) -> bool {
+    let name = name.to_lowercase();
+    if !name.contains("nsfw") && !name.contains("censor") {
+        return false;
+    }
+    match value {
+        serde_json::Value::Bool(flagged) => *flagged,
+        serde_json::Value::Number(count) => count.as_f64().is_some_and(|count| count > 0.0),
+        serde_json::Value::String(text) => !matches!(
+            text.to_lowercase().trim(),
+            "" | "false" | "0" | "none" | "no"
+        ),
+        serde_json::Value::Array(values) => !values.is_empty(),
+        serde_json::Value::Null | serde_json::Value::Object(_) => false,
+    }
 }
 
 impl ImageParams for comfyui_api::models::Prompt {
@@ -88,6 +116,48 @@ impl ImageParams for comfyui_api::models::Prompt {
     }
 }
 
+impl ImageParams for comfyui_api::comfy::ImageInfo {
+    fn seed(&self) -> Option<i64> {
+        self.seed
+    }
+
+    fn steps(&self) -> Option<u32> {
+        self.steps
+    }
+
+    fn cfg(&self) -> Option<f32> {
+        self.cfg
+    }
+
+    fn width(&self) -> Option<u32> {
+        self.width
+    }
+
+    fn height(&self) -> Option<u32> {
+        self.height
+    }
+
+    fn prompt(&self) -> Option<String> {
+        self.prompt.clone()
+    }
+
+    fn negative_prompt(&self) -> Option<String> {
+        self.negative_prompt.clone()
+    }
+
+    fn denoising(&self) -> Option<f32> {
+        self.denoise
+    }
+
+    fn model(&self) -> Option<String> {
+        self.model.clone()
+    }
+
+    fn sampler(&self) -> Option<String> {
+        self.sampler.clone()
+    }
+}
+
 impl ImageParams for ImgInfo {
     fn seed(&self) -> Option<i64> {
         self.seed
@@ -128,4 +198,38 @@ impl ImageParams for ImgInfo {
     fn sampler(&self) -> Option<String> {
         self.sampler_name.clone()
     }
+
+    fn vae(&self) -> Option<String> {
+        self.sd_vae_name.clone()
+    }
+
+    fn censored(&self) -> bool {
+        self.extra_generation_params.as_ref().is_some_and(|params| {
+            params
+                .other
+                .iter()
+                .any(|(name, value)| is_censor_marker(name, value))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_censored() {
+        let info: ImgInfo = serde_json::from_value(serde_json::json!({
+            "extra_generation_params": {"Lora hashes": "cat: 1234", "NSFW filter": "True"},
+        }))
+        .unwrap();
+        assert!(info.censored());
+
+        let info: ImgInfo = serde_json::from_value(serde_json::json!({
+            "extra_generation_params": {"Censor": false, "Hires upscale": 2},
+        }))
+        .unwrap();
+        assert!(!info.censored());
+        assert!(!ImgInfo::default().censored());
+    }
 }
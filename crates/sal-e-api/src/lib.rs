@@ -2,5 +2,22 @@ mod gen_params;
 pub use gen_params::*;
 mod image_params;
 pub use image_params::*;
+mod image_header;
+pub use image_header::*;
 mod api;
 pub use api::*;
+mod error;
+mod fingerprint;
+mod pool;
+pub use pool::*;
+mod seed;
+pub use seed::*;
+mod registry;
+pub use registry::*;
+
+/// The version of `typetag` used to register [`GenParams`] implementations, for use by
+/// [`register_gen_params!`].
+pub use typetag;
+
+/// The version of this crate.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -0,0 +1,752 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use async_stream::try_stream;
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+
+use crate::{
+    gen_params::GenParams, BackendStatus, ImageStream, Img2ImgApi, Img2ImgApiError, OnProgress,
+    Response, Txt2ImgApi, Txt2ImgApiError,
+};
+
+/// How a `BackendPool` picks the backend for a request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BalanceStrategy {
+    /// Send requests to each backend in turn.
+    #[default]
+    RoundRobin,
+    /// Send requests to the backend with the shortest queue. Backends that don't report their
+    /// queue are measured by the number of requests the pool has in flight on them.
+    LeastBusy,
+    /// Send requests to the first backend, and only to the next ones while it's unavailable.
+    Failover,
+}
+
+/// A change in the availability of a pool member.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolEvent {
+    /// The backend at index `backend` failed `failures` requests in a row, so it isn't used
+    /// until it passes a health check.
+    Unavailable { backend: usize, failures: u32 },
+    /// The backend at index `backend` passed a health check and is used again.
+    Recovered { backend: usize },
+}
+
+/// A function that's called when the availability of a pool member changes.
+pub type OnPoolEvent = dyn Fn(PoolEvent) + Send + Sync;
+
+/// Wraps the pool's event listener so the pool can derive `Debug`.
+#[derive(Clone)]
+struct Listener(Arc<OnPoolEvent>);
+
+impl std::fmt::Debug for Listener {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Listener").finish_non_exhaustive()
+    }
+}
+
+/// A backend that can be a member of a `BackendPool`.
+#[async_trait]
+pub trait PoolBackend: std::fmt::Debug + Clone + Send + Sync {
+    /// Returns the number of jobs queued on the backend, if it reports it.
+    async fn load(&self) -> Option<u64>;
+
+    /// Checks whether the backend is reachable.
+    async fn health_check(&self) -> anyhow::Result<()>;
+
+    /// Interrupts the generation the backend is currently running.
+    async fn interrupt(&self) -> anyhow::Result<()>;
+}
+
+#[async_trait]
+impl PoolBackend for Box<dyn Txt2ImgApi> {
+    async fn load(&self) -> Option<u64> {
+        self.queue_length().await.ok()
+    }
+
+    async fn health_check(&self) -> anyhow::Result<()> {
+        self.backend_version().await.map(drop)
+    }
+
+    async fn interrupt(&self) -> anyhow::Result<()> {
+        (**self).interrupt().await
+    }
+}
+
+#[async_trait]
+impl PoolBackend for Box<dyn Img2ImgApi> {
+    async fn load(&self) -> Option<u64> {
+        self.queue_length().await.ok()
+    }
+
+    async fn health_check(&self) -> anyhow::Result<()> {
+        self.backend_version().await.map(drop)
+    }
+
+    async fn interrupt(&self) -> anyhow::Result<()> {
+        (**self).interrupt().await
+    }
+}
+
+/// Health of a pool member, shared between clones of the pool.
+#[derive(Debug, Default)]
+struct Health {
+    /// Requests the pool has in flight on the member.
+    in_flight: AtomicUsize,
+    /// Requests that failed in a row.
+    failures: AtomicU32,
+    /// When set, the member failed and isn't used until this time has passed.
+    unhealthy_until: Mutex<Option<Instant>>,
+}
+
+impl Health {
+    /// Returns whether the member is cooling down after a failure, and whether the cooldown
+    /// has passed so the member should be checked before it's used again.
+    fn status(&self, now: Instant) -> MemberStatus {
+        match *self.unhealthy_until.lock().unwrap() {
+            None => MemberStatus::Healthy,
+            Some(until) if until > now => MemberStatus::CoolingDown,
+            Some(_) => MemberStatus::Recovering,
+        }
+    }
+
+    /// Marks the member as healthy, returning whether it was unavailable.
+    fn record_success(&self) -> bool {
+        self.failures.store(0, Ordering::Relaxed);
+        self.unhealthy_until.lock().unwrap().take().is_some()
+    }
+
+    /// Records a failed request, returning how many times in a row the member has failed and
+    /// whether it has just become unavailable. Once it has failed `threshold` times in a row,
+    /// the member is unhealthy for `cooldown`.
+    fn record_failure(&self, cooldown: Duration, threshold: u32) -> (u32, bool) {
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures < threshold {
+            return (failures, false);
+        }
+        let mut unhealthy_until = self.unhealthy_until.lock().unwrap();
+        let became_unavailable = unhealthy_until.is_none();
+        *unhealthy_until = Some(Instant::now() + cooldown);
+        (failures, became_unavailable)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum MemberStatus {
+    Healthy,
+    Recovering,
+    CoolingDown,
+}
+
+/// Decrements a member's in-flight count when a request completes or is cancelled.
+struct InFlight<'a>(&'a Health);
+
+impl<'a> InFlight<'a> {
+    fn new(health: &'a Health) -> Self {
+        health.in_flight.fetch_add(1, Ordering::Relaxed);
+        Self(health)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Member<T> {
+    api: T,
+    health: Arc<Health>,
+}
+
+/// Struct that spreads requests over several interchangeable backends, such as one backend per
+/// GPU.
+///
+/// A request that fails on a backend is retried on the next backend. A backend that fails
+/// enough requests in a row is skipped for a cooldown period, and once the cooldown has passed
+/// it's health checked before it receives requests again. If every backend is cooling down,
+/// they are tried anyway.
+#[derive(Debug, Clone)]
+pub struct BackendPool<T> {
+    members: Vec<Member<T>>,
+    strategy: BalanceStrategy,
+    cooldown: Duration,
+    failure_threshold: u32,
+    listener: Option<Listener>,
+    next: Arc<AtomicUsize>,
+}
+
+impl<T: PoolBackend> BackendPool<T> {
+    /// Constructs a new `BackendPool` over `backends`.
+    ///
+    /// # Arguments
+    ///
+    /// * `backends` - The backends to spread requests over. Must not be empty.
+    /// * `strategy` - How to pick the backend for a request.
+    ///
+    /// # Errors
+    ///
+    /// If `backends` is empty, an error will be returned.
+    pub fn new(backends: Vec<T>, strategy: BalanceStrategy) -> anyhow::Result<Self> {
+        if backends.is_empty() {
+            return Err(anyhow!("A backend pool needs at least one backend"));
+        }
+        Ok(Self {
+            members: backends
+                .into_iter()
+                .map(|api| Member {
+                    api,
+                    health: Arc::default(),
+                })
+                .collect(),
+            strategy,
+            cooldown: Duration::from_secs(30),
+            failure_threshold: 1,
+            listener: None,
+            next: Arc::default(),
+        })
+    }
+
+    /// Sets how long a backend is skipped after it fails a request. Defaults to 30 seconds.
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Sets how many requests in a row a backend must fail before it's skipped. Defaults to 1.
+    pub fn with_failure_threshold(mut self, failure_threshold: u32) -> Self {
+        self.failure_threshold = failure_threshold.max(1);
+        self
+    }
+
+    /// Sets a function to call when a backend becomes unavailable or recovers.
+    pub fn with_listener(mut self, listener: Arc<OnPoolEvent>) -> Self {
+        self.listener = Some(Listener(listener));
+        self
+    }
+
+    /// Returns the backends in the pool.
+    pub fn backends(&self) -> impl Iterator<Item = &T> {
+        self.members.iter().map(|member| &member.api)
+    }
+
+    fn notify(&self, event: PoolEvent) {
+        if let Some(Listener(listener)) = &self.listener {
+            listener(event);
+        }
+    }
+
+    fn record_success(&self, index: usize) {
+        if self.members[index].health.record_success() {
+            info!(backend = index, "Backend recovered");
+            self.notify(PoolEvent::Recovered { backend: index });
+        }
+    }
+
+    /// Records a failure of the member at `index`, returning how many times in a row it has
+    /// failed.
+    fn record_failure(&self, index: usize) -> u32 {
+        let (failures, became_unavailable) = self.members[index]
+            .health
+            .record_failure(self.cooldown, self.failure_threshold);
+        if became_unavailable {
+            self.notify(PoolEvent::Unavailable {
+                backend: index,
+                failures,
+            });
+        }
+        failures
+    }
+
+    /// Returns the indices of the members in the order they should be tried for a request.
+    async fn order(&self) -> Vec<usize> {
+        let count = self.members.len();
+        let start = match self.strategy {
+            BalanceStrategy::Failover => 0,
+            _ => self.next.fetch_add(1, Ordering::Relaxed) % count,
+        };
+        let mut order: Vec<usize> = (0..count).map(|i| (start + i) % count).collect();
+
+        if self.strategy == BalanceStrategy::LeastBusy {
+            let loads = join_all(self.members.iter().map(|member| async {
+                match member.api.load().await {
+                    Some(load) => load,
+                    None => member.health.in_flight.load(Ordering::Relaxed) as u64,
+                }
+            }))
+            .await;
+            // The sort is stable, so ties are still broken round-robin.
+            order.sort_by_key(|&index| loads[index]);
+        }
+
+        let now = Instant::now();
+        let mut statuses = Vec::with_capacity(count);
+        for &index in &order {
+            let member = &self.members[index];
+            let status = match member.health.status(now) {
+                MemberStatus::Recovering => match member.api.health_check().await {
+                    Ok(()) => {
+                        self.record_success(index);
+                        MemberStatus::Healthy
+                    }
+                    Err(e) => {
+                        warn!(backend = index, "Backend failed health check: {:?}", e);
+                        self.record_failure(index);
+                        MemberStatus::CoolingDown
+                    }
+                },
+                status => status,
+            };
+            statuses.push((index, status));
+        }
+        statuses.sort_by_key(|&(_, status)| status);
+        statuses.into_iter().map(|(index, _)| index).collect()
+    }
+
+    /// Runs `request` on the members in turn until one succeeds or fails with an error that
+    /// isn't worth retrying elsewhere.
+    async fn dispatch<'a, R, E, F, Fut>(
+        &'a self,
+        request: F,
+        retryable: fn(&E) -> bool,
+    ) -> Result<R, E>
+    where
+        F: Fn(&'a T) -> Fut,
+        Fut: Future<Output = Result<R, E>>,
+        E: std::fmt::Debug,
+    {
+        let mut last_error = None;
+        for index in self.order().await {
+            let member = &self.members[index];
+            let result = {
+                let _in_flight = InFlight::new(&member.health);
+                request(&member.api).await
+            };
+            match result {
+                Ok(response) => {
+                    self.record_success(index);
+                    return Ok(response);
+                }
+                Err(e) if retryable(&e) => {
+                    let failures = self.record_failure(index);
+                    warn!(backend = index, failures, "Backend request failed: {:?}", e);
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_error.expect("A backend pool has at least one backend"))
+    }
+
+    /// Returns the first member that isn't cooling down, or the first member if all are.
+    fn first_healthy(&self) -> &T {
+        let now = Instant::now();
+        &self
+            .members
+            .iter()
+            .find(|member| member.health.status(now) != MemberStatus::CoolingDown)
+            .unwrap_or(&self.members[0])
+            .api
+    }
+}
+
+/// Returns whether a txt2img error is caused by the backend, so the request is worth retrying on
+/// another one. Errors caused by the request itself would fail on every backend.
+fn is_txt2img_backend_failure(error: &Txt2ImgApiError) -> bool {
+    !matches!(
+        error,
+        Txt2ImgApiError::EmptyPrompt
+            | Txt2ImgApiError::InvalidParameters(_)
+            | Txt2ImgApiError::ParseResponse(_)
+    )
+}
+
+/// Returns whether an img2img error is caused by the backend, like `is_txt2img_backend_failure`.
+fn is_img2img_backend_failure(error: &Img2ImgApiError) -> bool {
+    !matches!(
+        error,
+        Img2ImgApiError::EmptyPrompt
+            | Img2ImgApiError::NoImage
+            | Img2ImgApiError::InvalidParameters(_)
+            | Img2ImgApiError::ParseResponse(_)
+    )
+}
+
+/// Sums the queue lengths reported by the members.
+async fn total_queue_length<T: PoolBackend>(pool: &BackendPool<T>) -> anyhow::Result<u64> {
+    let loads: Vec<u64> = join_all(pool.backends().map(|api| api.load()))
+        .await
+        .into_iter()
+        .flatten()
+        .collect();
+    if loads.is_empty() {
+        return Err(anyhow!("Queue length is not supported"));
+    }
+    Ok(loads.into_iter().sum())
+}
+
+/// Interrupts the members that the pool has requests in flight on.
+async fn interrupt_busy<T: PoolBackend>(pool: &BackendPool<T>) -> anyhow::Result<()> {
+    join_all(
+        pool.members
+            .iter()
+            .filter(|member| member.health.in_flight.load(Ordering::Relaxed) > 0)
+            .map(|member| member.api.interrupt()),
+    )
+    .await
+    .into_iter()
+    .collect()
+}
+
+/// Describes the versions of the members, one per line.
+async fn pool_versions<F, Fut>(count: usize, version: F) -> anyhow::Result<String>
+where
+    F: Fn(usize) -> Fut,
+    Fut: Future<Output = anyhow::Result<String>>,
+{
+    let versions = join_all((0..count).map(version)).await;
+    Ok(versions
+        .into_iter()
+        .enumerate()
+        .map(|(index, version)| match version {
+            Ok(version) => format!("{}: {}", index + 1, version),
+            Err(e) => format!("{}: unavailable ({})", index + 1, e),
+        })
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+#[async_trait]
+impl Txt2ImgApi for BackendPool<Box<dyn Txt2ImgApi>> {
+    async fn txt2img(&self, config: &dyn GenParams) -> Result<Response, Txt2ImgApiError> {
+        self.txt2img_with_progress(config, &|_| {}).await
+    }
+
+    #[instrument(skip_all, fields(backend = "pool"))]
+    async fn txt2img_with_progress(
+        &self,
+        config: &dyn GenParams,
+        on_progress: &OnProgress<'_>,
+    ) -> Result<Response, Txt2ImgApiError> {
+        self.dispatch(
+            |api| api.txt2img_with_progress(config, on_progress),
+            is_txt2img_backend_failure,
+        )
+        .await
+    }
+
+    /// Streams from the backend a request would be sent to first. Streams aren't retried on
+    /// another backend, since some of the images may already have been yielded.
+    fn stream_txt2img<'a>(&'a self, config: &'a dyn GenParams) -> ImageStream<'a> {
+        Box::pin(try_stream! {
+            let index = self.order().await[0];
+            let member = &self.members[index];
+            let _in_flight = InFlight::new(&member.health);
+            for await chunk in member.api.stream_txt2img(config) {
+                match chunk {
+                    Ok(chunk) => yield chunk,
+                    Err(e) => {
+                        if is_txt2img_backend_failure(&e) {
+                            let failures = self.record_failure(index);
+                            warn!(backend = index, failures, "Backend stream failed: {:?}", e);
+                        }
+                        Err(e)?;
+                    }
+                }
+            }
+            self.record_success(index);
+        })
+    }
+
+    fn gen_params(&self, user_settings: Option<&dyn GenParams>) -> Box<dyn GenParams> {
+        self.members[0].api.gen_params(user_settings)
+    }
+
+    async fn backend_version(&self) -> anyhow::Result<String> {
+        pool_versions(self.members.len(), |index| {
+            self.members[index].api.backend_version()
+        })
+        .await
+    }
+
+    async fn queue_length(&self) -> anyhow::Result<u64> {
+        total_queue_length(self).await
+    }
+
+    async fn ping(&self) -> anyhow::Result<BackendStatus> {
+        self.first_healthy().ping().await
+    }
+
+    async fn models(&self) -> anyhow::Result<Vec<String>> {
+        self.first_healthy().models().await
+    }
+
+    async fn loras(&self) -> anyhow::Result<Vec<String>> {
+        self.first_healthy().loras().await
+    }
+
+    async fn vaes(&self) -> anyhow::Result<Vec<String>> {
+        self.first_healthy().vaes().await
+    }
+
+    async fn interrupt(&self) -> anyhow::Result<()> {
+        interrupt_busy(self).await
+    }
+}
+
+#[async_trait]
+impl Img2ImgApi for BackendPool<Box<dyn Img2ImgApi>> {
+    async fn img2img(&self, config: &dyn GenParams) -> Result<Response, Img2ImgApiError> {
+        self.img2img_with_progress(config, &|_| {}).await
+    }
+
+    #[instrument(skip_all, fields(backend = "pool"))]
+    async fn img2img_with_progress(
+        &self,
+        config: &dyn GenParams,
+        on_progress: &OnProgress<'_>,
+    ) -> Result<Response, Img2ImgApiError> {
+        self.dispatch(
+            |api| api.img2img_with_progress(config, on_progress),
+            is_img2img_backend_failure,
+        )
+        .await
+    }
+
+    fn gen_params(&self, user_settings: Option<&dyn GenParams>) -> Box<dyn GenParams> {
+        self.members[0].api.gen_params(user_settings)
+    }
+
+    async fn backend_version(&self) -> anyhow::Result<String> {
+        pool_versions(self.members.len(), |index| {
+            self.members[index].api.backend_version()
+        })
+        .await
+    }
+
+    async fn queue_length(&self) -> anyhow::Result<u64> {
+        total_queue_length(self).await
+    }
+
+    async fn interrupt(&self) -> anyhow::Result<()> {
+        interrupt_busy(self).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use futures_util::StreamExt as _;
+    use stable_diffusion_api::ImgInfo;
+
+    use super::*;
+    use crate::{GeneratedImage, Txt2ImgParams};
+
+    #[derive(Debug, Clone, Default)]
+    struct MockApi {
+        id: usize,
+        down: Arc<AtomicBool>,
+        invalid: Arc<AtomicBool>,
+        calls: Arc<Mutex<Vec<usize>>>,
+    }
+
+    #[async_trait]
+    impl Txt2ImgApi for MockApi {
+        async fn txt2img(&self, _config: &dyn GenParams) -> Result<Response, Txt2ImgApiError> {
+            self.calls.lock().unwrap().push(self.id);
+            if self.down.load(Ordering::Relaxed) {
+                return Err(Txt2ImgApiError::Txt2Img(anyhow!("backend is down")));
+            }
+            if self.invalid.load(Ordering::Relaxed) {
+                return Err(Txt2ImgApiError::InvalidParameters(anyhow!(
+                    "unknown sampler"
+                )));
+            }
+            Ok(Response {
+                images: vec![GeneratedImage::new(vec![self.id as u8])],
+                params: Box::new(ImgInfo::default()),
+                gen_params: Box::new(Txt2ImgParams::default()),
+            })
+        }
+
+        fn gen_params(&self, _user_settings: Option<&dyn GenParams>) -> Box<dyn GenParams> {
+            Box::new(Txt2ImgParams::default())
+        }
+
+        async fn backend_version(&self) -> anyhow::Result<String> {
+            if self.down.load(Ordering::Relaxed) {
+                return Err(anyhow!("backend is down"));
+            }
+            Ok(format!("mock {}", self.id))
+        }
+    }
+
+    fn pool(
+        count: usize,
+        strategy: BalanceStrategy,
+    ) -> (BackendPool<Box<dyn Txt2ImgApi>>, Vec<MockApi>) {
+        let calls = Arc::default();
+        let mocks: Vec<MockApi> = (0..count)
+            .map(|id| MockApi {
+                id,
+                calls: Arc::clone(&calls),
+                ..Default::default()
+            })
+            .collect();
+        let backends = mocks
+            .iter()
+            .map(|mock| Box::new(mock.clone()) as Box<dyn Txt2ImgApi>)
+            .collect();
+        let pool = BackendPool::new(backends, strategy).unwrap();
+        (pool, mocks)
+    }
+
+    #[tokio::test]
+    async fn test_pool_round_robin() {
+        let (pool, mocks) = pool(2, BalanceStrategy::RoundRobin);
+        let params = Txt2ImgParams::default();
+        for _ in 0..4 {
+            pool.txt2img(&params).await.unwrap();
+        }
+        assert_eq!(*mocks[0].calls.lock().unwrap(), vec![0, 1, 0, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_pool_stream() {
+        let (pool, mocks) = pool(2, BalanceStrategy::RoundRobin);
+        let params = Txt2ImgParams::default();
+        let chunks: Vec<_> = pool.stream_txt2img(&params).collect().await;
+        let images: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| {
+                let chunk = chunk.unwrap();
+                (chunk.index, chunk.image.data)
+            })
+            .collect();
+        assert_eq!(images, vec![(0, vec![0])]);
+
+        // The stream is spread over the backends like other requests, but isn't retried.
+        mocks[1].down.store(true, Ordering::Relaxed);
+        let mut stream = pool.stream_txt2img(&params);
+        assert!(stream.next().await.unwrap().is_err());
+        assert!(stream.next().await.is_none());
+        assert_eq!(*mocks[0].calls.lock().unwrap(), vec![0, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_pool_failover() {
+        let (pool, mocks) = pool(2, BalanceStrategy::RoundRobin);
+        let pool = pool.with_cooldown(Duration::ZERO);
+        let params = Txt2ImgParams::default();
+        mocks[0].down.store(true, Ordering::Relaxed);
+
+        // The failed request is retried on the other backend.
+        pool.txt2img(&params).await.unwrap();
+        assert_eq!(*mocks[0].calls.lock().unwrap(), vec![0, 1]);
+
+        // Once the cooldown has passed, the backend is only used again after a health check.
+        mocks[0].calls.lock().unwrap().clear();
+        pool.txt2img(&params).await.unwrap();
+        pool.txt2img(&params).await.unwrap();
+        assert_eq!(*mocks[0].calls.lock().unwrap(), vec![1, 1]);
+
+        mocks[0].down.store(false, Ordering::Relaxed);
+        mocks[0].calls.lock().unwrap().clear();
+        pool.txt2img(&params).await.unwrap();
+        pool.txt2img(&params).await.unwrap();
+        let mut calls = mocks[0].calls.lock().unwrap().clone();
+        calls.sort();
+        assert_eq!(calls, vec![0, 1]);
+
+        mocks[1].down.store(true, Ordering::Relaxed);
+        mocks[0].down.store(true, Ordering::Relaxed);
+        assert!(pool.txt2img(&params).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pool_invalid_parameters() {
+        let (pool, mocks) = pool(2, BalanceStrategy::Failover);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let pool = pool.with_listener({
+            let events = Arc::clone(&events);
+            Arc::new(move |event| events.lock().unwrap().push(event))
+        });
+        let params = Txt2ImgParams::default();
+        for mock in &mocks {
+            mock.invalid.store(true, Ordering::Relaxed);
+        }
+
+        // Rejected parameters would be rejected by every backend, so they aren't resent and
+        // don't count against the backend's health.
+        assert!(matches!(
+            pool.txt2img(&params).await,
+            Err(Txt2ImgApiError::InvalidParameters(_))
+        ));
+        assert_eq!(*mocks[0].calls.lock().unwrap(), vec![0]);
+        assert!(events.lock().unwrap().is_empty());
+
+        for mock in &mocks {
+            mock.invalid.store(false, Ordering::Relaxed);
+        }
+        pool.txt2img(&params).await.unwrap();
+        assert_eq!(*mocks[0].calls.lock().unwrap(), vec![0, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_pool_failover_strategy() {
+        let (pool, mocks) = pool(2, BalanceStrategy::Failover);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let pool = pool
+            .with_cooldown(Duration::ZERO)
+            .with_failure_threshold(2)
+            .with_listener({
+                let events = Arc::clone(&events);
+                Arc::new(move |event| events.lock().unwrap().push(event))
+            });
+        let params = Txt2ImgParams::default();
+
+        // Requests go to the primary while it's healthy.
+        pool.txt2img(&params).await.unwrap();
+        pool.txt2img(&params).await.unwrap();
+        assert_eq!(*mocks[0].calls.lock().unwrap(), vec![0, 0]);
+
+        // A single failure is retried on the secondary, but the primary isn't skipped yet.
+        mocks[0].down.store(true, Ordering::Relaxed);
+        mocks[0].calls.lock().unwrap().clear();
+        pool.txt2img(&params).await.unwrap();
+        assert!(events.lock().unwrap().is_empty());
+        pool.txt2img(&params).await.unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![PoolEvent::Unavailable {
+                backend: 0,
+                failures: 2
+            }]
+        );
+        pool.txt2img(&params).await.unwrap();
+        assert_eq!(*mocks[0].calls.lock().unwrap(), vec![0, 1, 0, 1, 1]);
+
+        // The primary is used again once it passes a health check.
+        mocks[0].down.store(false, Ordering::Relaxed);
+        mocks[0].calls.lock().unwrap().clear();
+        pool.txt2img(&params).await.unwrap();
+        assert_eq!(*mocks[0].calls.lock().unwrap(), vec![0]);
+        assert_eq!(
+            events.lock().unwrap().last(),
+            Some(&PoolEvent::Recovered { backend: 0 })
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::GenParams;
+
+/// Registers an implementation of [`GenParams`] so it can be serialized as a
+/// `Box<dyn GenParams>`, e.g. in a bot's dialogue storage.
+///
+/// Implementations are tagged with their type name and registered at link time, so crates
+/// embedding `sal-e-api` can add their own backends' parameters without changes upstream. Wrap
+/// the `impl` block in this macro instead of annotating it with `#[typetag::serde]`, which
+/// would require depending on the same version of `typetag` as this crate.
+///
+/// # Example
+///
+/// ```
+/// use sal_e_api::{register_gen_params, GenParams};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+/// struct MyParams {
+///     prompt: Option<String>,
+/// }
+///
+/// register_gen_params! {
+///     impl GenParams for MyParams {
+///         fn seed(&self) -> Option<i64> { None }
+///         fn set_seed(&mut self, _seed: i64) {}
+///         fn steps(&self) -> Option<u32> { None }
+///         fn set_steps(&mut self, _steps: u32) {}
+///         fn count(&self) -> Option<u32> { None }
+///         fn set_count(&mut self, _count: u32) {}
+///         fn cfg(&self) -> Option<f32> { None }
+///         fn set_cfg(&mut self, _cfg: f32) {}
+///         fn width(&self) -> Option<u32> { None }
+///         fn set_width(&mut self, _width: u32) {}
+///         fn height(&self) -> Option<u32> { None }
+///         fn set_height(&mut self, _height: u32) {}
+///         fn prompt(&self) -> Option<String> { self.prompt.clone() }
+///         fn set_prompt(&mut self, prompt: String) { self.prompt = Some(prompt) }
+///         fn negative_prompt(&self) -> Option<String> { None }
+///         fn set_negative_prompt(&mut self, _negative_prompt: String) {}
+///         fn denoising(&self) -> Option<f32> { None }
+///         fn set_denoising(&mut self, _denoising: f32) {}
+///         fn sampler(&self) -> Option<String> { None }
+///         fn set_sampler(&mut self, _sampler: String) {}
+///         fn model(&self) -> Option<String> { None }
+///         fn set_model(&mut self, _model: String) {}
+///         fn batch_size(&self) -> Option<u32> { None }
+///         fn set_batch_size(&mut self, _batch_size: u32) {}
+///         fn image(&self) -> Option<Vec<u8>> { None }
+///         fn set_image(&mut self, _image: Option<Vec<u8>>) {}
+///     }
+/// }
+///
+/// let mut params: Box<dyn GenParams> = Box::<MyParams>::default();
+/// params.set_prompt("a cat".to_owned());
+/// let json = serde_json::to_string(&params).unwrap();
+/// assert_eq!(json, r#"{"MyParams":{"prompt":"a cat"}}"#);
+/// let params: Box<dyn GenParams> = serde_json::from_str(&json).unwrap();
+/// assert_eq!(params.prompt(), Some("a cat".to_owned()));
+/// ```
+#[macro_export]
+macro_rules! register_gen_params {
+    ($($impl:tt)*) => {
+        const _: () = {
+            use $crate::typetag;
+
+            #[typetag::serde]
+            $($impl)*
+        };
+    };
+}
+
+/// Parameters whose type wasn't registered when they were deserialized, e.g. because they were
+/// saved by a build with a backend that has since been removed. They keep the serialized
+/// parameters but otherwise behave as if no parameters were set.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UnknownParams {
+    /// The serialized parameters, including their type tag.
+    pub value: serde_json::Value,
+}
+
+#[typetag::serde]
+impl GenParams for UnknownParams {
+    fn seed(&self) -> Option<i64> {
+        None
+    }
+
+    fn set_seed(&mut self, _seed: i64) {}
+
+    fn steps(&self) -> Option<u32> {
+        None
+    }
+
+    fn set_steps(&mut self, _steps: u32) {}
+
+    fn count(&self) -> Option<u32> {
+        None
+    }
+
+    fn set_count(&mut self, _count: u32) {}
+
+    fn cfg(&self) -> Option<f32> {
+        None
+    }
+
+    fn set_cfg(&mut self, _cfg: f32) {}
+
+    fn width(&self) -> Option<u32> {
+        None
+    }
+
+    fn set_width(&mut self, _width: u32) {}
+
+    fn height(&self) -> Option<u32> {
+        None
+    }
+
+    fn set_height(&mut self, _height: u32) {}
+
+    fn prompt(&self) -> Option<String> {
+        None
+    }
+
+    fn set_prompt(&mut self, _prompt: String) {}
+
+    fn negative_prompt(&self) -> Option<String> {
+        None
+    }
+
+    fn set_negative_prompt(&mut self, _negative_prompt: String) {}
+
+    fn denoising(&self) -> Option<f32> {
+        None
+    }
+
+    fn set_denoising(&mut self, _denoising: f32) {}
+
+    fn sampler(&self) -> Option<String> {
+        None
+    }
+
+    fn set_sampler(&mut self, _sampler: String) {}
+
+    fn model(&self) -> Option<String> {
+        None
+    }
+
+    fn set_model(&mut self, _model: String) {}
+
+    fn batch_size(&self) -> Option<u32> {
+        None
+    }
+
+    fn set_batch_size(&mut self, _batch_size: u32) {}
+
+    fn image(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn set_image(&mut self, _image: Option<Vec<u8>>) {}
+}
+
+/// Enum capturing registered and unknown parameter types.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GenParamsOrUnknown {
+    Known(Box<dyn GenParams>),
+    Unknown(serde_json::Value),
+}
+
+/// Deserializes a `Box<dyn GenParams>`, falling back to [`UnknownParams`] if its type isn't
+/// registered or its fields don't match, instead of failing. Use it with
+/// `#[serde(deserialize_with = "sal_e_api::deserialize_gen_params")]` so one stale value doesn't
+/// make a whole structure unreadable.
+pub fn deserialize_gen_params<'de, D>(deserializer: D) -> Result<Box<dyn GenParams>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match GenParamsOrUnknown::deserialize(deserializer)? {
+        GenParamsOrUnknown::Known(params) => params,
+        GenParamsOrUnknown::Unknown(value) => {
+            tracing::warn!("Failed to deserialize generation parameters, ignoring them");
+            Box::new(UnknownParams { value })
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::Txt2ImgParams;
+
+    #[derive(Deserialize)]
+    struct Wrapper {
+        #[serde(deserialize_with = "deserialize_gen_params")]
+        params: Box<dyn GenParams>,
+    }
+
+    #[test]
+    fn test_deserialize_gen_params() {
+        let wrapper: Wrapper = serde_json::from_value(json!({
+            "params": {"Txt2ImgParams": {"user_params": {"steps": 20}}}
+        }))
+        .unwrap();
+        assert!(wrapper.params.as_any().is::<Txt2ImgParams>());
+        assert_eq!(wrapper.params.steps(), Some(20));
+
+        let unknown = json!({"RemovedParams": {"steps": 20}});
+        let wrapper: Wrapper = serde_json::from_value(json!({ "params": unknown })).unwrap();
+        let params = wrapper.params.as_any().downcast_ref::<UnknownParams>();
+        assert_eq!(params, Some(&UnknownParams { value: unknown }));
+    }
+}
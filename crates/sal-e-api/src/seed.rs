@@ -0,0 +1,196 @@
+use std::{fmt, str::FromStr};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// The seed that asks for a random seed to be picked for each generation.
+pub const RANDOM_SEED: i64 = -1;
+
+/// The largest seed that every backend accepts. ComfyUI accepts seeds up to `u64::MAX`, but the
+/// Stable Diffusion WebUI treats them as signed, so seeds are kept within `0..=i64::MAX`.
+pub const MAX_SEED: i64 = i64::MAX;
+
+/// Returns a random seed, uniformly distributed over `0..=MAX_SEED`.
+pub fn random_seed() -> i64 {
+    rand::thread_rng().gen_range(0..=MAX_SEED)
+}
+
+/// Returns whether `seed` asks for a random seed. Backends disagree on what negative seeds
+/// mean, so all of them are treated as random.
+pub fn is_random_seed(seed: i64) -> bool {
+    seed < 0
+}
+
+/// Normalizes `seed`, mapping every negative seed to `RANDOM_SEED`.
+pub fn normalize_seed(seed: i64) -> i64 {
+    if is_random_seed(seed) {
+        RANDOM_SEED
+    } else {
+        seed
+    }
+}
+
+/// Returns the seed to send to a backend for `seed`, picking a random seed if it asks for one.
+pub fn resolve_seed(seed: i64) -> i64 {
+    if is_random_seed(seed) {
+        random_seed()
+    } else {
+        seed
+    }
+}
+
+/// Formats `seed` for display, e.g. `1234` or `random`.
+pub fn format_seed(seed: i64) -> String {
+    if is_random_seed(seed) {
+        "random".to_owned()
+    } else {
+        seed.to_string()
+    }
+}
+
+/// Errors that can occur when parsing a seed.
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseSeedError {
+    /// The seed was neither a number nor `random`.
+    #[error("Seed must be a whole number or \"random\"")]
+    Invalid,
+    /// The seed was larger than `MAX_SEED`.
+    #[error("Seed must be at most {MAX_SEED}")]
+    TooLarge,
+}
+
+/// Parses a seed entered by a user. Accepts `random`, any negative number for a random seed,
+/// and numbers up to `MAX_SEED`.
+///
+/// # Arguments
+///
+/// * `s` - The text to parse.
+///
+/// # Returns
+///
+/// The normalized seed on success, or an error if `s` isn't a valid seed.
+pub fn parse_seed(s: &str) -> Result<i64, ParseSeedError> {
+    let s = s.trim();
+    if s.eq_ignore_ascii_case("random") {
+        return Ok(RANDOM_SEED);
+    }
+    if let Ok(seed) = s.parse::<i64>() {
+        return Ok(normalize_seed(seed));
+    }
+    match s.parse::<u64>() {
+        Ok(_) => Err(ParseSeedError::TooLarge),
+        Err(_) => Err(ParseSeedError::Invalid),
+    }
+}
+
+/// How a fixed seed changes after each generation, like the `control_after_generate` widget of
+/// ComfyUI's samplers. Random seeds stay random whatever the mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SeedMode {
+    /// Keeps the seed, so generations can be repeated.
+    #[default]
+    Fixed,
+    /// Increments the seed by one after each generation.
+    Increment,
+    /// Picks a new random seed after each generation, which is then shown and can be reused.
+    Randomize,
+}
+
+impl SeedMode {
+    /// Returns the seed to use after a generation with `seed`, or `None` to keep it.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - The seed of the last generation.
+    pub fn next(self, seed: i64) -> Option<i64> {
+        if is_random_seed(seed) {
+            return None;
+        }
+        match self {
+            Self::Fixed => None,
+            Self::Increment => Some(if seed == MAX_SEED { 0 } else { seed + 1 }),
+            Self::Randomize => Some(random_seed()),
+        }
+    }
+}
+
+impl fmt::Display for SeedMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Fixed => "fixed",
+            Self::Increment => "increment",
+            Self::Randomize => "randomize",
+        })
+    }
+}
+
+/// Error returned when parsing an unknown seed mode.
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error("Seed mode must be \"fixed\", \"increment\" or \"randomize\"")]
+pub struct ParseSeedModeError;
+
+impl FromStr for SeedMode {
+    type Err = ParseSeedModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "fixed" => Ok(Self::Fixed),
+            "increment" => Ok(Self::Increment),
+            "randomize" | "random" => Ok(Self::Randomize),
+            _ => Err(ParseSeedModeError),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_seed() {
+        assert_eq!(resolve_seed(42), 42);
+        assert_eq!(resolve_seed(0), 0);
+        for seed in [RANDOM_SEED, i64::MIN] {
+            assert!((0..=MAX_SEED).contains(&resolve_seed(seed)));
+        }
+    }
+
+    #[test]
+    fn test_format_seed() {
+        assert_eq!(format_seed(1234), "1234");
+        assert_eq!(format_seed(RANDOM_SEED), "random");
+        assert_eq!(format_seed(-5), "random");
+    }
+
+    #[test]
+    fn test_parse_seed() {
+        assert_eq!(parse_seed("1234"), Ok(1234));
+        assert_eq!(parse_seed(" Random "), Ok(RANDOM_SEED));
+        assert_eq!(parse_seed("-7"), Ok(RANDOM_SEED));
+        assert_eq!(parse_seed(&MAX_SEED.to_string()), Ok(MAX_SEED));
+        assert_eq!(
+            parse_seed(&u64::MAX.to_string()),
+            Err(ParseSeedError::TooLarge)
+        );
+        assert_eq!(parse_seed("abc"), Err(ParseSeedError::Invalid));
+    }
+
+    #[test]
+    fn test_seed_mode() {
+        assert_eq!(SeedMode::Fixed.next(42), None);
+        assert_eq!(SeedMode::Increment.next(42), Some(43));
+        assert_eq!(SeedMode::Increment.next(MAX_SEED), Some(0));
+        assert_eq!(SeedMode::Increment.next(RANDOM_SEED), None);
+        assert!(SeedMode::Randomize
+            .next(42)
+            .is_some_and(|seed| (0..=MAX_SEED).contains(&seed)));
+        assert_eq!(" Increment ".parse(), Ok(SeedMode::Increment));
+        assert_eq!(
+            SeedMode::Randomize.to_string().parse(),
+            Ok(SeedMode::Randomize)
+        );
+        assert_eq!("sometimes".parse::<SeedMode>(), Err(ParseSeedModeError));
+    }
+}
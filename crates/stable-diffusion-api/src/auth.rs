@@ -0,0 +1,48 @@
+use std::fmt;
+
+/// Credentials sent with every request, for a WebUI started with `--api-auth` or behind a
+/// reverse proxy that requires authentication.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Auth {
+    /// HTTP basic authentication, as used by `--api-auth`.
+    Basic {
+        /// The user name.
+        username: String,
+        /// The password.
+        password: String,
+    },
+    /// A bearer token sent in the `Authorization` header.
+    Bearer {
+        /// The token.
+        token: String,
+    },
+}
+
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Keeps the secrets out of logs.
+        match self {
+            Auth::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .finish_non_exhaustive(),
+            Auth::Bearer { .. } => f.debug_struct("Bearer").finish_non_exhaustive(),
+        }
+    }
+}
+
+/// Extension trait for attaching optional credentials to a request.
+pub(crate) trait Authorize {
+    /// Attaches `auth` to the request, if there is any.
+    fn authorize(self, auth: Option<&Auth>) -> Self;
+}
+
+impl Authorize for reqwest::RequestBuilder {
+    fn authorize(self, auth: Option<&Auth>) -> Self {
+        match auth {
+            Some(Auth::Basic { username, password }) => self.basic_auth(username, Some(password)),
+            Some(Auth::Bearer { token }) => self.bearer_auth(token),
+            None => self,
+        }
+    }
+}
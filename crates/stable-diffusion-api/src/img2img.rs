@@ -4,7 +4,7 @@ use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 
-use super::ImgResponse;
+use super::{request::RequestOptions, Auth, ImgResponse};
 
 /// Struct representing an image to image request.
 #[skip_serializing_none]
@@ -41,7 +41,7 @@ pub struct Img2ImgRequest {
     /// Subseed.
     pub subseed: Option<i64>,
     /// Strength of the subseed.
-    pub subseed_strength: Option<u32>,
+    pub subseed_strength: Option<f64>,
     /// Height to resize the seed image from.
     pub seed_resize_from_h: Option<i32>,
     /// Width to resize the seed image from.
@@ -71,7 +71,7 @@ pub struct Img2ImgRequest {
     /// Negative prompt.
     pub negative_prompt: Option<String>,
     /// Eta value.
-    pub eta: Option<u32>,
+    pub eta: Option<f64>,
     /// Churn value.
     pub s_churn: Option<f64>,
     /// Maximum temperature value.
@@ -110,6 +110,7 @@ impl Img2ImgRequest {
     /// # Example
     ///
     /// ```
+    /// # use stable_diffusion_api::Img2ImgRequest;
     /// let mut req = Img2ImgRequest::default();
     /// req.with_prompt("A blue sky with green grass".to_string());
     /// ```
@@ -126,8 +127,9 @@ impl Img2ImgRequest {
     ///
     /// # Examples
     ///
-    /// ```
+    /// ```no_run
     /// use std::fs;
+    /// # use stable_diffusion_api::Img2ImgRequest;
     /// let mut req = Img2ImgRequest::default();
     /// let image_data = fs::read("path/to/image.jpg").unwrap();
     /// req.with_image(image_data);
@@ -146,6 +148,31 @@ impl Img2ImgRequest {
         }
     }
 
+    /// Sets the inpainting mask, which is white where the image is inpainted.
+    ///
+    /// # Arguments
+    ///
+    /// * `mask` - array bytes of the mask image.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs;
+    /// # use stable_diffusion_api::Img2ImgRequest;
+    /// let mut req = Img2ImgRequest::default();
+    /// let mask_data = fs::read("path/to/mask.png").unwrap();
+    /// req.with_mask(mask_data);
+    /// ```
+    pub fn with_mask<T>(&mut self, mask: T) -> &mut Self
+    where
+        T: AsRef<[u8]>,
+    {
+        use base64::{engine::general_purpose, Engine as _};
+
+        self.mask = Some(general_purpose::STANDARD.encode(mask));
+        self
+    }
+
     /// Adds multiple images to the request.
     ///
     /// # Arguments
@@ -154,8 +181,9 @@ impl Img2ImgRequest {
     ///
     /// # Examples
     ///
-    /// ```
+    /// ```no_run
     /// use std::fs;
+    /// # use stable_diffusion_api::Img2ImgRequest;
     /// let mut req = Img2ImgRequest::default();
     /// let image_data1 = fs::read("path/to/image1.jpg").unwrap();
     /// let image_data2 = fs::read("path/to/image2.jpg").unwrap();
@@ -184,6 +212,7 @@ impl Img2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Img2ImgRequest;
     /// let mut req = Img2ImgRequest::default();
     /// req.with_styles(vec!["cubism".to_string(), "impressionism".to_string()]);
     /// ```
@@ -206,6 +235,7 @@ impl Img2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Img2ImgRequest;
     /// let mut req = Img2ImgRequest::default();
     /// req.with_style("cubism".to_string());
     /// ```
@@ -227,6 +257,7 @@ impl Img2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Img2ImgRequest;
     /// let mut req = Img2ImgRequest::default();
     /// req.with_denoising_strength(0.4);
     /// ```
@@ -240,11 +271,12 @@ impl Img2ImgRequest {
     /// # Arguments
     ///
     /// * `seed` - An i64 value representing the seed for random number generation.
-    ///            Set to `-1` to randomize.
+    ///   Set to `-1` to randomize.
     ///
     /// # Example
     ///
     /// ```
+    /// # use stable_diffusion_api::Img2ImgRequest;
     /// let mut req = Img2ImgRequest::default();
     /// req.with_seed(12345);
     /// ```
@@ -258,11 +290,12 @@ impl Img2ImgRequest {
     /// # Arguments
     ///
     /// * `subseed` - An i64 value representing the subseed for random number generation.
-    ///               Set to `-1` to randomize.
+    ///   Set to `-1` to randomize.
     ///
     /// # Example
     ///
     /// ```
+    /// # use stable_diffusion_api::Img2ImgRequest;
     /// let mut req = Img2ImgRequest::default();
     /// req.with_subseed(12345);
     /// ```
@@ -275,15 +308,17 @@ impl Img2ImgRequest {
     ///
     /// # Arguments
     ///
-    /// * `subseed_strength` - A u32 value representing the strength of the subseed parameter.
+    /// * `subseed_strength` - An f64 value from 0 to 1 representing the strength of the subseed
+    ///   parameter.
     ///
     /// # Example
     ///
     /// ```
+    /// # use stable_diffusion_api::Img2ImgRequest;
     /// let mut req = Img2ImgRequest::default();
-    /// req.with_subseed_strength(5);
+    /// req.with_subseed_strength(0.5);
     /// ```
-    pub fn with_subseed_strength(&mut self, subseed_strength: u32) -> &mut Self {
+    pub fn with_subseed_strength(&mut self, subseed_strength: f64) -> &mut Self {
         self.subseed_strength = Some(subseed_strength);
         self
     }
@@ -297,6 +332,7 @@ impl Img2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Img2ImgRequest;
     /// let mut req = Img2ImgRequest::default();
     /// req.with_sampler_name("Euler".to_string());
     /// ```
@@ -314,6 +350,7 @@ impl Img2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Img2ImgRequest;
     /// let mut req = Img2ImgRequest::default();
     /// req.with_batch_size(16);
     /// ```
@@ -331,6 +368,7 @@ impl Img2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Img2ImgRequest;
     /// let mut req = Img2ImgRequest::default();
     /// req.with_n_iter(1000);
     /// ```
@@ -348,6 +386,7 @@ impl Img2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Img2ImgRequest;
     /// let mut req = Img2ImgRequest::default();
     /// req.with_steps(50);
     /// ```
@@ -365,6 +404,7 @@ impl Img2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Img2ImgRequest;
     /// let mut req = Img2ImgRequest::default();
     /// req.with_cfg_scale(0.7);
     /// ```
@@ -382,6 +422,7 @@ impl Img2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Img2ImgRequest;
     /// let mut req = Img2ImgRequest::default();
     /// req.with_width(512);
     /// ```
@@ -399,6 +440,7 @@ impl Img2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Img2ImgRequest;
     /// let mut req = Img2ImgRequest::default();
     /// req.with_height(512);
     /// ```
@@ -416,6 +458,7 @@ impl Img2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Img2ImgRequest;
     /// let mut req = Img2ImgRequest::default();
     /// req.with_restore_faces(true);
     /// ```
@@ -433,6 +476,7 @@ impl Img2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Img2ImgRequest;
     /// let mut req = Img2ImgRequest::default();
     /// req.with_tiling(true);
     /// ```
@@ -450,6 +494,7 @@ impl Img2ImgRequest {
     /// # Example
     ///
     /// ```
+    /// # use stable_diffusion_api::Img2ImgRequest;
     /// let mut req = Img2ImgRequest::default();
     /// req.with_prompt("bad, ugly, worst quality".to_string());
     /// ```
@@ -551,6 +596,7 @@ type Result<T> = std::result::Result<T, Img2ImgError>;
 pub struct Img2Img {
     client: reqwest::Client,
     endpoint: Url,
+    options: RequestOptions,
 }
 
 impl Img2Img {
@@ -580,7 +626,28 @@ impl Img2Img {
     ///
     /// A new Img2Img instance.
     pub fn new_with_url(client: reqwest::Client, endpoint: Url) -> Self {
-        Self { client, endpoint }
+        Self {
+            client,
+            endpoint,
+            options: RequestOptions::default(),
+        }
+    }
+
+    /// Sets the credentials sent with the client's requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth` - The credentials to send, or `None` to send none.
+    pub fn with_auth(mut self, auth: Option<Auth>) -> Self {
+        self.options.auth = auth;
+        self
+    }
+
+    backend_request::retry_builders!(options.retry);
+
+    pub(crate) fn with_options(mut self, options: RequestOptions) -> Self {
+        self.options = options;
+        self
     }
 
     /// Sends an image request using the Img2Img client.
@@ -593,12 +660,8 @@ impl Img2Img {
     ///
     /// A `Result` containing an `ImgResponse<Img2ImgRequest>` on success, or an error if one occurred.
     pub async fn send(&self, request: &Img2ImgRequest) -> Result<ImgResponse<Img2ImgRequest>> {
-        let response = self
-            .client
-            .post(self.endpoint.clone())
-            .json(&request)
-            .send()
-            .await?;
+        let request = self.client.post(self.endpoint.clone()).json(&request);
+        let response = self.options.send(request).await?;
         if response.status().is_success() {
             return response.json().await.map_err(Img2ImgError::InvalidResponse);
         }
@@ -0,0 +1,108 @@
+use reqwest::Url;
+
+use super::{request::RequestOptions, Auth};
+
+/// Errors that can occur when interrupting a generation.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum InterruptError {
+    /// Error parsing endpoint URL
+    #[error("Failed to parse endpoint URL")]
+    ParseError(#[from] url::ParseError),
+    /// Error sending request
+    #[error("Failed to send request")]
+    RequestFailed(#[from] reqwest::Error),
+    /// An error occurred getting response data.
+    #[error("Failed to get response data")]
+    GetDataFailed(#[source] reqwest::Error),
+    /// Server returned an error for the interrupt request
+    #[error("Interrupt request failed: {status}: {error}")]
+    InterruptFailed {
+        status: reqwest::StatusCode,
+        error: String,
+    },
+}
+
+type Result<T> = std::result::Result<T, InterruptError>;
+
+/// A client for interrupting the generation the Stable Diffusion WebUI is running.
+pub struct Interrupter {
+    client: reqwest::Client,
+    endpoint: Url,
+    options: RequestOptions,
+}
+
+impl Interrupter {
+    /// Constructs a new Interrupter client with a given `reqwest::Client` and Stable Diffusion API
+    /// endpoint `String`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `String` representation of the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a new Interrupter instance on success, or an error if url parsing failed.
+    pub fn new(client: reqwest::Client, endpoint: String) -> Result<Self> {
+        Ok(Self::new_with_url(client, Url::parse(&endpoint)?))
+    }
+
+    /// Constructs a new Interrupter client with a given `reqwest::Client` and endpoint `Url`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `Url` representing the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A new Interrupter instance.
+    pub fn new_with_url(client: reqwest::Client, endpoint: Url) -> Self {
+        Self {
+            client,
+            endpoint,
+            options: RequestOptions::default(),
+        }
+    }
+
+    /// Sets the credentials sent with the client's requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth` - The credentials to send, or `None` to send none.
+    pub fn with_auth(mut self, auth: Option<Auth>) -> Self {
+        self.options.auth = auth;
+        self
+    }
+
+    backend_request::retry_builders!(options.retry);
+
+    pub(crate) fn with_options(mut self, options: RequestOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Interrupts the generation that's currently running. Requests waiting for it to finish
+    /// are not affected.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `()` on success, or an error if one occurred.
+    pub async fn interrupt(&self) -> Result<()> {
+        let request = self.client.post(self.endpoint.clone());
+        let response = self.options.send(request).await?;
+        if response.status().is_success() {
+            return Ok(());
+        }
+        let status = response.status();
+        let text = response
+            .text()
+            .await
+            .map_err(InterruptError::GetDataFailed)?;
+        Err(InterruptError::InterruptFailed {
+            status,
+            error: text,
+        })
+    }
+}
@@ -1,3 +1,5 @@
+use std::{collections::BTreeMap, time::Duration};
+
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
@@ -8,6 +10,42 @@ pub use txt2img::*;
 mod img2img;
 pub use img2img::*;
 
+mod interrupt;
+pub use interrupt::*;
+
+mod loras;
+pub use loras::*;
+
+mod memory;
+pub use memory::*;
+
+mod models;
+pub use models::*;
+
+mod options;
+pub use options::*;
+
+mod progress;
+pub use progress::*;
+
+mod upscale;
+pub use upscale::*;
+
+mod vaes;
+pub use vaes::*;
+
+mod version;
+pub use version::*;
+
+mod auth;
+pub use auth::Auth;
+
+mod request;
+use request::RequestOptions;
+
+/// The version of this crate.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
 /// Errors that can occur when interacting with the Stable Diffusion API.
 #[derive(thiserror::Error, Debug)]
 #[non_exhaustive]
@@ -30,6 +68,7 @@ type Result<T> = std::result::Result<T, ApiError>;
 pub struct Api {
     client: reqwest::Client,
     url: Url,
+    options: RequestOptions,
 }
 
 impl Default for Api {
@@ -37,6 +76,7 @@ impl Default for Api {
         Self {
             client: reqwest::Client::new(),
             url: Url::parse("http://localhost:7860").expect("Failed to parse default URL"),
+            options: RequestOptions::default(),
         }
     }
 }
@@ -83,19 +123,55 @@ impl Api {
         Ok(Self {
             client,
             url: Url::parse(url.as_ref())?,
+            options: RequestOptions::default(),
         })
     }
 
+    /// Sets the credentials sent with every request to the WebUI, for example when it was
+    /// started with `--api-auth`.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth` - The credentials to send with each request.
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.options.auth = Some(auth);
+        self
+    }
+
+    /// Sets how long to wait for a response to any request before giving up. Requests wait
+    /// indefinitely by default, since large generations can take a long time.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The time to wait for each response.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.options.retry.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how often requests that failed to connect, or idempotent requests that got a server
+    /// error, are retried. Generations are `POST`s, so they're only retried if they never
+    /// reached the WebUI. The delay before each retry doubles, starting at half a second.
+    /// Requests aren't retried by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `retries` - The number of retries after the first attempt.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.options.retry.retries = retries;
+        self
+    }
+
     /// Returns a new instance of `Txt2Img` with the API's cloned `reqwest::Client` and the URL for `txt2img` endpoint.
     ///
     /// # Errors
     ///
     /// If the URL fails to parse, an error will be returned.
     pub fn txt2img(&self) -> Result<Txt2Img> {
-        Ok(Txt2Img::new_with_url(
-            self.client.clone(),
-            self.url.join("sdapi/v1/txt2img")?,
-        ))
+        Ok(
+            Txt2Img::new_with_url(self.client.clone(), self.url.join("sdapi/v1/txt2img")?)
+                .with_options(self.options.clone()),
+        )
     }
 
     /// Returns a new instance of `Img2Img` with the API's cloned `reqwest::Client` and the URL for `img2img` endpoint.
@@ -104,10 +180,119 @@ impl Api {
     ///
     /// If the URL fails to parse, an error will be returned.
     pub fn img2img(&self) -> Result<Img2Img> {
-        Ok(Img2Img::new_with_url(
+        Ok(
+            Img2Img::new_with_url(self.client.clone(), self.url.join("sdapi/v1/img2img")?)
+                .with_options(self.options.clone()),
+        )
+    }
+
+    /// Returns a new instance of `Models` with the API's cloned `reqwest::Client` and the URL for `sd-models` endpoint.
+    ///
+    /// # Errors
+    ///
+    /// If the URL fails to parse, an error will be returned.
+    pub fn models(&self) -> Result<Models> {
+        Ok(
+            Models::new_with_url(self.client.clone(), self.url.join("sdapi/v1/sd-models")?)
+                .with_options(self.options.clone()),
+        )
+    }
+
+    /// Returns a new instance of `Loras` with the API's cloned `reqwest::Client` and the URL for `loras` endpoint.
+    ///
+    /// # Errors
+    ///
+    /// If the URL fails to parse, an error will be returned.
+    pub fn loras(&self) -> Result<Loras> {
+        Ok(
+            Loras::new_with_url(self.client.clone(), self.url.join("sdapi/v1/loras")?)
+                .with_options(self.options.clone()),
+        )
+    }
+
+    /// Returns a new instance of `Vaes` with the API's cloned `reqwest::Client` and the URL for `sd-vae` endpoint.
+    ///
+    /// # Errors
+    ///
+    /// If the URL fails to parse, an error will be returned.
+    pub fn vaes(&self) -> Result<Vaes> {
+        Ok(
+            Vaes::new_with_url(self.client.clone(), self.url.join("sdapi/v1/sd-vae")?)
+                .with_options(self.options.clone()),
+        )
+    }
+
+    /// Returns a new instance of `Upscaler` with the API's cloned `reqwest::Client` and the URL for `extra-single-image` endpoint.
+    ///
+    /// # Errors
+    ///
+    /// If the URL fails to parse, an error will be returned.
+    pub fn upscaler(&self) -> Result<Upscaler> {
+        Ok(Upscaler::new_with_url(
             self.client.clone(),
-            self.url.join("sdapi/v1/img2img")?,
-        ))
+            self.url.join("sdapi/v1/extra-single-image")?,
+        )
+        .with_options(self.options.clone()))
+    }
+
+    /// Returns a new instance of `Interrupter` with the API's cloned `reqwest::Client` and the URL for `interrupt` endpoint.
+    ///
+    /// # Errors
+    ///
+    /// If the URL fails to parse, an error will be returned.
+    pub fn interrupter(&self) -> Result<Interrupter> {
+        Ok(
+            Interrupter::new_with_url(self.client.clone(), self.url.join("sdapi/v1/interrupt")?)
+                .with_options(self.options.clone()),
+        )
+    }
+
+    /// Returns a new instance of `Progress` with the API's cloned `reqwest::Client` and the URL for `progress` endpoint.
+    ///
+    /// # Errors
+    ///
+    /// If the URL fails to parse, an error will be returned.
+    pub fn progress(&self) -> Result<Progress> {
+        Ok(
+            Progress::new_with_url(self.client.clone(), self.url.join("sdapi/v1/progress")?)
+                .with_options(self.options.clone()),
+        )
+    }
+
+    /// Returns a new instance of `Options` with the API's cloned `reqwest::Client` and the URL for `options` endpoint.
+    ///
+    /// # Errors
+    ///
+    /// If the URL fails to parse, an error will be returned.
+    pub fn options(&self) -> Result<Options> {
+        Ok(
+            Options::new_with_url(self.client.clone(), self.url.join("sdapi/v1/options")?)
+                .with_options(self.options.clone()),
+        )
+    }
+
+    /// Returns a new instance of `Memory` with the API's cloned `reqwest::Client` and the URL for `memory` endpoint.
+    ///
+    /// # Errors
+    ///
+    /// If the URL fails to parse, an error will be returned.
+    pub fn memory(&self) -> Result<Memory> {
+        Ok(
+            Memory::new_with_url(self.client.clone(), self.url.join("sdapi/v1/memory")?)
+                .with_options(self.options.clone()),
+        )
+    }
+
+    /// Returns a new instance of `Version` with the API's cloned `reqwest::Client` and the URL for `internal/version` endpoint.
+    ///
+    /// # Errors
+    ///
+    /// If the URL fails to parse, an error will be returned.
+    pub fn version(&self) -> Result<Version> {
+        Ok(
+            Version::new_with_url(self.client.clone(), self.url.join("internal/version")?)
+                .with_options(self.options.clone()),
+        )
     }
 }
 
@@ -172,7 +357,7 @@ pub struct ImgInfo {
     /// A vector of all the subseeds used for image generation.
     pub all_subseeds: Option<Vec<i64>>,
     /// The strength of the subseed used when generating the image.
-    pub subseed_strength: Option<u32>,
+    pub subseed_strength: Option<f64>,
     /// The width of the generated image.
     pub width: Option<i32>,
     /// The height of the generated image.
@@ -229,4 +414,7 @@ pub struct ExtraGenParams {
     /// Names and hashes of Textual Inversion models used for image generation.
     #[serde(rename = "TI hashes")]
     pub ti_hashes: Option<String>,
+    /// Other parameters, such as those added by extensions, keyed by their name.
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_json::Value>,
 }
@@ -0,0 +1,119 @@
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use super::{request::RequestOptions, Auth};
+
+/// Errors that can occur when listing the available LoRAs.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum LorasError {
+    /// Error parsing endpoint URL
+    #[error("Failed to parse endpoint URL")]
+    ParseError(#[from] url::ParseError),
+    /// Error sending request
+    #[error("Failed to send request")]
+    RequestFailed(#[from] reqwest::Error),
+    /// An error occurred while parsing the response from the API.
+    #[error("Parsing response failed")]
+    InvalidResponse(#[source] reqwest::Error),
+    /// An error occurred getting response data.
+    #[error("Failed to get response data")]
+    GetDataFailed(#[source] reqwest::Error),
+    /// Server returned an error for the LoRAs request
+    #[error("LoRAs request failed: {status}: {error}")]
+    LorasFailed {
+        status: reqwest::StatusCode,
+        error: String,
+    },
+}
+
+type Result<T> = std::result::Result<T, LorasError>;
+
+/// A struct that represents a LoRA available to the WebUI.
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct Lora {
+    /// The name of the LoRA, which is used to apply it in a prompt, e.g. `<lora:name:1.0>`.
+    pub name: String,
+    /// An alternative name for the LoRA, from its metadata.
+    pub alias: Option<String>,
+    /// The path of the LoRA file.
+    pub path: String,
+}
+
+/// A client for listing the LoRAs available to the Stable Diffusion WebUI.
+pub struct Loras {
+    client: reqwest::Client,
+    endpoint: Url,
+    options: RequestOptions,
+}
+
+impl Loras {
+    /// Constructs a new Loras client with a given `reqwest::Client` and Stable Diffusion API
+    /// endpoint `String`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `String` representation of the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a new Loras instance on success, or an error if url parsing failed.
+    pub fn new(client: reqwest::Client, endpoint: String) -> Result<Self> {
+        Ok(Self::new_with_url(client, Url::parse(&endpoint)?))
+    }
+
+    /// Constructs a new Loras client with a given `reqwest::Client` and endpoint `Url`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `Url` representing the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A new Loras instance.
+    pub fn new_with_url(client: reqwest::Client, endpoint: Url) -> Self {
+        Self {
+            client,
+            endpoint,
+            options: RequestOptions::default(),
+        }
+    }
+
+    /// Sets the credentials sent with the client's requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth` - The credentials to send, or `None` to send none.
+    pub fn with_auth(mut self, auth: Option<Auth>) -> Self {
+        self.options.auth = auth;
+        self
+    }
+
+    backend_request::retry_builders!(options.retry);
+
+    pub(crate) fn with_options(mut self, options: RequestOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Requests the list of available LoRAs.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the LoRAs on success, or an error if one occurred.
+    pub async fn get(&self) -> Result<Vec<Lora>> {
+        let request = self.client.get(self.endpoint.clone());
+        let response = self.options.send(request).await?;
+        if response.status().is_success() {
+            return response.json().await.map_err(LorasError::InvalidResponse);
+        }
+        let status = response.status();
+        let text = response.text().await.map_err(LorasError::GetDataFailed)?;
+        Err(LorasError::LorasFailed {
+            status,
+            error: text,
+        })
+    }
+}
@@ -0,0 +1,136 @@
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use super::{request::RequestOptions, Auth};
+
+/// Errors that can occur when requesting the WebUI's memory usage.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum MemoryError {
+    /// Error parsing endpoint URL
+    #[error("Failed to parse endpoint URL")]
+    ParseError(#[from] url::ParseError),
+    /// Error sending request
+    #[error("Failed to send request")]
+    RequestFailed(#[from] reqwest::Error),
+    /// An error occurred while parsing the response from the API.
+    #[error("Parsing response failed")]
+    InvalidResponse(#[source] reqwest::Error),
+    /// An error occurred getting response data.
+    #[error("Failed to get response data")]
+    GetDataFailed(#[source] reqwest::Error),
+    /// Server returned an error for the memory request
+    #[error("Memory request failed: {status}: {error}")]
+    MemoryFailed {
+        status: reqwest::StatusCode,
+        error: String,
+    },
+}
+
+type Result<T> = std::result::Result<T, MemoryError>;
+
+/// A struct that represents the memory usage reported by the WebUI.
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct MemoryStats {
+    /// Memory usage of the CUDA device, if there is one.
+    pub cuda: Option<CudaMemory>,
+}
+
+/// A struct that represents the memory usage of the CUDA device.
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct CudaMemory {
+    /// Usage of the device's memory by all processes. Missing if the WebUI runs without CUDA.
+    pub system: Option<MemoryUsage>,
+}
+
+/// A struct that represents the usage of a memory.
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct MemoryUsage {
+    /// Free memory in bytes.
+    #[serde(default)]
+    pub free: u64,
+    /// Used memory in bytes.
+    #[serde(default)]
+    pub used: u64,
+    /// Total memory in bytes.
+    #[serde(default)]
+    pub total: u64,
+}
+
+/// A client for requesting the WebUI's RAM and VRAM usage.
+pub struct Memory {
+    client: reqwest::Client,
+    endpoint: Url,
+    options: RequestOptions,
+}
+
+impl Memory {
+    /// Constructs a new Memory client with a given `reqwest::Client` and Stable Diffusion API
+    /// endpoint `String`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `String` representation of the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a new Memory instance on success, or an error if url parsing failed.
+    pub fn new(client: reqwest::Client, endpoint: String) -> Result<Self> {
+        Ok(Self::new_with_url(client, Url::parse(&endpoint)?))
+    }
+
+    /// Constructs a new Memory client with a given `reqwest::Client` and endpoint `Url`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `Url` representing the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A new Memory instance.
+    pub fn new_with_url(client: reqwest::Client, endpoint: Url) -> Self {
+        Self {
+            client,
+            endpoint,
+            options: RequestOptions::default(),
+        }
+    }
+
+    /// Sets the credentials sent with the client's requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth` - The credentials to send, or `None` to send none.
+    pub fn with_auth(mut self, auth: Option<Auth>) -> Self {
+        self.options.auth = auth;
+        self
+    }
+
+    backend_request::retry_builders!(options.retry);
+
+    pub(crate) fn with_options(mut self, options: RequestOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Requests the WebUI's memory usage.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the memory usage on success, or an error if one occurred.
+    pub async fn get(&self) -> Result<MemoryStats> {
+        let request = self.client.get(self.endpoint.clone());
+        let response = self.options.send(request).await?;
+        if response.status().is_success() {
+            return response.json().await.map_err(MemoryError::InvalidResponse);
+        }
+        let status = response.status();
+        let text = response.text().await.map_err(MemoryError::GetDataFailed)?;
+        Err(MemoryError::MemoryFailed {
+            status,
+            error: text,
+        })
+    }
+}
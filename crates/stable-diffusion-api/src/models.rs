@@ -0,0 +1,123 @@
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use super::{request::RequestOptions, Auth};
+
+/// Errors that can occur when listing the available models.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum ModelsError {
+    /// Error parsing endpoint URL
+    #[error("Failed to parse endpoint URL")]
+    ParseError(#[from] url::ParseError),
+    /// Error sending request
+    #[error("Failed to send request")]
+    RequestFailed(#[from] reqwest::Error),
+    /// An error occurred while parsing the response from the API.
+    #[error("Parsing response failed")]
+    InvalidResponse(#[source] reqwest::Error),
+    /// An error occurred getting response data.
+    #[error("Failed to get response data")]
+    GetDataFailed(#[source] reqwest::Error),
+    /// Server returned an error for the models request
+    #[error("Models request failed: {status}: {error}")]
+    ModelsFailed {
+        status: reqwest::StatusCode,
+        error: String,
+    },
+}
+
+type Result<T> = std::result::Result<T, ModelsError>;
+
+/// A struct that represents a Stable Diffusion checkpoint available to the WebUI.
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct SdModel {
+    /// The title of the model, which is used to select it, e.g. `model.safetensors [abcdef1234]`.
+    pub title: String,
+    /// The name of the model.
+    pub model_name: String,
+    /// The short hash of the model, if it has been calculated.
+    pub hash: Option<String>,
+    /// The SHA-256 hash of the model, if it has been calculated.
+    pub sha256: Option<String>,
+    /// The path of the model file.
+    pub filename: String,
+}
+
+/// A client for listing the Stable Diffusion checkpoints available to the WebUI.
+pub struct Models {
+    client: reqwest::Client,
+    endpoint: Url,
+    options: RequestOptions,
+}
+
+impl Models {
+    /// Constructs a new Models client with a given `reqwest::Client` and Stable Diffusion API
+    /// endpoint `String`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `String` representation of the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a new Models instance on success, or an error if url parsing failed.
+    pub fn new(client: reqwest::Client, endpoint: String) -> Result<Self> {
+        Ok(Self::new_with_url(client, Url::parse(&endpoint)?))
+    }
+
+    /// Constructs a new Models client with a given `reqwest::Client` and endpoint `Url`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `Url` representing the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A new Models instance.
+    pub fn new_with_url(client: reqwest::Client, endpoint: Url) -> Self {
+        Self {
+            client,
+            endpoint,
+            options: RequestOptions::default(),
+        }
+    }
+
+    /// Sets the credentials sent with the client's requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth` - The credentials to send, or `None` to send none.
+    pub fn with_auth(mut self, auth: Option<Auth>) -> Self {
+        self.options.auth = auth;
+        self
+    }
+
+    backend_request::retry_builders!(options.retry);
+
+    pub(crate) fn with_options(mut self, options: RequestOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Requests the list of available models.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the models on success, or an error if one occurred.
+    pub async fn get(&self) -> Result<Vec<SdModel>> {
+        let request = self.client.get(self.endpoint.clone());
+        let response = self.options.send(request).await?;
+        if response.status().is_success() {
+            return response.json().await.map_err(ModelsError::InvalidResponse);
+        }
+        let status = response.status();
+        let text = response.text().await.map_err(ModelsError::GetDataFailed)?;
+        Err(ModelsError::ModelsFailed {
+            status,
+            error: text,
+        })
+    }
+}
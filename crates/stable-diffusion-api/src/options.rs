@@ -0,0 +1,117 @@
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use super::{request::RequestOptions, Auth};
+
+/// Errors that can occur when requesting the WebUI's options.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum OptionsError {
+    /// Error parsing endpoint URL
+    #[error("Failed to parse endpoint URL")]
+    ParseError(#[from] url::ParseError),
+    /// Error sending request
+    #[error("Failed to send request")]
+    RequestFailed(#[from] reqwest::Error),
+    /// An error occurred while parsing the response from the API.
+    #[error("Parsing response failed")]
+    InvalidResponse(#[source] reqwest::Error),
+    /// An error occurred getting response data.
+    #[error("Failed to get response data")]
+    GetDataFailed(#[source] reqwest::Error),
+    /// Server returned an error for the options request
+    #[error("Options request failed: {status}: {error}")]
+    OptionsFailed {
+        status: reqwest::StatusCode,
+        error: String,
+    },
+}
+
+type Result<T> = std::result::Result<T, OptionsError>;
+
+/// A struct that represents the WebUI's options. Only the options the crate uses are included.
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct WebUiOptions {
+    /// The title of the loaded checkpoint, e.g. `model.safetensors [abcdef1234]`.
+    pub sd_model_checkpoint: Option<String>,
+    /// The name of the selected VAE, or `Automatic` or `None`.
+    pub sd_vae: Option<String>,
+}
+
+/// A client for requesting the WebUI's options, which include the loaded checkpoint.
+pub struct Options {
+    client: reqwest::Client,
+    endpoint: Url,
+    options: RequestOptions,
+}
+
+impl Options {
+    /// Constructs a new Options client with a given `reqwest::Client` and Stable Diffusion API
+    /// endpoint `String`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `String` representation of the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a new Options instance on success, or an error if url parsing failed.
+    pub fn new(client: reqwest::Client, endpoint: String) -> Result<Self> {
+        Ok(Self::new_with_url(client, Url::parse(&endpoint)?))
+    }
+
+    /// Constructs a new Options client with a given `reqwest::Client` and endpoint `Url`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `Url` representing the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A new Options instance.
+    pub fn new_with_url(client: reqwest::Client, endpoint: Url) -> Self {
+        Self {
+            client,
+            endpoint,
+            options: RequestOptions::default(),
+        }
+    }
+
+    /// Sets the credentials sent with the client's requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth` - The credentials to send, or `None` to send none.
+    pub fn with_auth(mut self, auth: Option<Auth>) -> Self {
+        self.options.auth = auth;
+        self
+    }
+
+    backend_request::retry_builders!(options.retry);
+
+    pub(crate) fn with_options(mut self, options: RequestOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Requests the WebUI's options.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the options on success, or an error if one occurred.
+    pub async fn get(&self) -> Result<WebUiOptions> {
+        let request = self.client.get(self.endpoint.clone());
+        let response = self.options.send(request).await?;
+        if response.status().is_success() {
+            return response.json().await.map_err(OptionsError::InvalidResponse);
+        }
+        let status = response.status();
+        let text = response.text().await.map_err(OptionsError::GetDataFailed)?;
+        Err(OptionsError::OptionsFailed {
+            status,
+            error: text,
+        })
+    }
+}
@@ -0,0 +1,163 @@
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use super::{request::RequestOptions, Auth};
+
+/// Errors that can occur when getting the progress of a generation.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum ProgressError {
+    /// Error parsing endpoint URL
+    #[error("Failed to parse endpoint URL")]
+    ParseError(#[from] url::ParseError),
+    /// Error sending request
+    #[error("Failed to send request")]
+    RequestFailed(#[from] reqwest::Error),
+    /// An error occurred while parsing the response from the API.
+    #[error("Parsing response failed")]
+    InvalidResponse(#[source] reqwest::Error),
+    /// An error occurred getting response data.
+    #[error("Failed to get response data")]
+    GetDataFailed(#[source] reqwest::Error),
+    /// Server returned an error for the progress request
+    #[error("Progress request failed: {status}: {error}")]
+    ProgressFailed {
+        status: reqwest::StatusCode,
+        error: String,
+    },
+}
+
+type Result<T> = std::result::Result<T, ProgressError>;
+
+/// A struct that represents the state of the job the WebUI is running.
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ProgressState {
+    /// Whether the current image of the job was skipped.
+    pub skipped: bool,
+    /// Whether the job was interrupted.
+    pub interrupted: bool,
+    /// The name of the job, e.g. `scripts_txt2img`.
+    pub job: String,
+    /// The number of batches in the job. `0` when no job is running.
+    pub job_count: i64,
+    /// When the job started, e.g. `20240101120000`.
+    pub job_timestamp: String,
+    /// The index of the batch being generated.
+    pub job_no: i64,
+    /// The number of sampling steps completed for the current batch.
+    pub sampling_step: i64,
+    /// The number of sampling steps for each batch.
+    pub sampling_steps: i64,
+}
+
+/// A struct that represents the progress of the job the WebUI is running.
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ProgressResponse {
+    /// The progress of the job, from `0.0` to `1.0`.
+    pub progress: f64,
+    /// The estimated time until the job completes, in seconds.
+    pub eta_relative: f64,
+    /// The state of the job.
+    pub state: ProgressState,
+    /// A base64-encoded preview of the image being generated, if requested.
+    pub current_image: Option<String>,
+    /// Text describing the progress of the job, if any.
+    pub textinfo: Option<String>,
+}
+
+impl ProgressResponse {
+    /// Returns whether the WebUI is running a job.
+    pub fn is_running(&self) -> bool {
+        self.state.job_count > 0
+    }
+}
+
+/// A client for getting the progress of the job the Stable Diffusion WebUI is running.
+pub struct Progress {
+    client: reqwest::Client,
+    endpoint: Url,
+    options: RequestOptions,
+}
+
+impl Progress {
+    /// Constructs a new Progress client with a given `reqwest::Client` and Stable Diffusion API
+    /// endpoint `String`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `String` representation of the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a new Progress instance on success, or an error if url parsing failed.
+    pub fn new(client: reqwest::Client, endpoint: String) -> Result<Self> {
+        Ok(Self::new_with_url(client, Url::parse(&endpoint)?))
+    }
+
+    /// Constructs a new Progress client with a given `reqwest::Client` and endpoint `Url`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `Url` representing the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A new Progress instance.
+    pub fn new_with_url(client: reqwest::Client, endpoint: Url) -> Self {
+        Self {
+            client,
+            endpoint,
+            options: RequestOptions::default(),
+        }
+    }
+
+    /// Sets the credentials sent with the client's requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth` - The credentials to send, or `None` to send none.
+    pub fn with_auth(mut self, auth: Option<Auth>) -> Self {
+        self.options.auth = auth;
+        self
+    }
+
+    backend_request::retry_builders!(options.retry);
+
+    pub(crate) fn with_options(mut self, options: RequestOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Requests the progress of the job that's currently running, which may have been
+    /// requested by another client. The preview of the image being generated is skipped.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the progress on success, or an error if one occurred.
+    pub async fn get(&self) -> Result<ProgressResponse> {
+        let request = self
+            .client
+            .get(self.endpoint.clone())
+            .query(&[("skip_current_image", "true")]);
+        let response = self.options.send(request).await?;
+        if response.status().is_success() {
+            return response
+                .json()
+                .await
+                .map_err(ProgressError::InvalidResponse);
+        }
+        let status = response.status();
+        let text = response
+            .text()
+            .await
+            .map_err(ProgressError::GetDataFailed)?;
+        Err(ProgressError::ProgressFailed {
+            status,
+            error: text,
+        })
+    }
+}
@@ -0,0 +1,22 @@
+use backend_request::RetryPolicy;
+
+use super::{auth::Authorize, Auth};
+
+/// How the clients send their requests.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RequestOptions {
+    /// Credentials sent with every request.
+    pub auth: Option<Auth>,
+    /// Timeout and retries of every request.
+    pub retry: RetryPolicy,
+}
+
+impl RequestOptions {
+    /// Sends `request` with the credentials, retrying it as configured.
+    pub async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> reqwest::Result<reqwest::Response> {
+        self.retry.send(request.authorize(self.auth.as_ref())).await
+    }
+}
@@ -4,7 +4,7 @@ use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 
-use super::ImgResponse;
+use super::{request::RequestOptions, Auth, ImgResponse};
 
 /// Struct representing a text to image request.
 #[skip_serializing_none]
@@ -28,6 +28,10 @@ pub struct Txt2ImgRequest {
     pub hr_resize_x: Option<u32>,
     /// Height of the image after resizing in high resolution mode.
     pub hr_resize_y: Option<u32>,
+    /// Checkpoint used for the last steps of the generation.
+    pub refiner_checkpoint: Option<String>,
+    /// Fraction of the steps after which to switch to the refiner checkpoint.
+    pub refiner_switch_at: Option<f64>,
     /// Text prompt for generating the image.
     pub prompt: Option<String>,
     /// List of style prompts for generating the image.
@@ -37,7 +41,7 @@ pub struct Txt2ImgRequest {
     /// Subseed for generating the image.
     pub subseed: Option<i64>,
     /// Strength of subseed.
-    pub subseed_strength: Option<u32>,
+    pub subseed_strength: Option<f64>,
     /// Height of the seed image.
     pub seed_resize_from_h: Option<i32>,
     /// Width of the seed image.
@@ -67,7 +71,7 @@ pub struct Txt2ImgRequest {
     /// Negative text prompt.
     pub negative_prompt: Option<String>,
     /// Eta value.
-    pub eta: Option<u32>,
+    pub eta: Option<f64>,
     /// Churn value.
     pub s_churn: Option<f64>,
     /// Maximum temperature value.
@@ -104,6 +108,7 @@ impl Txt2ImgRequest {
     /// # Example
     ///
     /// ```
+    /// # use stable_diffusion_api::Txt2ImgRequest;
     /// let mut req = Txt2ImgRequest::default();
     /// req.with_prompt("A blue sky with green grass".to_string());
     /// ```
@@ -121,6 +126,7 @@ impl Txt2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Txt2ImgRequest;
     /// let mut req = Txt2ImgRequest::default();
     /// req.with_styles(vec!["cubism".to_string(), "impressionism".to_string()]);
     /// ```
@@ -139,6 +145,7 @@ impl Txt2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Txt2ImgRequest;
     /// let mut req = Txt2ImgRequest::default();
     /// req.with_style("cubism".to_string());
     /// ```
@@ -156,11 +163,12 @@ impl Txt2ImgRequest {
     /// # Arguments
     ///
     /// * `seed` - An i64 value representing the seed for random number generation.
-    ///            Set to `-1` to randomize.
+    ///   Set to `-1` to randomize.
     ///
     /// # Example
     ///
     /// ```
+    /// # use stable_diffusion_api::Txt2ImgRequest;
     /// let mut req = Txt2ImgRequest::default();
     /// req.with_seed(12345);
     /// ```
@@ -174,11 +182,12 @@ impl Txt2ImgRequest {
     /// # Arguments
     ///
     /// * `subseed` - An i64 value representing the subseed for random number generation.
-    ///               Set to `-1` to randomize.
+    ///   Set to `-1` to randomize.
     ///
     /// # Example
     ///
     /// ```
+    /// # use stable_diffusion_api::Txt2ImgRequest;
     /// let mut req = Txt2ImgRequest::default();
     /// req.with_subseed(12345);
     /// ```
@@ -191,15 +200,17 @@ impl Txt2ImgRequest {
     ///
     /// # Arguments
     ///
-    /// * `subseed_strength` - A u32 value representing the strength of the subseed parameter.
+    /// * `subseed_strength` - An f64 value from 0 to 1 representing the strength of the subseed
+    ///   parameter.
     ///
     /// # Example
     ///
     /// ```
+    /// # use stable_diffusion_api::Txt2ImgRequest;
     /// let mut req = Txt2ImgRequest::default();
-    /// req.with_subseed_strength(5);
+    /// req.with_subseed_strength(0.5);
     /// ```
-    pub fn with_subseed_strength(&mut self, subseed_strength: u32) -> &mut Self {
+    pub fn with_subseed_strength(&mut self, subseed_strength: f64) -> &mut Self {
         self.subseed_strength = Some(subseed_strength);
         self
     }
@@ -213,6 +224,7 @@ impl Txt2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Txt2ImgRequest;
     /// let mut req = Txt2ImgRequest::default();
     /// req.with_sampler_name("Euler".to_string());
     /// ```
@@ -230,6 +242,7 @@ impl Txt2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Txt2ImgRequest;
     /// let mut req = Txt2ImgRequest::default();
     /// req.with_batch_size(16);
     /// ```
@@ -247,6 +260,7 @@ impl Txt2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Txt2ImgRequest;
     /// let mut req = Txt2ImgRequest::default();
     /// req.with_n_iter(1000);
     /// ```
@@ -264,6 +278,7 @@ impl Txt2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Txt2ImgRequest;
     /// let mut req = Txt2ImgRequest::default();
     /// req.with_steps(50);
     /// ```
@@ -281,6 +296,7 @@ impl Txt2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Txt2ImgRequest;
     /// let mut req = Txt2ImgRequest::default();
     /// req.with_cfg_scale(0.7);
     /// ```
@@ -298,6 +314,7 @@ impl Txt2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Txt2ImgRequest;
     /// let mut req = Txt2ImgRequest::default();
     /// req.with_width(512);
     /// ```
@@ -315,6 +332,7 @@ impl Txt2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Txt2ImgRequest;
     /// let mut req = Txt2ImgRequest::default();
     /// req.with_height(512);
     /// ```
@@ -332,6 +350,7 @@ impl Txt2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Txt2ImgRequest;
     /// let mut req = Txt2ImgRequest::default();
     /// req.with_restore_faces(true);
     /// ```
@@ -349,6 +368,7 @@ impl Txt2ImgRequest {
     /// # Examples
     ///
     /// ```
+    /// # use stable_diffusion_api::Txt2ImgRequest;
     /// let mut req = Txt2ImgRequest::default();
     /// req.with_tiling(true);
     /// ```
@@ -366,6 +386,7 @@ impl Txt2ImgRequest {
     /// # Example
     ///
     /// ```
+    /// # use stable_diffusion_api::Txt2ImgRequest;
     /// let mut req = Txt2ImgRequest::default();
     /// req.with_prompt("bad, ugly, worst quality".to_string());
     /// ```
@@ -390,6 +411,10 @@ impl Txt2ImgRequest {
             hr_second_pass_steps: request.hr_second_pass_steps.or(self.hr_second_pass_steps),
             hr_resize_x: request.hr_resize_x.or(self.hr_resize_x),
             hr_resize_y: request.hr_resize_y.or(self.hr_resize_y),
+            refiner_checkpoint: request
+                .refiner_checkpoint
+                .or(self.refiner_checkpoint.clone()),
+            refiner_switch_at: request.refiner_switch_at.or(self.refiner_switch_at),
             prompt: request.prompt.or(self.prompt.clone()),
             styles: request.styles.or(self.styles.clone()),
             seed: request.seed.or(self.seed),
@@ -458,6 +483,7 @@ type Result<T> = std::result::Result<T, Txt2ImgError>;
 pub struct Txt2Img {
     client: reqwest::Client,
     endpoint: Url,
+    options: RequestOptions,
 }
 
 impl Txt2Img {
@@ -487,7 +513,28 @@ impl Txt2Img {
     ///
     /// A new Txt2Img instance.
     pub fn new_with_url(client: reqwest::Client, endpoint: Url) -> Self {
-        Self { client, endpoint }
+        Self {
+            client,
+            endpoint,
+            options: RequestOptions::default(),
+        }
+    }
+
+    /// Sets the credentials sent with the client's requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth` - The credentials to send, or `None` to send none.
+    pub fn with_auth(mut self, auth: Option<Auth>) -> Self {
+        self.options.auth = auth;
+        self
+    }
+
+    backend_request::retry_builders!(options.retry);
+
+    pub(crate) fn with_options(mut self, options: RequestOptions) -> Self {
+        self.options = options;
+        self
     }
 
     /// Sends an image request using the Txt2Img client.
@@ -500,11 +547,10 @@ impl Txt2Img {
     ///
     /// A `Result` containing an `ImgResponse<Txt2ImgRequest>` on success, or an error if one occurred.
     pub async fn send(&self, request: &Txt2ImgRequest) -> Result<ImgResponse<Txt2ImgRequest>> {
+        let request = self.client.post(self.endpoint.clone()).json(&request);
         let response = self
-            .client
-            .post(self.endpoint.clone())
-            .json(&request)
-            .send()
+            .options
+            .send(request)
             .await
             .map_err(Txt2ImgError::RequestFailed)?;
         if response.status().is_success() {
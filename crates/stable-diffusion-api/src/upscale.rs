@@ -0,0 +1,233 @@
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+
+use super::{request::RequestOptions, Auth};
+
+/// Struct representing a request to upscale a single image with the extras endpoint.
+#[skip_serializing_none]
+#[derive(Default, PartialEq, Serialize, Deserialize, Debug, Clone)]
+pub struct UpscaleRequest {
+    /// The base64-encoded image to upscale.
+    pub image: Option<String>,
+    /// How to resize the image: 0 to scale by `upscaling_resize`, 1 to resize to
+    /// `upscaling_resize_w` by `upscaling_resize_h`.
+    pub resize_mode: Option<u32>,
+    /// Factor to scale the image by.
+    pub upscaling_resize: Option<f64>,
+    /// Width to resize the image to.
+    pub upscaling_resize_w: Option<u32>,
+    /// Height to resize the image to.
+    pub upscaling_resize_h: Option<u32>,
+    /// Whether to crop the image to fit the target size.
+    pub upscaling_crop: Option<bool>,
+    /// Name of the upscaler to use, e.g. `R-ESRGAN 4x+`.
+    pub upscaler_1: Option<String>,
+    /// Name of a second upscaler to blend with the first.
+    pub upscaler_2: Option<String>,
+    /// Visibility of the second upscaler, from 0 to 1.
+    pub extras_upscaler_2_visibility: Option<f64>,
+    /// Visibility of GFPGAN face restoration, from 0 to 1.
+    pub gfpgan_visibility: Option<f64>,
+    /// Visibility of CodeFormer face restoration, from 0 to 1.
+    pub codeformer_visibility: Option<f64>,
+    /// Weight of CodeFormer face restoration, from 0 to 1.
+    pub codeformer_weight: Option<f64>,
+    /// Whether to upscale before restoring faces.
+    pub upscale_first: Option<bool>,
+}
+
+impl UpscaleRequest {
+    /// Adds an image to the request.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - The image to upscale.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_diffusion_api::UpscaleRequest;
+    /// let mut req = UpscaleRequest::default();
+    /// req.with_image(b"image data");
+    /// ```
+    pub fn with_image<T>(&mut self, image: T) -> &mut Self
+    where
+        T: AsRef<[u8]>,
+    {
+        use base64::{engine::general_purpose, Engine as _};
+        self.image = Some(general_purpose::STANDARD.encode(image));
+        self
+    }
+
+    /// Sets the factor to scale the image by.
+    ///
+    /// # Arguments
+    ///
+    /// * `scale` - The factor to scale the image by.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_diffusion_api::UpscaleRequest;
+    /// let mut req = UpscaleRequest::default();
+    /// req.with_scale(2.0);
+    /// ```
+    pub fn with_scale(&mut self, scale: f64) -> &mut Self {
+        self.resize_mode = Some(0);
+        self.upscaling_resize = Some(scale);
+        self
+    }
+
+    /// Sets the upscaler to use.
+    ///
+    /// # Arguments
+    ///
+    /// * `upscaler` - The name of the upscaler, e.g. `R-ESRGAN 4x+`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_diffusion_api::UpscaleRequest;
+    /// let mut req = UpscaleRequest::default();
+    /// req.with_upscaler("R-ESRGAN 4x+".to_string());
+    /// ```
+    pub fn with_upscaler(&mut self, upscaler: String) -> &mut Self {
+        self.upscaler_1 = Some(upscaler);
+        self
+    }
+}
+
+/// A struct that represents the response from the extras endpoint.
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct UpscaleResponse {
+    /// The base64-encoded upscaled image.
+    pub image: String,
+    /// HTML describing the processing that was done.
+    pub html_info: Option<String>,
+}
+
+impl UpscaleResponse {
+    /// Decodes and returns the upscaled image.
+    ///
+    /// # Errors
+    ///
+    /// If the image fails to decode, an error will be returned.
+    pub fn image(&self) -> Result<Vec<u8>> {
+        use base64::{engine::general_purpose, Engine as _};
+        general_purpose::STANDARD
+            .decode(&self.image)
+            .map_err(UpscaleError::DecodeError)
+    }
+}
+
+/// Errors that can occur when interacting with the extras API.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum UpscaleError {
+    /// Error parsing endpoint URL
+    #[error("Failed to parse endpoint URL")]
+    ParseError(#[from] url::ParseError),
+    /// Error sending request
+    #[error("Failed to send request")]
+    RequestFailed(#[from] reqwest::Error),
+    /// An error occurred while parsing the response from the API.
+    #[error("Parsing response failed")]
+    InvalidResponse(#[source] reqwest::Error),
+    /// An error occurred getting response data.
+    #[error("Failed to get response data")]
+    GetDataFailed(#[source] reqwest::Error),
+    /// Error decoding image from response
+    #[error("Failed to decode image from response")]
+    DecodeError(#[source] base64::DecodeError),
+    /// Server returned an error for the upscale request
+    #[error("Upscale request failed: {status}: {error}")]
+    UpscaleFailed {
+        status: reqwest::StatusCode,
+        error: String,
+    },
+}
+
+type Result<T> = std::result::Result<T, UpscaleError>;
+
+/// A client for upscaling images with the extras endpoint.
+pub struct Upscaler {
+    client: reqwest::Client,
+    endpoint: Url,
+    options: RequestOptions,
+}
+
+impl Upscaler {
+    /// Constructs a new Upscaler client with a given `reqwest::Client` and Stable Diffusion API
+    /// endpoint `String`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `String` representation of the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a new Upscaler instance on success, or an error if url parsing failed.
+    pub fn new(client: reqwest::Client, endpoint: String) -> Result<Self> {
+        Ok(Self::new_with_url(client, Url::parse(&endpoint)?))
+    }
+
+    /// Constructs a new Upscaler client with a given `reqwest::Client` and endpoint `Url`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `Url` representing the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A new Upscaler instance.
+    pub fn new_with_url(client: reqwest::Client, endpoint: Url) -> Self {
+        Self {
+            client,
+            endpoint,
+            options: RequestOptions::default(),
+        }
+    }
+
+    /// Sets the credentials sent with the client's requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth` - The credentials to send, or `None` to send none.
+    pub fn with_auth(mut self, auth: Option<Auth>) -> Self {
+        self.options.auth = auth;
+        self
+    }
+
+    backend_request::retry_builders!(options.retry);
+
+    pub(crate) fn with_options(mut self, options: RequestOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sends an upscale request using the Upscaler client.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - An UpscaleRequest containing the image and how to upscale it.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an `UpscaleResponse` on success, or an error if one occurred.
+    pub async fn send(&self, request: &UpscaleRequest) -> Result<UpscaleResponse> {
+        let request = self.client.post(self.endpoint.clone()).json(&request);
+        let response = self.options.send(request).await?;
+        if response.status().is_success() {
+            return response.json().await.map_err(UpscaleError::InvalidResponse);
+        }
+        let status = response.status();
+        let text = response.text().await.map_err(UpscaleError::GetDataFailed)?;
+        Err(UpscaleError::UpscaleFailed {
+            status,
+            error: text,
+        })
+    }
+}
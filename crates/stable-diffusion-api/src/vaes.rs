@@ -0,0 +1,117 @@
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use super::{request::RequestOptions, Auth};
+
+/// Errors that can occur when listing the available VAEs.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum VaesError {
+    /// Error parsing endpoint URL
+    #[error("Failed to parse endpoint URL")]
+    ParseError(#[from] url::ParseError),
+    /// Error sending request
+    #[error("Failed to send request")]
+    RequestFailed(#[from] reqwest::Error),
+    /// An error occurred while parsing the response from the API.
+    #[error("Parsing response failed")]
+    InvalidResponse(#[source] reqwest::Error),
+    /// An error occurred getting response data.
+    #[error("Failed to get response data")]
+    GetDataFailed(#[source] reqwest::Error),
+    /// Server returned an error for the VAEs request
+    #[error("VAEs request failed: {status}: {error}")]
+    VaesFailed {
+        status: reqwest::StatusCode,
+        error: String,
+    },
+}
+
+type Result<T> = std::result::Result<T, VaesError>;
+
+/// A struct that represents a VAE available to the WebUI.
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct Vae {
+    /// The name of the VAE, which is used to select it with the `sd_vae` setting.
+    pub model_name: String,
+    /// The path of the VAE file.
+    pub filename: String,
+}
+
+/// A client for listing the VAEs available to the Stable Diffusion WebUI.
+pub struct Vaes {
+    client: reqwest::Client,
+    endpoint: Url,
+    options: RequestOptions,
+}
+
+impl Vaes {
+    /// Constructs a new Vaes client with a given `reqwest::Client` and Stable Diffusion API
+    /// endpoint `String`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `String` representation of the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a new Vaes instance on success, or an error if url parsing failed.
+    pub fn new(client: reqwest::Client, endpoint: String) -> Result<Self> {
+        Ok(Self::new_with_url(client, Url::parse(&endpoint)?))
+    }
+
+    /// Constructs a new Vaes client with a given `reqwest::Client` and endpoint `Url`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `Url` representing the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A new Vaes instance.
+    pub fn new_with_url(client: reqwest::Client, endpoint: Url) -> Self {
+        Self {
+            client,
+            endpoint,
+            options: RequestOptions::default(),
+        }
+    }
+
+    /// Sets the credentials sent with the client's requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth` - The credentials to send, or `None` to send none.
+    pub fn with_auth(mut self, auth: Option<Auth>) -> Self {
+        self.options.auth = auth;
+        self
+    }
+
+    backend_request::retry_builders!(options.retry);
+
+    pub(crate) fn with_options(mut self, options: RequestOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Requests the list of available VAEs.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the VAEs on success, or an error if one occurred.
+    pub async fn get(&self) -> Result<Vec<Vae>> {
+        let request = self.client.get(self.endpoint.clone());
+        let response = self.options.send(request).await?;
+        if response.status().is_success() {
+            return response.json().await.map_err(VaesError::InvalidResponse);
+        }
+        let status = response.status();
+        let text = response.text().await.map_err(VaesError::GetDataFailed)?;
+        Err(VaesError::VaesFailed {
+            status,
+            error: text,
+        })
+    }
+}
@@ -0,0 +1,117 @@
+use reqwest::Url;
+
+use super::{request::RequestOptions, Auth};
+
+/// Errors that can occur when requesting the WebUI version.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum VersionError {
+    /// Error parsing endpoint URL
+    #[error("Failed to parse endpoint URL")]
+    ParseError(#[from] url::ParseError),
+    /// Error sending request
+    #[error("Failed to send request")]
+    RequestFailed(#[from] reqwest::Error),
+    /// An error occurred getting response data.
+    #[error("Failed to get response data")]
+    GetDataFailed(#[source] reqwest::Error),
+    /// Server returned an error for the version request
+    #[error("Version request failed: {status}: {error}")]
+    VersionFailed {
+        status: reqwest::StatusCode,
+        error: String,
+    },
+}
+
+type Result<T> = std::result::Result<T, VersionError>;
+
+/// A client for requesting the version of the Stable Diffusion WebUI.
+pub struct Version {
+    client: reqwest::Client,
+    endpoint: Url,
+    options: RequestOptions,
+}
+
+impl Version {
+    /// Constructs a new Version client with a given `reqwest::Client` and Stable Diffusion API
+    /// endpoint `String`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `String` representation of the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a new Version instance on success, or an error if url parsing failed.
+    pub fn new(client: reqwest::Client, endpoint: String) -> Result<Self> {
+        Ok(Self::new_with_url(client, Url::parse(&endpoint)?))
+    }
+
+    /// Constructs a new Version client with a given `reqwest::Client` and endpoint `Url`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` used to send requests.
+    /// * `endpoint` - A `Url` representing the endpoint url.
+    ///
+    /// # Returns
+    ///
+    /// A new Version instance.
+    pub fn new_with_url(client: reqwest::Client, endpoint: Url) -> Self {
+        Self {
+            client,
+            endpoint,
+            options: RequestOptions::default(),
+        }
+    }
+
+    /// Sets the credentials sent with the client's requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth` - The credentials to send, or `None` to send none.
+    pub fn with_auth(mut self, auth: Option<Auth>) -> Self {
+        self.options.auth = auth;
+        self
+    }
+
+    backend_request::retry_builders!(options.retry);
+
+    pub(crate) fn with_options(mut self, options: RequestOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Requests the version of the WebUI.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the version on success, or an error if one occurred.
+    pub async fn get(&self) -> Result<String> {
+        let request = self.client.get(self.endpoint.clone());
+        let response = self.options.send(request).await?;
+        let status = response.status();
+        let text = response.text().await.map_err(VersionError::GetDataFailed)?;
+        if status.is_success() {
+            return Ok(parse_version(&text));
+        }
+        Err(VersionError::VersionFailed {
+            status,
+            error: text,
+        })
+    }
+}
+
+/// Extracts the version from a response, which may be a JSON object with a `version` field, a
+/// JSON string, or plain text, depending on the WebUI version.
+fn parse_version(text: &str) -> String {
+    match serde_json::from_str::<serde_json::Value>(text) {
+        Ok(serde_json::Value::String(version)) => version,
+        Ok(serde_json::Value::Object(object)) => match object.get("version") {
+            Some(serde_json::Value::String(version)) => version.clone(),
+            _ => text.trim().to_owned(),
+        },
+        _ => text.trim().to_owned(),
+    }
+}
@@ -0,0 +1,131 @@
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+/// Quotes that may open a prompt. Phones often replace straight quotes with curly ones.
+const OPENING_QUOTES: [char; 2] = ['"', '“'];
+/// Quotes that may close a prompt.
+const CLOSING_QUOTES: [char; 2] = ['"', '”'];
+
+/// Parses the two quoted prompts of `/ab "<prompt A>" "<prompt B>"`. Returns `None` unless
+/// `text` is exactly two non-empty quoted prompts.
+pub(crate) fn parse_prompts(text: &str) -> Option<(String, String)> {
+    fn quoted(text: &str) -> Option<(&str, &str)> {
+        let rest = text.trim_start().strip_prefix(OPENING_QUOTES)?;
+        let end = rest.find(CLOSING_QUOTES)?;
+        let prompt = rest[..end].trim();
+        let quote_len = rest[end..].chars().next()?.len_utf8();
+        (!prompt.is_empty()).then_some((prompt, &rest[end + quote_len..]))
+    }
+
+    let (a, rest) = quoted(text)?;
+    let (b, rest) = quoted(rest)?;
+    rest.trim().is_empty().then(|| (a.to_owned(), b.to_owned()))
+}
+
+/// One of the two prompts of an A/B test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AbChoice {
+    A,
+    B,
+}
+
+impl AbChoice {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::A => "a",
+            Self::B => "b",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "a" => Some(Self::A),
+            "b" => Some(Self::B),
+            _ => None,
+        }
+    }
+}
+
+/// A vote cast with a button below an A/B test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AbVote {
+    /// Id of the test in the history.
+    pub test_id: i64,
+    pub choice: AbChoice,
+}
+
+impl AbVote {
+    /// Parses a vote from callback data.
+    pub fn parse(data: &str) -> Option<Self> {
+        let (test_id, choice) = data.strip_prefix("ab/")?.split_once('/')?;
+        Some(Self {
+            test_id: test_id.parse().ok()?,
+            choice: AbChoice::parse(choice)?,
+        })
+    }
+
+    pub fn callback_data(&self) -> String {
+        format!("ab/{}/{}", self.test_id, self.choice.as_str())
+    }
+}
+
+/// The votes cast in an A/B test.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct AbTally {
+    pub a: u32,
+    pub b: u32,
+}
+
+/// Builds the keyboard for voting in the test `test_id`, showing the votes cast so far.
+pub(crate) fn vote_keyboard(test_id: i64, tally: AbTally) -> InlineKeyboardMarkup {
+    let button = |choice, label: &str, votes| {
+        InlineKeyboardButton::callback(
+            format!("{label} ({votes})"),
+            AbVote { test_id, choice }.callback_data(),
+        )
+    };
+    InlineKeyboardMarkup::new([[
+        button(AbChoice::A, "👍 A", tally.a),
+        button(AbChoice::B, "👍 B", tally.b),
+    ]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_prompts() {
+        assert_eq!(
+            parse_prompts(r#""a red fox" "a fox, red fur""#),
+            Some(("a red fox".to_owned(), "a fox, red fur".to_owned()))
+        );
+        assert_eq!(
+            parse_prompts(" “a cat” \"a dog\" "),
+            Some(("a cat".to_owned(), "a dog".to_owned()))
+        );
+        assert_eq!(parse_prompts(r#""a cat""#), None);
+        assert_eq!(parse_prompts(r#""a cat" """#), None);
+        assert_eq!(parse_prompts(r#""a cat" "a dog" "a bird""#), None);
+        assert_eq!(parse_prompts("a cat a dog"), None);
+    }
+
+    #[test]
+    fn test_vote() {
+        let vote = AbVote {
+            test_id: 12,
+            choice: AbChoice::B,
+        };
+        assert_eq!(vote.callback_data(), "ab/12/b");
+        assert_eq!(AbVote::parse("ab/12/b"), Some(vote));
+        assert_eq!(AbVote::parse("ab/12/c"), None);
+        assert_eq!(AbVote::parse("ab/x/a"), None);
+
+        let buttons = vote_keyboard(12, AbTally { a: 3, b: 0 })
+            .inline_keyboard
+            .into_iter()
+            .flatten()
+            .map(|button| button.text)
+            .collect::<Vec<_>>();
+        assert_eq!(buttons, vec!["👍 A (3)", "👍 B (0)"]);
+    }
+}
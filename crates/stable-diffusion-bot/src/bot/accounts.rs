@@ -0,0 +1,88 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::anyhow;
+use teloxide::types::UserId;
+
+/// The identity that a user's generations are attributed to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum Account {
+    /// A user who doesn't share an account.
+    User(UserId),
+    /// A named account shared by several users, such as a family or team.
+    Shared(String),
+}
+
+impl Account {
+    /// Returns the key that the account's usage is stored under.
+    pub fn key(&self) -> String {
+        match self {
+            Self::User(user_id) => format!("user:{user_id}"),
+            Self::Shared(name) => format!("shared:{name}"),
+        }
+    }
+}
+
+/// Resolves users to the accounts they share, if any.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AccountResolver {
+    shared: HashMap<UserId, String>,
+}
+
+impl AccountResolver {
+    /// Constructs a new `AccountResolver` from a map of account names to member user ids.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a user is a member of more than one account.
+    pub fn new(accounts: BTreeMap<String, Vec<u64>>) -> anyhow::Result<Self> {
+        let mut shared = HashMap::new();
+        for (name, members) in accounts {
+            for member in members {
+                if let Some(other) = shared.insert(UserId(member), name.clone()) {
+                    if other != name {
+                        return Err(anyhow!(
+                            "User {member} is a member of both the {other} and {name} accounts"
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(Self { shared })
+    }
+
+    /// Returns the account of a user.
+    pub fn resolve(&self, user_id: UserId) -> Account {
+        match self.shared.get(&user_id) {
+            Some(name) => Account::Shared(name.clone()),
+            None => Account::User(user_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_account() {
+        let resolver =
+            AccountResolver::new(BTreeMap::from([("family".to_owned(), vec![1, 2])])).unwrap();
+        assert_eq!(
+            resolver.resolve(UserId(1)),
+            Account::Shared("family".to_owned())
+        );
+        assert_eq!(resolver.resolve(UserId(1)), resolver.resolve(UserId(2)));
+        assert_eq!(resolver.resolve(UserId(3)), Account::User(UserId(3)));
+        assert_eq!(resolver.resolve(UserId(3)).key(), "user:3");
+        assert_eq!(resolver.resolve(UserId(2)).key(), "shared:family");
+    }
+
+    #[test]
+    fn test_resolve_account_overlapping() {
+        assert!(AccountResolver::new(BTreeMap::from([
+            ("family".to_owned(), vec![1, 2]),
+            ("team".to_owned(), vec![2, 3]),
+        ]))
+        .is_err());
+    }
+}
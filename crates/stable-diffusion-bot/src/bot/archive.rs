@@ -0,0 +1,274 @@
+use sal_e_api::{GeneratedImage, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+/// Struct that represents the configuration for sending large batches as zip files instead of
+/// albums.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ZipConfig {
+    /// Batches with at least this many images are sent as zip files.
+    #[serde(default = "default_min_images")]
+    pub min_images: usize,
+    /// Maximum size of each zip file, in bytes. Larger batches are split over several files,
+    /// each with its own manifest. Telegram's cloud Bot API accepts files up to 50 MB.
+    #[serde(default = "default_max_part_bytes")]
+    pub max_part_bytes: usize,
+}
+
+fn default_min_images() -> usize {
+    10
+}
+
+fn default_max_part_bytes() -> usize {
+    45 * 1024 * 1024
+}
+
+impl Default for ZipConfig {
+    fn default() -> Self {
+        Self {
+            min_images: default_min_images(),
+            max_part_bytes: default_max_part_bytes(),
+        }
+    }
+}
+
+impl ZipConfig {
+    /// Returns whether a batch of `count` images should be sent as zip files.
+    pub(crate) fn applies_to(&self, count: usize) -> bool {
+        count >= self.min_images.max(2)
+    }
+}
+
+/// Size of the headers stored for each file, excluding its name.
+const ENTRY_OVERHEAD: usize = 30 + 46;
+
+/// Writes zip files. Files are stored uncompressed, since the images are already compressed.
+#[derive(Default)]
+struct ZipWriter {
+    data: Vec<u8>,
+    central_directory: Vec<u8>,
+    entries: u16,
+}
+
+impl ZipWriter {
+    fn add_file(&mut self, name: &str, contents: &[u8]) {
+        // 1980-01-01 00:00, the earliest date zip files can represent.
+        const DOS_TIME: u16 = 0;
+        const DOS_DATE: u16 = (1 << 5) | 1;
+        let crc = crc32fast::hash(contents);
+        let offset = self.data.len() as u32;
+
+        let mut header = Vec::with_capacity(26);
+        header.extend(20u16.to_le_bytes()); // version needed to extract
+        header.extend(0u16.to_le_bytes()); // flags
+        header.extend(0u16.to_le_bytes()); // compression: stored
+        header.extend(DOS_TIME.to_le_bytes());
+        header.extend(DOS_DATE.to_le_bytes());
+        header.extend(crc.to_le_bytes());
+        header.extend((contents.len() as u32).to_le_bytes()); // compressed size
+        header.extend((contents.len() as u32).to_le_bytes()); // uncompressed size
+        header.extend((name.len() as u16).to_le_bytes());
+        header.extend(0u16.to_le_bytes()); // extra field length
+
+        self.data.extend(0x04034b50u32.to_le_bytes());
+        self.data.extend(&header);
+        self.data.extend(name.as_bytes());
+        self.data.extend(contents);
+
+        self.central_directory.extend(0x02014b50u32.to_le_bytes());
+        self.central_directory.extend(20u16.to_le_bytes()); // version made by
+        self.central_directory.extend(&header);
+        self.central_directory.extend(0u16.to_le_bytes()); // comment length
+        self.central_directory.extend(0u16.to_le_bytes()); // disk number
+        self.central_directory.extend(0u16.to_le_bytes()); // internal attributes
+        self.central_directory.extend(0u32.to_le_bytes()); // external attributes
+        self.central_directory.extend(offset.to_le_bytes());
+        self.central_directory.extend(name.as_bytes());
+
+        self.entries += 1;
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let offset = self.data.len() as u32;
+        let size = self.central_directory.len() as u32;
+        self.data.append(&mut self.central_directory);
+        self.data.extend(0x06054b50u32.to_le_bytes());
+        self.data.extend(0u16.to_le_bytes()); // disk number
+        self.data.extend(0u16.to_le_bytes()); // disk with the central directory
+        self.data.extend(self.entries.to_le_bytes()); // entries on this disk
+        self.data.extend(self.entries.to_le_bytes()); // total entries
+        self.data.extend(size.to_le_bytes());
+        self.data.extend(offset.to_le_bytes());
+        self.data.extend(0u16.to_le_bytes()); // comment length
+        self.data
+    }
+}
+
+/// Returns the file name of image `index` in a batch.
+fn file_name(index: usize, image: &GeneratedImage) -> String {
+    let extension = match image.mime_type {
+        Some("image/jpeg") => "jpg",
+        Some("image/webp") => "webp",
+        _ => "png",
+    };
+    format!("{:05}.{extension}", index + 1)
+}
+
+/// Returns the parameters that generated `resp`, as a JSON object.
+pub(crate) fn params_json(resp: &Response) -> Map<String, Value> {
+    let params = &resp.params;
+    let json = json!({
+        "prompt": params.prompt(),
+        "negative_prompt": params.negative_prompt(),
+        "steps": params.steps(),
+        "sampler": params.sampler(),
+        "cfg_scale": params.cfg(),
+        "seed": params.seed(),
+        "width": params.width(),
+        "height": params.height(),
+        "model": params.model(),
+        "denoising_strength": params.denoising(),
+    });
+    match json {
+        Value::Object(map) => map,
+        _ => unreachable!("Parameters are a JSON object"),
+    }
+}
+
+/// Returns the `params.json` manifest describing the files in a zip.
+fn manifest(resp: &Response, files: &[(String, &GeneratedImage)]) -> Vec<u8> {
+    let mut manifest = params_json(resp);
+    manifest.insert(
+        "images".to_owned(),
+        files
+            .iter()
+            .map(|(name, image)| {
+                let size = image.dimensions();
+                json!({
+                    "file": name,
+                    "seed": image.seed,
+                    "width": size.map(|(width, _)| width),
+                    "height": size.map(|(_, height)| height),
+                })
+            })
+            .collect(),
+    );
+    serde_json::to_vec_pretty(&manifest).expect("Manifest is valid JSON")
+}
+
+/// Bundles the images in `resp` into zip files of at most `max_part_bytes` each, unless a
+/// single image is larger. Each zip contains a `params.json` manifest of its images.
+pub(crate) fn zip_response(resp: &Response, config: &ZipConfig) -> Vec<Vec<u8>> {
+    let files: Vec<_> = resp
+        .images
+        .iter()
+        .enumerate()
+        .map(|(index, image)| (file_name(index, image), image))
+        .collect();
+
+    let mut parts: Vec<&[(String, &GeneratedImage)]> = Vec::new();
+    let (mut start, mut size) = (0, 0);
+    for (index, (name, image)) in files.iter().enumerate() {
+        let entry_size = ENTRY_OVERHEAD + 2 * name.len() + image.data.len();
+        if index > start && size + entry_size > config.max_part_bytes {
+            parts.push(&files[start..index]);
+            (start, size) = (index, 0);
+        }
+        size += entry_size;
+    }
+    parts.push(&files[start..]);
+
+    parts
+        .into_iter()
+        .map(|part| {
+            let mut zip = ZipWriter::default();
+            for (name, image) in part {
+                zip.add_file(name, &image.data);
+            }
+            zip.add_file("params.json", &manifest(resp, part));
+            zip.finish()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use sal_e_api::Txt2ImgParams;
+    use stable_diffusion_api::ImgInfo;
+
+    use super::*;
+
+    fn response(sizes: &[usize]) -> Response {
+        Response {
+            images: sizes
+                .iter()
+                .map(|size| GeneratedImage::new(vec![0; *size]))
+                .collect(),
+            params: Box::new(ImgInfo {
+                prompt: Some("a cat".to_owned()),
+                ..Default::default()
+            }),
+            gen_params: Box::<Txt2ImgParams>::default(),
+        }
+    }
+
+    /// Returns the names of the files in the central directory of `zip`.
+    fn file_names(zip: &[u8]) -> Vec<String> {
+        let eocd = zip.len() - 22;
+        assert_eq!(zip[eocd..eocd + 4], 0x06054b50u32.to_le_bytes());
+        let entries = u16::from_le_bytes([zip[eocd + 10], zip[eocd + 11]]);
+        let mut offset = u32::from_le_bytes(zip[eocd + 16..eocd + 20].try_into().unwrap()) as usize;
+        (0..entries)
+            .map(|_| {
+                assert_eq!(zip[offset..offset + 4], 0x02014b50u32.to_le_bytes());
+                let name_len = u16::from_le_bytes([zip[offset + 28], zip[offset + 29]]) as usize;
+                let name = String::from_utf8(zip[offset + 46..offset + 46 + name_len].to_vec());
+                offset += 46 + name_len;
+                name.unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_zip_response() {
+        let parts = zip_response(&response(&[10, 10, 10]), &ZipConfig::default());
+        assert_eq!(parts.len(), 1);
+        assert_eq!(
+            file_names(&parts[0]),
+            ["00001.png", "00002.png", "00003.png", "params.json"]
+        );
+        let manifest = String::from_utf8_lossy(&parts[0]);
+        assert!(manifest.contains(r#""prompt": "a cat""#));
+    }
+
+    #[test]
+    fn test_zip_response_parts() {
+        let config = ZipConfig {
+            min_images: 2,
+            max_part_bytes: 250,
+        };
+        let parts = zip_response(&response(&[100, 100, 300, 10]), &config);
+        let names: Vec<_> = parts.iter().map(|part| file_names(part)).collect();
+        assert_eq!(
+            names,
+            [
+                vec!["00001.png", "params.json"],
+                vec!["00002.png", "params.json"],
+                vec!["00003.png", "params.json"],
+                vec!["00004.png", "params.json"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_applies_to() {
+        let config = ZipConfig::default();
+        assert!(!config.applies_to(9));
+        assert!(config.applies_to(10));
+        let config = ZipConfig {
+            min_images: 0,
+            ..Default::default()
+        };
+        assert!(!config.applies_to(1));
+    }
+}
@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+use sal_e_api::Txt2ImgApi;
+use serde::{Deserialize, Serialize};
+use teloxide::{prelude::*, types::ChatId};
+use tracing::{info, warn};
+
+/// How long to wait for each attempt to reach the backend.
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Struct that represents the configuration for waiting for the image generation backend to
+/// respond before the bot starts handling updates.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WaitForBackendConfig {
+    /// Number of times to try reaching the backend before starting without it.
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    /// Time to wait between attempts, in seconds.
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_retries() -> u32 {
+    30
+}
+
+fn default_interval_secs() -> u64 {
+    2
+}
+
+impl Default for WaitForBackendConfig {
+    fn default() -> Self {
+        Self {
+            retries: default_retries(),
+            interval_secs: default_interval_secs(),
+        }
+    }
+}
+
+/// Tries to reach `api` until it responds or the configured retries run out, returning whether
+/// it responded.
+pub(crate) async fn wait_for_backend(config: &WaitForBackendConfig, api: &dyn Txt2ImgApi) -> bool {
+    let attempts = config.retries.max(1);
+    for attempt in 1..=attempts {
+        match tokio::time::timeout(ATTEMPT_TIMEOUT, api.backend_version()).await {
+            Ok(Ok(version)) => {
+                info!("Backend is up: {}", version);
+                return true;
+            }
+            Ok(Err(e)) => warn!(
+                "Backend is not responding (attempt {}/{}): {:?}",
+                attempt, attempts, e
+            ),
+            Err(_) => warn!(
+                "Backend is not responding (attempt {}/{}): timed out",
+                attempt, attempts
+            ),
+        }
+        if attempt < attempts {
+            tokio::time::sleep(Duration::from_secs(config.interval_secs)).await;
+        }
+    }
+    false
+}
+
+/// Tells `admins` that the bot started while the backend was unreachable.
+pub(crate) async fn notify_degraded(bot: &Bot, admins: impl IntoIterator<Item = ChatId>) {
+    for admin in admins {
+        if let Err(e) = bot
+            .send_message(
+                admin,
+                "The image generation backend did not respond at startup. \
+                 The bot is running, but generations will fail until it's back.",
+            )
+            .await
+        {
+            warn!(
+                "Failed to notify admin {} about the backend being down: {:?}",
+                admin, e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use anyhow::anyhow;
+    use async_trait::async_trait;
+    use sal_e_api::{GenParams, Response, Txt2ImgApiError, Txt2ImgParams};
+
+    use super::*;
+
+    /// Backend that fails until it has been probed `up_after` times.
+    #[derive(Debug, Default)]
+    struct FlakyApi {
+        up_after: u32,
+        probes: AtomicU32,
+    }
+
+    impl Clone for FlakyApi {
+        fn clone(&self) -> Self {
+            Self {
+                up_after: self.up_after,
+                probes: AtomicU32::new(self.probes.load(Ordering::SeqCst)),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Txt2ImgApi for FlakyApi {
+        fn gen_params(&self, _user_settings: Option<&dyn GenParams>) -> Box<dyn GenParams> {
+            Box::<Txt2ImgParams>::default()
+        }
+
+        async fn txt2img(&self, _config: &dyn GenParams) -> Result<Response, Txt2ImgApiError> {
+            Err(anyhow!("Not implemented"))?
+        }
+
+        async fn backend_version(&self) -> anyhow::Result<String> {
+            if self.probes.fetch_add(1, Ordering::SeqCst) + 1 >= self.up_after {
+                Ok("mock".to_owned())
+            } else {
+                Err(anyhow!("Connection refused"))
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_backend() {
+        let config = WaitForBackendConfig {
+            retries: 3,
+            interval_secs: 0,
+        };
+
+        let api = FlakyApi {
+            up_after: 3,
+            ..Default::default()
+        };
+        assert!(wait_for_backend(&config, &api).await);
+        assert_eq!(api.probes.load(Ordering::SeqCst), 3);
+
+        let api = FlakyApi {
+            up_after: 4,
+            ..Default::default()
+        };
+        assert!(!wait_for_backend(&config, &api).await);
+        assert_eq!(api.probes.load(Ordering::SeqCst), 3);
+    }
+}
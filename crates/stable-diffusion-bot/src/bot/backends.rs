@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use teloxide::types::{ChatId, Update};
+use tracing::warn;
+
+use super::{config::BackendHandles, history::HistoryStore, ApiType, ComfyUIConfig};
+
+/// Name of the backend configured with `sd_api_url`.
+pub(crate) const DEFAULT_BACKEND: &str = "default";
+
+/// Struct that represents a backend users can switch to with `/backend`, in addition to the
+/// one configured with `sd_api_url`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackendConfig {
+    /// Name users select the backend by.
+    pub name: String,
+    /// URL of the backend.
+    pub url: String,
+    #[serde(default)]
+    pub api_type: ApiType,
+    /// Workflows and headers, if the backend is ComfyUI.
+    #[serde(default)]
+    pub comfyui: ComfyUIConfig,
+}
+
+/// The backends chats can generate with, by name. The first one is the default.
+#[derive(Debug)]
+pub(crate) struct BackendRegistry {
+    backends: Vec<(String, Arc<BackendHandles>)>,
+}
+
+impl BackendRegistry {
+    /// Creates a registry of the `default` backend and `others`, whose names must be unique.
+    pub fn new(
+        default: Arc<BackendHandles>,
+        others: Vec<(String, Arc<BackendHandles>)>,
+    ) -> anyhow::Result<Self> {
+        let backends: Vec<_> = std::iter::once((DEFAULT_BACKEND.to_owned(), default))
+            .chain(others)
+            .collect();
+        for (index, (name, _)) in backends.iter().enumerate() {
+            if backends[..index].iter().any(|(other, _)| other == name) {
+                return Err(anyhow!("There is more than one backend named {name}"));
+            }
+        }
+        Ok(Self { backends })
+    }
+
+    /// Returns the names of the backends, the default first.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.backends.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Returns whether there is a choice of backends.
+    pub fn has_choice(&self) -> bool {
+        self.backends.len() > 1
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<BackendHandles>> {
+        self.backends
+            .iter()
+            .find(|(other, _)| other == name)
+            .map(|(_, backends)| backends)
+    }
+
+    pub fn default_backend(&self) -> &Arc<BackendHandles> {
+        &self.backends[0].1
+    }
+
+    /// Returns the name of the backend selected in `chat_id`. Selections of backends that are
+    /// no longer configured fall back to the default.
+    pub async fn selected(&self, history: &HistoryStore, chat_id: ChatId) -> &str {
+        if !self.has_choice() {
+            return DEFAULT_BACKEND;
+        }
+        let name = history.chat_backend(chat_id).await.unwrap_or_else(|e| {
+            warn!("Failed to get the selected backend: {:?}", e);
+            None
+        });
+        name.and_then(|name| self.names().find(|other| *other == name))
+            .unwrap_or(DEFAULT_BACKEND)
+    }
+
+    /// Returns the backend selected in `chat_id`.
+    pub async fn for_chat(&self, history: &HistoryStore, chat_id: ChatId) -> Arc<BackendHandles> {
+        let name = self.selected(history, chat_id).await;
+        self.get(name)
+            .unwrap_or_else(|| self.default_backend())
+            .clone()
+    }
+}
+
+/// Returns the backend selected in the chat of `update`, to replace the default backend for the
+/// handlers of the update.
+pub(crate) async fn select_backend(
+    registry: Arc<BackendRegistry>,
+    history: HistoryStore,
+    update: Update,
+) -> Arc<BackendHandles> {
+    match update.chat() {
+        Some(chat) => registry.for_chat(&history, chat.id).await,
+        None => registry.default_backend().clone(),
+    }
+}
@@ -0,0 +1,127 @@
+use sal_e_api::{is_random_seed, GenParams, Response, RANDOM_SEED};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Number of pixels sampled along each axis when checking an image.
+const SAMPLES_PER_AXIS: u32 = 64;
+
+/// Struct that represents the configuration for detecting blank images, such as the solid black
+/// images some models produce when the VAE overflows, and retrying them with another seed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlankCheckConfig {
+    /// Images whose brightness varies less than this are considered blank. The variance is
+    /// measured on a 0-255 scale.
+    #[serde(default = "default_max_variance")]
+    pub max_variance: f64,
+}
+
+fn default_max_variance() -> f64 {
+    2.0
+}
+
+impl Default for BlankCheckConfig {
+    fn default() -> Self {
+        Self {
+            max_variance: default_max_variance(),
+        }
+    }
+}
+
+impl BlankCheckConfig {
+    /// Checks whether an encoded image is blank. Images that can't be decoded are not
+    /// considered blank.
+    pub(crate) fn is_blank(&self, data: &[u8]) -> bool {
+        let image = match image::load_from_memory(data) {
+            Ok(image) => image.into_luma8(),
+            Err(e) => {
+                warn!("Failed to decode image for blank check: {}", e);
+                return false;
+            }
+        };
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return true;
+        }
+        // A grid of samples is enough to tell a solid image from a real one.
+        let step_x = (width / SAMPLES_PER_AXIS).max(1);
+        let step_y = (height / SAMPLES_PER_AXIS).max(1);
+        let samples: Vec<f64> = (0..height)
+            .step_by(step_y as usize)
+            .flat_map(|y| (0..width).step_by(step_x as usize).map(move |x| (x, y)))
+            .map(|(x, y)| f64::from(image.get_pixel(x, y).0[0]))
+            .collect();
+        let count = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / count;
+        let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / count;
+        variance < self.max_variance
+    }
+
+    /// Checks the images in `resp`, which was generated from `params`. If any of them is
+    /// blank, logs the incident and returns a copy of `params` with a bumped seed to retry
+    /// with.
+    pub(crate) fn retry_params<'a>(
+        &self,
+        params: &(dyn GenParams + 'a),
+        resp: &Response,
+    ) -> Option<Box<dyn GenParams + 'a>> {
+        let blank = resp
+            .images
+            .iter()
+            .filter(|image| self.is_blank(&image.data))
+            .count();
+        if blank == 0 {
+            return None;
+        }
+        let seed = resp.params.seed().or_else(|| params.seed());
+        warn!(
+            blank,
+            total = resp.images.len(),
+            ?seed,
+            "Backend returned blank images, retrying with another seed"
+        );
+        let mut params = dyn_clone::clone_box(params);
+        params.set_seed(bump_seed(seed));
+        Some(params)
+    }
+}
+
+/// Returns the seed to retry a blank generation with. Unknown and random seeds stay random.
+fn bump_seed(seed: Option<i64>) -> i64 {
+    match seed {
+        Some(seed) if !is_random_seed(seed) => seed.checked_add(1).unwrap_or(0),
+        _ => RANDOM_SEED,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{GrayImage, ImageOutputFormat, Luma};
+
+    use super::*;
+
+    fn encode(image: GrayImage) -> Vec<u8> {
+        let mut data = Cursor::new(Vec::new());
+        image.write_to(&mut data, ImageOutputFormat::Png).unwrap();
+        data.into_inner()
+    }
+
+    #[test]
+    fn test_is_blank() {
+        let config = BlankCheckConfig::default();
+        let black = GrayImage::from_pixel(512, 512, Luma([0]));
+        assert!(config.is_blank(&encode(black)));
+        let gradient = GrayImage::from_fn(512, 512, |x, y| Luma([((x + y) % 256) as u8]));
+        assert!(!config.is_blank(&encode(gradient)));
+        assert!(!config.is_blank(b"not an image"));
+    }
+
+    #[test]
+    fn test_bump_seed() {
+        assert_eq!(bump_seed(Some(42)), 43);
+        assert_eq!(bump_seed(Some(i64::MAX)), 0);
+        assert_eq!(bump_seed(Some(-1)), -1);
+        assert_eq!(bump_seed(None), -1);
+    }
+}
@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use tracing::warn;
+
+use super::BackendHandles;
+
+/// How long to wait for the backend to report its version.
+const BACKEND_VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Returns the versions of the bot and its crates, and when and from which commit it was built.
+pub(crate) fn build_info() -> String {
+    format!(
+        "stable-diffusion-bot {} ({}, built {})\n\
+         sal-e-api {}\n\
+         comfyui-api {}\n\
+         stable-diffusion-api {}",
+        env!("CARGO_PKG_VERSION"),
+        env!("SD_BOT_GIT_HASH"),
+        env!("SD_BOT_BUILD_TIMESTAMP"),
+        sal_e_api::VERSION,
+        comfyui_api::VERSION,
+        stable_diffusion_api::VERSION,
+    )
+}
+
+/// Returns the name and version of the image generation backend, or why it couldn't be detected.
+pub(crate) async fn backend_version(backends: &BackendHandles) -> String {
+    match tokio::time::timeout(
+        BACKEND_VERSION_TIMEOUT,
+        backends.txt2img_api.backend_version(),
+    )
+    .await
+    {
+        Ok(Ok(version)) => version,
+        Ok(Err(e)) => {
+            warn!("Failed to detect backend version: {:?}", e);
+            format!("unknown ({e})")
+        }
+        Err(_) => "unknown (timed out)".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_info() {
+        let info = build_info();
+        assert!(info.starts_with(&format!(
+            "stable-diffusion-bot {} (",
+            env!("CARGO_PKG_VERSION")
+        )));
+        assert_eq!(info.lines().count(), 4);
+    }
+}
@@ -0,0 +1,226 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use teloxide::utils::markdown::escape;
+
+/// The caption used unless the configuration sets another one.
+const DEFAULT_TEMPLATE: &str = "`{prompt}`
+
+Negative prompt: `{negative_prompt}`
+Steps: `{steps}`
+Sampler: `{sampler}`
+CFG scale: `{cfg}`
+Seed: `{seed}`
+Size: `{width}×{height}`
+Model: `{model}`
+VAE: `{vae}`
+Denoising strength: `{denoising}`";
+
+/// A setting of a generation that a caption can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CaptionField {
+    Prompt,
+    NegativePrompt,
+    Steps,
+    Sampler,
+    Cfg,
+    Seed,
+    Width,
+    Height,
+    Model,
+    Vae,
+    Denoising,
+}
+
+impl CaptionField {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "prompt" => Some(Self::Prompt),
+            "negative_prompt" => Some(Self::NegativePrompt),
+            "steps" => Some(Self::Steps),
+            "sampler" => Some(Self::Sampler),
+            "cfg" => Some(Self::Cfg),
+            "seed" => Some(Self::Seed),
+            "width" => Some(Self::Width),
+            "height" => Some(Self::Height),
+            "model" => Some(Self::Model),
+            "vae" => Some(Self::Vae),
+            "denoising" => Some(Self::Denoising),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    /// A backtick, which starts or ends text shown as code.
+    Code,
+    Field(CaptionField),
+}
+
+/// A template for the captions of generated images, such as `{prompt}\nSeed: {seed}`.
+///
+/// Settings are written as `{name}`, and `{{` and `}}` stand for literal braces. Text between
+/// backticks is shown as code. Lines showing a setting that the generation doesn't have, such
+/// as a VAE on a backend that can't choose one, are left out.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct CaptionTemplate {
+    source: String,
+    lines: Vec<Vec<Segment>>,
+}
+
+impl Default for CaptionTemplate {
+    fn default() -> Self {
+        DEFAULT_TEMPLATE
+            .parse()
+            .expect("The default caption template is valid")
+    }
+}
+
+/// Parses one line of a template.
+fn parse_line(line: &str) -> anyhow::Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => bail!(
+                            "Unmatched {{ in caption template, write {{{{ for a literal brace"
+                        ),
+                    }
+                }
+                let field = CaptionField::parse(&name)
+                    .ok_or_else(|| anyhow!("Unknown caption setting {{{name}}}"))?;
+                segments.push(Segment::Text(std::mem::take(&mut text)));
+                segments.push(Segment::Field(field));
+            }
+            '}' => bail!("Unmatched }} in caption template, write }}}} for a literal brace"),
+            '`' => {
+                segments.push(Segment::Text(std::mem::take(&mut text)));
+                segments.push(Segment::Code);
+            }
+            c => text.push(c),
+        }
+    }
+    segments.push(Segment::Text(text));
+    segments.retain(|segment| !matches!(segment, Segment::Text(text) if text.is_empty()));
+
+    let backticks = segments
+        .iter()
+        .filter(|segment| **segment == Segment::Code)
+        .count();
+    if backticks % 2 != 0 {
+        bail!("Unmatched ` in caption template line {line:?}");
+    }
+    Ok(segments)
+}
+
+impl FromStr for CaptionTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let lines = template
+            .lines()
+            .map(parse_line)
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            source: template.to_owned(),
+            lines,
+        })
+    }
+}
+
+impl TryFrom<String> for CaptionTemplate {
+    type Error = anyhow::Error;
+
+    fn try_from(template: String) -> Result<Self, Self::Error> {
+        template.parse()
+    }
+}
+
+impl From<CaptionTemplate> for String {
+    fn from(template: CaptionTemplate) -> Self {
+        template.source
+    }
+}
+
+impl CaptionTemplate {
+    /// Renders the template as MarkdownV2, with the settings returned by `value`.
+    pub(crate) fn render(&self, value: impl Fn(CaptionField) -> Option<String>) -> String {
+        self.lines
+            .iter()
+            .filter_map(|line| {
+                line.iter()
+                    .map(|segment| match segment {
+                        Segment::Text(text) => Some(escape(text)),
+                        Segment::Code => Some("`".to_owned()),
+                        Segment::Field(field) => value(*field).map(|value| escape(&value)),
+                    })
+                    .collect::<Option<String>>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let template: CaptionTemplate = "{prompt}\nSeed: {seed} | {model}\nVAE: {vae}"
+            .parse()
+            .unwrap();
+        let caption = template.render(|field| match field {
+            CaptionField::Prompt => Some("a cat.".to_owned()),
+            CaptionField::Seed => Some("42".to_owned()),
+            CaptionField::Model => Some("sdxl".to_owned()),
+            _ => None,
+        });
+        assert_eq!(caption, "a cat\\.\nSeed: 42 \\| sdxl");
+
+        let template: CaptionTemplate = "`{prompt}` {{literal}}".parse().unwrap();
+        assert_eq!(
+            template.render(|_| Some("a_cat".to_owned())),
+            "`a\\_cat` \\{literal\\}"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!("{prompt} {lora}".parse::<CaptionTemplate>().is_err());
+        assert!("`{prompt}".parse::<CaptionTemplate>().is_err());
+        assert!("{prompt}}".parse::<CaptionTemplate>().is_err());
+        assert!("{prompt".parse::<CaptionTemplate>().is_err());
+    }
+
+    #[test]
+    fn test_default_template() {
+        let caption = CaptionTemplate::default().render(|field| match field {
+            CaptionField::Prompt => Some("a cat".to_owned()),
+            CaptionField::Seed => Some("42".to_owned()),
+            CaptionField::Width => Some("512".to_owned()),
+            CaptionField::Height => Some("768".to_owned()),
+            _ => None,
+        });
+        assert_eq!(caption, "`a cat`\n\nSeed: `42`\nSize: `512×768`");
+    }
+}
@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+/// How much of the generation settings the caption of an image shows.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptionMode {
+    /// Show the prompt and every setting.
+    #[default]
+    Full,
+    /// Show only the prompt, with a button that reveals the other settings.
+    Compact,
+}
@@ -0,0 +1,32 @@
+use sal_e_api::Response;
+use tracing::warn;
+
+/// Error returned when a censor extension on the backend blocked the generated images, so
+/// users are told why instead of getting blank images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct BlockedByBackend;
+
+impl std::fmt::Display for BlockedByBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The backend's content filter blocked the generated images"
+        )
+    }
+}
+
+impl std::error::Error for BlockedByBackend {}
+
+/// Returns an error if the backend reported that it censored the images in `resp`. Backends
+/// don't say which images of a batch were censored, so the whole response is blocked.
+pub(crate) fn check(resp: &Response) -> anyhow::Result<()> {
+    if !resp.params.censored() {
+        return Ok(());
+    }
+    warn!(
+        images = resp.images.len(),
+        seed = ?resp.params.seed(),
+        "Backend censored the generated images"
+    );
+    Err(BlockedByBackend.into())
+}
@@ -0,0 +1,323 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, RwLock},
+};
+
+use teloxide::{
+    dptree::{self, di::DependencyMap},
+    types::{ChatId, UserId},
+};
+
+use sal_e_api::GenParams;
+
+use super::{
+    accounts::AccountResolver, backends::BackendRegistry, dashboard::GenerationStats,
+    defaults::ChatDefaults, guest::GuestMode, i18n::Translations, inline::InlineGenerator,
+    metrics::Metrics, progress::Jobs, upscale::ImageUpscaler, vision::ImageDescriber,
+    webhook::Webhooks, BlankCheckConfig, CaptionMode, CaptionTemplate, GroupMode, NegativePreset,
+    ProgressConfig, PromptCleaningConfig, PromptPolicy, QuietHoursConfig, QuotaConfig,
+    RandomConfig, ReplyKeyboardConfig, State, Style, UnknownCommandMode, ZipConfig,
+};
+
+/// Access control settings, injected into handlers as `Arc<AuthConfig>`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct AuthConfig {
+    /// Users allowed in any chat, and chats all of whose members are allowed. Admins can change
+    /// the users at runtime.
+    pub allowed_users: Arc<RwLock<HashSet<ChatId>>>,
+    /// Chats all of whose members are allowed, but only within the chat.
+    pub allowed_chats: HashSet<ChatId>,
+    /// Users that are never allowed, even in allowed chats or if all users are allowed. Admins
+    /// can change them at runtime.
+    pub banned_users: Arc<RwLock<HashSet<ChatId>>>,
+    pub admin_users: HashSet<ChatId>,
+    pub allow_all_users: bool,
+    /// Resolves users to the accounts that their limits and statistics are attributed to.
+    pub accounts: AccountResolver,
+}
+
+impl AuthConfig {
+    /// Checks whether an update from `user_id` in `chat_id` is allowed by the config. Inline
+    /// queries have no chat, so only the user is checked.
+    pub fn chat_is_allowed(&self, chat_id: Option<ChatId>, user_id: Option<UserId>) -> bool {
+        let user_id = user_id.map(ChatId::from);
+        if user_id.is_some_and(|user_id| self.banned_users.read().unwrap().contains(&user_id)) {
+            return false;
+        }
+        let allowed_users = self.allowed_users.read().unwrap();
+        self.allow_all_users
+            || chat_id.is_some_and(|chat_id| {
+                allowed_users.contains(&chat_id) || self.allowed_chats.contains(&chat_id)
+            })
+            || user_id.is_some_and(|user_id| allowed_users.contains(&user_id))
+    }
+
+    /// Allows a user, lifting any ban.
+    pub fn allow_user(&self, user_id: UserId) {
+        self.banned_users.write().unwrap().remove(&user_id.into());
+        self.allowed_users.write().unwrap().insert(user_id.into());
+    }
+
+    /// Bans a user, revoking their access.
+    pub fn ban_user(&self, user_id: UserId) {
+        self.allowed_users.write().unwrap().remove(&user_id.into());
+        self.banned_users.write().unwrap().insert(user_id.into());
+    }
+
+    /// Applies the access decisions admins made at runtime, as returned by
+    /// `HistoryStore::user_access`.
+    pub fn apply_user_access(&self, access: impl IntoIterator<Item = (UserId, bool)>) {
+        for (user_id, allowed) in access {
+            if allowed {
+                self.allow_user(user_id);
+            } else {
+                self.ban_user(user_id);
+            }
+        }
+    }
+
+    /// Checks whether a user is banned.
+    pub fn user_is_banned(&self, user_id: UserId) -> bool {
+        self.banned_users.read().unwrap().contains(&user_id.into())
+    }
+
+    /// Checks whether a user is a bot administrator.
+    pub fn user_is_admin(&self, chat_id: &ChatId) -> bool {
+        self.admin_users.contains(chat_id)
+    }
+}
+
+/// Settings for how the bot treats user messages, injected into handlers as `Arc<UiConfig>`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct UiConfig {
+    pub prompt_policy: PromptPolicy,
+    pub negative_presets: Vec<NegativePreset>,
+    /// Styles every user can select, in addition to their own.
+    pub styles: Vec<Style>,
+    pub progress: ProgressConfig,
+    pub unknown_command_mode: UnknownCommandMode,
+    /// How much of the generation settings captions show.
+    pub captions: CaptionMode,
+    /// The template of the captions of generated images.
+    pub caption_template: CaptionTemplate,
+    /// Which messages in group chats are prompts.
+    pub group_mode: GroupMode,
+    /// How prompts copied from other chats are cleaned.
+    pub prompt_cleaning: PromptCleaningConfig,
+    /// Translations of the bot's messages.
+    pub translations: Arc<Translations>,
+    /// Generates images for inline queries, if enabled.
+    pub inline: Option<InlineGenerator>,
+    /// Sends large batches as zip files, if enabled.
+    pub zip: Option<ZipConfig>,
+    /// Hours during which no images are generated, if any.
+    pub quiet_hours: Option<QuietHoursConfig>,
+    /// Limits how many images each user can generate, if enabled.
+    pub quota: Option<QuotaConfig>,
+    /// Posts generation results to the webhooks configured for their chats.
+    pub webhooks: Webhooks,
+    /// The reply keyboard shown in private chats, if enabled.
+    pub reply_keyboard: Option<ReplyKeyboardConfig>,
+    /// What `/random` chooses from.
+    pub random: RandomConfig,
+    /// Whether to ask for a denoising strength before generating from a photo.
+    pub denoising_presets: bool,
+    /// Largest number of pixels the size presets offer, or `None` for the default.
+    pub size_presets_max_pixels: Option<u32>,
+    /// Guest mode for users who aren't allowed, if enabled.
+    pub guest: Option<GuestMode>,
+}
+
+/// Image generation backends, injected into handlers as `Arc<BackendHandles>`.
+#[derive(Clone, Debug)]
+pub(crate) struct BackendHandles {
+    /// Name of the backend, which generations are recorded with.
+    pub name: String,
+    pub txt2img_api: Box<dyn sal_e_api::Txt2ImgApi>,
+    pub img2img_api: Box<dyn sal_e_api::Img2ImgApi>,
+    pub describer: Option<ImageDescriber>,
+    pub blank_check: Option<BlankCheckConfig>,
+    pub upscaler: Option<ImageUpscaler>,
+    /// Generations in flight, so they can be cancelled.
+    pub jobs: Jobs,
+    /// Finished generations, shown on the dashboard.
+    pub stats: GenerationStats,
+    /// Counters exported on `/metrics`, including how long generations take.
+    pub metrics: Arc<Metrics>,
+    /// Default settings for specific users and chats.
+    pub defaults: ChatDefaults,
+}
+
+impl BackendHandles {
+    /// Returns the default txt2img settings in `chat_id`.
+    pub fn txt2img_defaults(&self, chat_id: ChatId) -> Box<dyn GenParams> {
+        let mut params = self.txt2img_api.gen_params(None);
+        self.defaults.apply_txt2img(chat_id, params.as_mut());
+        params
+    }
+
+    /// Returns the default img2img settings in `chat_id`.
+    pub fn img2img_defaults(&self, chat_id: ChatId) -> Box<dyn GenParams> {
+        let mut params = self.img2img_api.gen_params(None);
+        self.defaults.apply_img2img(chat_id, params.as_mut());
+        params
+    }
+
+    /// Returns handles for another backend named `name`, which share everything else with these.
+    pub fn with_apis(
+        &self,
+        name: String,
+        txt2img_api: Box<dyn sal_e_api::Txt2ImgApi>,
+        img2img_api: Box<dyn sal_e_api::Img2ImgApi>,
+        upscaler: Option<ImageUpscaler>,
+    ) -> Self {
+        Self {
+            name,
+            txt2img_api,
+            img2img_api,
+            upscaler,
+            ..self.clone()
+        }
+    }
+
+    /// Returns the state of a chat in `chat_id` that hasn't changed any settings.
+    pub fn default_state(&self, chat_id: ChatId) -> State {
+        State::new_with_defaults(
+            self.txt2img_defaults(chat_id),
+            self.img2img_defaults(chat_id),
+        )
+    }
+}
+
+/// A live view of the allowed users, which admins can change at runtime.
+#[derive(Clone)]
+pub(crate) struct AllowedUsers(Arc<RwLock<HashSet<ChatId>>>);
+
+impl AllowedUsers {
+    /// Returns the number of allowed users.
+    pub fn len(&self) -> usize {
+        self.0.read().unwrap().len()
+    }
+}
+
+impl std::fmt::Debug for AllowedUsers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.read().unwrap().fmt(f)
+    }
+}
+
+impl PartialEq<HashSet<ChatId>> for AllowedUsers {
+    fn eq(&self, other: &HashSet<ChatId>) -> bool {
+        *self.0.read().unwrap() == *other
+    }
+}
+
+/// The complete runtime configuration of the bot.
+///
+/// Handlers depend on the individual parts rather than on this struct, so each part can be
+/// shared and replaced on its own. The remaining fields are shortcuts into the parts, for code
+/// that reads the configuration as a whole.
+#[derive(Clone, Debug)]
+pub(crate) struct ConfigParameters {
+    pub auth: Arc<AuthConfig>,
+    pub ui: Arc<UiConfig>,
+    /// The default backend.
+    pub backends: Arc<BackendHandles>,
+    /// Every backend, including the default, for chats to choose from.
+    pub registry: Arc<BackendRegistry>,
+    /// The users allowed by `auth`.
+    pub allowed_users: AllowedUsers,
+    /// Whether `auth` allows all users.
+    pub allow_all_users: bool,
+    /// The txt2img API of the default backend.
+    pub txt2img_api: Box<dyn sal_e_api::Txt2ImgApi>,
+    /// The img2img API of the default backend.
+    #[allow(dead_code)]
+    pub img2img_api: Box<dyn sal_e_api::Img2ImgApi>,
+}
+
+impl ConfigParameters {
+    /// Assembles the configuration from its parts.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth` - The access control settings.
+    /// * `ui` - The settings for how the bot treats user messages.
+    /// * `backends` - The default backend.
+    /// * `registry` - Every backend, including the default.
+    pub fn new(
+        auth: Arc<AuthConfig>,
+        ui: Arc<UiConfig>,
+        backends: Arc<BackendHandles>,
+        registry: Arc<BackendRegistry>,
+    ) -> Self {
+        Self {
+            allowed_users: AllowedUsers(auth.allowed_users.clone()),
+            allow_all_users: auth.allow_all_users,
+            txt2img_api: backends.txt2img_api.clone(),
+            img2img_api: backends.img2img_api.clone(),
+            auth,
+            ui,
+            backends,
+            registry,
+        }
+    }
+
+    /// Returns the parts of the configuration as handler dependencies.
+    pub fn dependencies(&self) -> DependencyMap {
+        dptree::deps![
+            self.auth.clone(),
+            self.ui.clone(),
+            self.backends.clone(),
+            self.registry.clone()
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chat_is_allowed() {
+        let auth = AuthConfig {
+            allowed_users: Arc::new(RwLock::new(HashSet::from([ChatId(1), ChatId(-100)]))),
+            allowed_chats: HashSet::from([ChatId(-200)]),
+            banned_users: Arc::new(RwLock::new(HashSet::from([ChatId(2)]))),
+            ..Default::default()
+        };
+        // Allowed users are allowed everywhere, including inline queries.
+        assert!(auth.chat_is_allowed(Some(ChatId(-300)), Some(UserId(1))));
+        assert!(auth.chat_is_allowed(None, Some(UserId(1))));
+        // Members of allowed chats are only allowed within them.
+        assert!(auth.chat_is_allowed(Some(ChatId(-200)), Some(UserId(3))));
+        assert!(auth.chat_is_allowed(Some(ChatId(-100)), Some(UserId(3))));
+        assert!(!auth.chat_is_allowed(Some(ChatId(3)), Some(UserId(3))));
+        assert!(!auth.chat_is_allowed(None, Some(UserId(3))));
+        // Banned users are refused even in allowed chats.
+        assert!(!auth.chat_is_allowed(Some(ChatId(-200)), Some(UserId(2))));
+        assert!(!auth.chat_is_allowed(Some(ChatId(-100)), Some(UserId(2))));
+
+        let auth = AuthConfig {
+            allow_all_users: true,
+            ..auth
+        };
+        assert!(auth.chat_is_allowed(Some(ChatId(3)), Some(UserId(3))));
+        assert!(!auth.chat_is_allowed(Some(ChatId(2)), Some(UserId(2))));
+    }
+
+    #[test]
+    fn test_user_access() {
+        let auth = AuthConfig {
+            allowed_users: Arc::new(RwLock::new(HashSet::from([ChatId(1)]))),
+            ..Default::default()
+        };
+        auth.apply_user_access([(UserId(1), false), (UserId(2), true)]);
+        assert!(!auth.chat_is_allowed(None, Some(UserId(1))));
+        assert!(auth.chat_is_allowed(None, Some(UserId(2))));
+
+        auth.allow_user(UserId(1));
+        assert!(auth.chat_is_allowed(None, Some(UserId(1))));
+        assert!(auth.banned_users.read().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,344 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    extract::State,
+    response::{Html, Json},
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use teloxide::types::ChatId;
+
+use super::config::BackendHandles;
+
+/// Number of finished generations kept for the dashboard.
+const RECENT_GENERATIONS: usize = 50;
+
+/// How long to wait for the backend to report its version before considering it down.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The dashboard page, which renders the JSON endpoints.
+const DASHBOARD_HTML: &str = include_str!("dashboard.html");
+
+/// What a generation shown on the dashboard did.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum GenerationKind<'a> {
+    Txt2Img {
+        prompt: &'a str,
+        /// The fingerprint of the request, if known. See `GenParams::fingerprint`.
+        fingerprint: Option<&'a str>,
+    },
+    Img2Img {
+        prompt: &'a str,
+    },
+    Upscale,
+}
+
+impl GenerationKind<'_> {
+    fn name(&self) -> &'static str {
+        match self {
+            GenerationKind::Txt2Img { .. } => "txt2img",
+            GenerationKind::Img2Img { .. } => "img2img",
+            GenerationKind::Upscale => "upscale",
+        }
+    }
+
+    /// Returns a hash of the prompt, so generations of the same prompt can be recognized without
+    /// showing what users asked for.
+    fn prompt_hash(&self) -> Option<String> {
+        match self {
+            GenerationKind::Txt2Img { prompt, .. } | GenerationKind::Img2Img { prompt } => {
+                Some(format!("{:08x}", crc32fast::hash(prompt.as_bytes())))
+            }
+            GenerationKind::Upscale => None,
+        }
+    }
+
+    /// Returns the start of the request's fingerprint, so identical requests can be recognized.
+    fn fingerprint(&self) -> Option<String> {
+        match self {
+            GenerationKind::Txt2Img {
+                fingerprint: Some(fingerprint),
+                ..
+            } => Some(fingerprint.chars().take(12).collect()),
+            _ => None,
+        }
+    }
+}
+
+/// How a generation ended.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Outcome {
+    Succeeded,
+    Failed,
+    TimedOut,
+    Cancelled,
+    /// The backend's content filter blocked the images.
+    Blocked,
+}
+
+/// A finished generation.
+#[derive(Serialize, Debug, Clone)]
+struct GenerationRecord {
+    finished_at: DateTime<Utc>,
+    chat_id: i64,
+    kind: &'static str,
+    prompt_hash: Option<String>,
+    fingerprint: Option<String>,
+    duration_ms: u64,
+    outcome: Outcome,
+}
+
+/// Number of generations by outcome.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+struct Counts {
+    succeeded: u64,
+    failed: u64,
+    timed_out: u64,
+    cancelled: u64,
+    blocked: u64,
+}
+
+impl Counts {
+    fn add(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Succeeded => self.succeeded += 1,
+            Outcome::Failed => self.failed += 1,
+            Outcome::TimedOut => self.timed_out += 1,
+            Outcome::Cancelled => self.cancelled += 1,
+            Outcome::Blocked => self.blocked += 1,
+        }
+    }
+
+    /// Returns the share of generations that failed or timed out. Cancelled and blocked
+    /// generations aren't counted, since users stopped them or asked for something the backend
+    /// doesn't allow.
+    fn error_rate(&self) -> Option<f64> {
+        let errors = self.failed + self.timed_out;
+        let total = self.succeeded + errors;
+        (total > 0).then(|| errors as f64 / total as f64)
+    }
+}
+
+/// Counts with their error rate, as served by the JSON endpoints.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct Summary {
+    #[serde(flatten)]
+    counts: Counts,
+    error_rate: Option<f64>,
+}
+
+impl From<Counts> for Summary {
+    fn from(counts: Counts) -> Self {
+        Self {
+            counts,
+            error_rate: counts.error_rate(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct ChatSummary {
+    chat_id: i64,
+    #[serde(flatten)]
+    summary: Summary,
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    recent: VecDeque<GenerationRecord>,
+    totals: Counts,
+    chats: HashMap<ChatId, Counts>,
+}
+
+/// Statistics about the generations since the bot started, shown on the dashboard. They're
+/// kept in memory only.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct GenerationStats(Arc<Mutex<Stats>>);
+
+impl GenerationStats {
+    /// Records a generation in `chat_id` that ended with `outcome` after `duration`.
+    pub fn record(
+        &self,
+        chat_id: ChatId,
+        kind: GenerationKind<'_>,
+        duration: Duration,
+        outcome: Outcome,
+    ) {
+        let record = GenerationRecord {
+            finished_at: Utc::now(),
+            chat_id: chat_id.0,
+            kind: kind.name(),
+            prompt_hash: kind.prompt_hash(),
+            fingerprint: kind.fingerprint(),
+            duration_ms: duration.as_millis().try_into().unwrap_or(u64::MAX),
+            outcome,
+        };
+        let mut stats = self.0.lock().unwrap();
+        if stats.recent.len() >= RECENT_GENERATIONS {
+            stats.recent.pop_back();
+        }
+        stats.recent.push_front(record);
+        stats.totals.add(outcome);
+        stats.chats.entry(chat_id).or_default().add(outcome);
+    }
+
+    /// Returns the most recent generations, newest first.
+    fn recent(&self) -> Vec<GenerationRecord> {
+        self.0.lock().unwrap().recent.iter().cloned().collect()
+    }
+
+    fn totals(&self) -> Counts {
+        self.0.lock().unwrap().totals
+    }
+
+    /// Returns the counts of each chat, busiest first.
+    fn chats(&self) -> Vec<ChatSummary> {
+        let stats = self.0.lock().unwrap();
+        let mut chats: Vec<_> = stats
+            .chats
+            .iter()
+            .map(|(chat_id, counts)| ChatSummary {
+                chat_id: chat_id.0,
+                summary: (*counts).into(),
+            })
+            .collect();
+        chats.sort_by_key(|chat| {
+            let counts = chat.summary.counts;
+            std::cmp::Reverse(
+                counts.succeeded
+                    + counts.failed
+                    + counts.timed_out
+                    + counts.cancelled
+                    + counts.blocked,
+            )
+        });
+        chats
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct BackendHealth {
+    up: bool,
+    version: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct Status {
+    /// Generations the bot is waiting on.
+    generations_in_flight: usize,
+    /// Jobs queued on the backend, if it reports them.
+    backend_queue: Option<u64>,
+    backend: BackendHealth,
+    totals: Summary,
+}
+
+async fn status(State(backends): State<Arc<BackendHandles>>) -> Json<Status> {
+    let (version, queue) = tokio::join!(
+        tokio::time::timeout(HEALTH_CHECK_TIMEOUT, backends.txt2img_api.backend_version()),
+        tokio::time::timeout(HEALTH_CHECK_TIMEOUT, backends.txt2img_api.queue_length()),
+    );
+    let backend = match version {
+        Ok(Ok(version)) => BackendHealth {
+            up: true,
+            version: Some(version),
+            error: None,
+        },
+        Ok(Err(e)) => BackendHealth {
+            up: false,
+            version: None,
+            error: Some(format!("{e:#}")),
+        },
+        Err(_) => BackendHealth {
+            up: false,
+            version: None,
+            error: Some("Timed out".to_owned()),
+        },
+    };
+    Json(Status {
+        generations_in_flight: backends.jobs.len(),
+        backend_queue: queue.ok().and_then(Result::ok),
+        backend,
+        totals: backends.stats.totals().into(),
+    })
+}
+
+/// Returns a router serving a read-only dashboard on `/dashboard`, with its data on
+/// `/dashboard/status`, `/dashboard/generations` and `/dashboard/chats`.
+pub(crate) fn router(backends: Arc<BackendHandles>) -> Router {
+    Router::new()
+        .route("/dashboard", get(|| async { Html(DASHBOARD_HTML) }))
+        .route("/dashboard/status", get(status))
+        .route(
+            "/dashboard/generations",
+            get(|State(backends): State<Arc<BackendHandles>>| async move {
+                Json(backends.stats.recent())
+            }),
+        )
+        .route(
+            "/dashboard/chats",
+            get(|State(backends): State<Arc<BackendHandles>>| async move {
+                Json(backends.stats.chats())
+            }),
+        )
+        .with_state(backends)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generation_stats() {
+        let stats = GenerationStats::default();
+        let txt2img = GenerationKind::Txt2Img {
+            prompt: "a cat",
+            fingerprint: Some("0123456789abcdef"),
+        };
+        stats.record(
+            ChatId(1),
+            txt2img,
+            Duration::from_secs(2),
+            Outcome::Succeeded,
+        );
+        stats.record(ChatId(1), txt2img, Duration::from_secs(2), Outcome::Failed);
+        stats.record(
+            ChatId(2),
+            GenerationKind::Upscale,
+            Duration::from_secs(1),
+            Outcome::Cancelled,
+        );
+        stats.record(ChatId(3), txt2img, Duration::from_secs(3), Outcome::Blocked);
+
+        let recent = stats.recent();
+        assert_eq!(recent.len(), 4);
+        assert_eq!(recent[0].outcome, Outcome::Blocked);
+        assert_eq!(recent[1].kind, "upscale");
+        assert_eq!(recent[1].prompt_hash, None);
+        assert_eq!(recent[2].prompt_hash, recent[3].prompt_hash);
+        assert_eq!(recent[2].fingerprint.as_deref(), Some("0123456789ab"));
+        assert_eq!(recent[3].duration_ms, 2000);
+
+        let totals = Summary::from(stats.totals());
+        assert_eq!(totals.counts.succeeded, 1);
+        assert_eq!(totals.counts.blocked, 1);
+        assert_eq!(totals.error_rate, Some(0.5));
+
+        let chats = stats.chats();
+        assert_eq!(chats[0].chat_id, 1);
+        assert_eq!(chats[1].summary.error_rate, None);
+
+        for _ in 0..RECENT_GENERATIONS {
+            stats.record(ChatId(1), txt2img, Duration::ZERO, Outcome::Succeeded);
+        }
+        assert_eq!(stats.recent().len(), RECENT_GENERATIONS);
+        assert_eq!(stats.totals().succeeded, RECENT_GENERATIONS as u64 + 1);
+    }
+}
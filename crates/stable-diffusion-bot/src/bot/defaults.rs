@@ -0,0 +1,181 @@
+use std::collections::BTreeMap;
+
+use sal_e_api::GenParams;
+use serde::{Deserialize, Serialize};
+use teloxide::types::ChatId;
+
+/// Settings that replace the configured defaults. Settings that are left unset keep the
+/// configured default.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SettingsOverrides {
+    /// The seed. `-1` picks a random seed for each generation.
+    pub seed: Option<i64>,
+    /// The number of steps.
+    pub steps: Option<u32>,
+    /// The number of images to generate.
+    pub count: Option<u32>,
+    /// The CFG scale.
+    pub cfg: Option<f32>,
+    /// The image width.
+    pub width: Option<u32>,
+    /// The image height.
+    pub height: Option<u32>,
+    /// The negative prompt.
+    pub negative_prompt: Option<String>,
+    /// The denoising strength.
+    pub denoising: Option<f32>,
+    /// The sampler.
+    pub sampler: Option<String>,
+    /// The model to generate with.
+    pub model: Option<String>,
+    /// The batch size.
+    pub batch_size: Option<u32>,
+}
+
+impl SettingsOverrides {
+    /// Applies the settings that are set to `params`.
+    fn apply(&self, params: &mut dyn GenParams) {
+        if let Some(seed) = self.seed {
+            params.set_seed(seed);
+        }
+        if let Some(steps) = self.steps {
+            params.set_steps(steps);
+        }
+        if let Some(count) = self.count {
+            params.set_count(count);
+        }
+        if let Some(cfg) = self.cfg {
+            params.set_cfg(cfg);
+        }
+        if let Some(width) = self.width {
+            params.set_width(width);
+        }
+        if let Some(height) = self.height {
+            params.set_height(height);
+        }
+        if let Some(negative_prompt) = &self.negative_prompt {
+            params.set_negative_prompt(negative_prompt.clone());
+        }
+        if let Some(denoising) = self.denoising {
+            params.set_denoising(denoising);
+        }
+        if let Some(sampler) = &self.sampler {
+            params.set_sampler(sampler.clone());
+        }
+        if let Some(model) = &self.model {
+            params.set_model(model.clone());
+        }
+        if let Some(batch_size) = self.batch_size {
+            params.set_batch_size(batch_size);
+        }
+    }
+}
+
+/// Struct that represents the default settings of a single user or chat, replacing the
+/// `[txt2img]` and `[img2img]` defaults.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct DefaultSettings {
+    /// Defaults for text to image generation.
+    #[serde(default)]
+    pub txt2img: SettingsOverrides,
+    /// Defaults for image to image generation.
+    #[serde(default)]
+    pub img2img: SettingsOverrides,
+}
+
+/// Default settings for specific users and chats.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ChatDefaults {
+    /// Defaults for users, keyed by user id. They apply in the user's private chat with the
+    /// bot and to their inline queries.
+    pub users: BTreeMap<i64, DefaultSettings>,
+    /// Defaults for chats, keyed by chat id.
+    pub chats: BTreeMap<i64, DefaultSettings>,
+}
+
+impl ChatDefaults {
+    /// Returns the defaults that apply in `chat_id`, in the order they should be applied.
+    ///
+    /// Settings are shared by everyone in a chat, so in group chats only the chat's defaults
+    /// apply. In private chats, which have the id of the user, the user's defaults win.
+    fn for_chat(&self, chat_id: ChatId) -> impl Iterator<Item = &DefaultSettings> {
+        self.chats
+            .get(&chat_id.0)
+            .into_iter()
+            .chain(self.users.get(&chat_id.0))
+    }
+
+    /// Applies the txt2img defaults for `chat_id` to `params`.
+    pub(crate) fn apply_txt2img(&self, chat_id: ChatId, params: &mut dyn GenParams) {
+        for defaults in self.for_chat(chat_id) {
+            defaults.txt2img.apply(params);
+        }
+    }
+
+    /// Applies the img2img defaults for `chat_id` to `params`.
+    pub(crate) fn apply_img2img(&self, chat_id: ChatId, params: &mut dyn GenParams) {
+        for defaults in self.for_chat(chat_id) {
+            defaults.img2img.apply(params);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sal_e_api::Txt2ImgParams;
+
+    use super::*;
+
+    #[test]
+    fn test_apply_chat_defaults() {
+        let defaults = ChatDefaults {
+            users: BTreeMap::from([(
+                1,
+                DefaultSettings {
+                    txt2img: SettingsOverrides {
+                        steps: Some(30),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            )]),
+            chats: BTreeMap::from([
+                (
+                    1,
+                    DefaultSettings {
+                        txt2img: SettingsOverrides {
+                            steps: Some(20),
+                            width: Some(768),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                ),
+                (
+                    -100,
+                    DefaultSettings {
+                        txt2img: SettingsOverrides {
+                            model: Some("anime".to_owned()),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                ),
+            ]),
+        };
+
+        let mut params = Txt2ImgParams::default();
+        defaults.apply_txt2img(ChatId(1), &mut params);
+        assert_eq!(params.steps(), Some(30));
+        assert_eq!(params.width(), Some(768));
+
+        let mut params = Txt2ImgParams::default();
+        defaults.apply_txt2img(ChatId(-100), &mut params);
+        assert_eq!(params.model(), Some("anime".to_owned()));
+        assert_eq!(params.steps(), None);
+
+        let mut params = Txt2ImgParams::default();
+        defaults.apply_img2img(ChatId(1), &mut params);
+        assert_eq!(params.steps(), None);
+    }
+}
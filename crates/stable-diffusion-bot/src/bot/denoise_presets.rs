@@ -0,0 +1,82 @@
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+use super::i18n::Translator;
+
+/// Prefix of the callback data of the denoising strength buttons.
+const CALLBACK_PREFIX: &str = "denoise/";
+
+/// Denoising strengths offered before generating from a photo, with their labels.
+pub(crate) const PRESETS: [(&str, f32); 3] =
+    [("Subtle", 0.3), ("Balanced", 0.55), ("Strong", 0.75)];
+
+/// Returns the keyboard offering the presets and, unless it matches one of them, the
+/// denoising strength `current` from the user's settings.
+pub(crate) fn keyboard(current: Option<f32>, translator: &Translator) -> InlineKeyboardMarkup {
+    let mut buttons: Vec<_> = PRESETS
+        .iter()
+        .map(|(label, value)| {
+            InlineKeyboardButton::callback(
+                format!("{} ({value})", translator.text(label)),
+                callback_data(*value),
+            )
+        })
+        .collect();
+    if let Some(current) = current.filter(|current| label(*current).is_none()) {
+        buttons.push(InlineKeyboardButton::callback(
+            translator.format("My setting ({value})", &[("value", &current.to_string())]),
+            callback_data(current),
+        ));
+    }
+    InlineKeyboardMarkup::new(buttons.chunks(2).map(<[_]>::to_vec))
+}
+
+/// Returns the label of the preset with denoising strength `value`, if there is one.
+pub(crate) fn label(value: f32) -> Option<&'static str> {
+    PRESETS
+        .iter()
+        .find(|(_, preset)| *preset == value)
+        .map(|(label, _)| *label)
+}
+
+fn callback_data(value: f32) -> String {
+    format!("{CALLBACK_PREFIX}{value}")
+}
+
+/// Parses the denoising strength from the callback data of a button.
+pub(crate) fn parse_callback_data(data: &str) -> Option<f32> {
+    data.strip_prefix(CALLBACK_PREFIX)?
+        .parse()
+        .ok()
+        .filter(|value| (0.0..=1.0).contains(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_callback_data_round_trip() {
+        for (_, value) in PRESETS {
+            assert_eq!(parse_callback_data(&callback_data(value)), Some(value));
+        }
+        assert_eq!(parse_callback_data("denoise/1.5"), None);
+        assert_eq!(parse_callback_data("denoise/strong"), None);
+        assert_eq!(parse_callback_data("rerun"), None);
+    }
+
+    #[test]
+    fn test_keyboard() {
+        let translator = Translator::default();
+        assert_eq!(
+            keyboard(Some(0.55), &translator)
+                .inline_keyboard
+                .concat()
+                .len(),
+            3
+        );
+        let keyboard = keyboard(Some(0.4), &translator);
+        let buttons = keyboard.inline_keyboard.concat();
+        assert_eq!(buttons.len(), 4);
+        assert_eq!(buttons[3].text, "My setting (0.4)");
+    }
+}
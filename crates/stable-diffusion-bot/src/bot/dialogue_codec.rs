@@ -0,0 +1,278 @@
+use std::{borrow::Cow, collections::BTreeMap, io};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use teloxide::dispatching::dialogue::serializer::Serializer;
+
+use super::State;
+
+/// Magic number at the start of every zstd frame. JSON never starts with it, so data without it
+/// is stored uncompressed.
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression level used for stored data. Higher levels barely shrink JSON further.
+const ZSTD_LEVEL: i32 = 3;
+
+/// Compresses `data` with zstd.
+pub(crate) fn compress(data: &[u8]) -> io::Result<Vec<u8>> {
+    zstd::encode_all(data, ZSTD_LEVEL)
+}
+
+/// Returns `data`, decompressed if it was compressed with `compress`.
+pub(crate) fn decompress(data: &[u8]) -> io::Result<Cow<'_, [u8]>> {
+    if !data.starts_with(ZSTD_MAGIC) {
+        return Ok(Cow::Borrowed(data));
+    }
+    zstd::decode_all(data).map(Cow::Owned)
+}
+
+/// Returns the reference kept for an image that was left out of a stored state.
+fn image_reference(image: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(image))
+}
+
+/// A dialogue state whose images were left out, with references to them by their SHA-256
+/// digests, keyed by where they were, e.g. `img2img.image`. States without images are stored
+/// as they are.
+#[derive(Serialize, Deserialize)]
+struct StrippedState {
+    state: State,
+    dropped_images: BTreeMap<String, String>,
+}
+
+/// Error returned when a dialogue state can't be stored or loaded.
+#[derive(Debug)]
+pub(crate) enum DialogueCodecError {
+    Json(serde_json::Error),
+    Compression(io::Error),
+}
+
+impl std::fmt::Display for DialogueCodecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Json(e) => write!(f, "invalid dialogue state: {e}"),
+            Self::Compression(e) => write!(f, "failed to (de)compress dialogue state: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for DialogueCodecError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Json(e) => Some(e),
+            Self::Compression(e) => Some(e),
+        }
+    }
+}
+
+/// Stores dialogue states as JSON, compressed with zstd if enabled. Image data is left out and
+/// replaced by a reference to the image, as photos waiting for a prompt are kept by their
+/// Telegram file ids instead. Both compressed and plain states are loaded, so compression can be
+/// turned on and off.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct DialogueCodec {
+    /// Whether to compress the states.
+    pub compress: bool,
+}
+
+impl DialogueCodec {
+    /// Encodes the JSON of a state, compressing it if enabled.
+    fn encode(&self, json: &[u8]) -> io::Result<Vec<u8>> {
+        if self.compress {
+            compress(json)
+        } else {
+            Ok(json.to_vec())
+        }
+    }
+
+    /// Decodes a stored state into its JSON, decompressing it if needed.
+    pub fn decode(data: &[u8]) -> io::Result<Cow<'_, [u8]>> {
+        decompress(data)
+    }
+
+    /// Re-encodes the stored dialogue states that aren't encoded as configured, returning how
+    /// many were changed. States that can't be decoded are left for the quarantine.
+    pub async fn migrate(&self, pool: &SqlitePool) -> anyhow::Result<u64> {
+        let mut tx = pool.begin().await?;
+        let rows: Vec<(i64, Vec<u8>)> =
+            sqlx::query_as("SELECT chat_id, dialogue FROM teloxide_dialogues")
+                .fetch_all(&mut tx)
+                .await
+                .context("Failed to get dialogue states")?;
+        let mut migrated = 0;
+        for (chat_id, dialogue) in rows {
+            if dialogue.starts_with(ZSTD_MAGIC) == self.compress {
+                continue;
+            }
+            let Ok(json) = Self::decode(&dialogue) else {
+                continue;
+            };
+            sqlx::query("UPDATE teloxide_dialogues SET dialogue = ? WHERE chat_id = ?")
+                .bind(self.encode(&json)?)
+                .bind(chat_id)
+                .execute(&mut tx)
+                .await
+                .context("Failed to migrate dialogue state")?;
+            migrated += 1;
+        }
+        tx.commit().await?;
+        Ok(migrated)
+    }
+}
+
+impl Serializer<State> for DialogueCodec {
+    type Error = DialogueCodecError;
+
+    fn serialize(&self, state: &State) -> Result<Vec<u8>, Self::Error> {
+        let mut state = state.clone();
+        let mut dropped_images = BTreeMap::new();
+        if let State::Ready {
+            txt2img, img2img, ..
+        } = &mut state
+        {
+            for (name, params) in [("txt2img", txt2img), ("img2img", img2img)] {
+                if let Some(image) = params.image() {
+                    dropped_images.insert(format!("{name}.image"), image_reference(&image));
+                    params.set_image(None);
+                }
+                if let Some(mask) = params.mask() {
+                    dropped_images.insert(format!("{name}.mask"), image_reference(&mask));
+                    params.set_mask(None);
+                }
+            }
+        }
+        let json = if dropped_images.is_empty() {
+            serde_json::to_vec(&state)
+        } else {
+            serde_json::to_vec(&StrippedState {
+                state,
+                dropped_images,
+            })
+        }
+        .map_err(DialogueCodecError::Json)?;
+        self.encode(&json).map_err(DialogueCodecError::Compression)
+    }
+
+    fn deserialize(&self, data: &[u8]) -> Result<State, Self::Error> {
+        let json = Self::decode(data).map_err(DialogueCodecError::Compression)?;
+        match serde_json::from_slice::<StrippedState>(&json) {
+            Ok(stripped) => Ok(stripped.state),
+            Err(_) => serde_json::from_slice(&json).map_err(DialogueCodecError::Json),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sal_e_api::{GenParams, Img2ImgParams, Txt2ImgParams};
+
+    use super::*;
+    use crate::bot::history::HistoryStore;
+
+    fn state_with_image() -> State {
+        let mut img2img = Img2ImgParams::default();
+        img2img.set_prompt("a castle".to_owned());
+        img2img.set_image(Some(vec![1, 2, 3]));
+        State::new_with_defaults(Box::<Txt2ImgParams>::default(), Box::new(img2img))
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for compress in [false, true] {
+            let codec = DialogueCodec { compress };
+            let data = codec.serialize(&state_with_image()).unwrap();
+            assert_eq!(data.starts_with(ZSTD_MAGIC), compress);
+            let State::Ready { img2img, .. } = codec.deserialize(&data).unwrap() else {
+                panic!("expected a ready state");
+            };
+            assert_eq!(img2img.prompt(), Some("a castle".to_owned()));
+            assert_eq!(img2img.image(), None);
+        }
+    }
+
+    #[test]
+    fn test_dropped_image_reference() {
+        let data = DialogueCodec::default()
+            .serialize(&state_with_image())
+            .unwrap();
+        let stored: serde_json::Value = serde_json::from_slice(&data).unwrap();
+        assert_eq!(
+            stored["dropped_images"]["img2img.image"],
+            image_reference(&[1, 2, 3])
+        );
+        assert!(image_reference(&[1, 2, 3]).starts_with("sha256:039058c6f2c0cb49"));
+
+        // States without images are stored as they are.
+        let data = DialogueCodec::default()
+            .serialize(&State::default())
+            .unwrap();
+        assert_eq!(data, serde_json::to_vec(&State::default()).unwrap());
+    }
+
+    #[test]
+    fn test_reads_either_encoding() {
+        let plain = DialogueCodec { compress: false }
+            .serialize(&state_with_image())
+            .unwrap();
+        let compressed = DialogueCodec { compress: true }.encode(&plain).unwrap();
+        assert_eq!(DialogueCodec::decode(&compressed).unwrap(), plain);
+        assert!(DialogueCodec { compress: true }.deserialize(&plain).is_ok());
+        assert!(DialogueCodec { compress: false }
+            .deserialize(&compressed)
+            .is_ok());
+
+        // States stored before images were left out are plain JSON of the state.
+        let mut img2img = Img2ImgParams::default();
+        img2img.set_image(Some(vec![1, 2, 3]));
+        let legacy = serde_json::to_vec(&State::new_with_defaults(
+            Box::<Txt2ImgParams>::default(),
+            Box::new(img2img),
+        ))
+        .unwrap();
+        assert!(matches!(
+            DialogueCodec { compress: true }.deserialize(&legacy),
+            Ok(State::Ready { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_migrate() {
+        let history = HistoryStore::open(None).await.unwrap();
+        sqlx::query(
+            "CREATE TABLE teloxide_dialogues (chat_id BIGINT PRIMARY KEY, dialogue BLOB NOT NULL)",
+        )
+        .execute(history.pool())
+        .await
+        .unwrap();
+        let plain = DialogueCodec::default()
+            .serialize(&State::default())
+            .unwrap();
+        let mut broken = ZSTD_MAGIC.to_vec();
+        broken.extend_from_slice(b"broken");
+        for (chat_id, dialogue) in [(1, plain.clone()), (2, broken)] {
+            sqlx::query("INSERT INTO teloxide_dialogues (chat_id, dialogue) VALUES (?, ?)")
+                .bind(chat_id)
+                .bind(dialogue)
+                .execute(history.pool())
+                .await
+                .unwrap();
+        }
+
+        let codec = DialogueCodec { compress: true };
+        assert_eq!(codec.migrate(history.pool()).await.unwrap(), 1);
+        assert_eq!(codec.migrate(history.pool()).await.unwrap(), 0);
+        let dialogue: Vec<u8> =
+            sqlx::query_scalar("SELECT dialogue FROM teloxide_dialogues WHERE chat_id = 1")
+                .fetch_one(history.pool())
+                .await
+                .unwrap();
+        assert!(dialogue.starts_with(ZSTD_MAGIC));
+        assert_eq!(DialogueCodec::decode(&dialogue).unwrap(), plain);
+
+        // The broken state is left alone, while decompressing the others.
+        let codec = DialogueCodec { compress: false };
+        assert_eq!(codec.migrate(history.pool()).await.unwrap(), 1);
+    }
+}
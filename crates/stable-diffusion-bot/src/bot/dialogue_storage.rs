@@ -0,0 +1,181 @@
+use std::{error::Error, fmt, sync::Arc};
+
+use anyhow::Context;
+use futures::future::BoxFuture;
+use sqlx::SqlitePool;
+use teloxide::{
+    dispatching::dialogue::{serializer::Serializer, Storage},
+    types::ChatId,
+};
+
+/// Dialogue storage kept in the `teloxide_dialogues` table of the bot's database, laid out like
+/// teloxide's own SQLite storage so existing databases keep working. It's kept here because
+/// teloxide's `sqlite-storage` feature always builds sqlx with native TLS.
+pub(crate) struct SqliteStorage<S> {
+    pool: SqlitePool,
+    serializer: S,
+}
+
+/// Error returned when a dialogue can't be stored, loaded or removed.
+#[derive(Debug)]
+pub(crate) enum SqliteStorageError<E> {
+    Codec(E),
+    Sqlite(sqlx::Error),
+    NotFound,
+}
+
+impl<E: fmt::Display> fmt::Display for SqliteStorageError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Codec(e) => write!(f, "dialogue codec error: {e}"),
+            Self::Sqlite(e) => write!(f, "sqlite error: {e}"),
+            Self::NotFound => write!(f, "dialogue not found"),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for SqliteStorageError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Codec(e) => Some(e),
+            Self::Sqlite(e) => Some(e),
+            Self::NotFound => None,
+        }
+    }
+}
+
+impl<E> From<sqlx::Error> for SqliteStorageError<E> {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Sqlite(e)
+    }
+}
+
+impl<S> SqliteStorage<S> {
+    /// Stores dialogues in the database behind `pool`, creating the table if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - The database to store dialogues in.
+    /// * `serializer` - How to encode the dialogue states.
+    pub async fn open(pool: SqlitePool, serializer: S) -> anyhow::Result<Arc<Self>> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS teloxide_dialogues (
+                chat_id BIGINT PRIMARY KEY,
+                dialogue BLOB NOT NULL
+            )",
+        )
+        .execute(&pool)
+        .await
+        .context("Failed to create dialogue table")?;
+        Ok(Arc::new(Self { pool, serializer }))
+    }
+}
+
+impl<S, D> Storage<D> for SqliteStorage<S>
+where
+    S: Serializer<D> + Send + Sync + 'static,
+    D: Send + 'static,
+{
+    type Error = SqliteStorageError<S::Error>;
+
+    fn remove_dialogue(
+        self: Arc<Self>,
+        ChatId(chat_id): ChatId,
+    ) -> BoxFuture<'static, Result<(), Self::Error>> {
+        Box::pin(async move {
+            let deleted = sqlx::query("DELETE FROM teloxide_dialogues WHERE chat_id = ?")
+                .bind(chat_id)
+                .execute(&self.pool)
+                .await?
+                .rows_affected();
+            if deleted == 0 {
+                return Err(SqliteStorageError::NotFound);
+            }
+            Ok(())
+        })
+    }
+
+    fn update_dialogue(
+        self: Arc<Self>,
+        ChatId(chat_id): ChatId,
+        dialogue: D,
+    ) -> BoxFuture<'static, Result<(), Self::Error>> {
+        Box::pin(async move {
+            let data = self
+                .serializer
+                .serialize(&dialogue)
+                .map_err(SqliteStorageError::Codec)?;
+            sqlx::query(
+                "INSERT INTO teloxide_dialogues (chat_id, dialogue) VALUES (?, ?)
+                 ON CONFLICT(chat_id) DO UPDATE SET dialogue = excluded.dialogue",
+            )
+            .bind(chat_id)
+            .bind(data)
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        })
+    }
+
+    fn get_dialogue(
+        self: Arc<Self>,
+        ChatId(chat_id): ChatId,
+    ) -> BoxFuture<'static, Result<Option<D>, Self::Error>> {
+        Box::pin(async move {
+            let data: Option<Vec<u8>> =
+                sqlx::query_scalar("SELECT dialogue FROM teloxide_dialogues WHERE chat_id = ?")
+                    .bind(chat_id)
+                    .fetch_optional(&self.pool)
+                    .await?;
+            data.map(|data| {
+                self.serializer
+                    .deserialize(&data)
+                    .map_err(SqliteStorageError::Codec)
+            })
+            .transpose()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sal_e_api::{Img2ImgParams, Txt2ImgParams};
+
+    use super::*;
+    use crate::bot::{dialogue_codec::DialogueCodec, history::HistoryStore, State};
+
+    #[tokio::test]
+    async fn test_storage() {
+        let history = HistoryStore::open(None).await.unwrap();
+        let storage = SqliteStorage::open(history.pool().clone(), DialogueCodec::default())
+            .await
+            .unwrap();
+        let chat = ChatId(1);
+
+        assert!(matches!(
+            Storage::<State>::get_dialogue(storage.clone(), chat).await,
+            Ok(None)
+        ));
+        storage
+            .clone()
+            .update_dialogue(chat, State::New)
+            .await
+            .unwrap();
+        let ready = State::new_with_defaults(
+            Box::<Txt2ImgParams>::default(),
+            Box::<Img2ImgParams>::default(),
+        );
+        storage.clone().update_dialogue(chat, ready).await.unwrap();
+        assert!(matches!(
+            Storage::<State>::get_dialogue(storage.clone(), chat).await,
+            Ok(Some(State::Ready { .. }))
+        ));
+        Storage::<State>::remove_dialogue(storage.clone(), chat)
+            .await
+            .unwrap();
+        assert!(matches!(
+            Storage::<State>::remove_dialogue(storage, chat).await,
+            Err(SqliteStorageError::NotFound)
+        ));
+    }
+}
@@ -0,0 +1,120 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use teloxide::types::{Me, Message};
+
+/// Which messages in group chats the bot treats as prompts.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupMode {
+    /// Every message the bot receives is a prompt.
+    #[default]
+    All,
+    /// Only messages that mention the bot or reply to it are prompts. Commands such as `/gen`
+    /// work as usual.
+    MentionOnly,
+}
+
+/// Returns a pattern matching mentions of the bot named `bot_name`, in any case.
+fn mention_pattern(bot_name: &str) -> Regex {
+    Regex::new(&format!(r"(?i)(^|\s)@{}\b", regex::escape(bot_name)))
+        .expect("An escaped bot name is a valid pattern")
+}
+
+/// Returns `text` without mentions of the bot named `bot_name`, e.g. `a cat` for
+/// `@sd_bot a cat`.
+pub(crate) fn strip_mention(text: &str, bot_name: &str) -> String {
+    mention_pattern(bot_name)
+        .replace_all(text, "$1")
+        .trim()
+        .to_owned()
+}
+
+/// Returns the prompt in `text`, the text or caption of `msg`, or `None` if `mode` says `msg`
+/// isn't meant for the bot. In group chats with `GroupMode::MentionOnly`, messages must mention
+/// the bot, whose mention is removed from the prompt, or reply to one of its messages.
+pub(crate) fn addressed_prompt(
+    mode: GroupMode,
+    me: &Me,
+    msg: &Message,
+    text: &str,
+) -> Option<String> {
+    let is_group = msg.chat.is_group() || msg.chat.is_supergroup();
+    if mode == GroupMode::All || !is_group {
+        return Some(text.to_owned());
+    }
+    let bot_name = me.user.username.as_deref().unwrap_or_default();
+    if mention_pattern(bot_name).is_match(text) {
+        return Some(strip_mention(text, bot_name));
+    }
+    let replies_to_bot = msg
+        .reply_to_message()
+        .and_then(Message::from)
+        .is_some_and(|user| user.id == me.user.id);
+    replies_to_bot.then(|| text.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn me() -> Me {
+        serde_json::from_value(serde_json::json!({
+            "id": 99,
+            "is_bot": true,
+            "first_name": "SD",
+            "username": "sd_bot",
+            "can_join_groups": true,
+            "can_read_all_group_messages": true,
+            "supports_inline_queries": false,
+        }))
+        .unwrap()
+    }
+
+    fn group_message(text: &str, reply_from: Option<u64>) -> Message {
+        let mut message = serde_json::json!({
+            "message_id": 2,
+            "date": 1634567890,
+            "chat": {"id": -100, "type": "supergroup", "title": "Group"},
+            "from": {"id": 1, "is_bot": false, "first_name": "User"},
+            "text": text,
+        });
+        if let Some(id) = reply_from {
+            message["reply_to_message"] = serde_json::json!({
+                "message_id": 1,
+                "date": 1634567880,
+                "chat": {"id": -100, "type": "supergroup", "title": "Group"},
+                "from": {"id": id, "is_bot": id == 99, "first_name": "Someone"},
+                "text": "earlier",
+            });
+        }
+        serde_json::from_value(message).unwrap()
+    }
+
+    #[test]
+    fn test_addressed_prompt() {
+        let me = me();
+        let prompt = |mode, text, reply_from| {
+            addressed_prompt(mode, &me, &group_message(text, reply_from), text)
+        };
+
+        assert_eq!(
+            prompt(GroupMode::All, "a cat", None),
+            Some("a cat".to_owned())
+        );
+        assert_eq!(prompt(GroupMode::MentionOnly, "a cat", None), None);
+        assert_eq!(
+            prompt(GroupMode::MentionOnly, "@SD_bot a cat", None),
+            Some("a cat".to_owned())
+        );
+        assert_eq!(
+            prompt(GroupMode::MentionOnly, "a cat", Some(99)),
+            Some("a cat".to_owned())
+        );
+        assert_eq!(prompt(GroupMode::MentionOnly, "a cat", Some(5)), None);
+        assert_eq!(prompt(GroupMode::MentionOnly, "a cat @sd_botx", None), None);
+        assert_eq!(
+            prompt(GroupMode::MentionOnly, "a cat\nby @sd_bot", None),
+            Some("a cat\nby".to_owned())
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use std::{io::Cursor, path::PathBuf, sync::Arc};
+
+use anyhow::Context;
+use image::{imageops, ImageOutputFormat, Rgba, RgbaImage};
+use sal_e_api::GenParams;
+use serde::{Deserialize, Serialize};
+
+use super::QuotaConfig;
+
+/// Spacing of the stripes of the built-in watermark, in pixels.
+const STRIPE_PERIOD: u32 = 48;
+/// Width of the stripes of the built-in watermark, in pixels.
+const STRIPE_WIDTH: u32 = 10;
+/// Opacity of the stripes of the built-in watermark, out of 255.
+const STRIPE_OPACITY: u16 = 96;
+/// Quality of the JPEGs sent to guests.
+const JPEG_QUALITY: u8 = 80;
+
+/// Struct that represents the configuration for guest mode, which lets users who aren't allowed
+/// try the bot with small, watermarked images instead of rejecting them.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GuestConfig {
+    /// Longest side of guest images, in pixels. Larger default sizes are scaled down.
+    #[serde(default = "default_max_size")]
+    pub max_size: u32,
+    /// Maximum number of sampling steps for guest images.
+    #[serde(default = "default_max_steps")]
+    pub max_steps: u32,
+    /// The number of images each guest can generate in any hour.
+    #[serde(default = "default_images_per_hour")]
+    pub images_per_hour: u32,
+    /// The number of images each guest can generate in any 24 hours.
+    #[serde(default = "default_images_per_day")]
+    pub images_per_day: u32,
+    /// Path to an image, usually a PNG with transparency, tiled over guest images. If unset,
+    /// diagonal stripes are drawn instead.
+    pub watermark: Option<PathBuf>,
+    /// Message sent with guest images, telling guests how to request access.
+    pub message: Option<String>,
+}
+
+fn default_max_size() -> u32 {
+    384
+}
+
+fn default_max_steps() -> u32 {
+    10
+}
+
+fn default_images_per_hour() -> u32 {
+    2
+}
+
+fn default_images_per_day() -> u32 {
+    5
+}
+
+impl Default for GuestConfig {
+    fn default() -> Self {
+        Self {
+            max_size: default_max_size(),
+            max_steps: default_max_steps(),
+            images_per_hour: default_images_per_hour(),
+            images_per_day: default_images_per_day(),
+            watermark: None,
+            message: None,
+        }
+    }
+}
+
+/// Guest mode, injected into handlers as part of the `UiConfig`.
+#[derive(Debug, Clone)]
+pub(crate) struct GuestMode {
+    config: GuestConfig,
+    watermark: Option<Arc<RgbaImage>>,
+}
+
+impl GuestMode {
+    /// Constructs a new `GuestMode` from its configuration, loading the watermark.
+    pub fn new(config: GuestConfig) -> anyhow::Result<Self> {
+        let watermark = config
+            .watermark
+            .as_ref()
+            .map(|path| {
+                image::open(path)
+                    .with_context(|| format!("Failed to load guest watermark {}", path.display()))
+                    .map(|image| Arc::new(image.into_rgba8()))
+            })
+            .transpose()?;
+        Ok(Self { config, watermark })
+    }
+
+    /// Returns the message sent with guest images, if one is configured.
+    pub fn message(&self) -> Option<&str> {
+        self.config.message.as_deref()
+    }
+
+    /// Returns the quota guests are limited to.
+    pub fn quota(&self) -> QuotaConfig {
+        QuotaConfig {
+            images_per_hour: Some(self.config.images_per_hour),
+            images_per_day: Some(self.config.images_per_day),
+            utc_offset: None,
+        }
+    }
+
+    /// Limits `params` to a single small image with few steps and without the hires fix.
+    pub fn limit_params(&self, params: &mut dyn GenParams) {
+        let (width, height) = scaled_size(
+            params.width().unwrap_or(self.config.max_size),
+            params.height().unwrap_or(self.config.max_size),
+            self.config.max_size,
+        );
+        params.set_width(width);
+        params.set_height(height);
+        params.set_steps(params.steps().map_or(self.config.max_steps, |steps| {
+            steps.min(self.config.max_steps)
+        }));
+        params.set_count(1);
+        params.set_batch_size(1);
+        params.set_hires_fix(false);
+    }
+
+    /// Watermarks an encoded image, returning it as a JPEG.
+    pub fn watermark(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut image = image::load_from_memory(data)
+            .context("Failed to decode image to watermark")?
+            .into_rgba8();
+        match &self.watermark {
+            Some(watermark) => tile(&mut image, watermark),
+            None => stripe(&mut image),
+        }
+        let mut output = Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgba8(image)
+            .into_rgb8()
+            .write_to(&mut output, ImageOutputFormat::Jpeg(JPEG_QUALITY))
+            .context("Failed to encode watermarked image")?;
+        Ok(output.into_inner())
+    }
+}
+
+/// Scales `width` and `height` down so that neither exceeds `max_size`, keeping the aspect
+/// ratio. Sizes are rounded down to multiples of 8, which models expect.
+fn scaled_size(width: u32, height: u32, max_size: u32) -> (u32, u32) {
+    let longest = width.max(height);
+    if longest <= max_size {
+        return (width, height);
+    }
+    let scale = |side: u32| {
+        ((u64::from(side) * u64::from(max_size) / u64::from(longest)) as u32 / 8 * 8).max(8)
+    };
+    (scale(width), scale(height))
+}
+
+/// Tiles `watermark` over `image`, leaving a gap of the watermark's size between tiles.
+fn tile(image: &mut RgbaImage, watermark: &RgbaImage) {
+    let (width, height) = watermark.dimensions();
+    if width == 0 || height == 0 {
+        return;
+    }
+    for y in (0..image.height()).step_by(2 * height as usize) {
+        for x in (0..image.width()).step_by(2 * width as usize) {
+            imageops::overlay(image, watermark, i64::from(x), i64::from(y));
+        }
+    }
+}
+
+/// Draws translucent white diagonal stripes over `image`.
+fn stripe(image: &mut RgbaImage) {
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        if (x + y) % STRIPE_PERIOD >= STRIPE_WIDTH {
+            continue;
+        }
+        let Rgba([r, g, b, a]) = *pixel;
+        let blend =
+            |c: u8| ((u16::from(c) * (255 - STRIPE_OPACITY) + 255 * STRIPE_OPACITY) / 255) as u8;
+        *pixel = Rgba([blend(r), blend(g), blend(b), a]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sal_e_api::Txt2ImgParams;
+
+    use super::*;
+
+    #[test]
+    fn test_scaled_size() {
+        assert_eq!(scaled_size(512, 512, 384), (384, 384));
+        assert_eq!(scaled_size(1024, 768, 384), (384, 288));
+        assert_eq!(scaled_size(256, 320, 384), (256, 320));
+    }
+
+    #[test]
+    fn test_limit_params() {
+        let guest = GuestMode::new(GuestConfig::default()).unwrap();
+        let mut params = Txt2ImgParams::default();
+        params.set_width(768);
+        params.set_height(512);
+        params.set_steps(30);
+        params.set_count(4);
+        guest.limit_params(&mut params);
+        assert_eq!(params.width(), Some(384));
+        assert_eq!(params.height(), Some(256));
+        assert_eq!(params.steps(), Some(10));
+        assert_eq!(params.count(), Some(1));
+    }
+
+    #[test]
+    fn test_watermark() {
+        let guest = GuestMode::new(GuestConfig::default()).unwrap();
+        let black = RgbaImage::from_pixel(64, 64, Rgba([0, 0, 0, 255]));
+        let mut data = Cursor::new(Vec::new());
+        black.write_to(&mut data, ImageOutputFormat::Png).unwrap();
+
+        let watermarked = image::load_from_memory(&guest.watermark(&data.into_inner()).unwrap())
+            .unwrap()
+            .into_luma8();
+        assert!(watermarked.get_pixel(0, 0).0[0] > 50);
+        assert!(watermarked.get_pixel(30, 0).0[0] < 20);
+        assert!(guest.watermark(b"not an image").is_err());
+    }
+}
@@ -0,0 +1,886 @@
+use std::{collections::HashSet, sync::Arc};
+
+use anyhow::{anyhow, Context};
+use comfyui_api::comfy::GenericAccessor;
+use itertools::Itertools as _;
+use sal_e_api::{BackendPool, ComfyParams, ComfyPromptApi, GenParams, Txt2ImgApi};
+use teloxide::{dispatching::UpdateHandler, dptree::case, macros::BotCommands, prelude::*};
+use tracing::{info, instrument};
+
+use crate::bot::{backends::BackendRegistry, build_info, history::HistoryStore, i18n::Translator};
+
+use super::{
+    filter_command, relay_txt2img, stored_txt2img, AuthConfig, BackendHandles, DialogueStorage,
+    DiffusionDialogue, SendContext, State, UiConfig,
+};
+
+/// Number of recent jobs listed by the `jobs` command.
+const RECENT_JOBS: u32 = 10;
+
+/// Number of characters of a quarantined state shown by the `quarantine` command.
+const QUARANTINE_PREVIEW_LENGTH: usize = 3000;
+
+const QUARANTINE_USAGE: &str = "Usage: /quarantine [<id>], or /quarantine purge [<id>]";
+
+const GENFOR_USAGE: &str = "Usage: /genfor <chat id> <prompt>";
+
+/// BotCommands for bot administrators.
+#[derive(BotCommands, Debug, Clone)]
+#[command(rename_rule = "lowercase", description = "Admin commands")]
+pub(crate) enum AdminCommands {
+    /// Command to list recent backend jobs
+    #[command(description = "list recent backend jobs.")]
+    Jobs,
+    /// Command to override a raw node input
+    #[command(description = "override a node input, e.g. /set [img2img] 12.denoise 0.55.")]
+    Set(String),
+    /// Command to remove a node input override
+    #[command(description = "remove a node input override, e.g. /unset [img2img] 12.denoise.")]
+    Unset(String),
+    /// Command to show current state
+    #[command(description = "show node input overrides with /show overrides.")]
+    Show(String),
+    /// Command to show build and backend versions
+    #[command(description = "show the bot and backend versions.")]
+    Version,
+    /// Command to allow a user
+    #[command(description = "allow a user by id, or the sender of the message you reply to.")]
+    Allow(String),
+    /// Command to ban a user
+    #[command(description = "ban a user by id, or the sender of the message you reply to.")]
+    Ban(String),
+    /// Command to list allowed and banned users
+    #[command(description = "list the allowed and banned users.")]
+    ListUsers,
+    /// Command to inspect or delete quarantined dialogue states
+    #[command(
+        description = "list settings that couldn't be loaded, show one with /quarantine <id>, or delete them with /quarantine purge [id]."
+    )]
+    Quarantine(String),
+    /// Command to generate an image in another chat
+    #[command(
+        description = "generate an image in another allowed chat with its settings, e.g. /genfor -1001234567890 a lighthouse at dusk."
+    )]
+    Genfor(String),
+}
+
+/// Which workflow an override applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Workflow {
+    Txt2Img,
+    Img2Img,
+}
+
+impl Workflow {
+    /// Splits an optional leading workflow name off the command arguments.
+    fn parse(args: &str) -> (Self, &str) {
+        let args = args.trim();
+        match args.split_once(char::is_whitespace) {
+            Some(("txt2img", rest)) => (Workflow::Txt2Img, rest.trim_start()),
+            Some(("img2img", rest)) => (Workflow::Img2Img, rest.trim_start()),
+            _ => (Workflow::Txt2Img, args),
+        }
+    }
+}
+
+impl std::fmt::Display for Workflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Workflow::Txt2Img => write!(f, "txt2img"),
+            Workflow::Img2Img => write!(f, "img2img"),
+        }
+    }
+}
+
+/// Parses an override value as JSON, falling back to a plain string.
+fn parse_value(value: &str) -> serde_json::Value {
+    serde_json::from_str(value).unwrap_or_else(|_| serde_json::Value::String(value.to_owned()))
+}
+
+fn comfy_params(params: &mut Box<dyn GenParams>) -> Option<&mut ComfyParams> {
+    params.as_mut().as_any_mut().downcast_mut::<ComfyParams>()
+}
+
+/// Applies `f` to the chat's ComfyUI parameters for `workflow`, saving the state on success.
+async fn update_overrides<F>(
+    dialogue: &DiffusionDialogue,
+    state: State,
+    workflow: Workflow,
+    translator: &Translator,
+    f: F,
+) -> anyhow::Result<String>
+where
+    F: FnOnce(&mut ComfyParams) -> Result<String, String>,
+{
+    let State::Ready {
+        bot_state,
+        mut txt2img,
+        mut img2img,
+    } = state
+    else {
+        return Ok(translator.text("Please /start the bot first."));
+    };
+    let params = match workflow {
+        Workflow::Txt2Img => comfy_params(&mut txt2img),
+        Workflow::Img2Img => comfy_params(&mut img2img),
+    };
+    let Some(params) = params else {
+        return Ok(translator.text("Overrides are only supported by the ComfyUI backend."));
+    };
+    let reply = match f(params) {
+        Ok(reply) => reply,
+        Err(reply) => return Ok(reply),
+    };
+    dialogue
+        .update(State::Ready {
+            bot_state,
+            txt2img,
+            img2img,
+        })
+        .await
+        .map_err(|e| anyhow!(e))?;
+    Ok(reply)
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "set"
+    )
+)]
+async fn handle_set(
+    bot: Bot,
+    translator: Translator,
+    msg: Message,
+    dialogue: DiffusionDialogue,
+    state: State,
+    args: String,
+) -> anyhow::Result<()> {
+    let (workflow, args) = Workflow::parse(&args);
+    let parsed = args
+        .split_once(char::is_whitespace)
+        .map(|(path, value)| (path.parse::<GenericAccessor>(), value.trim()));
+    let reply = match parsed {
+        Some((Ok(accessor), value)) if !value.is_empty() => {
+            let value = parse_value(value);
+            update_overrides(&dialogue, state, workflow, &translator, |params| {
+                if let Some(prompt) = &params.prompt {
+                    accessor
+                        .set(&mut prompt.clone(), value.clone())
+                        .map_err(|e| {
+                            translator.format(
+                                "Failed to set {input}: {error}",
+                                &[("input", &accessor.to_string()), ("error", &e.to_string())],
+                            )
+                        })?;
+                }
+                let reply = translator.format(
+                    "Set {input} = {value} for {workflow}.",
+                    &[
+                        ("input", &accessor.to_string()),
+                        ("value", &value.to_string()),
+                        ("workflow", &workflow.to_string()),
+                    ],
+                );
+                params.overrides.insert(accessor.to_string(), value);
+                Ok(reply)
+            })
+            .await?
+        }
+        _ => translator.text("Usage: /set [txt2img|img2img] node.input value"),
+    };
+    SendContext::of(&msg).send_message(&bot, reply).await?;
+    Ok(())
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "unset"
+    )
+)]
+async fn handle_unset(
+    bot: Bot,
+    translator: Translator,
+    msg: Message,
+    dialogue: DiffusionDialogue,
+    state: State,
+    args: String,
+) -> anyhow::Result<()> {
+    let (workflow, args) = Workflow::parse(&args);
+    let reply = match args.parse::<GenericAccessor>() {
+        Ok(accessor) => {
+            update_overrides(&dialogue, state, workflow, &translator, |params| {
+                let (input, workflow) = (accessor.to_string(), workflow.to_string());
+                let args = [("input", input.as_str()), ("workflow", workflow.as_str())];
+                params
+                    .overrides
+                    .remove(&accessor.to_string())
+                    .map(|_| translator.format("Removed override {input} for {workflow}.", &args))
+                    .ok_or_else(|| translator.format("No override {input} for {workflow}.", &args))
+            })
+            .await?
+        }
+        Err(_) => translator.text("Usage: /unset [txt2img|img2img] node.input"),
+    };
+    SendContext::of(&msg).send_message(&bot, reply).await?;
+    Ok(())
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "show"
+    )
+)]
+async fn handle_show(
+    bot: Bot,
+    translator: Translator,
+    msg: Message,
+    state: State,
+    args: String,
+) -> anyhow::Result<()> {
+    if args.trim() != "overrides" {
+        SendContext::of(&msg)
+            .send_message(&bot, translator.text("Usage: /show overrides"))
+            .await?;
+        return Ok(());
+    }
+    let State::Ready {
+        txt2img, img2img, ..
+    } = state
+    else {
+        SendContext::of(&msg)
+            .send_message(&bot, translator.text("Please /start the bot first."))
+            .await?;
+        return Ok(());
+    };
+    let lines = [(Workflow::Txt2Img, txt2img), (Workflow::Img2Img, img2img)]
+        .into_iter()
+        .filter_map(|(workflow, params)| {
+            let params = params.as_any().downcast_ref::<ComfyParams>()?;
+            Some(
+                params
+                    .overrides
+                    .iter()
+                    .map(|(path, value)| format!("{} {} = {}", workflow, path, value))
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .flatten()
+        .collect::<Vec<_>>();
+    let reply = if lines.is_empty() {
+        translator.text("No overrides set.")
+    } else {
+        lines.join("\n")
+    };
+    SendContext::of(&msg).send_message(&bot, reply).await?;
+    Ok(())
+}
+
+/// Returns the ComfyUI backends behind `api`, which may be a single backend or a pool.
+fn comfy_apis(api: &dyn Txt2ImgApi) -> Vec<&ComfyPromptApi> {
+    if let Some(pool) = api
+        .as_any()
+        .downcast_ref::<BackendPool<Box<dyn Txt2ImgApi>>>()
+    {
+        return pool
+            .backends()
+            .filter_map(|api| api.as_any().downcast_ref::<ComfyPromptApi>())
+            .collect();
+    }
+    api.as_any()
+        .downcast_ref::<ComfyPromptApi>()
+        .into_iter()
+        .collect()
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "jobs"
+    )
+)]
+async fn handle_jobs(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    translator: Translator,
+    msg: Message,
+) -> anyhow::Result<()> {
+    let apis = comfy_apis(backends.txt2img_api.as_ref());
+    if apis.is_empty() {
+        SendContext::of(&msg)
+            .send_message(
+                &bot,
+                translator.text("Job listing is only supported by the ComfyUI backend."),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let mut sections = Vec::new();
+    for (index, api) in apis.iter().enumerate() {
+        let tasks = api
+            .client
+            .history(RECENT_JOBS)
+            .await
+            .context("Failed to get backend history")?;
+
+        let jobs = if tasks.is_empty() {
+            translator.text("No recent jobs.")
+        } else {
+            tasks
+                .iter()
+                .map(|task| {
+                    let status = task
+                        .status
+                        .as_ref()
+                        .map(|status| status.status.to_string())
+                        .unwrap_or_else(|| translator.text("pending"));
+                    translator.format(
+                        "{id} {status} ({nodes} nodes)",
+                        &[
+                            ("id", &task.prompt.id.to_string()),
+                            ("status", &status),
+                            ("nodes", &task.node_count().to_string()),
+                        ],
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        sections.push(if apis.len() > 1 {
+            format!(
+                "{}\n{jobs}",
+                translator.format("Backend {number}:", &[("number", &(index + 1).to_string())])
+            )
+        } else {
+            jobs
+        });
+    }
+    let text = sections.join("\n\n");
+
+    SendContext::of(&msg).send_message(&bot, text).await?;
+
+    Ok(())
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "version"
+    )
+)]
+async fn handle_version(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    translator: Translator,
+    msg: Message,
+) -> anyhow::Result<()> {
+    let text = format!(
+        "{}\n{}",
+        build_info::build_info(),
+        translator.format(
+            "Backend: {version}",
+            &[("version", &build_info::backend_version(&backends).await)]
+        )
+    );
+    SendContext::of(&msg).send_message(&bot, text).await?;
+    Ok(())
+}
+
+/// Returns the user an access command applies to: the id in `args`, or else the sender of the
+/// message it replies to.
+fn target_user(msg: &Message, args: &str) -> Option<UserId> {
+    let args = args.trim();
+    if args.is_empty() {
+        return msg
+            .reply_to_message()?
+            .from()
+            .filter(|user| !user.is_bot)
+            .map(|user| user.id);
+    }
+    args.parse().ok().map(UserId)
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "allow"
+    )
+)]
+async fn handle_allow(
+    bot: Bot,
+    auth: Arc<AuthConfig>,
+    history: HistoryStore,
+    translator: Translator,
+    msg: Message,
+    args: String,
+) -> anyhow::Result<()> {
+    let reply = match target_user(&msg, &args) {
+        Some(user_id) => {
+            history.set_user_access(user_id, true).await?;
+            auth.allow_user(user_id);
+            translator.format("Allowed user {id}.", &[("id", &user_id.to_string())])
+        }
+        None => translator.text("Usage: /allow <user id>, or reply to a message from the user."),
+    };
+    SendContext::of(&msg).send_message(&bot, reply).await?;
+    Ok(())
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "ban"
+    )
+)]
+async fn handle_ban(
+    bot: Bot,
+    auth: Arc<AuthConfig>,
+    history: HistoryStore,
+    translator: Translator,
+    msg: Message,
+    args: String,
+) -> anyhow::Result<()> {
+    let reply = match target_user(&msg, &args) {
+        Some(user_id) if auth.user_is_admin(&user_id.into()) => {
+            translator.text("Admins can't be banned.")
+        }
+        Some(user_id) => {
+            history.set_user_access(user_id, false).await?;
+            auth.ban_user(user_id);
+            translator.format("Banned user {id}.", &[("id", &user_id.to_string())])
+        }
+        None => translator.text("Usage: /ban <user id>, or reply to a message from the user."),
+    };
+    SendContext::of(&msg).send_message(&bot, reply).await?;
+    Ok(())
+}
+
+/// Formats a set of ids as a sorted, comma separated list.
+fn format_ids(ids: &HashSet<ChatId>, translator: &Translator) -> String {
+    if ids.is_empty() {
+        return translator.text("none");
+    }
+    ids.iter().map(|id| id.0).sorted().join(", ")
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "listusers"
+    )
+)]
+async fn handle_list_users(
+    bot: Bot,
+    auth: Arc<AuthConfig>,
+    translator: Translator,
+    msg: Message,
+) -> anyhow::Result<()> {
+    let mut text = translator.format(
+        "Allowed: {allowed}\nBanned: {banned}",
+        &[
+            (
+                "allowed",
+                &format_ids(&auth.allowed_users.read().unwrap(), &translator),
+            ),
+            (
+                "banned",
+                &format_ids(&auth.banned_users.read().unwrap(), &translator),
+            ),
+        ],
+    );
+    if auth.allow_all_users {
+        text.push('\n');
+        text.push_str(&translator.text("All users who aren't banned are allowed."));
+    }
+    SendContext::of(&msg).send_message(&bot, text).await?;
+    Ok(())
+}
+
+/// Runs a `quarantine` command, returning the reply.
+async fn quarantine_reply(
+    history: &HistoryStore,
+    args: &str,
+    translator: &Translator,
+) -> anyhow::Result<String> {
+    let mut args = args.split_whitespace();
+    let reply = match (args.next(), args.next(), args.next()) {
+        (None, _, _) => {
+            let quarantined = history.quarantined_dialogues().await?;
+            if quarantined.is_empty() {
+                return Ok(translator.text("No settings are quarantined."));
+            }
+            quarantined
+                .iter()
+                .map(|dialogue| {
+                    let created_at = chrono::DateTime::from_timestamp(dialogue.created_at, 0)
+                        .map(|time| time.format("%Y-%m-%d %H:%M UTC").to_string())
+                        .unwrap_or_default();
+                    translator.format(
+                        "{id}: chat {chat}, {created_at}, {size} bytes",
+                        &[
+                            ("id", &dialogue.id.to_string()),
+                            ("chat", &dialogue.chat_id.to_string()),
+                            ("created_at", &created_at),
+                            ("size", &dialogue.dialogue.len().to_string()),
+                        ],
+                    )
+                })
+                .join("\n")
+        }
+        (Some("purge"), id, None) => {
+            let id = match id.map(str::parse).transpose() {
+                Ok(id) => id,
+                Err(_) => return Ok(translator.text(QUARANTINE_USAGE)),
+            };
+            let deleted = history.delete_quarantined_dialogues(id).await?;
+            translator.format(
+                "Deleted {count} quarantined settings.",
+                &[("count", &deleted.to_string())],
+            )
+        }
+        (Some(id), None, _) => {
+            let Ok(id) = id.parse::<i64>() else {
+                return Ok(translator.text(QUARANTINE_USAGE));
+            };
+            let quarantined = history.quarantined_dialogues().await?;
+            let Some(dialogue) = quarantined.iter().find(|dialogue| dialogue.id == id) else {
+                return Ok(translator.format(
+                    "There are no quarantined settings with id {id}.",
+                    &[("id", &id.to_string())],
+                ));
+            };
+            let contents = String::from_utf8_lossy(&dialogue.dialogue);
+            format!(
+                "{}\n\n{}",
+                translator.format(
+                    "Chat {chat}\nError: {error}",
+                    &[
+                        ("chat", &dialogue.chat_id.to_string()),
+                        ("error", &dialogue.error),
+                    ],
+                ),
+                contents
+                    .chars()
+                    .take(QUARANTINE_PREVIEW_LENGTH)
+                    .collect::<String>()
+            )
+        }
+        _ => translator.text(QUARANTINE_USAGE),
+    };
+    Ok(reply)
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "quarantine"
+    )
+)]
+async fn handle_quarantine(
+    bot: Bot,
+    history: HistoryStore,
+    translator: Translator,
+    msg: Message,
+    args: String,
+) -> anyhow::Result<()> {
+    let reply = quarantine_reply(&history, &args, &translator).await?;
+    SendContext::of(&msg).send_message(&bot, reply).await?;
+    Ok(())
+}
+
+/// Splits the arguments of a `genfor` command into the target chat and the prompt.
+fn parse_genfor(args: &str) -> Option<(ChatId, String)> {
+    let (chat_id, prompt) = args.trim().split_once(char::is_whitespace)?;
+    let prompt = prompt.trim();
+    if prompt.is_empty() {
+        return None;
+    }
+    Some((ChatId(chat_id.parse().ok()?), prompt.to_owned()))
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "genfor"
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+async fn handle_genfor(
+    bot: Bot,
+    auth: Arc<AuthConfig>,
+    registry: Arc<BackendRegistry>,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
+    storage: DialogueStorage,
+    translator: Translator,
+    msg: Message,
+    args: String,
+) -> anyhow::Result<()> {
+    let Some((target, prompt)) = parse_genfor(&args) else {
+        SendContext::of(&msg)
+            .send_message(&bot, translator.text(GENFOR_USAGE))
+            .await?;
+        return Ok(());
+    };
+    if !auth.chat_is_allowed(Some(target), None) {
+        SendContext::of(&msg)
+            .send_message(
+                &bot,
+                translator.format(
+                    "Chat {chat} isn't allowed.",
+                    &[("chat", &target.to_string())],
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let backends = registry.for_chat(&history, target).await;
+    let mut txt2img = stored_txt2img(storage, &backends, target).await;
+    let messages = relay_txt2img(
+        &bot,
+        &backends,
+        &ui,
+        &history,
+        txt2img.as_mut(),
+        &msg,
+        target,
+        prompt.clone(),
+    )
+    .await?;
+    info!(
+        target: "audit",
+        admin = ?msg.from().map(|user| user.id),
+        chat = %target,
+        messages = messages.len(),
+        prompt,
+        "Relayed generation"
+    );
+    SendContext::of(&msg)
+        .send_message(
+            &bot,
+            translator.format("Posted to chat {chat}.", &[("chat", &target.to_string())]),
+        )
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+pub(crate) fn admin_filter() -> UpdateHandler<anyhow::Error> {
+    dptree::filter(|auth: Arc<AuthConfig>, upd: Update| {
+        upd.user()
+            .map(|user| auth.user_is_admin(&user.id.into()))
+            .unwrap_or_default()
+    })
+}
+
+pub fn admin_schema() -> UpdateHandler<anyhow::Error> {
+    Update::filter_message()
+        .chain(admin_filter())
+        .chain(filter_command::<AdminCommands>())
+        .branch(case![AdminCommands::Jobs].endpoint(handle_jobs))
+        .branch(case![AdminCommands::Set(args)].endpoint(handle_set))
+        .branch(case![AdminCommands::Unset(args)].endpoint(handle_unset))
+        .branch(case![AdminCommands::Show(args)].endpoint(handle_show))
+        .branch(case![AdminCommands::Version].endpoint(handle_version))
+        .branch(case![AdminCommands::Allow(args)].endpoint(handle_allow))
+        .branch(case![AdminCommands::Ban(args)].endpoint(handle_ban))
+        .branch(case![AdminCommands::ListUsers].endpoint(handle_list_users))
+        .branch(case![AdminCommands::Quarantine(args)].endpoint(handle_quarantine))
+        .branch(case![AdminCommands::Genfor(args)].endpoint(handle_genfor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::{backends::DEFAULT_BACKEND, ConfigParameters};
+    use sal_e_api::StableDiffusionWebUiApi;
+    use teloxide::types::UpdateKind;
+
+    fn create_update(user_id: u64) -> Update {
+        let json = format!(
+            r#"{{
+          "message_id": 123456,
+          "from": {{
+           "id": {user_id},
+           "is_bot": false,
+           "first_name": "Stable",
+           "username": "sd"
+          }},
+          "chat": {{
+           "id": 1234567890,
+           "first_name": "Stable",
+           "username": "sd",
+           "type": "private"
+          }},
+          "date": 1634567890,
+          "text": "/jobs"
+         }}"#
+        );
+        Update {
+            id: 1,
+            kind: UpdateKind::Message(serde_json::from_str(&json).unwrap()),
+        }
+    }
+
+    fn create_config(admin_users: Vec<i64>) -> ConfigParameters {
+        let backends = Arc::new(BackendHandles {
+            name: DEFAULT_BACKEND.to_owned(),
+            txt2img_api: Box::<StableDiffusionWebUiApi>::default(),
+            img2img_api: Box::<StableDiffusionWebUiApi>::default(),
+            describer: None,
+            blank_check: None,
+            upscaler: None,
+            jobs: Default::default(),
+            stats: Default::default(),
+            metrics: Default::default(),
+            defaults: Default::default(),
+        });
+        ConfigParameters::new(
+            Arc::new(AuthConfig {
+                allowed_users: Default::default(),
+                allowed_chats: Default::default(),
+                banned_users: Default::default(),
+                admin_users: admin_users.into_iter().map(ChatId).collect(),
+                allow_all_users: true,
+                accounts: Default::default(),
+            }),
+            Default::default(),
+            backends.clone(),
+            Arc::new(BackendRegistry::new(backends, Vec::new()).unwrap()),
+        )
+    }
+
+    #[test]
+    fn test_workflow_parse() {
+        assert_eq!(
+            Workflow::parse("12.denoise 0.55"),
+            (Workflow::Txt2Img, "12.denoise 0.55")
+        );
+        assert_eq!(
+            Workflow::parse(" img2img  12.denoise 0.55"),
+            (Workflow::Img2Img, "12.denoise 0.55")
+        );
+        assert_eq!(
+            Workflow::parse("txt2img 6.text a cat"),
+            (Workflow::Txt2Img, "6.text a cat")
+        );
+    }
+
+    #[test]
+    fn test_target_user() {
+        let msg: Message = serde_json::from_value(serde_json::json!({
+            "message_id": 2,
+            "date": 1634567890,
+            "chat": {"id": 1, "type": "private", "first_name": "Admin"},
+            "from": {"id": 1, "is_bot": false, "first_name": "Admin"},
+            "text": "/ban",
+            "reply_to_message": {
+                "message_id": 1,
+                "date": 1634567880,
+                "chat": {"id": 1, "type": "private", "first_name": "Admin"},
+                "from": {"id": 42, "is_bot": false, "first_name": "User"},
+                "text": "a cat",
+            },
+        }))
+        .unwrap();
+        assert_eq!(target_user(&msg, ""), Some(UserId(42)));
+        assert_eq!(target_user(&msg, " 7 "), Some(UserId(7)));
+        assert_eq!(target_user(&msg, "someone"), None);
+    }
+
+    #[test]
+    fn test_parse_genfor() {
+        assert_eq!(
+            parse_genfor(" -1001234567890  a lighthouse at dusk "),
+            Some((ChatId(-1001234567890), "a lighthouse at dusk".to_owned()))
+        );
+        assert_eq!(parse_genfor("-1001234567890"), None);
+        assert_eq!(parse_genfor("channel a lighthouse"), None);
+        assert_eq!(parse_genfor(""), None);
+    }
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(parse_value("0.55"), serde_json::json!(0.55));
+        assert_eq!(parse_value("true"), serde_json::json!(true));
+        assert_eq!(parse_value("a cat"), serde_json::json!("a cat"));
+    }
+
+    #[tokio::test]
+    async fn test_admin_filter_admin() {
+        let cfg = create_config(vec![123456789]);
+        let update = create_update(123456789);
+
+        assert!(matches!(
+            admin_filter()
+                .endpoint(|| async { anyhow::Ok(()) })
+                .dispatch(dptree::deps![update, cfg.auth])
+                .await,
+            ControlFlow::Break(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_admin_filter_not_admin() {
+        let cfg = create_config(vec![123456789]);
+        let update = create_update(987654321);
+
+        assert!(matches!(
+            admin_filter()
+                .endpoint(|| async { anyhow::Ok(()) })
+                .dispatch(dptree::deps![update, cfg.auth])
+                .await,
+            ControlFlow::Continue(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_quarantine_reply() {
+        let translator = Translator::default();
+        let history = HistoryStore::open(None).await.unwrap();
+        assert_eq!(
+            quarantine_reply(&history, "", &translator).await.unwrap(),
+            "No settings are quarantined."
+        );
+        assert_eq!(
+            quarantine_reply(&history, "purge", &translator)
+                .await
+                .unwrap(),
+            "Deleted 0 quarantined settings."
+        );
+        assert_eq!(
+            quarantine_reply(&history, "3", &translator).await.unwrap(),
+            "There are no quarantined settings with id 3."
+        );
+        assert_eq!(
+            quarantine_reply(&history, "purge all", &translator)
+                .await
+                .unwrap(),
+            QUARANTINE_USAGE
+        );
+    }
+}
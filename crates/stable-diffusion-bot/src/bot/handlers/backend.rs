@@ -0,0 +1,298 @@
+use std::sync::Arc;
+
+use teloxide::{
+    dispatching::UpdateHandler,
+    dptree::case,
+    macros::BotCommands,
+    payloads::setters::*,
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+use tracing::{instrument, warn};
+
+use super::{filter_command, BackendHandles, SendContext};
+use crate::bot::{
+    backends::{BackendRegistry, DEFAULT_BACKEND},
+    history::HistoryStore,
+    i18n::Translator,
+};
+
+/// Telegram limits callback data to 64 bytes.
+const MAX_CALLBACK_DATA_LENGTH: usize = 64;
+
+/// BotCommands for choosing the backend.
+#[derive(BotCommands, Debug, Clone)]
+#[command(rename_rule = "lowercase", description = "Backend commands")]
+pub(crate) enum BackendCommands {
+    /// Command to choose the backend that generates images in the chat.
+    #[command(description = "choose the backend that generates your images.")]
+    Backend(String),
+}
+
+/// Builds an inline keyboard listing the backends in `registry`, marking the `current` one.
+/// Backends whose names are too long for a button can still be chosen with `/backend <name>`.
+fn backend_keyboard(registry: &BackendRegistry, current: &str) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(
+        registry
+            .names()
+            .map(|name| (name, format!("backend/{name}")))
+            .filter(|(_, data)| data.len() <= MAX_CALLBACK_DATA_LENGTH)
+            .map(|(name, data)| {
+                let text = if name == current {
+                    format!("✅ {name}")
+                } else {
+                    name.to_owned()
+                };
+                [InlineKeyboardButton::callback(text, data)]
+            }),
+    )
+}
+
+/// Selects the backend called `name` in `chat_id`, whose current backend is `current`,
+/// returning the reply.
+async fn select(
+    registry: &BackendRegistry,
+    history: &HistoryStore,
+    current: &BackendHandles,
+    chat_id: ChatId,
+    name: &str,
+    translator: &Translator,
+) -> anyhow::Result<String> {
+    let Some(backend) = registry.get(name) else {
+        return Ok(translator.format("There is no backend named {name}.", &[("name", name)]));
+    };
+    history
+        .set_chat_backend(chat_id, (name != DEFAULT_BACKEND).then_some(name))
+        .await?;
+    // Settings of another type of backend are reset when the dialogue is next loaded.
+    let txt2img = current.txt2img_api.gen_params(None);
+    if txt2img.as_any().type_id() == backend.txt2img_api.gen_params(None).as_any().type_id() {
+        Ok(translator.format("Using backend {name}.", &[("name", name)]))
+    } else {
+        Ok(translator.format(
+            "Using backend {name}. Your settings are reset to its defaults.",
+            &[("name", name)],
+        ))
+    }
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "backend"
+    )
+)]
+async fn handle_backend_command(
+    bot: Bot,
+    registry: Arc<BackendRegistry>,
+    backends: Arc<BackendHandles>,
+    history: HistoryStore,
+    msg: Message,
+    name: String,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let context = SendContext::of(&msg);
+    let name = name.trim();
+    if !registry.has_choice() {
+        context
+            .send_message(&bot, translator.text("There is only one backend."))
+            .reply_to_message_id(msg.id)
+            .await?;
+    } else if name.is_empty() {
+        let current = registry.selected(&history, msg.chat.id).await;
+        context
+            .send_message(&bot, translator.text("Please choose a backend."))
+            .reply_markup(backend_keyboard(&registry, current))
+            .await?;
+    } else {
+        let reply = select(
+            &registry,
+            &history,
+            &backends,
+            msg.chat.id,
+            name,
+            &translator,
+        )
+        .await?;
+        context
+            .send_message(&bot, reply)
+            .reply_to_message_id(msg.id)
+            .await?;
+    }
+    Ok(())
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "backend"
+    )
+)]
+async fn handle_backend_selection(
+    bot: Bot,
+    registry: Arc<BackendRegistry>,
+    backends: Arc<BackendHandles>,
+    history: HistoryStore,
+    q: CallbackQuery,
+    name: String,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let Some(message) = q.message else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, this message is no longer available."))
+            .await?;
+        return Ok(());
+    };
+    let reply = select(
+        &registry,
+        &history,
+        &backends,
+        message.chat.id,
+        &name,
+        &translator,
+    )
+    .await?;
+
+    if let Err(e) = bot.answer_callback_query(q.id).await {
+        warn!("Failed to answer backend selection callback query: {}", e)
+    }
+    bot.edit_message_text(message.chat.id, message.id, reply)
+        .reply_markup(InlineKeyboardMarkup::default())
+        .await?;
+    Ok(())
+}
+
+pub fn backend_schema() -> UpdateHandler<anyhow::Error> {
+    let command_handler = Update::filter_message()
+        .chain(filter_command::<BackendCommands>())
+        .branch(case![BackendCommands::Backend(name)].endpoint(handle_backend_command));
+
+    let callback_handler = Update::filter_callback_query()
+        .chain(dptree::filter_map(|q: CallbackQuery| {
+            q.data?.strip_prefix("backend/").map(str::to_owned)
+        }))
+        .endpoint(handle_backend_selection);
+
+    dptree::entry()
+        .branch(command_handler)
+        .branch(callback_handler)
+}
+
+#[cfg(test)]
+mod tests {
+    use sal_e_api::{ComfyPromptApi, StableDiffusionWebUiApi};
+
+    use super::*;
+
+    fn backend_handles(comfy: bool) -> Arc<BackendHandles> {
+        let (txt2img_api, img2img_api): (
+            Box<dyn sal_e_api::Txt2ImgApi>,
+            Box<dyn sal_e_api::Img2ImgApi>,
+        ) = if comfy {
+            (
+                Box::<ComfyPromptApi>::default(),
+                Box::<ComfyPromptApi>::default(),
+            )
+        } else {
+            (
+                Box::<StableDiffusionWebUiApi>::default(),
+                Box::<StableDiffusionWebUiApi>::default(),
+            )
+        };
+        Arc::new(BackendHandles {
+            name: DEFAULT_BACKEND.to_owned(),
+            txt2img_api,
+            img2img_api,
+            describer: None,
+            blank_check: None,
+            upscaler: None,
+            jobs: Default::default(),
+            stats: Default::default(),
+            metrics: Default::default(),
+            defaults: Default::default(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_select() {
+        let default = backend_handles(false);
+        let registry = BackendRegistry::new(
+            default.clone(),
+            vec![
+                ("gpu2".to_owned(), backend_handles(false)),
+                ("comfy".to_owned(), backend_handles(true)),
+            ],
+        )
+        .unwrap();
+        let history = HistoryStore::open(None).await.unwrap();
+
+        assert_eq!(
+            select(
+                &registry,
+                &history,
+                &default,
+                ChatId(1),
+                "gpu2",
+                &Translator::default()
+            )
+            .await
+            .unwrap(),
+            "Using backend gpu2."
+        );
+        assert_eq!(registry.selected(&history, ChatId(1)).await, "gpu2");
+        assert_eq!(registry.selected(&history, ChatId(2)).await, "default");
+        assert!(select(
+            &registry,
+            &history,
+            &default,
+            ChatId(1),
+            "comfy",
+            &Translator::default()
+        )
+        .await
+        .unwrap()
+        .contains("reset"));
+        assert_eq!(
+            select(
+                &registry,
+                &history,
+                &default,
+                ChatId(1),
+                "missing",
+                &Translator::default()
+            )
+            .await
+            .unwrap(),
+            "There is no backend named missing."
+        );
+        assert_eq!(registry.selected(&history, ChatId(1)).await, "comfy");
+        select(
+            &registry,
+            &history,
+            &default,
+            ChatId(1),
+            "default",
+            &Translator::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(history.chat_backend(ChatId(1)).await.unwrap(), None);
+
+        let buttons = backend_keyboard(&registry, "gpu2")
+            .inline_keyboard
+            .into_iter()
+            .flatten()
+            .map(|button| button.text)
+            .collect::<Vec<_>>();
+        assert_eq!(buttons, vec!["default", "✅ gpu2", "comfy"]);
+
+        assert!(
+            BackendRegistry::new(default.clone(), vec![("default".to_owned(), default)]).is_err()
+        );
+    }
+}
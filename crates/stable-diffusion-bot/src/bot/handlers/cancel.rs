@@ -0,0 +1,135 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use teloxide::{dispatching::UpdateHandler, macros::BotCommands, prelude::*};
+use tracing::instrument;
+
+use super::{filter_command, SendContext};
+use crate::bot::{i18n::Translator, progress, BackendHandles, BotState, DiffusionDialogue, State};
+
+/// BotCommands for cancelling generations.
+#[derive(BotCommands, Debug, Clone)]
+#[command(rename_rule = "lowercase", description = "Cancel commands")]
+pub(crate) enum CancelCommands {
+    /// Command to cancel the user's latest generation in the chat.
+    #[command(description = "cancel your image that's being generated.")]
+    Cancel,
+}
+
+/// Checks whether `update` asks to cancel a generation. These updates must be handled while
+/// the generation they cancel is still running.
+pub(crate) fn is_cancel_request(update: &Update) -> bool {
+    match &update.kind {
+        teloxide::types::UpdateKind::Message(msg) => msg
+            .text()
+            .and_then(|text| text.split_whitespace().next())
+            .is_some_and(|command| command == "/cancel" || command.starts_with("/cancel@")),
+        teloxide::types::UpdateKind::CallbackQuery(q) => q
+            .data
+            .as_deref()
+            .and_then(progress::parse_cancel_button)
+            .is_some(),
+        _ => false,
+    }
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "cancel"
+    )
+)]
+async fn handle_cancel_command(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    dialogue: DiffusionDialogue,
+    state: State,
+    msg: Message,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let Some(user) = msg.from() else {
+        return Ok(());
+    };
+    let cancelled = backends.jobs.cancel_latest(msg.chat.id, user.id);
+
+    // Also leave the settings or inpainting, in case the user was in the middle of them.
+    let mut left = None;
+    if let State::Ready {
+        bot_state,
+        txt2img,
+        img2img,
+    } = state
+    {
+        left = match bot_state {
+            BotState::Generate => None,
+            BotState::Inpaint { .. } => Some(translator.text("Stopped inpainting.")),
+            BotState::Img2ImgPending { .. } => Some(translator.text("Discarded the photo.")),
+            _ => Some(translator.text("Stopped changing settings.")),
+        };
+        if left.is_some() {
+            dialogue
+                .update(State::Ready {
+                    bot_state: BotState::default(),
+                    txt2img,
+                    img2img,
+                })
+                .await
+                .map_err(|e| anyhow!(e))?;
+        }
+    }
+
+    // A cancelled generation is reported in its progress message.
+    let text = match (cancelled, left) {
+        (true, _) => return Ok(()),
+        (false, Some(left)) => left,
+        (false, None) => translator.text("You have no images being generated in this chat."),
+    };
+    SendContext::of(&msg)
+        .send_message(&bot, text)
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "cancel"
+    )
+)]
+async fn handle_cancel_button(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    q: CallbackQuery,
+    id: u64,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let text = if backends.jobs.cancel(id, q.from.id) {
+        translator.text("Cancelling...")
+    } else {
+        translator.text(
+            "Only the person who requested this image can cancel it, and only while it's being \
+            generated.",
+        )
+    };
+    bot.answer_callback_query(q.id).text(text).await?;
+    Ok(())
+}
+
+pub fn cancel_schema() -> UpdateHandler<anyhow::Error> {
+    let command_handler = Update::filter_message()
+        .chain(filter_command::<CancelCommands>())
+        .endpoint(handle_cancel_command);
+
+    let callback_handler = Update::filter_callback_query()
+        .filter_map(|q: CallbackQuery| q.data.as_deref().and_then(progress::parse_cancel_button))
+        .endpoint(handle_cancel_button);
+
+    dptree::entry()
+        .branch(command_handler)
+        .branch(callback_handler)
+}
@@ -0,0 +1,187 @@
+use std::sync::Arc;
+
+use teloxide::{
+    dispatching::UpdateHandler,
+    prelude::*,
+    types::{ChatAction, InputFile},
+};
+use tracing::{info, instrument, warn};
+
+use super::{enforce_prompt_policy, SendContext};
+use crate::bot::{
+    censor,
+    config::{AuthConfig, UiConfig},
+    history::{self, HistoryStore},
+    i18n::Translator,
+    progress,
+    prompt_flags::PromptFlags,
+    quota, BackendHandles,
+};
+
+/// Message sent with guest images if none is configured.
+const DEFAULT_MESSAGE: &str = "You're trying this bot as a guest, so images are small and \
+    watermarked. Ask the bot's admin for full access.";
+
+/// Checks whether `msg` was sent by a guest: a user who isn't allowed, but isn't banned either,
+/// in a private chat with guest mode enabled.
+fn is_guest(auth: &AuthConfig, ui: &UiConfig, msg: &Message) -> bool {
+    let Some(user) = msg.from() else {
+        return false;
+    };
+    ui.guest.is_some()
+        && msg.chat.is_private()
+        && !auth.user_is_banned(user.id)
+        && !auth.chat_is_allowed(Some(msg.chat.id), Some(user.id))
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+async fn handle_guest_prompt(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
+    msg: Message,
+    prompt: String,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let (Some(guest), Some(user)) = (&ui.guest, msg.from()) else {
+        return Ok(());
+    };
+    let message = guest
+        .message()
+        .map_or_else(|| translator.text(DEFAULT_MESSAGE), str::to_owned);
+
+    let mut params = backends.txt2img_defaults(msg.chat.id);
+    guest.limit_params(params.as_mut());
+    if !enforce_prompt_policy(
+        &bot,
+        &ui.prompt_policy,
+        &translator,
+        &msg,
+        &prompt,
+        params.as_ref(),
+        None,
+        &PromptFlags::default(),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let now = history::now();
+    let usage = history
+        .usage_since(user.id, now - quota::MAX_PERIOD)
+        .await?;
+    if let Err(exceeded) = guest.quota().check(&usage, 1, now) {
+        info!("Guest generation exceeds quota: {:?}", exceeded);
+        SendContext::of(&msg)
+            .send_message(
+                &bot,
+                format!("{}\n\n{message}", exceeded.user_message(&translator)),
+            )
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    SendContext::of(&msg)
+        .send_chat_action(&bot, ChatAction::UploadPhoto)
+        .await?;
+    params.set_prompt(prompt);
+    let result = async {
+        let resp = backends.txt2img_api.txt2img(params.as_ref()).await?;
+        censor::check(&resp)?;
+        anyhow::Ok(resp)
+    };
+    let resp = match result.await {
+        Ok(resp) => resp,
+        Err(e) => {
+            warn!("Guest generation failed: {:?}", e);
+            let text = progress::failure_text(&e, &translator)
+                .unwrap_or_else(|| translator.text("Sorry, something went wrong."));
+            SendContext::of(&msg)
+                .send_message(&bot, text)
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let recorded = async {
+        history
+            .record_usage(user.id, resp.images.len() as u32)
+            .await?;
+        history
+            .delete_usage_before(history::now() - quota::MAX_PERIOD)
+            .await
+    };
+    if let Err(e) = recorded.await {
+        warn!("Failed to record guest image usage: {:?}", e);
+    }
+
+    for image in &resp.images {
+        SendContext::of(&msg)
+            .send_photo(&bot, InputFile::memory(guest.watermark(&image.data)?))
+            .caption(message.clone())
+            .reply_to_message_id(msg.id)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Handles prompts from guests, who get small, watermarked images instead of being rejected.
+/// Commands other than the unauthenticated ones aren't available to guests.
+pub fn guest_schema() -> UpdateHandler<anyhow::Error> {
+    Update::filter_message()
+        .filter(|auth: Arc<AuthConfig>, ui: Arc<UiConfig>, msg: Message| is_guest(&auth, &ui, &msg))
+        .filter_map(|msg: Message| {
+            msg.text()
+                .map(str::trim)
+                .filter(|text| !text.is_empty() && !text.starts_with('/'))
+                .map(str::to_owned)
+        })
+        .endpoint(handle_guest_prompt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::guest::{GuestConfig, GuestMode};
+
+    fn message(chat: serde_json::Value) -> Message {
+        serde_json::from_value(serde_json::json!({
+            "message_id": 1,
+            "date": 1634567890,
+            "from": {"id": 1, "is_bot": false, "first_name": "Guest"},
+            "chat": chat,
+            "text": "a cat",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_is_guest() {
+        let private =
+            message(serde_json::json!({"id": 1, "type": "private", "first_name": "Guest"}));
+        let group = message(serde_json::json!({"id": -1, "type": "group", "title": "Group"}));
+        let auth = AuthConfig::default();
+        let mut ui = UiConfig::default();
+        assert!(!is_guest(&auth, &ui, &private));
+
+        ui.guest = Some(GuestMode::new(GuestConfig::default()).unwrap());
+        assert!(is_guest(&auth, &ui, &private));
+        assert!(!is_guest(&auth, &ui, &group));
+
+        auth.allow_user(UserId(1));
+        assert!(!is_guest(&auth, &ui, &private));
+        auth.ban_user(UserId(1));
+        assert!(!is_guest(&auth, &ui, &private));
+    }
+}
@@ -0,0 +1,446 @@
+use std::sync::Arc;
+
+use sal_e_api::GenParams;
+use teloxide::{
+    dispatching::UpdateHandler,
+    dptree::case,
+    macros::BotCommands,
+    payloads::setters::*,
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+use tracing::{instrument, warn};
+
+use super::{filter_command, filter_map_settings, send_txt2img, SendContext, UiConfig};
+use crate::bot::{
+    backends::{BackendRegistry, DEFAULT_BACKEND},
+    history::{Generation, HistoryStore},
+    i18n::Translator,
+};
+
+/// Number of generations shown on each page of the history.
+const PAGE_SIZE: u32 = 5;
+
+/// Number of characters of each prompt shown in the history.
+const PROMPT_PREVIEW_LENGTH: usize = 60;
+
+const LIST_TEXT: &str = "Recent generations, newest first. Tap a number to generate it again \
+    with the same settings and seed. Generations from photos (🖼) can't be run again.";
+
+const PARTIAL_RERUN_TEXT: &str = "The backend of this generation is no longer available, so it \
+    runs on this chat's backend with the same seed and settings, but this chat's model.";
+
+/// BotCommands for browsing past generations.
+#[derive(BotCommands, Debug, Clone)]
+#[command(rename_rule = "lowercase", description = "History commands")]
+pub(crate) enum HistoryCommands {
+    /// Command to list the recent generations in the chat, to run them again.
+    #[command(description = "browse and rerun recent generations in this chat.")]
+    History,
+}
+
+/// Returns the start of `prompt`, shortened to fit on a line of the history.
+fn prompt_preview(prompt: &str) -> String {
+    let mut preview: String = prompt.chars().take(PROMPT_PREVIEW_LENGTH).collect();
+    if preview.len() < prompt.len() {
+        preview = format!("{}…", preview.trim_end());
+    }
+    preview
+}
+
+/// Returns the text listing page `page` of the history, which holds `generations`.
+fn page_text(generations: &[Generation], page: u32, translator: &Translator) -> String {
+    if generations.is_empty() {
+        return translator.text(if page == 0 {
+            "Nothing has been generated in this chat yet."
+        } else {
+            "There are no older generations."
+        });
+    }
+    let lines: Vec<_> = generations
+        .iter()
+        .zip(page * PAGE_SIZE + 1..)
+        .map(|(generation, number)| {
+            let mut details = vec![chrono::DateTime::from_timestamp(generation.created_at, 0)
+                .map(|time| time.format("%Y-%m-%d %H:%M UTC").to_string())
+                .unwrap_or_default()];
+            if let Some(seed) = generation.seed {
+                details.push(translator.format("seed {seed}", &[("seed", &seed.to_string())]));
+            }
+            if let Some(backend) = generation
+                .backend
+                .as_deref()
+                .filter(|backend| *backend != DEFAULT_BACKEND)
+            {
+                details.push(translator.format("on {backend}", &[("backend", backend)]));
+            }
+            let photo = if generation.params.is_some() {
+                ""
+            } else {
+                "🖼 "
+            };
+            format!(
+                "{number}. {photo}{} ({})",
+                prompt_preview(&generation.prompt),
+                details.join(", ")
+            )
+        })
+        .collect();
+    format!("{}\n\n{}", translator.text(LIST_TEXT), lines.join("\n"))
+}
+
+/// Builds an inline keyboard with a button to run each generation on page `page` again, and
+/// buttons to move between pages.
+fn history_keyboard(
+    generations: &[Generation],
+    page: u32,
+    has_older: bool,
+    translator: &Translator,
+) -> InlineKeyboardMarkup {
+    let mut keyboard = vec![generations
+        .iter()
+        .zip(page * PAGE_SIZE + 1..)
+        .filter(|(generation, _)| generation.params.is_some())
+        .map(|(generation, number)| {
+            InlineKeyboardButton::callback(
+                format!("🔁 {number}"),
+                format!("history/run/{}", generation.id),
+            )
+        })
+        .collect::<Vec<_>>()];
+    let mut navigation = Vec::new();
+    if page > 0 {
+        navigation.push(InlineKeyboardButton::callback(
+            translator.text("◀️ Newer"),
+            format!("history/page/{}", page - 1),
+        ));
+    }
+    if has_older {
+        navigation.push(InlineKeyboardButton::callback(
+            translator.text("Older ▶️"),
+            format!("history/page/{}", page + 1),
+        ));
+    }
+    keyboard.push(navigation);
+    InlineKeyboardMarkup::new(keyboard)
+}
+
+/// Returns the text and keyboard showing page `page` of the history of `chat_id`.
+async fn history_page(
+    history: &HistoryStore,
+    chat_id: ChatId,
+    page: u32,
+    translator: &Translator,
+) -> anyhow::Result<(String, InlineKeyboardMarkup)> {
+    // One more generation is fetched to tell whether there is an older page.
+    let mut generations = history
+        .recent(chat_id, page * PAGE_SIZE, PAGE_SIZE + 1)
+        .await?;
+    let has_older = generations.len() > PAGE_SIZE as usize;
+    generations.truncate(PAGE_SIZE as usize);
+    Ok((
+        page_text(&generations, page, translator),
+        history_keyboard(&generations, page, has_older, translator),
+    ))
+}
+
+/// Applies the settings of a past generation to `params` of another backend, leaving settings
+/// that are specific to a backend, such as the model or a ComfyUI workflow, as they are now. The seed of the generated image is
+/// used if it's known, so random seeds are repeated too.
+fn apply_generation(generation: &Generation, stored: &dyn GenParams, params: &mut dyn GenParams) {
+    if let Some(negative_prompt) = &generation.negative_prompt {
+        params.set_negative_prompt(negative_prompt.clone());
+    }
+    if let Some(seed) = generation.seed.or_else(|| stored.seed()) {
+        params.set_seed(seed);
+    }
+    if let Some(steps) = stored.steps() {
+        params.set_steps(steps);
+    }
+    if let Some(cfg) = stored.cfg() {
+        params.set_cfg(cfg);
+    }
+    if let Some(sampler) = stored.sampler() {
+        params.set_sampler(sampler);
+    }
+    if let (Some(width), Some(height)) = (stored.width(), stored.height()) {
+        params.set_width(width);
+        params.set_height(height);
+    }
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "history"
+    )
+)]
+async fn handle_history_command(
+    bot: Bot,
+    history: HistoryStore,
+    msg: Message,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let (text, keyboard) = history_page(&history, msg.chat.id, 0, &translator).await?;
+    // The list replies to the command, so generations run from it reply to the user.
+    SendContext::of(&msg)
+        .send_message(&bot, text)
+        .reply_markup(keyboard)
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "history"
+    )
+)]
+async fn handle_page_button(
+    bot: Bot,
+    history: HistoryStore,
+    q: CallbackQuery,
+    page: u32,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let Some(message) = q.message else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, this message is no longer available."))
+            .await?;
+        return Ok(());
+    };
+    if let Err(e) = bot.answer_callback_query(q.id).await {
+        warn!("Failed to answer history page callback query: {}", e)
+    }
+    let (text, keyboard) = history_page(&history, message.chat.id, page, &translator).await?;
+    bot.edit_message_text(message.chat.id, message.id, text)
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "history"
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+async fn handle_run_button(
+    bot: Bot,
+    registry: Arc<BackendRegistry>,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
+    (txt2img, _): (Box<dyn GenParams>, Box<dyn GenParams>),
+    q: CallbackQuery,
+    id: i64,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let Some(parent) = q
+        .message
+        .as_ref()
+        .and_then(|message| message.reply_to_message())
+        .cloned()
+    else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, this message is no longer available."))
+            .await?;
+        return Ok(());
+    };
+    let generation = history.find(parent.chat.id, id).await?;
+    let Some((generation, stored)) = generation.and_then(|generation| {
+        let stored: Box<dyn GenParams> =
+            serde_json::from_str(generation.params.as_deref()?).ok()?;
+        Some((generation, stored))
+    }) else {
+        bot.answer_callback_query(q.id)
+            .text(translator.text("Sorry, this generation can't be run again."))
+            .await?;
+        return Ok(());
+    };
+
+    // The settings of the generation are used without saving them.
+    let recorded = registry.get(generation.backend.as_deref().unwrap_or(DEFAULT_BACKEND));
+    let (backends, mut params, notice) = match recorded {
+        Some(backends) => {
+            let mut params = stored;
+            if let Some(seed) = generation.seed {
+                params.set_seed(seed);
+            }
+            (backends.clone(), params, "Running this generation again...")
+        }
+        None => {
+            let mut params = txt2img;
+            apply_generation(&generation, stored.as_ref(), params.as_mut());
+            (
+                registry.for_chat(&history, parent.chat.id).await,
+                params,
+                PARTIAL_RERUN_TEXT,
+            )
+        }
+    };
+    if let Err(e) = bot
+        .answer_callback_query(q.id)
+        .text(translator.text(notice))
+        .await
+    {
+        warn!("Failed to answer history rerun callback query: {}", e)
+    }
+    send_txt2img(
+        &bot,
+        &backends,
+        &ui,
+        &history,
+        params.as_mut(),
+        &parent,
+        generation.prompt,
+    )
+    .await?;
+    Ok(())
+}
+
+pub fn history_schema() -> UpdateHandler<anyhow::Error> {
+    let command_handler = Update::filter_message()
+        .chain(filter_command::<HistoryCommands>())
+        .branch(case![HistoryCommands::History].endpoint(handle_history_command));
+
+    let callback_handler = Update::filter_callback_query()
+        .branch(
+            dptree::filter_map(|q: CallbackQuery| {
+                q.data?.strip_prefix("history/page/")?.parse::<u32>().ok()
+            })
+            .endpoint(handle_page_button),
+        )
+        .branch(
+            dptree::filter_map(|q: CallbackQuery| {
+                q.data?.strip_prefix("history/run/")?.parse::<i64>().ok()
+            })
+            .chain(filter_map_settings())
+            .endpoint(handle_run_button),
+        );
+
+    dptree::entry()
+        .branch(command_handler)
+        .branch(callback_handler)
+}
+
+#[cfg(test)]
+mod tests {
+    use sal_e_api::Txt2ImgParams;
+    use teloxide::types::MessageId;
+
+    use super::*;
+
+    fn generation(id: i64, prompt: &str, params: Option<&str>) -> Generation {
+        Generation {
+            id,
+            chat_id: ChatId(1),
+            user_id: None,
+            source_message_id: MessageId(1),
+            reply_message_ids: vec![MessageId(2)],
+            prompt: prompt.to_owned(),
+            created_at: 0,
+            purged_at: None,
+            seed: Some(42),
+            details: None,
+            fingerprint: None,
+            negative_prompt: Some("blurry".to_owned()),
+            params: params.map(str::to_owned),
+            backend: Some("sdxl".to_owned()),
+        }
+    }
+
+    fn button_data(keyboard: InlineKeyboardMarkup) -> Vec<String> {
+        keyboard
+            .inline_keyboard
+            .into_iter()
+            .flatten()
+            .filter_map(|button| match button.kind {
+                teloxide::types::InlineKeyboardButtonKind::CallbackData(data) => Some(data),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_page_text() {
+        assert_eq!(
+            page_text(&[], 0, &Translator::default()),
+            "Nothing has been generated in this chat yet."
+        );
+        let generations = [
+            generation(7, "a castle", Some("{}")),
+            generation(6, &"a very long prompt ".repeat(10), None),
+        ];
+        let text = page_text(&generations, 1, &Translator::default());
+        let lines: Vec<_> = text.lines().skip(2).collect();
+        assert_eq!(
+            lines[0],
+            "6. a castle (1970-01-01 00:00 UTC, seed 42, on sdxl)"
+        );
+        assert!(lines[1].starts_with("7. 🖼 a very long prompt"));
+        assert!(lines[1].contains("… (1970"));
+
+        let translations =
+            std::sync::Arc::new(crate::bot::i18n::Translations::load(None, None).unwrap());
+        let german = Translator::new(translations, Some("de".to_owned()));
+        let text = page_text(&generations, 1, &german);
+        assert!(text.starts_with(&german.text(LIST_TEXT)));
+        assert_ne!(german.text(LIST_TEXT), LIST_TEXT);
+        assert!(text.contains("Seed 42, auf sdxl"));
+    }
+
+    #[test]
+    fn test_history_keyboard() {
+        let generations = [
+            generation(7, "a castle", Some("{}")),
+            generation(6, "a photo", None),
+        ];
+        assert_eq!(
+            button_data(history_keyboard(
+                &generations,
+                0,
+                true,
+                &Translator::default()
+            )),
+            ["history/run/7", "history/page/1"]
+        );
+        assert_eq!(
+            button_data(history_keyboard(
+                &generations,
+                2,
+                false,
+                &Translator::default()
+            )),
+            ["history/run/7", "history/page/1"]
+        );
+    }
+
+    #[test]
+    fn test_apply_generation() {
+        let mut stored = Txt2ImgParams::default();
+        stored.set_steps(30);
+        stored.set_cfg(5.0);
+        stored.set_width(768);
+        stored.set_height(512);
+        stored.set_seed(-1);
+
+        let mut params = Txt2ImgParams::default();
+        params.set_steps(20);
+        apply_generation(&generation(1, "a castle", Some("{}")), &stored, &mut params);
+        assert_eq!(params.steps(), Some(30));
+        assert_eq!(params.cfg(), Some(5.0));
+        assert_eq!((params.width(), params.height()), (Some(768), Some(512)));
+        assert_eq!(params.seed(), Some(42));
+        assert_eq!(params.negative_prompt(), Some("blurry".to_owned()));
+    }
+}
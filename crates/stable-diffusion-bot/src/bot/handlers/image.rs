@@ -1,5 +1,10 @@
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+
 use anyhow::{anyhow, Context};
-use sal_e_api::{GenParams, ImageParams, Response};
+use sal_e_api::{is_random_seed, resolve_seed, GenParams, ImageParams, Response, RANDOM_SEED};
 use teloxide::{
     dispatching::UpdateHandler,
     dptree::case,
@@ -7,22 +12,60 @@ use teloxide::{
     payloads::setters::*,
     prelude::*,
     types::{
-        ChatAction, InlineKeyboardButton, InlineKeyboardMarkup, InputFile, InputMedia,
-        InputMediaPhoto, Me, MessageId, PhotoSize,
+        InlineKeyboardButton, InlineKeyboardButtonKind, InlineKeyboardMarkup, InputFile,
+        InputMedia, InputMediaPhoto, Me, MessageId, PhotoSize,
     },
     utils::command::BotCommands as _,
 };
-use tracing::{info, instrument, warn};
+use tracing::{info, instrument, warn, Instrument};
 
 use crate::{
-    bot::{helpers, State},
+    bot::{
+        ab::{self, AbTally, AbVote},
+        archive, censor,
+        dashboard::GenerationKind,
+        denoise_presets,
+        group_mode::{addressed_prompt, strip_mention},
+        helpers,
+        history::{self, HistoryStore, NewGeneration},
+        i18n::{self, Translator},
+        infotext::Infotext,
+        policy::PromptPolicy,
+        presets::{self, with_negative_presets},
+        progress::{with_progress, ProgressTracker, RequestGuard},
+        prompt_flags::{self, PromptFlags},
+        quiet_hours::{self, QuietHoursMode},
+        quota,
+        random::RandomChoices,
+        settings_store::{self, SettingsKind},
+        snippets,
+        styles::{self, with_style, Style},
+        unknown_commands, variables, CaptionField, CaptionMode, CaptionTemplate,
+        StableDiffusionBot, State,
+    },
     BotState,
 };
 
 use super::{
-    filter_command, filter_map_bot_state, filter_map_settings, ConfigParameters, DiffusionDialogue,
+    filter_command, filter_map_bot_state, filter_map_settings, AuthConfig, BackendHandles,
+    DiffusionDialogue, SendContext, UiConfig,
 };
 
+/// How long after a generation an edit to its prompt offers to regenerate.
+const REVISION_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// How strongly variations differ from the original image, unless the user set a strength.
+const VARIATION_STRENGTH: f32 = 0.2;
+
+/// Longest caption Telegram accepts, in characters.
+const CAPTION_LIMIT: usize = 1024;
+
+/// How much of a prompt is shown in the caption when the full prompt is sent as a file.
+const TRUNCATED_PROMPT_LENGTH: usize = 300;
+
+/// Name of the text file with the full prompt and settings of an image.
+const PROMPT_FILE_NAME: &str = "prompt.txt";
+
 /// BotCommands for generating images.
 #[derive(BotCommands, Debug, Clone)]
 #[command(rename_rule = "lowercase", description = "Image generation commands")]
@@ -38,9 +81,42 @@ pub(crate) enum GenCommands {
     Generate(String),
 }
 
+/// BotCommands for inpainting.
+#[derive(BotCommands, Debug, Clone)]
+#[command(rename_rule = "lowercase", description = "Inpainting commands")]
+pub(crate) enum InpaintCommands {
+    /// Command to inpaint the image that a photo of a mask replies to.
+    #[command(
+        description = "reply to an image with a photo of a mask to regenerate its white area."
+    )]
+    Mask(String),
+}
+
+/// BotCommands for generating with random settings.
+#[derive(BotCommands, Debug, Clone)]
+#[command(rename_rule = "lowercase", description = "Random commands")]
+pub(crate) enum RandomCommands {
+    /// Command to generate an image with a random style, sampler and CFG scale.
+    #[command(description = "generate an image with a random style, sampler and CFG scale.")]
+    Random(String),
+}
+
+/// BotCommands for comparing prompts.
+#[derive(BotCommands, Debug, Clone)]
+#[command(rename_rule = "lowercase", description = "A/B test commands")]
+pub(crate) enum AbCommands {
+    /// Command to generate two prompts with the same seed and settings, and vote on the better one.
+    #[command(
+        description = "compare two prompts with the same seed and settings: /ab \"<prompt A>\" \"<prompt B>\""
+    )]
+    Ab(String),
+}
+
 enum Photo {
     Single(Vec<u8>),
     Album(Vec<Vec<u8>>),
+    /// Zip files of a large batch.
+    Archive(Vec<Vec<u8>>),
 }
 
 impl Photo {
@@ -66,14 +142,21 @@ struct Reply {
     caption: String,
     images: Photo,
     source: MessageId,
-    seed: i64,
+    seed: SeedButton,
+    upscale_scales: Vec<u32>,
+    /// Callback data of the button that keeps the settings of a `/random` generation.
+    keep: Option<String>,
+    /// The full caption, if the caption only shows the prompt.
+    details: Option<String>,
+    /// The full prompt and settings, sent as a text file when they don't fit in the caption.
+    prompt_file: Option<String>,
 }
 
 impl Reply {
     pub fn new(
         caption: String,
         images: Vec<Vec<u8>>,
-        seed: i64,
+        seed: SeedButton,
         source: MessageId,
     ) -> anyhow::Result<Self> {
         let images = Photo::album(images)?;
@@ -82,18 +165,111 @@ impl Reply {
             images,
             source,
             seed,
+            upscale_scales: Vec::new(),
+            keep: None,
+            details: None,
+            prompt_file: None,
         })
     }
 
-    pub async fn send(self, bot: &Bot, chat_id: ChatId) -> anyhow::Result<()> {
+    /// Constructs a reply that sends a large batch as zip files.
+    pub fn archive(
+        caption: String,
+        parts: Vec<Vec<u8>>,
+        seed: SeedButton,
+        source: MessageId,
+    ) -> Self {
+        Self {
+            caption,
+            images: Photo::Archive(parts),
+            source,
+            seed,
+            upscale_scales: Vec::new(),
+            keep: None,
+            details: None,
+            prompt_file: None,
+        }
+    }
+
+    /// Offers to upscale the image by `scales`. Albums can't be upscaled, since their buttons
+    /// are on a separate message.
+    pub fn with_upscale(mut self, scales: &[u32]) -> Self {
+        self.upscale_scales = scales.to_vec();
+        self
+    }
+
+    /// Offers to keep the settings of a `/random` generation with a button carrying `data`.
+    pub fn with_keep(mut self, data: Option<String>) -> Self {
+        self.keep = data;
+        self
+    }
+
+    /// Captions the images with only the prompt, offering a button that reveals `details`.
+    pub fn with_details(mut self, details: Option<String>) -> Self {
+        self.details = details;
+        self
+    }
+
+    /// Sends `text`, the full prompt and settings, as a text file next to the images.
+    pub fn with_prompt_file(mut self, text: Option<String>) -> Self {
+        self.prompt_file = text;
+        self
+    }
+
+    /// Sends the prompt file, if any, in reply to the source message.
+    async fn send_prompt_file(
+        bot: &Bot,
+        to: SendContext,
+        text: Option<String>,
+        source: MessageId,
+    ) -> anyhow::Result<Option<MessageId>> {
+        let Some(text) = text else {
+            return Ok(None);
+        };
+        let message = to
+            .send_document(
+                bot,
+                InputFile::memory(text.into_bytes()).file_name(PROMPT_FILE_NAME),
+            )
+            .reply_to_message_id(source)
+            .await?;
+        Ok(Some(message.id))
+    }
+
+    /// Sends the reply, returning the ids of the sent messages.
+    pub async fn send(
+        self,
+        bot: &Bot,
+        to: SendContext,
+        translator: &Translator,
+    ) -> anyhow::Result<Vec<MessageId>> {
+        let details = self.details.is_some();
+        let single_keyboard = with_details_button(
+            with_keep_button(
+                keyboard(self.seed, &self.upscale_scales, translator),
+                self.keep.clone(),
+                translator,
+            ),
+            details,
+            translator,
+        );
+        let keyboard = with_details_button(
+            with_keep_button(keyboard(self.seed, &[], translator), self.keep, translator),
+            details,
+            translator,
+        );
         match self.images {
             Photo::Single(image) => {
-                bot.send_photo(chat_id, InputFile::memory(image))
+                let message = to
+                    .send_photo(bot, InputFile::memory(image))
                     .parse_mode(teloxide::types::ParseMode::MarkdownV2)
                     .caption(self.caption)
-                    .reply_markup(keyboard(self.seed))
+                    .reply_markup(single_keyboard)
                     .reply_to_message_id(self.source)
                     .await?;
+                let prompt_file =
+                    Self::send_prompt_file(bot, to, self.prompt_file, self.source).await?;
+                Ok([message.id].into_iter().chain(prompt_file).collect())
             }
             Photo::Album(images) => {
                 let mut caption = Some(self.caption);
@@ -104,59 +280,181 @@ impl Reply {
                     InputMedia::Photo(media)
                 });
 
-                bot.send_media_group(chat_id, input_media)
+                let messages = to
+                    .send_media_group(bot, input_media)
                     .reply_to_message_id(self.source)
                     .await?;
-                bot.send_message(
-                    chat_id,
-                    "What would you like to do? Select below, or enter a new prompt.",
-                )
-                .reply_markup(keyboard(self.seed))
-                .reply_to_message_id(self.source)
-                .await?;
+                let prompt_file =
+                    Self::send_prompt_file(bot, to, self.prompt_file, self.source).await?;
+                let keyboard_message = to
+                    .send_message(
+                        bot,
+                        translator.text(
+                            "What would you like to do? Select below, or enter a new prompt.",
+                        ),
+                    )
+                    .reply_markup(keyboard)
+                    .reply_to_message_id(self.source)
+                    .await?;
+                Ok(messages
+                    .iter()
+                    .map(|message| message.id)
+                    .chain(prompt_file)
+                    .chain([keyboard_message.id])
+                    .collect())
+            }
+            Photo::Archive(parts) => {
+                let count = parts.len();
+                let mut caption = Some(self.caption);
+                let mut message_ids = Vec::with_capacity(count);
+                for (index, part) in parts.into_iter().enumerate() {
+                    let file_name = if count == 1 {
+                        "images.zip".to_owned()
+                    } else {
+                        format!("images-{}-of-{count}.zip", index + 1)
+                    };
+                    let mut request = to
+                        .send_document(bot, InputFile::memory(part).file_name(file_name))
+                        .reply_to_message_id(self.source);
+                    if let Some(caption) = caption.take() {
+                        request = request
+                            .caption(caption)
+                            .parse_mode(teloxide::types::ParseMode::MarkdownV2);
+                    }
+                    // The buttons go on the last part, below all of the files.
+                    if index + 1 == count {
+                        request = request.reply_markup(keyboard.clone());
+                    }
+                    message_ids.push(request.await?.id);
+                }
+                message_ids
+                    .extend(Self::send_prompt_file(bot, to, self.prompt_file, self.source).await?);
+                Ok(message_ids)
             }
         }
-
-        Ok(())
     }
 }
 
-struct MessageText(String);
+pub(crate) struct MessageText(pub String);
+
+impl MessageText {
+    /// Builds the caption of an image generated from `prompt` with the settings in `infotxt`.
+    /// `size` is the size of the image that was actually generated, if known, which is shown
+    /// instead of the requested size.
+    pub fn from_template(
+        template: &CaptionTemplate,
+        prompt: &str,
+        infotxt: &dyn ImageParams,
+        size: Option<(u32, u32)>,
+    ) -> Self {
+        let size = size.or_else(|| infotxt.width().zip(infotxt.height()));
+        Self(template.render(|field| {
+            match field {
+                CaptionField::Prompt => Some(prompt.to_owned()),
+                CaptionField::NegativePrompt => infotxt
+                    .negative_prompt()
+                    .filter(|negative_prompt| !negative_prompt.trim().is_empty()),
+                CaptionField::Steps => infotxt.steps().map(|steps| steps.to_string()),
+                CaptionField::Sampler => infotxt.sampler(),
+                CaptionField::Cfg => infotxt.cfg().map(|cfg| cfg.to_string()),
+                CaptionField::Seed => infotxt.seed().map(|seed| seed.to_string()),
+                CaptionField::Width => size.map(|(width, _)| width.to_string()),
+                CaptionField::Height => size.map(|(_, height)| height.to_string()),
+                CaptionField::Model => infotxt.model(),
+                CaptionField::Vae => infotxt.vae(),
+                CaptionField::Denoising => {
+                    infotxt.denoising().map(|denoising| denoising.to_string())
+                }
+            }
+        }))
+    }
+
+    pub fn new_with_image_params(
+        prompt: &str,
+        infotxt: &dyn ImageParams,
+        size: Option<(u32, u32)>,
+    ) -> Self {
+        Self::from_template(&CaptionTemplate::default(), prompt, infotxt, size)
+    }
+
+    /// Builds the caption of the images in `response` from `template`.
+    pub fn for_response(response: &Response, template: &CaptionTemplate) -> anyhow::Result<Self> {
+        let prompt = if let Some(prompt) = response.params.prompt() {
+            prompt
+        } else {
+            return Err(anyhow!("No prompt in image info response"));
+        };
+        // Prefer the size of the actual output, which may differ from the requested size if
+        // the workflow resized or upscaled the image.
+        let size = response.image_dimensions().into_iter().next().flatten();
+        Ok(Self::from_template(
+            template,
+            prompt.as_str(),
+            response.params.as_ref(),
+            size,
+        ))
+    }
+}
 
 impl MessageText {
-    pub fn new_with_image_params(prompt: &str, infotxt: &dyn ImageParams) -> Self {
+    /// Appends a description of the image, e.g. from a vision model.
+    pub fn with_description(self, description: &str) -> Self {
+        use teloxide::utils::markdown::escape;
+
+        Self(format!("{}\n\n_{}_", self.0, escape(description)))
+    }
+
+    /// Returns a caption showing only `prompt`.
+    pub fn prompt_only(prompt: &str) -> Self {
+        use teloxide::utils::markdown::escape;
+
+        Self(format!("`{}`", escape(prompt)))
+    }
+
+    /// Returns a caption showing the start of `prompt`, for prompts whose full caption is too
+    /// long and is sent as a text file instead.
+    pub fn truncated_prompt(prompt: &str, translator: &Translator) -> Self {
         use teloxide::utils::markdown::escape;
 
+        let start: String = prompt.chars().take(TRUNCATED_PROMPT_LENGTH).collect();
         Self(format!(
-            "`{}`\n\n{}",
-            escape(prompt),
-            [
-                infotxt
-                    .negative_prompt()
-                    .as_ref()
-                    .and_then(|s| (!s.trim().is_empty()).then(|| escape(s)))
-                    .map(|s| format!("Negative prompt: `{s}`")),
-                infotxt.steps().map(|s| format!("Steps: `{s}`")),
-                infotxt
-                    .sampler()
-                    .as_ref()
-                    .map(|s| format!("Sampler: `{s}`")),
-                infotxt.cfg().map(|s| format!("CFG scale: `{s}`")),
-                infotxt.seed().map(|s| format!("Seed: `{s}`")),
-                infotxt
-                    .width()
-                    .and_then(|w| infotxt.height().map(|h| format!("Size: `{w}×{h}`"))),
-                infotxt.model().as_ref().map(|s| format!("Model: `{s}`")),
-                infotxt
-                    .denoising()
-                    .map(|s| format!("Denoising strength: `{s}`")),
-            ]
-            .into_iter()
-            .flatten()
-            .collect::<Vec<_>>()
-            .join("\n")
+            "`{}…`\n\n_{}_",
+            escape(start.trim_end()),
+            escape(&translator.text("The full prompt and settings are in the attached file."))
         ))
     }
+
+    /// Checks whether the caption fits in a Telegram caption. The formatting characters are
+    /// counted too, so long captions may be sent as files a bit early.
+    pub fn fits_in_caption(&self) -> bool {
+        self.0.chars().count() <= CAPTION_LIMIT
+    }
+
+    /// Appends the settings chosen by `/random`.
+    pub fn with_random_label(self, label: &str) -> Self {
+        use teloxide::utils::markdown::escape;
+
+        Self(format!("{}\n🎲 {}", self.0, escape(label)))
+    }
+}
+
+/// Appends a description of the first image in `resp` to `caption`, if image descriptions are
+/// enabled and one could be produced in time.
+async fn describe_response(
+    backends: &BackendHandles,
+    caption: MessageText,
+    resp: &Response,
+) -> MessageText {
+    let Some(describer) = &backends.describer else {
+        return caption;
+    };
+    let Some(image) = resp.images.first() else {
+        return caption;
+    };
+    match describer.describe(image).await {
+        Some(description) => caption.with_description(&description),
+        None => caption,
+    }
 }
 
 impl TryFrom<&dyn ImageParams> for MessageText {
@@ -168,144 +466,2250 @@ impl TryFrom<&dyn ImageParams> for MessageText {
         } else {
             return Err(anyhow!("No prompt in image info response"));
         };
-        Ok(Self::new_with_image_params(prompt.as_str(), params))
+        Ok(Self::new_with_image_params(prompt.as_str(), params, None))
     }
 }
 
-impl TryFrom<Response> for MessageText {
-    type Error = anyhow::Error;
+/// Builds the reply to `resp`, sending the images as zip files if the batch is large enough.
+/// With compact captions, `caption` is only shown when the user asks for the details.
+fn build_reply(
+    backends: &BackendHandles,
+    ui: &UiConfig,
+    caption: MessageText,
+    resp: &Response,
+    source: MessageId,
+    translator: &Translator,
+) -> anyhow::Result<Reply> {
+    let prompt = resp.params.prompt().unwrap_or_default();
+    let (caption, details, prompt_file) = match ui.captions {
+        _ if !caption.fits_in_caption() => {
+            let size = resp.image_dimensions().into_iter().next().flatten();
+            let infotext = Infotext::from_image_params(&prompt, resp.params.as_ref(), size);
+            (
+                MessageText::truncated_prompt(&prompt, translator),
+                None,
+                Some(infotext.to_string()),
+            )
+        }
+        CaptionMode::Full => (caption, None, None),
+        CaptionMode::Compact => (MessageText::prompt_only(&prompt), Some(caption.0), None),
+    };
+    let seed = SeedButton::for_response(resp);
+    if let Some(zip) = ui
+        .zip
+        .as_ref()
+        .filter(|zip| zip.applies_to(resp.images.len()))
+    {
+        let parts = archive::zip_response(resp, zip);
+        return Ok(Reply::archive(caption.0, parts, seed, source)
+            .with_details(details)
+            .with_prompt_file(prompt_file));
+    }
+    Ok(Reply::new(
+        caption.0,
+        resp.images.iter().map(|image| image.data.clone()).collect(),
+        seed,
+        source,
+    )
+    .context("Failed to create response!")?
+    .with_upscale(upscale_scales(backends))
+    .with_details(details)
+    .with_prompt_file(prompt_file))
+}
+
+/// Returns a copy of `params` with `style` applied, then the `flags` of the prompt, which win
+/// over the style. These are the settings a generation runs with, apart from negative presets.
+fn generation_params<'a>(
+    params: &(dyn GenParams + 'a),
+    style: Option<&Style>,
+    flags: &PromptFlags,
+) -> Box<dyn GenParams + 'a> {
+    let mut params = with_style(params, style);
+    flags.apply(params.as_mut());
+    params
+}
+
+/// Returns the fingerprint of generating `prompt` with `params`, `style`, `flags` and
+/// `negative_presets`, as sent to the backend. Deduplication, the history and the dashboard all
+/// use it, so they agree on which requests are the same.
+fn request_fingerprint(
+    params: &dyn GenParams,
+    prompt: &str,
+    style: Option<&Style>,
+    flags: &PromptFlags,
+    negative_presets: &[String],
+) -> String {
+    let mut params = dyn_clone::clone_box(params);
+    params.set_prompt(prompt.to_owned());
+    let params = generation_params(params.as_ref(), style, flags);
+    with_negative_presets(params.as_ref(), negative_presets).fingerprint()
+}
+
+/// Claims the request identified by `key` in the chat of `msg` while it generates, so the same
+/// request sent again in the meantime isn't generated twice. Replies to such duplicates and
+/// returns `None` for them.
+async fn claim_request(
+    bot: &Bot,
+    backends: &BackendHandles,
+    translator: &Translator,
+    msg: &Message,
+    key: &str,
+) -> anyhow::Result<Option<RequestGuard>> {
+    if let Some(guard) = backends.jobs.claim(msg.chat.id, key) {
+        return Ok(Some(guard));
+    }
+    info!("Skipping a request that's already in flight");
+    backends
+        .metrics
+        .duplicate_requests
+        .fetch_add(1, Ordering::Relaxed);
+    SendContext::of(msg)
+        .send_message(
+            bot,
+            translator.text("The same request is already being generated."),
+        )
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(None)
+}
+
+/// Checks the prompt and negative prompt, with `style` and `flags` applied, against the prompt
+/// policy, logging violations to the audit log and replying to the user. Returns whether
+/// generation may proceed.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn enforce_prompt_policy(
+    bot: &Bot,
+    policy: &PromptPolicy,
+    translator: &Translator,
+    msg: &Message,
+    prompt: &str,
+    params: &dyn GenParams,
+    style: Option<&Style>,
+    flags: &PromptFlags,
+) -> anyhow::Result<bool> {
+    let mut params = dyn_clone::clone_box(params);
+    params.set_prompt(prompt.to_owned());
+    let params = generation_params(params.as_ref(), style, flags);
+    let Err(violation) = policy.check_params(params.as_ref()) else {
+        return Ok(true);
+    };
+    warn!(
+        target: "audit",
+        user = ?msg.from().map(|user| user.id),
+        chat = %msg.chat.id,
+        %violation,
+        "Rejected prompt"
+    );
+    SendContext::of(msg)
+        .send_message(bot, violation.user_message(translator))
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(false)
+}
+
+/// When to run a generation, given the quiet hours of the chat it was requested in.
+enum Schedule {
+    Now,
+    /// Run the generation after waiting this long.
+    Deferred(Duration),
+    Rejected,
+}
+
+/// Checks whether a generation requested by `msg` falls in quiet hours. If it does, replies to
+/// the user with when generating resumes. Generations are only deferred if `can_defer` is set
+/// and quiet hours are configured to defer them; otherwise they're rejected.
+async fn schedule_generation(
+    bot: &Bot,
+    ui: &UiConfig,
+    translator: &Translator,
+    msg: &Message,
+    can_defer: bool,
+) -> anyhow::Result<Schedule> {
+    let Some(config) = &ui.quiet_hours else {
+        return Ok(Schedule::Now);
+    };
+    let now = chrono::Utc::now();
+    let Some(until) = config.quiet_until(msg.chat.id, now) else {
+        return Ok(Schedule::Now);
+    };
+    let resumes = quiet_hours::format_time(&until);
+    let (text, schedule) = if can_defer && config.mode == QuietHoursMode::Defer {
+        (
+            "🌙 It's quiet hours, so your image will be generated at {time}.",
+            Schedule::Deferred((until.with_timezone(&chrono::Utc) - now).to_std()?),
+        )
+    } else {
+        (
+            "🌙 It's quiet hours. Generating resumes at {time}.",
+            Schedule::Rejected,
+        )
+    };
+    let text = translator.format(text, &[("time", &resumes)]);
+    info!("Generation requested during quiet hours, until {until}");
+    SendContext::of(msg)
+        .send_message(bot, text)
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(schedule)
+}
+
+/// Checks whether the user who sent `msg` has quota left to generate the images requested by
+/// `params`. If they don't, replies with when their quota resets. Returns whether generation may
+/// proceed.
+async fn enforce_quota(
+    bot: &Bot,
+    ui: &UiConfig,
+    history: &HistoryStore,
+    translator: &Translator,
+    msg: &Message,
+    params: &dyn GenParams,
+) -> anyhow::Result<bool> {
+    let (Some(config), Some(user)) = (&ui.quota, msg.from()) else {
+        return Ok(true);
+    };
+    let now = history::now();
+    let usage = history
+        .usage_since(user.id, now - quota::MAX_PERIOD)
+        .await?;
+    let requested = params.count().unwrap_or(1) * params.batch_size().unwrap_or(1);
+    let Err(exceeded) = config.check(&usage, requested, now) else {
+        return Ok(true);
+    };
+    info!("Generation exceeds quota: {:?}", exceeded);
+    SendContext::of(msg)
+        .send_message(bot, exceeded.user_message(translator))
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(false)
+}
+
+/// Records the images generated for the user who sent `msg` against their quota. Failures are
+/// logged, since the images have already been generated.
+async fn record_usage(ui: &UiConfig, history: &HistoryStore, msg: &Message, resp: &Response) {
+    let (Some(_), Some(user)) = (&ui.quota, msg.from()) else {
+        return;
+    };
+    let result = async {
+        history
+            .record_usage(user.id, resp.images.len() as u32)
+            .await?;
+        history
+            .delete_usage_before(history::now() - quota::MAX_PERIOD)
+            .await
+    };
+    if let Err(e) = result.await {
+        warn!("Failed to record image usage: {:?}", e);
+    }
+}
+
+/// Expands the snippets of the user who sent `msg` in `prompt`, then the variables of the chat
+/// it was sent in. If the prompt references an unknown snippet, replies to the user and returns
+/// `None`.
+async fn expand_prompt(
+    bot: &Bot,
+    history: &HistoryStore,
+    msg: &Message,
+    translator: &Translator,
+    prompt: String,
+) -> anyhow::Result<Option<String>> {
+    let prompt = if snippets::has_references(&prompt) {
+        let user_snippets = match msg.from() {
+            Some(user) => history.snippets(user.id).await?.into_iter().collect(),
+            None => Default::default(),
+        };
+        snippets::expand(&prompt, &user_snippets)
+    } else {
+        Ok(prompt)
+    };
+    match prompt {
+        Ok(prompt) if prompt.contains('$') => {
+            let chat_variables = history
+                .chat_variables(msg.chat.id)
+                .await?
+                .into_iter()
+                .collect();
+            Ok(Some(variables::expand(&prompt, &chat_variables)))
+        }
+        Ok(prompt) => Ok(Some(prompt)),
+        Err(name) => {
+            SendContext::of(msg)
+                .send_message(
+                    bot,
+                    translator.format(
+                        "You have no snippet named {name}. See /snippet list.",
+                        &[("name", &name)],
+                    ),
+                )
+                .reply_to_message_id(msg.id)
+                .await?;
+            Ok(None)
+        }
+    }
+}
 
-    fn try_from(response: Response) -> Result<Self, Self::Error> {
-        Self::try_from(response.params.as_ref())
+/// Splits the flags off the end of `text`, replying to the user if one of them is invalid.
+/// Every txt2img generation parses its prompt with this, so prompts that are sent again, like
+/// reruns and variations, keep their flags.
+async fn parse_prompt_flags(
+    bot: &Bot,
+    msg: &Message,
+    translator: &Translator,
+    text: &str,
+) -> anyhow::Result<Option<(String, PromptFlags)>> {
+    match prompt_flags::parse(text) {
+        Ok(parsed) => Ok(Some(parsed)),
+        Err(invalid) => {
+            SendContext::of(msg)
+                .send_message(
+                    bot,
+                    translator.format(
+                        "Sorry, \"{value}\" isn't a valid value for --{flag}.",
+                        &[("value", &invalid.value), ("flag", &invalid.flag)],
+                    ),
+                )
+                .reply_to_message_id(msg.id)
+                .await?;
+            Ok(None)
+        }
     }
 }
 
+/// Returns the parameters to retry a generation with if blank image checks are enabled and
+/// `resp` contains a blank image. Generations are only retried once.
+fn retry_params<'a>(
+    backends: &BackendHandles,
+    params: &(dyn GenParams + 'a),
+    resp: &Response,
+) -> Option<Box<dyn GenParams + 'a>> {
+    backends.blank_check.as_ref()?.retry_params(params, resp)
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn do_img2img(
     bot: &Bot,
-    cfg: &ConfigParameters,
+    backends: &BackendHandles,
     img2img: &mut Box<dyn GenParams>,
     msg: &Message,
+    translator: &Translator,
     photo: Vec<PhotoSize>,
     prompt: String,
+    style: Option<&Style>,
+    negative_presets: &[String],
+    progress: &ProgressTracker,
 ) -> anyhow::Result<Response> {
     img2img.set_prompt(prompt);
 
-    let photo = if let Some(photo) = photo
-        .iter()
-        .reduce(|a, p| if a.height > p.height { a } else { p })
-    {
-        photo
+    let photo = if let Some(photo) = photo
+        .iter()
+        .reduce(|a, p| if a.height > p.height { a } else { p })
+    {
+        photo
+    } else {
+        SendContext::of(msg)
+            .send_message(bot, translator.text("Something went wrong."))
+            .await?;
+        return Err(anyhow!("Photo vec was empty!"));
+    };
+    let file = bot.get_file(&photo.file.id).send().await?;
+
+    let photo = helpers::get_file(bot, &file).await?;
+
+    img2img.set_image(Some(photo.into()));
+
+    let params = with_style(img2img.as_ref(), style);
+    let params = with_negative_presets(params.as_ref(), negative_presets);
+    let on_progress = |update| progress.report(update);
+    let mut resp = backends
+        .img2img_api
+        .img2img_with_progress(params.as_ref(), &on_progress)
+        .await?;
+    if let Some(retry) = retry_params(backends, params.as_ref(), &resp) {
+        resp = backends
+            .img2img_api
+            .img2img_with_progress(retry.as_ref(), &on_progress)
+            .await?;
+    }
+    progress.generated();
+
+    img2img.set_image(None);
+    censor::check(&resp)?;
+
+    Ok(resp)
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "img2img"
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+async fn handle_image(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
+    dialogue: DiffusionDialogue,
+    (txt2img, img2img): (Box<dyn GenParams>, Box<dyn GenParams>),
+    msg: Message,
+    photo: Vec<PhotoSize>,
+    text: String,
+) -> anyhow::Result<()> {
+    if text.is_empty() {
+        let translator = i18n::translator_for(&ui, &history, msg.from().map(|user| user.id)).await;
+        SendContext::of(&msg)
+            .send_message(&bot, translator.text("A prompt is required."))
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    if ui.denoising_presets && img2img.denoising().is_some() {
+        let translator = i18n::translator_for(&ui, &history, msg.from().map(|user| user.id)).await;
+        SendContext::of(&msg)
+            .send_message(&bot, translator.text("How much should the image change?"))
+            .reply_markup(denoise_presets::keyboard(img2img.denoising(), &translator))
+            .reply_to_message_id(msg.id)
+            .await?;
+        dialogue
+            .update(State::Ready {
+                bot_state: BotState::Img2ImgPending {
+                    photo,
+                    prompt: text,
+                    source: msg.id,
+                },
+                txt2img,
+                img2img,
+            })
+            .await
+            .map_err(|e| anyhow!(e))?;
+        return Ok(());
+    }
+
+    start_img2img(
+        bot, backends, ui, history, dialogue, txt2img, img2img, msg, photo, text,
+    )
+    .await
+}
+
+/// Generates an image from `photo` and `text` with `img2img` and replies to `msg` with it, now
+/// or after the quiet hours, then saves the settings.
+#[allow(clippy::too_many_arguments)]
+async fn start_img2img(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
+    dialogue: DiffusionDialogue,
+    txt2img: Box<dyn GenParams>,
+    mut img2img: Box<dyn GenParams>,
+    msg: Message,
+    photo: Vec<PhotoSize>,
+    text: String,
+) -> anyhow::Result<()> {
+    let translator = i18n::translator_for(&ui, &history, msg.from().map(|user| user.id)).await;
+    match schedule_generation(&bot, &ui, &translator, &msg, true).await? {
+        Schedule::Now => {}
+        Schedule::Rejected => return Ok(()),
+        Schedule::Deferred(wait) => {
+            // The settings are saved now, since they may change before the generation runs.
+            dialogue
+                .update(State::Ready {
+                    bot_state: BotState::default(),
+                    txt2img,
+                    img2img: img2img.clone(),
+                })
+                .await
+                .map_err(|e| anyhow!(e))?;
+            tokio::spawn(
+                async move {
+                    tokio::time::sleep(wait).await;
+                    if let Err(e) = send_img2img(
+                        &bot,
+                        &backends,
+                        &ui,
+                        &history,
+                        &mut img2img,
+                        &msg,
+                        photo,
+                        text,
+                    )
+                    .await
+                    {
+                        warn!("Deferred generation failed: {:?}", e);
+                    }
+                }
+                .in_current_span(),
+            );
+            return Ok(());
+        }
+    }
+
+    let seed = send_img2img(
+        &bot,
+        &backends,
+        &ui,
+        &history,
+        &mut img2img,
+        &msg,
+        photo,
+        text,
+    )
+    .await?;
+    // The seed mode advances the saved seed once the generation is done.
+    if let Some(seed) = seed {
+        img2img.advance_seed(seed);
+    }
+
+    dialogue
+        .update(State::Ready {
+            bot_state: BotState::default(),
+            txt2img,
+            img2img,
+        })
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+    Ok(())
+}
+
+/// Generates an image from `photo` and `text` with `img2img` and replies to `msg` with it.
+/// Returns the seed of the first image, for the caller to advance the chat's seed with.
+#[allow(clippy::too_many_arguments)]
+async fn send_img2img(
+    bot: &Bot,
+    backends: &BackendHandles,
+    ui: &UiConfig,
+    history: &HistoryStore,
+    img2img: &mut Box<dyn GenParams>,
+    msg: &Message,
+    photo: Vec<PhotoSize>,
+    text: String,
+) -> anyhow::Result<Option<i64>> {
+    let translator = i18n::translator_for(ui, history, msg.from().map(|user| user.id)).await;
+    let Some(text) = expand_prompt(bot, history, msg, &translator, text).await? else {
+        return Ok(None);
+    };
+    let mut params = settings_store::with_user_settings(
+        history,
+        msg.from().map(|user| user.id),
+        SettingsKind::Img2Img,
+        img2img.as_ref(),
+    )
+    .await;
+    let img2img = &mut params;
+
+    let style = styles::selected_style(
+        &ui.styles,
+        history,
+        msg.chat.id,
+        msg.from().map(|user| user.id),
+    )
+    .await;
+    if !enforce_prompt_policy(
+        bot,
+        &ui.prompt_policy,
+        &translator,
+        msg,
+        &text,
+        img2img.as_ref(),
+        style.as_ref(),
+        &PromptFlags::default(),
+    )
+    .await?
+    {
+        return Ok(None);
+    }
+
+    if !matches!(
+        schedule_generation(bot, ui, &translator, msg, false).await?,
+        Schedule::Now
+    ) {
+        return Ok(None);
+    }
+
+    if !enforce_quota(bot, ui, history, &translator, msg, img2img.as_ref()).await? {
+        return Ok(None);
+    }
+
+    let negative_presets =
+        presets::enabled_preset_prompts(&ui.negative_presets, history, msg.chat.id).await;
+    // The photo isn't downloaded yet, so it's told apart by its file id.
+    let key = format!(
+        "{}/{}",
+        request_fingerprint(
+            img2img.as_ref(),
+            &text,
+            style.as_ref(),
+            &PromptFlags::default(),
+            &negative_presets,
+        ),
+        photo
+            .first()
+            .map(|photo| photo.file.unique_id.as_str())
+            .unwrap_or_default()
+    );
+    let Some(_request) = claim_request(bot, backends, &translator, msg, &key).await? else {
+        return Ok(None);
+    };
+
+    let progress = ProgressTracker::default();
+    let kind = GenerationKind::Img2Img { prompt: &text };
+    let (replies, seed, details, fingerprint) = with_progress(
+        bot,
+        backends,
+        &ui.progress,
+        msg,
+        &progress,
+        kind,
+        &translator,
+        async {
+            let resp = do_img2img(
+                bot,
+                backends,
+                img2img,
+                msg,
+                &translator,
+                photo,
+                text.clone(),
+                style.as_ref(),
+                &negative_presets,
+                &progress,
+            )
+            .await?;
+            record_usage(ui, history, msg, &resp).await;
+
+            let caption = MessageText::for_response(&resp, &ui.caption_template)
+                .context("Failed to build caption from response")?;
+            let caption = describe_response(backends, caption, &resp).await;
+
+            let reply = build_reply(backends, ui, caption, &resp, msg.id, &translator)?;
+            let details = reply.details.clone();
+            let replies = reply.send(bot, SendContext::of(msg), &translator).await?;
+            ui.webhooks.post(msg, &replies, &resp);
+            Ok((
+                replies,
+                resp.images.first().and_then(|image| image.seed),
+                details,
+                resp.gen_params.fingerprint(),
+            ))
+        },
+    )
+    .await?;
+
+    record_generation(
+        history,
+        &backends.name,
+        msg,
+        &replies,
+        &text,
+        seed,
+        details.as_deref(),
+        &fingerprint,
+        img2img.as_ref(),
+        false,
+    )
+    .await;
+
+    Ok(seed)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn do_txt2img(
+    prompt: String,
+    backends: &BackendHandles,
+    txt2img: &mut dyn GenParams,
+    style: Option<&Style>,
+    flags: &PromptFlags,
+    negative_presets: &[String],
+    progress: &ProgressTracker,
+) -> anyhow::Result<Response> {
+    txt2img.set_prompt(prompt);
+
+    let params = generation_params(txt2img, style, flags);
+    let params = with_negative_presets(params.as_ref(), negative_presets);
+    let on_progress = |update| progress.report(update);
+    let mut resp = backends
+        .txt2img_api
+        .txt2img_with_progress(params.as_ref(), &on_progress)
+        .await?;
+    if let Some(retry) = retry_params(backends, params.as_ref(), &resp) {
+        resp = backends
+            .txt2img_api
+            .txt2img_with_progress(retry.as_ref(), &on_progress)
+            .await?;
+    }
+    progress.generated();
+    censor::check(&resp)?;
+
+    Ok(resp)
+}
+
+/// Records a generation prompted by `msg` with `params` on `backend` in the history. The parameters are only
+/// kept for rerunning from `/history` if `rerunnable`, since img2img needs the photo, which isn't
+/// stored. Failures are logged, since the images have already been sent.
+#[allow(clippy::too_many_arguments)]
+async fn record_generation(
+    history: &HistoryStore,
+    backend: &str,
+    msg: &Message,
+    reply_message_ids: &[MessageId],
+    prompt: &str,
+    seed: Option<i64>,
+    details: Option<&str>,
+    fingerprint: &str,
+    params: &dyn GenParams,
+    rerunnable: bool,
+) {
+    let negative_prompt = params.negative_prompt();
+    let stored_params = if rerunnable {
+        serde_json::to_string(params)
+            .map_err(|e| warn!("Failed to serialize generation parameters: {:?}", e))
+            .ok()
+    } else {
+        None
+    };
+    if let Err(e) = history
+        .record(NewGeneration {
+            chat_id: msg.chat.id,
+            user_id: msg.from().map(|user| user.id),
+            source_message_id: msg.id,
+            reply_message_ids,
+            prompt,
+            seed,
+            details,
+            fingerprint: Some(fingerprint),
+            negative_prompt: negative_prompt.as_deref(),
+            params: stored_params.as_deref(),
+            backend: Some(backend),
+        })
+        .await
+    {
+        warn!("Failed to record generation: {:?}", e);
+    }
+}
+
+/// Generates an image from `text` with `txt2img` and replies to `msg` with it. Returns the seed
+/// of the first image, for the caller to advance the chat's seed with.
+pub(crate) async fn send_txt2img(
+    bot: &Bot,
+    backends: &BackendHandles,
+    ui: &UiConfig,
+    history: &HistoryStore,
+    txt2img: &mut dyn GenParams,
+    msg: &Message,
+    text: String,
+) -> anyhow::Result<Option<i64>> {
+    generate_txt2img(bot, backends, ui, history, txt2img, msg, text, None).await
+}
+
+/// Generates an image from `text` with `txt2img` and replies to `msg` with it. For `/random`
+/// generations, `random` holds the settings that were chosen, which replace the chat's style
+/// and are shown in the caption.
+#[allow(clippy::too_many_arguments)]
+async fn generate_txt2img(
+    bot: &Bot,
+    backends: &BackendHandles,
+    ui: &UiConfig,
+    history: &HistoryStore,
+    txt2img: &mut dyn GenParams,
+    msg: &Message,
+    text: String,
+    random: Option<&RandomChoices>,
+) -> anyhow::Result<Option<i64>> {
+    let translator = i18n::translator_for(ui, history, msg.from().map(|user| user.id)).await;
+    let Some(text) = expand_prompt(bot, history, msg, &translator, text).await? else {
+        return Ok(None);
+    };
+    let Some((prompt, flags)) = parse_prompt_flags(bot, msg, &translator, &text).await? else {
+        return Ok(None);
+    };
+    let mut params = settings_store::with_user_settings(
+        history,
+        msg.from().map(|user| user.id),
+        SettingsKind::Txt2Img,
+        txt2img,
+    )
+    .await;
+    let txt2img = params.as_mut();
+
+    let style = match random {
+        Some(random) => random
+            .style
+            .as_ref()
+            .and_then(|name| ui.styles.iter().find(|style| &style.name == name))
+            .cloned(),
+        None => {
+            styles::selected_style(
+                &ui.styles,
+                history,
+                msg.chat.id,
+                msg.from().map(|user| user.id),
+            )
+            .await
+        }
+    };
+    if !enforce_prompt_policy(
+        bot,
+        &ui.prompt_policy,
+        &translator,
+        msg,
+        &prompt,
+        txt2img,
+        style.as_ref(),
+        &flags,
+    )
+    .await?
+    {
+        return Ok(None);
+    }
+
+    if !matches!(
+        schedule_generation(bot, ui, &translator, msg, false).await?,
+        Schedule::Now
+    ) {
+        return Ok(None);
+    }
+
+    if !enforce_quota(bot, ui, history, &translator, msg, txt2img).await? {
+        return Ok(None);
+    }
+
+    let negative_presets =
+        presets::enabled_preset_prompts(&ui.negative_presets, history, msg.chat.id).await;
+    let fingerprint =
+        request_fingerprint(txt2img, &prompt, style.as_ref(), &flags, &negative_presets);
+    let Some(_request) = claim_request(bot, backends, &translator, msg, &fingerprint).await? else {
+        return Ok(None);
+    };
+
+    let progress = ProgressTracker::default();
+    let kind = GenerationKind::Txt2Img {
+        prompt: &prompt,
+        fingerprint: Some(&fingerprint),
+    };
+    let (replies, seed, details) = with_progress(
+        bot,
+        backends,
+        &ui.progress,
+        msg,
+        &progress,
+        kind,
+        &translator,
+        async {
+            let resp = do_txt2img(
+                prompt.clone(),
+                backends,
+                txt2img,
+                style.as_ref(),
+                &flags,
+                &negative_presets,
+                &progress,
+            )
+            .await?;
+            record_usage(ui, history, msg, &resp).await;
+
+            let mut caption = MessageText::for_response(&resp, &ui.caption_template)
+                .context("Failed to build caption from response")?;
+            if let Some(random) = random {
+                caption = caption.with_random_label(&random.label(&ui.random));
+            }
+            let caption = describe_response(backends, caption, &resp).await;
+
+            let reply = build_reply(backends, ui, caption, &resp, msg.id, &translator)?
+                .with_keep(random.and_then(RandomChoices::callback_data));
+            let details = reply.details.clone();
+            let replies = reply.send(bot, SendContext::of(msg), &translator).await?;
+            ui.webhooks.post(msg, &replies, &resp);
+            Ok((
+                replies,
+                resp.images.first().and_then(|image| image.seed),
+                details,
+            ))
+        },
+    )
+    .await?;
+
+    record_generation(
+        history,
+        &backends.name,
+        msg,
+        &replies,
+        &text,
+        seed,
+        details.as_deref(),
+        &fingerprint,
+        txt2img,
+        true,
+    )
+    .await;
+
+    Ok(seed)
+}
+
+/// Generates an image from `prompt` with `txt2img` and posts it to `target` instead of the chat
+/// of `msg`, which only shows the progress. The style and negative presets of `target` apply.
+/// The images are posted without buttons, since they aren't a reply to anyone in `target`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn relay_txt2img(
+    bot: &Bot,
+    backends: &BackendHandles,
+    ui: &UiConfig,
+    history: &HistoryStore,
+    txt2img: &mut dyn GenParams,
+    msg: &Message,
+    target: ChatId,
+    prompt: String,
+) -> anyhow::Result<Vec<MessageId>> {
+    let translator = i18n::translator_for(ui, history, msg.from().map(|user| user.id)).await;
+    let progress = ProgressTracker::default();
+    let kind = GenerationKind::Txt2Img {
+        prompt: &prompt,
+        fingerprint: None,
+    };
+    with_progress(
+        bot,
+        backends,
+        &ui.progress,
+        msg,
+        &progress,
+        kind,
+        &translator,
+        async {
+            let style = styles::selected_style(&ui.styles, history, target, None).await;
+            let negative_presets =
+                presets::enabled_preset_prompts(&ui.negative_presets, history, target).await;
+            let resp = do_txt2img(
+                prompt.clone(),
+                backends,
+                txt2img,
+                style.as_ref(),
+                &PromptFlags::default(),
+                &negative_presets,
+                &progress,
+            )
+            .await?;
+
+            let caption = MessageText::for_response(&resp, &ui.caption_template)
+                .context("Failed to build caption from response")?;
+            let caption = describe_response(backends, caption, &resp).await;
+
+            let to = SendContext {
+                chat_id: target,
+                thread_id: None,
+            };
+            let images = resp.images.into_iter().map(|image| image.data).collect();
+            match Photo::album(images)? {
+                Photo::Single(image) => {
+                    let message = to
+                        .send_photo(bot, InputFile::memory(image))
+                        .parse_mode(teloxide::types::ParseMode::MarkdownV2)
+                        .caption(caption.0)
+                        .await?;
+                    Ok(vec![message.id])
+                }
+                Photo::Album(images) | Photo::Archive(images) => {
+                    let mut caption = Some(caption.0);
+                    let input_media = images.into_iter().map(|image| {
+                        let mut media = InputMediaPhoto::new(InputFile::memory(image));
+                        media.caption = caption.take();
+                        media.parse_mode = Some(teloxide::types::ParseMode::MarkdownV2);
+                        InputMedia::Photo(media)
+                    });
+                    let messages = to.send_media_group(bot, input_media).await?;
+                    Ok(messages.iter().map(|message| message.id).collect())
+                }
+            }
+        },
+    )
+    .await
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "txt2img"
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+async fn handle_prompt(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
+    dialogue: DiffusionDialogue,
+    (mut txt2img, img2img): (Box<dyn GenParams>, Box<dyn GenParams>),
+    msg: Message,
+    text: String,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    if text.is_empty() {
+        SendContext::of(&msg)
+            .send_message(&bot, translator.text("A prompt is required."))
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    match schedule_generation(&bot, &ui, &translator, &msg, true).await? {
+        Schedule::Now => {}
+        Schedule::Rejected => return Ok(()),
+        Schedule::Deferred(wait) => {
+            // The settings are saved now, since they may change before the generation runs.
+            dialogue
+                .update(State::Ready {
+                    bot_state: BotState::default(),
+                    txt2img: txt2img.clone(),
+                    img2img,
+                })
+                .await
+                .map_err(|e| anyhow!(e))?;
+            let mut params = txt2img;
+            tokio::spawn(
+                async move {
+                    tokio::time::sleep(wait).await;
+                    if let Err(e) =
+                        send_txt2img(&bot, &backends, &ui, &history, params.as_mut(), &msg, text)
+                            .await
+                    {
+                        warn!("Deferred generation failed: {:?}", e);
+                    }
+                }
+                .in_current_span(),
+            );
+            return Ok(());
+        }
+    }
+
+    // Flags at the end of the prompt only apply to the generation, not to the saved settings.
+    let seed = send_txt2img(&bot, &backends, &ui, &history, txt2img.as_mut(), &msg, text).await?;
+    // The seed mode advances the saved seed once the generation is done.
+    if let Some(seed) = seed {
+        txt2img.advance_seed(seed);
+    }
+
+    dialogue
+        .update(State::Ready {
+            bot_state: BotState::default(),
+            txt2img,
+            img2img,
+        })
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+    Ok(())
+}
+
+/// Generates an image with a random style, sampler and CFG scale. The chat's settings are left
+/// alone unless the user keeps the random ones.
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "random"
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+async fn handle_random(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
+    (txt2img, _): (Box<dyn GenParams>, Box<dyn GenParams>),
+    msg: Message,
+    text: String,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    if text.is_empty() {
+        SendContext::of(&msg)
+            .send_message(&bot, translator.text("A prompt is required."))
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    let choices = RandomChoices::pick(&ui.random, &ui.styles, &mut rand::thread_rng());
+    let mut params = txt2img;
+    choices.apply(&ui.random, params.as_mut());
+
+    match schedule_generation(&bot, &ui, &translator, &msg, true).await? {
+        Schedule::Now => {}
+        Schedule::Rejected => return Ok(()),
+        Schedule::Deferred(wait) => {
+            tokio::spawn(
+                async move {
+                    tokio::time::sleep(wait).await;
+                    if let Err(e) = generate_txt2img(
+                        &bot,
+                        &backends,
+                        &ui,
+                        &history,
+                        params.as_mut(),
+                        &msg,
+                        text,
+                        Some(&choices),
+                    )
+                    .await
+                    {
+                        warn!("Deferred generation failed: {:?}", e);
+                    }
+                }
+                .in_current_span(),
+            );
+            return Ok(());
+        }
+    }
+
+    generate_txt2img(
+        &bot,
+        &backends,
+        &ui,
+        &history,
+        params.as_mut(),
+        &msg,
+        text,
+        Some(&choices),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Generates two prompts with the same fixed seed and settings, replying with an album of both
+/// and buttons to vote on the better one. The chat's settings are left alone.
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "ab"
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+async fn handle_ab(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
+    (txt2img, _): (Box<dyn GenParams>, Box<dyn GenParams>),
+    msg: Message,
+    text: String,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let Some((prompt_a, prompt_b)) = ab::parse_prompts(&text) else {
+        SendContext::of(&msg)
+            .send_message(
+                &bot,
+                translator.text("Usage: /ab \"<prompt A>\" \"<prompt B>\""),
+            )
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    };
+    let Some(prompt_a) = expand_prompt(&bot, &history, &msg, &translator, prompt_a).await? else {
+        return Ok(());
+    };
+    let Some(prompt_b) = expand_prompt(&bot, &history, &msg, &translator, prompt_b).await? else {
+        return Ok(());
+    };
+
+    let mut params = txt2img;
+    params.set_count(1);
+    params.set_batch_size(1);
+    let seed = resolve_seed(params.seed().unwrap_or(RANDOM_SEED));
+    params.set_seed(seed);
+
+    let style = styles::selected_style(
+        &ui.styles,
+        &history,
+        msg.chat.id,
+        msg.from().map(|user| user.id),
+    )
+    .await;
+    for prompt in [&prompt_a, &prompt_b] {
+        if !enforce_prompt_policy(
+            &bot,
+            &ui.prompt_policy,
+            &translator,
+            &msg,
+            prompt,
+            params.as_ref(),
+            style.as_ref(),
+            &PromptFlags::default(),
+        )
+        .await?
+        {
+            return Ok(());
+        }
+    }
+
+    if !matches!(
+        schedule_generation(&bot, &ui, &translator, &msg, false).await?,
+        Schedule::Now
+    ) {
+        return Ok(());
+    }
+
+    let mut both = params.clone();
+    both.set_count(2);
+    if !enforce_quota(&bot, &ui, &history, &translator, &msg, both.as_ref()).await? {
+        return Ok(());
+    }
+
+    let negative_presets =
+        presets::enabled_preset_prompts(&ui.negative_presets, &history, msg.chat.id).await;
+
+    let mut captions = Vec::with_capacity(2);
+    let mut images = Vec::with_capacity(2);
+    for (label, prompt) in [("A", &prompt_a), ("B", &prompt_b)] {
+        let progress = ProgressTracker::default();
+        let kind = GenerationKind::Txt2Img {
+            prompt,
+            fingerprint: None,
+        };
+        let resp = with_progress(
+            &bot,
+            &backends,
+            &ui.progress,
+            &msg,
+            &progress,
+            kind,
+            &translator,
+            async {
+                do_txt2img(
+                    prompt.clone(),
+                    &backends,
+                    params.as_mut(),
+                    style.as_ref(),
+                    &PromptFlags::default(),
+                    &negative_presets,
+                    &progress,
+                )
+                .await
+            },
+        )
+        .await?;
+        record_usage(&ui, &history, &msg, &resp).await;
+
+        let caption = MessageText::for_response(&resp, &ui.caption_template)
+            .context("Failed to build caption from response")?;
+        captions.push(format!("*{label}*\n{}", caption.0));
+        images.push(
+            resp.images
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("Failed to get image"))?
+                .data,
+        );
+    }
+
+    let test_id = history
+        .create_ab_test(
+            msg.chat.id,
+            msg.from().map(|user| user.id),
+            &prompt_a,
+            &prompt_b,
+            seed,
+        )
+        .await?;
+
+    let context = SendContext::of(&msg);
+    let input_media = images.into_iter().zip(captions).map(|(image, caption)| {
+        let mut media = InputMediaPhoto::new(InputFile::memory(image));
+        media.caption = Some(caption);
+        media.parse_mode = Some(teloxide::types::ParseMode::MarkdownV2);
+        InputMedia::Photo(media)
+    });
+    context
+        .send_media_group(&bot, input_media)
+        .reply_to_message_id(msg.id)
+        .await?;
+    context
+        .send_message(&bot, translator.text("Which prompt is better?"))
+        .reply_markup(ab::vote_keyboard(test_id, AbTally::default()))
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+/// Records a vote in an A/B test and updates the tally on the buttons.
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "ab"
+    )
+)]
+async fn handle_ab_vote(
+    bot: Bot,
+    history: HistoryStore,
+    q: CallbackQuery,
+    vote: AbVote,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let Some(message) = q.message else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, this message is no longer available."))
+            .await?;
+        return Ok(());
+    };
+    let Some(tally) = history
+        .vote_ab_test(vote.test_id, q.from.id, vote.choice)
+        .await?
+    else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, this test is no longer available."))
+            .await?;
+        return Ok(());
+    };
+
+    if let Err(e) = bot
+        .answer_callback_query(q.id)
+        .text(translator.format(
+            "You voted for {choice}.",
+            &[("choice", &vote.choice.as_str().to_uppercase())],
+        ))
+        .await
+    {
+        warn!("Failed to answer A/B vote callback query: {}", e)
+    }
+    // Telegram rejects edits that don't change the markup, e.g. when a user votes again for the
+    // same prompt.
+    if let Err(e) = bot
+        .edit_message_reply_markup(message.chat.id, message.id)
+        .reply_markup(ab::vote_keyboard(vote.test_id, tally))
+        .await
+    {
+        warn!("Failed to update A/B vote tally: {}", e)
+    }
+    Ok(())
+}
+
+/// Inpaints `image` with `prompt` in the white area of `mask`, replying to `msg`. The mask
+/// only applies to this generation.
+#[allow(clippy::too_many_arguments)]
+async fn send_inpaint(
+    bot: &Bot,
+    backends: &BackendHandles,
+    ui: &UiConfig,
+    history: &HistoryStore,
+    mut img2img: Box<dyn GenParams>,
+    msg: &Message,
+    image: Vec<PhotoSize>,
+    mask: Vec<PhotoSize>,
+    prompt: String,
+) -> anyhow::Result<()> {
+    let mask = mask
+        .iter()
+        .max_by_key(|photo| photo.height)
+        .ok_or_else(|| anyhow!("Mask photo vec was empty!"))?;
+    let file = bot.get_file(&mask.file.id).send().await?;
+    let mask = helpers::get_file(bot, &file).await?;
+    img2img.set_mask(Some(mask.to_vec()));
+    send_img2img(bot, backends, ui, history, &mut img2img, msg, image, prompt).await?;
+    Ok(())
+}
+
+/// Inpaints the image that a photo of a mask captioned `/mask` replies to. Without a prompt in
+/// the caption, asks for one.
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "mask"
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+async fn handle_mask(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
+    dialogue: DiffusionDialogue,
+    (txt2img, img2img): (Box<dyn GenParams>, Box<dyn GenParams>),
+    msg: Message,
+    prompt: String,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let (Some(mask), Some(image)) = (
+        msg.photo().map(ToOwned::to_owned),
+        msg.reply_to_message()
+            .and_then(|parent| parent.photo())
+            .map(ToOwned::to_owned),
+    ) else {
+        SendContext::of(&msg)
+            .send_message(
+                &bot,
+                translator.text(
+                    "Reply to the image to inpaint with a photo of the mask, captioned /mask. \
+                     The white area of the mask is regenerated.",
+                ),
+            )
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    };
+
+    if !img2img.supports_mask() {
+        SendContext::of(&msg)
+            .send_message(
+                &bot,
+                translator.text("Sorry, inpainting isn't supported by this backend."),
+            )
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    if prompt.trim().is_empty() {
+        dialogue
+            .update(State::Ready {
+                bot_state: BotState::Inpaint { image, mask },
+                txt2img,
+                img2img,
+            })
+            .await
+            .map_err(|e| anyhow!(e))?;
+        SendContext::of(&msg)
+            .send_message(
+                &bot,
+                translator.text("Now send the prompt for the white area of the mask, or /cancel."),
+            )
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    send_inpaint(
+        &bot, &backends, &ui, &history, img2img, &msg, image, mask, prompt,
+    )
+    .await
+}
+
+/// Inpaints the image and mask saved by `/mask` with the prompt in `msg`.
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "mask"
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+async fn handle_inpaint_prompt(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
+    dialogue: DiffusionDialogue,
+    (txt2img, img2img): (Box<dyn GenParams>, Box<dyn GenParams>),
+    (image, mask): (Vec<PhotoSize>, Vec<PhotoSize>),
+    msg: Message,
+    text: String,
+) -> anyhow::Result<()> {
+    dialogue
+        .update(State::Ready {
+            bot_state: BotState::default(),
+            txt2img,
+            img2img: img2img.clone(),
+        })
+        .await
+        .map_err(|e| anyhow!(e))?;
+    send_inpaint(
+        &bot, &backends, &ui, &history, img2img, &msg, image, mask, text,
+    )
+    .await
+}
+
+/// Offers to generate with the settings from a pasted infotext block, rather than using it
+/// verbatim as the prompt.
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "infotext"
+    )
+)]
+async fn handle_infotext(
+    bot: Bot,
+    msg: Message,
+    infotext: Infotext,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let settings = infotext
+        .params
+        .iter()
+        .map(|(key, value)| format!("{key}: {value}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    SendContext::of(&msg)
+        .send_message(
+            &bot,
+            translator.format(
+                "This looks like generation settings ({settings}). Generate with them, or use \
+                 /gen to use the text as a prompt.",
+                &[("settings", &settings)],
+            ),
+        )
+        .reply_markup(InlineKeyboardMarkup::new([[
+            InlineKeyboardButton::callback(
+                translator.text("▶️ Generate with these settings"),
+                "infotext",
+            ),
+        ]]))
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "infotext"
+    )
+)]
+async fn handle_infotext_generate(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
+    (txt2img, _): (Box<dyn GenParams>, Box<dyn GenParams>),
+    q: CallbackQuery,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let Some((message, parent, infotext)) = q.message.and_then(|message| {
+        let parent = message.reply_to_message().cloned()?;
+        let infotext = Infotext::parse(parent.text()?)?;
+        Some((message, parent, infotext))
+    }) else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, this message is no longer available."))
+            .await?;
+        return Ok(());
+    };
+
+    if let Err(e) = bot
+        .answer_callback_query(q.id)
+        .text(translator.text("Generating with these settings..."))
+        .await
+    {
+        warn!("Failed to answer infotext callback query: {}", e)
+    }
+
+    bot.edit_message_reply_markup(message.chat.id, message.id)
+        .reply_markup(InlineKeyboardMarkup::new([[]]))
+        .send()
+        .await?;
+
+    // The settings only apply to this generation; the user's saved settings are untouched.
+    let mut params = txt2img;
+    infotext.apply_to(params.as_mut());
+    send_txt2img(
+        &bot,
+        &backends,
+        &ui,
+        &history,
+        params.as_mut(),
+        &parent,
+        infotext.prompt,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Returns the prompt in `text`, without the `/gen` command if there is one.
+fn strip_gen_command(me: &Me, text: &str) -> String {
+    let bot_name = me
+        .user
+        .username
+        .as_deref()
+        .expect("Bots must have a username");
+    match GenCommands::parse(text, bot_name) {
+        Ok(GenCommands::Gen(s) | GenCommands::G(s) | GenCommands::Generate(s)) => s,
+        Err(_) => match RandomCommands::parse(text, bot_name) {
+            Ok(RandomCommands::Random(s)) => s,
+            Err(_) => strip_mention(text, bot_name),
+        },
+    }
+}
+
+/// Offers to regenerate when the prompt of a recent generation is edited.
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "edit"
+    )
+)]
+async fn handle_prompt_edit(
+    me: Me,
+    bot: Bot,
+    history: HistoryStore,
+    msg: Message,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let Some(text) = msg.text().or_else(|| msg.caption()) else {
+        return Ok(());
+    };
+    let Some(generation) = history.find_by_source(msg.chat.id, msg.id).await? else {
+        return Ok(());
+    };
+    if history::now() - generation.created_at > REVISION_WINDOW.as_secs() as i64 {
+        return Ok(());
+    }
+    if strip_gen_command(&me, text).trim() == generation.prompt.trim() {
+        return Ok(());
+    }
+
+    SendContext::of(&msg)
+        .send_message(&bot, translator.text("Your prompt changed — regenerate?"))
+        .reply_markup(InlineKeyboardMarkup::new([[
+            InlineKeyboardButton::callback(translator.text("🔄 Regenerate"), "revise"),
+        ]]))
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+/// Regenerates from the edited prompt message that the pressed button replies to.
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "revise"
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+async fn handle_revision(
+    me: Me,
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
+    dialogue: DiffusionDialogue,
+    settings: (Box<dyn GenParams>, Box<dyn GenParams>),
+    q: CallbackQuery,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let Some((message, parent)) = q.message.and_then(|message| {
+        let parent = message.reply_to_message().cloned()?;
+        Some((message, parent))
+    }) else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, this message is no longer available."))
+            .await?;
+        return Ok(());
+    };
+
+    if let Err(e) = bot
+        .answer_callback_query(q.id)
+        .text(translator.text("Regenerating..."))
+        .await
+    {
+        warn!("Failed to answer revision callback query: {}", e)
+    }
+
+    bot.edit_message_reply_markup(message.chat.id, message.id)
+        .reply_markup(InlineKeyboardMarkup::new([[]]))
+        .send()
+        .await?;
+
+    let text = strip_gen_command(
+        &me,
+        parent
+            .text()
+            .or_else(|| parent.caption())
+            .unwrap_or_default(),
+    );
+    match parent.photo().map(ToOwned::to_owned) {
+        Some(photo) => {
+            handle_image(
+                bot, backends, ui, history, dialogue, settings, parent, photo, text,
+            )
+            .await
+        }
+        None => {
+            handle_prompt(
+                bot, backends, ui, history, dialogue, settings, parent, text, translator,
+            )
+            .await
+        }
+    }
+}
+
+/// Changes `params` to generate variations of the image generated with `seed`. Backends that
+/// support variation seeds blend random noise into the noise of `seed`; others use the next seed.
+fn apply_variation(params: &mut dyn GenParams, seed: i64) {
+    if params.supports_subseed() {
+        let strength = params
+            .subseed_strength()
+            .filter(|strength| *strength > 0.0)
+            .unwrap_or(VARIATION_STRENGTH);
+        params.set_seed(seed);
+        params.set_subseed(RANDOM_SEED);
+        params.set_subseed_strength(strength);
+    } else {
+        params.set_seed(seed.wrapping_add(1));
+    }
+}
+
+/// Generates variations of the image that the pressed button belongs to, keeping its seed.
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "variations"
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+async fn handle_variations(
+    me: Me,
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
+    (mut txt2img, mut img2img): (Box<dyn GenParams>, Box<dyn GenParams>),
+    q: CallbackQuery,
+    seed: i64,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let Some((message, parent)) = q.message.and_then(|message| {
+        let parent = message.reply_to_message().cloned()?;
+        Some((message, parent))
+    }) else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, this message is no longer available."))
+            .await?;
+        return Ok(());
+    };
+
+    if let Err(e) = bot
+        .answer_callback_query(q.id)
+        .text(translator.text("Generating variations..."))
+        .await
+    {
+        warn!("Failed to answer variations callback query: {}", e)
+    }
+
+    bot.edit_message_reply_markup(message.chat.id, message.id)
+        .reply_markup(InlineKeyboardMarkup::new([[]]))
+        .send()
+        .await?;
+
+    // The variation settings only apply to this generation; the user's saved settings are
+    // untouched.
+    let text = strip_gen_command(
+        &me,
+        parent
+            .text()
+            .or_else(|| parent.caption())
+            .unwrap_or_default(),
+    );
+    match parent.photo().map(ToOwned::to_owned) {
+        Some(photo) => {
+            apply_variation(img2img.as_mut(), seed);
+            send_img2img(
+                &bot,
+                &backends,
+                &ui,
+                &history,
+                &mut img2img,
+                &parent,
+                photo,
+                text,
+            )
+            .await?;
+        }
+        None => {
+            let text = match Infotext::parse(&text) {
+                Some(infotext) => {
+                    infotext.apply_to(txt2img.as_mut());
+                    infotext.prompt
+                }
+                None => text,
+            };
+            apply_variation(txt2img.as_mut(), seed);
+            send_txt2img(
+                &bot,
+                &backends,
+                &ui,
+                &history,
+                txt2img.as_mut(),
+                &parent,
+                text,
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Deletes the reply that the pressed button belongs to and purges it from the history.
+///
+/// Only the user who requested the generation, or another member of their shared account, may
+/// delete it.
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "delete"
+    )
+)]
+async fn handle_delete(
+    bot: Bot,
+    auth: Arc<AuthConfig>,
+    history: HistoryStore,
+    q: CallbackQuery,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let Some(message) = q.message else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, this message is no longer available."))
+            .await?;
+        return Ok(());
+    };
+
+    let generation = history.find_by_reply(message.chat.id, message.id).await?;
+    if generation
+        .as_ref()
+        .and_then(|generation| generation.user_id)
+        .is_some_and(|user_id| auth.accounts.resolve(user_id) != auth.accounts.resolve(q.from.id))
+    {
+        bot.answer_callback_query(q.id)
+            .text(translator.text("Only the person who requested this image can delete it."))
+            .await?;
+        return Ok(());
+    }
+
+    let message_ids = generation
+        .as_ref()
+        .map(|generation| generation.reply_message_ids.clone())
+        .unwrap_or_else(|| vec![message.id]);
+    for id in message_ids {
+        if let Err(e) = bot.delete_message(message.chat.id, id).await {
+            warn!("Failed to delete message {}: {}", id, e);
+        }
+    }
+
+    if let Some(generation) = generation {
+        history.purge(generation.id).await?;
+    }
+
+    if let Err(e) = bot
+        .answer_callback_query(q.id)
+        .text(translator.text("Deleted."))
+        .await
+    {
+        warn!("Failed to answer delete callback query: {}", e)
+    }
+    Ok(())
+}
+
+/// Responds to a command the bot doesn't know, as configured by the unknown command mode.
+async fn handle_unknown_command(
+    me: Me,
+    bot: Bot,
+    ui: Arc<UiConfig>,
+    msg: Message,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let text = msg.text().unwrap_or_default();
+    info!("Unknown command: {}", text);
+
+    let bot_name = me.user.username.as_deref().unwrap_or_default();
+    // Other bots in a group may handle commands the bot doesn't know.
+    let require_mention = msg.chat.is_group() || msg.chat.is_supergroup();
+    let Some(reply) =
+        unknown_commands::command_name(text, bot_name, require_mention).and_then(|name| {
+            let commands = StableDiffusionBot::commands();
+            ui.unknown_command_mode.reply(
+                name,
+                commands.iter().map(|command| command.command.as_str()),
+                &translator,
+            )
+        })
+    else {
+        return Ok(());
+    };
+    SendContext::of(&msg)
+        .send_message(&bot, reply)
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+/// The seed button on a reply keyboard, which toggles between reusing the reply's seed and
+/// randomizing it again.
+///
+/// The original seed is carried in the callback data of the randomize button, so toggling back
+/// restores it instead of losing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SeedButton {
+    /// Offers to reuse the seed for subsequent generations.
+    Reuse(i64),
+    /// Offers to randomize the seed, remembering the seed to offer again, if known.
+    Randomize(Option<i64>),
+}
+
+impl SeedButton {
+    /// Returns the button for a reply to `resp`: if the user's seed was already fixed to the
+    /// seed used, offer to randomize it, otherwise offer to reuse it.
+    fn for_response(resp: &Response) -> Self {
+        match resp.params.seed() {
+            Some(seed) if resp.gen_params.seed() == Some(seed) => Self::Randomize(Some(seed)),
+            Some(seed) if !is_random_seed(seed) => Self::Reuse(seed),
+            _ => Self::Randomize(None),
+        }
+    }
+
+    /// Parses the button from callback data.
+    fn parse(data: &str) -> Option<Self> {
+        let mut parts = data.strip_prefix("reuse/")?.split('/');
+        match parts.next()?.parse::<i64>().ok()? {
+            seed if is_random_seed(seed) => Some(Self::Randomize(
+                parts.next().and_then(|seed| seed.parse().ok()),
+            )),
+            seed => Some(Self::Reuse(seed)),
+        }
+    }
+
+    fn callback_data(&self) -> String {
+        match self {
+            Self::Reuse(seed) => format!("reuse/{seed}"),
+            Self::Randomize(None) => format!("reuse/{RANDOM_SEED}"),
+            Self::Randomize(Some(seed)) => format!("reuse/{RANDOM_SEED}/{seed}"),
+        }
+    }
+
+    /// Returns the seed of the reply, if known.
+    fn seed(&self) -> Option<i64> {
+        match self {
+            Self::Reuse(seed) => Some(*seed),
+            Self::Randomize(seed) => *seed,
+        }
+    }
+
+    fn button(&self, translator: &Translator) -> InlineKeyboardButton {
+        let label = match self {
+            Self::Reuse(_) => "♻️ Seed",
+            Self::Randomize(_) => "🎲 Seed",
+        };
+        InlineKeyboardButton::callback(translator.text(label), self.callback_data())
+    }
+}
+
+/// Returns the factors that images can be upscaled by, if upscaling is available.
+fn upscale_scales(backends: &BackendHandles) -> &[u32] {
+    backends
+        .upscaler
+        .as_ref()
+        .map(|upscaler| upscaler.scales())
+        .unwrap_or_default()
+}
+
+fn keyboard(
+    seed: SeedButton,
+    upscale_scales: &[u32],
+    translator: &Translator,
+) -> InlineKeyboardMarkup {
+    let variations = seed.seed().map(|seed| {
+        InlineKeyboardButton::callback(translator.text("🎛 Variations"), format!("vary/{seed}"))
+    });
+    let mut keyboard = InlineKeyboardMarkup::new([[
+        Some(InlineKeyboardButton::callback(
+            translator.text("🔄 Rerun"),
+            "rerun",
+        )),
+        variations,
+        Some(seed.button(translator)),
+        Some(InlineKeyboardButton::callback(
+            translator.text("⚙️ Settings"),
+            "settings",
+        )),
+    ]
+    .into_iter()
+    .flatten()]);
+    if !upscale_scales.is_empty() {
+        keyboard = keyboard.append_row(upscale_scales.iter().map(|scale| {
+            InlineKeyboardButton::callback(
+                translator.format("⬆️ Upscale {scale}x", &[("scale", &scale.to_string())]),
+                format!("upscale/{scale}"),
+            )
+        }));
+    }
+    keyboard.append_row([InlineKeyboardButton::callback(
+        translator.text("🗑 Delete"),
+        "delete",
+    )])
+}
+
+/// Adds a button that keeps the settings of a `/random` generation, if there are any.
+fn with_keep_button(
+    keyboard: InlineKeyboardMarkup,
+    data: Option<String>,
+    translator: &Translator,
+) -> InlineKeyboardMarkup {
+    match data {
+        Some(data) => keyboard.append_row([InlineKeyboardButton::callback(
+            translator.text("⭐ Keep these settings"),
+            data,
+        )]),
+        None => keyboard,
+    }
+}
+
+/// Adds a button that reveals the full caption, if `details` is set.
+fn with_details_button(
+    keyboard: InlineKeyboardMarkup,
+    details: bool,
+    translator: &Translator,
+) -> InlineKeyboardMarkup {
+    if details {
+        keyboard.append_row([InlineKeyboardButton::callback(
+            translator.text("ℹ️ Details"),
+            "details",
+        )])
     } else {
-        bot.send_message(msg.chat.id, "Something went wrong.")
-            .await?;
-        return Err(anyhow!("Photo vec was empty!"));
-    };
-    let file = bot.get_file(&photo.file.id).send().await?;
+        keyboard
+    }
+}
 
-    let photo = helpers::get_file(bot, &file).await?;
+fn is_details_button(button: &InlineKeyboardButton) -> bool {
+    matches!(&button.kind, InlineKeyboardButtonKind::CallbackData(data) if data == "details")
+}
 
-    img2img.set_image(Some(photo.into()));
+/// Returns whether the keyboard of `message` has a button that reveals the full caption.
+fn has_details_button(message: &Message) -> bool {
+    message.reply_markup().is_some_and(|markup| {
+        markup
+            .inline_keyboard
+            .iter()
+            .flatten()
+            .any(is_details_button)
+    })
+}
 
-    let resp = cfg.img2img_api.img2img(img2img.as_ref()).await?;
+/// Returns the keyboard of `message` without the button that reveals the full caption.
+fn without_details_button(message: &Message) -> InlineKeyboardMarkup {
+    let rows = message
+        .reply_markup()
+        .map(|markup| markup.inline_keyboard.clone())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .filter(|button| !is_details_button(button))
+                .collect::<Vec<_>>()
+        })
+        .filter(|row| !row.is_empty());
+    InlineKeyboardMarkup::new(rows)
+}
 
-    img2img.set_image(None);
+/// Reveals the full caption of the generation that the pressed button belongs to. Photos and
+/// files get their caption replaced, while the details of an album are sent in reply to the
+/// message carrying its buttons.
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "details"
+    )
+)]
+async fn handle_details(
+    bot: Bot,
+    history: HistoryStore,
+    q: CallbackQuery,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let Some(message) = q.message else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, this message is no longer available."))
+            .await?;
+        return Ok(());
+    };
+    let Some(details) = history
+        .find_by_reply(message.chat.id, message.id)
+        .await?
+        .and_then(|generation| generation.details)
+    else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, the details of this image are no longer available."))
+            .await?;
+        return Ok(());
+    };
 
-    Ok(resp)
+    if let Err(e) = bot.answer_callback_query(q.id).await {
+        warn!("Failed to answer details callback query: {}", e)
+    }
+    let keyboard = without_details_button(&message);
+    if message.caption().is_some() {
+        bot.edit_message_caption(message.chat.id, message.id)
+            .caption(details)
+            .parse_mode(teloxide::types::ParseMode::MarkdownV2)
+            .reply_markup(keyboard)
+            .await?;
+    } else {
+        bot.edit_message_reply_markup(message.chat.id, message.id)
+            .reply_markup(keyboard)
+            .await?;
+        SendContext::of(&message)
+            .send_message(&bot, details)
+            .parse_mode(teloxide::types::ParseMode::MarkdownV2)
+            .reply_to_message_id(message.id)
+            .await?;
+    }
+    Ok(())
 }
 
-async fn handle_image(
+/// Returns the data of the button that keeps the settings in the keyboard of `message`.
+fn keep_button_data(message: &Message) -> Option<String> {
+    message
+        .reply_markup()?
+        .inline_keyboard
+        .iter()
+        .flatten()
+        .find_map(|button| match &button.kind {
+            InlineKeyboardButtonKind::CallbackData(data) if data.starts_with("keep/") => {
+                Some(data.clone())
+            }
+            _ => None,
+        })
+}
+
+/// Generates from the photo waiting for a denoising strength with the strength of the pressed
+/// button, which is saved to the settings.
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "img2img"
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+async fn handle_denoise_preset(
     bot: Bot,
-    cfg: ConfigParameters,
+    backends: Arc<BackendHandles>,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
     dialogue: DiffusionDialogue,
     (txt2img, mut img2img): (Box<dyn GenParams>, Box<dyn GenParams>),
-    msg: Message,
-    photo: Vec<PhotoSize>,
-    text: String,
+    q: CallbackQuery,
+    (photo, prompt, source): (Vec<PhotoSize>, String, MessageId),
+    denoising: f32,
 ) -> anyhow::Result<()> {
-    if text.is_empty() {
-        bot.send_message(msg.chat.id, "A prompt is required.")
-            .reply_to_message_id(msg.id)
+    let translator = i18n::translator_for(&ui, &history, Some(q.from.id)).await;
+    let Some((message, msg)) = q.message.as_ref().and_then(|message| {
+        message
+            .reply_to_message()
+            .filter(|parent| parent.id == source)
+            .map(|parent| (message, parent.clone()))
+    }) else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, this message is no longer available."))
             .await?;
         return Ok(());
-    }
-
-    bot.send_chat_action(msg.chat.id, ChatAction::UploadPhoto)
-        .await?;
-
-    let resp = do_img2img(&bot, &cfg, &mut img2img, &msg, photo, text).await?;
-
-    let seed = if resp.params.seed() == resp.gen_params.seed() {
-        -1
-    } else {
-        resp.params.seed().unwrap_or(-1)
     };
+    if msg.from().map(|user| user.id) != Some(q.from.id) {
+        bot.answer_callback_query(q.id)
+            .text(translator.text("Only the sender of the photo can choose."))
+            .await?;
+        return Ok(());
+    }
 
-    let caption = MessageText::try_from(resp.params.as_ref())
-        .context("Failed to build caption from response")?;
-
-    Reply::new(caption.0, resp.images, seed, msg.id)
-        .context("Failed to create response!")?
-        .send(&bot, msg.chat.id)
-        .await?;
-
-    dialogue
-        .update(State::Ready {
-            bot_state: BotState::default(),
-            txt2img,
-            img2img,
-        })
+    if let Err(e) = bot.answer_callback_query(q.id).await {
+        warn!("Failed to answer denoising preset callback query: {}", e)
+    }
+    let value = denoise_presets::label(denoising).map_or_else(
+        || denoising.to_string(),
+        |label| format!("{} ({denoising})", translator.text(label)),
+    );
+    if let Err(e) = bot
+        .edit_message_text(
+            message.chat.id,
+            message.id,
+            translator.format("Denoising strength: {value}.", &[("value", &value)]),
+        )
         .await
-        .map_err(|e| anyhow!(e))?;
+    {
+        warn!("Failed to edit denoising preset message: {}", e)
+    }
 
-    Ok(())
+    img2img.set_denoising(denoising);
+    start_img2img(
+        bot, backends, ui, history, dialogue, txt2img, img2img, msg, photo, prompt,
+    )
+    .await
 }
 
-async fn do_txt2img(
-    prompt: String,
-    cfg: &ConfigParameters,
-    txt2img: &mut dyn GenParams,
-) -> anyhow::Result<Response> {
-    txt2img.set_prompt(prompt);
-
-    let resp = cfg.txt2img_api.txt2img(txt2img).await?;
-
-    Ok(resp)
+/// Answers presses of denoising strength buttons whose photo is no longer waiting, e.g.
+/// because another prompt was sent since.
+async fn handle_stale_denoise_preset(
+    bot: Bot,
+    q: CallbackQuery,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    bot.answer_callback_query(q.id)
+        .cache_time(60)
+        .text(translator.text("Sorry, this message is no longer available."))
+        .await?;
+    Ok(())
 }
 
-async fn handle_prompt(
+/// Saves the settings of the `/random` generation that the pressed button belongs to.
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "keep"
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+async fn handle_keep(
     bot: Bot,
-    cfg: ConfigParameters,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
     dialogue: DiffusionDialogue,
-    (mut txt2img, img2img): (Box<dyn GenParams>, Box<dyn GenParams>),
-    msg: Message,
-    text: String,
+    (mut txt2img, mut img2img): (Box<dyn GenParams>, Box<dyn GenParams>),
+    q: CallbackQuery,
+    choices: RandomChoices,
+    translator: Translator,
 ) -> anyhow::Result<()> {
-    if text.is_empty() {
-        bot.send_message(msg.chat.id, "A prompt is required.")
-            .reply_to_message_id(msg.id)
+    let Some(message) = q.message else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, this message is no longer available."))
             .await?;
         return Ok(());
-    }
-
-    bot.send_chat_action(msg.chat.id, ChatAction::UploadPhoto)
-        .await?;
-
-    let resp = do_txt2img(text, &cfg, txt2img.as_mut()).await?;
-
-    let seed = if resp.params.seed() == resp.gen_params.seed() {
-        -1
-    } else {
-        resp.params.seed().unwrap_or(-1)
     };
 
-    let caption = MessageText::try_from(resp.params.as_ref())
-        .context("Failed to build caption from response")?;
-
-    Reply::new(caption.0, resp.images, seed, msg.id)
-        .context("Failed to create response!")?
-        .send(&bot, msg.chat.id)
-        .await?;
-
+    choices.apply(&ui.random, txt2img.as_mut());
+    choices.apply(&ui.random, img2img.as_mut());
     dialogue
         .update(State::Ready {
             bot_state: BotState::default(),
@@ -314,38 +2718,138 @@ async fn handle_prompt(
         })
         .await
         .map_err(|e| anyhow!(e))?;
+    if let Some(style) = &choices.style {
+        history.set_chat_style(message.chat.id, Some(style)).await?;
+    }
 
+    if let Err(e) = bot
+        .answer_callback_query(q.id)
+        .text(translator.format(
+            "Settings saved: {choices}.",
+            &[("choices", &choices.label(&ui.random))],
+        ))
+        .await
+    {
+        warn!("Failed to answer keep settings callback query: {}", e)
+    }
     Ok(())
 }
 
-fn keyboard(seed: i64) -> InlineKeyboardMarkup {
-    let seed_button = if seed == -1 {
-        InlineKeyboardButton::callback("🎲 Seed", "reuse/-1")
-    } else {
-        InlineKeyboardButton::callback("♻️ Seed", format!("reuse/{seed}"))
+/// Upscales the image that the pressed button belongs to and sends it as a file, so Telegram
+/// doesn't compress it.
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "upscale"
+    )
+)]
+async fn handle_upscale(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    ui: Arc<UiConfig>,
+    q: CallbackQuery,
+    scale: u32,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let Some(message) = q.message else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, this message is no longer available."))
+            .await?;
+        return Ok(());
+    };
+    let Some(upscaler) = &backends.upscaler else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, upscaling isn't available."))
+            .await?;
+        return Ok(());
+    };
+    let Some(photo) = message
+        .photo()
+        .and_then(|photo| photo.iter().max_by_key(|size| size.width * size.height))
+    else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Oops, something went wrong."))
+            .await?;
+        return Ok(());
     };
-    InlineKeyboardMarkup::new([[
-        InlineKeyboardButton::callback("🔄 Rerun", "rerun"),
-        seed_button,
-        InlineKeyboardButton::callback("⚙️ Settings", "settings"),
-    ]])
+
+    if !matches!(
+        schedule_generation(&bot, &ui, &translator, &message, false).await?,
+        Schedule::Now
+    ) {
+        bot.answer_callback_query(q.id).await?;
+        return Ok(());
+    }
+
+    if let Err(e) = bot
+        .answer_callback_query(q.id)
+        .text(translator.format(
+            "Upscaling this image {scale}x...",
+            &[("scale", &scale.to_string())],
+        ))
+        .await
+    {
+        warn!("Failed to answer upscale callback query: {}", e)
+    }
+
+    let progress = ProgressTracker::default();
+    let kind = GenerationKind::Upscale;
+    with_progress(
+        &bot,
+        &backends,
+        &ui.progress,
+        &message,
+        &progress,
+        kind,
+        &translator,
+        async {
+            let file = bot.get_file(&photo.file.id).send().await?;
+            let image = helpers::get_file(&bot, &file).await?;
+            let upscaled = upscaler.upscale(&image, scale).await?;
+            SendContext::of(&message)
+                .send_document(
+                    &bot,
+                    InputFile::memory(upscaled).file_name(format!("upscaled-{scale}x.png")),
+                )
+                .reply_to_message_id(message.id)
+                .await?;
+            Ok(())
+        },
+    )
+    .await
 }
 
-#[instrument(skip_all)]
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "rerun"
+    )
+)]
+#[allow(clippy::too_many_arguments)]
 async fn handle_rerun(
     me: Me,
     bot: Bot,
-    cfg: ConfigParameters,
+    backends: Arc<BackendHandles>,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
     dialogue: DiffusionDialogue,
     (txt2img, img2img): (Box<dyn GenParams>, Box<dyn GenParams>),
     q: CallbackQuery,
+    translator: Translator,
 ) -> anyhow::Result<()> {
     let message = if let Some(message) = q.message {
         message
     } else {
         bot.answer_callback_query(q.id)
             .cache_time(60)
-            .text("Sorry, this message is no longer available.")
+            .text(translator.text("Sorry, this message is no longer available."))
             .await?;
         return Ok(());
     };
@@ -358,7 +2862,7 @@ async fn handle_rerun(
     } else {
         bot.answer_callback_query(q.id)
             .cache_time(60)
-            .text("Oops, something went wrong.")
+            .text(translator.text("Oops, something went wrong."))
             .await?;
         return Ok(());
     };
@@ -377,14 +2881,16 @@ async fn handle_rerun(
             if let Err(e) = bot
                 .answer_callback_query(q.id)
                 .cache_time(60)
-                .text("Rerunning this image...")
+                .text(translator.text("Rerunning this image..."))
                 .await
             {
                 warn!("Failed to answer image rerun callback query: {}", e)
             }
             handle_image(
                 bot.clone(),
-                cfg,
+                backends,
+                ui,
+                history,
                 dialogue,
                 (txt2img, img2img),
                 parent,
@@ -393,7 +2899,11 @@ async fn handle_rerun(
             )
             .await?;
         } else {
-            bot.send_message(message.chat.id, "A prompt is required to run img2img.")
+            SendContext::of(&message)
+                .send_message(
+                    &bot,
+                    translator.text("A prompt is required to run img2img."),
+                )
                 .await?;
             return Err(anyhow!("No prompt provided for img2img"));
         }
@@ -401,24 +2911,77 @@ async fn handle_rerun(
         if let Err(e) = bot
             .answer_callback_query(q.id)
             .cache_time(60)
-            .text("Rerunning this prompt...")
+            .text(translator.text("Rerunning this prompt..."))
             .await
         {
             warn!("Failed to answer prompt rerun callback query: {}", e)
         }
         let bot_name = me.user.username.expect("Bots must have a username");
-        let text = if let Ok(command) = GenCommands::parse(&text, &bot_name) {
-            match command {
-                GenCommands::Gen(s) | GenCommands::G(s) | GenCommands::Generate(s) => s,
+        // Reruns of a `/random` generation pick new settings.
+        if let Ok(RandomCommands::Random(s)) = RandomCommands::parse(&text, &bot_name) {
+            return handle_random(
+                bot,
+                backends,
+                ui,
+                history,
+                (txt2img, img2img),
+                parent,
+                s,
+                translator,
+            )
+            .await;
+        }
+        match GenCommands::parse(&text, &bot_name) {
+            Ok(GenCommands::Gen(s) | GenCommands::G(s) | GenCommands::Generate(s)) => {
+                handle_prompt(
+                    bot.clone(),
+                    backends,
+                    ui,
+                    history,
+                    dialogue,
+                    (txt2img, img2img),
+                    parent,
+                    s,
+                    translator,
+                )
+                .await?
             }
-        } else {
-            text
-        };
-        handle_prompt(bot.clone(), cfg, dialogue, (txt2img, img2img), parent, text).await?;
+            Err(_) => match Infotext::parse(&text) {
+                // Reruns of an infotext generation reuse its settings without saving them.
+                Some(infotext) => {
+                    let mut params = txt2img;
+                    infotext.apply_to(params.as_mut());
+                    send_txt2img(
+                        &bot,
+                        &backends,
+                        &ui,
+                        &history,
+                        params.as_mut(),
+                        &parent,
+                        infotext.prompt,
+                    )
+                    .await?;
+                }
+                None => {
+                    handle_prompt(
+                        bot.clone(),
+                        backends,
+                        ui,
+                        history,
+                        dialogue,
+                        (txt2img, img2img),
+                        parent,
+                        text,
+                        translator,
+                    )
+                    .await?
+                }
+            },
+        }
     } else {
         bot.answer_callback_query(q.id)
             .cache_time(60)
-            .text("Oops, something went wrong.")
+            .text(translator.text("Oops, something went wrong."))
             .await?;
         return Ok(());
     }
@@ -431,19 +2994,33 @@ async fn handle_rerun(
     Ok(())
 }
 
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "reuse"
+    )
+)]
 async fn handle_reuse(
     bot: Bot,
+    backends: Arc<BackendHandles>,
     dialogue: DiffusionDialogue,
     (mut txt2img, mut img2img): (Box<dyn GenParams>, Box<dyn GenParams>),
     q: CallbackQuery,
-    seed: i64,
+    pressed: SeedButton,
+    translator: Translator,
 ) -> anyhow::Result<()> {
+    let seed = match pressed {
+        SeedButton::Reuse(seed) => seed,
+        SeedButton::Randomize(_) => RANDOM_SEED,
+    };
     let message = if let Some(message) = q.message {
         message
     } else {
         bot.answer_callback_query(q.id)
             .cache_time(60)
-            .text("Sorry, this message is no longer available.")
+            .text(translator.text("Sorry, this message is no longer available."))
             .await?;
         return Ok(());
     };
@@ -456,7 +3033,7 @@ async fn handle_reuse(
     } else {
         bot.answer_callback_query(q.id)
             .cache_time(60)
-            .text("Oops, something went wrong.")
+            .text(translator.text("Oops, something went wrong."))
             .await?;
         return Ok(());
     };
@@ -484,28 +3061,52 @@ async fn handle_reuse(
     } else {
         bot.answer_callback_query(q.id)
             .cache_time(60)
-            .text("Oops, something went wrong.")
+            .text(translator.text("Oops, something went wrong."))
             .await?;
         return Ok(());
     }
-    if seed == -1 {
-        if let Err(e) = bot
-            .answer_callback_query(q.id)
-            .text("Seed randomized.")
-            .await
-        {
-            warn!("Failed to answer randomize seed callback query: {}", e)
+    let toggled = match pressed {
+        SeedButton::Reuse(seed) => {
+            if let Err(e) = bot
+                .answer_callback_query(q.id)
+                .text(translator.format("Seed set to {seed}.", &[("seed", &seed.to_string())]))
+                .await
+            {
+                warn!("Failed to answer set seed callback query: {}", e)
+            }
+            Some(SeedButton::Randomize(Some(seed)))
         }
-    } else {
-        if let Err(e) = bot
-            .answer_callback_query(q.id)
-            .text(format!("Seed set to {seed}."))
-            .await
-        {
-            warn!("Failed to answer set seed callback query: {}", e)
+        SeedButton::Randomize(original) => {
+            if let Err(e) = bot
+                .answer_callback_query(q.id)
+                .text(translator.text("Seed randomized."))
+                .await
+            {
+                warn!("Failed to answer randomize seed callback query: {}", e)
+            }
+            original.map(SeedButton::Reuse)
         }
+    };
+    if let Some(toggled) = toggled {
         bot.edit_message_reply_markup(chat_id, id)
-            .reply_markup(keyboard(-1))
+            .reply_markup(with_details_button(
+                with_keep_button(
+                    keyboard(
+                        toggled,
+                        // Only single images carry upscale buttons.
+                        if message.photo().is_some() {
+                            upscale_scales(&backends)
+                        } else {
+                            &[]
+                        },
+                        &translator,
+                    ),
+                    keep_button_data(&message),
+                    &translator,
+                ),
+                has_details_button(&message),
+                &translator,
+            ))
             .send()
             .await?;
     }
@@ -513,54 +3114,317 @@ async fn handle_reuse(
     Ok(())
 }
 
-pub(crate) fn image_schema() -> UpdateHandler<anyhow::Error> {
+pub fn image_schema() -> UpdateHandler<anyhow::Error> {
     let gen_command_handler = Update::filter_message()
         .chain(filter_command::<GenCommands>())
         .chain(dptree::filter_map(|g: GenCommands| match g {
             GenCommands::Gen(s) | GenCommands::G(s) | GenCommands::Generate(s) => Some(s),
         }))
+        .map(|msg: Message, ui: Arc<UiConfig>, text: String| ui.prompt_cleaning.clean(&msg, &text))
         .branch(Message::filter_photo().endpoint(handle_image))
         .branch(dptree::endpoint(handle_prompt));
 
+    let random_command_handler = Update::filter_message()
+        .chain(filter_command::<RandomCommands>())
+        .branch(case![RandomCommands::Random(text)].endpoint(handle_random));
+
+    let ab_command_handler = Update::filter_message()
+        .chain(filter_command::<AbCommands>())
+        .branch(case![AbCommands::Ab(text)].endpoint(handle_ab));
+
+    let mask_handler = Update::filter_message()
+        .chain(Message::filter_photo())
+        .chain(filter_command::<InpaintCommands>())
+        .map(|cmd: InpaintCommands| match cmd {
+            InpaintCommands::Mask(prompt) => prompt,
+        })
+        .endpoint(handle_mask);
+
     let message_handler = Update::filter_message()
         .branch(
             dptree::filter(|msg: Message| {
                 msg.text().map(|t| t.starts_with('/')).unwrap_or_default()
             })
-            .endpoint(|msg: Message| async move {
-                info!(
-                    "Ignoring unknown command: {}",
-                    msg.text().unwrap_or_default()
-                );
-                Ok(())
-            }),
+            .endpoint(handle_unknown_command),
         )
         .branch(
             Message::filter_photo()
-                .map(|msg: Message| msg.caption().map(str::to_string).unwrap_or_default())
+                .filter_map(|msg: Message, ui: Arc<UiConfig>, me: Me| {
+                    addressed_prompt(ui.group_mode, &me, &msg, msg.caption().unwrap_or_default())
+                        .map(|text| ui.prompt_cleaning.clean(&msg, &text))
+                })
                 .endpoint(handle_image),
         )
-        .branch(Message::filter_text().endpoint(handle_prompt));
+        .branch(
+            Message::filter_text()
+                .filter_map(|msg: Message, ui: Arc<UiConfig>, me: Me, text: String| {
+                    addressed_prompt(ui.group_mode, &me, &msg, &text)
+                })
+                .branch(
+                    dptree::filter_map(|text: String| Infotext::parse(&text))
+                        .endpoint(handle_infotext),
+                )
+                .branch(
+                    dptree::map(|msg: Message, ui: Arc<UiConfig>, text: String| {
+                        ui.prompt_cleaning.clean(&msg, &text)
+                    })
+                    .endpoint(handle_prompt),
+                ),
+        );
 
     let callback_handler = Update::filter_callback_query()
+        .branch(
+            dptree::filter_map(|q: CallbackQuery| q.data.as_deref().and_then(SeedButton::parse))
+                .endpoint(handle_reuse),
+        )
+        .branch(
+            dptree::filter(|q: CallbackQuery| q.data.filter(|d| d.starts_with("rerun")).is_some())
+                .endpoint(handle_rerun),
+        )
+        .branch(
+            dptree::filter_map(|q: CallbackQuery| {
+                q.data?.strip_prefix("vary/")?.parse::<i64>().ok()
+            })
+            .endpoint(handle_variations),
+        )
+        .branch(
+            dptree::filter(|q: CallbackQuery| q.data.filter(|d| d == "infotext").is_some())
+                .endpoint(handle_infotext_generate),
+        )
+        .branch(
+            dptree::filter(|q: CallbackQuery| q.data.filter(|d| d == "revise").is_some())
+                .endpoint(handle_revision),
+        )
+        .branch(
+            dptree::filter(|q: CallbackQuery| q.data.filter(|d| d == "delete").is_some())
+                .endpoint(handle_delete),
+        )
+        .branch(
+            dptree::filter(|q: CallbackQuery| q.data.filter(|d| d == "details").is_some())
+                .endpoint(handle_details),
+        )
         .branch(
             dptree::filter_map(|q: CallbackQuery| {
                 q.data
-                    .filter(|d| d.starts_with("reuse"))
-                    .and_then(|d| d.split('/').skip(1).flat_map(str::parse::<i64>).next())
+                    .as_deref()
+                    .and_then(RandomChoices::parse_callback_data)
             })
-            .endpoint(handle_reuse),
+            .endpoint(handle_keep),
         )
         .branch(
-            dptree::filter(|q: CallbackQuery| q.data.filter(|d| d.starts_with("rerun")).is_some())
-                .endpoint(handle_rerun),
+            dptree::filter_map(|q: CallbackQuery| {
+                q.data?.strip_prefix("upscale/")?.parse::<u32>().ok()
+            })
+            .endpoint(handle_upscale),
+        )
+        .branch(
+            dptree::filter_map(|q: CallbackQuery| q.data.as_deref().and_then(AbVote::parse))
+                .endpoint(handle_ab_vote),
         );
 
+    let edit_handler = Update::filter_edited_message().endpoint(handle_prompt_edit);
+
+    let denoise_preset_handler = Update::filter_callback_query()
+        .filter_map(|q: CallbackQuery| {
+            q.data
+                .as_deref()
+                .and_then(denoise_presets::parse_callback_data)
+        })
+        .branch(
+            case![BotState::Img2ImgPending {
+                photo,
+                prompt,
+                source
+            }]
+            .endpoint(handle_denoise_preset),
+        )
+        .endpoint(handle_stale_denoise_preset);
+
+    let inpaint_prompt_handler = Update::filter_message()
+        .chain(Message::filter_text())
+        .chain(case![BotState::Inpaint { image, mask }])
+        .endpoint(handle_inpaint_prompt);
+
     dptree::entry()
         .chain(filter_map_bot_state())
-        .chain(case![BotState::Generate])
         .chain(filter_map_settings())
-        .branch(gen_command_handler)
-        .branch(message_handler)
-        .branch(callback_handler)
+        .branch(denoise_preset_handler)
+        .branch(
+            // A photo waiting for a denoising strength doesn't hold up other prompts.
+            dptree::filter(|state: BotState| {
+                matches!(state, BotState::Generate | BotState::Img2ImgPending { .. })
+            })
+            .branch(mask_handler)
+            .branch(gen_command_handler)
+            .branch(random_command_handler)
+            .branch(ab_command_handler)
+            .branch(message_handler)
+            .branch(callback_handler)
+            .branch(edit_handler),
+        )
+        .branch(inpaint_prompt_handler)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seed_button_parse() {
+        assert_eq!(SeedButton::parse("reuse/42"), Some(SeedButton::Reuse(42)));
+        assert_eq!(
+            SeedButton::parse("reuse/-1"),
+            Some(SeedButton::Randomize(None))
+        );
+        assert_eq!(
+            SeedButton::parse("reuse/-1/42"),
+            Some(SeedButton::Randomize(Some(42)))
+        );
+        assert_eq!(SeedButton::parse("rerun"), None);
+        assert_eq!(SeedButton::parse("reuse/abc"), None);
+    }
+
+    #[test]
+    fn test_seed_button_round_trip() {
+        for button in [
+            SeedButton::Reuse(1234567890123),
+            SeedButton::Randomize(None),
+            SeedButton::Randomize(Some(1234567890123)),
+        ] {
+            assert_eq!(SeedButton::parse(&button.callback_data()), Some(button));
+        }
+    }
+
+    #[test]
+    fn test_keyboard_upscale_buttons() {
+        let translator = Translator::default();
+        let texts = |keyboard: InlineKeyboardMarkup| {
+            keyboard
+                .inline_keyboard
+                .into_iter()
+                .map(|row| {
+                    row.into_iter()
+                        .map(|button| button.text)
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+        let seed = SeedButton::Reuse(1);
+        assert_eq!(
+            texts(keyboard(seed, &[2, 4], &translator))[1],
+            vec!["⬆️ Upscale 2x", "⬆️ Upscale 4x"]
+        );
+        assert_eq!(texts(keyboard(seed, &[], &translator)).len(), 2);
+        assert_eq!(
+            texts(with_keep_button(
+                keyboard(seed, &[], &translator),
+                Some("keep/7//".to_owned()),
+                &translator
+            ))[2],
+            vec!["⭐ Keep these settings"]
+        );
+        assert_eq!(
+            texts(with_details_button(
+                keyboard(seed, &[], &translator),
+                true,
+                &translator
+            ))[2],
+            vec!["ℹ️ Details"]
+        );
+        assert_eq!(
+            texts(with_details_button(
+                keyboard(seed, &[], &translator),
+                false,
+                &translator
+            ))
+            .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_keyboard_variations_button() {
+        let translator = Translator::default();
+        let first_row = |seed: SeedButton| {
+            keyboard(seed, &[], &translator).inline_keyboard[0]
+                .iter()
+                .map(|button| button.text.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            first_row(SeedButton::Randomize(Some(42))),
+            vec!["🔄 Rerun", "🎛 Variations", "🎲 Seed", "⚙️ Settings"]
+        );
+        assert_eq!(
+            first_row(SeedButton::Randomize(None)),
+            vec!["🔄 Rerun", "🎲 Seed", "⚙️ Settings"]
+        );
+    }
+
+    #[test]
+    fn test_parse_mask_command() {
+        assert!(matches!(
+            InpaintCommands::parse("/mask a red hat", "bot"),
+            Ok(InpaintCommands::Mask(prompt)) if prompt == "a red hat"
+        ));
+        assert!(matches!(
+            InpaintCommands::parse("/mask", "bot"),
+            Ok(InpaintCommands::Mask(prompt)) if prompt.is_empty()
+        ));
+    }
+
+    #[test]
+    fn test_apply_variation() {
+        let mut params = sal_e_api::Txt2ImgParams::default();
+        apply_variation(&mut params, 42);
+        assert_eq!(params.seed(), Some(42));
+        assert_eq!(params.subseed(), Some(RANDOM_SEED));
+        assert_eq!(params.subseed_strength(), Some(VARIATION_STRENGTH));
+
+        params.set_subseed_strength(0.5);
+        apply_variation(&mut params, 42);
+        assert_eq!(params.subseed_strength(), Some(0.5));
+
+        let mut params = sal_e_api::ComfyParams::default();
+        apply_variation(&mut params, 42);
+        assert_eq!(params.seed(), Some(43));
+    }
+
+    #[test]
+    fn test_generation_params() {
+        let mut params = sal_e_api::Txt2ImgParams::default();
+        params.set_prompt("a castle".to_owned());
+        params.set_negative_prompt("blurry".to_owned());
+        let style = Style {
+            name: "painting".to_owned(),
+            prompt_prefix: Some("oil painting of".to_owned()),
+            negative_prompt: Some("photo".to_owned()),
+            cfg: Some(9.0),
+            steps: Some(40),
+            ..Default::default()
+        };
+        let (_, flags) = prompt_flags::parse("a castle --no people --cfg 5").unwrap();
+
+        let generated = generation_params(&params, Some(&style), &flags);
+        assert_eq!(
+            generated.prompt().as_deref(),
+            Some("oil painting of, a castle")
+        );
+        assert_eq!(generated.negative_prompt().as_deref(), Some("people"));
+        assert_eq!(generated.cfg(), Some(5.0));
+        assert_eq!(generated.steps(), Some(40));
+        assert_eq!(params.cfg(), None);
+    }
+
+    #[test]
+    fn test_truncated_prompt() {
+        let prompt = "a very long prompt, ".repeat(100);
+        assert!(!MessageText::prompt_only(&prompt).fits_in_caption());
+        let caption = MessageText::truncated_prompt(&prompt, &Translator::default());
+        assert!(caption.fits_in_caption());
+        assert!(caption
+            .0
+            .starts_with("`a very long prompt, a very long prompt,"));
+        assert!(caption.0.contains("attached file"));
+    }
 }
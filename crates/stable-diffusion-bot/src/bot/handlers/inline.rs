@@ -0,0 +1,182 @@
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+
+use anyhow::Context;
+use teloxide::{
+    dispatching::UpdateHandler,
+    prelude::*,
+    types::{InlineQueryResult, InlineQueryResultCachedPhoto, InputFile, ParseMode},
+};
+use tokio::time::Instant;
+use tracing::{info, instrument, warn};
+
+use crate::bot::{
+    censor,
+    history::HistoryStore,
+    inline::{CachedResults, InlineGenerator},
+    policy::PromptKind,
+    presets::{self, with_negative_presets},
+    settings_store::{self, SettingsKind},
+    styles::{self, with_style},
+};
+
+use super::{stored_txt2img, AuthConfig, BackendHandles, DialogueStorage, MessageText, UiConfig};
+
+/// How long Telegram clients may cache the results of an inline query, in seconds. Typing the
+/// same prompt again within this time shows the same images instead of generating new ones.
+const RESULT_CACHE_SECS: u32 = 300;
+
+/// Returns inline results offering the `cached` images.
+fn inline_results(q: &InlineQuery, cached: &CachedResults) -> Vec<InlineQueryResult> {
+    cached
+        .file_ids
+        .iter()
+        .enumerate()
+        .map(|(index, file_id)| {
+            InlineQueryResult::CachedPhoto(
+                InlineQueryResultCachedPhoto::new(format!("{}-{index}", q.id), file_id)
+                    .caption(cached.caption.clone())
+                    .parse_mode(ParseMode::MarkdownV2),
+            )
+        })
+        .collect()
+}
+
+/// Generates images for an inline query and posts them to the cache chat, returning them as
+/// inline results. Requests with the same fingerprint as one generated recently, by any user,
+/// get the same images without generating or counting towards the rate limit.
+async fn generate(
+    bot: &Bot,
+    backends: &BackendHandles,
+    ui: &UiConfig,
+    history: &HistoryStore,
+    storage: DialogueStorage,
+    inline: &InlineGenerator,
+    q: &InlineQuery,
+) -> anyhow::Result<Vec<InlineQueryResult>> {
+    // Use the settings the user saved in their private chat with the bot.
+    let chat_id = ChatId(q.from.id.0 as i64);
+    let txt2img = stored_txt2img(storage, backends, chat_id).await;
+    let mut txt2img = settings_store::with_user_settings(
+        history,
+        Some(q.from.id),
+        SettingsKind::Txt2Img,
+        txt2img.as_ref(),
+    )
+    .await;
+    txt2img.set_prompt(q.query.trim().to_owned());
+    let style = styles::selected_style(&ui.styles, history, chat_id, Some(q.from.id)).await;
+    let negative_presets =
+        presets::enabled_preset_prompts(&ui.negative_presets, history, chat_id).await;
+    let params = with_style(txt2img.as_ref(), style.as_ref());
+    if let Err(violation) = ui.prompt_policy.check_params(params.as_ref()) {
+        warn!(
+            target: "audit",
+            user = %q.from.id,
+            %violation,
+            "Rejected inline prompt"
+        );
+        return Ok(Vec::new());
+    }
+    let params = with_negative_presets(params.as_ref(), &negative_presets);
+
+    let fingerprint = params.fingerprint();
+    let max_age = Duration::from_secs(RESULT_CACHE_SECS.into());
+    if let Some(cached) = inline.cached(&fingerprint, Instant::now(), max_age) {
+        backends
+            .metrics
+            .inline_cache_hits
+            .fetch_add(1, Ordering::Relaxed);
+        return Ok(inline_results(q, &cached));
+    }
+    if let Err(wait) = inline.try_start(q.from.id, Instant::now()) {
+        warn!("Inline query rate limited, next allowed in {:?}", wait);
+        return Ok(Vec::new());
+    }
+
+    let request = backends.txt2img_api.txt2img(params.as_ref());
+    let resp = match ui.progress.timeout_secs.map(Duration::from_secs) {
+        Some(timeout) => tokio::time::timeout(timeout, request)
+            .await
+            .context("Request timed out")??,
+        None => request.await?,
+    };
+    censor::check(&resp)?;
+
+    let caption = MessageText::for_response(&resp, &ui.caption_template)
+        .context("Failed to build caption from response")?;
+    let mut file_ids = Vec::with_capacity(resp.images.len());
+    for image in resp.images {
+        let message = bot
+            .send_photo(inline.cache_chat_id(), InputFile::memory(image.data))
+            .await
+            .context("Failed to post image to the inline cache chat")?;
+        if let Some(photo) = message.photo().and_then(|sizes| sizes.last()) {
+            file_ids.push(photo.file.id.clone());
+        }
+    }
+    let cached = CachedResults {
+        file_ids,
+        caption: caption.0,
+    };
+    let results = inline_results(q, &cached);
+    inline.cache(fingerprint, Instant::now(), cached);
+    Ok(results)
+}
+
+/// Generates an image from the text of an inline query and offers it as a result.
+///
+/// Queries from users who aren't allowed, whose prompt is rejected, or who generated an image
+/// too recently get no results, as do queries during the quiet hours of the user's private chat.
+/// Requests generated recently are answered from the cache, however recently the user generated.
+#[instrument(skip_all, fields(user_id = %q.from.id, command = "inline"))]
+#[allow(clippy::too_many_arguments)]
+async fn handle_inline_query(
+    bot: Bot,
+    auth: Arc<AuthConfig>,
+    backends: Arc<BackendHandles>,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
+    storage: DialogueStorage,
+    q: InlineQuery,
+) -> anyhow::Result<()> {
+    let prompt = q.query.trim();
+    let Some(inline) = &ui.inline else {
+        return Ok(());
+    };
+    if prompt.is_empty() || !auth.chat_is_allowed(None, Some(q.from.id)) {
+        return Ok(());
+    }
+    if let Err(violation) = ui.prompt_policy.check(PromptKind::Prompt, prompt) {
+        warn!(
+            target: "audit",
+            user = %q.from.id,
+            %violation,
+            "Rejected inline prompt"
+        );
+        return Ok(());
+    }
+    if let Some(until) = ui
+        .quiet_hours
+        .as_ref()
+        .and_then(|config| config.quiet_until(q.from.id.into(), chrono::Utc::now()))
+    {
+        info!("Inline query during quiet hours, until {until}");
+        return Ok(());
+    }
+
+    let results = generate(&bot, &backends, &ui, &history, storage, inline, &q).await?;
+    bot.answer_inline_query(q.id, results)
+        .is_personal(true)
+        .cache_time(RESULT_CACHE_SECS)
+        .await?;
+    Ok(())
+}
+
+/// Returns the handler for inline queries. Inline queries don't belong to a chat, so unlike
+/// the other handlers, this one must not be a branch of the dialogue handler.
+pub fn inline_schema() -> UpdateHandler<anyhow::Error> {
+    Update::filter_inline_query().endpoint(handle_inline_query)
+}
@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use teloxide::{dispatching::UpdateHandler, dptree::case, macros::BotCommands, prelude::*};
+use tracing::instrument;
+
+use super::{filter_command, SendContext, UiConfig};
+use crate::bot::{
+    history::HistoryStore,
+    i18n::{Translations, Translator},
+};
+
+/// BotCommands for choosing the language of the bot's messages.
+#[derive(BotCommands, Debug, Clone)]
+#[command(rename_rule = "lowercase", description = "Language commands")]
+pub(crate) enum LanguageCommands {
+    /// Command to show or change the language of the bot's messages.
+    #[command(description = "choose the language of messages, e.g. /language de.")]
+    Language(String),
+}
+
+/// Runs `/language` with `args` for `user_id`, whose messages are translated by `translator`,
+/// returning the reply in the language the user ends up with.
+async fn run_language_command(
+    translations: &Arc<Translations>,
+    history: &HistoryStore,
+    translator: &Translator,
+    user_id: UserId,
+    args: &str,
+) -> anyhow::Result<String> {
+    let languages = translations.languages().join(", ");
+    let reply = match args.trim() {
+        "" => translator.format(
+            "Messages are shown in {language}. Available languages: {languages}. Use /language \
+             default to go back to the default language.",
+            &[
+                ("language", translator.language()),
+                ("languages", &languages),
+            ],
+        ),
+        "default" => {
+            history.set_user_language(user_id, None).await?;
+            let translator = Translator::new(translations.clone(), None);
+            translator.format(
+                "Messages are shown in {language}.",
+                &[("language", translator.language())],
+            )
+        }
+        language if translations.has_language(language) => {
+            history.set_user_language(user_id, Some(language)).await?;
+            let translator = Translator::new(translations.clone(), Some(language.to_owned()));
+            translator.format(
+                "Messages are shown in {language}.",
+                &[("language", translator.language())],
+            )
+        }
+        language => translator.format(
+            "There are no translations for {language}. Available languages: {languages}.",
+            &[("language", language), ("languages", &languages)],
+        ),
+    };
+    Ok(reply)
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "language"
+    )
+)]
+async fn handle_language_command(
+    bot: Bot,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
+    translator: Translator,
+    msg: Message,
+    args: String,
+) -> anyhow::Result<()> {
+    let reply = match msg.from() {
+        Some(user) => {
+            run_language_command(&ui.translations, &history, &translator, user.id, &args).await?
+        }
+        None => translator.text("Languages are chosen per user, so they can't be chosen here."),
+    };
+    SendContext::of(&msg)
+        .send_message(&bot, reply)
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+pub fn language_schema() -> UpdateHandler<anyhow::Error> {
+    Update::filter_message()
+        .chain(filter_command::<LanguageCommands>())
+        .branch(case![LanguageCommands::Language(args)].endpoint(handle_language_command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_language_command() {
+        let translations = Arc::new(Translations::load(None, None).unwrap());
+        let history = HistoryStore::open(None).await.unwrap();
+        let translator = Translator::new(translations.clone(), None);
+        let user = UserId(1);
+
+        let reply = run_language_command(&translations, &history, &translator, user, "xx")
+            .await
+            .unwrap();
+        assert!(reply.starts_with("There are no translations for xx."));
+        assert_eq!(history.user_language(user).await.unwrap(), None);
+
+        run_language_command(&translations, &history, &translator, user, "de")
+            .await
+            .unwrap();
+        assert_eq!(
+            history.user_language(user).await.unwrap().as_deref(),
+            Some("de")
+        );
+
+        let reply = run_language_command(&translations, &history, &translator, user, "default")
+            .await
+            .unwrap();
+        assert_eq!(reply, "Messages are shown in en.");
+        assert_eq!(history.user_language(user).await.unwrap(), None);
+    }
+}
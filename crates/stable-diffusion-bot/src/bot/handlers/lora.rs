@@ -0,0 +1,314 @@
+use std::sync::Arc;
+
+use teloxide::{
+    dispatching::UpdateHandler,
+    dptree::case,
+    macros::BotCommands,
+    payloads::setters::*,
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, ParseMode},
+    utils::markdown,
+};
+use tracing::{instrument, warn};
+
+use super::{filter_command, BackendHandles, SendContext};
+use crate::bot::i18n::Translator;
+
+/// Number of LoRAs shown on each page of the list.
+const PAGE_SIZE: usize = 10;
+
+/// Weight used in the snippets offered for each LoRA.
+const DEFAULT_WEIGHT: &str = "1.0";
+
+const LIST_TEXT: &str = "Tap a LoRA to get a snippet to add to your prompt.";
+
+/// BotCommands for browsing LoRAs.
+#[derive(BotCommands, Debug, Clone)]
+#[command(rename_rule = "lowercase", description = "LoRA commands")]
+pub(crate) enum LoraCommands {
+    /// Command to list the LoRAs that can be used in prompts.
+    #[command(description = "list the LoRAs you can use in prompts.")]
+    Loras,
+}
+
+/// Returns the snippet that applies the LoRA `name` in a prompt.
+fn lora_snippet(name: &str) -> String {
+    format!("<lora:{name}:{DEFAULT_WEIGHT}>")
+}
+
+/// Returns the number of pages needed to list `count` LoRAs.
+fn page_count(count: usize) -> usize {
+    count.div_ceil(PAGE_SIZE).max(1)
+}
+
+/// Builds an inline keyboard listing page `page` of `loras`, with buttons to move between pages.
+fn lora_keyboard(loras: &[String], page: usize, translator: &Translator) -> InlineKeyboardMarkup {
+    let pages = page_count(loras.len());
+    let page = page.min(pages - 1);
+    let mut keyboard: Vec<Vec<InlineKeyboardButton>> = loras
+        .iter()
+        .enumerate()
+        .skip(page * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .map(|(index, lora)| {
+            vec![InlineKeyboardButton::callback(
+                lora.clone(),
+                format!("lora/{index}"),
+            )]
+        })
+        .collect();
+    if pages > 1 {
+        let mut navigation = Vec::new();
+        if page > 0 {
+            navigation.push(InlineKeyboardButton::callback(
+                translator.text("◀️ Previous"),
+                format!("loras/{}", page - 1),
+            ));
+        }
+        if page + 1 < pages {
+            navigation.push(InlineKeyboardButton::callback(
+                translator.text("Next ▶️"),
+                format!("loras/{}", page + 1),
+            ));
+        }
+        keyboard.push(navigation);
+    }
+    InlineKeyboardMarkup::new(keyboard)
+}
+
+/// Returns the text shown above page `page` of a list of `count` LoRAs.
+fn page_text(count: usize, page: usize, translator: &Translator) -> String {
+    match page_count(count) {
+        1 => translator.text(LIST_TEXT),
+        pages => format!(
+            "{}\n\n{}",
+            translator.text(LIST_TEXT),
+            translator.format(
+                "Page {page} of {pages}.",
+                &[
+                    ("page", &(page.min(pages - 1) + 1).to_string()),
+                    ("pages", &pages.to_string())
+                ]
+            )
+        ),
+    }
+}
+
+/// Lists the backend's LoRAs, or returns a message explaining why they can't be listed.
+async fn list_loras(
+    backends: &BackendHandles,
+    translator: &Translator,
+) -> Result<Vec<String>, String> {
+    match backends.txt2img_api.loras().await {
+        Ok(loras) if loras.is_empty() => {
+            Err(translator.text("The backend has no LoRAs to choose from."))
+        }
+        Ok(loras) => Ok(loras),
+        Err(e) => {
+            warn!("Failed to list LoRAs: {:?}", e);
+            Err(translator.text("Sorry, the list of LoRAs isn't available."))
+        }
+    }
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "loras"
+    )
+)]
+async fn handle_loras_command(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    msg: Message,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    match list_loras(&backends, &translator).await {
+        Ok(loras) => {
+            SendContext::of(&msg)
+                .send_message(&bot, page_text(loras.len(), 0, &translator))
+                .reply_markup(lora_keyboard(&loras, 0, &translator))
+                .await?;
+        }
+        Err(text) => {
+            SendContext::of(&msg).send_message(&bot, text).await?;
+        }
+    }
+    Ok(())
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "loras"
+    )
+)]
+async fn handle_page_button(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    q: CallbackQuery,
+    page: usize,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let Some(message) = q.message else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, this message is no longer available."))
+            .await?;
+        return Ok(());
+    };
+    let loras = match list_loras(&backends, &translator).await {
+        Ok(loras) => loras,
+        Err(text) => {
+            bot.answer_callback_query(q.id).text(text).await?;
+            return Ok(());
+        }
+    };
+    if let Err(e) = bot.answer_callback_query(q.id).await {
+        warn!("Failed to answer LoRA page callback query: {}", e)
+    }
+    bot.edit_message_text(
+        message.chat.id,
+        message.id,
+        page_text(loras.len(), page, &translator),
+    )
+    .reply_markup(lora_keyboard(&loras, page, &translator))
+    .await?;
+    Ok(())
+}
+
+/// Sends the snippet for the chosen LoRA as code, which Telegram copies when it's tapped, so it
+/// can be pasted into a prompt.
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "loras"
+    )
+)]
+async fn handle_lora_selection(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    q: CallbackQuery,
+    index: usize,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let Some(message) = q.message else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, this message is no longer available."))
+            .await?;
+        return Ok(());
+    };
+    // The list is fetched again, so a LoRA removed since the keyboard was sent can't be chosen.
+    let lora = match list_loras(&backends, &translator).await {
+        Ok(loras) => loras.into_iter().nth(index),
+        Err(text) => {
+            bot.answer_callback_query(q.id).text(text).await?;
+            return Ok(());
+        }
+    };
+    let Some(lora) = lora else {
+        bot.answer_callback_query(q.id)
+            .text(translator.text("Sorry, this LoRA is no longer available."))
+            .await?;
+        return Ok(());
+    };
+
+    if let Err(e) = bot.answer_callback_query(q.id).await {
+        warn!("Failed to answer LoRA selection callback query: {}", e)
+    }
+    SendContext::of(&message).send_message(&bot, format!(
+            "{}\n\n{}",
+            markdown::code_inline(&lora_snippet(&lora)),
+            markdown::escape(&translator.format(
+                "Tap to copy it, then add it to your prompt. Change {weight} to adjust its strength.",
+                &[("weight", DEFAULT_WEIGHT)]
+            ))
+        ),
+    )
+    .parse_mode(ParseMode::MarkdownV2)
+    .await?;
+    Ok(())
+}
+
+pub fn lora_schema() -> UpdateHandler<anyhow::Error> {
+    let command_handler = Update::filter_message()
+        .chain(filter_command::<LoraCommands>())
+        .branch(case![LoraCommands::Loras].endpoint(handle_loras_command));
+
+    let callback_handler = Update::filter_callback_query()
+        .branch(
+            dptree::filter_map(|q: CallbackQuery| {
+                q.data?.strip_prefix("loras/")?.parse::<usize>().ok()
+            })
+            .endpoint(handle_page_button),
+        )
+        .branch(
+            dptree::filter_map(|q: CallbackQuery| {
+                q.data?.strip_prefix("lora/")?.parse::<usize>().ok()
+            })
+            .endpoint(handle_lora_selection),
+        );
+
+    dptree::entry()
+        .branch(command_handler)
+        .branch(callback_handler)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn button_data(keyboard: InlineKeyboardMarkup) -> Vec<String> {
+        keyboard
+            .inline_keyboard
+            .into_iter()
+            .flatten()
+            .filter_map(|button| match button.kind {
+                teloxide::types::InlineKeyboardButtonKind::CallbackData(data) => Some(data),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lora_keyboard() {
+        let loras: Vec<String> = (0..23).map(|index| format!("lora{index}")).collect();
+        let first = button_data(lora_keyboard(&loras, 0, &Translator::default()));
+        assert_eq!(first.len(), PAGE_SIZE + 1);
+        assert_eq!(first[0], "lora/0");
+        assert_eq!(first[PAGE_SIZE], "loras/1");
+
+        let middle = button_data(lora_keyboard(&loras, 1, &Translator::default()));
+        assert_eq!(middle[0], "lora/10");
+        assert_eq!(middle[PAGE_SIZE..], ["loras/0", "loras/2"]);
+
+        // Pages past the end show the last page.
+        let last = button_data(lora_keyboard(&loras, 5, &Translator::default()));
+        assert_eq!(last, ["lora/20", "lora/21", "lora/22", "loras/1"]);
+
+        let single = button_data(lora_keyboard(&loras[..3], 0, &Translator::default()));
+        assert_eq!(single, ["lora/0", "lora/1", "lora/2"]);
+    }
+
+    #[test]
+    fn test_page_text() {
+        let translator = Translator::default();
+        assert_eq!(page_text(3, 0, &translator), LIST_TEXT);
+        assert_eq!(
+            page_text(23, 1, &translator),
+            format!("{LIST_TEXT}\n\nPage 2 of 3.")
+        );
+    }
+
+    #[test]
+    fn test_lora_snippet() {
+        assert_eq!(lora_snippet("add_detail"), "<lora:add_detail:1.0>");
+    }
+}
@@ -1,22 +1,72 @@
+use std::sync::Arc;
+
 use anyhow::anyhow;
+use sal_e_api::GenParams;
 use teloxide::{
     dispatching::UpdateHandler,
+    dptree::case,
     prelude::*,
     types::{Me, ParseMode},
     utils::{command::BotCommands, markdown},
 };
+use tracing::{instrument, warn};
 
 use crate::BotState;
 
-use super::{ConfigParameters, DiffusionDialogue, State};
+use super::{
+    history::HistoryStore, i18n::Translator, retention, send::SendContext, AuthConfig,
+    BackendHandles, DialogueStorage, DiffusionDialogue, State, UiConfig,
+};
+
+mod admin;
+pub use admin::*;
+
+mod backend;
+pub use backend::*;
+
+mod cancel;
+pub use cancel::*;
+
+mod history;
+pub use history::*;
 
 mod image;
-pub(crate) use image::*;
+pub use image::*;
+
+mod inline;
+pub use inline::*;
+
+mod language;
+pub use language::*;
+
+mod lora;
+pub use lora::*;
+
+mod model;
+pub use model::*;
 
 mod settings;
-pub(crate) use settings::*;
+pub use settings::*;
+
+mod snippet;
+pub use snippet::*;
+
+mod status;
+pub use status::*;
+
+mod style;
+pub use style::*;
 
-#[derive(BotCommands, Clone)]
+mod vae;
+pub use vae::*;
+
+mod guest;
+pub use guest::*;
+
+mod variable;
+pub use variable::*;
+
+#[derive(BotCommands, Debug, Clone)]
 #[command(rename_rule = "lowercase", description = "Simple commands")]
 pub(crate) enum UnauthenticatedCommands {
     #[command(description = "show help message.")]
@@ -25,57 +75,146 @@ pub(crate) enum UnauthenticatedCommands {
     Start,
     #[command(description = "change settings.")]
     Settings,
+    #[command(description = "delete all data stored about you.")]
+    ForgetMe,
 }
 
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = ?cmd
+    )
+)]
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn unauthenticated_commands_handler(
-    cfg: ConfigParameters,
+    auth: Arc<AuthConfig>,
+    backends: Arc<BackendHandles>,
+    ui: Arc<UiConfig>,
     bot: Bot,
     me: teloxide::types::Me,
     msg: Message,
     cmd: UnauthenticatedCommands,
     dialogue: DiffusionDialogue,
+    translator: Translator,
 ) -> anyhow::Result<()> {
     let text = match cmd {
         UnauthenticatedCommands::Help => {
-            if cfg.chat_is_allowed(&msg.chat.id)
-                || cfg.chat_is_allowed(&msg.from().unwrap().id.into())
-            {
-                format!(
-                    "{}\n\n{}\n\n{}",
-                    UnauthenticatedCommands::descriptions(),
-                    SettingsCommands::descriptions(),
-                    GenCommands::descriptions()
-                )
+            let text = if auth.chat_is_allowed(Some(msg.chat.id), msg.from().map(|user| user.id)) {
+                let mut sections = vec![
+                    UnauthenticatedCommands::descriptions().to_string(),
+                    SettingsCommands::descriptions().to_string(),
+                    BackendCommands::descriptions().to_string(),
+                    ModelCommands::descriptions().to_string(),
+                    VaeCommands::descriptions().to_string(),
+                    LoraCommands::descriptions().to_string(),
+                    SnippetCommands::descriptions().to_string(),
+                    VarCommands::descriptions().to_string(),
+                    LanguageCommands::descriptions().to_string(),
+                    StyleCommands::descriptions().to_string(),
+                    HistoryCommands::descriptions().to_string(),
+                    GenCommands::descriptions().to_string(),
+                    RandomCommands::descriptions().to_string(),
+                    AbCommands::descriptions().to_string(),
+                    InpaintCommands::descriptions().to_string(),
+                    CancelCommands::descriptions().to_string(),
+                    StatusCommands::descriptions().to_string(),
+                ];
+                if auth.user_is_admin(&msg.from().unwrap().id.into()) {
+                    sections.push(AdminCommands::descriptions().to_string());
+                }
+                sections.join("\n\n")
             } else if msg.chat.is_group() || msg.chat.is_supergroup() {
                 UnauthenticatedCommands::descriptions()
                     .username_from_me(&me)
                     .to_string()
             } else {
                 UnauthenticatedCommands::descriptions().to_string()
-            }
+            };
+            translator.descriptions(&text)
         }
         UnauthenticatedCommands::Start => {
             dialogue
                 .update(State::Ready {
                     bot_state: BotState::default(),
-                    txt2img: cfg.txt2img_api.gen_params(None),
-                    img2img: cfg.img2img_api.gen_params(None),
+                    txt2img: backends.txt2img_defaults(msg.chat.id),
+                    img2img: backends.img2img_defaults(msg.chat.id),
                 })
                 .await
                 .map_err(|e| anyhow!(e))?;
-            "This bot generates images using stable diffusion! Enter a prompt to get started!"
-                .to_owned()
+            translator.text(
+                "This bot generates images using stable diffusion! Enter a prompt to get started!",
+            )
         }
-        UnauthenticatedCommands::Settings => "Sorry, not yet implemented.".to_owned(),
+        UnauthenticatedCommands::Settings => translator.text("Sorry, not yet implemented."),
+        UnauthenticatedCommands::ForgetMe => unreachable!("handled by handle_forget_me"),
     };
 
-    bot.send_message(msg.chat.id, markdown::escape(&text))
-        .parse_mode(ParseMode::MarkdownV2)
-        .await?;
+    let mut request = SendContext::of(&msg)
+        .send_message(&bot, markdown::escape(&text))
+        .parse_mode(ParseMode::MarkdownV2);
+    if let Some(reply_keyboard) = ui.reply_keyboard.as_ref().filter(|_| msg.chat.is_private()) {
+        request = request.reply_markup(reply_keyboard.markup());
+    }
+    request.await?;
 
     Ok(())
 }
 
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "forgetme"
+    )
+)]
+async fn handle_forget_me(
+    storage: DialogueStorage,
+    history: HistoryStore,
+    bot: Bot,
+    msg: Message,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let Some(user) = msg.from() else {
+        return Ok(());
+    };
+    retention::forget_user(storage, &history, user.id).await?;
+    SendContext::of(&msg)
+        .send_message(
+            &bot,
+            translator.text(
+                "All data stored about you has been deleted. Settings shared by a group chat are \
+                 kept.",
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Returns the txt2img settings saved in `chat_id`, or the defaults if it hasn't saved any or
+/// they were saved for another backend.
+pub(crate) async fn stored_txt2img(
+    storage: DialogueStorage,
+    backends: &BackendHandles,
+    chat_id: ChatId,
+) -> Box<dyn GenParams> {
+    let defaults = backends.txt2img_defaults(chat_id);
+    match storage.get_dialogue(chat_id).await {
+        Ok(Some(State::Ready { txt2img, .. }))
+            if txt2img.as_any().type_id() == defaults.as_any().type_id() =>
+        {
+            backends.txt2img_api.gen_params(Some(txt2img.as_ref()))
+        }
+        Ok(_) => defaults,
+        Err(e) => {
+            warn!("Failed to get stored settings: {:?}", e);
+            defaults
+        }
+    }
+}
+
 pub(crate) fn filter_map_bot_state() -> UpdateHandler<anyhow::Error> {
     dptree::filter_map(|state: State| match state {
         State::Ready { bot_state, .. } => Some(bot_state),
@@ -92,15 +231,12 @@ pub(crate) fn filter_map_settings() -> UpdateHandler<anyhow::Error> {
     })
 }
 
-pub(crate) fn auth_filter() -> UpdateHandler<anyhow::Error> {
-    dptree::filter(|cfg: ConfigParameters, upd: Update| {
-        upd.chat()
-            .map(|chat| cfg.chat_is_allowed(&chat.id))
-            .unwrap_or_default()
-            || upd
-                .user()
-                .map(|user| cfg.chat_is_allowed(&user.id.into()))
-                .unwrap_or_default()
+pub fn auth_filter() -> UpdateHandler<anyhow::Error> {
+    dptree::filter(|auth: Arc<AuthConfig>, upd: Update| {
+        auth.chat_is_allowed(
+            upd.chat().map(|chat| chat.id),
+            upd.user().map(|user| user.id),
+        )
     })
 }
 
@@ -125,19 +261,38 @@ pub(crate) fn unauth_command_filter() -> UpdateHandler<anyhow::Error> {
     Update::filter_message().chain(teloxide::filter_command::<UnauthenticatedCommands, _>())
 }
 
-pub(crate) fn unauth_command_handler() -> UpdateHandler<anyhow::Error> {
-    unauth_command_filter().endpoint(unauthenticated_commands_handler)
+pub fn unauth_command_handler() -> UpdateHandler<anyhow::Error> {
+    unauth_command_filter()
+        .branch(case![UnauthenticatedCommands::ForgetMe].endpoint(handle_forget_me))
+        .endpoint(unauthenticated_commands_handler)
 }
 
-pub(crate) fn authenticated_command_handler() -> UpdateHandler<anyhow::Error> {
+pub fn authenticated_command_handler() -> UpdateHandler<anyhow::Error> {
     auth_filter()
+        .branch(cancel_schema())
+        .branch(status_schema())
+        .branch(backend_schema())
+        .branch(model_schema())
+        .branch(vae_schema())
+        .branch(lora_schema())
+        .branch(snippet_schema())
+        .branch(var_schema())
+        .branch(language_schema())
+        .branch(style_schema())
+        .branch(history_schema())
         .branch(settings_schema())
         .branch(image_schema())
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::RwLock;
+
     use super::*;
+    use crate::bot::{
+        backends::{BackendRegistry, DEFAULT_BACKEND},
+        ConfigParameters,
+    };
     use async_trait::async_trait;
     use sal_e_api::{
         GenParams, Img2ImgApi, Img2ImgApiError, Img2ImgParams, Response, Txt2ImgApi,
@@ -216,12 +371,33 @@ mod tests {
     }
 
     fn create_config(allowed_users: Vec<i64>, allow_all_users: bool) -> ConfigParameters {
-        ConfigParameters {
-            allowed_users: allowed_users.into_iter().map(ChatId).collect(),
-            allow_all_users,
+        let backends = Arc::new(BackendHandles {
+            name: DEFAULT_BACKEND.to_owned(),
             txt2img_api: Box::new(MockApi),
             img2img_api: Box::new(MockApi),
-        }
+            describer: None,
+            blank_check: None,
+            upscaler: None,
+            jobs: Default::default(),
+            stats: Default::default(),
+            metrics: Default::default(),
+            defaults: Default::default(),
+        });
+        ConfigParameters::new(
+            Arc::new(AuthConfig {
+                allowed_users: Arc::new(RwLock::new(
+                    allowed_users.into_iter().map(ChatId).collect(),
+                )),
+                allowed_chats: Default::default(),
+                banned_users: Default::default(),
+                admin_users: Default::default(),
+                allow_all_users,
+                accounts: Default::default(),
+            }),
+            Default::default(),
+            backends.clone(),
+            Arc::new(BackendRegistry::new(backends, Vec::new()).unwrap()),
+        )
     }
 
     #[tokio::test]
@@ -264,6 +440,29 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn test_unauth_command_filter_forgetme() {
+        let me = create_me();
+
+        let msg = create_message("/forgetme");
+
+        let update = Update {
+            id: 1,
+            kind: UpdateKind::Message(msg.clone()),
+        };
+
+        assert!(matches!(
+            unauth_command_filter()
+                .endpoint(|cmd: UnauthenticatedCommands| async move {
+                    assert!(matches!(cmd, UnauthenticatedCommands::ForgetMe));
+                    anyhow::Ok(())
+                })
+                .dispatch(dptree::deps![msg, update, me])
+                .await,
+            ControlFlow::Break(_)
+        ));
+    }
+
     #[tokio::test]
     async fn test_unauth_command_filter_settings() {
         let me = create_me();
@@ -300,7 +499,7 @@ mod tests {
         assert!(matches!(
             auth_filter()
                 .endpoint(|| async move { anyhow::Ok(()) })
-                .dispatch(dptree::deps![msg, update, me, cfg])
+                .dispatch(dptree::deps![msg, update, me, cfg.auth])
                 .await,
             ControlFlow::Break(_)
         ));
@@ -322,7 +521,7 @@ mod tests {
         assert!(matches!(
             auth_filter()
                 .endpoint(|| async move { anyhow::Ok(()) })
-                .dispatch(dptree::deps![msg, update, me, cfg])
+                .dispatch(dptree::deps![msg, update, me, cfg.auth])
                 .await,
             ControlFlow::Continue(_)
         ));
@@ -344,7 +543,7 @@ mod tests {
         assert!(matches!(
             auth_filter()
                 .endpoint(|| async move { anyhow::Ok(()) })
-                .dispatch(dptree::deps![msg, update, me, cfg])
+                .dispatch(dptree::deps![msg, update, me, cfg.auth])
                 .await,
             ControlFlow::Break(_)
         ));
@@ -366,7 +565,7 @@ mod tests {
         assert!(matches!(
             auth_filter()
                 .endpoint(|| async move { anyhow::Ok(()) })
-                .dispatch(dptree::deps![msg, update, me, cfg])
+                .dispatch(dptree::deps![msg, update, me, cfg.auth])
                 .await,
             ControlFlow::Break(_)
         ));
@@ -0,0 +1,266 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use teloxide::{
+    dispatching::UpdateHandler,
+    dptree::case,
+    macros::BotCommands,
+    payloads::setters::*,
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+use tracing::{instrument, warn};
+
+use super::{filter_command, BackendHandles, DiffusionDialogue, SendContext, State};
+use crate::bot::i18n::Translator;
+
+/// Telegram allows at most 100 buttons in an inline keyboard.
+const MAX_MODELS: usize = 100;
+
+/// BotCommands for choosing the model.
+#[derive(BotCommands, Debug, Clone)]
+#[command(rename_rule = "lowercase", description = "Model commands")]
+pub(crate) enum ModelCommands {
+    /// Command to choose the model to generate images with.
+    #[command(description = "choose the model to generate images with.")]
+    Model,
+}
+
+/// Returns the model currently selected in `state`, falling back to the default in `chat_id`.
+fn current_model(backends: &BackendHandles, state: &State, chat_id: ChatId) -> Option<String> {
+    match state {
+        State::Ready { txt2img, .. } => txt2img.model(),
+        State::New => backends.txt2img_defaults(chat_id).model(),
+    }
+}
+
+/// Builds an inline keyboard listing `models`, marking the `current` one.
+fn model_keyboard(models: &[String], current: Option<&str>) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(
+        models
+            .iter()
+            .take(MAX_MODELS)
+            .enumerate()
+            .map(|(index, model)| {
+                let text = if current == Some(model.as_str()) {
+                    format!("✅ {model}")
+                } else {
+                    model.clone()
+                };
+                [InlineKeyboardButton::callback(
+                    text,
+                    format!("model/{index}"),
+                )]
+            }),
+    )
+}
+
+/// Lists the backend's models, or returns a message explaining why they can't be listed.
+async fn list_models(
+    backends: &BackendHandles,
+    translator: &Translator,
+) -> Result<Vec<String>, String> {
+    match backends.txt2img_api.models().await {
+        Ok(models) if models.is_empty() => {
+            Err(translator.text("The backend has no models to choose from."))
+        }
+        Ok(models) => Ok(models),
+        Err(e) => {
+            warn!("Failed to list models: {:?}", e);
+            Err(translator.text("Sorry, the list of models isn't available."))
+        }
+    }
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "model"
+    )
+)]
+async fn handle_model_command(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    msg: Message,
+    state: State,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    match list_models(&backends, &translator).await {
+        Ok(models) => {
+            let current = current_model(&backends, &state, msg.chat.id);
+            SendContext::of(&msg)
+                .send_message(&bot, translator.text("Please choose a model."))
+                .reply_markup(model_keyboard(&models, current.as_deref()))
+                .await?;
+        }
+        Err(text) => {
+            SendContext::of(&msg).send_message(&bot, text).await?;
+        }
+    }
+    Ok(())
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "model"
+    )
+)]
+async fn handle_model_button(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    state: State,
+    q: CallbackQuery,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let Some(message) = q.message else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, this message is no longer available."))
+            .await?;
+        return Ok(());
+    };
+    let models = match list_models(&backends, &translator).await {
+        Ok(models) => models,
+        Err(text) => {
+            bot.answer_callback_query(q.id).text(text).await?;
+            return Ok(());
+        }
+    };
+    if let Err(e) = bot.answer_callback_query(q.id).await {
+        warn!("Failed to answer model button callback query: {}", e)
+    }
+    let current = current_model(&backends, &state, message.chat.id);
+    bot.edit_message_text(
+        message.chat.id,
+        message.id,
+        translator.text("Please choose a model."),
+    )
+    .reply_markup(model_keyboard(&models, current.as_deref()))
+    .await?;
+    Ok(())
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "model"
+    )
+)]
+async fn handle_model_selection(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    dialogue: DiffusionDialogue,
+    state: State,
+    q: CallbackQuery,
+    index: usize,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let Some(message) = q.message else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, this message is no longer available."))
+            .await?;
+        return Ok(());
+    };
+    // The list is fetched again, so a model removed since the keyboard was sent can't be chosen.
+    let model = match list_models(&backends, &translator).await {
+        Ok(models) => models.into_iter().nth(index),
+        Err(text) => {
+            bot.answer_callback_query(q.id).text(text).await?;
+            return Ok(());
+        }
+    };
+    let Some(model) = model else {
+        bot.answer_callback_query(q.id)
+            .text(translator.text("Sorry, this model is no longer available."))
+            .await?;
+        return Ok(());
+    };
+
+    let state = match state {
+        State::Ready {
+            bot_state,
+            mut txt2img,
+            mut img2img,
+        } => {
+            txt2img.set_model(model.clone());
+            img2img.set_model(model.clone());
+            State::Ready {
+                bot_state,
+                txt2img,
+                img2img,
+            }
+        }
+        State::New => {
+            let mut txt2img = backends.txt2img_defaults(message.chat.id);
+            let mut img2img = backends.img2img_defaults(message.chat.id);
+            txt2img.set_model(model.clone());
+            img2img.set_model(model.clone());
+            State::new_with_defaults(txt2img, img2img)
+        }
+    };
+    dialogue.update(state).await.map_err(|e| anyhow!(e))?;
+
+    if let Err(e) = bot
+        .answer_callback_query(q.id)
+        .text(translator.text("Model set."))
+        .await
+    {
+        warn!("Failed to answer model selection callback query: {}", e)
+    }
+    bot.edit_message_text(
+        message.chat.id,
+        message.id,
+        translator.format("Using {name}.", &[("name", &model)]),
+    )
+    .reply_markup(InlineKeyboardMarkup::default())
+    .await?;
+    Ok(())
+}
+
+pub fn model_schema() -> UpdateHandler<anyhow::Error> {
+    let command_handler = Update::filter_message()
+        .chain(filter_command::<ModelCommands>())
+        .branch(case![ModelCommands::Model].endpoint(handle_model_command));
+
+    let callback_handler = Update::filter_callback_query()
+        .branch(
+            dptree::filter(|q: CallbackQuery| q.data.filter(|d| d == "model").is_some())
+                .endpoint(handle_model_button),
+        )
+        .branch(
+            dptree::filter_map(|q: CallbackQuery| {
+                q.data?.strip_prefix("model/")?.parse::<usize>().ok()
+            })
+            .endpoint(handle_model_selection),
+        );
+
+    dptree::entry()
+        .branch(command_handler)
+        .branch(callback_handler)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_keyboard() {
+        let models = vec!["a.safetensors".to_owned(), "b.safetensors".to_owned()];
+        let keyboard = model_keyboard(&models, Some("b.safetensors"));
+        let buttons = keyboard
+            .inline_keyboard
+            .into_iter()
+            .flatten()
+            .map(|button| button.text)
+            .collect::<Vec<_>>();
+        assert_eq!(buttons, vec!["a.safetensors", "✅ b.safetensors"]);
+    }
+}
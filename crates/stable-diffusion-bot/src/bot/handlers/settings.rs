@@ -1,6 +1,8 @@
+use std::sync::Arc;
+
 use anyhow::anyhow;
 use itertools::Itertools as _;
-use sal_e_api::GenParams;
+use sal_e_api::{format_seed, parse_seed, GenParams, SeedMode};
 use teloxide::{
     dispatching::UpdateHandler,
     dptree::case,
@@ -9,11 +11,22 @@ use teloxide::{
     prelude::*,
     types::{InlineKeyboardButton, InlineKeyboardMarkup},
 };
-use tracing::{error, warn};
-
-use crate::{bot::ConfigParameters, BotState};
+use tracing::{debug, error, instrument, warn};
+
+use crate::{
+    bot::{
+        history::HistoryStore,
+        i18n::Translator,
+        presets,
+        settings_store::{self, SettingsKind},
+        size_presets, styles, BackendHandles,
+    },
+    BotState,
+};
 
-use super::{filter_map_bot_state, filter_map_settings, DiffusionDialogue, State};
+use super::{
+    filter_map_bot_state, filter_map_settings, DiffusionDialogue, SendContext, State, UiConfig,
+};
 
 /// BotCommands for settings.
 #[derive(BotCommands, Clone)]
@@ -25,6 +38,15 @@ pub(crate) enum SettingsCommands {
     /// Command to set img2img settings
     #[command(description = "img2img settings")]
     Img2ImgSettings,
+    /// Command to show the settings that will be used
+    #[command(description = "show the settings your next images will use")]
+    Current,
+    /// Command to reuse the seed of the last image
+    #[command(description = "use the seed of the last image in this chat")]
+    LastSeed,
+    /// Command to reset the settings to the defaults
+    #[command(description = "reset your settings to the defaults")]
+    ResetSettings,
 }
 
 /// User-configurable image generation settings.
@@ -34,6 +56,8 @@ pub(crate) struct Settings {
     pub steps: Option<u32>,
     // Random seed.
     pub seed: Option<i64>,
+    // How a fixed seed changes after each generation.
+    pub seed_mode: Option<SeedMode>,
     // Number of images to generate per batch.
     pub batch_size: Option<u32>,
     // Number of batches of images to generate.
@@ -50,62 +74,533 @@ pub(crate) struct Settings {
     pub denoising_strength: Option<f32>,
     // Sampler name.
     pub sampler_index: Option<String>,
+    // Whether the backend supports the advanced sampler settings below.
+    pub advanced_sampler: bool,
+    // Sampler eta.
+    pub eta: Option<f32>,
+    // Sampler churn.
+    pub s_churn: Option<f32>,
+    // Sampler minimum sigma.
+    pub s_tmin: Option<f32>,
+    // Sampler maximum sigma.
+    pub s_tmax: Option<f32>,
+    // Sampler noise multiplier.
+    pub s_noise: Option<f32>,
+    // Whether the backend supports the face restoration settings below.
+    pub face_restoration: bool,
+    // Whether faces are restored.
+    pub restore_faces: Option<bool>,
+    // Face restoration model.
+    pub face_restoration_model: Option<String>,
+    // CodeFormer weight.
+    pub codeformer_weight: Option<f32>,
+    // Whether the backend supports the hires fix settings below.
+    pub hires_fix: bool,
+    // Whether the hires fix is enabled.
+    pub enable_hr: Option<bool>,
+    // Hires fix upscale factor.
+    pub hr_scale: Option<f32>,
+    // Hires fix upscaler.
+    pub hr_upscaler: Option<String>,
+    // Hires fix second pass steps.
+    pub hr_second_pass_steps: Option<u32>,
+    // Whether the backend supports the refiner settings below.
+    pub refiner: bool,
+    // Refiner checkpoint, if the refiner is enabled.
+    pub refiner_checkpoint: Option<String>,
+    // Fraction of the steps after which the refiner is used.
+    pub refiner_switch_at: Option<f32>,
+    // Names of the configured negative prompt presets, and whether each is enabled.
+    pub negative_presets: Vec<(String, bool)>,
+    // Model name.
+    pub model: Option<String>,
+    // Whether the backend supports choosing the VAE.
+    pub supports_vae: bool,
+    // VAE name.
+    pub vae: Option<String>,
+    // Whether changes to common settings also apply to the other kind of settings, if known.
+    pub apply_to_both: Option<bool>,
 }
 
 impl Settings {
     /// Build an inline keyboard to configure settings.
-    pub fn keyboard(&self) -> InlineKeyboardMarkup {
+    pub fn keyboard(&self, translator: &Translator) -> InlineKeyboardMarkup {
+        let label = |text: &str, value: &str| translator.format(text, &[("value", value)]);
+        let default = translator.text("default");
         InlineKeyboardMarkup::new(
             [
+                Some(InlineKeyboardButton::callback(
+                    label("Model: {value}", self.model.as_deref().unwrap_or(&default)),
+                    "model",
+                )),
+                self.supports_vae.then(|| {
+                    InlineKeyboardButton::callback(
+                        label("VAE: {value}", self.vae.as_deref().unwrap_or(&default)),
+                        "vae",
+                    )
+                }),
                 self.steps.map(|steps| {
-                    InlineKeyboardButton::callback(format!("Steps: {}", steps), "settings_steps")
+                    InlineKeyboardButton::callback(
+                        label("Steps: {value}", &steps.to_string()),
+                        "settings_steps",
+                    )
                 }),
                 self.seed.map(|seed| {
-                    InlineKeyboardButton::callback(format!("Seed: {}", seed), "settings_seed")
+                    InlineKeyboardButton::callback(
+                        label("Seed: {value}", &format_seed(seed)),
+                        "settings_seed",
+                    )
+                }),
+                self.seed_mode.map(|mode| {
+                    InlineKeyboardButton::callback(
+                        label("Seed Mode: {value}", &mode.to_string()),
+                        "settings_seed_mode",
+                    )
                 }),
                 self.n_iter.map(|n_iter| {
                     InlineKeyboardButton::callback(
-                        format!("Batch Count: {}", n_iter),
+                        label("Batch Count: {value}", &n_iter.to_string()),
                         "settings_count",
                     )
                 }),
                 self.cfg_scale.map(|cfg_scale| {
                     InlineKeyboardButton::callback(
-                        format!("CFG Scale: {}", cfg_scale),
+                        label("CFG Scale: {value}", &cfg_scale.to_string()),
                         "settings_cfg",
                     )
                 }),
                 self.width.map(|width| {
-                    InlineKeyboardButton::callback(format!("Width: {}", width), "settings_width")
+                    InlineKeyboardButton::callback(
+                        label("Width: {value}", &width.to_string()),
+                        "settings_width",
+                    )
                 }),
                 self.height.map(|height| {
-                    InlineKeyboardButton::callback(format!("Height: {}", height), "settings_height")
+                    InlineKeyboardButton::callback(
+                        label("Height: {value}", &height.to_string()),
+                        "settings_height",
+                    )
+                }),
+                (self.width.is_some() && self.height.is_some()).then(|| {
+                    InlineKeyboardButton::callback(translator.text("📐 Size"), "settings_size")
                 }),
                 self.negative_prompt.as_ref().map(|_| {
                     InlineKeyboardButton::callback(
-                        "Negative Prompt".to_owned(),
+                        translator.text("Negative Prompt"),
                         "settings_negative",
                     )
                 }),
+            ]
+            .into_iter()
+            .chain(
+                self.negative_presets
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (name, enabled))| {
+                        Some(InlineKeyboardButton::callback(
+                            format!("{} {}", if *enabled { "✅" } else { "⬜" }, name),
+                            format!("settings_preset/{index}"),
+                        ))
+                    }),
+            )
+            .chain([
                 self.denoising_strength.map(|denoising_strength| {
                     InlineKeyboardButton::callback(
-                        format!("Denoising Strength: {}", denoising_strength),
+                        label(
+                            "Denoising Strength: {value}",
+                            &denoising_strength.to_string(),
+                        ),
                         "settings_denoising",
                     )
                 }),
+                (self.advanced_sampler || self.refiner).then(|| {
+                    InlineKeyboardButton::callback(translator.text("Advanced"), "settings_advanced")
+                }),
+                self.face_restoration.then(|| {
+                    InlineKeyboardButton::callback(translator.text("Faces"), "settings_faces")
+                }),
+                self.hires_fix.then(|| {
+                    InlineKeyboardButton::callback(translator.text("Hires Fix"), "settings_hires")
+                }),
+                self.apply_to_both.map(|enabled| {
+                    InlineKeyboardButton::callback(
+                        format!(
+                            "{} {}",
+                            if enabled { "✅" } else { "⬜" },
+                            translator.text("Apply to both")
+                        ),
+                        "settings_link",
+                    )
+                }),
                 Some(InlineKeyboardButton::callback(
-                    "Cancel".to_owned(),
+                    translator.text("Cancel"),
                     "settings_back",
                 )),
+            ])
+            .flatten()
+            .chunks(2)
+            .into_iter()
+            .map(Iterator::collect)
+            .collect::<Vec<Vec<_>>>(),
+        )
+    }
+
+    /// Build an inline keyboard to configure the advanced sampler and refiner settings.
+    pub fn advanced_keyboard(&self, translator: &Translator) -> InlineKeyboardMarkup {
+        let button = |name: &str, value: Option<f32>, setting: &str| {
+            InlineKeyboardButton::callback(
+                translator.format(
+                    name,
+                    &[(
+                        "value",
+                        &value.map_or_else(|| translator.text("default"), |v| v.to_string()),
+                    )],
+                ),
+                format!("settings_{setting}"),
+            )
+        };
+        let sampler = self.advanced_sampler.then(|| {
+            [
+                button("Eta: {value}", self.eta, "eta"),
+                button("Churn: {value}", self.s_churn, "s_churn"),
+                button("Sigma Min: {value}", self.s_tmin, "s_tmin"),
+                button("Sigma Max: {value}", self.s_tmax, "s_tmax"),
+                button("Noise: {value}", self.s_noise, "s_noise"),
+            ]
+        });
+        let refiner = self.refiner.then(|| {
+            [
+                InlineKeyboardButton::callback(
+                    translator.format(
+                        "Refiner: {value}",
+                        &[(
+                            "value",
+                            &self
+                                .refiner_checkpoint
+                                .clone()
+                                .unwrap_or_else(|| translator.text("off")),
+                        )],
+                    ),
+                    "settings_refiner",
+                ),
+                button(
+                    "Switch At: {value}",
+                    self.refiner_switch_at,
+                    "refiner_switch_at",
+                ),
+            ]
+        });
+        InlineKeyboardMarkup::new(
+            sampler
+                .into_iter()
+                .flatten()
+                .chain(refiner.into_iter().flatten())
+                .chain([InlineKeyboardButton::callback(
+                    translator.text("Back"),
+                    "settings_main",
+                )])
+                .chunks(2)
+                .into_iter()
+                .map(Iterator::collect)
+                .collect::<Vec<Vec<_>>>(),
+        )
+    }
+}
+
+impl Settings {
+    /// Returns the settings as text, one per line, leaving out settings the backend doesn't use.
+    pub fn summary(&self, translator: &Translator) -> String {
+        let line = |text: &str, value: &str| translator.format(text, &[("value", value)]);
+        let default = translator.text("default");
+        let off = translator.text("off");
+        let optional =
+            |value: Option<f32>| value.map_or_else(|| default.clone(), |v| v.to_string());
+        let enabled_presets = self
+            .negative_presets
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| name)
+            .join(", ");
+        [
+            Some(line(
+                "Model: {value}",
+                self.model.as_deref().unwrap_or(&default),
+            )),
+            self.supports_vae
+                .then(|| line("VAE: {value}", self.vae.as_deref().unwrap_or(&default))),
+            self.sampler_index
+                .as_ref()
+                .map(|sampler| line("Sampler: {value}", sampler)),
+            self.steps
+                .map(|steps| line("Steps: {value}", &steps.to_string())),
+            self.seed
+                .map(|seed| line("Seed: {value}", &format_seed(seed))),
+            self.seed_mode
+                .map(|mode| line("Seed Mode: {value}", &mode.to_string())),
+            self.n_iter
+                .map(|n_iter| line("Batch Count: {value}", &n_iter.to_string())),
+            self.batch_size
+                .map(|batch_size| line("Batch Size: {value}", &batch_size.to_string())),
+            self.cfg_scale
+                .map(|cfg_scale| line("CFG Scale: {value}", &cfg_scale.to_string())),
+            self.width
+                .map(|width| line("Width: {value}", &width.to_string())),
+            self.height
+                .map(|height| line("Height: {value}", &height.to_string())),
+            self.denoising_strength
+                .map(|denoising| line("Denoising Strength: {value}", &denoising.to_string())),
+            self.negative_prompt
+                .as_ref()
+                .map(|negative_prompt| line("Negative Prompt: {value}", negative_prompt)),
+            (!enabled_presets.is_empty())
+                .then(|| line("Negative Presets: {value}", &enabled_presets)),
+            self.advanced_sampler.then(|| {
+                translator.format(
+                    "Eta: {eta}, Churn: {churn}, Sigma Min: {tmin}, Sigma Max: {tmax}, \
+                     Noise: {noise}",
+                    &[
+                        ("eta", &optional(self.eta)),
+                        ("churn", &optional(self.s_churn)),
+                        ("tmin", &optional(self.s_tmin)),
+                        ("tmax", &optional(self.s_tmax)),
+                        ("noise", &optional(self.s_noise)),
+                    ],
+                )
+            }),
+            self.face_restoration.then(|| {
+                line(
+                    "Restore Faces: {value}",
+                    &match self.restore_faces.unwrap_or_default() {
+                        true => translator.format(
+                            "on ({model}, CodeFormer Weight: {weight})",
+                            &[
+                                (
+                                    "model",
+                                    self.face_restoration_model.as_deref().unwrap_or(&default),
+                                ),
+                                ("weight", &optional(self.codeformer_weight)),
+                            ],
+                        ),
+                        false => off.clone(),
+                    },
+                )
+            }),
+            self.refiner.then(|| {
+                line(
+                    "Refiner: {value}",
+                    &match &self.refiner_checkpoint {
+                        Some(checkpoint) => translator.format(
+                            "{checkpoint} (Switch At: {switch_at})",
+                            &[
+                                ("checkpoint", checkpoint),
+                                ("switch_at", &optional(self.refiner_switch_at)),
+                            ],
+                        ),
+                        None => off.clone(),
+                    },
+                )
+            }),
+            self.hires_fix.then(|| {
+                line(
+                    "Hires Fix: {value}",
+                    &match self.enable_hr.unwrap_or_default() {
+                        true => translator.format(
+                            "on (Scale: {scale}, Upscaler: {upscaler}, \
+                             Second Pass Steps: {steps})",
+                            &[
+                                ("scale", &optional(self.hr_scale)),
+                                ("upscaler", self.hr_upscaler.as_deref().unwrap_or(&default)),
+                                (
+                                    "steps",
+                                    &self
+                                        .hr_second_pass_steps
+                                        .map_or_else(|| default.clone(), |v| v.to_string()),
+                                ),
+                            ],
+                        ),
+                        false => off.clone(),
+                    },
+                )
+            }),
+        ]
+        .into_iter()
+        .flatten()
+        .join("\n")
+    }
+
+    /// Build an inline keyboard to configure face restoration.
+    pub fn faces_keyboard(&self, translator: &Translator) -> InlineKeyboardMarkup {
+        let label = |text: &str, value: &str| translator.format(text, &[("value", value)]);
+        let default = translator.text("default");
+        let restore_faces = self.restore_faces.unwrap_or_default();
+        InlineKeyboardMarkup::new(
+            [
+                InlineKeyboardButton::callback(
+                    label(
+                        "Restore Faces: {value}",
+                        &translator.text(if restore_faces { "on" } else { "off" }),
+                    ),
+                    "settings_restore_faces",
+                ),
+                InlineKeyboardButton::callback(
+                    label(
+                        "Model: {value}",
+                        self.face_restoration_model.as_deref().unwrap_or(&default),
+                    ),
+                    "settings_face_model",
+                ),
+                InlineKeyboardButton::callback(
+                    label(
+                        "CodeFormer Weight: {value}",
+                        &self
+                            .codeformer_weight
+                            .map_or_else(|| default.clone(), |v| v.to_string()),
+                    ),
+                    "settings_codeformer_weight",
+                ),
+                InlineKeyboardButton::callback(translator.text("Back"), "settings_main"),
             ]
             .into_iter()
-            .flatten()
             .chunks(2)
             .into_iter()
             .map(Iterator::collect)
             .collect::<Vec<Vec<_>>>(),
         )
     }
+
+    /// Build an inline keyboard to configure the hires fix.
+    pub fn hires_keyboard(&self, translator: &Translator) -> InlineKeyboardMarkup {
+        let label = |text: &str, value: Option<String>| {
+            translator.format(
+                text,
+                &[(
+                    "value",
+                    &value.unwrap_or_else(|| translator.text("default")),
+                )],
+            )
+        };
+        InlineKeyboardMarkup::new(
+            [
+                InlineKeyboardButton::callback(
+                    label(
+                        "Hires Fix: {value}",
+                        Some(translator.text(if self.enable_hr.unwrap_or_default() {
+                            "on"
+                        } else {
+                            "off"
+                        })),
+                    ),
+                    "settings_enable_hr",
+                ),
+                InlineKeyboardButton::callback(
+                    label("Scale: {value}", self.hr_scale.map(|v| v.to_string())),
+                    "settings_hr_scale",
+                ),
+                InlineKeyboardButton::callback(
+                    label("Upscaler: {value}", self.hr_upscaler.clone()),
+                    "settings_hr_upscaler",
+                ),
+                InlineKeyboardButton::callback(
+                    label(
+                        "Second Pass Steps: {value}",
+                        self.hr_second_pass_steps.map(|v| v.to_string()),
+                    ),
+                    "settings_hr_steps",
+                ),
+                InlineKeyboardButton::callback(translator.text("Back"), "settings_main"),
+            ]
+            .into_iter()
+            .chunks(2)
+            .into_iter()
+            .map(Iterator::collect)
+            .collect::<Vec<Vec<_>>>(),
+        )
+    }
+
+    /// Build an inline keyboard to pick the denoising strength with quick picks and fine
+    /// adjustments, so it can be tweaked without typing.
+    pub fn denoising_keyboard(&self, translator: &Translator) -> InlineKeyboardMarkup {
+        let current = self.denoising_strength.unwrap_or_default();
+        let button = |label: String, value: f32| {
+            InlineKeyboardButton::callback(label, format!("settings_denoising/{value}"))
+        };
+        let quick_picks = (2..=9).map(|tenths| {
+            let value = tenths as f32 / 10.0;
+            let label = if value == current {
+                format!("✅ {value}")
+            } else {
+                value.to_string()
+            };
+            button(label, value)
+        });
+        let fine = [
+            button(
+                format!("− {DENOISING_STEP}"),
+                clamp_denoising(current - DENOISING_STEP),
+            ),
+            button(
+                translator.format("Denoising: {value}", &[("value", &current.to_string())]),
+                current,
+            ),
+            button(
+                format!("+ {DENOISING_STEP}"),
+                clamp_denoising(current + DENOISING_STEP),
+            ),
+        ];
+        InlineKeyboardMarkup::new(
+            quick_picks
+                .chunks(4)
+                .into_iter()
+                .map(Iterator::collect)
+                .chain([
+                    fine.to_vec(),
+                    vec![InlineKeyboardButton::callback(
+                        translator.text("Back"),
+                        "settings_main",
+                    )],
+                ])
+                .collect::<Vec<Vec<_>>>(),
+        )
+    }
+
+    /// Build an inline keyboard to pick the image size from common aspect ratios, offering only
+    /// sizes with at most `max_pixels` pixels.
+    pub fn size_keyboard(
+        &self,
+        max_pixels: Option<u32>,
+        translator: &Translator,
+    ) -> InlineKeyboardMarkup {
+        let current = self.width.zip(self.height);
+        let buttons = size_presets::available(max_pixels).map(|(ratio, width, height)| {
+            let label = format!("{ratio} {width}×{height}");
+            let label = if current == Some((width, height)) {
+                format!("✅ {label}")
+            } else {
+                label
+            };
+            InlineKeyboardButton::callback(label, format!("settings_size/{width}x{height}"))
+        });
+        InlineKeyboardMarkup::new(
+            buttons
+                .chunks(3)
+                .into_iter()
+                .map(Iterator::collect)
+                .chain([vec![InlineKeyboardButton::callback(
+                    translator.text("Back"),
+                    "settings_main",
+                )]])
+                .collect::<Vec<Vec<_>>>(),
+        )
+    }
+}
+
+/// How much the fine adjustment buttons change the denoising strength by.
+const DENOISING_STEP: f32 = 0.05;
+
+/// Clamps a denoising strength to its valid range, rounded to two decimals so repeated
+/// adjustments don't accumulate floating point noise.
+fn clamp_denoising(value: f32) -> f32 {
+    ((value * 100.0).round() / 100.0).clamp(0.0, 1.0)
 }
 
 impl From<&dyn GenParams> for Settings {
@@ -113,6 +608,7 @@ impl From<&dyn GenParams> for Settings {
         Self {
             steps: value.steps(),
             seed: value.seed(),
+            seed_mode: value.seed_mode(),
             batch_size: value.batch_size(),
             n_iter: value.count(),
             cfg_scale: value.cfg(),
@@ -121,18 +617,115 @@ impl From<&dyn GenParams> for Settings {
             negative_prompt: value.negative_prompt().clone(),
             denoising_strength: value.denoising(),
             sampler_index: value.sampler().clone(),
+            advanced_sampler: value.supports_advanced_sampler(),
+            eta: value.eta(),
+            s_churn: value.s_churn(),
+            s_tmin: value.s_tmin(),
+            s_tmax: value.s_tmax(),
+            s_noise: value.s_noise(),
+            face_restoration: value.supports_face_restoration(),
+            restore_faces: value.restore_faces(),
+            face_restoration_model: value.face_restoration_model(),
+            codeformer_weight: value.codeformer_weight(),
+            hires_fix: value.supports_hires_fix(),
+            enable_hr: value.hires_fix(),
+            hr_scale: value.hr_scale(),
+            hr_upscaler: value.hr_upscaler(),
+            hr_second_pass_steps: value.hr_second_pass_steps(),
+            refiner: value.supports_refiner(),
+            refiner_checkpoint: value.refiner_checkpoint(),
+            refiner_switch_at: value.refiner_switch_at(),
+            negative_presets: Vec::new(),
+            model: value.model(),
+            supports_vae: value.supports_vae(),
+            vae: value.vae(),
+            apply_to_both: None,
         }
     }
 }
 
+/// Builds the settings for `params`, including which negative prompt presets are enabled in the
+/// chat and whether its common settings are linked.
+async fn chat_settings(
+    params: &dyn GenParams,
+    ui: &UiConfig,
+    history: &HistoryStore,
+    chat_id: ChatId,
+) -> Settings {
+    let mut settings = Settings::from(params);
+    if !ui.negative_presets.is_empty() {
+        let enabled = presets::enabled_preset_names(history, chat_id).await;
+        settings.negative_presets = ui
+            .negative_presets
+            .iter()
+            .map(|preset| (preset.name.clone(), enabled.contains(&preset.name)))
+            .collect();
+    }
+    match history.linked_settings(chat_id).await {
+        Ok(linked) => settings.apply_to_both = Some(linked),
+        Err(e) => warn!("Failed to get linked settings: {:?}", e),
+    }
+    settings
+}
+
+/// Settings that txt2img and img2img have in common, which "Apply to both" changes together.
+const SHARED_SETTINGS: [&str; 10] = [
+    "steps", "cfg", "width", "height", "negative", "eta", "s_churn", "s_tmin", "s_tmax", "s_noise",
+];
+
+/// Applies a setting changed in `kind` to `other`, the other kind of settings, if it's a common
+/// setting and the chat linked its settings. Returns whether it was applied.
+async fn apply_to_other(
+    history: &HistoryStore,
+    chat_id: ChatId,
+    kind: SettingsKind,
+    other: &mut dyn GenParams,
+    setting: &str,
+    value: &str,
+) -> bool {
+    if !SHARED_SETTINGS.contains(&setting) {
+        return false;
+    }
+    match history.linked_settings(chat_id).await {
+        Ok(true) => {}
+        Ok(false) => return false,
+        Err(e) => {
+            warn!("Failed to get linked settings: {:?}", e);
+            return false;
+        }
+    }
+    let other_kind = match kind {
+        SettingsKind::Txt2Img => SettingsKind::Img2Img,
+        SettingsKind::Img2Img => SettingsKind::Txt2Img,
+    };
+    if let Err(e) = apply_setting(other_kind, other, setting, value) {
+        debug!("Not applying {} to both settings: {:?}", setting, e);
+        return false;
+    }
+    settings_store::save_for_chat(history, chat_id, other_kind, setting, value).await;
+    true
+}
+
 pub(crate) fn filter_callback_query_chat_id() -> UpdateHandler<anyhow::Error> {
     dptree::filter_map(|q: CallbackQuery| q.message.map(|m| m.chat.id))
 }
 
-pub(crate) async fn handle_message_expired(bot: Bot, q: CallbackQuery) -> anyhow::Result<()> {
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "settings"
+    )
+)]
+pub(crate) async fn handle_message_expired(
+    bot: Bot,
+    translator: Translator,
+    q: CallbackQuery,
+) -> anyhow::Result<()> {
     bot.answer_callback_query(q.id)
         .cache_time(60)
-        .text("Sorry, this message is no longer available.")
+        .text(translator.text("Sorry, this message is no longer available."))
         .await?;
     Ok(())
 }
@@ -141,24 +734,48 @@ pub(crate) fn filter_callback_query_parent() -> UpdateHandler<anyhow::Error> {
     dptree::filter_map(|q: CallbackQuery| q.message.and_then(|m| m.reply_to_message().cloned()))
 }
 
-pub(crate) async fn handle_parent_unavailable(bot: Bot, q: CallbackQuery) -> anyhow::Result<()> {
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "settings"
+    )
+)]
+pub(crate) async fn handle_parent_unavailable(
+    bot: Bot,
+    translator: Translator,
+    q: CallbackQuery,
+) -> anyhow::Result<()> {
     bot.answer_callback_query(q.id)
         .cache_time(60)
-        .text("Oops, something went wrong.")
+        .text(translator.text("Oops, something went wrong."))
         .await?;
     Ok(())
 }
 
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "settings"
+    )
+)]
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn handle_settings(
     bot: Bot,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
     dialogue: DiffusionDialogue,
     (txt2img, img2img): (Box<dyn GenParams>, Box<dyn GenParams>),
     q: CallbackQuery,
     chat_id: ChatId,
     parent: Message,
+    translator: Translator,
 ) -> anyhow::Result<()> {
     let settings = if parent.photo().is_some() {
-        let settings = Settings::from(img2img.as_ref());
+        let settings = chat_settings(img2img.as_ref(), &ui, &history, chat_id).await;
         dialogue
             .update(State::Ready {
                 bot_state: BotState::SettingsImg2Img { selection: None },
@@ -169,7 +786,7 @@ pub(crate) async fn handle_settings(
             .map_err(|e| anyhow!(e))?;
         settings
     } else if parent.text().is_some() {
-        let settings = Settings::from(txt2img.as_ref());
+        let settings = chat_settings(txt2img.as_ref(), &ui, &history, chat_id).await;
         dialogue
             .update(State::Ready {
                 bot_state: BotState::SettingsTxt2Img { selection: None },
@@ -182,7 +799,7 @@ pub(crate) async fn handle_settings(
     } else {
         bot.answer_callback_query(q.id)
             .cache_time(60)
-            .text("Oops, something went wrong.")
+            .text(translator.text("Oops, something went wrong."))
             .await?;
         return Ok(());
     };
@@ -190,20 +807,33 @@ pub(crate) async fn handle_settings(
     if let Err(e) = bot.answer_callback_query(q.id).await {
         warn!("Failed to answer settings callback query: {}", e)
     }
-    bot.send_message(chat_id, "Please make a selection.")
-        .reply_markup(settings.keyboard())
+    SendContext::of(&parent)
+        .send_message(&bot, translator.text("Please make a selection."))
+        .reply_markup(settings.keyboard(&translator))
         .send()
         .await?;
 
     Ok(())
 }
 
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "settings"
+    )
+)]
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn handle_settings_button(
     bot: Bot,
-    cfg: ConfigParameters,
+    backends: Arc<BackendHandles>,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
     dialogue: DiffusionDialogue,
     (_, txt2img, img2img): (Option<String>, Box<dyn GenParams>, Box<dyn GenParams>),
     q: CallbackQuery,
+    translator: Translator,
 ) -> anyhow::Result<()> {
     let (message, data) = match q {
         CallbackQuery {
@@ -214,7 +844,7 @@ pub(crate) async fn handle_settings_button(
         _ => {
             bot.answer_callback_query(q.id)
                 .cache_time(60)
-                .text("Sorry, something went wrong.")
+                .text(translator.text("Sorry, something went wrong."))
                 .await?;
             return Ok(());
         }
@@ -225,7 +855,7 @@ pub(crate) async fn handle_settings_button(
         None => {
             bot.answer_callback_query(q.id)
                 .cache_time(60)
-                .text("Sorry, something went wrong.")
+                .text(translator.text("Sorry, something went wrong."))
                 .await?;
             return Ok(());
         }
@@ -240,15 +870,23 @@ pub(crate) async fn handle_settings_button(
             })
             .await
             .map_err(|e| anyhow!(e))?;
-        if let Err(e) = bot.answer_callback_query(q.id).text("Canceled.").await {
+        if let Err(e) = bot
+            .answer_callback_query(q.id)
+            .text(translator.text("Canceled."))
+            .await
+        {
             warn!("Failed to answer back button callback query: {}", e)
         }
 
         if let Err(e) = bot.delete_message(message.chat.id, message.id).await {
             error!("Failed to delete message: {:?}", e);
-            bot.edit_message_text(message.chat.id, message.id, "Please enter a prompt.")
-                .reply_markup(InlineKeyboardMarkup::new([[]]))
-                .await?;
+            bot.edit_message_text(
+                message.chat.id,
+                message.id,
+                translator.text("Please enter a prompt."),
+            )
+            .reply_markup(InlineKeyboardMarkup::new([[]]))
+            .await?;
         }
         return Ok(());
     }
@@ -257,12 +895,259 @@ pub(crate) async fn handle_settings_button(
         .get()
         .await
         .map_err(|e| anyhow!(e))?
-        .unwrap_or_else(|| {
-            State::new_with_defaults(
-                cfg.txt2img_api.gen_params(None),
-                cfg.img2img_api.gen_params(None),
+        .unwrap_or_else(|| backends.default_state(dialogue.chat_id()));
+    let preset_toggled = match setting.strip_prefix("preset/") {
+        Some(index) => {
+            let Some(preset) = index
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| ui.negative_presets.get(index))
+            else {
+                bot.answer_callback_query(q.id)
+                    .cache_time(60)
+                    .text(translator.text("Sorry, this preset is no longer available."))
+                    .await?;
+                return Ok(());
+            };
+            let enabled = history
+                .toggle_negative_preset(message.chat.id, &preset.name)
+                .await?;
+            let text = if enabled {
+                "Enabled {preset}."
+            } else {
+                "Disabled {preset}."
+            };
+            Some(translator.format(text, &[("preset", &preset.name)]))
+        }
+        None => None,
+    };
+
+    let link_toggled = if setting == "link" {
+        let linked = history.toggle_linked_settings(message.chat.id).await?;
+        Some(translator.text(if linked {
+            "Changes to common settings now apply to txt2img and img2img."
+        } else {
+            "Changes to settings now only apply to the settings you're editing."
+        }))
+    } else {
+        None
+    };
+
+    let faces_toggled = if setting == "restore_faces" {
+        let Some((kind, params)) = selected_params_mut(&mut state) else {
+            bot.answer_callback_query(q.id)
+                .cache_time(60)
+                .text(translator.text("Sorry, something went wrong."))
+                .await?;
+            return Ok(());
+        };
+        let enabled = !params.restore_faces().unwrap_or_default();
+        params.set_restore_faces(enabled);
+        dialogue
+            .update(state.clone())
+            .await
+            .map_err(|e| anyhow!(e))?;
+        settings_store::save_for_chat(
+            &history,
+            message.chat.id,
+            kind,
+            setting,
+            &enabled.to_string(),
+        )
+        .await;
+        Some(translator.text(if enabled {
+            "Face restoration enabled."
+        } else {
+            "Face restoration disabled."
+        }))
+    } else {
+        None
+    };
+
+    let hires_toggled = if setting == "enable_hr" {
+        let Some((kind, params)) = selected_params_mut(&mut state) else {
+            bot.answer_callback_query(q.id)
+                .cache_time(60)
+                .text(translator.text("Sorry, something went wrong."))
+                .await?;
+            return Ok(());
+        };
+        let enabled = !params.hires_fix().unwrap_or_default();
+        params.set_hires_fix(enabled);
+        dialogue
+            .update(state.clone())
+            .await
+            .map_err(|e| anyhow!(e))?;
+        settings_store::save_for_chat(
+            &history,
+            message.chat.id,
+            kind,
+            setting,
+            &enabled.to_string(),
+        )
+        .await;
+        Some(translator.text(if enabled {
+            "Hires fix enabled."
+        } else {
+            "Hires fix disabled."
+        }))
+    } else {
+        None
+    };
+
+    let denoising_set = match setting.strip_prefix("denoising/") {
+        Some(value) => {
+            let (Ok(value), Some((kind, params))) =
+                (value.parse::<f32>(), selected_params_mut(&mut state))
+            else {
+                bot.answer_callback_query(q.id)
+                    .cache_time(60)
+                    .text(translator.text("Sorry, something went wrong."))
+                    .await?;
+                return Ok(());
+            };
+            let value = clamp_denoising(value);
+            if params.denoising() == Some(value) {
+                // Editing the keyboard to the same markup fails, so there's nothing to do.
+                if let Err(e) = bot.answer_callback_query(q.id).await {
+                    warn!("Failed to answer denoising callback query: {}", e)
+                }
+                return Ok(());
+            }
+            params.set_denoising(value);
+            dialogue
+                .update(state.clone())
+                .await
+                .map_err(|e| anyhow!(e))?;
+            settings_store::save_for_chat(
+                &history,
+                message.chat.id,
+                kind,
+                "denoising",
+                &value.to_string(),
             )
-        });
+            .await;
+            Some(translator.format(
+                "Denoising strength set to {value}.",
+                &[("value", &value.to_string())],
+            ))
+        }
+        None => None,
+    };
+
+    let size_set = match setting.strip_prefix("size/") {
+        Some(value) => {
+            let (Some((width, height)), Some((kind, params))) = (
+                size_presets::parse(value, ui.size_presets_max_pixels),
+                selected_params_mut(&mut state),
+            ) else {
+                bot.answer_callback_query(q.id)
+                    .cache_time(60)
+                    .text(translator.text("Sorry, this size is no longer available."))
+                    .await?;
+                return Ok(());
+            };
+            if params.width() == Some(width) && params.height() == Some(height) {
+                // Editing the keyboard to the same markup fails, so there's nothing to do.
+                if let Err(e) = bot.answer_callback_query(q.id).await {
+                    warn!("Failed to answer size callback query: {}", e)
+                }
+                return Ok(());
+            }
+            params.set_width(width);
+            params.set_height(height);
+            if let State::Ready {
+                txt2img, img2img, ..
+            } = &mut state
+            {
+                let other = match kind {
+                    SettingsKind::Txt2Img => img2img.as_mut(),
+                    SettingsKind::Img2Img => txt2img.as_mut(),
+                };
+                for (setting, value) in [("width", width), ("height", height)] {
+                    let value = value.to_string();
+                    settings_store::save_for_chat(&history, message.chat.id, kind, setting, &value)
+                        .await;
+                    apply_to_other(&history, message.chat.id, kind, other, setting, &value).await;
+                }
+            }
+            dialogue
+                .update(state.clone())
+                .await
+                .map_err(|e| anyhow!(e))?;
+            Some(translator.format(
+                "Size set to {width}×{height}.",
+                &[
+                    ("width", &width.to_string()),
+                    ("height", &height.to_string()),
+                ],
+            ))
+        }
+        None => None,
+    };
+
+    if setting == "advanced"
+        || setting == "main"
+        || setting == "faces"
+        || setting == "denoising"
+        || setting == "hires"
+        || setting == "size"
+        || preset_toggled.is_some()
+        || link_toggled.is_some()
+        || faces_toggled.is_some()
+        || hires_toggled.is_some()
+        || denoising_set.is_some()
+        || size_set.is_some()
+    {
+        let params = match &state {
+            State::Ready {
+                bot_state: BotState::SettingsTxt2Img { .. },
+                txt2img,
+                ..
+            } => txt2img.as_ref(),
+            State::Ready {
+                bot_state: BotState::SettingsImg2Img { .. },
+                img2img,
+                ..
+            } => img2img.as_ref(),
+            _ => {
+                bot.answer_callback_query(q.id)
+                    .cache_time(60)
+                    .text(translator.text("Sorry, something went wrong."))
+                    .await?;
+                return Ok(());
+            }
+        };
+        let settings = chat_settings(params, &ui, &history, message.chat.id).await;
+        let mut answer = bot.answer_callback_query(q.id);
+        if let Some(text) = preset_toggled
+            .or(link_toggled)
+            .or(faces_toggled)
+            .or(hires_toggled)
+            .or(denoising_set)
+            .or(size_set)
+        {
+            answer = answer.text(text);
+        }
+        if let Err(e) = answer.await {
+            warn!("Failed to answer settings page callback query: {}", e)
+        }
+        let keyboard = match setting {
+            "advanced" => settings.advanced_keyboard(&translator),
+            "faces" | "restore_faces" => settings.faces_keyboard(&translator),
+            "hires" | "enable_hr" => settings.hires_keyboard(&translator),
+            setting if setting.starts_with("denoising") => settings.denoising_keyboard(&translator),
+            setting if setting.starts_with("size") => {
+                settings.size_keyboard(ui.size_presets_max_pixels, &translator)
+            }
+            _ => settings.keyboard(&translator),
+        };
+        bot.edit_message_reply_markup(message.chat.id, message.id)
+            .reply_markup(keyboard)
+            .await?;
+        return Ok(());
+    }
+
     match &mut state {
         State::Ready {
             bot_state: BotState::SettingsTxt2Img { selection },
@@ -275,7 +1160,7 @@ pub(crate) async fn handle_settings_button(
         _ => {
             bot.answer_callback_query(q.id)
                 .cache_time(60)
-                .text("Sorry, something went wrong.")
+                .text(translator.text("Sorry, something went wrong."))
                 .await?;
             return Ok(());
         }
@@ -286,12 +1171,47 @@ pub(crate) async fn handle_settings_button(
     }
     dialogue.update(state).await.map_err(|e| anyhow!(e))?;
 
-    bot.send_message(message.chat.id, "Please enter a new value.")
+    SendContext::of(&message)
+        .send_message(&bot, translator.text("Please enter a new value."))
         .await?;
 
     Ok(())
 }
 
+/// Returns the parameters being configured in a settings state, and which they are.
+fn selected_params_mut(state: &mut State) -> Option<(SettingsKind, &mut dyn GenParams)> {
+    match state {
+        State::Ready {
+            bot_state: BotState::SettingsTxt2Img { .. },
+            txt2img,
+            ..
+        } => Some((SettingsKind::Txt2Img, txt2img.as_mut())),
+        State::Ready {
+            bot_state: BotState::SettingsImg2Img { .. },
+            img2img,
+            ..
+        } => Some((SettingsKind::Img2Img, img2img.as_mut())),
+        _ => None,
+    }
+}
+
+/// Applies a setting saved with [`settings_store`] to `params`, including the settings toggled
+/// with buttons.
+pub(crate) fn apply_setting(
+    kind: SettingsKind,
+    params: &mut dyn GenParams,
+    setting: &str,
+    value: &str,
+) -> anyhow::Result<()> {
+    match (setting, kind) {
+        ("restore_faces", _) => params.set_restore_faces(value.parse()?),
+        ("enable_hr", _) => params.set_hires_fix(value.parse()?),
+        (setting, SettingsKind::Txt2Img) => update_txt2img_setting(params, setting, value)?,
+        (setting, SettingsKind::Img2Img) => update_img2img_setting(params, setting, value)?,
+    }
+    Ok(())
+}
+
 fn update_txt2img_setting<S1, S2>(
     txt2img: &mut dyn GenParams,
     setting: S1,
@@ -304,14 +1224,24 @@ where
     let value = value.as_ref();
     match setting.as_ref() {
         "steps" => txt2img.set_steps(value.parse()?),
-        "seed" => txt2img.set_seed(value.parse()?),
+        "seed" => txt2img.set_seed(parse_seed(value)?),
+        "seed_mode" => txt2img.set_seed_mode(value.parse()?),
         "count" => txt2img.set_count(value.parse()?),
         "cfg" => txt2img.set_cfg(value.parse()?),
         "width" => txt2img.set_width(value.parse()?),
         "height" => txt2img.set_height(value.parse()?),
         "negative" => txt2img.set_negative_prompt(value.to_owned()),
         "denoising" => txt2img.set_denoising(value.parse()?),
-        _ => return Err(anyhow!("Got invalid setting: {}", setting.as_ref())),
+        setting @ ("face_model" | "codeformer_weight") => {
+            update_face_restoration_setting(txt2img, setting, value)?
+        }
+        setting @ ("hr_scale" | "hr_upscaler" | "hr_steps") => {
+            update_hires_fix_setting(txt2img, setting, value)?
+        }
+        setting @ ("refiner" | "refiner_switch_at") => {
+            update_refiner_setting(txt2img, setting, value)?
+        }
+        setting => update_advanced_sampler_setting(txt2img, setting, value)?,
     }
     Ok(())
 }
@@ -328,7 +1258,8 @@ where
     let value = value.as_ref();
     match setting.as_ref() {
         "steps" => img2img.set_steps(200.min(value.parse()?)),
-        "seed" => img2img.set_seed((-1).max(value.parse()?)),
+        "seed" => img2img.set_seed(parse_seed(value)?),
+        "seed_mode" => img2img.set_seed_mode(value.parse()?),
         "count" => img2img.set_count(value.parse::<u32>()?.clamp(1, 10)),
         "cfg" => img2img.set_cfg(value.parse::<f32>()?.clamp(0.0, 20.0)),
         "width" => img2img.set_width({
@@ -343,24 +1274,109 @@ where
         }),
         "negative" => img2img.set_negative_prompt(value.to_owned()),
         "denoising" => img2img.set_denoising(value.parse::<f32>()?.clamp(0.0, 1.0)),
-        _ => return Err(anyhow!("invalid setting: {}", setting.as_ref())),
+        setting @ ("face_model" | "codeformer_weight") => {
+            update_face_restoration_setting(img2img, setting, value)?
+        }
+        setting @ ("hr_scale" | "hr_upscaler" | "hr_steps") => {
+            update_hires_fix_setting(img2img, setting, value)?
+        }
+        setting @ ("refiner" | "refiner_switch_at") => {
+            update_refiner_setting(img2img, setting, value)?
+        }
+        setting => update_advanced_sampler_setting(img2img, setting, value)?,
+    }
+    Ok(())
+}
+
+fn update_advanced_sampler_setting(
+    params: &mut dyn GenParams,
+    setting: &str,
+    value: &str,
+) -> anyhow::Result<()> {
+    if !params.supports_advanced_sampler() {
+        return Err(anyhow!("Got invalid setting: {}", setting));
+    }
+    match setting {
+        "eta" => params.set_eta(value.parse::<f32>()?.clamp(0.0, 1.0)),
+        "s_churn" => params.set_s_churn(value.parse::<f32>()?.max(0.0)),
+        "s_tmin" => params.set_s_tmin(value.parse::<f32>()?.max(0.0)),
+        "s_tmax" => params.set_s_tmax(value.parse::<f32>()?.max(0.0)),
+        "s_noise" => params.set_s_noise(value.parse::<f32>()?.max(0.0)),
+        _ => return Err(anyhow!("Got invalid setting: {}", setting)),
+    }
+    Ok(())
+}
+
+fn update_face_restoration_setting(
+    params: &mut dyn GenParams,
+    setting: &str,
+    value: &str,
+) -> anyhow::Result<()> {
+    if !params.supports_face_restoration() {
+        return Err(anyhow!("Got invalid setting: {}", setting));
+    }
+    match setting {
+        "face_model" => params.set_face_restoration_model(
+            match value.to_lowercase().as_str() {
+                "codeformer" => "CodeFormer",
+                "gfpgan" => "GFPGAN",
+                _ => return Err(anyhow!("Expected CodeFormer or GFPGAN")),
+            }
+            .to_owned(),
+        ),
+        "codeformer_weight" => params.set_codeformer_weight(value.parse::<f32>()?.clamp(0.0, 1.0)),
+        _ => return Err(anyhow!("Got invalid setting: {}", setting)),
+    }
+    Ok(())
+}
+
+fn update_hires_fix_setting(
+    params: &mut dyn GenParams,
+    setting: &str,
+    value: &str,
+) -> anyhow::Result<()> {
+    if !params.supports_hires_fix() {
+        return Err(anyhow!("Got invalid setting: {}", setting));
+    }
+    match setting {
+        "hr_scale" => params.set_hr_scale(value.parse::<f32>()?.clamp(1.0, 4.0)),
+        "hr_upscaler" => params.set_hr_upscaler(value.to_owned()),
+        "hr_steps" => params.set_hr_second_pass_steps(200.min(value.parse()?)),
+        _ => return Err(anyhow!("Got invalid setting: {}", setting)),
+    }
+    Ok(())
+}
+
+fn update_refiner_setting(
+    params: &mut dyn GenParams,
+    setting: &str,
+    value: &str,
+) -> anyhow::Result<()> {
+    if !params.supports_refiner() {
+        return Err(anyhow!("Got invalid setting: {}", setting));
+    }
+    match setting {
+        "refiner" => params.set_refiner_checkpoint(match value.trim() {
+            off if off.eq_ignore_ascii_case("off") || off.eq_ignore_ascii_case("none") => None,
+            checkpoint => Some(checkpoint.to_owned()),
+        }),
+        "refiner_switch_at" => params.set_refiner_switch_at(value.parse::<f32>()?.clamp(0.0, 1.0)),
+        _ => return Err(anyhow!("Got invalid setting: {}", setting)),
     }
     Ok(())
 }
 
 pub(crate) fn state_or_default() -> UpdateHandler<anyhow::Error> {
     dptree::map_async(
-        |cfg: ConfigParameters, dialogue: DiffusionDialogue| async move {
+        |backends: Arc<BackendHandles>, dialogue: DiffusionDialogue| async move {
             let result = dialogue.get().await;
             if let Err(ref err) = result {
                 error!("Failed to get state: {:?}", err);
             }
-            result.ok().flatten().unwrap_or_else(|| {
-                State::new_with_defaults(
-                    cfg.txt2img_api.gen_params(None),
-                    cfg.img2img_api.gen_params(None),
-                )
-            })
+            result
+                .ok()
+                .flatten()
+                .unwrap_or_else(|| backends.default_state(dialogue.chat_id()))
         },
     )
 }
@@ -368,32 +1384,63 @@ pub(crate) fn state_or_default() -> UpdateHandler<anyhow::Error> {
 pub(crate) async fn update_settings_value(
     bot: Bot,
     dialogue: DiffusionDialogue,
-    chat_id: ChatId,
+    to: SendContext,
     settings: Settings,
     state: State,
+    translator: &Translator,
 ) -> anyhow::Result<()> {
     dialogue.update(state).await.map_err(|e| anyhow!(e))?;
 
-    bot.send_message(chat_id, "Please make a selection.")
-        .reply_markup(settings.keyboard())
+    to.send_message(&bot, translator.text("Please make a selection."))
+        .reply_markup(settings.keyboard(translator))
         .await?;
 
     Ok(())
 }
 
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "txt2img_settings"
+    )
+)]
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn handle_txt2img_settings_value(
     bot: Bot,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
     dialogue: DiffusionDialogue,
     msg: Message,
     text: String,
-    (selection, mut txt2img, img2img): (Option<String>, Box<dyn GenParams>, Box<dyn GenParams>),
+    (selection, mut txt2img, mut img2img): (Option<String>, Box<dyn GenParams>, Box<dyn GenParams>),
+    translator: Translator,
 ) -> anyhow::Result<()> {
     if let Some(ref setting) = selection {
-        if let Err(e) = update_txt2img_setting(txt2img.as_mut(), setting, text) {
-            bot.send_message(msg.chat.id, format!("Please enter a valid value: {e:?}."))
+        if let Err(e) = update_txt2img_setting(txt2img.as_mut(), setting, &text) {
+            SendContext::of(&msg)
+                .send_message(
+                    &bot,
+                    translator.format(
+                        "Please enter a valid value: {error}.",
+                        &[("error", &format!("{e:?}"))],
+                    ),
+                )
                 .await?;
             return Ok(());
         }
+        settings_store::save_for_chat(&history, msg.chat.id, SettingsKind::Txt2Img, setting, &text)
+            .await;
+        apply_to_other(
+            &history,
+            msg.chat.id,
+            SettingsKind::Txt2Img,
+            img2img.as_mut(),
+            setting,
+            &text,
+        )
+        .await;
     }
 
     let bot_state = BotState::SettingsTxt2Img { selection: None };
@@ -401,30 +1448,61 @@ pub(crate) async fn handle_txt2img_settings_value(
     update_settings_value(
         bot,
         dialogue,
-        msg.chat.id,
-        Settings::from(txt2img.as_ref()),
+        SendContext::of(&msg),
+        chat_settings(txt2img.as_ref(), &ui, &history, msg.chat.id).await,
         State::Ready {
             bot_state,
             txt2img,
             img2img,
         },
+        &translator,
     )
     .await
 }
 
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "img2img_settings"
+    )
+)]
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn handle_img2img_settings_value(
     bot: Bot,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
     dialogue: DiffusionDialogue,
     msg: Message,
     text: String,
-    (selection, txt2img, mut img2img): (Option<String>, Box<dyn GenParams>, Box<dyn GenParams>),
+    (selection, mut txt2img, mut img2img): (Option<String>, Box<dyn GenParams>, Box<dyn GenParams>),
+    translator: Translator,
 ) -> anyhow::Result<()> {
     if let Some(ref setting) = selection {
-        if let Err(e) = update_img2img_setting(img2img.as_mut(), setting, text) {
-            bot.send_message(msg.chat.id, format!("Please enter a valid value: {e:?}."))
+        if let Err(e) = update_img2img_setting(img2img.as_mut(), setting, &text) {
+            SendContext::of(&msg)
+                .send_message(
+                    &bot,
+                    translator.format(
+                        "Please enter a valid value: {error}.",
+                        &[("error", &format!("{e:?}"))],
+                    ),
+                )
                 .await?;
             return Ok(());
         }
+        settings_store::save_for_chat(&history, msg.chat.id, SettingsKind::Img2Img, setting, &text)
+            .await;
+        apply_to_other(
+            &history,
+            msg.chat.id,
+            SettingsKind::Img2Img,
+            txt2img.as_mut(),
+            setting,
+            &text,
+        )
+        .await;
     }
 
     let bot_state = BotState::SettingsImg2Img { selection: None };
@@ -432,36 +1510,48 @@ pub(crate) async fn handle_img2img_settings_value(
     update_settings_value(
         bot,
         dialogue,
-        msg.chat.id,
-        Settings::from(img2img.as_ref()),
+        SendContext::of(&msg),
+        chat_settings(img2img.as_ref(), &ui, &history, msg.chat.id).await,
         State::Ready {
             bot_state,
             txt2img,
             img2img,
         },
+        &translator,
     )
     .await
 }
 
 pub(crate) fn map_settings() -> UpdateHandler<anyhow::Error> {
-    dptree::map(|cfg: ConfigParameters, state: State| match state {
+    dptree::map(|backends: Arc<BackendHandles>, state: State| match state {
         State::Ready {
             txt2img, img2img, ..
         } => (txt2img, img2img),
         State::New => (
-            cfg.txt2img_api.gen_params(None),
-            cfg.img2img_api.gen_params(None),
+            backends.txt2img_api.gen_params(None),
+            backends.img2img_api.gen_params(None),
         ),
     })
 }
 
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "img2img_settings"
+    )
+)]
 async fn handle_img2img_settings_command(
     msg: Message,
     bot: Bot,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
     dialogue: DiffusionDialogue,
     (txt2img, img2img): (Box<dyn GenParams>, Box<dyn GenParams>),
+    translator: Translator,
 ) -> anyhow::Result<()> {
-    let settings = Settings::from(img2img.as_ref());
+    let settings = chat_settings(img2img.as_ref(), &ui, &history, msg.chat.id).await;
     dialogue
         .update(State::Ready {
             bot_state: BotState::SettingsImg2Img { selection: None },
@@ -470,20 +1560,32 @@ async fn handle_img2img_settings_command(
         })
         .await
         .map_err(|e| anyhow!(e))?;
-    bot.send_message(msg.chat.id, "Please make a selection.")
-        .reply_markup(settings.keyboard())
+    SendContext::of(&msg)
+        .send_message(&bot, translator.text("Please make a selection."))
+        .reply_markup(settings.keyboard(&translator))
         .send()
         .await?;
     Ok(())
 }
 
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "txt2img_settings"
+    )
+)]
 async fn handle_txt2img_settings_command(
     msg: Message,
     bot: Bot,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
     dialogue: DiffusionDialogue,
     (txt2img, img2img): (Box<dyn GenParams>, Box<dyn GenParams>),
+    translator: Translator,
 ) -> anyhow::Result<()> {
-    let settings = Settings::from(txt2img.as_ref());
+    let settings = chat_settings(txt2img.as_ref(), &ui, &history, msg.chat.id).await;
     dialogue
         .update(State::Ready {
             bot_state: BotState::SettingsTxt2Img { selection: None },
@@ -492,15 +1594,149 @@ async fn handle_txt2img_settings_command(
         })
         .await
         .map_err(|e| anyhow!(e))?;
-    bot.send_message(msg.chat.id, "Please make a selection.")
-        .reply_markup(settings.keyboard())
+    SendContext::of(&msg)
+        .send_message(&bot, translator.text("Please make a selection."))
+        .reply_markup(settings.keyboard(&translator))
         .send()
         .await?;
     Ok(())
 }
 
-async fn handle_invalid_setting_value(bot: Bot, msg: Message) -> anyhow::Result<()> {
-    bot.send_message(msg.chat.id, "Please enter a valid value.")
+/// Shows the settings that the chat's next images will be generated with: the saved settings on
+/// top of the defaults, with the selected style and the enabled negative prompt presets applied.
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "current"
+    )
+)]
+async fn handle_current_command(
+    msg: Message,
+    bot: Bot,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
+    translator: Translator,
+    (txt2img, img2img): (Box<dyn GenParams>, Box<dyn GenParams>),
+) -> anyhow::Result<()> {
+    let style = styles::selected_style(
+        &ui.styles,
+        &history,
+        msg.chat.id,
+        msg.from().map(|user| user.id),
+    )
+    .await;
+    let preset_prompts =
+        presets::enabled_preset_prompts(&ui.negative_presets, &history, msg.chat.id).await;
+    let mut sections = Vec::new();
+    if let Some(style) = &style {
+        sections.push(translator.format("Style: {name}", &[("name", &style.name)]));
+    }
+    for (name, params) in [("txt2img", txt2img), ("img2img", img2img)] {
+        let params = styles::with_style(params.as_ref(), style.as_ref());
+        let mut settings = chat_settings(params.as_ref(), &ui, &history, msg.chat.id).await;
+        let effective = presets::with_negative_presets(params.as_ref(), &preset_prompts);
+        settings.negative_prompt = effective.negative_prompt();
+        sections.push(format!("{name}\n{}", settings.summary(&translator)));
+    }
+    SendContext::of(&msg)
+        .send_message(&bot, sections.join("\n\n"))
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+/// Sets the seed of the chat's last image for both txt2img and img2img, so the next images
+/// start from it.
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "lastseed"
+    )
+)]
+async fn handle_last_seed_command(
+    msg: Message,
+    bot: Bot,
+    history: HistoryStore,
+    dialogue: DiffusionDialogue,
+    (mut txt2img, mut img2img): (Box<dyn GenParams>, Box<dyn GenParams>),
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let seed = history
+        .latest(msg.chat.id)
+        .await?
+        .and_then(|generation| generation.seed);
+    let text = match seed {
+        Some(seed) => {
+            txt2img.set_seed(seed);
+            img2img.set_seed(seed);
+            dialogue
+                .update(State::Ready {
+                    bot_state: BotState::default(),
+                    txt2img,
+                    img2img,
+                })
+                .await
+                .map_err(|e| anyhow!(e))?;
+            translator.format("Seed set to {seed}.", &[("seed", &seed.to_string())])
+        }
+        None => translator.text("There's no image with a known seed in this chat yet."),
+    };
+    SendContext::of(&msg).send_message(&bot, text).await?;
+    Ok(())
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "resetsettings"
+    )
+)]
+async fn handle_reset_settings_command(
+    msg: Message,
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    history: HistoryStore,
+    dialogue: DiffusionDialogue,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    // Saved settings only apply in private chats, so group chats just reset their dialogue.
+    if let Some(user) = msg.from().filter(|_| msg.chat.is_private()) {
+        settings_store::reset(&history, user.id).await?;
+    }
+    dialogue
+        .update(backends.default_state(msg.chat.id))
+        .await
+        .map_err(|e| anyhow!(e))?;
+    SendContext::of(&msg)
+        .send_message(
+            &bot,
+            translator.text("Your settings were reset to the defaults."),
+        )
+        .await?;
+    Ok(())
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "settings"
+    )
+)]
+async fn handle_invalid_setting_value(
+    bot: Bot,
+    translator: Translator,
+    msg: Message,
+) -> anyhow::Result<()> {
+    SendContext::of(&msg)
+        .send_message(&bot, translator.text("Please enter a valid value."))
         .await?;
     Ok(())
 }
@@ -512,6 +1748,9 @@ pub(crate) fn settings_command_handler() -> UpdateHandler<anyhow::Error> {
         .chain(map_settings())
         .branch(case![SettingsCommands::Txt2ImgSettings].endpoint(handle_txt2img_settings_command))
         .branch(case![SettingsCommands::Img2ImgSettings].endpoint(handle_img2img_settings_command))
+        .branch(case![SettingsCommands::Current].endpoint(handle_current_command))
+        .branch(case![SettingsCommands::LastSeed].endpoint(handle_last_seed_command))
+        .branch(case![SettingsCommands::ResetSettings].endpoint(handle_reset_settings_command))
 }
 
 pub(crate) fn filter_settings_callback_query() -> UpdateHandler<anyhow::Error> {
@@ -550,7 +1789,7 @@ pub(crate) fn filter_map_settings_state() -> UpdateHandler<anyhow::Error> {
     })
 }
 
-pub(crate) fn settings_schema() -> UpdateHandler<anyhow::Error> {
+pub fn settings_schema() -> UpdateHandler<anyhow::Error> {
     let callback_handler = filter_settings_callback_query()
         .branch(
             filter_map_bot_state()
@@ -597,10 +1836,10 @@ mod tests {
         Txt2ImgParams,
     };
     use stable_diffusion_api::{Img2ImgRequest, Txt2ImgRequest};
-    use teloxide::types::{UpdateKind, User};
+    use teloxide::types::{InlineKeyboardButtonKind, UpdateKind, User};
 
     use super::*;
-    use crate::BotState;
+    use crate::{bot::backends::DEFAULT_BACKEND, BotState};
 
     fn create_callback_query_update(data: Option<String>) -> Update {
         let query = CallbackQuery {
@@ -810,12 +2049,18 @@ mod tests {
                     }
                 )
                 .dispatch(dptree::deps![
-                    ConfigParameters {
+                    Arc::new(BackendHandles {
+                        name: DEFAULT_BACKEND.to_owned(),
                         txt2img_api: Box::new(MockApi),
                         img2img_api: Box::new(MockApi),
-                        allowed_users: Default::default(),
-                        allow_all_users: false
-                    },
+                        describer: None,
+                        blank_check: None,
+                        upscaler: None,
+                        jobs: Default::default(),
+                        stats: Default::default(),
+                        metrics: Default::default(),
+                        defaults: Default::default(),
+                    }),
                     State::New
                 ])
                 .await,
@@ -876,12 +2121,18 @@ mod tests {
                     }
                 )
                 .dispatch(dptree::deps![
-                    ConfigParameters {
+                    Arc::new(BackendHandles {
+                        name: DEFAULT_BACKEND.to_owned(),
                         txt2img_api: Box::new(MockApi),
                         img2img_api: Box::new(MockApi),
-                        allowed_users: Default::default(),
-                        allow_all_users: false
-                    },
+                        describer: None,
+                        blank_check: None,
+                        upscaler: None,
+                        jobs: Default::default(),
+                        stats: Default::default(),
+                        metrics: Default::default(),
+                        defaults: Default::default(),
+                    }),
                     State::Ready {
                         bot_state: BotState::Generate,
                         txt2img: Box::new(txt2img),
@@ -892,4 +2143,247 @@ mod tests {
             ControlFlow::Break(_)
         ));
     }
+
+    #[test]
+    fn test_update_advanced_sampler_setting() {
+        let mut txt2img = Txt2ImgParams::default();
+        update_txt2img_setting(&mut txt2img, "eta", "0.5").unwrap();
+        update_txt2img_setting(&mut txt2img, "s_noise", "1.003").unwrap();
+        assert_eq!(txt2img.eta(), Some(0.5));
+        assert_eq!(txt2img.s_noise(), Some(1.003));
+
+        let mut img2img = Img2ImgParams::default();
+        update_img2img_setting(&mut img2img, "eta", "2").unwrap();
+        assert_eq!(img2img.eta(), Some(1.0));
+
+        let mut comfy = sal_e_api::ComfyParams::default();
+        assert!(update_txt2img_setting(&mut comfy, "eta", "0.5").is_err());
+        assert!(!Settings::from(&comfy as &dyn GenParams).advanced_sampler);
+    }
+
+    #[test]
+    fn test_update_seed_mode_setting() {
+        let mut comfy = sal_e_api::ComfyParams::default();
+        update_txt2img_setting(&mut comfy, "seed", "42").unwrap();
+        update_txt2img_setting(&mut comfy, "seed_mode", "increment").unwrap();
+        assert_eq!(comfy.seed_mode(), Some(SeedMode::Increment));
+        assert!(update_img2img_setting(&mut comfy, "seed_mode", "sometimes").is_err());
+        comfy.advance_seed(42);
+        assert_eq!(comfy.seed(), Some(43));
+
+        // Users who haven't chosen a seed mode get the backend's.
+        let mut api = sal_e_api::ComfyPromptApi::default();
+        api.params.seed_mode = Some(SeedMode::Randomize);
+        let params = sal_e_api::Txt2ImgApi::gen_params(&api, Some(&comfy));
+        assert_eq!(params.seed_mode(), Some(SeedMode::Increment));
+        let unset = sal_e_api::ComfyParams::default();
+        let params = sal_e_api::Txt2ImgApi::gen_params(&api, Some(&unset));
+        assert_eq!(params.seed_mode(), Some(SeedMode::Randomize));
+
+        let mut txt2img = Txt2ImgParams::default();
+        update_txt2img_setting(&mut txt2img, "seed_mode", "increment").unwrap();
+        assert!(Settings::from(&txt2img as &dyn GenParams)
+            .seed_mode
+            .is_none());
+    }
+
+    #[test]
+    fn test_update_face_restoration_setting() {
+        let mut txt2img = Txt2ImgParams::default();
+        update_txt2img_setting(&mut txt2img, "face_model", "gfpgan").unwrap();
+        update_txt2img_setting(&mut txt2img, "codeformer_weight", "1.5").unwrap();
+        assert_eq!(txt2img.face_restoration_model(), Some("GFPGAN".to_owned()));
+        assert_eq!(txt2img.codeformer_weight(), Some(1.0));
+        assert!(update_txt2img_setting(&mut txt2img, "face_model", "other").is_err());
+
+        let mut comfy = sal_e_api::ComfyParams::default();
+        assert!(update_txt2img_setting(&mut comfy, "codeformer_weight", "0.5").is_err());
+        assert!(!Settings::from(&comfy as &dyn GenParams).face_restoration);
+    }
+
+    #[test]
+    fn test_update_hires_fix_setting() {
+        let mut txt2img = Txt2ImgParams::default();
+        update_txt2img_setting(&mut txt2img, "hr_scale", "8").unwrap();
+        update_txt2img_setting(&mut txt2img, "hr_upscaler", "Latent").unwrap();
+        update_txt2img_setting(&mut txt2img, "hr_steps", "10").unwrap();
+        assert_eq!(txt2img.hr_scale(), Some(4.0));
+        assert_eq!(txt2img.hr_upscaler(), Some("Latent".to_owned()));
+        assert_eq!(txt2img.hr_second_pass_steps(), Some(10));
+        assert!(update_txt2img_setting(&mut txt2img, "hr_steps", "many").is_err());
+
+        let mut img2img = Img2ImgParams::default();
+        assert!(update_img2img_setting(&mut img2img, "hr_scale", "2").is_err());
+        assert!(!Settings::from(&img2img as &dyn GenParams).hires_fix);
+    }
+
+    #[test]
+    fn test_update_refiner_setting() {
+        let mut txt2img = Txt2ImgParams {
+            defaults: Some(Txt2ImgRequest {
+                refiner_checkpoint: Some("sdxl_refiner".to_owned()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        update_txt2img_setting(&mut txt2img, "refiner_switch_at", "1.5").unwrap();
+        assert_eq!(txt2img.refiner_switch_at(), Some(1.0));
+        let settings = Settings::from(&txt2img as &dyn GenParams);
+        assert_eq!(settings.refiner_checkpoint.as_deref(), Some("sdxl_refiner"));
+
+        update_txt2img_setting(&mut txt2img, "refiner", "off").unwrap();
+        assert_eq!(txt2img.refiner_checkpoint(), None);
+        assert!(Settings::from(&txt2img as &dyn GenParams)
+            .summary(&Translator::default())
+            .contains("Refiner: off"));
+
+        let mut img2img = Img2ImgParams::default();
+        assert!(update_img2img_setting(&mut img2img, "refiner", "x").is_err());
+    }
+
+    #[test]
+    fn test_settings_keyboard_negative_presets() {
+        let mut settings = Settings::from(&Txt2ImgParams::default() as &dyn GenParams);
+        settings.negative_presets = vec![("Quality".to_owned(), true), ("Text".to_owned(), false)];
+        let buttons = settings
+            .keyboard(&Translator::default())
+            .inline_keyboard
+            .into_iter()
+            .flatten()
+            .map(|button| button.text)
+            .collect::<Vec<_>>();
+        assert!(buttons.contains(&"✅ Quality".to_owned()));
+        assert!(buttons.contains(&"⬜ Text".to_owned()));
+    }
+
+    #[tokio::test]
+    async fn test_apply_to_other() {
+        let history = HistoryStore::open(None).await.unwrap();
+        let chat = ChatId(1);
+        let mut img2img = Img2ImgParams::default();
+        assert!(
+            !apply_to_other(
+                &history,
+                chat,
+                SettingsKind::Txt2Img,
+                &mut img2img,
+                "width",
+                "768"
+            )
+            .await
+        );
+
+        history.toggle_linked_settings(chat).await.unwrap();
+        assert!(
+            apply_to_other(
+                &history,
+                chat,
+                SettingsKind::Txt2Img,
+                &mut img2img,
+                "width",
+                "768"
+            )
+            .await
+        );
+        assert_eq!(img2img.width(), Some(768));
+        assert!(
+            !apply_to_other(
+                &history,
+                chat,
+                SettingsKind::Txt2Img,
+                &mut img2img,
+                "seed",
+                "42"
+            )
+            .await
+        );
+        assert_ne!(img2img.seed(), Some(42));
+    }
+
+    #[test]
+    fn test_size_keyboard() {
+        let mut params = Txt2ImgParams::default();
+        params.set_width(768);
+        params.set_height(512);
+        let settings = Settings::from(&params as &dyn GenParams);
+        let buttons = settings
+            .keyboard(&Translator::default())
+            .inline_keyboard
+            .concat();
+        assert!(buttons.iter().any(|button| matches!(
+            &button.kind,
+            InlineKeyboardButtonKind::CallbackData(data) if data == "settings_size"
+        )));
+
+        let keyboard = settings.size_keyboard(Some(768 * 512), &Translator::default());
+        let buttons = keyboard.inline_keyboard.concat();
+        let labels: Vec<_> = buttons.iter().map(|button| button.text.as_str()).collect();
+        assert_eq!(
+            labels,
+            ["1:1 512×512", "2:3 512×768", "✅ 3:2 768×512", "Back"]
+        );
+    }
+
+    #[test]
+    fn test_denoising_keyboard() {
+        let mut params = Img2ImgParams::default();
+        params.set_denoising(0.5);
+        let keyboard =
+            Settings::from(&params as &dyn GenParams).denoising_keyboard(&Translator::default());
+        let rows = keyboard
+            .inline_keyboard
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|button| match &button.kind {
+                        InlineKeyboardButtonKind::CallbackData(data) => data.as_str(),
+                        _ => panic!("unexpected button kind"),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(rows[0][0], "settings_denoising/0.2");
+        assert_eq!(rows[1][3], "settings_denoising/0.9");
+        assert_eq!(
+            rows[2],
+            [
+                "settings_denoising/0.45",
+                "settings_denoising/0.5",
+                "settings_denoising/0.55"
+            ]
+        );
+        assert_eq!(rows[3], ["settings_main"]);
+        assert_eq!(keyboard.inline_keyboard[0][3].text, "✅ 0.5");
+
+        params.set_denoising(1.0);
+        let keyboard =
+            Settings::from(&params as &dyn GenParams).denoising_keyboard(&Translator::default());
+        match &keyboard.inline_keyboard[2][2].kind {
+            InlineKeyboardButtonKind::CallbackData(data) => {
+                assert_eq!(data, "settings_denoising/1")
+            }
+            _ => panic!("unexpected button kind"),
+        }
+    }
+
+    #[test]
+    fn test_settings_summary() {
+        let mut params = Txt2ImgParams::default();
+        params.set_steps(20);
+        params.set_seed(-1);
+        params.set_negative_prompt("lowres".to_owned());
+        params.set_vae("Automatic".to_owned());
+        let mut settings = Settings::from(&params as &dyn GenParams);
+        settings.negative_presets = vec![("Quality".to_owned(), true), ("Text".to_owned(), false)];
+        let summary = settings.summary(&Translator::default());
+        let lines = summary.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "Model: default");
+        assert_eq!(lines[1], "VAE: Automatic");
+        assert!(lines.contains(&"Steps: 20"));
+        assert!(lines.contains(&"Seed: random"));
+        assert!(lines.contains(&"Negative Prompt: lowres"));
+        assert!(lines.contains(&"Negative Presets: Quality"));
+        assert!(lines.contains(&"Restore Faces: off"));
+        assert!(!summary.contains("Width"));
+    }
 }
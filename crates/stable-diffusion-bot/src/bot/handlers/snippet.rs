@@ -0,0 +1,180 @@
+use teloxide::{dispatching::UpdateHandler, dptree::case, macros::BotCommands, prelude::*};
+use tracing::instrument;
+
+use super::{filter_command, SendContext};
+use crate::bot::{
+    history::HistoryStore,
+    i18n::Translator,
+    snippets::{self, SnippetAction, MAX_SNIPPETS, MAX_TEXT_LENGTH},
+};
+
+const USAGE: &str = "Usage:\n\
+    /snippet add <name> \"<text>\"\n\
+    /snippet list\n\
+    /snippet delete <name>\n\n\
+    Use a snippet in a prompt by writing {{name}}.";
+
+/// BotCommands for managing prompt snippets.
+#[derive(BotCommands, Debug, Clone)]
+#[command(rename_rule = "lowercase", description = "Snippet commands")]
+pub(crate) enum SnippetCommands {
+    /// Command to add, list or delete prompt snippets.
+    #[command(
+        description = "save prompt snippets, e.g. /snippet add lighting \"god rays\", and use them as {{lighting}}."
+    )]
+    Snippet(String),
+}
+
+/// Runs a snippet command for `user_id`, returning the reply.
+async fn run_action(
+    history: &HistoryStore,
+    user_id: UserId,
+    action: SnippetAction<'_>,
+    translator: &Translator,
+) -> anyhow::Result<String> {
+    let reply = match action {
+        SnippetAction::List => {
+            let snippets = history.snippets(user_id).await?;
+            if snippets.is_empty() {
+                format!(
+                    "{}\n\n{}",
+                    translator.text("You have no snippets."),
+                    translator.text(USAGE)
+                )
+            } else {
+                snippets
+                    .iter()
+                    .map(|(name, text)| format!("{{{{{name}}}}}: {text}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        }
+        SnippetAction::Add { name, .. } if !snippets::is_valid_name(name) => translator.format(
+            "Snippet names may only contain letters, numbers, - and _, and be at most {max} characters long.",
+            &[("max", &snippets::MAX_NAME_LENGTH.to_string())],
+        ),
+        SnippetAction::Add { text, .. } if text.chars().count() > MAX_TEXT_LENGTH => translator
+            .format(
+                "Snippets may be at most {max} characters long.",
+                &[("max", &MAX_TEXT_LENGTH.to_string())],
+            ),
+        SnippetAction::Add { name, text } => {
+            let snippets = history.snippets(user_id).await?;
+            if snippets.len() >= MAX_SNIPPETS && !snippets.iter().any(|(n, _)| n == name) {
+                translator.format(
+                    "You can save at most {max} snippets. Delete one first.",
+                    &[("max", &MAX_SNIPPETS.to_string())],
+                )
+            } else {
+                history.set_snippet(user_id, name, text).await?;
+                translator.format("Saved snippet {{{name}}}.", &[("name", name)])
+            }
+        }
+        SnippetAction::Delete { name } => {
+            if history.delete_snippet(user_id, name).await? {
+                translator.format("Deleted snippet {{{name}}}.", &[("name", name)])
+            } else {
+                translator.format("You have no snippet named {name}.", &[("name", name)])
+            }
+        }
+    };
+    Ok(reply)
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "snippet"
+    )
+)]
+async fn handle_snippet_command(
+    bot: Bot,
+    history: HistoryStore,
+    msg: Message,
+    args: String,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let reply = match (msg.from(), snippets::parse_action(&args)) {
+        (Some(user), Some(action)) => run_action(&history, user.id, action, &translator).await?,
+        (None, _) => translator.text("Snippets are saved per user, so they can't be used here."),
+        (_, None) => translator.text(USAGE),
+    };
+    SendContext::of(&msg)
+        .send_message(&bot, reply)
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+pub fn snippet_schema() -> UpdateHandler<anyhow::Error> {
+    Update::filter_message()
+        .chain(filter_command::<SnippetCommands>())
+        .branch(case![SnippetCommands::Snippet(args)].endpoint(handle_snippet_command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_action() {
+        let history = HistoryStore::open(None).await.unwrap();
+        let user = UserId(1);
+        assert_eq!(
+            run_action(
+                &history,
+                user,
+                SnippetAction::Add {
+                    name: "lighting",
+                    text: "god rays"
+                },
+                &Translator::default()
+            )
+            .await
+            .unwrap(),
+            "Saved snippet {{lighting}}."
+        );
+        assert!(run_action(
+            &history,
+            user,
+            SnippetAction::Add {
+                name: "two words",
+                text: "x"
+            },
+            &Translator::default()
+        )
+        .await
+        .unwrap()
+        .starts_with("Snippet names"));
+        assert_eq!(
+            run_action(&history, user, SnippetAction::List, &Translator::default())
+                .await
+                .unwrap(),
+            "{{lighting}}: god rays"
+        );
+        assert_eq!(
+            run_action(
+                &history,
+                user,
+                SnippetAction::Delete { name: "lighting" },
+                &Translator::default()
+            )
+            .await
+            .unwrap(),
+            "Deleted snippet {{lighting}}."
+        );
+
+        let translations =
+            std::sync::Arc::new(crate::bot::i18n::Translations::load(None, None).unwrap());
+        let german = Translator::new(translations, Some("de".to_owned()));
+        assert_ne!(german.text(USAGE), USAGE);
+        assert_eq!(
+            run_action(&history, user, SnippetAction::List, &german)
+                .await
+                .unwrap(),
+            format!("Du hast keine Bausteine.\n\n{}", german.text(USAGE))
+        );
+    }
+}
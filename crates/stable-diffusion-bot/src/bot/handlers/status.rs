@@ -0,0 +1,118 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::anyhow;
+use sal_e_api::{BackendStatus, VramUsage};
+use teloxide::{dispatching::UpdateHandler, dptree::case, macros::BotCommands, prelude::*};
+use tracing::{instrument, warn};
+
+use super::{filter_command, BackendHandles, SendContext};
+use crate::bot::i18n::Translator;
+
+/// How long to wait for the backend to respond before reporting it unreachable.
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// BotCommands for checking on the backend.
+#[derive(BotCommands, Debug, Clone)]
+#[command(rename_rule = "lowercase", description = "Status commands")]
+pub(crate) enum StatusCommands {
+    /// Command to show whether the backend is available.
+    #[command(description = "show whether image generation is available.")]
+    Status,
+}
+
+/// Formats a number of bytes in GiB.
+fn format_gib(bytes: u64) -> String {
+    format!("{:.1} GiB", bytes as f64 / (1024 * 1024 * 1024) as f64)
+}
+
+/// Returns the reply to `/status`, given the result of pinging the backend and its queue length.
+fn status_text(
+    status: Option<&BackendStatus>,
+    queue_length: Option<u64>,
+    translator: &Translator,
+) -> String {
+    let Some(status) = status else {
+        return translator
+            .text("🔴 The backend is unreachable. Generating images will fail until it's back.");
+    };
+    let mut lines = vec![translator.text("🟢 The backend is up.")];
+    if let Some(model) = &status.model {
+        lines.push(translator.format("Model: {value}", &[("value", model)]));
+    }
+    if let Some(length) = queue_length {
+        lines.push(translator.format("Queue: {length} jobs", &[("length", &length.to_string())]));
+    }
+    if let Some(VramUsage { used, total }) = status.vram {
+        lines.push(translator.format(
+            "VRAM: {used} of {total}",
+            &[("used", &format_gib(used)), ("total", &format_gib(total))],
+        ));
+    }
+    lines.join("\n")
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "status"
+    )
+)]
+async fn handle_status_command(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    translator: Translator,
+    msg: Message,
+) -> anyhow::Result<()> {
+    let api = &backends.txt2img_api;
+    let (status, queue_length) = tokio::join!(
+        tokio::time::timeout(PING_TIMEOUT, api.ping()),
+        tokio::time::timeout(PING_TIMEOUT, api.queue_length()),
+    );
+    let status = status
+        .unwrap_or_else(|_| Err(anyhow!("Timed out")))
+        .map_err(|e| warn!("Backend is unreachable: {:?}", e))
+        .ok();
+    // Not every backend reports its queue.
+    let queue_length = queue_length.ok().and_then(Result::ok);
+    SendContext::of(&msg)
+        .send_message(
+            &bot,
+            status_text(status.as_ref(), queue_length, &translator),
+        )
+        .await?;
+    Ok(())
+}
+
+pub fn status_schema() -> UpdateHandler<anyhow::Error> {
+    Update::filter_message()
+        .chain(filter_command::<StatusCommands>())
+        .branch(case![StatusCommands::Status].endpoint(handle_status_command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_text() {
+        let translator = Translator::default();
+        let status = BackendStatus {
+            model: Some("sdxl.safetensors".to_owned()),
+            vram: Some(VramUsage {
+                used: 6 * 1024 * 1024 * 1024,
+                total: 24 * 1024 * 1024 * 1024,
+            }),
+        };
+        assert_eq!(
+            status_text(Some(&status), Some(2), &translator),
+            "🟢 The backend is up.\nModel: sdxl.safetensors\nQueue: 2 jobs\nVRAM: 6.0 GiB of 24.0 GiB"
+        );
+        assert_eq!(
+            status_text(Some(&BackendStatus::default()), None, &translator),
+            "🟢 The backend is up."
+        );
+        assert!(status_text(None, Some(2), &translator).starts_with("🔴"));
+    }
+}
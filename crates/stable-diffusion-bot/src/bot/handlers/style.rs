@@ -0,0 +1,470 @@
+use std::sync::Arc;
+
+use teloxide::{
+    dispatching::UpdateHandler,
+    dptree::case,
+    macros::BotCommands,
+    payloads::setters::*,
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+use tracing::{instrument, warn};
+
+use super::{filter_command, SendContext};
+use crate::bot::{
+    config::UiConfig,
+    history::HistoryStore,
+    i18n::Translator,
+    policy::PromptPolicy,
+    styles::{self, Style, StyleAction, MAX_STYLES, MAX_TEXT_LENGTH},
+};
+
+const USAGE: &str = "Usage:\n\
+    /style - choose a style\n\
+    /style <name> - use a style\n\
+    /style off - stop using a style\n\
+    /style list\n\
+    /style add <name> prefix=\"...\" suffix=\"...\" negative=\"...\" cfg=7 steps=30\n\
+    /style delete <name>";
+
+/// Names that can't be used for styles, because they are `/style` actions.
+const RESERVED_NAMES: [&str; 6] = ["add", "delete", "list", "none", "off", "remove"];
+
+/// Telegram limits callback data to 64 bytes.
+const MAX_CALLBACK_DATA_LENGTH: usize = 64;
+
+/// Callback data of the button that deselects the chat's style.
+const STYLE_OFF: &str = "style_off";
+
+/// BotCommands for choosing and managing styles.
+#[derive(BotCommands, Debug, Clone)]
+#[command(rename_rule = "lowercase", description = "Style commands")]
+pub(crate) enum StyleCommands {
+    /// Command to choose, add, list or delete styles.
+    #[command(
+        description = "choose a style that is applied to your prompts and settings, or save your own."
+    )]
+    Style(String),
+}
+
+/// Builds an inline keyboard listing `styles`, marking the `current` one. Styles whose names
+/// are too long for a button can still be chosen with `/style <name>`.
+fn style_keyboard(
+    styles: &[Style],
+    current: Option<&str>,
+    translator: &Translator,
+) -> InlineKeyboardMarkup {
+    let buttons = styles
+        .iter()
+        .map(|style| (style, format!("style/{}", style.name)))
+        .filter(|(_, data)| data.len() <= MAX_CALLBACK_DATA_LENGTH)
+        .map(|(style, data)| {
+            let text = if current == Some(style.name.as_str()) {
+                format!("✅ {}", style.name)
+            } else {
+                style.name.clone()
+            };
+            [InlineKeyboardButton::callback(text, data)]
+        });
+    let off_text = if current.is_none() {
+        translator.text("✅ No style")
+    } else {
+        translator.text("No style")
+    };
+    InlineKeyboardMarkup::new(
+        buttons.chain([[InlineKeyboardButton::callback(off_text, STYLE_OFF)]]),
+    )
+}
+
+/// Runs a style command in `chat_id` for `user_id`, returning the reply.
+async fn run_action(
+    configured: &[Style],
+    policy: &PromptPolicy,
+    history: &HistoryStore,
+    chat_id: ChatId,
+    user_id: Option<UserId>,
+    action: StyleAction<'_>,
+    translator: &Translator,
+) -> anyhow::Result<String> {
+    let reply = match action {
+        StyleAction::Menu => unreachable!("Menus are sent by the command handler"),
+        StyleAction::Select { name } => {
+            let available = styles::available_styles(configured, history, user_id).await;
+            if available.iter().any(|style| style.name == name) {
+                history.set_chat_style(chat_id, Some(name)).await?;
+                translator.format("Using style {name}.", &[("name", name)])
+            } else {
+                translator.format("There is no style named {name}.", &[("name", name)])
+            }
+        }
+        StyleAction::Off => {
+            history.set_chat_style(chat_id, None).await?;
+            translator.text("Not using a style.")
+        }
+        StyleAction::List => {
+            let available = styles::available_styles(configured, history, user_id).await;
+            if available.is_empty() {
+                format!(
+                    "{}\n\n{}",
+                    translator.text("There are no styles yet."),
+                    translator.text(USAGE)
+                )
+            } else {
+                available
+                    .iter()
+                    .map(|style| format!("{}\n{}", style.name, style.summary(translator)))
+                    .collect::<Vec<_>>()
+                    .join("\n\n")
+            }
+        }
+        StyleAction::Add(_) | StyleAction::Delete { .. } if user_id.is_none() => {
+            translator.text("Styles are saved per user, so they can't be saved here.")
+        }
+        StyleAction::Add(style)
+            if !styles::is_valid_name(&style.name)
+                || RESERVED_NAMES.contains(&style.name.as_str()) =>
+        {
+            translator.format(
+                "Style names may only contain letters, numbers, - and _, be at most {max} \
+                 characters long, and not be one of {names}.",
+                &[
+                    ("max", &styles::MAX_NAME_LENGTH.to_string()),
+                    ("names", &RESERVED_NAMES.join(", ")),
+                ],
+            )
+        }
+        StyleAction::Add(style) if configured.iter().any(|s| s.name == style.name) => translator
+            .format(
+                "There already is a style named {name}.",
+                &[("name", &style.name)],
+            ),
+        StyleAction::Add(style)
+            if [
+                &style.prompt_prefix,
+                &style.prompt_suffix,
+                &style.negative_prompt,
+            ]
+            .into_iter()
+            .flatten()
+            .any(|text| text.chars().count() > MAX_TEXT_LENGTH) =>
+        {
+            translator.format(
+                "Style texts may be at most {max} characters long.",
+                &[("max", &MAX_TEXT_LENGTH.to_string())],
+            )
+        }
+        StyleAction::Add(style) => {
+            if let Err(violation) = style.check_policy(policy) {
+                return Ok(translator.format(
+                    "Can't save style {name}. {reason}",
+                    &[
+                        ("name", &style.name),
+                        ("reason", &violation.user_message(translator)),
+                    ],
+                ));
+            }
+            let user_id = user_id.expect("Users are checked above");
+            let own = history.styles(user_id).await?;
+            if own.len() >= MAX_STYLES && !own.iter().any(|s| s.name == style.name) {
+                translator.format(
+                    "You can save at most {max} styles. Delete one first.",
+                    &[("max", &MAX_STYLES.to_string())],
+                )
+            } else {
+                history.set_style(user_id, &style).await?;
+                translator.format(
+                    "Saved style {name}. Use it with /style {name}.",
+                    &[("name", &style.name)],
+                )
+            }
+        }
+        StyleAction::Delete { name } => {
+            let user_id = user_id.expect("Users are checked above");
+            if history.delete_style(user_id, name).await? {
+                translator.format("Deleted style {name}.", &[("name", name)])
+            } else {
+                translator.format("You have no style named {name}.", &[("name", name)])
+            }
+        }
+    };
+    Ok(reply)
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "style"
+    )
+)]
+async fn handle_style_command(
+    bot: Bot,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
+    translator: Translator,
+    msg: Message,
+    args: String,
+) -> anyhow::Result<()> {
+    let user_id = msg.from().map(|user| user.id);
+    let context = SendContext::of(&msg);
+    match styles::parse_action(&args) {
+        Some(StyleAction::Menu) => {
+            let available = styles::available_styles(&ui.styles, &history, user_id).await;
+            if available.is_empty() {
+                context
+                    .send_message(
+                        &bot,
+                        format!(
+                            "{}\n\n{}",
+                            translator.text("There are no styles yet."),
+                            translator.text(USAGE)
+                        ),
+                    )
+                    .reply_to_message_id(msg.id)
+                    .await?;
+            } else {
+                let current = history.chat_style(msg.chat.id).await?;
+                context
+                    .send_message(&bot, translator.text("Please choose a style."))
+                    .reply_markup(style_keyboard(&available, current.as_deref(), &translator))
+                    .await?;
+            }
+        }
+        Some(action) => {
+            let reply = run_action(
+                &ui.styles,
+                &ui.prompt_policy,
+                &history,
+                msg.chat.id,
+                user_id,
+                action,
+                &translator,
+            )
+            .await?;
+            context
+                .send_message(&bot, reply)
+                .reply_to_message_id(msg.id)
+                .await?;
+        }
+        None => {
+            context
+                .send_message(&bot, translator.text(USAGE))
+                .reply_to_message_id(msg.id)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "style"
+    )
+)]
+async fn handle_style_selection(
+    bot: Bot,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
+    translator: Translator,
+    q: CallbackQuery,
+    name: Option<String>,
+) -> anyhow::Result<()> {
+    let Some(message) = q.message else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, this message is no longer available."))
+            .await?;
+        return Ok(());
+    };
+    let action = match &name {
+        Some(name) => StyleAction::Select { name },
+        None => StyleAction::Off,
+    };
+    let reply = run_action(
+        &ui.styles,
+        &ui.prompt_policy,
+        &history,
+        message.chat.id,
+        Some(q.from.id),
+        action,
+        &translator,
+    )
+    .await?;
+
+    if let Err(e) = bot.answer_callback_query(q.id).await {
+        warn!("Failed to answer style selection callback query: {}", e)
+    }
+    bot.edit_message_text(message.chat.id, message.id, reply)
+        .reply_markup(InlineKeyboardMarkup::default())
+        .await?;
+    Ok(())
+}
+
+pub fn style_schema() -> UpdateHandler<anyhow::Error> {
+    let command_handler = Update::filter_message()
+        .chain(filter_command::<StyleCommands>())
+        .branch(case![StyleCommands::Style(args)].endpoint(handle_style_command));
+
+    let callback_handler = Update::filter_callback_query()
+        .chain(dptree::filter_map(|q: CallbackQuery| {
+            let data = q.data?;
+            if data == STYLE_OFF {
+                Some(None)
+            } else {
+                data.strip_prefix("style/")
+                    .map(|name| Some(name.to_owned()))
+            }
+        }))
+        .endpoint(handle_style_selection);
+
+    dptree::entry()
+        .branch(command_handler)
+        .branch(callback_handler)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::policy::BannedWord;
+
+    #[test]
+    fn test_style_keyboard() {
+        let styles = vec![
+            Style {
+                name: "noir".to_owned(),
+                ..Default::default()
+            },
+            Style {
+                name: "x".repeat(MAX_CALLBACK_DATA_LENGTH),
+                ..Default::default()
+            },
+        ];
+        let keyboard = style_keyboard(&styles, Some("noir"), &Translator::default());
+        let buttons = keyboard
+            .inline_keyboard
+            .into_iter()
+            .flatten()
+            .map(|button| button.text)
+            .collect::<Vec<_>>();
+        assert_eq!(buttons, vec!["✅ noir", "No style"]);
+    }
+
+    #[tokio::test]
+    async fn test_run_action() {
+        let history = HistoryStore::open(None).await.unwrap();
+        let configured = vec![Style {
+            name: "anime".to_owned(),
+            prompt_suffix: Some("anime style".to_owned()),
+            ..Default::default()
+        }];
+        let policy = PromptPolicy {
+            max_prompt_length: None,
+            banned_words: vec![BannedWord::Word("gore".to_owned())],
+        };
+        let translator = Translator::default();
+        let chat_id = ChatId(1);
+        let user_id = Some(UserId(1));
+
+        let add = styles::parse_action("add horror suffix=gore").unwrap();
+        assert_eq!(
+            run_action(
+                &configured,
+                &policy,
+                &history,
+                chat_id,
+                user_id,
+                add,
+                &translator
+            )
+            .await
+            .unwrap(),
+            "Can't save style horror. Your prompt contains a banned word."
+        );
+        let add = styles::parse_action("add noir prefix=noir").unwrap();
+        assert_eq!(
+            run_action(
+                &configured,
+                &policy,
+                &history,
+                chat_id,
+                user_id,
+                add,
+                &translator
+            )
+            .await
+            .unwrap(),
+            "Saved style noir. Use it with /style noir."
+        );
+        let add = styles::parse_action("add anime prefix=anime").unwrap();
+        assert_eq!(
+            run_action(
+                &configured,
+                &policy,
+                &history,
+                chat_id,
+                user_id,
+                add,
+                &translator
+            )
+            .await
+            .unwrap(),
+            "There already is a style named anime."
+        );
+
+        let select = StyleAction::Select { name: "noir" };
+        assert_eq!(
+            run_action(
+                &configured,
+                &policy,
+                &history,
+                chat_id,
+                None,
+                select.clone(),
+                &translator
+            )
+            .await
+            .unwrap(),
+            "There is no style named noir."
+        );
+        assert_eq!(
+            run_action(
+                &configured,
+                &policy,
+                &history,
+                chat_id,
+                user_id,
+                select,
+                &translator
+            )
+            .await
+            .unwrap(),
+            "Using style noir."
+        );
+        assert_eq!(
+            styles::selected_style(&configured, &history, chat_id, user_id)
+                .await
+                .map(|style| style.name),
+            Some("noir".to_owned())
+        );
+
+        run_action(
+            &configured,
+            &policy,
+            &history,
+            chat_id,
+            user_id,
+            StyleAction::Off,
+            &translator,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            styles::selected_style(&configured, &history, chat_id, user_id).await,
+            None
+        );
+    }
+}
@@ -0,0 +1,315 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use sal_e_api::AUTOMATIC_VAE;
+use teloxide::{
+    dispatching::UpdateHandler,
+    dptree::case,
+    macros::BotCommands,
+    payloads::setters::*,
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+use tracing::{instrument, warn};
+
+use super::{filter_command, BackendHandles, DiffusionDialogue, SendContext, State};
+use crate::bot::i18n::Translator;
+
+/// Telegram allows at most 100 buttons in an inline keyboard, one of which resets the VAE.
+const MAX_VAES: usize = 99;
+
+/// BotCommands for choosing the VAE.
+#[derive(BotCommands, Debug, Clone)]
+#[command(rename_rule = "lowercase", description = "VAE commands")]
+pub(crate) enum VaeCommands {
+    /// Command to choose the VAE that decodes the images.
+    #[command(description = "choose the VAE that decodes your images.")]
+    Vae,
+}
+
+/// A VAE chosen with a button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VaeChoice {
+    /// Let the backend use the VAE matching the model.
+    Automatic,
+    /// The VAE at this index of the backend's list.
+    Index(usize),
+}
+
+impl VaeChoice {
+    fn parse(data: &str) -> Option<Self> {
+        match data.strip_prefix("vae/")? {
+            "auto" => Some(VaeChoice::Automatic),
+            index => index.parse().ok().map(VaeChoice::Index),
+        }
+    }
+}
+
+/// Returns the VAE currently selected in `state`, falling back to the default in `chat_id`, or
+/// `None` if the backend doesn't support choosing one.
+fn current_vae(
+    backends: &BackendHandles,
+    state: &State,
+    chat_id: ChatId,
+) -> Option<Option<String>> {
+    let params = match state {
+        State::Ready { txt2img, .. } => txt2img.clone(),
+        State::New => backends.txt2img_defaults(chat_id),
+    };
+    params.supports_vae().then(|| params.vae())
+}
+
+/// Builds an inline keyboard listing `vaes` below the button that resets the VAE, marking the
+/// `current` one.
+fn vae_keyboard(vaes: &[String], current: Option<&str>) -> InlineKeyboardMarkup {
+    let label = |name: &str, selected: bool| {
+        if selected {
+            format!("✅ {name}")
+        } else {
+            name.to_owned()
+        }
+    };
+    let automatic = InlineKeyboardButton::callback(
+        label(AUTOMATIC_VAE, current == Some(AUTOMATIC_VAE)),
+        "vae/auto",
+    );
+    InlineKeyboardMarkup::new(std::iter::once([automatic]).chain(
+        vaes.iter().take(MAX_VAES).enumerate().map(|(index, vae)| {
+            [InlineKeyboardButton::callback(
+                label(vae, current == Some(vae.as_str())),
+                format!("vae/{index}"),
+            )]
+        }),
+    ))
+}
+
+/// Lists the backend's VAEs, or returns a message explaining why they can't be listed.
+async fn list_vaes(
+    backends: &BackendHandles,
+    translator: &Translator,
+) -> Result<Vec<String>, String> {
+    backends.txt2img_api.vaes().await.map_err(|e| {
+        warn!("Failed to list VAEs: {:?}", e);
+        translator.text("Sorry, the list of VAEs isn't available.")
+    })
+}
+
+/// Returns the keyboard for choosing a VAE, or a message explaining why there is no choice.
+async fn vae_menu(
+    backends: &BackendHandles,
+    state: &State,
+    chat_id: ChatId,
+    translator: &Translator,
+) -> Result<InlineKeyboardMarkup, String> {
+    let Some(current) = current_vae(backends, state, chat_id) else {
+        return Err(translator.text("The backend doesn't support choosing the VAE."));
+    };
+    let vaes = list_vaes(backends, translator).await?;
+    Ok(vae_keyboard(&vaes, current.as_deref()))
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "vae"
+    )
+)]
+async fn handle_vae_command(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    msg: Message,
+    state: State,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    match vae_menu(&backends, &state, msg.chat.id, &translator).await {
+        Ok(keyboard) => {
+            SendContext::of(&msg)
+                .send_message(&bot, translator.text("Please choose a VAE."))
+                .reply_markup(keyboard)
+                .await?;
+        }
+        Err(text) => {
+            SendContext::of(&msg).send_message(&bot, text).await?;
+        }
+    }
+    Ok(())
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "vae"
+    )
+)]
+async fn handle_vae_button(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    state: State,
+    q: CallbackQuery,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let Some(message) = q.message else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, this message is no longer available."))
+            .await?;
+        return Ok(());
+    };
+    let keyboard = match vae_menu(&backends, &state, message.chat.id, &translator).await {
+        Ok(keyboard) => keyboard,
+        Err(text) => {
+            bot.answer_callback_query(q.id).text(text).await?;
+            return Ok(());
+        }
+    };
+    if let Err(e) = bot.answer_callback_query(q.id).await {
+        warn!("Failed to answer VAE button callback query: {}", e)
+    }
+    bot.edit_message_text(
+        message.chat.id,
+        message.id,
+        translator.text("Please choose a VAE."),
+    )
+    .reply_markup(keyboard)
+    .await?;
+    Ok(())
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = ?q.message.as_ref().map(|message| message.chat.id),
+        user_id = %q.from.id,
+        command = "vae"
+    )
+)]
+async fn handle_vae_selection(
+    bot: Bot,
+    backends: Arc<BackendHandles>,
+    dialogue: DiffusionDialogue,
+    state: State,
+    q: CallbackQuery,
+    choice: VaeChoice,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let Some(message) = q.message else {
+        bot.answer_callback_query(q.id)
+            .cache_time(60)
+            .text(translator.text("Sorry, this message is no longer available."))
+            .await?;
+        return Ok(());
+    };
+    let vae = match choice {
+        VaeChoice::Automatic => Some(AUTOMATIC_VAE.to_owned()),
+        // The list is fetched again, so a VAE removed since the keyboard was sent can't be
+        // chosen.
+        VaeChoice::Index(index) => match list_vaes(&backends, &translator).await {
+            Ok(vaes) => vaes.into_iter().nth(index),
+            Err(text) => {
+                bot.answer_callback_query(q.id).text(text).await?;
+                return Ok(());
+            }
+        },
+    };
+    let Some(vae) = vae else {
+        bot.answer_callback_query(q.id)
+            .text(translator.text("Sorry, this VAE is no longer available."))
+            .await?;
+        return Ok(());
+    };
+
+    let state = match state {
+        State::Ready {
+            bot_state,
+            mut txt2img,
+            mut img2img,
+        } => {
+            txt2img.set_vae(vae.clone());
+            img2img.set_vae(vae.clone());
+            State::Ready {
+                bot_state,
+                txt2img,
+                img2img,
+            }
+        }
+        State::New => {
+            let mut txt2img = backends.txt2img_defaults(message.chat.id);
+            let mut img2img = backends.img2img_defaults(message.chat.id);
+            txt2img.set_vae(vae.clone());
+            img2img.set_vae(vae.clone());
+            State::new_with_defaults(txt2img, img2img)
+        }
+    };
+    dialogue.update(state).await.map_err(|e| anyhow!(e))?;
+
+    if let Err(e) = bot
+        .answer_callback_query(q.id)
+        .text(translator.text("VAE set."))
+        .await
+    {
+        warn!("Failed to answer VAE selection callback query: {}", e)
+    }
+    let text = if choice == VaeChoice::Automatic {
+        translator.text("The backend picks the VAE matching the model.")
+    } else {
+        translator.format("Using {name}.", &[("name", &vae)])
+    };
+    bot.edit_message_text(message.chat.id, message.id, text)
+        .reply_markup(InlineKeyboardMarkup::default())
+        .await?;
+    Ok(())
+}
+
+pub fn vae_schema() -> UpdateHandler<anyhow::Error> {
+    let command_handler = Update::filter_message()
+        .chain(filter_command::<VaeCommands>())
+        .branch(case![VaeCommands::Vae].endpoint(handle_vae_command));
+
+    let callback_handler = Update::filter_callback_query()
+        .branch(
+            dptree::filter(|q: CallbackQuery| q.data.filter(|d| d == "vae").is_some())
+                .endpoint(handle_vae_button),
+        )
+        .branch(
+            dptree::filter_map(|q: CallbackQuery| VaeChoice::parse(q.data.as_deref()?))
+                .endpoint(handle_vae_selection),
+        );
+
+    dptree::entry()
+        .branch(command_handler)
+        .branch(callback_handler)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vae_keyboard() {
+        let vaes = vec!["sdxl_vae.safetensors".to_owned()];
+        let buttons = |current| {
+            vae_keyboard(&vaes, current)
+                .inline_keyboard
+                .into_iter()
+                .flatten()
+                .map(|button| button.text)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(buttons(None), vec!["Automatic", "sdxl_vae.safetensors"]);
+        assert_eq!(
+            buttons(Some("Automatic")),
+            vec!["✅ Automatic", "sdxl_vae.safetensors"]
+        );
+        assert_eq!(
+            buttons(Some("sdxl_vae.safetensors")),
+            vec!["Automatic", "✅ sdxl_vae.safetensors"]
+        );
+        assert_eq!(VaeChoice::parse("vae/auto"), Some(VaeChoice::Automatic));
+        assert_eq!(VaeChoice::parse("vae/3"), Some(VaeChoice::Index(3)));
+        assert_eq!(VaeChoice::parse("vae"), None);
+    }
+}
@@ -0,0 +1,168 @@
+use teloxide::{dispatching::UpdateHandler, dptree::case, macros::BotCommands, prelude::*};
+use tracing::instrument;
+
+use super::{filter_command, SendContext};
+use crate::bot::{
+    history::HistoryStore,
+    i18n::Translator,
+    variables::{self, VarAction, MAX_VALUE_LENGTH, MAX_VARIABLES},
+};
+
+const USAGE: &str = "Usage:\n\
+    /var set <name> \"<value>\"\n\
+    /var list\n\
+    /var unset <name>\n\n\
+    Use a variable in a prompt by writing $name.";
+
+/// BotCommands for managing chat variables.
+#[derive(BotCommands, Debug, Clone)]
+#[command(rename_rule = "lowercase", description = "Variable commands")]
+pub(crate) enum VarCommands {
+    /// Command to set, list or unset the prompt variables of a chat.
+    #[command(
+        description = "set prompt variables for this chat, e.g. /var set character \"a red-haired knight\", and use them as $character."
+    )]
+    Var(String),
+}
+
+/// Runs a variable command for `chat_id`, returning the reply.
+async fn run_action(
+    history: &HistoryStore,
+    chat_id: ChatId,
+    action: VarAction<'_>,
+    translator: &Translator,
+) -> anyhow::Result<String> {
+    let reply = match action {
+        VarAction::List => {
+            let variables = history.chat_variables(chat_id).await?;
+            if variables.is_empty() {
+                format!(
+                    "{}\n\n{}",
+                    translator.text("This chat has no variables."),
+                    translator.text(USAGE)
+                )
+            } else {
+                variables
+                    .iter()
+                    .map(|(name, value)| format!("${name}: {value}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        }
+        VarAction::Set { name, .. } if !variables::is_valid_name(name) => translator.format(
+            "Variable names must start with a letter and may only contain letters, numbers and _, and be at most {max} characters long.",
+            &[("max", &variables::MAX_NAME_LENGTH.to_string())],
+        ),
+        VarAction::Set { value, .. } if value.chars().count() > MAX_VALUE_LENGTH => translator
+            .format(
+                "Variables may be at most {max} characters long.",
+                &[("max", &MAX_VALUE_LENGTH.to_string())],
+            ),
+        VarAction::Set { name, value } => {
+            let variables = history.chat_variables(chat_id).await?;
+            if variables.len() >= MAX_VARIABLES && !variables.iter().any(|(n, _)| n == name) {
+                translator.format(
+                    "A chat can have at most {max} variables. Unset one first.",
+                    &[("max", &MAX_VARIABLES.to_string())],
+                )
+            } else {
+                history.set_chat_variable(chat_id, name, value).await?;
+                translator.format("Set ${name}.", &[("name", name)])
+            }
+        }
+        VarAction::Unset { name } => {
+            if history.unset_chat_variable(chat_id, name).await? {
+                translator.format("Unset ${name}.", &[("name", name)])
+            } else {
+                translator.format("This chat has no variable named {name}.", &[("name", name)])
+            }
+        }
+    };
+    Ok(reply)
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        chat_id = %msg.chat.id,
+        user_id = ?msg.from().map(|user| user.id),
+        command = "var"
+    )
+)]
+async fn handle_var_command(
+    bot: Bot,
+    history: HistoryStore,
+    msg: Message,
+    args: String,
+    translator: Translator,
+) -> anyhow::Result<()> {
+    let reply = match variables::parse_action(&args) {
+        Some(action) => run_action(&history, msg.chat.id, action, &translator).await?,
+        None => translator.text(USAGE),
+    };
+    SendContext::of(&msg)
+        .send_message(&bot, reply)
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+pub fn var_schema() -> UpdateHandler<anyhow::Error> {
+    Update::filter_message()
+        .chain(filter_command::<VarCommands>())
+        .branch(case![VarCommands::Var(args)].endpoint(handle_var_command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_action() {
+        let history = HistoryStore::open(None).await.unwrap();
+        let chat = ChatId(1);
+        assert_eq!(
+            run_action(
+                &history,
+                chat,
+                VarAction::Set {
+                    name: "character",
+                    value: "a red-haired knight"
+                },
+                &Translator::default()
+            )
+            .await
+            .unwrap(),
+            "Set $character."
+        );
+        assert!(run_action(
+            &history,
+            chat,
+            VarAction::Set {
+                name: "red-knight",
+                value: "x"
+            },
+            &Translator::default()
+        )
+        .await
+        .unwrap()
+        .starts_with("Variable names"));
+        assert_eq!(
+            run_action(&history, chat, VarAction::List, &Translator::default())
+                .await
+                .unwrap(),
+            "$character: a red-haired knight"
+        );
+        assert_eq!(
+            run_action(
+                &history,
+                chat,
+                VarAction::Unset { name: "character" },
+                &Translator::default()
+            )
+            .await
+            .unwrap(),
+            "Unset $character."
+        );
+    }
+}
@@ -1,9 +1,13 @@
+use std::path::Path;
+
 use anyhow::Context;
 use futures::TryStreamExt;
 use teloxide::{net::Download, types::File, Bot};
 
 /// Download a Telegram `File` and return its contents as bytes.
 ///
+/// Files from a local Bot API server are read directly from disk.
+///
 /// # Examples
 ///
 /// ```ignore
@@ -24,6 +28,20 @@ use teloxide::{net::Download, types::File, Bot};
 /// }
 /// ```
 pub async fn get_file(bot: &Bot, file: &File) -> anyhow::Result<bytes::Bytes> {
+    // A Bot API server running in `--local` mode returns absolute paths on its own filesystem
+    // instead of paths relative to the file download endpoint.
+    if Path::new(&file.path).is_absolute() {
+        return tokio::fs::read(&file.path)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to read {} from the local Bot API server, is its data directory \
+                     accessible to the bot?",
+                    file.path
+                )
+            })
+            .map(bytes::Bytes::from);
+    }
     bot.download_file_stream(&file.path)
         .try_collect()
         .await
@@ -0,0 +1,1649 @@
+use std::{
+    borrow::Cow,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteRow},
+    Row, SqlitePool,
+};
+use teloxide::types::{ChatId, MessageId, UserId};
+
+use super::{
+    ab::{AbChoice, AbTally},
+    accounts::AccountResolver,
+    dialogue_codec::{compress, decompress, DialogueCodec},
+    styles::Style,
+};
+
+/// Schema migrations, applied in order and tracked with `PRAGMA user_version`.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE generations (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        chat_id INTEGER NOT NULL,
+        user_id INTEGER,
+        source_message_id INTEGER NOT NULL,
+        reply_message_ids TEXT NOT NULL,
+        prompt TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    CREATE INDEX generations_source ON generations (chat_id, source_message_id);",
+    "ALTER TABLE generations ADD COLUMN purged_at INTEGER;",
+    "CREATE TABLE negative_presets (
+        chat_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        PRIMARY KEY (chat_id, name)
+    );",
+    "CREATE TABLE snippets (
+        user_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        text TEXT NOT NULL,
+        PRIMARY KEY (user_id, name)
+    );",
+    "ALTER TABLE generations ADD COLUMN seed INTEGER;
+    ALTER TABLE generations ADD COLUMN negative_prompt TEXT;
+    ALTER TABLE generations ADD COLUMN params TEXT;
+    ALTER TABLE generations ADD COLUMN backend TEXT;",
+    "CREATE TABLE image_usage (
+        user_id INTEGER NOT NULL,
+        images INTEGER NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    CREATE INDEX image_usage_user ON image_usage (user_id, created_at);",
+    "CREATE TABLE user_access (
+        user_id INTEGER PRIMARY KEY,
+        allowed INTEGER NOT NULL
+    );",
+    "CREATE TABLE styles (
+        user_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        prompt_prefix TEXT,
+        prompt_suffix TEXT,
+        negative_prompt TEXT,
+        cfg REAL,
+        steps INTEGER,
+        PRIMARY KEY (user_id, name)
+    );
+    CREATE TABLE chat_styles (
+        chat_id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL
+    );",
+    "CREATE TABLE chat_backends (
+        chat_id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL
+    );",
+    "CREATE TABLE state_corrupt (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        chat_id INTEGER NOT NULL,
+        dialogue BLOB NOT NULL,
+        error TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );",
+    "CREATE TABLE ab_tests (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        chat_id INTEGER NOT NULL,
+        user_id INTEGER,
+        prompt_a TEXT NOT NULL,
+        prompt_b TEXT NOT NULL,
+        seed INTEGER NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    CREATE TABLE ab_votes (
+        test_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        choice TEXT NOT NULL,
+        PRIMARY KEY (test_id, user_id)
+    );",
+    "ALTER TABLE generations ADD COLUMN details TEXT;",
+    "ALTER TABLE generations ADD COLUMN fingerprint TEXT;",
+    "CREATE TABLE user_languages (
+        user_id INTEGER PRIMARY KEY,
+        language TEXT NOT NULL
+    );",
+    "CREATE TABLE chat_variables (
+        chat_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (chat_id, name)
+    );",
+    "CREATE TABLE user_settings (
+        user_id INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        setting TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (user_id, kind, setting)
+    );",
+    "CREATE TABLE linked_settings (chat_id INTEGER PRIMARY KEY);",
+    "ALTER TABLE image_usage ADD COLUMN account TEXT;
+    UPDATE image_usage SET account = 'user:' || user_id;
+    CREATE INDEX image_usage_account ON image_usage (account, created_at);",
+];
+
+const GENERATION_COLUMNS: &str = "id, chat_id, user_id, source_message_id, reply_message_ids, \
+    prompt, created_at, purged_at, seed, details, fingerprint, negative_prompt, params, backend";
+
+/// Returns the current time in seconds since the Unix epoch.
+pub(crate) fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// A generation to add to the history.
+#[derive(Debug, Clone)]
+pub(crate) struct NewGeneration<'a> {
+    pub chat_id: ChatId,
+    pub user_id: Option<UserId>,
+    /// The message containing the prompt.
+    pub source_message_id: MessageId,
+    /// The messages sent in reply, containing the images and keyboard.
+    pub reply_message_ids: &'a [MessageId],
+    pub prompt: &'a str,
+    /// The seed of the first image, if known.
+    pub seed: Option<i64>,
+    /// The full caption, if the images were sent with only the prompt.
+    pub details: Option<&'a str>,
+    /// The fingerprint of the parameters, see `GenParams::fingerprint`.
+    pub fingerprint: Option<&'a str>,
+    pub negative_prompt: Option<&'a str>,
+    /// The parameters as JSON, if the generation can be run again from the history.
+    pub params: Option<&'a str>,
+    /// The backend that generated the images.
+    pub backend: Option<&'a str>,
+}
+
+/// A generation recorded in the history.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Generation {
+    pub id: i64,
+    pub chat_id: ChatId,
+    pub user_id: Option<UserId>,
+    pub source_message_id: MessageId,
+    pub reply_message_ids: Vec<MessageId>,
+    pub prompt: String,
+    /// Time of the generation in seconds since the Unix epoch.
+    pub created_at: i64,
+    /// Time the generation was deleted by the user, if it was.
+    pub purged_at: Option<i64>,
+    /// The seed of the first image, if known.
+    pub seed: Option<i64>,
+    /// The full caption, if the images were sent with only the prompt.
+    pub details: Option<String>,
+    /// The fingerprint of the parameters, see `GenParams::fingerprint`.
+    pub fingerprint: Option<String>,
+    pub negative_prompt: Option<String>,
+    /// The parameters as JSON, if the generation can be run again from the history.
+    pub params: Option<String>,
+    /// The backend that generated the images.
+    pub backend: Option<String>,
+}
+
+impl TryFrom<SqliteRow> for Generation {
+    type Error = anyhow::Error;
+
+    fn try_from(row: SqliteRow) -> Result<Self, Self::Error> {
+        let reply_message_ids: Vec<i32> = serde_json::from_str(row.try_get("reply_message_ids")?)?;
+        Ok(Self {
+            id: row.try_get("id")?,
+            chat_id: ChatId(row.try_get("chat_id")?),
+            user_id: row
+                .try_get::<Option<i64>, _>("user_id")?
+                .map(|id| UserId(id as u64)),
+            source_message_id: MessageId(row.try_get("source_message_id")?),
+            reply_message_ids: reply_message_ids.into_iter().map(MessageId).collect(),
+            prompt: row.try_get("prompt")?,
+            created_at: row.try_get("created_at")?,
+            purged_at: row.try_get("purged_at")?,
+            seed: row.try_get("seed")?,
+            details: row.try_get("details")?,
+            fingerprint: row.try_get("fingerprint")?,
+            negative_prompt: row.try_get("negative_prompt")?,
+            params: row
+                .try_get::<Option<Vec<u8>>, _>("params")?
+                .map(|params| anyhow::Ok(String::from_utf8(decompress(&params)?.into_owned())?))
+                .transpose()?,
+            backend: row.try_get("backend")?,
+        })
+    }
+}
+
+/// A dialogue state that couldn't be loaded, moved out of the dialogue storage.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct QuarantinedDialogue {
+    pub id: i64,
+    pub chat_id: ChatId,
+    /// The serialized state as it was stored.
+    pub dialogue: Vec<u8>,
+    /// Why the state couldn't be loaded.
+    pub error: String,
+    /// Time the state was quarantined in seconds since the Unix epoch.
+    pub created_at: i64,
+}
+
+/// Store of past generations and per-chat preferences, kept alongside the dialogue storage.
+#[derive(Debug, Clone)]
+pub(crate) struct HistoryStore {
+    pool: SqlitePool,
+    /// Whether to compress the parameters of recorded generations.
+    compress: bool,
+    /// Resolves users to the accounts that their usage is counted against.
+    accounts: AccountResolver,
+}
+
+impl HistoryStore {
+    /// Opens the history store in the database at `path`, or in memory if `None`.
+    pub async fn open(path: Option<&str>) -> anyhow::Result<Self> {
+        let pool = match path {
+            Some(path) => {
+                let options = SqliteConnectOptions::new()
+                    .filename(path)
+                    .create_if_missing(true)
+                    .busy_timeout(Duration::from_secs(5));
+                SqlitePoolOptions::new().connect_with(options).await
+            }
+            // Each in-memory connection is a separate database, so keep exactly one alive.
+            None => {
+                SqlitePoolOptions::new()
+                    .max_connections(1)
+                    .idle_timeout(None)
+                    .max_lifetime(None)
+                    .connect("sqlite::memory:")
+                    .await
+            }
+        }
+        .context("Failed to open history database")?;
+
+        let store = Self {
+            pool,
+            compress: false,
+            accounts: AccountResolver::default(),
+        };
+        store.migrate().await?;
+        Ok(store)
+    }
+
+    /// Sets whether to compress the parameters of generations recorded from now on. Parameters
+    /// are read whether they were compressed or not.
+    pub(crate) fn with_compression(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Sets the accounts that usage is counted against, so members of a shared account share
+    /// their quota.
+    pub(crate) fn with_accounts(mut self, accounts: AccountResolver) -> Self {
+        self.accounts = accounts;
+        self
+    }
+
+    /// Returns the connection pool of the database, for stores that keep their own tables in it.
+    pub(crate) fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+
+    async fn migrate(&self) -> anyhow::Result<()> {
+        let version: i64 = sqlx::query_scalar("PRAGMA user_version")
+            .fetch_one(&self.pool)
+            .await?;
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            let mut tx = self.pool.begin().await?;
+            sqlx::query(migration)
+                .execute(&mut tx)
+                .await
+                .with_context(|| format!("Failed to apply history migration {}", index + 1))?;
+            sqlx::query(&format!("PRAGMA user_version = {}", index + 1))
+                .execute(&mut tx)
+                .await?;
+            tx.commit().await?;
+        }
+        Ok(())
+    }
+
+    /// Adds a generation to the history, returning its id.
+    pub async fn record(&self, generation: NewGeneration<'_>) -> anyhow::Result<i64> {
+        let reply_message_ids = serde_json::to_string(
+            &generation
+                .reply_message_ids
+                .iter()
+                .map(|id| id.0)
+                .collect::<Vec<_>>(),
+        )?;
+        // Plain parameters are kept as text, so the database stays readable without the bot.
+        let (params, compressed_params) = match generation.params {
+            Some(params) if self.compress => (
+                None,
+                Some(
+                    compress(params.as_bytes())
+                        .context("Failed to compress generation parameters")?,
+                ),
+            ),
+            params => (params, None),
+        };
+        let id = sqlx::query(
+            "INSERT INTO generations
+                (chat_id, user_id, source_message_id, reply_message_ids, prompt, created_at, seed,
+                 details, fingerprint, negative_prompt, params, backend)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, COALESCE(?, ?), ?)",
+        )
+        .bind(generation.chat_id.0)
+        .bind(generation.user_id.map(|id| id.0 as i64))
+        .bind(generation.source_message_id.0)
+        .bind(reply_message_ids)
+        .bind(generation.prompt)
+        .bind(now())
+        .bind(generation.seed)
+        .bind(generation.details)
+        .bind(generation.fingerprint)
+        .bind(generation.negative_prompt)
+        .bind(params)
+        .bind(compressed_params)
+        .bind(generation.backend)
+        .execute(&self.pool)
+        .await
+        .context("Failed to record generation")?
+        .last_insert_rowid();
+        Ok(id)
+    }
+
+    /// Returns the most recent generation prompted by the given message, unless it was purged.
+    pub async fn find_by_source(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+    ) -> anyhow::Result<Option<Generation>> {
+        sqlx::query(&format!(
+            "SELECT {GENERATION_COLUMNS} FROM generations
+             WHERE chat_id = ? AND source_message_id = ? AND purged_at IS NULL
+             ORDER BY id DESC LIMIT 1"
+        ))
+        .bind(chat_id.0)
+        .bind(message_id.0)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to look up generation")?
+        .map(Generation::try_from)
+        .transpose()
+    }
+
+    /// Returns the generation whose reply includes the given message, unless it was purged.
+    pub async fn find_by_reply(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+    ) -> anyhow::Result<Option<Generation>> {
+        sqlx::query(&format!(
+            "SELECT {GENERATION_COLUMNS} FROM generations
+             WHERE chat_id = ? AND purged_at IS NULL
+                AND EXISTS (SELECT 1 FROM json_each(reply_message_ids) WHERE value = ?)
+             ORDER BY id DESC LIMIT 1"
+        ))
+        .bind(chat_id.0)
+        .bind(message_id.0)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to look up generation")?
+        .map(Generation::try_from)
+        .transpose()
+    }
+
+    /// Returns the most recent generation in a chat, unless it was purged.
+    pub async fn latest(&self, chat_id: ChatId) -> anyhow::Result<Option<Generation>> {
+        sqlx::query(&format!(
+            "SELECT {GENERATION_COLUMNS} FROM generations
+             WHERE chat_id = ? AND purged_at IS NULL
+             ORDER BY id DESC LIMIT 1"
+        ))
+        .bind(chat_id.0)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to look up generation")?
+        .map(Generation::try_from)
+        .transpose()
+    }
+
+    /// Returns a generation in a chat by its id, unless it was purged.
+    pub async fn find(&self, chat_id: ChatId, id: i64) -> anyhow::Result<Option<Generation>> {
+        sqlx::query(&format!(
+            "SELECT {GENERATION_COLUMNS} FROM generations
+             WHERE chat_id = ? AND id = ? AND purged_at IS NULL"
+        ))
+        .bind(chat_id.0)
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to look up generation")?
+        .map(Generation::try_from)
+        .transpose()
+    }
+
+    /// Returns up to `limit` generations in a chat, newest first, skipping the `offset` newest.
+    /// Purged generations are left out.
+    pub async fn recent(
+        &self,
+        chat_id: ChatId,
+        offset: u32,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Generation>> {
+        sqlx::query(&format!(
+            "SELECT {GENERATION_COLUMNS} FROM generations
+             WHERE chat_id = ? AND purged_at IS NULL
+             ORDER BY id DESC LIMIT ? OFFSET ?"
+        ))
+        .bind(chat_id.0)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to get recent generations")?
+        .into_iter()
+        .map(Generation::try_from)
+        .collect()
+    }
+
+    /// Marks a generation as purged, erasing its prompts, caption, parameters and fingerprint,
+    /// which could be used to confirm a guess of the prompt.
+    pub async fn purge(&self, id: i64) -> anyhow::Result<()> {
+        sqlx::query(
+            "UPDATE generations SET prompt = '', details = NULL, fingerprint = NULL,
+                negative_prompt = NULL, params = NULL, purged_at = ?
+             WHERE id = ?",
+        )
+        .bind(now())
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .context("Failed to purge generation")?;
+        Ok(())
+    }
+
+    /// Deletes generations, A/B tests and quarantined dialogue states created before `cutoff`, in
+    /// seconds since the Unix epoch, returning how many generations were deleted.
+    pub async fn delete_older_than(&self, cutoff: i64) -> anyhow::Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        for query in [
+            "DELETE FROM ab_votes WHERE test_id IN (SELECT id FROM ab_tests WHERE created_at < ?)",
+            "DELETE FROM ab_tests WHERE created_at < ?",
+        ] {
+            sqlx::query(query)
+                .bind(cutoff)
+                .execute(&mut tx)
+                .await
+                .context("Failed to delete expired A/B tests")?;
+        }
+        sqlx::query("DELETE FROM state_corrupt WHERE created_at < ?")
+            .bind(cutoff)
+            .execute(&mut tx)
+            .await
+            .context("Failed to delete expired quarantined dialogues")?;
+        let result = sqlx::query("DELETE FROM generations WHERE created_at < ?")
+            .bind(cutoff)
+            .execute(&mut tx)
+            .await
+            .context("Failed to delete expired generations")?;
+        tx.commit().await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Returns the names of the negative prompt presets enabled in a chat.
+    pub async fn negative_presets(&self, chat_id: ChatId) -> anyhow::Result<Vec<String>> {
+        sqlx::query_scalar("SELECT name FROM negative_presets WHERE chat_id = ? ORDER BY name")
+            .bind(chat_id.0)
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to get negative prompt presets")
+    }
+
+    /// Toggles a negative prompt preset in a chat, returning whether it is now enabled.
+    pub async fn toggle_negative_preset(
+        &self,
+        chat_id: ChatId,
+        name: &str,
+    ) -> anyhow::Result<bool> {
+        let removed = sqlx::query("DELETE FROM negative_presets WHERE chat_id = ? AND name = ?")
+            .bind(chat_id.0)
+            .bind(name)
+            .execute(&self.pool)
+            .await
+            .context("Failed to disable negative prompt preset")?
+            .rows_affected();
+        if removed > 0 {
+            return Ok(false);
+        }
+        sqlx::query("INSERT INTO negative_presets (chat_id, name) VALUES (?, ?)")
+            .bind(chat_id.0)
+            .bind(name)
+            .execute(&self.pool)
+            .await
+            .context("Failed to enable negative prompt preset")?;
+        Ok(true)
+    }
+
+    /// Returns whether changes to common settings in a chat apply to both txt2img and img2img.
+    pub async fn linked_settings(&self, chat_id: ChatId) -> anyhow::Result<bool> {
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM linked_settings WHERE chat_id = ?)")
+            .bind(chat_id.0)
+            .fetch_one(&self.pool)
+            .await
+            .context("Failed to get linked settings")
+    }
+
+    /// Toggles whether changes to common settings in a chat apply to both txt2img and img2img,
+    /// returning whether they now do.
+    pub async fn toggle_linked_settings(&self, chat_id: ChatId) -> anyhow::Result<bool> {
+        let removed = sqlx::query("DELETE FROM linked_settings WHERE chat_id = ?")
+            .bind(chat_id.0)
+            .execute(&self.pool)
+            .await
+            .context("Failed to unlink settings")?
+            .rows_affected();
+        if removed > 0 {
+            return Ok(false);
+        }
+        sqlx::query("INSERT INTO linked_settings (chat_id) VALUES (?)")
+            .bind(chat_id.0)
+            .execute(&self.pool)
+            .await
+            .context("Failed to link settings")?;
+        Ok(true)
+    }
+
+    /// Returns the name of the style selected in a chat, if any.
+    pub async fn chat_style(&self, chat_id: ChatId) -> anyhow::Result<Option<String>> {
+        sqlx::query_scalar("SELECT name FROM chat_styles WHERE chat_id = ?")
+            .bind(chat_id.0)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to get the selected style")
+    }
+
+    /// Selects a style in a chat, or deselects the selected style if `name` is `None`.
+    pub async fn set_chat_style(&self, chat_id: ChatId, name: Option<&str>) -> anyhow::Result<()> {
+        let query = match name {
+            Some(name) => {
+                sqlx::query("INSERT OR REPLACE INTO chat_styles (chat_id, name) VALUES (?, ?)")
+                    .bind(chat_id.0)
+                    .bind(name)
+            }
+            None => sqlx::query("DELETE FROM chat_styles WHERE chat_id = ?").bind(chat_id.0),
+        };
+        query
+            .execute(&self.pool)
+            .await
+            .context("Failed to select style")?;
+        Ok(())
+    }
+
+    /// Returns the name of the backend selected in a chat, if any.
+    pub async fn chat_backend(&self, chat_id: ChatId) -> anyhow::Result<Option<String>> {
+        sqlx::query_scalar("SELECT name FROM chat_backends WHERE chat_id = ?")
+            .bind(chat_id.0)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to get the selected backend")
+    }
+
+    /// Selects a backend in a chat, or goes back to the default backend if `name` is `None`.
+    pub async fn set_chat_backend(
+        &self,
+        chat_id: ChatId,
+        name: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let query = match name {
+            Some(name) => {
+                sqlx::query("INSERT OR REPLACE INTO chat_backends (chat_id, name) VALUES (?, ?)")
+                    .bind(chat_id.0)
+                    .bind(name)
+            }
+            None => sqlx::query("DELETE FROM chat_backends WHERE chat_id = ?").bind(chat_id.0),
+        };
+        query
+            .execute(&self.pool)
+            .await
+            .context("Failed to select backend")?;
+        Ok(())
+    }
+
+    /// Deletes the preferences of a chat.
+    pub async fn delete_chat_preferences(&self, chat_id: ChatId) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM negative_presets WHERE chat_id = ?")
+            .bind(chat_id.0)
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete chat preferences")?;
+        sqlx::query("DELETE FROM linked_settings WHERE chat_id = ?")
+            .bind(chat_id.0)
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete chat preferences")?;
+        self.set_chat_backend(chat_id, None).await?;
+        self.set_chat_style(chat_id, None).await
+    }
+
+    /// Returns a user's styles, ordered by name.
+    pub async fn styles(&self, user_id: UserId) -> anyhow::Result<Vec<Style>> {
+        let rows = sqlx::query(
+            "SELECT name, prompt_prefix, prompt_suffix, negative_prompt, cfg, steps
+             FROM styles WHERE user_id = ? ORDER BY name",
+        )
+        .bind(user_id.0 as i64)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to get styles")?;
+        rows.iter()
+            .map(|row| {
+                Ok(Style {
+                    name: row.try_get("name")?,
+                    prompt_prefix: row.try_get("prompt_prefix")?,
+                    prompt_suffix: row.try_get("prompt_suffix")?,
+                    negative_prompt: row.try_get("negative_prompt")?,
+                    cfg: row.try_get("cfg")?,
+                    steps: row.try_get("steps")?,
+                })
+            })
+            .collect::<Result<_, sqlx::Error>>()
+            .context("Failed to read style")
+    }
+
+    /// Saves a style for a user, replacing any style with the same name.
+    pub async fn set_style(&self, user_id: UserId, style: &Style) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO styles
+             (user_id, name, prompt_prefix, prompt_suffix, negative_prompt, cfg, steps)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(user_id.0 as i64)
+        .bind(&style.name)
+        .bind(&style.prompt_prefix)
+        .bind(&style.prompt_suffix)
+        .bind(&style.negative_prompt)
+        .bind(style.cfg)
+        .bind(style.steps)
+        .execute(&self.pool)
+        .await
+        .context("Failed to save style")?;
+        Ok(())
+    }
+
+    /// Deletes one of a user's styles, returning whether it existed.
+    pub async fn delete_style(&self, user_id: UserId, name: &str) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM styles WHERE user_id = ? AND name = ?")
+            .bind(user_id.0 as i64)
+            .bind(name)
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete style")?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Deletes all of a user's styles.
+    pub async fn delete_styles(&self, user_id: UserId) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM styles WHERE user_id = ?")
+            .bind(user_id.0 as i64)
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete styles")?;
+        Ok(())
+    }
+
+    /// Returns a user's prompt snippets as `(name, text)` pairs, ordered by name.
+    pub async fn snippets(&self, user_id: UserId) -> anyhow::Result<Vec<(String, String)>> {
+        sqlx::query_as("SELECT name, text FROM snippets WHERE user_id = ? ORDER BY name")
+            .bind(user_id.0 as i64)
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to get snippets")
+    }
+
+    /// Saves a prompt snippet for a user, replacing any snippet with the same name.
+    pub async fn set_snippet(&self, user_id: UserId, name: &str, text: &str) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO snippets (user_id, name, text) VALUES (?, ?, ?)
+            ON CONFLICT (user_id, name) DO UPDATE SET text = excluded.text",
+        )
+        .bind(user_id.0 as i64)
+        .bind(name)
+        .bind(text)
+        .execute(&self.pool)
+        .await
+        .context("Failed to save snippet")?;
+        Ok(())
+    }
+
+    /// Deletes one of a user's prompt snippets, returning whether it existed.
+    pub async fn delete_snippet(&self, user_id: UserId, name: &str) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM snippets WHERE user_id = ? AND name = ?")
+            .bind(user_id.0 as i64)
+            .bind(name)
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete snippet")?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Deletes all of a user's prompt snippets.
+    pub async fn delete_snippets(&self, user_id: UserId) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM snippets WHERE user_id = ?")
+            .bind(user_id.0 as i64)
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete snippets")?;
+        Ok(())
+    }
+
+    /// Returns a chat's prompt variables as `(name, value)` pairs, ordered by name.
+    pub async fn chat_variables(&self, chat_id: ChatId) -> anyhow::Result<Vec<(String, String)>> {
+        sqlx::query_as("SELECT name, value FROM chat_variables WHERE chat_id = ? ORDER BY name")
+            .bind(chat_id.0)
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to get chat variables")
+    }
+
+    /// Sets a prompt variable for a chat, replacing its value if it's already set.
+    pub async fn set_chat_variable(
+        &self,
+        chat_id: ChatId,
+        name: &str,
+        value: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO chat_variables (chat_id, name, value) VALUES (?, ?, ?)
+            ON CONFLICT (chat_id, name) DO UPDATE SET value = excluded.value",
+        )
+        .bind(chat_id.0)
+        .bind(name)
+        .bind(value)
+        .execute(&self.pool)
+        .await
+        .context("Failed to set chat variable")?;
+        Ok(())
+    }
+
+    /// Unsets one of a chat's prompt variables, returning whether it was set.
+    pub async fn unset_chat_variable(&self, chat_id: ChatId, name: &str) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM chat_variables WHERE chat_id = ? AND name = ?")
+            .bind(chat_id.0)
+            .bind(name)
+            .execute(&self.pool)
+            .await
+            .context("Failed to unset chat variable")?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns the language a user chose for the bot's messages, if any.
+    pub async fn user_language(&self, user_id: UserId) -> anyhow::Result<Option<String>> {
+        sqlx::query_scalar("SELECT language FROM user_languages WHERE user_id = ?")
+            .bind(user_id.0 as i64)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to get user language")
+    }
+
+    /// Sets the language of a user's messages, or goes back to the default if `None`.
+    pub async fn set_user_language(
+        &self,
+        user_id: UserId,
+        language: Option<&str>,
+    ) -> anyhow::Result<()> {
+        match language {
+            Some(language) => sqlx::query(
+                "INSERT INTO user_languages (user_id, language) VALUES (?, ?)
+                ON CONFLICT (user_id) DO UPDATE SET language = excluded.language",
+            )
+            .bind(user_id.0 as i64)
+            .bind(language),
+            None => {
+                sqlx::query("DELETE FROM user_languages WHERE user_id = ?").bind(user_id.0 as i64)
+            }
+        }
+        .execute(&self.pool)
+        .await
+        .context("Failed to set user language")?;
+        Ok(())
+    }
+
+    /// Records that a user generated `images` images, for enforcing quotas. The usage counts
+    /// against the user's account.
+    pub async fn record_usage(&self, user_id: UserId, images: u32) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO image_usage (user_id, account, images, created_at) VALUES (?, ?, ?, ?)",
+        )
+        .bind(user_id.0 as i64)
+        .bind(self.accounts.resolve(user_id).key())
+        .bind(images)
+        .bind(now())
+        .execute(&self.pool)
+        .await
+        .context("Failed to record image usage")?;
+        Ok(())
+    }
+
+    /// Returns the usage of a user's account since `since`, in seconds since the Unix epoch, as
+    /// `(time, images)` pairs in ascending order of time. For a shared account, this includes
+    /// the usage of every member.
+    pub async fn usage_since(
+        &self,
+        user_id: UserId,
+        since: i64,
+    ) -> anyhow::Result<Vec<(i64, u32)>> {
+        sqlx::query_as(
+            "SELECT created_at, images FROM image_usage
+             WHERE account = ? AND created_at > ? ORDER BY created_at",
+        )
+        .bind(self.accounts.resolve(user_id).key())
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to get image usage")
+    }
+
+    /// Deletes usage recorded before `cutoff`, in seconds since the Unix epoch.
+    pub async fn delete_usage_before(&self, cutoff: i64) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM image_usage WHERE created_at < ?")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete expired image usage")?;
+        Ok(())
+    }
+
+    /// Records that an admin allowed or banned a user, replacing any earlier decision.
+    pub async fn set_user_access(&self, user_id: UserId, allowed: bool) -> anyhow::Result<()> {
+        sqlx::query("INSERT OR REPLACE INTO user_access (user_id, allowed) VALUES (?, ?)")
+            .bind(user_id.0 as i64)
+            .bind(allowed)
+            .execute(&self.pool)
+            .await
+            .context("Failed to save user access")?;
+        Ok(())
+    }
+
+    /// Returns the users admins have allowed or banned, and whether each is allowed.
+    pub async fn user_access(&self) -> anyhow::Result<Vec<(UserId, bool)>> {
+        let rows: Vec<(i64, bool)> =
+            sqlx::query_as("SELECT user_id, allowed FROM user_access ORDER BY user_id")
+                .fetch_all(&self.pool)
+                .await
+                .context("Failed to get user access")?;
+        Ok(rows
+            .into_iter()
+            .map(|(user_id, allowed)| (UserId(user_id as u64), allowed))
+            .collect())
+    }
+
+    /// Copies the stored dialogue state of a chat, which couldn't be loaded because of `error`,
+    /// to the quarantine. Returns whether it's the first state of the chat to be quarantined, or
+    /// `None` if the chat has no stored state.
+    pub async fn quarantine_dialogue(
+        &self,
+        chat_id: ChatId,
+        error: &str,
+    ) -> anyhow::Result<Option<bool>> {
+        let mut tx = self.pool.begin().await?;
+        let earlier: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM state_corrupt WHERE chat_id = ?")
+                .bind(chat_id.0)
+                .fetch_one(&mut tx)
+                .await
+                .context("Failed to count quarantined dialogues")?;
+        let copied = sqlx::query(
+            "INSERT INTO state_corrupt (chat_id, dialogue, error, created_at)
+             SELECT chat_id, dialogue, ?, ? FROM teloxide_dialogues WHERE chat_id = ?",
+        )
+        .bind(error)
+        .bind(now())
+        .bind(chat_id.0)
+        .execute(&mut tx)
+        .await
+        .context("Failed to quarantine dialogue")?
+        .rows_affected();
+        tx.commit().await?;
+        Ok((copied > 0).then_some(earlier == 0))
+    }
+
+    /// Returns the quarantined dialogue states, newest first.
+    pub async fn quarantined_dialogues(&self) -> anyhow::Result<Vec<QuarantinedDialogue>> {
+        let rows: Vec<(i64, i64, Vec<u8>, String, i64)> = sqlx::query_as(
+            "SELECT id, chat_id, dialogue, error, created_at FROM state_corrupt ORDER BY id DESC",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to get quarantined dialogues")?;
+        Ok(rows
+            .into_iter()
+            .map(
+                |(id, chat_id, dialogue, error, created_at)| QuarantinedDialogue {
+                    id,
+                    chat_id: ChatId(chat_id),
+                    // Compressed states are shown decompressed if they can be.
+                    dialogue: DialogueCodec::decode(&dialogue)
+                        .map_or_else(|_| dialogue.clone(), Cow::into_owned),
+                    error,
+                    created_at,
+                },
+            )
+            .collect())
+    }
+
+    /// Deletes a quarantined dialogue state, or all of them if `id` is `None`, returning how
+    /// many were deleted.
+    pub async fn delete_quarantined_dialogues(&self, id: Option<i64>) -> anyhow::Result<u64> {
+        let query = match id {
+            Some(id) => sqlx::query("DELETE FROM state_corrupt WHERE id = ?").bind(id),
+            None => sqlx::query("DELETE FROM state_corrupt"),
+        };
+        Ok(query
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete quarantined dialogues")?
+            .rows_affected())
+    }
+
+    /// Records an A/B test of `prompt_a` and `prompt_b` with `seed`, returning its id.
+    pub async fn create_ab_test(
+        &self,
+        chat_id: ChatId,
+        user_id: Option<UserId>,
+        prompt_a: &str,
+        prompt_b: &str,
+        seed: i64,
+    ) -> anyhow::Result<i64> {
+        let result = sqlx::query(
+            "INSERT INTO ab_tests (chat_id, user_id, prompt_a, prompt_b, seed, created_at)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(chat_id.0)
+        .bind(user_id.map(|id| id.0 as i64))
+        .bind(prompt_a)
+        .bind(prompt_b)
+        .bind(seed)
+        .bind(now())
+        .execute(&self.pool)
+        .await
+        .context("Failed to record A/B test")?;
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Records a user's vote in an A/B test, replacing their earlier vote, and returns the
+    /// votes cast in the test. Returns `None` if there is no such test.
+    pub async fn vote_ab_test(
+        &self,
+        test_id: i64,
+        user_id: UserId,
+        choice: AbChoice,
+    ) -> anyhow::Result<Option<AbTally>> {
+        let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM ab_tests WHERE id = ?)")
+            .bind(test_id)
+            .fetch_one(&self.pool)
+            .await
+            .context("Failed to get A/B test")?;
+        if !exists {
+            return Ok(None);
+        }
+        sqlx::query("INSERT OR REPLACE INTO ab_votes (test_id, user_id, choice) VALUES (?, ?, ?)")
+            .bind(test_id)
+            .bind(user_id.0 as i64)
+            .bind(choice.as_str())
+            .execute(&self.pool)
+            .await
+            .context("Failed to record A/B vote")?;
+        self.ab_tally(test_id).await.map(Some)
+    }
+
+    /// Returns the votes cast in an A/B test.
+    pub async fn ab_tally(&self, test_id: i64) -> anyhow::Result<AbTally> {
+        let rows: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT choice, COUNT(*) FROM ab_votes WHERE test_id = ? GROUP BY choice",
+        )
+        .bind(test_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to count A/B votes")?;
+        let mut tally = AbTally::default();
+        for (choice, votes) in rows {
+            match AbChoice::parse(&choice) {
+                Some(AbChoice::A) => tally.a = votes as u32,
+                Some(AbChoice::B) => tally.b = votes as u32,
+                None => {}
+            }
+        }
+        Ok(tally)
+    }
+
+    /// Deletes all generations and A/B tests requested by a user, their votes in other A/B tests
+    /// and the prompt variables and quarantined dialogue states of their private chat, returning
+    /// how many generations were deleted.
+    pub async fn delete_user(&self, user_id: UserId) -> anyhow::Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        for query in [
+            "DELETE FROM ab_votes WHERE user_id = ?1
+                OR test_id IN (SELECT id FROM ab_tests WHERE user_id = ?1)",
+            "DELETE FROM ab_tests WHERE user_id = ?",
+        ] {
+            sqlx::query(query)
+                .bind(user_id.0 as i64)
+                .execute(&mut tx)
+                .await
+                .context("Failed to delete user's A/B tests")?;
+        }
+        // Telegram uses the user's id as the id of their private chat with the bot.
+        sqlx::query("DELETE FROM chat_variables WHERE chat_id = ?")
+            .bind(user_id.0 as i64)
+            .execute(&mut tx)
+            .await
+            .context("Failed to delete user's chat variables")?;
+        sqlx::query("DELETE FROM state_corrupt WHERE chat_id = ?")
+            .bind(user_id.0 as i64)
+            .execute(&mut tx)
+            .await
+            .context("Failed to delete user's quarantined dialogues")?;
+        let result = sqlx::query("DELETE FROM generations WHERE user_id = ?")
+            .bind(user_id.0 as i64)
+            .execute(&mut tx)
+            .await
+            .context("Failed to delete user's generations")?;
+        tx.commit().await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_history_record_and_find() {
+        let history = HistoryStore::open(None).await.unwrap();
+        assert_eq!(
+            history
+                .find_by_source(ChatId(1), MessageId(10))
+                .await
+                .unwrap(),
+            None
+        );
+
+        for prompt in ["a cat", "a dog"] {
+            history
+                .record(NewGeneration {
+                    chat_id: ChatId(1),
+                    user_id: Some(UserId(2)),
+                    source_message_id: MessageId(10),
+                    reply_message_ids: &[MessageId(11), MessageId(12)],
+                    prompt,
+                    seed: Some(42),
+                    details: Some("Seed: `42`"),
+                    fingerprint: Some("0123abcd"),
+                    negative_prompt: Some("blurry"),
+                    params: Some("{}"),
+                    backend: Some("default"),
+                })
+                .await
+                .unwrap();
+        }
+
+        let generation = history
+            .find_by_source(ChatId(1), MessageId(10))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(generation.prompt, "a dog");
+        assert_eq!(generation.user_id, Some(UserId(2)));
+        assert_eq!(generation.seed, Some(42));
+        assert_eq!(generation.details.as_deref(), Some("Seed: `42`"));
+        assert_eq!(generation.fingerprint.as_deref(), Some("0123abcd"));
+        assert_eq!(generation.negative_prompt.as_deref(), Some("blurry"));
+        assert_eq!(generation.params.as_deref(), Some("{}"));
+        assert_eq!(generation.backend.as_deref(), Some("default"));
+        assert_eq!(
+            history.find(ChatId(1), generation.id).await.unwrap(),
+            Some(generation.clone())
+        );
+        assert_eq!(history.find(ChatId(2), generation.id).await.unwrap(), None);
+        let recent = history.recent(ChatId(1), 0, 10).await.unwrap();
+        assert_eq!(
+            recent.iter().map(|g| g.prompt.as_str()).collect::<Vec<_>>(),
+            ["a dog", "a cat"]
+        );
+        assert_eq!(history.recent(ChatId(1), 1, 10).await.unwrap().len(), 1);
+        assert_eq!(
+            history.latest(ChatId(1)).await.unwrap().map(|g| g.id),
+            Some(generation.id)
+        );
+        assert_eq!(
+            generation.reply_message_ids,
+            vec![MessageId(11), MessageId(12)]
+        );
+        assert!(history
+            .find_by_source(ChatId(2), MessageId(10))
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_compressed_params() {
+        let history = HistoryStore::open(None).await.unwrap();
+        // A row recorded before compression was turned on.
+        sqlx::query(
+            "INSERT INTO generations
+                (chat_id, source_message_id, reply_message_ids, prompt, created_at, params)
+             VALUES (1, 10, '[]', 'a cat', 0, '{\"prompt\":\"a cat\"}')",
+        )
+        .execute(history.pool())
+        .await
+        .unwrap();
+        let history = history.with_compression(true);
+        history
+            .record(NewGeneration {
+                chat_id: ChatId(1),
+                user_id: None,
+                source_message_id: MessageId(20),
+                reply_message_ids: &[],
+                prompt: "a dog",
+                seed: None,
+                details: None,
+                fingerprint: None,
+                negative_prompt: None,
+                params: Some(r#"{"prompt":"a dog"}"#),
+                backend: None,
+            })
+            .await
+            .unwrap();
+
+        let stored: Vec<Vec<u8>> = sqlx::query_scalar("SELECT params FROM generations ORDER BY id")
+            .fetch_all(history.pool())
+            .await
+            .unwrap();
+        assert_eq!(stored[0], br#"{"prompt":"a cat"}"#);
+        assert_ne!(stored[1], br#"{"prompt":"a dog"}"#);
+        let params = |message_id| {
+            let history = history.clone();
+            async move {
+                history
+                    .find_by_source(ChatId(1), MessageId(message_id))
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .params
+            }
+        };
+        assert_eq!(params(10).await.as_deref(), Some(r#"{"prompt":"a cat"}"#));
+        assert_eq!(params(20).await.as_deref(), Some(r#"{"prompt":"a dog"}"#));
+    }
+
+    #[tokio::test]
+    async fn test_history_purge() {
+        let history = HistoryStore::open(None).await.unwrap();
+        let id = history
+            .record(NewGeneration {
+                chat_id: ChatId(1),
+                user_id: None,
+                source_message_id: MessageId(10),
+                reply_message_ids: &[MessageId(11), MessageId(12)],
+                prompt: "a cat",
+                seed: None,
+                details: None,
+                fingerprint: None,
+                negative_prompt: None,
+                params: None,
+                backend: None,
+            })
+            .await
+            .unwrap();
+
+        let generation = history
+            .find_by_reply(ChatId(1), MessageId(12))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(generation.id, id);
+        assert!(history
+            .find_by_reply(ChatId(1), MessageId(10))
+            .await
+            .unwrap()
+            .is_none());
+
+        history.purge(id).await.unwrap();
+        assert!(history.find(ChatId(1), id).await.unwrap().is_none());
+        assert!(history.recent(ChatId(1), 0, 10).await.unwrap().is_empty());
+        assert!(history
+            .find_by_reply(ChatId(1), MessageId(12))
+            .await
+            .unwrap()
+            .is_none());
+        assert!(history
+            .find_by_source(ChatId(1), MessageId(10))
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_history_delete() {
+        let history = HistoryStore::open(None).await.unwrap();
+        for (user_id, source) in [(2, 10), (2, 20), (3, 30)] {
+            history
+                .record(NewGeneration {
+                    chat_id: ChatId(1),
+                    user_id: Some(UserId(user_id)),
+                    source_message_id: MessageId(source),
+                    reply_message_ids: &[MessageId(source + 1)],
+                    prompt: "a cat",
+                    seed: None,
+                    details: None,
+                    fingerprint: None,
+                    negative_prompt: None,
+                    params: None,
+                    backend: None,
+                })
+                .await
+                .unwrap();
+        }
+
+        let own_test = history
+            .create_ab_test(ChatId(1), Some(UserId(2)), "a cat", "a dog", 42)
+            .await
+            .unwrap();
+        let other_test = history
+            .create_ab_test(ChatId(1), Some(UserId(3)), "a cat", "a dog", 42)
+            .await
+            .unwrap();
+        for user_id in [2, 3] {
+            history
+                .vote_ab_test(other_test, UserId(user_id), AbChoice::A)
+                .await
+                .unwrap();
+        }
+
+        for chat_id in [1, 2, 3] {
+            history
+                .set_chat_variable(ChatId(chat_id), "subject", "a cat")
+                .await
+                .unwrap();
+        }
+        for (chat_id, created_at) in [(2, now()), (3, now()), (3, 0)] {
+            sqlx::query(
+                "INSERT INTO state_corrupt (chat_id, dialogue, error, created_at)
+                 VALUES (?, 'broken', 'invalid', ?)",
+            )
+            .bind(chat_id)
+            .bind(created_at)
+            .execute(history.pool())
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(history.delete_user(UserId(2)).await.unwrap(), 2);
+        assert!(history.chat_variables(ChatId(2)).await.unwrap().is_empty());
+        for chat_id in [1, 3] {
+            assert_eq!(
+                history.chat_variables(ChatId(chat_id)).await.unwrap().len(),
+                1
+            );
+        }
+        let quarantined = history.quarantined_dialogues().await.unwrap();
+        assert!(quarantined
+            .iter()
+            .all(|dialogue| dialogue.chat_id == ChatId(3)));
+        assert_eq!(quarantined.len(), 2);
+        assert!(history
+            .find_by_source(ChatId(1), MessageId(10))
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            history
+                .vote_ab_test(own_test, UserId(3), AbChoice::A)
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            history.ab_tally(other_test).await.unwrap(),
+            AbTally { a: 1, b: 0 }
+        );
+
+        assert_eq!(history.delete_older_than(now() - 60).await.unwrap(), 0);
+        assert_eq!(history.quarantined_dialogues().await.unwrap().len(), 1);
+        assert_eq!(history.delete_older_than(now() + 1).await.unwrap(), 1);
+        assert!(history
+            .find_by_source(ChatId(1), MessageId(30))
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            history.ab_tally(other_test).await.unwrap(),
+            AbTally::default()
+        );
+        assert_eq!(
+            history
+                .vote_ab_test(other_test, UserId(3), AbChoice::A)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_linked_settings() {
+        let history = HistoryStore::open(None).await.unwrap();
+        assert!(!history.linked_settings(ChatId(1)).await.unwrap());
+        assert!(history.toggle_linked_settings(ChatId(1)).await.unwrap());
+        assert!(history.linked_settings(ChatId(1)).await.unwrap());
+        assert!(!history.linked_settings(ChatId(2)).await.unwrap());
+        history.delete_chat_preferences(ChatId(1)).await.unwrap();
+        assert!(!history.linked_settings(ChatId(1)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_negative_presets() {
+        let history = HistoryStore::open(None).await.unwrap();
+        assert!(history
+            .toggle_negative_preset(ChatId(1), "b")
+            .await
+            .unwrap());
+        assert!(history
+            .toggle_negative_preset(ChatId(1), "a")
+            .await
+            .unwrap());
+        assert!(history
+            .toggle_negative_preset(ChatId(2), "a")
+            .await
+            .unwrap());
+        assert_eq!(
+            history.negative_presets(ChatId(1)).await.unwrap(),
+            vec!["a".to_owned(), "b".to_owned()]
+        );
+
+        assert!(!history
+            .toggle_negative_preset(ChatId(1), "b")
+            .await
+            .unwrap());
+        assert_eq!(
+            history.negative_presets(ChatId(1)).await.unwrap(),
+            vec!["a".to_owned()]
+        );
+
+        history.delete_chat_preferences(ChatId(1)).await.unwrap();
+        assert!(history
+            .negative_presets(ChatId(1))
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            history.negative_presets(ChatId(2)).await.unwrap(),
+            vec!["a".to_owned()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ab_votes() {
+        let history = HistoryStore::open(None).await.unwrap();
+        let id = history
+            .create_ab_test(ChatId(1), Some(UserId(2)), "a cat", "a dog", 42)
+            .await
+            .unwrap();
+        assert_eq!(history.ab_tally(id).await.unwrap(), AbTally::default());
+
+        history
+            .vote_ab_test(id, UserId(2), AbChoice::A)
+            .await
+            .unwrap();
+        history
+            .vote_ab_test(id, UserId(3), AbChoice::A)
+            .await
+            .unwrap();
+        assert_eq!(
+            history
+                .vote_ab_test(id, UserId(3), AbChoice::B)
+                .await
+                .unwrap(),
+            Some(AbTally { a: 1, b: 1 })
+        );
+        assert_eq!(
+            history
+                .vote_ab_test(id + 1, UserId(2), AbChoice::B)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_snippets() {
+        let history = HistoryStore::open(None).await.unwrap();
+        history
+            .set_snippet(UserId(1), "lighting", "god rays")
+            .await
+            .unwrap();
+        history
+            .set_snippet(UserId(1), "lighting", "volumetric light")
+            .await
+            .unwrap();
+        history.set_snippet(UserId(1), "art", "oil").await.unwrap();
+        history.set_snippet(UserId(2), "art", "ink").await.unwrap();
+        assert_eq!(
+            history.snippets(UserId(1)).await.unwrap(),
+            vec![
+                ("art".to_owned(), "oil".to_owned()),
+                ("lighting".to_owned(), "volumetric light".to_owned())
+            ]
+        );
+
+        assert!(history.delete_snippet(UserId(1), "art").await.unwrap());
+        assert!(!history.delete_snippet(UserId(1), "art").await.unwrap());
+        history.delete_snippets(UserId(1)).await.unwrap();
+        assert!(history.snippets(UserId(1)).await.unwrap().is_empty());
+        assert_eq!(history.snippets(UserId(2)).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_chat_variables() {
+        let history = HistoryStore::open(None).await.unwrap();
+        history
+            .set_chat_variable(ChatId(1), "character", "a knight")
+            .await
+            .unwrap();
+        history
+            .set_chat_variable(ChatId(1), "character", "a red-haired knight")
+            .await
+            .unwrap();
+        history
+            .set_chat_variable(ChatId(2), "character", "a wizard")
+            .await
+            .unwrap();
+        assert_eq!(
+            history.chat_variables(ChatId(1)).await.unwrap(),
+            vec![("character".to_owned(), "a red-haired knight".to_owned())]
+        );
+        assert!(history
+            .unset_chat_variable(ChatId(1), "character")
+            .await
+            .unwrap());
+        assert!(!history
+            .unset_chat_variable(ChatId(1), "character")
+            .await
+            .unwrap());
+        assert_eq!(history.chat_variables(ChatId(2)).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_styles() {
+        let history = HistoryStore::open(None).await.unwrap();
+        let style = Style {
+            name: "noir".to_owned(),
+            prompt_prefix: Some("film noir".to_owned()),
+            cfg: Some(6.5),
+            steps: Some(30),
+            ..Default::default()
+        };
+        history.set_style(UserId(1), &style).await.unwrap();
+        assert_eq!(history.styles(UserId(1)).await.unwrap(), vec![style]);
+        assert!(history.styles(UserId(2)).await.unwrap().is_empty());
+        assert!(history.delete_style(UserId(1), "noir").await.unwrap());
+        assert!(!history.delete_style(UserId(1), "noir").await.unwrap());
+
+        history
+            .set_chat_style(ChatId(1), Some("noir"))
+            .await
+            .unwrap();
+        assert_eq!(
+            history.chat_style(ChatId(1)).await.unwrap().as_deref(),
+            Some("noir")
+        );
+        history.delete_chat_preferences(ChatId(1)).await.unwrap();
+        assert_eq!(history.chat_style(ChatId(1)).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_chat_backend() {
+        let history = HistoryStore::open(None).await.unwrap();
+        assert_eq!(history.chat_backend(ChatId(1)).await.unwrap(), None);
+        history
+            .set_chat_backend(ChatId(1), Some("comfy"))
+            .await
+            .unwrap();
+        assert_eq!(
+            history.chat_backend(ChatId(1)).await.unwrap().as_deref(),
+            Some("comfy")
+        );
+        assert_eq!(history.chat_backend(ChatId(2)).await.unwrap(), None);
+        history.delete_chat_preferences(ChatId(1)).await.unwrap();
+        assert_eq!(history.chat_backend(ChatId(1)).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_user_language() {
+        let history = HistoryStore::open(None).await.unwrap();
+        assert_eq!(history.user_language(UserId(1)).await.unwrap(), None);
+        history
+            .set_user_language(UserId(1), Some("de"))
+            .await
+            .unwrap();
+        history
+            .set_user_language(UserId(1), Some("fr"))
+            .await
+            .unwrap();
+        assert_eq!(
+            history.user_language(UserId(1)).await.unwrap().as_deref(),
+            Some("fr")
+        );
+        history.set_user_language(UserId(1), None).await.unwrap();
+        assert_eq!(history.user_language(UserId(1)).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_image_usage() {
+        let history = HistoryStore::open(None).await.unwrap();
+        history.record_usage(UserId(1), 4).await.unwrap();
+        history.record_usage(UserId(1), 2).await.unwrap();
+        history.record_usage(UserId(2), 1).await.unwrap();
+
+        let usage = history.usage_since(UserId(1), 0).await.unwrap();
+        assert_eq!(
+            usage.iter().map(|(_, images)| *images).collect::<Vec<_>>(),
+            vec![4, 2]
+        );
+        assert!(history
+            .usage_since(UserId(1), now())
+            .await
+            .unwrap()
+            .is_empty());
+
+        history.delete_usage_before(now() + 1).await.unwrap();
+        assert!(history.usage_since(UserId(2), 0).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_shared_account_usage() {
+        let accounts = AccountResolver::new(std::collections::BTreeMap::from([(
+            "family".to_owned(),
+            vec![1, 2],
+        )]))
+        .unwrap();
+        let history = HistoryStore::open(None)
+            .await
+            .unwrap()
+            .with_accounts(accounts);
+        let quota = crate::bot::quota::QuotaConfig {
+            images_per_hour: Some(4),
+            ..Default::default()
+        };
+
+        // Each member used half of the quota, which together is all of it.
+        history.record_usage(UserId(1), 2).await.unwrap();
+        history.record_usage(UserId(2), 2).await.unwrap();
+        for user_id in [UserId(1), UserId(2)] {
+            let usage = history.usage_since(user_id, 0).await.unwrap();
+            assert_eq!(usage.len(), 2);
+            assert!(quota.check(&usage, 1, now()).is_err());
+        }
+
+        // Users outside the account have their own quota.
+        let usage = history.usage_since(UserId(3), 0).await.unwrap();
+        assert!(quota.check(&usage, 1, now()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_user_access() {
+        let history = HistoryStore::open(None).await.unwrap();
+        history.set_user_access(UserId(2), true).await.unwrap();
+        history.set_user_access(UserId(1), true).await.unwrap();
+        history.set_user_access(UserId(2), false).await.unwrap();
+        assert_eq!(
+            history.user_access().await.unwrap(),
+            vec![(UserId(1), true), (UserId(2), false)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_quarantine_dialogue() {
+        let history = HistoryStore::open(None).await.unwrap();
+        // Created by the dialogue storage in the same database.
+        sqlx::query(
+            "CREATE TABLE teloxide_dialogues (chat_id BIGINT PRIMARY KEY, dialogue BLOB NOT NULL)",
+        )
+        .execute(&history.pool)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO teloxide_dialogues VALUES (1, ?)")
+            .bind(b"{\"Ready\":".as_slice())
+            .execute(&history.pool)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            history
+                .quarantine_dialogue(ChatId(2), "missing")
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            history.quarantine_dialogue(ChatId(1), "EOF").await.unwrap(),
+            Some(true)
+        );
+        assert_eq!(
+            history.quarantine_dialogue(ChatId(1), "EOF").await.unwrap(),
+            Some(false)
+        );
+
+        let quarantined = history.quarantined_dialogues().await.unwrap();
+        assert_eq!(quarantined.len(), 2);
+        assert_eq!(quarantined[0].chat_id, ChatId(1));
+        assert_eq!(quarantined[0].dialogue, b"{\"Ready\":");
+        assert_eq!(quarantined[0].error, "EOF");
+
+        assert_eq!(
+            history
+                .delete_quarantined_dialogues(Some(quarantined[0].id))
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(history.delete_quarantined_dialogues(None).await.unwrap(), 1);
+        assert!(history.quarantined_dialogues().await.unwrap().is_empty());
+    }
+}
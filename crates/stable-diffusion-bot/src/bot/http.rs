@@ -0,0 +1,238 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Context};
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{HeaderName, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+/// Struct that represents the configuration for the bot's HTTP server.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HttpConfig {
+    /// Address to listen on, e.g. `127.0.0.1:9000`.
+    pub listen_address: SocketAddr,
+    /// Authentication settings for the HTTP endpoints.
+    #[serde(default)]
+    pub auth: HttpAuthConfig,
+    /// Whether to serve a read-only dashboard on `/dashboard`.
+    #[serde(default)]
+    pub dashboard: bool,
+}
+
+/// Struct that represents the authentication settings for the bot's HTTP server.
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct HttpAuthConfig {
+    /// Header set by a trusted reverse proxy containing the authenticated user, e.g.
+    /// `X-Forwarded-User`. Requests without this header are rejected.
+    pub trusted_header: Option<String>,
+    /// Users permitted in the trusted header. If empty, any user is permitted.
+    #[serde(default)]
+    pub allowed_users: Vec<String>,
+    /// IP addresses or CIDR ranges permitted to connect. If empty, any address is permitted.
+    #[serde(default)]
+    pub allowed_ips: Vec<String>,
+}
+
+/// Authentication policy applied to every request to the HTTP server.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HttpAuth {
+    trusted_header: Option<HeaderName>,
+    allowed_users: Vec<String>,
+    allowed_ips: Vec<IpNet>,
+}
+
+impl TryFrom<&HttpAuthConfig> for HttpAuth {
+    type Error = anyhow::Error;
+
+    fn try_from(config: &HttpAuthConfig) -> Result<Self, Self::Error> {
+        let trusted_header = config
+            .trusted_header
+            .as_deref()
+            .map(HeaderName::try_from)
+            .transpose()
+            .context("Invalid trusted header name")?;
+        let allowed_ips = config
+            .allowed_ips
+            .iter()
+            .map(|ip| {
+                ip.parse::<IpNet>()
+                    .or_else(|_| ip.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| anyhow!("Invalid IP address or range: {}", ip))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            trusted_header,
+            allowed_users: config.allowed_users.clone(),
+            allowed_ips,
+        })
+    }
+}
+
+impl HttpAuth {
+    /// Checks whether a request from `addr` carrying the trusted header value `user` is allowed.
+    fn check(&self, addr: IpAddr, user: Option<&str>) -> Result<(), StatusCode> {
+        if !self.allowed_ips.is_empty() && !self.allowed_ips.iter().any(|net| net.contains(&addr)) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+        if self.trusted_header.is_some() {
+            match user.filter(|user| !user.is_empty()) {
+                None => return Err(StatusCode::UNAUTHORIZED),
+                Some(user)
+                    if !self.allowed_users.is_empty()
+                        && !self.allowed_users.iter().any(|allowed| allowed == user) =>
+                {
+                    return Err(StatusCode::FORBIDDEN)
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn auth_middleware<B>(
+    State(auth): State<Arc<HttpAuth>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let user = auth
+        .trusted_header
+        .as_ref()
+        .and_then(|header| request.headers().get(header))
+        .and_then(|value| value.to_str().ok());
+    if let Err(status) = auth.check(addr.ip(), user) {
+        warn!(
+            "Rejected HTTP request for {} from {}: {}",
+            request.uri(),
+            addr,
+            status
+        );
+        return status.into_response();
+    }
+    next.run(request).await
+}
+
+/// HTTP server shared by the bot's HTTP endpoints.
+#[derive(Clone)]
+pub(crate) struct HttpServer {
+    listen_address: SocketAddr,
+    auth: Arc<HttpAuth>,
+    router: Router,
+}
+
+impl HttpServer {
+    /// Constructs a new `HttpServer` from its configuration.
+    pub fn new(config: &HttpConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            listen_address: config.listen_address,
+            auth: Arc::new(HttpAuth::try_from(&config.auth)?),
+            router: Router::new().route("/health", get(|| async { "OK" })),
+        })
+    }
+
+    /// Adds the routes of `router` to the server.
+    pub fn merge(mut self, router: Router) -> Self {
+        self.router = self.router.merge(router);
+        self
+    }
+
+    /// Serves requests until the server fails.
+    pub async fn serve(self) -> anyhow::Result<()> {
+        let router = self
+            .router
+            .layer(middleware::from_fn_with_state(self.auth, auth_middleware));
+        info!("HTTP server listening on {}", self.listen_address);
+        axum::Server::try_bind(&self.listen_address)
+            .context("Failed to bind HTTP server")?
+            .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+            .await
+            .context("HTTP server exited with error")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth(
+        trusted_header: Option<&str>,
+        allowed_users: &[&str],
+        allowed_ips: &[&str],
+    ) -> HttpAuth {
+        HttpAuth::try_from(&HttpAuthConfig {
+            trusted_header: trusted_header.map(str::to_owned),
+            allowed_users: allowed_users.iter().map(|u| u.to_string()).collect(),
+            allowed_ips: allowed_ips.iter().map(|ip| ip.to_string()).collect(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_http_auth_open() {
+        let auth = auth(None, &[], &[]);
+        assert!(auth.check("192.0.2.1".parse().unwrap(), None).is_ok());
+    }
+
+    #[test]
+    fn test_http_auth_allowed_ips() {
+        let auth = auth(None, &[], &["10.0.0.0/8", "::1"]);
+        assert!(auth.check("10.1.2.3".parse().unwrap(), None).is_ok());
+        assert!(auth.check("::1".parse().unwrap(), None).is_ok());
+        assert_eq!(
+            auth.check("192.0.2.1".parse().unwrap(), None),
+            Err(StatusCode::FORBIDDEN)
+        );
+    }
+
+    #[test]
+    fn test_http_auth_trusted_header() {
+        let auth = auth(Some("X-Forwarded-User"), &[], &[]);
+        assert!(auth
+            .check("10.1.2.3".parse().unwrap(), Some("alice"))
+            .is_ok());
+        assert_eq!(
+            auth.check("10.1.2.3".parse().unwrap(), None),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            auth.check("10.1.2.3".parse().unwrap(), Some("")),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+    }
+
+    #[test]
+    fn test_http_auth_trusted_header_allowed_users() {
+        let auth = auth(Some("X-Forwarded-User"), &["alice"], &["127.0.0.1"]);
+        assert!(auth
+            .check("127.0.0.1".parse().unwrap(), Some("alice"))
+            .is_ok());
+        assert_eq!(
+            auth.check("127.0.0.1".parse().unwrap(), Some("mallory")),
+            Err(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(
+            auth.check("10.1.2.3".parse().unwrap(), Some("alice")),
+            Err(StatusCode::FORBIDDEN)
+        );
+    }
+
+    #[test]
+    fn test_http_auth_invalid_ip() {
+        assert!(HttpAuth::try_from(&HttpAuthConfig {
+            allowed_ips: vec!["not an ip".to_owned()],
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
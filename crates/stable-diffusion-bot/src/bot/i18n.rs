@@ -0,0 +1,328 @@
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use anyhow::{bail, Context};
+use teloxide::types::{Update, UserId};
+use tracing::warn;
+
+use super::{config::UiConfig, history::HistoryStore};
+
+/// The language the bot's messages are written in, which needs no translations.
+pub(crate) const SOURCE_LANGUAGE: &str = "en";
+
+/// Translations shipped with the bot, by language.
+const BUILTIN_TRANSLATIONS: &[(&str, &str)] = &[("de", include_str!("i18n/de.toml"))];
+
+/// Translations of the bot's messages into each language. Messages are looked up by their
+/// English text, so messages without a translation are shown in English.
+#[derive(Debug, Clone)]
+pub(crate) struct Translations {
+    default_language: String,
+    bundles: HashMap<String, HashMap<String, String>>,
+}
+
+impl Default for Translations {
+    fn default() -> Self {
+        Self {
+            default_language: SOURCE_LANGUAGE.to_owned(),
+            bundles: HashMap::new(),
+        }
+    }
+}
+
+fn parse_bundle(language: &str, source: &str) -> anyhow::Result<HashMap<String, String>> {
+    toml::from_str(source).with_context(|| format!("Failed to parse translations for {language}"))
+}
+
+impl Translations {
+    /// Loads the built-in translations and those in `dir`, which has a `<language>.toml` file
+    /// per language mapping English messages to their translations. Files in `dir` add to and
+    /// replace the built-in translations.
+    ///
+    /// # Arguments
+    ///
+    /// * `default_language` - The language used for users who haven't chosen one. English if
+    ///   `None`.
+    /// * `dir` - An optional directory with more translations.
+    pub fn load(default_language: Option<&str>, dir: Option<&Path>) -> anyhow::Result<Self> {
+        let mut bundles = HashMap::new();
+        for (language, source) in BUILTIN_TRANSLATIONS {
+            bundles.insert(language.to_string(), parse_bundle(language, source)?);
+        }
+        if let Some(dir) = dir {
+            let entries = std::fs::read_dir(dir)
+                .with_context(|| format!("Failed to read translations from {}", dir.display()))?;
+            for entry in entries {
+                let path = entry?.path();
+                if path.extension().and_then(|ext| ext.to_str()) != Some("toml") {
+                    continue;
+                }
+                let Some(language) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                    continue;
+                };
+                let source = std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                bundles
+                    .entry(language.to_owned())
+                    .or_insert_with(HashMap::new)
+                    .extend(parse_bundle(language, &source)?);
+            }
+        }
+
+        let translations = Self {
+            default_language: default_language.unwrap_or(SOURCE_LANGUAGE).to_owned(),
+            bundles,
+        };
+        if !translations.has_language(&translations.default_language) {
+            bail!(
+                "No translations for language {}, available languages are {}",
+                translations.default_language,
+                translations.languages().join(", ")
+            );
+        }
+        Ok(translations)
+    }
+
+    /// Returns the languages messages can be shown in, sorted.
+    pub fn languages(&self) -> Vec<&str> {
+        let mut languages: Vec<_> = self
+            .bundles
+            .keys()
+            .map(String::as_str)
+            .chain([SOURCE_LANGUAGE])
+            .collect();
+        languages.sort_unstable();
+        languages.dedup();
+        languages
+    }
+
+    pub fn has_language(&self, language: &str) -> bool {
+        language == SOURCE_LANGUAGE || self.bundles.contains_key(language)
+    }
+
+    /// Returns the language used for users who haven't chosen one.
+    pub fn default_language(&self) -> &str {
+        &self.default_language
+    }
+
+    fn lookup<'a>(&'a self, language: &str, text: &'a str) -> &'a str {
+        self.bundles
+            .get(language)
+            .and_then(|bundle| bundle.get(text))
+            .map_or(text, String::as_str)
+    }
+}
+
+/// Translates messages into the language of the user an update is from.
+#[derive(Debug, Clone)]
+pub(crate) struct Translator {
+    translations: Arc<Translations>,
+    language: String,
+}
+
+impl Default for Translator {
+    fn default() -> Self {
+        Self::new(Arc::default(), None)
+    }
+}
+
+impl Translator {
+    /// Creates a translator into `language`, or the default language if it's `None` or has no
+    /// translations.
+    pub fn new(translations: Arc<Translations>, language: Option<String>) -> Self {
+        let language = language
+            .filter(|language| translations.has_language(language))
+            .unwrap_or_else(|| translations.default_language().to_owned());
+        Self {
+            translations,
+            language,
+        }
+    }
+
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// Returns the translation of `text`.
+    pub fn text(&self, text: &str) -> String {
+        self.translations.lookup(&self.language, text).to_owned()
+    }
+
+    /// Returns the translation of `text` with each `{name}` replaced by its value in `args`.
+    pub fn format(&self, text: &str, args: &[(&str, &str)]) -> String {
+        args.iter().fold(self.text(text), |text, (name, value)| {
+            text.replace(&format!("{{{name}}}"), value)
+        })
+    }
+
+    /// Returns `descriptions`, as listed by `BotCommands::descriptions`, with the description
+    /// of each command and the headings translated.
+    pub fn descriptions(&self, descriptions: &str) -> String {
+        descriptions
+            .lines()
+            .map(|line| match line.split_once(" — ") {
+                Some((command, description)) => {
+                    format!("{command} — {}", self.text(description))
+                }
+                None if line.is_empty() => String::new(),
+                None => self.text(line),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Returns a translator into the language `user_id` chose, or the default language.
+pub(crate) async fn translator_for(
+    ui: &UiConfig,
+    history: &HistoryStore,
+    user_id: Option<UserId>,
+) -> Translator {
+    let language = match user_id {
+        Some(user_id) => history.user_language(user_id).await.unwrap_or_else(|e| {
+            warn!("Failed to get user language: {:?}", e);
+            None
+        }),
+        None => None,
+    };
+    Translator::new(ui.translations.clone(), language)
+}
+
+/// Returns a translator into the language the user `upd` is from chose, or the default
+/// language.
+pub(crate) async fn resolve_translator(
+    upd: Update,
+    ui: Arc<UiConfig>,
+    history: HistoryStore,
+) -> Translator {
+    translator_for(&ui, &history, upd.user().map(|user| user.id)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translator() {
+        let translations = Arc::new(Translations::load(None, None).unwrap());
+        assert!(translations.languages().contains(&"de"));
+
+        let german = Translator::new(translations.clone(), Some("de".to_owned()));
+        assert_eq!(
+            german.text("A prompt is required."),
+            "Ein Prompt ist erforderlich."
+        );
+        assert_eq!(german.text("Not translated."), "Not translated.");
+        assert_eq!(
+            german.format("Seed set to {seed}.", &[("seed", "42")]),
+            "Seed auf 42 gesetzt."
+        );
+
+        let fallback = Translator::new(translations, Some("xx".to_owned()));
+        assert_eq!(fallback.language(), SOURCE_LANGUAGE);
+        assert_eq!(
+            fallback.descriptions("Simple commands\n\n/help — show help message."),
+            "Simple commands\n\n/help — show help message."
+        );
+    }
+
+    #[test]
+    fn test_unknown_default_language() {
+        assert!(Translations::load(Some("xx"), None).is_err());
+    }
+
+    /// Returns the value of a Rust string literal's contents.
+    fn unescape(literal: &str) -> String {
+        let mut text = String::new();
+        let mut chars = literal.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                text.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => text.push('\n'),
+                Some('t') => text.push('\t'),
+                Some('u') => {
+                    let code: String = chars.by_ref().skip(1).take_while(|&c| c != '}').collect();
+                    text.extend(u32::from_str_radix(&code, 16).ok().and_then(char::from_u32));
+                }
+                // A line continuation skips the line break and the next line's indentation.
+                Some('\n') => while chars.next_if(|c| c.is_whitespace()).is_some() {},
+                Some(c) => text.push(c),
+                None => {}
+            }
+        }
+        text
+    }
+
+    fn source_files(dir: &Path, files: &mut Vec<std::path::PathBuf>) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                source_files(&path, files);
+            } else if path.extension().is_some_and(|ext| ext == "rs") {
+                files.push(path);
+            }
+        }
+    }
+
+    #[test]
+    fn test_translations_are_complete() {
+        let translations = Translations::load(None, None).unwrap();
+        let german = &translations.bundles["de"];
+        // Messages passed to the translator directly, or through the `label`, `line` and
+        // `button` helpers that build settings keyboards and summaries.
+        let call = regex::Regex::new(
+            r#"(?:translator\s*\.(?:text|format)|\blabel|\bline|\bbutton)\(\s*"((?:[^"\\]|\\(?s:.))*)""#,
+        )
+        .unwrap();
+        // Messages chosen by a condition, like `text(if enabled { ... } else { ... })`.
+        let conditional = regex::Regex::new(
+            r#"translator\s*\.text\(\s*if\b[^{]*\{\s*"((?:[^"\\]|\\.)*)"\s*\}\s*else\s*\{\s*"((?:[^"\\]|\\.)*)"\s*\}"#,
+        )
+        .unwrap();
+        // Messages kept in constants, like the usage texts.
+        let constant_call =
+            regex::Regex::new(r"translator\s*\.(?:text|format)\(\s*([A-Z][A-Z0-9_]*)\b").unwrap();
+        let mut files = Vec::new();
+        source_files(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("src"),
+            &mut files,
+        );
+        let mut missing = Vec::new();
+        for path in files {
+            let source = std::fs::read_to_string(&path).unwrap();
+            let constants = constant_call.captures_iter(&source).map(|captures| {
+                let name = &captures[1];
+                let definition = regex::Regex::new(&format!(
+                    r#"const {name}: &str =\s*"((?:[^"\\]|\\(?s:.))*)";"#
+                ))
+                .unwrap();
+                let captures = definition
+                    .captures(&source)
+                    .unwrap_or_else(|| panic!("{}: no constant {name}", path.display()));
+                unescape(&captures[1])
+            });
+            let keys = call
+                .captures_iter(&source)
+                .map(|captures| unescape(&captures[1]))
+                .chain(
+                    conditional
+                        .captures_iter(&source)
+                        .flat_map(|captures| [unescape(&captures[1]), unescape(&captures[2])]),
+                )
+                .chain(constants)
+                .collect::<Vec<_>>();
+            for key in keys {
+                if !german.contains_key(&key) {
+                    missing.push(format!("{}: {key:?}", path.display()));
+                }
+            }
+        }
+        assert!(
+            missing.is_empty(),
+            "Messages without German translations:\n{}",
+            missing.join("\n")
+        );
+    }
+}
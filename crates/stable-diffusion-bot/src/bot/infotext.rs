@@ -0,0 +1,247 @@
+use std::collections::BTreeMap;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use sal_e_api::{GenParams, ImageParams};
+
+lazy_static! {
+    // Matches `Key: value` pairs in the parameters line, where values may be quoted to contain
+    // commas, e.g. `Lora hashes: "a: 1, b: 2"`.
+    static ref PARAM_RE: Regex =
+        Regex::new(r#"\s*(\w[\w \-/]*):\s*("(?:\\.|[^\\"])*"|[^,]*)(?:,|$)"#).unwrap();
+}
+
+const NEGATIVE_PROMPT: &str = "Negative prompt:";
+
+/// Generation settings parsed from a Stable Diffusion WebUI infotext block, e.g.:
+///
+/// ```text
+/// a cat
+/// Negative prompt: blurry
+/// Steps: 20, Sampler: Euler a, CFG scale: 7, Seed: 1234, Size: 512x768
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Infotext {
+    pub prompt: String,
+    pub negative_prompt: Option<String>,
+    pub params: BTreeMap<String, String>,
+}
+
+impl Infotext {
+    /// Parses an infotext block, returning `None` if `text` doesn't look like one.
+    ///
+    /// The last line must be a parameters line starting with `Steps:`; everything before the
+    /// optional `Negative prompt:` line is the prompt.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let (body, params_line) = match text.rsplit_once('\n') {
+            Some((body, params_line)) => (body, params_line.trim()),
+            None => ("", text),
+        };
+        if !params_line.starts_with("Steps:") {
+            return None;
+        }
+
+        let params = PARAM_RE
+            .captures_iter(params_line)
+            .map(|c| {
+                let value = c[2].trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .unwrap_or(value);
+                (c[1].trim().to_owned(), value.to_owned())
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let (prompt, negative_prompt) = match body.find(NEGATIVE_PROMPT) {
+            Some(index) if index == 0 || body[..index].ends_with('\n') => (
+                &body[..index],
+                Some(body[index + NEGATIVE_PROMPT.len()..].trim().to_owned()),
+            ),
+            _ => (body, None),
+        };
+        let prompt = prompt.trim().to_owned();
+        if prompt.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            prompt,
+            negative_prompt,
+            params,
+        })
+    }
+
+    /// Applies the prompt and recognized settings to `params`. Unknown or malformed settings
+    /// are ignored.
+    pub fn apply_to(&self, params: &mut dyn GenParams) {
+        params.set_prompt(self.prompt.clone());
+        if let Some(negative_prompt) = &self.negative_prompt {
+            params.set_negative_prompt(negative_prompt.clone());
+        }
+        if let Some(steps) = self.param("Steps") {
+            params.set_steps(steps);
+        }
+        if let Some(sampler) = self.params.get("Sampler") {
+            params.set_sampler(sampler.clone());
+        }
+        if let Some(cfg) = self.param("CFG scale") {
+            params.set_cfg(cfg);
+        }
+        if let Some(seed) = self.param("Seed") {
+            params.set_seed(seed);
+        }
+        if let Some((width, height)) = self.size() {
+            params.set_width(width);
+            params.set_height(height);
+        }
+        if let Some(denoising) = self.param("Denoising strength") {
+            params.set_denoising(denoising);
+        }
+    }
+
+    /// Builds an infotext block for an image generated from `prompt` with the settings in
+    /// `image_params`, which can be sent back to the bot to generate the image again. `size`
+    /// is the size of the image that was actually generated, if known.
+    pub fn from_image_params(
+        prompt: &str,
+        image_params: &dyn ImageParams,
+        size: Option<(u32, u32)>,
+    ) -> Self {
+        let size = size.or_else(|| image_params.width().zip(image_params.height()));
+        let params = [
+            ("Steps", image_params.steps().map(|steps| steps.to_string())),
+            ("Sampler", image_params.sampler()),
+            ("CFG scale", image_params.cfg().map(|cfg| cfg.to_string())),
+            ("Seed", image_params.seed().map(|seed| seed.to_string())),
+            (
+                "Size",
+                size.map(|(width, height)| format!("{width}x{height}")),
+            ),
+            ("Model", image_params.model()),
+            ("VAE", image_params.vae()),
+            (
+                "Denoising strength",
+                image_params
+                    .denoising()
+                    .map(|denoising| denoising.to_string()),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key.to_owned(), value?)))
+        .collect();
+        Self {
+            prompt: prompt.to_owned(),
+            negative_prompt: image_params
+                .negative_prompt()
+                .filter(|negative_prompt| !negative_prompt.trim().is_empty()),
+            params,
+        }
+    }
+
+    fn param<T: std::str::FromStr>(&self, key: &str) -> Option<T> {
+        self.params.get(key).and_then(|value| value.parse().ok())
+    }
+
+    fn size(&self) -> Option<(u32, u32)> {
+        let (width, height) = self.params.get("Size")?.split_once('x')?;
+        Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+    }
+}
+
+impl std::fmt::Display for Infotext {
+    /// Writes the infotext block in the format [`Infotext::parse`] reads, with `Steps` first.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.prompt)?;
+        if let Some(negative_prompt) = &self.negative_prompt {
+            writeln!(f, "{NEGATIVE_PROMPT} {negative_prompt}")?;
+        }
+        let params = self
+            .params
+            .get_key_value("Steps")
+            .into_iter()
+            .chain(self.params.iter().filter(|(key, _)| *key != "Steps"))
+            .map(|(key, value)| {
+                if value.contains(',') {
+                    format!("{key}: \"{value}\"")
+                } else {
+                    format!("{key}: {value}")
+                }
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", params.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sal_e_api::ComfyParams;
+    use stable_diffusion_api::ImgInfo;
+
+    const INFOTEXT: &str = "a cat,\nwearing a hat\nNegative prompt: blurry, lowres\nSteps: 20, Sampler: DPM++ 2M Karras, CFG scale: 7.5, Seed: 1234, Size: 512x768, Model hash: abc123, Lora hashes: \"a: 1, b: 2\", Denoising strength: 0.4";
+
+    #[test]
+    fn test_parse_infotext() {
+        let infotext = Infotext::parse(INFOTEXT).unwrap();
+        assert_eq!(infotext.prompt, "a cat,\nwearing a hat");
+        assert_eq!(infotext.negative_prompt.as_deref(), Some("blurry, lowres"));
+        assert_eq!(infotext.params["Sampler"], "DPM++ 2M Karras");
+        assert_eq!(infotext.params["Model hash"], "abc123");
+        assert_eq!(infotext.params["Lora hashes"], "a: 1, b: 2");
+        assert_eq!(infotext.params["Denoising strength"], "0.4");
+        assert_eq!(infotext.size(), Some((512, 768)));
+    }
+
+    #[test]
+    fn test_parse_infotext_without_negative_prompt() {
+        let infotext = Infotext::parse("a dog\nSteps: 30, Seed: 5").unwrap();
+        assert_eq!(infotext.prompt, "a dog");
+        assert_eq!(infotext.negative_prompt, None);
+        assert_eq!(infotext.param::<u32>("Steps"), Some(30));
+    }
+
+    #[test]
+    fn test_display_infotext() {
+        let infotext = Infotext::parse(INFOTEXT).unwrap();
+        assert_eq!(Infotext::parse(&infotext.to_string()), Some(infotext));
+
+        let params = ImgInfo {
+            prompt: Some("a cat".to_owned()),
+            steps: Some(20),
+            seed: Some(5),
+            ..Default::default()
+        };
+        let infotext = Infotext::from_image_params("a cat", &params, Some((512, 768)));
+        assert_eq!(
+            infotext.to_string(),
+            "a cat\nSteps: 20, Seed: 5, Size: 512x768"
+        );
+    }
+
+    #[test]
+    fn test_parse_not_infotext() {
+        assert_eq!(Infotext::parse("a cat wearing a hat"), None);
+        assert_eq!(Infotext::parse("a cat\nwearing a hat"), None);
+        assert_eq!(Infotext::parse("Steps: 20, Seed: 5"), None);
+    }
+
+    #[test]
+    fn test_apply_infotext() {
+        let mut params = ComfyParams::default();
+        Infotext::parse(INFOTEXT).unwrap().apply_to(&mut params);
+        assert_eq!(params.prompt_text.as_deref(), Some("a cat,\nwearing a hat"));
+        assert_eq!(
+            params.negative_prompt_text.as_deref(),
+            Some("blurry, lowres")
+        );
+        assert_eq!(params.steps, Some(20));
+        assert_eq!(params.sampler.as_deref(), Some("DPM++ 2M Karras"));
+        assert_eq!(params.cfg, Some(7.5));
+        assert_eq!(params.seed, Some(1234));
+        assert_eq!(params.width, Some(512));
+        assert_eq!(params.height, Some(768));
+        assert_eq!(params.denoising, Some(0.4));
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use teloxide::types::{ChatId, UserId};
+use tokio::time::Instant;
+
+/// Struct that represents the configuration for generating images from inline queries, e.g.
+/// `@your_bot a cat in space` typed in any chat.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InlineConfig {
+    /// Chat that generated images are posted to before they're offered as results, since
+    /// inline results can only contain images that Telegram already has. A private channel
+    /// that the bot can post in works well.
+    pub cache_chat_id: i64,
+    /// Minimum time between inline generations for each user, in seconds. Telegram sends a
+    /// query for every change to the text, so this also stops partial prompts from queueing
+    /// many generations.
+    #[serde(default = "default_min_interval_secs")]
+    pub min_interval_secs: u64,
+}
+
+fn default_min_interval_secs() -> u64 {
+    30
+}
+
+/// Images generated for an inline query, kept to answer the same request again.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CachedResults {
+    /// Telegram file ids of the images posted to the cache chat.
+    pub file_ids: Vec<String>,
+    pub caption: String,
+}
+
+/// Generates images for inline queries, limiting how often each user can do so.
+#[derive(Debug, Clone)]
+pub(crate) struct InlineGenerator {
+    config: InlineConfig,
+    last_generation: Arc<Mutex<HashMap<UserId, Instant>>>,
+    /// Results by the fingerprint of the request they were generated for, with when they were
+    /// generated. See `GenParams::fingerprint`.
+    results: Arc<Mutex<HashMap<String, (Instant, CachedResults)>>>,
+}
+
+impl InlineGenerator {
+    pub fn new(config: InlineConfig) -> Self {
+        Self {
+            config,
+            last_generation: Default::default(),
+            results: Default::default(),
+        }
+    }
+
+    /// Returns the chat that generated images are posted to.
+    pub fn cache_chat_id(&self) -> ChatId {
+        ChatId(self.config.cache_chat_id)
+    }
+
+    /// Returns the results generated for the request with `fingerprint` within `max_age` of
+    /// `now`, by any user.
+    pub fn cached(
+        &self,
+        fingerprint: &str,
+        now: Instant,
+        max_age: Duration,
+    ) -> Option<CachedResults> {
+        let mut results = self.results.lock().unwrap();
+        results.retain(|_, (generated, _)| now.duration_since(*generated) < max_age);
+        results.get(fingerprint).map(|(_, cached)| cached.clone())
+    }
+
+    /// Keeps the results generated at `now` for the request with `fingerprint`.
+    pub fn cache(&self, fingerprint: String, now: Instant, results: CachedResults) {
+        self.results
+            .lock()
+            .unwrap()
+            .insert(fingerprint, (now, results));
+    }
+
+    /// Records a generation for `user` at `now` if they haven't generated an image within the
+    /// minimum interval. Otherwise returns how long they must wait.
+    pub fn try_start(&self, user: UserId, now: Instant) -> Result<(), Duration> {
+        let interval = Duration::from_secs(self.config.min_interval_secs);
+        let mut last_generation = self.last_generation.lock().unwrap();
+        if let Some(elapsed) = last_generation
+            .get(&user)
+            .map(|last| now.duration_since(*last))
+            .filter(|elapsed| *elapsed < interval)
+        {
+            return Err(interval - elapsed);
+        }
+        last_generation.retain(|_, last| now.duration_since(*last) < interval);
+        last_generation.insert(user, now);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_start() {
+        let generator = InlineGenerator::new(InlineConfig {
+            cache_chat_id: -100,
+            min_interval_secs: 30,
+        });
+        let (alice, bob) = (UserId(1), UserId(2));
+        let start = Instant::now();
+        assert_eq!(generator.try_start(alice, start), Ok(()));
+        assert_eq!(
+            generator.try_start(alice, start + Duration::from_secs(10)),
+            Err(Duration::from_secs(20))
+        );
+        assert_eq!(
+            generator.try_start(bob, start + Duration::from_secs(10)),
+            Ok(())
+        );
+        assert_eq!(
+            generator.try_start(alice, start + Duration::from_secs(30)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_cache() {
+        let generator = InlineGenerator::new(InlineConfig {
+            cache_chat_id: -100,
+            min_interval_secs: 30,
+        });
+        let start = Instant::now();
+        let max_age = Duration::from_secs(300);
+        let results = CachedResults {
+            file_ids: vec!["file".to_owned()],
+            caption: "a cat".to_owned(),
+        };
+        assert_eq!(generator.cached("abc", start, max_age), None);
+        generator.cache("abc".to_owned(), start, results.clone());
+        assert_eq!(
+            generator.cached("abc", start + Duration::from_secs(299), max_age),
+            Some(results)
+        );
+        assert_eq!(generator.cached("def", start, max_age), None);
+        assert_eq!(
+            generator.cached("abc", start + Duration::from_secs(300), max_age),
+            None
+        );
+    }
+}
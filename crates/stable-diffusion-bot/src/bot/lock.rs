@@ -0,0 +1,82 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use fs4::FileExt;
+use tracing::warn;
+
+/// Advisory lock preventing several bot instances from sharing one database.
+///
+/// The lock is held on a `<db_path>.lock` file for as long as this value is alive, and is
+/// released by the operating system when the process exits, so a crashed instance never leaves
+/// a stale lock behind. The lock file itself is never removed, since unlinking it could let two
+/// instances lock different files for the same database.
+#[derive(Debug)]
+pub(crate) struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    /// Acquires the lock for the database at `db_path`.
+    ///
+    /// # Arguments
+    ///
+    /// * `db_path` - Path to the storage database.
+    /// * `force` - Start even if another instance holds the lock.
+    ///
+    /// # Returns
+    ///
+    /// The held lock, `None` if the lock is held elsewhere and `force` is set, or an error if
+    /// another instance is running.
+    pub fn acquire<P: AsRef<Path>>(db_path: P, force: bool) -> anyhow::Result<Option<Self>> {
+        let mut path = db_path.as_ref().as_os_str().to_owned();
+        path.push(".lock");
+        let path = PathBuf::from(path);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open lock file {}", path.display()))?;
+        if file.try_lock_exclusive().is_err() {
+            if force {
+                warn!(
+                    "Another instance holds {}, starting anyway because of --force",
+                    path.display()
+                );
+                return Ok(None);
+            }
+            return Err(anyhow!(
+                "Another bot instance is already using the database {}. Running two instances \
+                 against the same database corrupts dialogue state. Stop the other instance, or \
+                 pass --force if you are sure it is not running.",
+                db_path.as_ref().display()
+            ));
+        }
+        file.set_len(0)?;
+        writeln!(file, "{}", std::process::id())?;
+        Ok(Some(Self { _file: file }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instance_lock() {
+        let db_path =
+            std::env::temp_dir().join(format!("sd-bot-lock-{}.sqlite", std::process::id()));
+
+        let lock = InstanceLock::acquire(&db_path, false).unwrap();
+        assert!(lock.is_some());
+        assert!(InstanceLock::acquire(&db_path, false).is_err());
+        assert!(InstanceLock::acquire(&db_path, true).unwrap().is_none());
+
+        drop(lock);
+        assert!(InstanceLock::acquire(&db_path, false).unwrap().is_some());
+    }
+}
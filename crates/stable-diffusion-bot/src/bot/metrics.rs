@@ -0,0 +1,136 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use axum::{routing::get, Router};
+
+use super::progress::Timings;
+
+/// Counters exported on the HTTP server's `/metrics` endpoint.
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    /// Number of times the dispatcher was restarted after a panic.
+    pub dispatcher_restarts: AtomicU64,
+    /// Number of dialogue states that couldn't be loaded and were quarantined.
+    pub dialogues_quarantined: AtomicU64,
+    /// Number of requests that weren't generated, since the same request was already in flight.
+    pub duplicate_requests: AtomicU64,
+    /// Number of inline queries answered with images generated for the same request earlier.
+    pub inline_cache_hits: AtomicU64,
+    /// Time successful generations waited for the backend to start.
+    pub queue_wait: Timer,
+    /// Time the backend spent on successful generations.
+    pub generation: Timer,
+    /// Time spent uploading the images of successful generations to Telegram.
+    pub upload: Timer,
+}
+
+/// The total and count of timed events, exported as a Prometheus summary.
+#[derive(Debug, Default)]
+pub(crate) struct Timer {
+    count: AtomicU64,
+    micros: AtomicU64,
+}
+
+impl Timer {
+    /// Records an event that took `duration`.
+    pub fn record(&self, duration: Duration) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.micros.fetch_add(
+            duration.as_micros().try_into().unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Renders the timer as a summary called `name`.
+    fn render(&self, name: &str, help: &str) -> String {
+        format!(
+            "# HELP {name} {help}\n\
+             # TYPE {name} summary\n\
+             {name}_sum {}\n\
+             {name}_count {}\n",
+            self.micros.load(Ordering::Relaxed) as f64 / 1_000_000.0,
+            self.count.load(Ordering::Relaxed)
+        )
+    }
+}
+
+impl Metrics {
+    /// Records how long the phases of a successful generation took.
+    pub fn record_timings(&self, timings: &Timings) {
+        if let Some(queue) = timings.queue {
+            self.queue_wait.record(queue);
+        }
+        self.generation.record(timings.generation);
+        self.upload.record(timings.upload);
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        format!(
+            "# HELP sd_bot_dispatcher_restarts_total Number of dispatcher restarts after a panic.\n\
+             # TYPE sd_bot_dispatcher_restarts_total counter\n\
+             sd_bot_dispatcher_restarts_total {}\n\
+             # HELP sd_bot_dialogues_quarantined_total Number of unreadable dialogue states moved to quarantine.\n\
+             # TYPE sd_bot_dialogues_quarantined_total counter\n\
+             sd_bot_dialogues_quarantined_total {}\n\
+             # HELP sd_bot_duplicate_requests_total Number of requests skipped while the same request was generating.\n\
+             # TYPE sd_bot_duplicate_requests_total counter\n\
+             sd_bot_duplicate_requests_total {}\n\
+             # HELP sd_bot_inline_cache_hits_total Number of inline queries answered from the result cache.\n\
+             # TYPE sd_bot_inline_cache_hits_total counter\n\
+             sd_bot_inline_cache_hits_total {}\n{}{}{}",
+            self.dispatcher_restarts.load(Ordering::Relaxed),
+            self.dialogues_quarantined.load(Ordering::Relaxed),
+            self.duplicate_requests.load(Ordering::Relaxed),
+            self.inline_cache_hits.load(Ordering::Relaxed),
+            self.queue_wait.render(
+                "sd_bot_queue_wait_seconds",
+                "Time generations waited until the backend reported progress."
+            ),
+            self.generation.render(
+                "sd_bot_generation_seconds",
+                "Time the backend spent generating images."
+            ),
+            self.upload.render(
+                "sd_bot_upload_seconds",
+                "Time spent sending generated images to Telegram."
+            )
+        )
+    }
+}
+
+/// Returns a router serving `metrics` on `/metrics`.
+pub(crate) fn router(metrics: Arc<Metrics>) -> Router {
+    Router::new().route(
+        "/metrics",
+        get(move || {
+            let metrics = metrics.clone();
+            async move { metrics.render() }
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_timings() {
+        let metrics = Metrics::default();
+        metrics.record_timings(&Timings {
+            queue: None,
+            generation: Duration::from_millis(2_500),
+            upload: Duration::from_millis(500),
+        });
+        let rendered = metrics.render();
+        assert!(rendered.contains("sd_bot_queue_wait_seconds_count 0\n"));
+        assert!(rendered.contains("sd_bot_generation_seconds_sum 2.5\n"));
+        assert!(rendered.contains("sd_bot_upload_seconds_count 1\n"));
+        assert!(rendered.contains("sd_bot_duplicate_requests_total 0\n"));
+    }
+}
@@ -1,29 +1,182 @@
-use std::{collections::HashSet, path::PathBuf, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
 
 use anyhow::{anyhow, Context};
-use comfyui_api::comfy::getter::{LoadImageExt, PromptExt, SeedExt};
-use sal_e_api::{ComfyPromptApi, GenParams, Img2ImgApi, StableDiffusionWebUiApi, Txt2ImgApi};
+use comfyui_api::comfy::{
+    getter::{LoadImageExt, PromptExt, SeedExt},
+    Comfy,
+};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use sal_e_api::{
+    ComfyPromptApi, GenParams, Img2ImgApi, SeedMode, StableDiffusionWebUiApi, Txt2ImgApi,
+};
 use serde::{Deserialize, Serialize};
 use teloxide::{
     dispatching::{
-        dialogue::{
-            serializer::Json, ErasedStorage, GetChatId, InMemStorage, SqliteStorage, Storage,
-        },
+        dialogue::{ErasedStorage, GetChatId, InMemStorage, Storage},
         DpHandlerDescription, UpdateHandler,
     },
     prelude::*,
-    types::Update,
+    types::{BotCommand, MessageId, PhotoSize, Update},
     utils::command::BotCommands,
 };
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
-use tracing::{error, warn};
+use tracing::{error, info, warn};
+
+use stable_diffusion_api::{Api, Auth, Img2ImgRequest, Txt2ImgRequest};
+
+mod ab;
+
+mod accounts;
+use accounts::AccountResolver;
+
+mod archive;
+pub use archive::ZipConfig;
+
+mod backend_wait;
+pub use backend_wait::WaitForBackendConfig;
+
+mod backends;
+pub use backends::BackendConfig;
+use backends::{BackendRegistry, DEFAULT_BACKEND};
+
+mod quiet_hours;
+pub use quiet_hours::{ChatQuietHours, QuietHoursConfig, QuietHoursMode};
+
+mod prompt_cleaning;
+pub use prompt_cleaning::PromptCleaningConfig;
+
+mod prompt_flags;
+
+mod quota;
+pub use quota::QuotaConfig;
+
+mod random;
+pub use random::RandomConfig;
 
-use stable_diffusion_api::{Api, Img2ImgRequest, Txt2ImgRequest};
+mod reply_keyboard;
+pub use reply_keyboard::{ReplyButton, ReplyKeyboardConfig};
 
+mod request;
+pub use request::RequestConfig;
+
+mod blank;
+pub use blank::BlankCheckConfig;
+
+mod censor;
+
+mod captions;
+pub use captions::CaptionMode;
+
+mod caption_template;
+use caption_template::CaptionField;
+pub use caption_template::CaptionTemplate;
+
+mod group_mode;
+pub use group_mode::GroupMode;
+
+mod defaults;
+
+mod denoise_presets;
+
+mod dialogue_codec;
+use defaults::ChatDefaults;
+pub use defaults::{DefaultSettings, SettingsOverrides};
+use dialogue_codec::DialogueCodec;
+
+mod dialogue_storage;
+use dialogue_storage::SqliteStorage;
+
+mod build_info;
+
+mod config;
+use config::{AuthConfig, BackendHandles, ConfigParameters, UiConfig};
+
+mod guest;
 mod handlers;
+pub use guest::GuestConfig;
+use guest::GuestMode;
+
 mod helpers;
+mod history;
+mod i18n;
+use i18n::Translations;
+mod infotext;
+
+mod inline;
 use handlers::*;
+use history::HistoryStore;
+pub use inline::InlineConfig;
+use inline::InlineGenerator;
+
+mod http;
+use http::HttpServer;
+pub use http::{HttpAuthConfig, HttpConfig};
+
+mod lock;
+use lock::InstanceLock;
+
+mod dashboard;
+
+mod metrics;
+use metrics::Metrics;
+
+mod policy;
+pub use policy::{BannedWord, PromptPolicy};
+
+mod pool;
+pub use pool::PoolConfig;
+
+mod presets;
+pub use presets::NegativePreset;
+
+mod progress;
+pub use progress::ProgressConfig;
+
+mod retention;
+pub use retention::RetentionConfig;
+
+mod send;
+
+mod settings_store;
+
+mod size_presets;
+
+mod snippets;
+
+mod styles;
+pub use styles::Style;
+
+mod supervisor;
+pub use supervisor::SupervisorConfig;
+
+mod telegram_webhook;
+use telegram_webhook::TelegramWebhook;
+pub use telegram_webhook::TelegramWebhookConfig;
+
+mod tls;
+pub use tls::TlsBackend;
+
+mod unknown_commands;
+pub use unknown_commands::UnknownCommandMode;
+
+mod upscale;
+use upscale::ImageUpscaler;
+pub use upscale::UpscaleConfig;
+
+mod variables;
+
+mod vision;
+use vision::ImageDescriber;
+pub use vision::VisionConfig;
+
+mod webhook;
+pub use webhook::WebhookConfig;
+use webhook::Webhooks;
 
 #[derive(Clone, Serialize, Deserialize, Debug, Default)]
 pub(crate) enum State {
@@ -31,7 +184,10 @@ pub(crate) enum State {
     New,
     Ready {
         bot_state: BotState,
+        // Parameters of unknown types are reset to the defaults when the dialogue is loaded.
+        #[serde(deserialize_with = "sal_e_api::deserialize_gen_params")]
         txt2img: Box<dyn GenParams>,
+        #[serde(deserialize_with = "sal_e_api::deserialize_gen_params")]
         img2img: Box<dyn GenParams>,
     },
 }
@@ -56,6 +212,18 @@ pub(crate) enum BotState {
     SettingsImg2Img {
         selection: Option<String>,
     },
+    /// Waiting for the prompt to inpaint `image` with, in the white area of `mask`.
+    Inpaint {
+        image: Vec<PhotoSize>,
+        mask: Vec<PhotoSize>,
+    },
+    /// Waiting for the denoising strength to generate from `photo` with, which was sent in the
+    /// message `source`.
+    Img2ImgPending {
+        photo: Vec<PhotoSize>,
+        prompt: String,
+        source: MessageId,
+    },
 }
 
 fn default_txt2img(txt2img: Txt2ImgRequest) -> Txt2ImgRequest {
@@ -100,20 +268,108 @@ type DiffusionDialogue = Dialogue<State, ErasedStorage<State>>;
 pub struct StableDiffusionBot {
     bot: Bot,
     storage: DialogueStorage,
+    history: HistoryStore,
     config: ConfigParameters,
+    http: Option<HttpServer>,
+    telegram_webhook: Option<TelegramWebhook>,
+    metrics: Arc<Metrics>,
+    supervisor_config: SupervisorConfig,
+    retention_config: Option<RetentionConfig>,
+    wait_for_backend_config: Option<WaitForBackendConfig>,
+    _lock: Option<Arc<InstanceLock>>,
+}
+
+/// Returns the key that orders the handling of `update`. Updates from the same chat are handled
+/// one at a time, except requests to cancel a generation, which would otherwise wait for the
+/// generation to finish.
+fn distribution_key(update: &Update) -> Option<ChatId> {
+    if is_cancel_request(update) {
+        return None;
+    }
+    update.chat().map(|chat| chat.id)
+}
+
+/// Moves the stored dialogue state of `chat_id`, which couldn't be loaded because of `error`, to
+/// the quarantine, so admins can inspect it. The chat is told the first time its settings are
+/// lost.
+async fn quarantine_dialogue(
+    bot: &Bot,
+    history: &HistoryStore,
+    metrics: &Metrics,
+    chat_id: ChatId,
+    error: &str,
+) {
+    match history.quarantine_dialogue(chat_id, error).await {
+        Ok(Some(first)) => {
+            metrics
+                .dialogues_quarantined
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            warn!(
+                "Quarantined the unreadable dialogue state of chat {}",
+                chat_id
+            );
+            if first {
+                if let Err(e) = bot
+                    .send_message(
+                        chat_id,
+                        "Sorry, your settings couldn't be loaded and were reset to the defaults.",
+                    )
+                    .await
+                {
+                    warn!("Failed to notify chat of reset settings: {}", e);
+                }
+            }
+        }
+        Ok(None) => {}
+        Err(e) => error!("Failed to quarantine dialogue state: {:?}", e),
+    }
 }
 
 impl StableDiffusionBot {
     /// Creates an UpdateHandler for the bot
-    fn schema() -> UpdateHandler<anyhow::Error> {
-        Self::enter::<Update, ErasedStorage<State>, _>()
-            .branch(unauth_command_handler())
-            .branch(authenticated_command_handler())
+    fn update_handler() -> UpdateHandler<anyhow::Error> {
+        dptree::entry().branch(inline_schema()).branch(
+            Self::enter_dialogue()
+                .branch(unauth_command_handler())
+                .branch(admin_schema())
+                .branch(authenticated_command_handler())
+                .branch(guest_schema()),
+        )
+    }
+
+    /// Creates an UpdateHandler that loads the chat's dialogue state.
+    fn enter_dialogue() -> UpdateHandler<anyhow::Error> {
+        dptree::map(reply_keyboard::resolve_button_press)
+            .map_async(backends::select_backend)
+            .map_async(i18n::resolve_translator)
+            .chain(Self::enter::<Update, ErasedStorage<State>, _>())
+    }
+
+    /// Returns the commands shown in the Telegram command menu.
+    fn commands() -> Vec<BotCommand> {
+        let mut commands = UnauthenticatedCommands::bot_commands();
+        commands.extend(SettingsCommands::bot_commands());
+        commands.extend(BackendCommands::bot_commands());
+        commands.extend(ModelCommands::bot_commands());
+        commands.extend(VaeCommands::bot_commands());
+        commands.extend(LoraCommands::bot_commands());
+        commands.extend(SnippetCommands::bot_commands());
+        commands.extend(VarCommands::bot_commands());
+        commands.extend(LanguageCommands::bot_commands());
+        commands.extend(StyleCommands::bot_commands());
+        commands.extend(HistoryCommands::bot_commands());
+        commands.extend(GenCommands::bot_commands());
+        commands.extend(RandomCommands::bot_commands());
+        commands.extend(AbCommands::bot_commands());
+        commands.extend(InpaintCommands::bot_commands());
+        commands.extend(CancelCommands::bot_commands());
+        commands.extend(StatusCommands::bot_commands());
+        commands
     }
 
     // Borrowed and adapted from Teloxide's `dialogue::enter()` function.
     // Instead of building a default dialogue if one doesn't exist via `get_or_default()`,
-    // we build a dialogue with the defaults that are defined in the `ConfigParameters`.
+    // we build a dialogue with the defaults that are defined in the `BackendHandles`.
     fn enter<Upd, S, Output>() -> Handler<'static, DependencyMap, Output, DpHandlerDescription>
     where
         S: Storage<State> + ?Sized + Send + Sync + 'static,
@@ -126,16 +382,20 @@ impl StableDiffusionBot {
             Some(Dialogue::new(storage, chat_id))
         })
         .filter_map_async(
-            |dialogue: Dialogue<State, S>, cfg: ConfigParameters| async move {
+            |dialogue: Dialogue<State, S>,
+             backends: Arc<BackendHandles>,
+             bot: Bot,
+             history: HistoryStore,
+             metrics: Arc<Metrics>| async move {
+                let chat_id = dialogue.chat_id();
                 match dialogue.get().await {
                     Ok(dialogue) => {
                         let mut dialogue = if let Some(dialogue) = dialogue {
                             dialogue
                         } else {
-                            return Some(State::new_with_defaults(
-                                cfg.txt2img_api.gen_params(None),
-                                cfg.img2img_api.gen_params(None),
-                            ));
+                            return Some(
+                                settings_store::default_state(&backends, &history, chat_id).await,
+                            );
                         };
                         match dialogue {
                             State::New => {}
@@ -144,19 +404,21 @@ impl StableDiffusionBot {
                                 ref mut img2img,
                                 ..
                             } => {
-                                let txt2img_params = cfg.txt2img_api.gen_params(None);
+                                let txt2img_params = backends.txt2img_defaults(chat_id);
                                 if txt2img.as_any().type_id() != txt2img_params.as_any().type_id() {
                                     warn!("txt2img settings type mismatch, resetting to default");
                                     *txt2img = txt2img_params;
                                 } else {
-                                    *txt2img = cfg.txt2img_api.gen_params(Some(txt2img.as_ref()));
+                                    *txt2img =
+                                        backends.txt2img_api.gen_params(Some(txt2img.as_ref()));
                                 }
-                                let img2img_params = cfg.img2img_api.gen_params(None);
+                                let img2img_params = backends.img2img_defaults(chat_id);
                                 if img2img.as_any().type_id() != img2img_params.as_any().type_id() {
                                     warn!("img2img settings type mismatch, resetting to default");
                                     *img2img = img2img_params;
                                 } else {
-                                    *img2img = cfg.img2img_api.gen_params(Some(img2img.as_ref()));
+                                    *img2img =
+                                        backends.img2img_api.gen_params(Some(img2img.as_ref()));
                                 }
                             }
                         }
@@ -164,10 +426,10 @@ impl StableDiffusionBot {
                     }
                     Err(err) => {
                         error!("dialogue.get() failed: {:?}", err);
-                        let defaults = State::new_with_defaults(
-                            cfg.txt2img_api.gen_params(None),
-                            cfg.img2img_api.gen_params(None),
-                        );
+                        quarantine_dialogue(&bot, &history, &metrics, chat_id, &format!("{err:?}"))
+                            .await;
+                        let defaults =
+                            settings_store::default_state(&backends, &history, chat_id).await;
                         match dialogue.update(defaults.clone()).await {
                             Ok(_) => {
                                 warn!("dialogue reset to default state: {:?}", defaults);
@@ -189,51 +451,187 @@ impl StableDiffusionBot {
         let StableDiffusionBot {
             bot,
             storage,
+            history,
             config,
+            http,
+            telegram_webhook,
+            metrics,
+            supervisor_config,
+            retention_config,
+            wait_for_backend_config,
+            _lock,
         } = self;
 
-        let mut commands = UnauthenticatedCommands::bot_commands();
-        commands.extend(SettingsCommands::bot_commands());
-        commands.extend(GenCommands::bot_commands());
-        bot.set_my_commands(commands)
+        info!("Starting {}", build_info::build_info().replace('\n', ", "));
+        if config.allow_all_users {
+            info!("Allowing all users");
+        } else {
+            info!("Allowing {} users and chats", config.allowed_users.len());
+        }
+        if let Some(wait_for_backend_config) = wait_for_backend_config {
+            info!("Waiting for the backend to respond");
+            if !backend_wait::wait_for_backend(
+                &wait_for_backend_config,
+                config.txt2img_api.as_ref(),
+            )
+            .await
+            {
+                warn!("Backend did not respond, starting in degraded mode");
+                backend_wait::notify_degraded(&bot, config.auth.admin_users.iter().copied()).await;
+            }
+        } else {
+            let backends = config.backends.clone();
+            tokio::spawn(async move {
+                info!("Backend: {}", build_info::backend_version(&backends).await);
+            });
+        }
+
+        if let Some(http) = http {
+            tokio::spawn(async move {
+                if let Err(e) = http.serve().await {
+                    error!("HTTP server failed: {:?}", e);
+                }
+            });
+        }
+
+        if let Some(retention_config) = retention_config {
+            tokio::spawn(retention::purge_expired(history.clone(), retention_config));
+        }
+
+        bot.set_my_commands(Self::commands())
             .scope(teloxide::types::BotCommandScope::Default)
             .await
             .context("Failed to set bot commands")?;
 
-        Dispatcher::builder(bot, Self::schema())
-            .dependencies(dptree::deps![config, storage])
+        let webhook_updates = match telegram_webhook {
+            Some(telegram_webhook) => Some(telegram_webhook.start(&bot).await?),
+            None => None,
+        };
+
+        let ctrlc_handler = supervisor::CtrlCHandler::install();
+        let result = supervisor::supervise(&supervisor_config, &metrics, || {
+            let mut dependencies = config.dependencies();
+            dependencies.insert(storage.clone());
+            dependencies.insert(history.clone());
+            dependencies.insert(metrics.clone());
+            let mut dispatcher = Dispatcher::builder(
+                bot.clone(),
+                supervisor::catch_panics(Self::update_handler()),
+            )
+            .dependencies(dependencies)
+            .distribution_function(distribution_key)
             .default_handler(|upd| async move {
                 warn!("Unhandled update: {:?}", upd);
             })
             .error_handler(LoggingErrorHandler::with_custom_text(
                 "An error has occurred in the dispatcher",
             ))
-            .enable_ctrlc_handler()
-            .build()
-            .dispatch()
-            .await;
+            .build();
+            let run = ctrlc_handler.watch(dispatcher.shutdown_token());
+            let webhook_updates = webhook_updates.clone();
+            async move {
+                if !run {
+                    return;
+                }
+                match webhook_updates {
+                    Some(updates) => {
+                        dispatcher
+                            .dispatch_with_listener(
+                                updates.listener().await,
+                                LoggingErrorHandler::with_custom_text(
+                                    "An error from the update listener",
+                                ),
+                            )
+                            .await
+                    }
+                    None => dispatcher.dispatch().await,
+                }
+            }
+        })
+        .await;
 
-        Ok(())
+        if webhook_updates.is_some() {
+            if let Err(e) = bot.delete_webhook().await {
+                error!("Failed to delete Telegram webhook: {:?}", e);
+            }
+        }
+        result
     }
 }
 
-#[derive(Clone, Debug)]
-pub(crate) struct ConfigParameters {
-    allowed_users: HashSet<ChatId>,
-    txt2img_api: Box<dyn sal_e_api::Txt2ImgApi>,
-    img2img_api: Box<dyn sal_e_api::Img2ImgApi>,
-    allow_all_users: bool,
-}
+#[cfg(feature = "embed")]
+impl StableDiffusionBot {
+    /// Returns the bot's complete `UpdateHandler`, for composing the bot into your own
+    /// `Dispatcher`.
+    ///
+    /// The handler requires the dependencies returned by [`StableDiffusionBot::dependencies`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use stable_diffusion_bot::StableDiffusionBot;
+    /// # use teloxide::prelude::*;
+    /// # async fn example(sd_bot: StableDiffusionBot, bot: Bot) {
+    /// let handler = dptree::entry()
+    ///     // Your own handlers go here.
+    ///     .branch(StableDiffusionBot::schema());
+    ///
+    /// Dispatcher::builder(bot, handler)
+    ///     .dependencies(sd_bot.dependencies())
+    ///     .build()
+    ///     .dispatch()
+    ///     .await;
+    /// # }
+    /// ```
+    pub fn schema() -> UpdateHandler<anyhow::Error> {
+        Self::update_handler()
+    }
+
+    /// Returns an `UpdateHandler` that loads the chat's dialogue state. The handlers in
+    /// [`schema`](crate::schema) must be branches of this handler.
+    pub fn dialogue_handler() -> UpdateHandler<anyhow::Error> {
+        Self::enter_dialogue()
+    }
+
+    /// Returns the dependencies required by the bot's handlers.
+    pub fn dependencies(&self) -> DependencyMap {
+        let mut dependencies = self.config.dependencies();
+        dependencies.insert(self.storage.clone());
+        dependencies.insert(self.history.clone());
+        dependencies.insert(self.metrics.clone());
+        dependencies
+    }
+
+    /// Returns the commands the bot registers in the Telegram command menu.
+    pub fn bot_commands() -> Vec<BotCommand> {
+        Self::commands()
+    }
 
-impl ConfigParameters {
-    /// Checks whether a chat is allowed by the config.
-    pub fn chat_is_allowed(&self, chat_id: &ChatId) -> bool {
-        self.allow_all_users || self.allowed_users.contains(chat_id)
+    /// Returns the key your `Dispatcher` should order updates by, using
+    /// `DispatcherBuilder::distribution_function`. Without it, requests to cancel a generation
+    /// wait until the generation has finished.
+    pub fn distribution_key(update: &Update) -> Option<ChatId> {
+        distribution_key(update)
     }
 }
 
+/// Handler builders for composing individual parts of the bot into your own `Dispatcher`.
+///
+/// Each handler except `inline_schema` must be a branch of
+/// [`StableDiffusionBot::dialogue_handler`], and all of them require the dependencies returned
+/// by [`StableDiffusionBot::dependencies`].
+#[cfg(feature = "embed")]
+pub mod schema {
+    pub use super::handlers::{
+        admin_schema, auth_filter, authenticated_command_handler, backend_schema, cancel_schema,
+        history_schema, image_schema, inline_schema, language_schema, lora_schema, model_schema,
+        settings_schema, snippet_schema, status_schema, style_schema, unauth_command_handler,
+        vae_schema, var_schema,
+    };
+}
+
 /// Enum representing the types of Stable Diffusion API.
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
 pub enum ApiType {
     /// ComfyUI API
     ComfyUI,
@@ -243,25 +641,284 @@ pub enum ApiType {
 }
 
 /// Struct that represents the configuration for the ComfyUI API.
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
 pub struct ComfyUIConfig {
     /// Path to the prompt file for text to image requests.
     pub txt2img_prompt_file: Option<PathBuf>,
     /// Path to the prompt file for image to image requests.
     pub img2img_prompt_file: Option<PathBuf>,
+    /// Maximum number of output images downloaded concurrently.
+    pub fetch_concurrency: Option<usize>,
+    /// How a fixed seed changes after each generation, unless a user picks a seed mode.
+    pub seed_mode: Option<SeedMode>,
+    /// Headers sent with every request to ComfyUI, e.g. `Authorization` or `Cookie` for
+    /// instances behind an authenticating proxy.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+/// Parses the configured ComfyUI headers, marking their values as sensitive.
+fn comfyui_headers(headers: &BTreeMap<String, String>) -> anyhow::Result<HeaderMap> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let name = HeaderName::try_from(name.as_str())
+                .with_context(|| format!("Invalid ComfyUI header name: {name}"))?;
+            let mut value = HeaderValue::try_from(value.as_str())
+                .with_context(|| format!("Invalid value for ComfyUI header {name}"))?;
+            value.set_sensitive(true);
+            Ok((name, value))
+        })
+        .collect()
+}
+
+/// Returns the `Authorization` header value that carries `auth`, for ComfyUI instances behind
+/// an authenticating proxy.
+fn authorization_header(auth: &Auth) -> anyhow::Result<HeaderValue> {
+    use base64::{engine::general_purpose, Engine as _};
+    let value = match auth {
+        Auth::Basic { username, password } => format!(
+            "Basic {}",
+            general_purpose::STANDARD.encode(format!("{username}:{password}"))
+        ),
+        Auth::Bearer { token } => format!("Bearer {token}"),
+    };
+    let mut value =
+        HeaderValue::try_from(value).context("Invalid characters in backend credentials")?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+/// Rebuilds a ComfyUI client so that every request carries `headers`, if any, and is sent as
+/// configured in `requests`.
+fn comfyui_client(
+    client: &Comfy,
+    headers: Option<&HeaderMap>,
+    requests: &RequestConfig,
+) -> anyhow::Result<Comfy> {
+    let mut api = requests.apply_comfyui(client.api().clone());
+    if let Some(headers) = headers {
+        api = api.with_headers(headers.clone());
+    }
+    Comfy::new_with_api(api).context("Failed to create ComfyUI client")
+}
+
+/// The clients of a backend, one for each of its URLs, and its upscaler.
+type BackendApis = (
+    Vec<Box<dyn Txt2ImgApi>>,
+    Vec<Box<dyn Img2ImgApi>>,
+    Option<ImageUpscaler>,
+);
+
+/// Settings shared by the clients of every backend.
+struct ApiSettings<'a> {
+    client: &'a reqwest::Client,
+    auth: Option<&'a Auth>,
+    requests: &'a RequestConfig,
+    txt2img_defaults: &'a Txt2ImgRequest,
+    img2img_defaults: &'a Img2ImgRequest,
+    upscale_config: &'a UpscaleConfig,
+}
+
+/// Creates the clients of a backend of type `api_type` for each of `urls`.
+async fn build_apis(
+    api_type: &ApiType,
+    urls: Vec<String>,
+    comfyui: &ComfyUIConfig,
+    settings: &ApiSettings<'_>,
+) -> anyhow::Result<BackendApis> {
+    let apis = match api_type {
+        ApiType::ComfyUI => {
+            let mut txt2img_prompt = String::new();
+
+            File::open(
+                comfyui
+                    .txt2img_prompt_file
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("No ComfyUI txt2img prompt file provided."))?,
+            )
+            .await
+            .context("Failed to open comfyui txt2img prompt file")?
+            .read_to_string(&mut txt2img_prompt)
+            .await?;
+
+            let mut img2img_prompt = String::new();
+
+            File::open(
+                comfyui
+                    .img2img_prompt_file
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("No ComfyUI img2img prompt file provided."))?,
+            )
+            .await
+            .context("Failed to open comfyui img2img prompt file")?
+            .read_to_string(&mut img2img_prompt)
+            .await?;
+
+            let txt2img_prompt =
+                serde_json::from_str::<comfyui_api::models::Prompt>(&txt2img_prompt)
+                    .context("Failed to deserialize prompt")?;
+
+            _ = txt2img_prompt
+                .prompt()
+                .context("Failed to find a valid txt2img prompt node.")?;
+            _ = txt2img_prompt
+                .seed()
+                .context("Failed to find a valid txt2img seed node.")?;
+
+            let img2img_prompt =
+                serde_json::from_str::<comfyui_api::models::Prompt>(&img2img_prompt)
+                    .context("Failed to deserialize prompt")?;
+
+            _ = img2img_prompt
+                .prompt()
+                .context("Failed to find a valid img2img prompt node.")?;
+            _ = img2img_prompt
+                .image()
+                .context("Failed to find a valid img2img image node.")?;
+            _ = img2img_prompt
+                .seed()
+                .context("Failed to find a valid img2img seed node.")?;
+
+            let mut headers = (!comfyui.headers.is_empty())
+                .then(|| comfyui_headers(&comfyui.headers))
+                .transpose()?;
+            // A configured Authorization header takes precedence over the credentials.
+            if let Some(auth) = settings.auth {
+                headers
+                    .get_or_insert_with(HeaderMap::new)
+                    .entry(AUTHORIZATION)
+                    .or_insert(authorization_header(auth)?);
+            }
+
+            let mut txt2img_apis: Vec<Box<dyn Txt2ImgApi>> = Vec::new();
+            let mut img2img_apis: Vec<Box<dyn Img2ImgApi>> = Vec::new();
+            for url in urls {
+                let mut txt2img_api = ComfyPromptApi::new_with_client_and_url(
+                    settings.client.clone(),
+                    url.clone(),
+                    txt2img_prompt.clone(),
+                )?;
+
+                let mut img2img_api = ComfyPromptApi::new_with_client_and_url(
+                    settings.client.clone(),
+                    url,
+                    img2img_prompt.clone(),
+                )
+                .context("Failed to create ComfyUI client")?;
+
+                txt2img_api.client =
+                    comfyui_client(&txt2img_api.client, headers.as_ref(), settings.requests)?;
+                img2img_api.client =
+                    comfyui_client(&img2img_api.client, headers.as_ref(), settings.requests)?;
+
+                if let Some(limit) = comfyui.fetch_concurrency {
+                    txt2img_api.client = txt2img_api.client.with_fetch_concurrency(limit);
+                    img2img_api.client = img2img_api.client.with_fetch_concurrency(limit);
+                }
+                if let Some(mode) = comfyui.seed_mode {
+                    txt2img_api.params.seed_mode = Some(mode);
+                    img2img_api.params.seed_mode = Some(mode);
+                }
+                txt2img_apis.push(Box::new(txt2img_api));
+                img2img_apis.push(Box::new(img2img_api));
+            }
+            (txt2img_apis, img2img_apis, None)
+        }
+        ApiType::StableDiffusionWebUi => {
+            let apis = urls
+                .into_iter()
+                .map(|url| {
+                    let mut api = settings.requests.apply_webui(
+                        Api::new_with_client_and_url(settings.client.clone(), url)
+                            .context("Failed to initialize sd api")?,
+                    );
+                    if let Some(auth) = settings.auth {
+                        api = api.with_auth(auth.clone());
+                    }
+                    Ok(StableDiffusionWebUiApi {
+                        client: api,
+                        txt2img_defaults: settings.txt2img_defaults.clone(),
+                        img2img_defaults: settings.img2img_defaults.clone(),
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            // Upscaling isn't balanced, it always uses the first backend.
+            let upscaler =
+                ImageUpscaler::new(Box::new(apis[0].clone()), settings.upscale_config.clone());
+
+            (
+                apis.iter()
+                    .map(|api| Box::new(api.clone()) as Box<dyn Txt2ImgApi>)
+                    .collect::<Vec<_>>(),
+                apis.into_iter()
+                    .map(|api| Box::new(api) as Box<dyn Img2ImgApi>)
+                    .collect::<Vec<_>>(),
+                upscaler,
+            )
+        }
+    };
+    Ok(apis)
 }
 
 /// Struct that builds a StableDiffusionBot instance.
 pub struct StableDiffusionBotBuilder {
     api_key: String,
     allowed_users: Vec<i64>,
+    allowed_chats: Vec<i64>,
+    banned_users: Vec<i64>,
+    admin_users: Vec<i64>,
+    shared_accounts: BTreeMap<String, Vec<u64>>,
     db_path: Option<String>,
+    force_db_lock: bool,
+    compress_dialogues: bool,
     sd_api_url: String,
     api_type: ApiType,
     txt2img_defaults: Option<Txt2ImgRequest>,
     img2img_defaults: Option<Img2ImgRequest>,
+    user_defaults: BTreeMap<i64, DefaultSettings>,
+    chat_defaults: BTreeMap<i64, DefaultSettings>,
     comfyui_img2img_prompt_file: Option<PathBuf>,
     comfyui_txt2img_prompt_file: Option<PathBuf>,
+    comfyui_fetch_concurrency: Option<usize>,
+    comfyui_seed_mode: Option<SeedMode>,
+    comfyui_headers: BTreeMap<String, String>,
+    backend_auth: Option<Auth>,
+    request_config: RequestConfig,
+    http_config: Option<HttpConfig>,
+    telegram_webhook_config: Option<TelegramWebhookConfig>,
+    prompt_policy: PromptPolicy,
+    negative_presets: Vec<NegativePreset>,
+    styles: Vec<Style>,
+    telegram_api_url: Option<String>,
+    tls_backend: TlsBackend,
+    supervisor_config: SupervisorConfig,
+    vision_config: Option<VisionConfig>,
+    retention_config: Option<RetentionConfig>,
+    wait_for_backend_config: Option<WaitForBackendConfig>,
+    blank_check_config: Option<BlankCheckConfig>,
+    progress_config: ProgressConfig,
+    upscale_config: UpscaleConfig,
+    pool_config: Option<PoolConfig>,
+    unknown_command_mode: UnknownCommandMode,
+    caption_mode: CaptionMode,
+    denoising_presets: bool,
+    size_presets_max_pixels: Option<u32>,
+    caption_template: CaptionTemplate,
+    group_mode: GroupMode,
+    prompt_cleaning_config: PromptCleaningConfig,
+    language: Option<String>,
+    translations_dir: Option<PathBuf>,
+    inline_config: Option<InlineConfig>,
+    zip_config: Option<ZipConfig>,
+    quiet_hours_config: Option<QuietHoursConfig>,
+    webhook_configs: Vec<WebhookConfig>,
+    quota_config: Option<QuotaConfig>,
+    reply_keyboard_config: Option<ReplyKeyboardConfig>,
+    random_config: RandomConfig,
+    guest_config: Option<GuestConfig>,
+    backend_configs: Vec<BackendConfig>,
     allow_all_users: bool,
 }
 
@@ -277,14 +934,60 @@ impl StableDiffusionBotBuilder {
         StableDiffusionBotBuilder {
             api_key,
             allowed_users,
+            allowed_chats: Vec::new(),
+            banned_users: Vec::new(),
+            admin_users: Vec::new(),
+            shared_accounts: BTreeMap::new(),
             db_path: None,
+            force_db_lock: false,
+            compress_dialogues: false,
             sd_api_url,
             txt2img_defaults: None,
             img2img_defaults: None,
+            user_defaults: BTreeMap::new(),
+            chat_defaults: BTreeMap::new(),
             allow_all_users,
             api_type,
             comfyui_txt2img_prompt_file: None,
             comfyui_img2img_prompt_file: None,
+            comfyui_fetch_concurrency: None,
+            comfyui_seed_mode: None,
+            comfyui_headers: BTreeMap::new(),
+            backend_auth: None,
+            request_config: RequestConfig::default(),
+            http_config: None,
+            telegram_webhook_config: None,
+            prompt_policy: PromptPolicy::default(),
+            negative_presets: Vec::new(),
+            styles: Vec::new(),
+            telegram_api_url: None,
+            tls_backend: TlsBackend::default(),
+            supervisor_config: SupervisorConfig::default(),
+            vision_config: None,
+            retention_config: None,
+            wait_for_backend_config: None,
+            blank_check_config: None,
+            progress_config: ProgressConfig::default(),
+            upscale_config: UpscaleConfig::default(),
+            pool_config: None,
+            unknown_command_mode: UnknownCommandMode::default(),
+            caption_mode: CaptionMode::default(),
+            denoising_presets: false,
+            size_presets_max_pixels: None,
+            caption_template: CaptionTemplate::default(),
+            group_mode: GroupMode::default(),
+            prompt_cleaning_config: PromptCleaningConfig::default(),
+            language: None,
+            translations_dir: None,
+            inline_config: None,
+            zip_config: None,
+            quiet_hours_config: None,
+            webhook_configs: Vec::new(),
+            quota_config: None,
+            reply_keyboard_config: None,
+            random_config: RandomConfig::default(),
+            guest_config: None,
+            backend_configs: Vec::new(),
         }
     }
 
@@ -313,6 +1016,71 @@ impl StableDiffusionBotBuilder {
         self
     }
 
+    /// Builder function that allows starting even if another instance holds the database lock.
+    ///
+    /// # Arguments
+    ///
+    /// * `force` - Whether to ignore the database lock held by another instance.
+    pub fn force_db_lock(mut self, force: bool) -> Self {
+        self.force_db_lock = force;
+        self
+    }
+
+    /// Builder function that sets whether to compress the dialogue states and the parameters of
+    /// recorded generations in the storage database. Existing states are converted when the bot
+    /// starts, while recorded parameters are read either way.
+    ///
+    /// # Arguments
+    ///
+    /// * `compress` - Whether to compress the stored dialogue states and generation parameters.
+    pub fn compress_dialogues(mut self, compress: bool) -> Self {
+        self.compress_dialogues = compress;
+        self
+    }
+
+    /// Builder function that sets chats whose members are allowed to use the bot within the chat.
+    ///
+    /// # Arguments
+    ///
+    /// * `allowed_chats` - A `Vec<i64>` of Telegram chat ids.
+    pub fn allowed_chats(mut self, allowed_chats: Vec<i64>) -> Self {
+        self.allowed_chats = allowed_chats;
+        self
+    }
+
+    /// Builder function that sets users who are never allowed to use the bot, even in allowed
+    /// chats or if all users are allowed.
+    ///
+    /// # Arguments
+    ///
+    /// * `banned_users` - A `Vec<i64>` of Telegram user ids.
+    pub fn banned_users(mut self, banned_users: Vec<i64>) -> Self {
+        self.banned_users = banned_users;
+        self
+    }
+
+    /// Builder function that sets the users allowed to run admin commands.
+    ///
+    /// # Arguments
+    ///
+    /// * `admin_users` - A `Vec<i64>` of Telegram user ids.
+    pub fn admin_users(mut self, admin_users: Vec<i64>) -> Self {
+        self.admin_users = admin_users;
+        self
+    }
+
+    /// Builder function that maps several users to shared accounts, so that they are treated
+    /// as one user, for example to share limits within a family or team.
+    ///
+    /// # Arguments
+    ///
+    /// * `accounts` - A `BTreeMap` of account names to the Telegram user ids of their members.
+    ///   A user may be a member of at most one account.
+    pub fn shared_accounts(mut self, accounts: BTreeMap<String, Vec<u64>>) -> Self {
+        self.shared_accounts = accounts;
+        self
+    }
+
     /// Builder function that sets the defaults for text to image requests.
     ///
     /// # Arguments
@@ -379,15 +1147,421 @@ impl StableDiffusionBotBuilder {
         self
     }
 
+    /// Builder function that gives specific users their own default settings, replacing the
+    /// text to image and image to image defaults in their private chats with the bot.
+    ///
+    /// # Arguments
+    ///
+    /// * `defaults` - A `BTreeMap` of Telegram user ids to their default settings.
+    pub fn user_defaults(mut self, defaults: BTreeMap<i64, DefaultSettings>) -> Self {
+        self.user_defaults = defaults;
+        self
+    }
+
+    /// Builder function that gives specific chats their own default settings, replacing the
+    /// text to image and image to image defaults. User defaults take precedence in private chats.
+    ///
+    /// # Arguments
+    ///
+    /// * `defaults` - A `BTreeMap` of Telegram chat ids to their default settings.
+    pub fn chat_defaults(mut self, defaults: BTreeMap<i64, DefaultSettings>) -> Self {
+        self.chat_defaults = defaults;
+        self
+    }
+
     pub fn comfyui_config(
         mut self,
         ComfyUIConfig {
             txt2img_prompt_file,
             img2img_prompt_file,
+            fetch_concurrency,
+            seed_mode,
+            headers,
         }: ComfyUIConfig,
     ) -> Self {
         self.comfyui_txt2img_prompt_file = txt2img_prompt_file;
         self.comfyui_img2img_prompt_file = img2img_prompt_file;
+        self.comfyui_fetch_concurrency = fetch_concurrency;
+        self.comfyui_seed_mode = seed_mode;
+        self.comfyui_headers = headers;
+        self
+    }
+
+    /// Builder function that enables the HTTP server.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - An optional `HttpConfig`. If `None`, the HTTP server is disabled.
+    pub fn http_config(mut self, config: Option<HttpConfig>) -> Self {
+        self.http_config = config;
+        self
+    }
+
+    /// Builder function that makes the bot receive updates via a webhook instead of polling.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - An optional `TelegramWebhookConfig`. If `None`, the bot polls for updates.
+    pub fn telegram_webhook_config(mut self, config: Option<TelegramWebhookConfig>) -> Self {
+        self.telegram_webhook_config = config;
+        self
+    }
+
+    /// Builder function that sets the limits enforced on prompts and negative prompts.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - A `PromptPolicy` with the maximum prompt length and banned words.
+    pub fn prompt_policy(mut self, policy: PromptPolicy) -> Self {
+        self.prompt_policy = policy;
+        self
+    }
+
+    /// Builder function that sets the URL of a self-hosted Telegram Bot API server.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - An optional URL of the Bot API server, e.g. `http://localhost:8081`. If `None`,
+    ///   the cloud Bot API is used.
+    pub fn telegram_api_url(mut self, url: Option<String>) -> Self {
+        self.telegram_api_url = url;
+        self
+    }
+
+    /// Builder function that sets the credentials sent with every request to the backend, for a
+    /// WebUI started with `--api-auth` or a backend behind an authenticating proxy.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth` - An optional `Auth`. If `None`, requests are sent without credentials.
+    pub fn backend_auth(mut self, auth: Option<Auth>) -> Self {
+        self.backend_auth = auth;
+        self
+    }
+
+    /// Builder function that sets the timeout and retries of requests to the backends.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - A `RequestConfig`. By default, requests wait indefinitely and aren't retried.
+    pub fn request_config(mut self, config: RequestConfig) -> Self {
+        self.request_config = config;
+        self
+    }
+
+    /// Builder function that sets the TLS implementation used for HTTPS connections.
+    ///
+    /// # Arguments
+    ///
+    /// * `tls` - A `TlsBackend`. Defaults to rustls.
+    pub fn tls_backend(mut self, tls: TlsBackend) -> Self {
+        self.tls_backend = tls;
+        self
+    }
+
+    /// Builder function that sets how often the dispatcher may be restarted after a panic.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - A `SupervisorConfig` with the restart limit and the window it applies to.
+    pub fn supervisor_config(mut self, config: SupervisorConfig) -> Self {
+        self.supervisor_config = config;
+        self
+    }
+
+    /// Builder function that enables describing generated images with a vision model.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - An optional `VisionConfig`. If `None`, images are sent without a
+    ///   description.
+    pub fn vision_config(mut self, config: Option<VisionConfig>) -> Self {
+        self.vision_config = config;
+        self
+    }
+
+    /// Builder function that sets the negative prompt presets users can enable in the settings
+    /// menu.
+    ///
+    /// # Arguments
+    ///
+    /// * `presets` - A `Vec<NegativePreset>`. Enabled presets are appended to the user's
+    ///   negative prompt.
+    pub fn negative_presets(mut self, presets: Vec<NegativePreset>) -> Self {
+        self.negative_presets = presets;
+        self
+    }
+
+    /// Builder function that sets the styles every user can select with `/style`.
+    ///
+    /// # Arguments
+    ///
+    /// * `styles` - A `Vec<Style>`. Users can also save their own styles, which can't reuse
+    ///   these names.
+    pub fn styles(mut self, styles: Vec<Style>) -> Self {
+        self.styles = styles;
+        self
+    }
+
+    /// Builder function that enables automatically purging old generation history.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - An optional `RetentionConfig`. If `None`, history is kept until users
+    ///   delete it.
+    pub fn retention_config(mut self, config: Option<RetentionConfig>) -> Self {
+        self.retention_config = config;
+        self
+    }
+
+    /// Builder function that makes the bot wait for the backend to respond before it starts
+    /// handling updates.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - An optional `WaitForBackendConfig`. If `None`, the bot starts right away.
+    ///   If the backend still doesn't respond after the configured retries, the bot starts
+    ///   anyway and notifies the admins.
+    pub fn wait_for_backend_config(mut self, config: Option<WaitForBackendConfig>) -> Self {
+        self.wait_for_backend_config = config;
+        self
+    }
+
+    /// Builder function that enables retrying generations that return blank images.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - An optional `BlankCheckConfig`. If `None`, images are sent without being
+    ///   checked.
+    pub fn blank_check_config(mut self, config: Option<BlankCheckConfig>) -> Self {
+        self.blank_check_config = config;
+        self
+    }
+
+    /// Builder function that sets how the progress of generations is reported.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - A `ProgressConfig` with how often to update the progress message and an
+    ///   optional timeout for requests.
+    pub fn progress_config(mut self, config: ProgressConfig) -> Self {
+        self.progress_config = config;
+        self
+    }
+
+    /// Builder function that configures the upscale buttons on generated images.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - An `UpscaleConfig` with the upscaler to use and the factors to offer. Only
+    ///   the Stable Diffusion WebUI backend supports upscaling.
+    pub fn upscale_config(mut self, config: UpscaleConfig) -> Self {
+        self.upscale_config = config;
+        self
+    }
+
+    /// Builder function that spreads requests over several backends of the same type.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - An optional `PoolConfig` with the URLs of backends to use along with the
+    ///   main one. If `None`, only the main backend is used.
+    pub fn pool_config(mut self, config: Option<PoolConfig>) -> Self {
+        self.pool_config = config;
+        self
+    }
+
+    /// Builder function that sets how the bot responds to commands it doesn't know.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - An `UnknownCommandMode`. Unknown commands are ignored by default.
+    pub fn unknown_command_mode(mut self, mode: UnknownCommandMode) -> Self {
+        self.unknown_command_mode = mode;
+        self
+    }
+
+    /// Builder function that sets how much of the generation settings captions show.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - A `CaptionMode`. Captions show every setting by default.
+    pub fn caption_mode(mut self, mode: CaptionMode) -> Self {
+        self.caption_mode = mode;
+        self
+    }
+
+    /// Builder function that sets whether to ask for a denoising strength before generating from
+    /// a photo.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - If `true`, photos are answered with buttons offering subtle, balanced and
+    ///   strong denoising strengths, and the image is generated once one is pressed.
+    pub fn denoising_presets(mut self, enabled: bool) -> Self {
+        self.denoising_presets = enabled;
+        self
+    }
+
+    /// Builder function that limits the sizes offered by the size presets in the settings menu.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_pixels` - The largest number of pixels, width times height, of an offered size.
+    ///   If `None`, sizes up to 1024×1024 are offered.
+    pub fn size_presets_max_pixels(mut self, max_pixels: Option<u32>) -> Self {
+        self.size_presets_max_pixels = max_pixels;
+        self
+    }
+
+    /// Builder function that sets the template of the captions of generated images.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - A `CaptionTemplate`. Captions list the prompt and each setting by
+    ///   default.
+    pub fn caption_template(mut self, template: CaptionTemplate) -> Self {
+        self.caption_template = template;
+        self
+    }
+
+    /// Builder function that sets which messages in group chats are prompts.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - A `GroupMode`. Every message is a prompt by default.
+    pub fn group_mode(mut self, mode: GroupMode) -> Self {
+        self.group_mode = mode;
+        self
+    }
+
+    /// Builder function that sets how prompts copied from other chats are cleaned.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - A `PromptCleaningConfig`. Leading mentions and hashtag signs are removed by
+    ///   default.
+    pub fn prompt_cleaning_config(mut self, config: PromptCleaningConfig) -> Self {
+        self.prompt_cleaning_config = config;
+        self
+    }
+
+    /// Builder function that sets the language of the bot's messages for users who haven't
+    /// chosen one with `/language`.
+    ///
+    /// # Arguments
+    ///
+    /// * `language` - An optional language code, such as `de`. English if `None`.
+    pub fn language(mut self, language: Option<String>) -> Self {
+        self.language = language;
+        self
+    }
+
+    /// Builder function that sets a directory with translations of the bot's messages, in
+    /// addition to the built-in ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - An optional directory with a `<language>.toml` file per language, mapping
+    ///   English messages to their translations.
+    pub fn translations_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.translations_dir = dir;
+        self
+    }
+
+    /// Builder function that enables generating images from inline queries.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - An optional `InlineConfig`. If `None`, inline queries are ignored.
+    pub fn inline_config(mut self, config: Option<InlineConfig>) -> Self {
+        self.inline_config = config;
+        self
+    }
+
+    /// Builder function that enables sending large batches as zip files instead of albums.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - An optional `ZipConfig` with the batch size to start sending zip files at.
+    ///   If `None`, batches are always sent as albums.
+    pub fn zip_config(mut self, config: Option<ZipConfig>) -> Self {
+        self.zip_config = config;
+        self
+    }
+
+    /// Builder function that sets quiet hours, during which no images are generated.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - An optional `QuietHoursConfig` with the quiet hours for all chats and for
+    ///   individual chats. If `None`, images are generated at any time.
+    pub fn quiet_hours_config(mut self, config: Option<QuietHoursConfig>) -> Self {
+        self.quiet_hours_config = config;
+        self
+    }
+
+    /// Builder function that sets webhooks that receive the results of generations.
+    ///
+    /// # Arguments
+    ///
+    /// * `configs` - A `Vec<WebhookConfig>` with the URL to post each chat's results to.
+    pub fn webhooks(mut self, configs: Vec<WebhookConfig>) -> Self {
+        self.webhook_configs = configs;
+        self
+    }
+
+    /// Builder function that limits how many images each user can generate.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - An optional `QuotaConfig` with the number of images each user can generate
+    ///   per hour and per day. If `None`, users can generate any number of images.
+    pub fn quota_config(mut self, config: Option<QuotaConfig>) -> Self {
+        self.quota_config = config;
+        self
+    }
+
+    /// Builder function that shows a reply keyboard with common actions in private chats.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - An optional `ReplyKeyboardConfig` with the buttons to show. If `None`, no
+    ///   reply keyboard is shown.
+    pub fn reply_keyboard_config(mut self, config: Option<ReplyKeyboardConfig>) -> Self {
+        self.reply_keyboard_config = config;
+        self
+    }
+
+    /// Builder function that sets what `/random` chooses from.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - A `RandomConfig` with the samplers and range of CFG scales to choose from.
+    pub fn random_config(mut self, config: RandomConfig) -> Self {
+        self.random_config = config;
+        self
+    }
+
+    /// Builder function that enables guest mode, which lets users who aren't allowed try the
+    /// bot with small, watermarked images.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - An optional `GuestConfig`. If `None`, users who aren't allowed are rejected.
+    pub fn guest_config(mut self, config: Option<GuestConfig>) -> Self {
+        self.guest_config = config;
+        self
+    }
+
+    /// Builder function that adds backends users can switch to with `/backend`.
+    ///
+    /// # Arguments
+    ///
+    /// * `configs` - A `Vec<BackendConfig>` with the name, URL and type of each backend. The
+    ///   backend at `sd_api_url` is named `default` and stays the default.
+    pub fn backends(mut self, configs: Vec<BackendConfig>) -> Self {
+        self.backend_configs = configs;
         self
     }
 
@@ -409,120 +1583,209 @@ impl StableDiffusionBotBuilder {
     /// # });
     /// ```
     pub async fn build(self) -> anyhow::Result<StableDiffusionBot> {
-        let storage: DialogueStorage = if let Some(path) = self.db_path {
-            SqliteStorage::open(&path, Json)
+        let lock = self
+            .db_path
+            .as_ref()
+            .map(|path| InstanceLock::acquire(path, self.force_db_lock))
+            .transpose()?
+            .flatten();
+
+        let accounts =
+            AccountResolver::new(self.shared_accounts).context("Invalid shared accounts")?;
+
+        let history = HistoryStore::open(self.db_path.as_deref())
+            .await?
+            .with_compression(self.compress_dialogues)
+            .with_accounts(accounts.clone());
+
+        let storage: DialogueStorage = if self.db_path.is_some() {
+            let codec = DialogueCodec {
+                compress: self.compress_dialogues,
+            };
+            let storage = SqliteStorage::open(history.pool().clone(), codec)
                 .await
-                .context("failed to open db")?
-                .erase()
+                .context("failed to open db")?;
+            let migrated = codec.migrate(history.pool()).await?;
+            if migrated > 0 {
+                info!("Converted {} stored dialogue states", migrated);
+            }
+            storage.erase()
         } else {
             InMemStorage::new().erase()
         };
 
-        let bot = Bot::new(self.api_key.clone());
+        let bot_client = self
+            .tls_backend
+            .configure(teloxide::net::default_reqwest_settings())?
+            .build()
+            .context("Failed to create Telegram client")?;
+        let mut bot = Bot::with_client(self.api_key.clone(), bot_client);
+        if let Some(url) = &self.telegram_api_url {
+            bot = bot.set_api_url(
+                reqwest::Url::parse(url).context("Invalid Telegram Bot API server URL")?,
+            );
+        }
 
-        let allowed_users = self.allowed_users.into_iter().map(ChatId).collect();
+        let metrics = Arc::new(Metrics::default());
 
-        let client = reqwest::Client::new();
+        let mut http = self
+            .http_config
+            .as_ref()
+            .map(HttpServer::new)
+            .transpose()
+            .context("Invalid HTTP server configuration")?
+            .map(|http| http.merge(metrics::router(metrics.clone())));
 
-        let (txt2img_api, img2img_api): (Box<dyn Txt2ImgApi>, Box<dyn Img2ImgApi>) = match self
-            .api_type
-        {
-            ApiType::ComfyUI => {
-                let mut txt2img_prompt = String::new();
+        let describer = self
+            .vision_config
+            .map(|config| ImageDescriber::new(config, self.tls_backend.client()?))
+            .transpose()
+            .context("Invalid vision configuration")?;
 
-                File::open(
-                    self.comfyui_txt2img_prompt_file
-                        .ok_or_else(|| anyhow!("No ComfyUI txt2img prompt file provided."))?,
-                )
-                .await
-                .context("Failed to open comfyui txt2img prompt file")?
-                .read_to_string(&mut txt2img_prompt)
-                .await?;
+        let allowed_users = Arc::new(RwLock::new(
+            self.allowed_users.into_iter().map(ChatId).collect(),
+        ));
 
-                let mut img2img_prompt = String::new();
+        let allowed_chats = self.allowed_chats.into_iter().map(ChatId).collect();
 
-                File::open(
-                    self.comfyui_img2img_prompt_file
-                        .ok_or_else(|| anyhow!("No ComfyUI img2img prompt file provided."))?,
-                )
-                .await
-                .context("Failed to open comfyui img2img prompt file")?
-                .read_to_string(&mut img2img_prompt)
-                .await?;
-
-                let txt2img_prompt =
-                    serde_json::from_str::<comfyui_api::models::Prompt>(&txt2img_prompt)
-                        .context("Failed to deserialize prompt")?;
-
-                _ = txt2img_prompt
-                    .prompt()
-                    .context("Failed to find a valid txt2img prompt node.")?;
-                _ = txt2img_prompt
-                    .seed()
-                    .context("Failed to find a valid txt2img seed node.")?;
-
-                let txt2img_api = ComfyPromptApi::new_with_client_and_url(
-                    client.clone(),
-                    self.sd_api_url.clone(),
-                    txt2img_prompt,
-                )?;
+        let banned_users = Arc::new(RwLock::new(
+            self.banned_users.into_iter().map(ChatId).collect(),
+        ));
 
-                let img2img_prompt =
-                    serde_json::from_str::<comfyui_api::models::Prompt>(&img2img_prompt)
-                        .context("Failed to deserialize prompt")?;
-
-                _ = img2img_prompt
-                    .prompt()
-                    .context("Failed to find a valid img2img prompt node.")?;
-                _ = img2img_prompt
-                    .image()
-                    .context("Failed to find a valid img2img image node.")?;
-                _ = img2img_prompt
-                    .seed()
-                    .context("Failed to find a valid img2img seed node.")?;
-
-                let img2img_api = ComfyPromptApi::new_with_client_and_url(
-                    client,
-                    self.sd_api_url,
-                    img2img_prompt,
-                )
-                .context("Failed to create ComfyUI client")?;
-                (Box::new(txt2img_api), Box::new(img2img_api))
-            }
-            ApiType::StableDiffusionWebUi => {
-                let api = Api::new_with_client_and_url(client, self.sd_api_url)
-                    .context("Failed to initialize sd api")?;
-                let txt2img_api = StableDiffusionWebUiApi {
-                    client: api.clone(),
-                    txt2img_defaults: default_txt2img(
-                        self.txt2img_defaults.clone().unwrap_or_default(),
-                    ),
-                    img2img_defaults: default_img2img(
-                        self.img2img_defaults.clone().unwrap_or_default(),
-                    ),
-                };
-
-                let img2img_api = StableDiffusionWebUiApi {
-                    client: api,
-                    txt2img_defaults: default_txt2img(self.txt2img_defaults.unwrap_or_default()),
-                    img2img_defaults: default_img2img(self.img2img_defaults.unwrap_or_default()),
-                };
-
-                (Box::new(txt2img_api), Box::new(img2img_api))
-            }
+        let admin_users = self.admin_users.into_iter().map(ChatId).collect();
+
+        let client = self.tls_backend.client()?;
+
+        let pool_config = self.pool_config.unwrap_or_default();
+        let urls: Vec<String> = std::iter::once(self.sd_api_url)
+            .chain(pool_config.urls.iter().cloned())
+            .collect();
+
+        let comfyui = ComfyUIConfig {
+            txt2img_prompt_file: self.comfyui_txt2img_prompt_file,
+            img2img_prompt_file: self.comfyui_img2img_prompt_file,
+            fetch_concurrency: self.comfyui_fetch_concurrency,
+            seed_mode: self.comfyui_seed_mode,
+            headers: self.comfyui_headers,
+        };
+        let txt2img_defaults = default_txt2img(self.txt2img_defaults.unwrap_or_default());
+        let img2img_defaults = default_img2img(self.img2img_defaults.unwrap_or_default());
+        let settings = ApiSettings {
+            client: &client,
+            auth: self.backend_auth.as_ref(),
+            requests: &self.request_config,
+            txt2img_defaults: &txt2img_defaults,
+            img2img_defaults: &img2img_defaults,
+            upscale_config: &self.upscale_config,
         };
 
-        let parameters = ConfigParameters {
-            allowed_users,
+        let (txt2img_apis, img2img_apis, upscaler) =
+            build_apis(&self.api_type, urls, &comfyui, &settings).await?;
+
+        let (txt2img_api, img2img_api) =
+            pool_config.build(txt2img_apis, img2img_apis, &bot, &admin_users)?;
+
+        let backends = Arc::new(BackendHandles {
+            name: DEFAULT_BACKEND.to_owned(),
             txt2img_api,
             img2img_api,
-            allow_all_users: self.allow_all_users,
-        };
+            describer,
+            blank_check: self.blank_check_config,
+            upscaler,
+            jobs: Default::default(),
+            stats: Default::default(),
+            metrics: metrics.clone(),
+            defaults: ChatDefaults {
+                users: self.user_defaults,
+                chats: self.chat_defaults,
+            },
+        });
+
+        let mut others = Vec::with_capacity(self.backend_configs.len());
+        for config in self.backend_configs {
+            let (mut txt2img_apis, mut img2img_apis, upscaler) = build_apis(
+                &config.api_type,
+                vec![config.url],
+                &config.comfyui,
+                &settings,
+            )
+            .await
+            .with_context(|| format!("Failed to create backend {}", config.name))?;
+            let (Some(txt2img_api), Some(img2img_api)) = (txt2img_apis.pop(), img2img_apis.pop())
+            else {
+                return Err(anyhow!("Failed to create backend {}", config.name));
+            };
+            let handles =
+                backends.with_apis(config.name.clone(), txt2img_api, img2img_api, upscaler);
+            others.push((config.name, Arc::new(handles)));
+        }
+        let registry = BackendRegistry::new(backends.clone(), others)?;
+        let translations =
+            Translations::load(self.language.as_deref(), self.translations_dir.as_deref())?;
+
+        let parameters = ConfigParameters::new(
+            Arc::new(AuthConfig {
+                allowed_users,
+                allowed_chats,
+                banned_users,
+                admin_users,
+                allow_all_users: self.allow_all_users,
+                accounts,
+            }),
+            Arc::new(UiConfig {
+                prompt_policy: self.prompt_policy,
+                negative_presets: self.negative_presets,
+                styles: self.styles,
+                progress: self.progress_config,
+                unknown_command_mode: self.unknown_command_mode,
+                captions: self.caption_mode,
+                denoising_presets: self.denoising_presets,
+                size_presets_max_pixels: self.size_presets_max_pixels,
+                caption_template: self.caption_template,
+                group_mode: self.group_mode,
+                prompt_cleaning: self.prompt_cleaning_config,
+                translations: Arc::new(translations),
+                inline: self.inline_config.map(InlineGenerator::new),
+                zip: self.zip_config,
+                webhooks: Webhooks::new(self.webhook_configs, self.tls_backend)?,
+                quota: self.quota_config,
+                quiet_hours: self.quiet_hours_config,
+                reply_keyboard: self.reply_keyboard_config,
+                random: self.random_config,
+                guest: self.guest_config.map(GuestMode::new).transpose()?,
+            }),
+            backends,
+            Arc::new(registry),
+        );
+        if self
+            .http_config
+            .as_ref()
+            .is_some_and(|config| config.dashboard)
+        {
+            http = http.map(|http| http.merge(dashboard::router(parameters.backends.clone())));
+        }
+
+        // Admins' decisions at runtime take precedence over the configured users.
+        parameters
+            .auth
+            .apply_user_access(history.user_access().await?);
 
         Ok(StableDiffusionBot {
             bot,
             storage,
+            history,
             config: parameters,
+            http,
+            telegram_webhook: self
+                .telegram_webhook_config
+                .as_ref()
+                .map(TelegramWebhook::new)
+                .transpose()?,
+            metrics,
+            supervisor_config: self.supervisor_config,
+            retention_config: self.retention_config,
+            wait_for_backend_config: self.wait_for_backend_config,
+            _lock: lock.map(Arc::new),
         })
     }
 }
@@ -718,4 +1981,40 @@ mod tests {
             default_img2img(Img2ImgRequest::default())
         );
     }
+
+    #[tokio::test]
+    async fn test_stable_diffusion_bot_admin_users() {
+        let builder = StableDiffusionBotBuilder::new(
+            "api_key".to_string(),
+            vec![1, 2, 3],
+            "http://localhost:7860".to_string(),
+            ApiType::StableDiffusionWebUi,
+            false,
+        );
+
+        let bot = builder.admin_users(vec![1]).build().await.unwrap();
+
+        assert!(bot.config.auth.user_is_admin(&ChatId(1)));
+        assert!(!bot.config.auth.user_is_admin(&ChatId(2)));
+    }
+
+    #[test]
+    fn test_authorization_header() {
+        let basic = Auth::Basic {
+            username: "user".to_owned(),
+            password: "secret".to_owned(),
+        };
+        assert_eq!(
+            authorization_header(&basic).unwrap(),
+            "Basic dXNlcjpzZWNyZXQ="
+        );
+        let bearer = Auth::Bearer {
+            token: "token".to_owned(),
+        };
+        assert_eq!(authorization_header(&bearer).unwrap(), "Bearer token");
+        let invalid = Auth::Bearer {
+            token: "line\nbreak".to_owned(),
+        };
+        assert!(authorization_header(&invalid).is_err());
+    }
 }
@@ -0,0 +1,229 @@
+use sal_e_api::GenParams;
+use serde::{Deserialize, Serialize};
+
+use super::i18n::Translator;
+
+/// A banned substring, optionally with a custom message shown to the user when it is rejected.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum BannedWord {
+    /// A banned substring using the default rejection message.
+    Word(String),
+    /// A banned substring with a custom rejection message.
+    WithMessage {
+        /// The banned substring.
+        word: String,
+        /// The message shown to the user when a prompt contains `word`.
+        message: String,
+    },
+}
+
+impl BannedWord {
+    /// Returns the banned substring.
+    pub fn word(&self) -> &str {
+        match self {
+            BannedWord::Word(word) | BannedWord::WithMessage { word, .. } => word,
+        }
+    }
+
+    /// Returns the custom rejection message, if any.
+    pub fn message(&self) -> Option<&str> {
+        match self {
+            BannedWord::Word(_) => None,
+            BannedWord::WithMessage { message, .. } => Some(message),
+        }
+    }
+}
+
+/// Struct that represents the limits enforced on prompts and negative prompts.
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct PromptPolicy {
+    /// Maximum prompt length in characters. Unlimited if unset.
+    pub max_prompt_length: Option<usize>,
+    /// Substrings that may not appear in a prompt, matched case-insensitively.
+    #[serde(default)]
+    pub banned_words: Vec<BannedWord>,
+}
+
+/// Which part of a request a violation was found in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PromptKind {
+    Prompt,
+    NegativePrompt,
+}
+
+impl std::fmt::Display for PromptKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PromptKind::Prompt => write!(f, "prompt"),
+            PromptKind::NegativePrompt => write!(f, "negative prompt"),
+        }
+    }
+}
+
+/// A prompt rejected by the `PromptPolicy`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum PolicyViolation {
+    TooLong {
+        kind: PromptKind,
+        length: usize,
+        max: usize,
+    },
+    BannedWord {
+        kind: PromptKind,
+        word: BannedWord,
+    },
+}
+
+impl PolicyViolation {
+    /// Returns the message shown to the user.
+    pub fn user_message(&self, translator: &Translator) -> String {
+        match self {
+            PolicyViolation::TooLong { kind, length, max } => {
+                let (length, max) = (length.to_string(), max.to_string());
+                let args = [("length", length.as_str()), ("max", max.as_str())];
+                match kind {
+                    PromptKind::Prompt => translator.format(
+                        "Your prompt is too long ({length} characters, the limit is {max}).",
+                        &args,
+                    ),
+                    PromptKind::NegativePrompt => translator.format(
+                        "Your negative prompt is too long ({length} characters, the limit is \
+                        {max}).",
+                        &args,
+                    ),
+                }
+            }
+            PolicyViolation::BannedWord { kind, word } => word
+                .message()
+                .map(ToOwned::to_owned)
+                .unwrap_or_else(|| match kind {
+                    PromptKind::Prompt => translator.text("Your prompt contains a banned word."),
+                    PromptKind::NegativePrompt => {
+                        translator.text("Your negative prompt contains a banned word.")
+                    }
+                }),
+        }
+    }
+}
+
+impl std::fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyViolation::TooLong { kind, length, max } => {
+                write!(f, "{kind} too long: {length} > {max}")
+            }
+            PolicyViolation::BannedWord { kind, word } => {
+                write!(f, "{kind} contains banned word {:?}", word.word())
+            }
+        }
+    }
+}
+
+impl PromptPolicy {
+    /// Checks a single prompt against the policy.
+    pub(crate) fn check(&self, kind: PromptKind, prompt: &str) -> Result<(), PolicyViolation> {
+        let length = prompt.chars().count();
+        if let Some(max) = self.max_prompt_length.filter(|max| length > *max) {
+            return Err(PolicyViolation::TooLong { kind, length, max });
+        }
+        let prompt = prompt.to_lowercase();
+        if let Some(word) = self
+            .banned_words
+            .iter()
+            .find(|word| !word.word().is_empty() && prompt.contains(&word.word().to_lowercase()))
+        {
+            return Err(PolicyViolation::BannedWord {
+                kind,
+                word: word.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks the prompt and negative prompt of `params`, which should be the parameters that
+    /// are generated with, so text added by styles is checked too.
+    pub(crate) fn check_params(&self, params: &dyn GenParams) -> Result<(), PolicyViolation> {
+        self.check(PromptKind::Prompt, &params.prompt().unwrap_or_default())?;
+        self.check(
+            PromptKind::NegativePrompt,
+            &params.negative_prompt().unwrap_or_default(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> PromptPolicy {
+        PromptPolicy {
+            max_prompt_length: Some(20),
+            banned_words: vec![
+                BannedWord::Word("forbidden".to_owned()),
+                BannedWord::WithMessage {
+                    word: "Secret".to_owned(),
+                    message: "No secrets, please.".to_owned(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_prompt_policy_allows() {
+        assert!(policy().check(PromptKind::Prompt, "a cat").is_ok());
+        assert!(PromptPolicy::default()
+            .check(PromptKind::Prompt, &"a".repeat(1000))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_prompt_policy_too_long() {
+        assert_eq!(
+            policy().check(PromptKind::NegativePrompt, &"a".repeat(21)),
+            Err(PolicyViolation::TooLong {
+                kind: PromptKind::NegativePrompt,
+                length: 21,
+                max: 20
+            })
+        );
+    }
+
+    #[test]
+    fn test_prompt_policy_banned_words() {
+        let violation = policy()
+            .check(PromptKind::Prompt, "a FORBIDDEN cat")
+            .unwrap_err();
+        assert_eq!(
+            violation.user_message(&Translator::default()),
+            "Your prompt contains a banned word."
+        );
+
+        let violation = policy()
+            .check(PromptKind::NegativePrompt, "top secret")
+            .unwrap_err();
+        assert_eq!(
+            violation.user_message(&Translator::default()),
+            "No secrets, please."
+        );
+    }
+
+    #[test]
+    fn test_prompt_policy_deserialize() {
+        let policy: PromptPolicy = serde_json::from_str(
+            r#"{"max_prompt_length": 5, "banned_words": ["a", {"word": "b", "message": "c"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(policy.max_prompt_length, Some(5));
+        assert_eq!(
+            policy.banned_words,
+            vec![
+                BannedWord::Word("a".to_owned()),
+                BannedWord::WithMessage {
+                    word: "b".to_owned(),
+                    message: "c".to_owned()
+                }
+            ]
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use sal_e_api::{
+    BackendPool, BalanceStrategy, Img2ImgApi, OnPoolEvent, PoolBackend, PoolEvent, Txt2ImgApi,
+};
+use serde::{Deserialize, Serialize};
+use teloxide::prelude::*;
+use tracing::warn;
+
+/// Struct that represents the configuration for spreading requests over several backends of
+/// the same type, such as one backend per GPU.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PoolConfig {
+    /// URLs of the backends to use along with the main `sd_api_url`.
+    #[serde(default)]
+    pub urls: Vec<String>,
+    /// How to pick the backend for a request.
+    #[serde(default)]
+    pub strategy: BalanceStrategy,
+    /// How long to skip a backend after it fails a request, in seconds. Requests that fail
+    /// are retried on the next backend.
+    #[serde(default = "default_cooldown_secs")]
+    pub cooldown_secs: u64,
+    /// How many requests in a row a backend must fail before it's skipped.
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// Whether to message the admins when a backend is skipped and when it recovers.
+    #[serde(default = "default_notify_admins")]
+    pub notify_admins: bool,
+}
+
+fn default_cooldown_secs() -> u64 {
+    30
+}
+
+fn default_failure_threshold() -> u32 {
+    1
+}
+
+fn default_notify_admins() -> bool {
+    true
+}
+
+/// Returns the message sent to the admins when the availability of a backend changes.
+fn event_text(kind: &str, event: PoolEvent) -> String {
+    match event {
+        PoolEvent::Unavailable { backend, failures } => format!(
+            "⚠️ {kind} backend {} failed {failures} requests in a row. Requests are sent to \
+             the other backends until it passes a health check.",
+            backend + 1
+        ),
+        PoolEvent::Recovered { backend } => format!(
+            "✅ {kind} backend {} passed a health check and is back in use.",
+            backend + 1
+        ),
+    }
+}
+
+/// Returns a listener that messages `admins` about changes in the availability of the
+/// backends in the `kind` pool.
+fn notify_admins(bot: Bot, admins: Vec<ChatId>, kind: &'static str) -> Arc<OnPoolEvent> {
+    Arc::new(move |event| {
+        let bot = bot.clone();
+        let admins = admins.clone();
+        let text = event_text(kind, event);
+        tokio::spawn(async move {
+            for admin in admins {
+                if let Err(e) = bot.send_message(admin, &text).await {
+                    warn!("Failed to notify admin {} about backend: {:?}", admin, e);
+                }
+            }
+        });
+    })
+}
+
+impl PoolConfig {
+    /// Combines the backends into pools, or returns them as they are if there is only one.
+    /// Changes in the availability of pooled backends are reported to `admins` with `bot`.
+    pub(crate) fn build(
+        &self,
+        mut txt2img_apis: Vec<Box<dyn Txt2ImgApi>>,
+        mut img2img_apis: Vec<Box<dyn Img2ImgApi>>,
+        bot: &Bot,
+        admins: &HashSet<ChatId>,
+    ) -> anyhow::Result<(Box<dyn Txt2ImgApi>, Box<dyn Img2ImgApi>)> {
+        if txt2img_apis.len() == 1 && img2img_apis.len() == 1 {
+            return Ok((txt2img_apis.remove(0), img2img_apis.remove(0)));
+        }
+        let admins: Vec<ChatId> = admins.iter().copied().collect();
+        let listener = |kind| {
+            (self.notify_admins && !admins.is_empty())
+                .then(|| notify_admins(bot.clone(), admins.clone(), kind))
+        };
+        Ok((
+            Box::new(self.pool(txt2img_apis, listener("txt2img"))?),
+            Box::new(self.pool(img2img_apis, listener("img2img"))?),
+        ))
+    }
+
+    fn pool<T: PoolBackend>(
+        &self,
+        backends: Vec<T>,
+        listener: Option<Arc<OnPoolEvent>>,
+    ) -> anyhow::Result<BackendPool<T>> {
+        let pool = BackendPool::new(backends, self.strategy)?
+            .with_cooldown(Duration::from_secs(self.cooldown_secs))
+            .with_failure_threshold(self.failure_threshold);
+        Ok(match listener {
+            Some(listener) => pool.with_listener(listener),
+            None => pool,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_text() {
+        assert_eq!(
+            event_text(
+                "txt2img",
+                PoolEvent::Unavailable {
+                    backend: 0,
+                    failures: 3
+                }
+            ),
+            "⚠️ txt2img backend 1 failed 3 requests in a row. Requests are sent to the other \
+             backends until it passes a health check."
+        );
+        assert_eq!(
+            event_text("img2img", PoolEvent::Recovered { backend: 1 }),
+            "✅ img2img backend 2 passed a health check and is back in use."
+        );
+    }
+}
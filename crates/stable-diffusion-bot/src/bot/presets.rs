@@ -0,0 +1,86 @@
+use itertools::Itertools as _;
+use sal_e_api::GenParams;
+use serde::{Deserialize, Serialize};
+use teloxide::types::ChatId;
+use tracing::warn;
+
+use super::history::HistoryStore;
+
+/// Struct that represents a negative prompt preset that users can toggle in the settings menu.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NegativePreset {
+    /// Name shown on the settings button. Toggles are stored by name, so renaming a preset
+    /// disables it in every chat.
+    pub name: String,
+    /// Text appended to the user's negative prompt while the preset is enabled.
+    pub prompt: String,
+}
+
+/// Returns the names of the presets enabled in a chat. Failures are logged and treated as no
+/// presets being enabled, so generation can continue.
+pub(crate) async fn enabled_preset_names(history: &HistoryStore, chat_id: ChatId) -> Vec<String> {
+    history.negative_presets(chat_id).await.unwrap_or_else(|e| {
+        warn!("Failed to get negative prompt presets: {:?}", e);
+        Vec::new()
+    })
+}
+
+/// Returns the prompts of the presets in `presets` that are enabled in a chat.
+pub(crate) async fn enabled_preset_prompts(
+    presets: &[NegativePreset],
+    history: &HistoryStore,
+    chat_id: ChatId,
+) -> Vec<String> {
+    if presets.is_empty() {
+        return Vec::new();
+    }
+    let enabled = enabled_preset_names(history, chat_id).await;
+    presets
+        .iter()
+        .filter(|preset| enabled.contains(&preset.name))
+        .map(|preset| preset.prompt.clone())
+        .collect()
+}
+
+/// Appends `prompts` to a negative prompt, skipping empty parts.
+fn combine_negative_prompt(negative_prompt: Option<&str>, prompts: &[String]) -> String {
+    negative_prompt
+        .into_iter()
+        .chain(prompts.iter().map(String::as_str))
+        .map(str::trim)
+        .filter(|prompt| !prompt.is_empty())
+        .join(", ")
+}
+
+/// Returns a copy of `params` with `prompts` appended to its negative prompt, leaving the
+/// user's own negative prompt unchanged.
+pub(crate) fn with_negative_presets<'a>(
+    params: &(dyn GenParams + 'a),
+    prompts: &[String],
+) -> Box<dyn GenParams + 'a> {
+    let mut params = dyn_clone::clone_box(params);
+    if !prompts.is_empty() {
+        let negative_prompt = combine_negative_prompt(params.negative_prompt().as_deref(), prompts);
+        params.set_negative_prompt(negative_prompt);
+    }
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_combine_negative_prompt() {
+        let prompts = vec!["lowres, blurry".to_owned(), " text ".to_owned()];
+        assert_eq!(
+            combine_negative_prompt(Some("cats"), &prompts),
+            "cats, lowres, blurry, text"
+        );
+        assert_eq!(
+            combine_negative_prompt(Some(" "), &prompts),
+            "lowres, blurry, text"
+        );
+        assert_eq!(combine_negative_prompt(None, &[]), "");
+    }
+}
@@ -0,0 +1,880 @@
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    pin::pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use sal_e_api::{GenerationProgress, Img2ImgApiError, Txt2ImgApi, Txt2ImgApiError};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    payloads::setters::*,
+    prelude::*,
+    types::{ChatAction, InlineKeyboardButton, InlineKeyboardMarkup, MessageId},
+    ApiError, RequestError,
+};
+use tokio::{sync::oneshot, time::Instant};
+use tracing::warn;
+
+use super::{
+    censor::BlockedByBackend,
+    config::BackendHandles,
+    dashboard::{GenerationKind, Outcome},
+    i18n::Translator,
+    send::SendContext,
+};
+
+/// Shortest allowed time between edits of the placeholder message, to stay well within
+/// Telegram's rate limits.
+const MIN_UPDATE_INTERVAL_SECS: u64 = 3;
+
+/// How often the "sending photo" chat action is repeated while a request runs. Telegram shows
+/// it for at most 5 seconds.
+const CHAT_ACTION_INTERVAL: Duration = Duration::from_secs(4);
+
+/// Struct that represents the configuration for reporting the progress of generations.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProgressConfig {
+    /// How often to update the placeholder message shown while an image is generated, in
+    /// seconds. Telegram limits how often messages can be edited, especially in groups.
+    #[serde(default = "default_update_interval_secs")]
+    pub update_interval_secs: u64,
+    /// Maximum time to spend on a request, from queueing the generation to sending the
+    /// images, in seconds. If unset, requests may take as long as the backend needs.
+    pub timeout_secs: Option<u64>,
+    /// Cancels requests whose prompt message is deleted before the images are sent. Telegram
+    /// doesn't tell bots about deleted messages, so each update of the placeholder also checks
+    /// whether the prompt message still exists.
+    #[serde(default)]
+    pub cancel_on_delete: bool,
+    /// Replaces the placeholder with how long the request spent in the queue, generating and
+    /// uploading the images, instead of deleting it, to tell slow backends from a slow network.
+    #[serde(default)]
+    pub show_timings: bool,
+}
+
+fn default_update_interval_secs() -> u64 {
+    5
+}
+
+impl Default for ProgressConfig {
+    fn default() -> Self {
+        Self {
+            update_interval_secs: default_update_interval_secs(),
+            timeout_secs: None,
+            cancel_on_delete: false,
+            show_timings: false,
+        }
+    }
+}
+
+/// Keeps the latest progress reported by the backend while a request runs, so the placeholder
+/// message can show it, and when the phases of the request ended.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ProgressTracker(Arc<Mutex<TrackerState>>);
+
+#[derive(Debug, Default)]
+struct TrackerState {
+    progress: Option<GenerationProgress>,
+    /// When the backend first reported progress.
+    started_at: Option<Instant>,
+    /// When the backend returned the images.
+    generated_at: Option<Instant>,
+}
+
+impl ProgressTracker {
+    /// Records the progress of the generation.
+    pub fn report(&self, progress: GenerationProgress) {
+        let mut state = self.0.lock().unwrap();
+        state.progress = Some(progress);
+        state.started_at.get_or_insert_with(Instant::now);
+    }
+
+    /// Records that the backend returned the images, so the rest of the request is spent
+    /// uploading them.
+    pub fn generated(&self) {
+        self.0.lock().unwrap().generated_at = Some(Instant::now());
+    }
+
+    /// Returns the latest progress the backend reported for the generation.
+    fn progress(&self) -> Option<GenerationProgress> {
+        self.0.lock().unwrap().progress
+    }
+
+    /// Returns whether the backend has reported any progress, meaning it's working on the
+    /// generation.
+    fn started(&self) -> bool {
+        self.0.lock().unwrap().progress.is_some()
+    }
+
+    /// Returns how long the phases of a request from `start` to `end` took, if the images
+    /// were generated.
+    fn timings(&self, start: Instant, end: Instant) -> Option<Timings> {
+        let state = self.0.lock().unwrap();
+        let generated_at = state.generated_at?;
+        let started_at = state
+            .started_at
+            .filter(|&started_at| started_at <= generated_at);
+        Some(Timings {
+            queue: started_at.map(|started_at| started_at.saturating_duration_since(start)),
+            generation: generated_at.saturating_duration_since(started_at.unwrap_or(start)),
+            upload: end.saturating_duration_since(generated_at),
+        })
+    }
+}
+
+/// How long the phases of a request took.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Timings {
+    /// Time until the backend first reported progress, if it did. Otherwise waiting is counted
+    /// as generating.
+    pub queue: Option<Duration>,
+    /// Time the backend spent generating the images.
+    pub generation: Duration,
+    /// Time spent sending the images to Telegram.
+    pub upload: Duration,
+}
+
+impl Timings {
+    /// Returns the text replacing the placeholder message.
+    fn text(&self, translator: &Translator) -> String {
+        let secs = |duration: Duration| format!("{:.1}s", duration.as_secs_f32());
+        translator.format(
+            "⏱ Queue: {queue}, generation: {generation}, upload: {upload}",
+            &[
+                ("queue", &self.queue.map_or_else(|| "?".to_owned(), secs)),
+                ("generation", &secs(self.generation)),
+                ("upload", &secs(self.upload)),
+            ],
+        )
+    }
+}
+
+/// A generation in flight.
+#[derive(Debug)]
+struct Job {
+    chat_id: ChatId,
+    user_id: Option<UserId>,
+    /// Taken when the generation is cancelled.
+    cancel: Option<oneshot::Sender<()>>,
+}
+
+/// Generations in flight, so they can be cancelled and aren't started twice.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Jobs {
+    next_id: Arc<AtomicU64>,
+    jobs: Arc<Mutex<HashMap<u64, Job>>>,
+    /// Fingerprints of the requests in flight in each chat, see `GenParams::fingerprint`.
+    requests: Arc<Mutex<HashSet<(ChatId, String)>>>,
+}
+
+impl Jobs {
+    /// Registers a generation requested by `user_id` in `chat_id`. Returns a guard that
+    /// removes the generation when dropped, and a receiver that resolves if it's cancelled.
+    fn start(&self, chat_id: ChatId, user_id: Option<UserId>) -> (JobGuard, oneshot::Receiver<()>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (cancel, cancelled) = oneshot::channel();
+        self.jobs.lock().unwrap().insert(
+            id,
+            Job {
+                chat_id,
+                user_id,
+                cancel: Some(cancel),
+            },
+        );
+        (
+            JobGuard {
+                jobs: self.clone(),
+                id,
+            },
+            cancelled,
+        )
+    }
+
+    /// Claims the request with `fingerprint` in `chat_id` until the returned guard is dropped.
+    /// Returns `None` if the same request is already in flight there, e.g. because a prompt was
+    /// sent twice by accident.
+    pub fn claim(&self, chat_id: ChatId, fingerprint: &str) -> Option<RequestGuard> {
+        let key = (chat_id, fingerprint.to_owned());
+        self.requests
+            .lock()
+            .unwrap()
+            .insert(key.clone())
+            .then(|| RequestGuard {
+                jobs: self.clone(),
+                key,
+            })
+    }
+
+    /// Returns the number of generations in flight.
+    pub fn len(&self) -> usize {
+        self.jobs.lock().unwrap().len()
+    }
+
+    /// Cancels generation `id` if `user_id` requested it. Returns whether it was cancelled.
+    pub fn cancel(&self, id: u64, user_id: UserId) -> bool {
+        let mut jobs = self.jobs.lock().unwrap();
+        match jobs.get_mut(&id) {
+            Some(job) if job.user_id == Some(user_id) => cancel_job(job),
+            _ => false,
+        }
+    }
+
+    /// Cancels the most recent generation `user_id` requested in `chat_id`. Returns whether
+    /// one was cancelled.
+    pub fn cancel_latest(&self, chat_id: ChatId, user_id: UserId) -> bool {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.iter_mut()
+            .filter(|(_, job)| {
+                job.chat_id == chat_id && job.user_id == Some(user_id) && job.cancel.is_some()
+            })
+            .max_by_key(|(&id, _)| id)
+            .is_some_and(|(_, job)| cancel_job(job))
+    }
+}
+
+fn cancel_job(job: &mut Job) -> bool {
+    job.cancel
+        .take()
+        .is_some_and(|cancel| cancel.send(()).is_ok())
+}
+
+/// Removes a generation from `Jobs` when it completes, fails or is cancelled.
+struct JobGuard {
+    jobs: Jobs,
+    id: u64,
+}
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        self.jobs.jobs.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Releases a request claimed with `Jobs::claim` when dropped.
+#[derive(Debug)]
+pub(crate) struct RequestGuard {
+    jobs: Jobs,
+    key: (ChatId, String),
+}
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        self.jobs.requests.lock().unwrap().remove(&self.key);
+    }
+}
+
+/// Returns the keyboard shown on the placeholder message of generation `id`.
+fn cancel_keyboard(id: u64, translator: &Translator) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([[InlineKeyboardButton::callback(
+        translator.text("❌ Cancel"),
+        format!("cancel/{id}"),
+    )]])
+}
+
+/// Parses the data of a cancel button into the id of the generation to cancel.
+pub(crate) fn parse_cancel_button(data: &str) -> Option<u64> {
+    data.strip_prefix("cancel/")?.parse().ok()
+}
+
+/// Formats a duration as minutes and seconds, e.g. `1:05`.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+/// Returns the text of the placeholder message.
+fn status_text(
+    elapsed: Duration,
+    timeout: Option<Duration>,
+    queue_length: Option<u64>,
+    progress: Option<GenerationProgress>,
+    translator: &Translator,
+) -> String {
+    let mut parts = Vec::new();
+    if let Some(percent) = progress.and_then(|progress| progress.percent()) {
+        parts.push(format!("{percent}%"));
+    }
+    if let Some(eta) = progress.and_then(|progress| progress.eta) {
+        parts.push(translator.format("about {eta} to go", &[("eta", &format_duration(eta))]));
+    }
+    parts.push(translator.format(
+        "{elapsed} elapsed",
+        &[("elapsed", &format_duration(elapsed))],
+    ));
+    if let Some(timeout) = timeout {
+        parts.push(translator.format(
+            "{left} left",
+            &[("left", &format_duration(timeout.saturating_sub(elapsed)))],
+        ));
+    }
+    let mut text = format!("{} {}", translator.text("⏳ Generating…"), parts.join(", "));
+    // The queue includes this request, so only mention it when others are waiting.
+    if let Some(length) = queue_length.filter(|&length| length > 1) {
+        text.push('\n');
+        text.push_str(&translator.format(
+            "{length} jobs in the queue",
+            &[("length", &length.to_string())],
+        ));
+    }
+    text
+}
+
+/// Periodically edits the placeholder message with the elapsed time, the progress reported by
+/// the backend and the length of its queue. Never returns.
+#[allow(clippy::too_many_arguments)]
+async fn update_placeholder(
+    bot: Bot,
+    api: Box<dyn Txt2ImgApi>,
+    tracker: ProgressTracker,
+    chat_id: ChatId,
+    message_id: MessageId,
+    keyboard: InlineKeyboardMarkup,
+    interval: Duration,
+    timeout: Option<Duration>,
+    start: Instant,
+    translator: Translator,
+) {
+    let mut interval = tokio::time::interval_at(start + interval, interval);
+    loop {
+        interval.tick().await;
+        // Not every backend reports its queue, so failures here aren't worth logging.
+        let queue_length = api.queue_length().await.ok();
+        let text = status_text(
+            start.elapsed(),
+            timeout,
+            queue_length,
+            tracker.progress(),
+            &translator,
+        );
+        if let Err(e) = bot
+            .edit_message_text(chat_id, message_id, text)
+            .reply_markup(keyboard.clone())
+            .await
+        {
+            warn!("Failed to update progress message: {}", e);
+        }
+    }
+}
+
+/// Shows that a photo is being sent in the chat until aborted, so the bot doesn't look idle
+/// during long generations. Stops at the first failure, since the chat action is cosmetic.
+async fn chat_action_heartbeat(bot: Bot, context: SendContext) {
+    let mut interval = tokio::time::interval(CHAT_ACTION_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = context
+            .send_chat_action(&bot, ChatAction::UploadPhoto)
+            .await
+        {
+            warn!("Failed to send chat action: {}", e);
+            return;
+        }
+    }
+}
+
+/// Checks whether the message `message_id` was deleted. Bots can't read messages, but editing
+/// a message fails differently depending on whether it exists, so an edit that can never
+/// succeed tells them apart without changing anything.
+async fn is_deleted(bot: &Bot, chat_id: ChatId, message_id: MessageId) -> bool {
+    matches!(
+        bot.edit_message_reply_markup(chat_id, message_id).await,
+        Err(RequestError::Api(ApiError::MessageToEditNotFound))
+    )
+}
+
+/// Returns once the prompt message `msg` is deleted, checking every `interval`.
+async fn watch_deleted(bot: Bot, msg: Message, interval: Duration) {
+    let mut interval = tokio::time::interval_at(Instant::now() + interval, interval);
+    loop {
+        interval.tick().await;
+        if is_deleted(&bot, msg.chat.id, msg.id).await {
+            return;
+        }
+    }
+}
+
+/// Checks whether sending the images failed because the prompt message they reply to was
+/// deleted.
+fn is_reply_to_deleted(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<RequestError>(),
+        Some(RequestError::Api(ApiError::MessageToReplyNotFound))
+    )
+}
+
+/// Why a request stopped before it completed.
+enum Stopped {
+    TimedOut,
+    Cancelled,
+    /// The prompt message was deleted.
+    Deleted,
+}
+
+/// Interrupts the generation the backend is running for a cancelled request. Backends run
+/// generations for every client one at a time, so this is only done when the backend must be
+/// working on this request: either it reported progress for it, or no other requests are in
+/// flight and the backend has nothing else queued.
+async fn interrupt_cancelled(backends: &BackendHandles, tracker: &ProgressTracker) {
+    let working_on_request = tracker.started()
+        || (backends.jobs.len() == 1
+            && backends
+                .txt2img_api
+                .queue_length()
+                .await
+                .map_or(true, |length| length <= 1));
+    if !working_on_request {
+        return;
+    }
+    // The request may have been sent to either endpoint, which may be pools of several
+    // backends, and pools only interrupt backends they have requests in flight on.
+    let (txt2img, img2img) = tokio::join!(
+        backends.txt2img_api.interrupt(),
+        backends.img2img_api.interrupt()
+    );
+    if let Err(e) = txt2img.and(img2img) {
+        warn!("Failed to interrupt cancelled generation: {:?}", e);
+    }
+}
+
+/// Runs `request` while showing its progress in a placeholder message replying to `msg`. The
+/// placeholder is deleted once the request completes, or replaced with the reason it failed if
+/// the backend gave one. If the request takes longer than the
+/// configured timeout, it's cancelled and the placeholder is replaced with an error. Progress
+/// reported to `tracker` while the request runs is shown in the placeholder.
+///
+/// The placeholder has a button to cancel the request, which can also be cancelled with
+/// `Jobs::cancel_latest`. The backend is interrupted if it's working on a cancelled request.
+/// Until the request stops, the chat shows that a photo is being sent. How the request ended
+/// is recorded for the dashboard as a generation of `kind`. Failures are explained in the
+/// language of `translator`.
+///
+/// If `msg` is deleted while the request runs and the configuration asks for it, the request is
+/// cancelled and the placeholder deleted, so nothing replies to the deleted prompt.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn with_progress<T>(
+    bot: &Bot,
+    backends: &BackendHandles,
+    config: &ProgressConfig,
+    msg: &Message,
+    tracker: &ProgressTracker,
+    kind: GenerationKind<'_>,
+    translator: &Translator,
+    request: impl Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    let start = Instant::now();
+    let timeout = config.timeout_secs.map(Duration::from_secs);
+    let interval = Duration::from_secs(config.update_interval_secs.max(MIN_UPDATE_INTERVAL_SECS));
+
+    let (job, cancelled) = backends
+        .jobs
+        .start(msg.chat.id, msg.from().map(|user| user.id));
+    let keyboard = cancel_keyboard(job.id, translator);
+    let heartbeat = tokio::spawn(chat_action_heartbeat(bot.clone(), SendContext::of(msg)));
+
+    let placeholder = match SendContext::of(msg)
+        .send_message(
+            bot,
+            status_text(Duration::ZERO, timeout, None, None, translator),
+        )
+        .reply_to_message_id(msg.id)
+        .reply_markup(keyboard.clone())
+        .await
+    {
+        Ok(placeholder) => Some(placeholder),
+        Err(e) => {
+            warn!("Failed to send progress message: {}", e);
+            None
+        }
+    };
+    let updates = placeholder.as_ref().map(|placeholder| {
+        tokio::spawn(update_placeholder(
+            bot.clone(),
+            backends.txt2img_api.clone(),
+            tracker.clone(),
+            placeholder.chat.id,
+            placeholder.id,
+            keyboard,
+            interval,
+            timeout,
+            start,
+            translator.clone(),
+        ))
+    });
+
+    let mut request = pin!(async {
+        match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, request)
+                .await
+                .map_err(|_| Stopped::TimedOut),
+            None => Ok(request.await),
+        }
+    });
+    let deleted = async {
+        match config.cancel_on_delete {
+            true => watch_deleted(bot.clone(), msg.clone(), interval).await,
+            false => std::future::pending().await,
+        }
+    };
+    let result = tokio::select! {
+        result = &mut request => match result {
+            Ok(Err(e)) if config.cancel_on_delete && is_reply_to_deleted(&e) => Err(Stopped::Deleted),
+            result => result,
+        },
+        Ok(()) = cancelled => {
+            // Interrupt before the request is dropped, while pools still know which backends
+            // it's running on.
+            interrupt_cancelled(backends, tracker).await;
+            Err(Stopped::Cancelled)
+        }
+        () = deleted => {
+            interrupt_cancelled(backends, tracker).await;
+            Err(Stopped::Deleted)
+        }
+    };
+    let timings = tracker.timings(start, Instant::now());
+    drop(job);
+    heartbeat.abort();
+
+    let outcome = match &result {
+        Ok(Ok(_)) => Outcome::Succeeded,
+        Ok(Err(e)) if e.is::<BlockedByBackend>() => Outcome::Blocked,
+        Ok(Err(_)) => Outcome::Failed,
+        Err(Stopped::TimedOut) => Outcome::TimedOut,
+        Err(Stopped::Cancelled | Stopped::Deleted) => Outcome::Cancelled,
+    };
+    backends
+        .stats
+        .record(msg.chat.id, kind, start.elapsed(), outcome);
+    if let (Some(timings), Outcome::Succeeded) = (timings, outcome) {
+        backends.metrics.record_timings(&timings);
+    }
+
+    if let Some(updates) = updates {
+        updates.abort();
+    }
+
+    let result = match result {
+        Ok(result) => result,
+        Err(stopped) => {
+            let (text, error) = match stopped {
+                Stopped::TimedOut => (
+                    Some(translator.format(
+                        "Sorry, generating took longer than {duration}.",
+                        &[("duration", &format_duration(start.elapsed()))],
+                    )),
+                    "Request timed out",
+                ),
+                Stopped::Cancelled => (
+                    Some(translator.text("❌ Cancelled.")),
+                    "Request was cancelled",
+                ),
+                // Nothing may reply to a deleted prompt, so the placeholder is just removed.
+                Stopped::Deleted => (None, "Prompt message was deleted"),
+            };
+            match (placeholder, text) {
+                (Some(placeholder), Some(text)) => {
+                    bot.edit_message_text(placeholder.chat.id, placeholder.id, text)
+                        .await?;
+                }
+                (Some(placeholder), None) => {
+                    if let Err(e) = bot
+                        .delete_message(placeholder.chat.id, placeholder.id)
+                        .await
+                    {
+                        warn!("Failed to delete progress message: {}", e);
+                    }
+                }
+                (None, Some(text)) => {
+                    SendContext::of(msg)
+                        .send_message(bot, text)
+                        .reply_to_message_id(msg.id)
+                        .await?;
+                }
+                (None, None) => {}
+            }
+            return Err(anyhow!(error));
+        }
+    };
+
+    // Failures the backend explained replace the placeholder, so the user knows what to do.
+    let failure = result
+        .as_ref()
+        .err()
+        .and_then(|error| failure_text(error, translator));
+    match (placeholder, failure) {
+        (Some(placeholder), Some(text)) => {
+            if let Err(e) = bot
+                .edit_message_text(placeholder.chat.id, placeholder.id, text)
+                .await
+            {
+                warn!("Failed to show generation failure: {}", e);
+            }
+        }
+        (None, Some(text)) => {
+            if let Err(e) = SendContext::of(msg)
+                .send_message(bot, text)
+                .reply_to_message_id(msg.id)
+                .await
+            {
+                warn!("Failed to show generation failure: {}", e);
+            }
+        }
+        (Some(placeholder), None) => match timings.filter(|_| config.show_timings) {
+            Some(timings) => {
+                if let Err(e) = bot
+                    .edit_message_text(
+                        placeholder.chat.id,
+                        placeholder.id,
+                        timings.text(translator),
+                    )
+                    .await
+                {
+                    warn!("Failed to show generation timings: {}", e);
+                }
+            }
+            None => {
+                if let Err(e) = bot
+                    .delete_message(placeholder.chat.id, placeholder.id)
+                    .await
+                {
+                    warn!("Failed to delete progress message: {}", e);
+                }
+            }
+        },
+        (None, None) => {}
+    }
+
+    result
+}
+
+const UNREACHABLE_TEXT: &str =
+    "Sorry, the image generator can't be reached. Please try again later.";
+const INVALID_PARAMETERS_TEXT: &str =
+    "Sorry, the image generator rejected your settings. Check them with /settings.";
+const SERVER_TEXT: &str = "Sorry, the image generator failed to generate your image.";
+const BLOCKED_TEXT: &str =
+    "Sorry, the image generator's content filter blocked your image. Try another prompt.";
+
+/// Returns a message telling the user why a generation failed with `error`, translated by
+/// `translator`, if the backend gave a reason they can act on.
+pub(crate) fn failure_text(error: &anyhow::Error, translator: &Translator) -> Option<String> {
+    failure_reason(error).map(|text| translator.text(text))
+}
+
+fn failure_reason(error: &anyhow::Error) -> Option<&'static str> {
+    if error.is::<BlockedByBackend>() {
+        return Some(BLOCKED_TEXT);
+    }
+    if let Some(e) = error.downcast_ref::<Txt2ImgApiError>() {
+        return match e {
+            Txt2ImgApiError::Unreachable(_) => Some(UNREACHABLE_TEXT),
+            Txt2ImgApiError::InvalidParameters(_) => Some(INVALID_PARAMETERS_TEXT),
+            Txt2ImgApiError::Server(_) => Some(SERVER_TEXT),
+            _ => None,
+        };
+    }
+    match error.downcast_ref::<Img2ImgApiError>()? {
+        Img2ImgApiError::Unreachable(_) => Some(UNREACHABLE_TEXT),
+        Img2ImgApiError::InvalidParameters(_) => Some(INVALID_PARAMETERS_TEXT),
+        Img2ImgApiError::Server(_) => Some(SERVER_TEXT),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failure_text() {
+        let error = anyhow::Error::new(Txt2ImgApiError::Unreachable(anyhow!("refused")));
+        assert_eq!(failure_reason(&error), Some(UNREACHABLE_TEXT));
+        let error = anyhow::Error::new(Img2ImgApiError::InvalidParameters(anyhow!("422")))
+            .context("Failed to run img2img");
+        assert_eq!(failure_reason(&error), Some(INVALID_PARAMETERS_TEXT));
+        let error = anyhow::Error::new(Txt2ImgApiError::EmptyPrompt);
+        assert_eq!(failure_reason(&error), None);
+        assert_eq!(failure_reason(&anyhow!("Failed to send photo")), None);
+
+        let translations = Arc::new(crate::bot::i18n::Translations::load(None, None).unwrap());
+        let german = Translator::new(translations, Some("de".to_owned()));
+        let error = anyhow::Error::new(Txt2ImgApiError::Server(anyhow!("500")));
+        assert_eq!(
+            failure_text(&error, &german).as_deref(),
+            Some("Der Bildgenerator konnte dein Bild leider nicht generieren.")
+        );
+    }
+
+    #[test]
+    fn test_is_reply_to_deleted() {
+        let error = anyhow::Error::new(RequestError::Api(ApiError::MessageToReplyNotFound))
+            .context("Failed to send photo");
+        assert!(is_reply_to_deleted(&error));
+        let error = anyhow::Error::new(RequestError::Api(ApiError::MessageNotModified));
+        assert!(!is_reply_to_deleted(&error));
+        assert!(!is_reply_to_deleted(&anyhow!("Failed to send photo")));
+    }
+
+    #[test]
+    fn test_timings() {
+        let tracker = ProgressTracker::default();
+        let start = Instant::now();
+        assert_eq!(tracker.timings(start, start), None);
+
+        tracker.generated();
+        assert_eq!(tracker.timings(start, Instant::now()).unwrap().queue, None);
+
+        let tracker = ProgressTracker::default();
+        tracker.report(GenerationProgress {
+            value: 1,
+            max: 2,
+            eta: None,
+        });
+        tracker.generated();
+        assert!(tracker
+            .timings(start, Instant::now())
+            .unwrap()
+            .queue
+            .is_some());
+
+        let timings = Timings {
+            queue: None,
+            generation: Duration::from_millis(14_200),
+            upload: Duration::from_millis(1_500),
+        };
+        assert_eq!(
+            timings.text(&Translator::default()),
+            "⏱ Queue: ?, generation: 14.2s, upload: 1.5s"
+        );
+    }
+
+    #[test]
+    fn test_status_text() {
+        let translator = Translator::default();
+        assert_eq!(
+            status_text(Duration::from_secs(65), None, None, None, &translator),
+            "⏳ Generating… 1:05 elapsed"
+        );
+        assert_eq!(
+            status_text(
+                Duration::from_secs(12),
+                Some(Duration::from_secs(300)),
+                Some(3),
+                None,
+                &translator
+            ),
+            "⏳ Generating… 0:12 elapsed, 4:48 left\n3 jobs in the queue"
+        );
+        assert_eq!(
+            status_text(Duration::from_secs(0), None, Some(1), None, &translator),
+            "⏳ Generating… 0:00 elapsed"
+        );
+        let progress = GenerationProgress {
+            value: 9,
+            max: 20,
+            eta: None,
+        };
+        assert_eq!(
+            status_text(
+                Duration::from_secs(20),
+                None,
+                None,
+                Some(progress),
+                &translator
+            ),
+            "⏳ Generating… 45%, 0:20 elapsed"
+        );
+        let progress = GenerationProgress {
+            eta: Some(Duration::from_secs(75)),
+            ..progress
+        };
+        assert_eq!(
+            status_text(
+                Duration::from_secs(20),
+                None,
+                None,
+                Some(progress),
+                &translator
+            ),
+            "⏳ Generating… 45%, about 1:15 to go, 0:20 elapsed"
+        );
+        let unknown = GenerationProgress {
+            value: 0,
+            max: 0,
+            eta: None,
+        };
+        assert_eq!(
+            status_text(
+                Duration::from_secs(20),
+                None,
+                None,
+                Some(unknown),
+                &translator
+            ),
+            "⏳ Generating… 0:20 elapsed"
+        );
+    }
+
+    #[test]
+    fn test_progress_tracker() {
+        let tracker = ProgressTracker::default();
+        assert_eq!(tracker.progress(), None);
+        assert!(!tracker.started());
+        let progress = GenerationProgress {
+            value: 9,
+            max: 20,
+            eta: None,
+        };
+        tracker.clone().report(progress);
+        assert_eq!(tracker.progress(), Some(progress));
+        assert!(tracker.started());
+    }
+
+    #[test]
+    fn test_jobs_cancel() {
+        let jobs = Jobs::default();
+        let (chat, user, other) = (ChatId(1), UserId(2), UserId(3));
+        let (first, mut first_cancelled) = jobs.start(chat, Some(user));
+        let (second, mut second_cancelled) = jobs.start(chat, Some(user));
+        assert_eq!(jobs.len(), 2);
+
+        // Only the user who requested a generation can cancel it.
+        assert!(!jobs.cancel(first.id, other));
+        assert!(!jobs.cancel_latest(chat, other));
+        assert!(jobs.cancel(first.id, user));
+        assert!(!jobs.cancel(first.id, user));
+        assert_eq!(first_cancelled.try_recv(), Ok(()));
+
+        // The latest generation that hasn't been cancelled yet is cancelled.
+        assert!(jobs.cancel_latest(chat, user));
+        assert_eq!(second_cancelled.try_recv(), Ok(()));
+        assert!(!jobs.cancel_latest(chat, user));
+
+        drop((first, second));
+        assert_eq!(jobs.len(), 0);
+    }
+
+    #[test]
+    fn test_jobs_claim() {
+        let jobs = Jobs::default();
+        let first = jobs.claim(ChatId(1), "abc").unwrap();
+        assert!(jobs.claim(ChatId(1), "abc").is_none());
+        assert!(jobs.claim(ChatId(2), "abc").is_some());
+        assert!(jobs.claim(ChatId(1), "def").is_some());
+        drop(first);
+        assert!(jobs.claim(ChatId(1), "abc").is_some());
+    }
+
+    #[test]
+    fn test_parse_cancel_button() {
+        assert_eq!(parse_cancel_button("cancel/7"), Some(7));
+        assert_eq!(parse_cancel_button("cancel/"), None);
+        assert_eq!(parse_cancel_button("upscale/2"), None);
+    }
+}
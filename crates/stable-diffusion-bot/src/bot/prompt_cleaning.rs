@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+use teloxide::types::{Message, MessageEntityKind};
+
+/// Struct that represents the configuration for cleaning prompts copied from other chats, using
+/// the entities Telegram marks in messages.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PromptCleaningConfig {
+    /// Removes mentions and commands at the start of prompts, such as `@other_bot`.
+    #[serde(default = "default_true")]
+    pub strip_leading_mentions: bool,
+    /// Turns hashtags into plain words, so `#cyberpunk` becomes `cyberpunk`.
+    #[serde(default = "default_true")]
+    pub strip_hashtags: bool,
+    /// Removes custom emoji. Otherwise they're kept as the standard emoji Telegram shows in
+    /// their place.
+    #[serde(default)]
+    pub drop_custom_emoji: bool,
+    /// Removes links.
+    #[serde(default)]
+    pub drop_urls: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for PromptCleaningConfig {
+    fn default() -> Self {
+        Self {
+            strip_leading_mentions: default_true(),
+            strip_hashtags: default_true(),
+            drop_custom_emoji: false,
+            drop_urls: false,
+        }
+    }
+}
+
+impl PromptCleaningConfig {
+    /// Returns `prompt`, taken from the text or caption of `msg`, without the entities of `msg`
+    /// that this configuration removes.
+    pub(crate) fn clean(&self, msg: &Message, prompt: &str) -> String {
+        let entities = msg
+            .parse_entities()
+            .or_else(|| msg.parse_caption_entities())
+            .unwrap_or_default();
+        let mut prompt = prompt.trim().to_owned();
+        for entity in entities {
+            let text = entity.text();
+            match entity.kind() {
+                MessageEntityKind::Mention | MessageEntityKind::BotCommand
+                    if self.strip_leading_mentions =>
+                {
+                    if let Some(rest) = prompt.strip_prefix(text) {
+                        prompt = rest.trim_start().to_owned();
+                    }
+                }
+                MessageEntityKind::Hashtag if self.strip_hashtags => {
+                    prompt = prompt.replace(text, text.trim_start_matches('#'));
+                }
+                MessageEntityKind::CustomEmoji { .. } if self.drop_custom_emoji => {
+                    prompt = prompt.replace(text, "");
+                }
+                MessageEntityKind::Url if self.drop_urls => {
+                    prompt = prompt.replace(text, "");
+                }
+                _ => {}
+            }
+        }
+        prompt
+            .lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect::<Vec<_>>()
+            .join("\n")
+            .trim()
+            .to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str, entities: serde_json::Value) -> Message {
+        serde_json::from_value(serde_json::json!({
+            "message_id": 1,
+            "date": 1634567890,
+            "chat": {"id": 1, "type": "private", "first_name": "User"},
+            "from": {"id": 1, "is_bot": false, "first_name": "User"},
+            "text": text,
+            "entities": entities,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_clean() {
+        let text = "@other_bot a #cyberpunk city 🌃 https://example.com";
+        let msg = message(
+            text,
+            serde_json::json!([
+                {"type": "mention", "offset": 0, "length": 10},
+                {"type": "hashtag", "offset": 13, "length": 10},
+                {"type": "custom_emoji", "offset": 29, "length": 2, "custom_emoji_id": "5"},
+                {"type": "url", "offset": 32, "length": 19},
+            ]),
+        );
+
+        let config = PromptCleaningConfig::default();
+        assert_eq!(
+            config.clean(&msg, text),
+            "a cyberpunk city 🌃 https://example.com"
+        );
+
+        let config = PromptCleaningConfig {
+            drop_custom_emoji: true,
+            drop_urls: true,
+            ..Default::default()
+        };
+        assert_eq!(config.clean(&msg, text), "a cyberpunk city");
+    }
+
+    #[test]
+    fn test_clean_keeps_later_mentions() {
+        let text = "a portrait by @artist\nsecond line";
+        let msg = message(
+            text,
+            serde_json::json!([{"type": "mention", "offset": 14, "length": 7}]),
+        );
+        assert_eq!(PromptCleaningConfig::default().clean(&msg, text), text);
+    }
+}
@@ -0,0 +1,200 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use sal_e_api::GenParams;
+
+/// Most sampling steps a flag can ask for.
+const MAX_STEPS: u32 = 200;
+/// Largest CFG scale a flag can ask for.
+const MAX_CFG: f32 = 30.0;
+/// Largest ratio between the long and the short side of an aspect ratio.
+const MAX_ASPECT_RATIO: f32 = 4.0;
+/// Image sizes are rounded to multiples of this, like sizes entered in the settings.
+const SIZE_STEP: u32 = 64;
+/// Side of the image assumed when the settings don't have a size.
+const DEFAULT_SIZE: u32 = 512;
+
+lazy_static! {
+    /// Matches a flag such as ` --no`, at the start of the prompt or after whitespace. The
+    /// whitespace after a flag isn't matched, so it can't hide the flag that follows.
+    static ref FLAG_RE: Regex = Regex::new(r"(?:^|\s)--(no|steps|cfg|ar)").unwrap();
+}
+
+/// Settings for a single generation, given as flags at the end of a prompt, e.g.
+/// `a castle --no people, text --ar 3:2`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct PromptFlags {
+    /// Negative prompt, from `--no`.
+    pub negative_prompt: Option<String>,
+    /// Number of sampling steps, from `--steps`.
+    pub steps: Option<u32>,
+    /// CFG scale, from `--cfg`.
+    pub cfg: Option<f32>,
+    /// Aspect ratio as width and height, from `--ar`.
+    pub aspect_ratio: Option<(u32, u32)>,
+}
+
+/// Error returned for a flag with an invalid value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct InvalidFlag {
+    /// Name of the flag, without the dashes.
+    pub flag: String,
+    /// Value given for the flag.
+    pub value: String,
+}
+
+impl PromptFlags {
+    /// Applies the flags to `params`. The aspect ratio keeps about the same number of pixels as
+    /// the size in `params`.
+    pub fn apply(&self, params: &mut dyn GenParams) {
+        if let Some(negative_prompt) = &self.negative_prompt {
+            params.set_negative_prompt(negative_prompt.clone());
+        }
+        if let Some(steps) = self.steps {
+            params.set_steps(steps);
+        }
+        if let Some(cfg) = self.cfg {
+            params.set_cfg(cfg);
+        }
+        if let Some((width, height)) = self.aspect_ratio {
+            let area = f64::from(params.width().unwrap_or(DEFAULT_SIZE))
+                * f64::from(params.height().unwrap_or(DEFAULT_SIZE));
+            let ratio = f64::from(width) / f64::from(height);
+            params.set_width(round_size((area * ratio).sqrt()));
+            params.set_height(round_size((area / ratio).sqrt()));
+        }
+    }
+}
+
+fn round_size(size: f64) -> u32 {
+    ((size / f64::from(SIZE_STEP)).round() as u32).max(1) * SIZE_STEP
+}
+
+/// Splits the flags off the end of `text`, returning the prompt and the flags. Text that merely
+/// contains `--` is left alone unless it's one of the known flags.
+pub(crate) fn parse(text: &str) -> Result<(String, PromptFlags), InvalidFlag> {
+    let flags: Vec<_> = FLAG_RE
+        .captures_iter(text)
+        .filter(|captures| {
+            text[captures.get(0).unwrap().end()..]
+                .chars()
+                .next()
+                .is_none_or(char::is_whitespace)
+        })
+        .collect();
+    let Some(first) = flags.first() else {
+        return Ok((text.to_owned(), PromptFlags::default()));
+    };
+    let prompt = text[..first.get(0).unwrap().start()].trim().to_owned();
+
+    let mut parsed = PromptFlags::default();
+    for (index, captures) in flags.iter().enumerate() {
+        let end = flags
+            .get(index + 1)
+            .map_or(text.len(), |next| next.get(0).unwrap().start());
+        let value = text[captures.get(0).unwrap().end()..end].trim();
+        let invalid = || InvalidFlag {
+            flag: captures[1].to_owned(),
+            value: value.to_owned(),
+        };
+        match &captures[1] {
+            "no" => parsed.negative_prompt = Some(value.to_owned()),
+            "steps" => {
+                parsed.steps = Some(
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|steps| (1..=MAX_STEPS).contains(steps))
+                        .ok_or_else(invalid)?,
+                )
+            }
+            "cfg" => {
+                parsed.cfg = Some(
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|cfg| (0.0..=MAX_CFG).contains(cfg))
+                        .ok_or_else(invalid)?,
+                )
+            }
+            _ => parsed.aspect_ratio = Some(parse_aspect_ratio(value).ok_or_else(invalid)?),
+        }
+    }
+    Ok((prompt, parsed))
+}
+
+/// Parses an aspect ratio such as `3:2`.
+fn parse_aspect_ratio(value: &str) -> Option<(u32, u32)> {
+    let (width, height) = value.split_once(':')?;
+    let (width, height): (u32, u32) = (width.trim().parse().ok()?, height.trim().parse().ok()?);
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let ratio = width.max(height) as f32 / width.min(height) as f32;
+    (ratio <= MAX_ASPECT_RATIO).then_some((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use sal_e_api::Txt2ImgParams;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("a castle").unwrap(),
+            ("a castle".to_owned(), PromptFlags::default())
+        );
+        assert_eq!(
+            parse("a castle --no people, text --steps 30 --cfg 6.5 --ar 3:2").unwrap(),
+            (
+                "a castle".to_owned(),
+                PromptFlags {
+                    negative_prompt: Some("people, text".to_owned()),
+                    steps: Some(30),
+                    cfg: Some(6.5),
+                    aspect_ratio: Some((3, 2)),
+                }
+            )
+        );
+        assert_eq!(
+            parse("a--no castle --nope").unwrap().0,
+            "a--no castle --nope"
+        );
+        assert_eq!(
+            parse("a castle --no --steps 30").unwrap().1,
+            PromptFlags {
+                negative_prompt: Some(String::new()),
+                steps: Some(30),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert_eq!(
+            parse("a castle --steps many"),
+            Err(InvalidFlag {
+                flag: "steps".to_owned(),
+                value: "many".to_owned()
+            })
+        );
+        assert!(parse("a castle --steps 0").is_err());
+        assert!(parse("a castle --cfg 100").is_err());
+        assert!(parse("a castle --ar 10:1").is_err());
+        assert!(parse("a castle --ar 3:0").is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut params = Txt2ImgParams::default();
+        params.set_width(512);
+        params.set_height(512);
+        let (_, flags) = parse("a castle --no people --ar 16:9").unwrap();
+        flags.apply(&mut params);
+        assert_eq!(params.negative_prompt(), Some("people".to_owned()));
+        assert_eq!(params.width(), Some(704));
+        assert_eq!(params.height(), Some(384));
+    }
+}
@@ -0,0 +1,180 @@
+use chrono::{DateTime, Days, FixedOffset, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use teloxide::types::ChatId;
+
+/// What happens to generations requested during quiet hours.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QuietHoursMode {
+    /// Reject the request, telling the user when generating resumes.
+    #[default]
+    Reject,
+    /// Generate the image once quiet hours end. Deferred requests are kept in memory, so they
+    /// are lost if the bot restarts.
+    Defer,
+}
+
+/// Quiet hours for a single chat, replacing the global ones.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatQuietHours {
+    /// The chat the quiet hours apply to.
+    pub chat_id: i64,
+    /// When quiet hours start. If either `start` or `end` is unset, the chat has no quiet hours.
+    pub start: Option<NaiveTime>,
+    /// When quiet hours end.
+    pub end: Option<NaiveTime>,
+}
+
+/// Struct that represents the configuration for quiet hours, during which no images are
+/// generated, e.g. to leave a shared GPU free for other jobs overnight.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct QuietHoursConfig {
+    /// When quiet hours start, e.g. `01:00`. Quiet hours may span midnight.
+    pub start: Option<NaiveTime>,
+    /// When quiet hours end, e.g. `07:00`.
+    pub end: Option<NaiveTime>,
+    /// The offset from UTC that `start` and `end` are in, e.g. `+02:00`. Defaults to UTC.
+    #[serde(default, with = "utc_offset")]
+    pub utc_offset: Option<FixedOffset>,
+    /// What happens to generations requested during quiet hours.
+    #[serde(default)]
+    pub mode: QuietHoursMode,
+    /// Chats with their own quiet hours.
+    #[serde(default)]
+    pub chats: Vec<ChatQuietHours>,
+}
+
+impl QuietHoursConfig {
+    fn offset(&self) -> FixedOffset {
+        self.utc_offset.unwrap_or(FixedOffset::east_opt(0).unwrap())
+    }
+
+    /// Returns the start and end of quiet hours in `chat_id`, if it has any.
+    fn window(&self, chat_id: ChatId) -> Option<(NaiveTime, NaiveTime)> {
+        let (start, end) = match self.chats.iter().find(|chat| chat.chat_id == chat_id.0) {
+            Some(chat) => (chat.start, chat.end),
+            None => (self.start, self.end),
+        };
+        Some((start?, end?)).filter(|(start, end)| start != end)
+    }
+
+    /// Returns when quiet hours end in `chat_id`, if `now` is within them.
+    pub(crate) fn quiet_until(
+        &self,
+        chat_id: ChatId,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<FixedOffset>> {
+        let (start, end) = self.window(chat_id)?;
+        let now = now.with_timezone(&self.offset());
+        let time = now.time();
+        let quiet = if start < end {
+            start <= time && time < end
+        } else {
+            start <= time || time < end
+        };
+        if !quiet {
+            return None;
+        }
+        let mut date = now.date_naive();
+        if time >= end {
+            date = date.checked_add_days(Days::new(1))?;
+        }
+        date.and_time(end)
+            .and_local_timezone(self.offset())
+            .single()
+    }
+}
+
+/// Formats `time` for telling users when quiet hours end, e.g. `07:00 (UTC+02:00)`.
+pub(crate) fn format_time(time: &DateTime<FixedOffset>) -> String {
+    time.format("%H:%M (UTC%:z)").to_string()
+}
+
+/// (De)serializes an optional UTC offset as a string, e.g. `+02:00`.
+pub(crate) mod utc_offset {
+    use chrono::FixedOffset;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        offset: &Option<FixedOffset>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match offset {
+            Some(offset) => serializer.collect_str(offset),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<FixedOffset>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|offset| offset.parse().map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn time(hour: u32, min: u32) -> Option<NaiveTime> {
+        NaiveTime::from_hms_opt(hour, min, 0)
+    }
+
+    fn at(hour: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, hour, min, 0).unwrap()
+    }
+
+    #[test]
+    fn test_quiet_until() {
+        let config = QuietHoursConfig {
+            start: time(22, 0),
+            end: time(6, 30),
+            chats: vec![
+                ChatQuietHours {
+                    chat_id: 1,
+                    start: time(1, 0),
+                    end: time(2, 0),
+                },
+                ChatQuietHours {
+                    chat_id: 2,
+                    start: None,
+                    end: None,
+                },
+            ],
+            ..Default::default()
+        };
+        let chat = ChatId(3);
+        assert_eq!(config.quiet_until(chat, at(21, 59)), None);
+        assert_eq!(
+            config.quiet_until(chat, at(22, 0)).unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 2, 6, 30, 0).unwrap()
+        );
+        assert_eq!(
+            config.quiet_until(chat, at(3, 0)).unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 1, 6, 30, 0).unwrap()
+        );
+        assert_eq!(config.quiet_until(chat, at(6, 30)), None);
+
+        assert_eq!(config.quiet_until(ChatId(1), at(23, 0)), None);
+        assert!(config.quiet_until(ChatId(1), at(1, 30)).is_some());
+        assert_eq!(config.quiet_until(ChatId(2), at(23, 0)), None);
+    }
+
+    #[test]
+    fn test_utc_offset() {
+        let config: QuietHoursConfig = serde_json::from_str(
+            r#"{"start": "01:00", "end": "07:00", "utc_offset": "+02:00", "mode": "defer"}"#,
+        )
+        .unwrap();
+        assert_eq!(config.mode, QuietHoursMode::Defer);
+        // 23:30 UTC is 01:30 at UTC+02:00.
+        let until = config.quiet_until(ChatId(1), at(23, 30)).unwrap();
+        assert_eq!(until, Utc.with_ymd_and_hms(2024, 1, 2, 5, 0, 0).unwrap());
+        assert_eq!(format_time(&until), "07:00 (UTC+02:00)");
+        assert_eq!(config.quiet_until(ChatId(1), at(5, 0)), None);
+    }
+}
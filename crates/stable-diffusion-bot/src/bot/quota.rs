@@ -0,0 +1,180 @@
+use chrono::{DateTime, FixedOffset, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use super::i18n::Translator;
+
+/// The longest period quotas are counted over, in seconds. Older usage can be discarded.
+pub(crate) const MAX_PERIOD: i64 = DAY;
+
+const HOUR: i64 = 60 * 60;
+const DAY: i64 = 24 * HOUR;
+
+/// Struct that represents the configuration for image quotas, which limit how many images each
+/// user can generate. Usage is stored in the database, so it survives restarts.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct QuotaConfig {
+    /// The number of images each user can generate in any hour, if limited.
+    pub images_per_hour: Option<u32>,
+    /// The number of images each user can generate in any 24 hours, if limited.
+    pub images_per_day: Option<u32>,
+    /// The offset from UTC to show reset times in, e.g. `+02:00`. Defaults to UTC.
+    #[serde(default, with = "super::quiet_hours::utc_offset")]
+    pub utc_offset: Option<FixedOffset>,
+}
+
+/// Why a generation was refused by the quota.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum QuotaExceeded {
+    /// The user has used their quota, which has room for the generation again at `resets_at`.
+    UsedUp {
+        limit: u32,
+        period: &'static str,
+        resets_at: DateTime<FixedOffset>,
+    },
+    /// The generation has more images than the quota allows at all.
+    TooLarge { limit: u32, period: &'static str },
+}
+
+impl QuotaExceeded {
+    /// Returns the message telling the user why their generation was refused.
+    pub(crate) fn user_message(&self, translator: &Translator) -> String {
+        match self {
+            Self::UsedUp {
+                limit,
+                period,
+                resets_at,
+            } => translator.format(
+                "⏳ You've used your quota of {limit} images per {period}. It resets at {time}.",
+                &[
+                    ("limit", &limit.to_string()),
+                    ("period", &translator.text(period)),
+                    ("time", &super::quiet_hours::format_time(resets_at)),
+                ],
+            ),
+            Self::TooLarge { limit, period } => translator.format(
+                "⏳ You can generate at most {limit} images per {period}. \
+                Lower the number of images in /settings and try again.",
+                &[
+                    ("limit", &limit.to_string()),
+                    ("period", &translator.text(period)),
+                ],
+            ),
+        }
+    }
+}
+
+impl QuotaConfig {
+    /// Checks whether a user can generate `requested` more images at `now`, given their `usage`
+    /// as `(time, images)` pairs in ascending order of time. Times are in seconds since the Unix
+    /// epoch.
+    pub(crate) fn check(
+        &self,
+        usage: &[(i64, u32)],
+        requested: u32,
+        now: i64,
+    ) -> Result<(), QuotaExceeded> {
+        let limits = [
+            (self.images_per_hour, HOUR, "hour"),
+            (self.images_per_day, DAY, "day"),
+        ];
+        for (limit, length, period) in limits {
+            let Some(limit) = limit else {
+                continue;
+            };
+            if requested > limit {
+                return Err(QuotaExceeded::TooLarge { limit, period });
+            }
+            let recent: Vec<_> = usage
+                .iter()
+                .filter(|(time, _)| *time > now - length)
+                .collect();
+            let used: u32 = recent.iter().map(|(_, images)| images).sum();
+            if used + requested <= limit {
+                continue;
+            }
+            let excess = used + requested - limit;
+            // Wait until enough of the oldest usage falls out of the period.
+            let mut expired = 0;
+            let resets_at = recent
+                .iter()
+                .find(|(_, images)| {
+                    expired += images;
+                    expired >= excess
+                })
+                .map_or(now, |(time, _)| time + length);
+            return Err(QuotaExceeded::UsedUp {
+                limit,
+                period,
+                resets_at: self.local_time(resets_at),
+            });
+        }
+        Ok(())
+    }
+
+    fn local_time(&self, time: i64) -> DateTime<FixedOffset> {
+        let offset = self.utc_offset.unwrap_or(FixedOffset::east_opt(0).unwrap());
+        offset.timestamp_opt(time, 0).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_quota() {
+        let config = QuotaConfig {
+            images_per_hour: Some(4),
+            images_per_day: Some(10),
+            ..Default::default()
+        };
+        let now = 100 * HOUR;
+
+        assert_eq!(config.check(&[], 4, now), Ok(()));
+        assert_eq!(
+            config.check(&[], 5, now),
+            Err(QuotaExceeded::TooLarge {
+                limit: 4,
+                period: "hour"
+            })
+        );
+
+        let usage = [(now - 50 * 60, 2), (now - 20 * 60, 1)];
+        assert_eq!(config.check(&usage, 1, now), Ok(()));
+        match config.check(&usage, 2, now) {
+            Err(QuotaExceeded::UsedUp {
+                period, resets_at, ..
+            }) => {
+                assert_eq!(period, "hour");
+                assert_eq!(resets_at.timestamp(), now + 10 * 60);
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        let usage = [
+            (now - 20 * HOUR, 4),
+            (now - 10 * HOUR, 4),
+            (now - 2 * HOUR, 2),
+        ];
+        match config.check(&usage, 3, now) {
+            Err(QuotaExceeded::UsedUp {
+                period, resets_at, ..
+            }) => {
+                assert_eq!(period, "day");
+                assert_eq!(resets_at.timestamp(), now + 4 * HOUR);
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_user_message() {
+        let config: QuotaConfig =
+            serde_json::from_str(r#"{"images_per_hour": 4, "utc_offset": "+02:00"}"#).unwrap();
+        let err = config.check(&[(0, 4)], 1, 60).unwrap_err();
+        assert_eq!(
+            err.user_message(&Translator::default()),
+            "⏳ You've used your quota of 4 images per hour. It resets at 03:00 (UTC+02:00)."
+        );
+    }
+}
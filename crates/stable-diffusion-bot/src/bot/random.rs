@@ -0,0 +1,200 @@
+use rand::{seq::SliceRandom, Rng};
+use sal_e_api::GenParams;
+use serde::{Deserialize, Serialize};
+
+use super::styles::Style;
+
+/// Telegram limits callback data to 64 bytes.
+const MAX_CALLBACK_DATA_LENGTH: usize = 64;
+
+/// Struct that represents the configuration for `/random`, which generates with a random
+/// style, sampler and CFG scale.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RandomConfig {
+    /// Samplers to choose from, named as the backend expects them. If empty, the user's sampler
+    /// is kept.
+    #[serde(default)]
+    pub samplers: Vec<String>,
+    /// Lowest CFG scale to choose.
+    #[serde(default = "default_cfg_min")]
+    pub cfg_min: f32,
+    /// Highest CFG scale to choose.
+    #[serde(default = "default_cfg_max")]
+    pub cfg_max: f32,
+}
+
+fn default_cfg_min() -> f32 {
+    4.0
+}
+
+fn default_cfg_max() -> f32 {
+    10.0
+}
+
+impl Default for RandomConfig {
+    fn default() -> Self {
+        Self {
+            samplers: Vec::new(),
+            cfg_min: default_cfg_min(),
+            cfg_max: default_cfg_max(),
+        }
+    }
+}
+
+/// The settings chosen for a `/random` generation.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RandomChoices {
+    /// Name of the chosen style, if any styles are configured.
+    pub style: Option<String>,
+    /// Index of the chosen sampler in `RandomConfig::samplers`.
+    pub sampler: Option<usize>,
+    pub cfg: f32,
+}
+
+impl RandomChoices {
+    /// Picks a style from `styles`, a sampler and a CFG scale in steps of 0.5.
+    pub fn pick(config: &RandomConfig, styles: &[Style], rng: &mut impl Rng) -> Self {
+        let (min, max) = if config.cfg_min <= config.cfg_max {
+            (config.cfg_min, config.cfg_max)
+        } else {
+            (config.cfg_max, config.cfg_min)
+        };
+        let steps = ((max - min) * 2.0).floor() as u32;
+        Self {
+            style: styles.choose(rng).map(|style| style.name.clone()),
+            sampler: (!config.samplers.is_empty()).then(|| rng.gen_range(0..config.samplers.len())),
+            cfg: min + rng.gen_range(0..=steps) as f32 / 2.0,
+        }
+    }
+
+    /// Returns the name of the chosen sampler.
+    pub fn sampler_name<'a>(&self, config: &'a RandomConfig) -> Option<&'a str> {
+        config.samplers.get(self.sampler?).map(String::as_str)
+    }
+
+    /// Sets the chosen sampler and CFG scale in `params`. The style is applied separately.
+    pub fn apply(&self, config: &RandomConfig, params: &mut dyn GenParams) {
+        if let Some(sampler) = self.sampler_name(config) {
+            params.set_sampler(sampler.to_owned());
+        }
+        params.set_cfg(self.cfg);
+    }
+
+    /// Returns a description of the choices, for the caption.
+    pub fn label(&self, config: &RandomConfig) -> String {
+        [
+            self.style.as_ref().map(|style| format!("Style: {style}")),
+            self.sampler_name(config)
+                .map(|sampler| format!("Sampler: {sampler}")),
+            Some(format!("CFG scale: {}", self.cfg)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(", ")
+    }
+
+    /// Returns the data of the button that keeps these settings, or `None` if they don't fit.
+    pub fn callback_data(&self) -> Option<String> {
+        let data = format!(
+            "keep/{}/{}/{}",
+            self.cfg,
+            self.sampler
+                .map(|index| index.to_string())
+                .unwrap_or_default(),
+            self.style.as_deref().unwrap_or_default()
+        );
+        (data.len() <= MAX_CALLBACK_DATA_LENGTH).then_some(data)
+    }
+
+    /// Parses the data of a button that keeps the settings.
+    pub fn parse_callback_data(data: &str) -> Option<Self> {
+        let mut parts = data.strip_prefix("keep/")?.splitn(3, '/');
+        let cfg = parts.next()?.parse().ok()?;
+        let sampler = match parts.next()? {
+            "" => None,
+            index => Some(index.parse().ok()?),
+        };
+        let style = Some(parts.next()?.to_owned()).filter(|style| !style.is_empty());
+        Some(Self {
+            style,
+            sampler,
+            cfg,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+    use sal_e_api::Txt2ImgParams;
+
+    use super::*;
+
+    #[test]
+    fn test_pick() {
+        let config = RandomConfig {
+            samplers: vec!["Euler a".to_owned(), "DPM++ 2M Karras".to_owned()],
+            cfg_min: 5.0,
+            cfg_max: 7.0,
+        };
+        let styles = vec![Style {
+            name: "noir".to_owned(),
+            ..Default::default()
+        }];
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let choices = RandomChoices::pick(&config, &styles, &mut rng);
+            assert_eq!(choices.style.as_deref(), Some("noir"));
+            assert!(choices.sampler_name(&config).is_some());
+            assert!((5.0..=7.0).contains(&choices.cfg));
+            assert_eq!(choices.cfg * 2.0, (choices.cfg * 2.0).round());
+        }
+
+        let choices = RandomChoices::pick(&RandomConfig::default(), &[], &mut rng);
+        assert_eq!(choices.style, None);
+        assert_eq!(choices.sampler, None);
+    }
+
+    #[test]
+    fn test_apply_and_label() {
+        let config = RandomConfig {
+            samplers: vec!["Euler a".to_owned()],
+            ..Default::default()
+        };
+        let choices = RandomChoices {
+            style: Some("noir".to_owned()),
+            sampler: Some(0),
+            cfg: 6.5,
+        };
+        let mut params = Txt2ImgParams::default();
+        choices.apply(&config, &mut params);
+        assert_eq!(params.sampler().as_deref(), Some("Euler a"));
+        assert_eq!(params.cfg(), Some(6.5));
+        assert_eq!(
+            choices.label(&config),
+            "Style: noir, Sampler: Euler a, CFG scale: 6.5"
+        );
+    }
+
+    #[test]
+    fn test_callback_data() {
+        let choices = RandomChoices {
+            style: Some("noir".to_owned()),
+            sampler: Some(1),
+            cfg: 7.5,
+        };
+        let data = choices.callback_data().unwrap();
+        assert_eq!(data, "keep/7.5/1/noir");
+        assert_eq!(RandomChoices::parse_callback_data(&data), Some(choices));
+
+        let choices = RandomChoices {
+            style: None,
+            sampler: None,
+            cfg: 4.0,
+        };
+        let data = choices.callback_data().unwrap();
+        assert_eq!(RandomChoices::parse_callback_data(&data), Some(choices));
+        assert_eq!(RandomChoices::parse_callback_data("keep/high//"), None);
+    }
+}
@@ -0,0 +1,178 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use teloxide::types::{
+    KeyboardButton, KeyboardMarkup, MediaKind, MessageCommon, MessageKind, Update, UpdateKind,
+};
+
+use super::config::UiConfig;
+
+/// A button on the reply keyboard, which sends one of the bot's commands.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplyButton {
+    /// Opens the txt2img settings.
+    Settings,
+    /// Uses the seed of the chat's last image for the next images.
+    LastSeed,
+    /// Shows the help message.
+    Help,
+    /// Cancels the image being generated.
+    Cancel,
+}
+
+impl ReplyButton {
+    /// Returns the text of the button, which is what Telegram sends when it's pressed.
+    fn label(self) -> &'static str {
+        match self {
+            ReplyButton::Settings => "⚙️ Settings",
+            ReplyButton::LastSeed => "🌱 Last seed",
+            ReplyButton::Help => "❓ Help",
+            ReplyButton::Cancel => "❌ Cancel",
+        }
+    }
+
+    /// Returns the command the button sends.
+    fn command(self) -> &'static str {
+        match self {
+            ReplyButton::Settings => "/txt2imgsettings",
+            ReplyButton::LastSeed => "/lastseed",
+            ReplyButton::Help => "/help",
+            ReplyButton::Cancel => "/cancel",
+        }
+    }
+}
+
+/// Struct that represents the configuration for the reply keyboard, a persistent keyboard shown
+/// in private chats so users don't have to remember commands. Group chats never get it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReplyKeyboardConfig {
+    /// The buttons to show, in order, two per row.
+    #[serde(default = "default_buttons")]
+    pub buttons: Vec<ReplyButton>,
+}
+
+impl Default for ReplyKeyboardConfig {
+    fn default() -> Self {
+        Self {
+            buttons: default_buttons(),
+        }
+    }
+}
+
+fn default_buttons() -> Vec<ReplyButton> {
+    vec![
+        ReplyButton::Settings,
+        ReplyButton::LastSeed,
+        ReplyButton::Help,
+        ReplyButton::Cancel,
+    ]
+}
+
+impl ReplyKeyboardConfig {
+    /// Returns the keyboard to send with replies in private chats.
+    pub(crate) fn markup(&self) -> KeyboardMarkup {
+        KeyboardMarkup::new(
+            self.buttons
+                .chunks(2)
+                .map(|row| row.iter().map(|button| KeyboardButton::new(button.label()))),
+        )
+        .persistent()
+        .resize_keyboard(true)
+    }
+
+    /// Returns the button whose text is `text`, if it's on the keyboard.
+    fn button(&self, text: &str) -> Option<ReplyButton> {
+        self.buttons
+            .iter()
+            .copied()
+            .find(|button| button.label() == text)
+    }
+}
+
+/// Replaces the text of messages sent by pressing a reply keyboard button with the button's
+/// command, so the command handlers handle them.
+pub(crate) fn resolve_button_press(mut update: Update, ui: Arc<UiConfig>) -> Update {
+    let Some(config) = &ui.reply_keyboard else {
+        return update;
+    };
+    let UpdateKind::Message(msg) = &mut update.kind else {
+        return update;
+    };
+    if !msg.chat.is_private() {
+        return update;
+    }
+    if let MessageKind::Common(MessageCommon {
+        media_kind: MediaKind::Text(media),
+        ..
+    }) = &mut msg.kind
+    {
+        if let Some(button) = config.button(&media.text) {
+            media.text = button.command().to_owned();
+            media.entities.clear();
+        }
+    }
+    update
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn update(private: bool, text: &str) -> Update {
+        let chat = if private {
+            json!({"id": 1, "type": "private", "first_name": "User"})
+        } else {
+            json!({"id": -1, "type": "group", "title": "Group"})
+        };
+        let msg = serde_json::from_value(json!({
+            "message_id": 5,
+            "date": 1675229140,
+            "chat": chat,
+            "from": {"id": 1, "is_bot": false, "first_name": "User"},
+            "text": text,
+        }))
+        .unwrap();
+        Update {
+            id: 1,
+            kind: UpdateKind::Message(msg),
+        }
+    }
+
+    fn text(update: &Update) -> Option<&str> {
+        match &update.kind {
+            UpdateKind::Message(msg) => msg.text(),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_resolve_button_press() {
+        let ui = Arc::new(UiConfig {
+            reply_keyboard: Some(ReplyKeyboardConfig {
+                buttons: vec![ReplyButton::Help, ReplyButton::LastSeed],
+            }),
+            ..Default::default()
+        });
+
+        let resolved = resolve_button_press(update(true, "🌱 Last seed"), ui.clone());
+        assert_eq!(text(&resolved), Some("/lastseed"));
+        let resolved = resolve_button_press(update(true, "❌ Cancel"), ui.clone());
+        assert_eq!(text(&resolved), Some("❌ Cancel"));
+        let resolved = resolve_button_press(update(false, "❓ Help"), ui);
+        assert_eq!(text(&resolved), Some("❓ Help"));
+
+        let resolved = resolve_button_press(update(true, "❓ Help"), Default::default());
+        assert_eq!(text(&resolved), Some("❓ Help"));
+    }
+
+    #[test]
+    fn test_markup() {
+        let markup = ReplyKeyboardConfig::default().markup();
+        assert_eq!(markup.keyboard.len(), 2);
+        assert_eq!(markup.keyboard[0][0].text, "⚙️ Settings");
+        assert!(markup.is_persistent);
+    }
+}
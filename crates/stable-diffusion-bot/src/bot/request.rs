@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Struct that represents how requests to the backends are sent.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestConfig {
+    /// Seconds to wait for a response before giving up. If unset, requests wait indefinitely,
+    /// so set this well above the time the slowest generation takes.
+    pub timeout_secs: Option<u64>,
+    /// How often to retry requests that failed to connect or got a server error. The delay
+    /// before each retry doubles, starting at half a second.
+    #[serde(default)]
+    pub retries: u32,
+}
+
+impl RequestConfig {
+    /// Returns the time to wait for a response, if any.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_secs.map(Duration::from_secs)
+    }
+
+    /// Applies the settings to a Stable Diffusion WebUI client.
+    pub(crate) fn apply_webui(&self, api: stable_diffusion_api::Api) -> stable_diffusion_api::Api {
+        let api = api.with_retries(self.retries);
+        match self.timeout() {
+            Some(timeout) => api.with_timeout(timeout),
+            None => api,
+        }
+    }
+
+    /// Applies the settings to a ComfyUI client.
+    pub(crate) fn apply_comfyui(&self, api: comfyui_api::api::Api) -> comfyui_api::api::Api {
+        let api = api.with_retries(self.retries);
+        match self.timeout() {
+            Some(timeout) => api.with_timeout(timeout),
+            None => api,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeout() {
+        let config: RequestConfig = serde_json::from_str(r#"{"timeout_secs": 600}"#).unwrap();
+        assert_eq!(config.timeout(), Some(Duration::from_secs(600)));
+        assert_eq!(config.retries, 0);
+        assert_eq!(RequestConfig::default().timeout(), None);
+    }
+}
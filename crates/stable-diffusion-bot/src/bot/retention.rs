@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use teloxide::types::UserId;
+use tracing::{info, warn};
+
+use super::{
+    history::{self, HistoryStore},
+    settings_store, DialogueStorage,
+};
+
+/// Struct that represents the configuration for automatically purging old generation history.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RetentionConfig {
+    /// Number of days to keep generations in the history before purging them.
+    #[serde(default = "default_max_age_days")]
+    pub max_age_days: u64,
+    /// How often to check for expired generations, in seconds.
+    #[serde(default = "default_purge_interval_secs")]
+    pub purge_interval_secs: u64,
+}
+
+fn default_max_age_days() -> u64 {
+    30
+}
+
+fn default_purge_interval_secs() -> u64 {
+    3600
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            max_age_days: default_max_age_days(),
+            purge_interval_secs: default_purge_interval_secs(),
+        }
+    }
+}
+
+/// Periodically deletes generations and quarantined dialogue states older than the configured
+/// maximum age. Never returns.
+pub(crate) async fn purge_expired(history: HistoryStore, config: RetentionConfig) {
+    let max_age = Duration::from_secs(config.max_age_days * 24 * 60 * 60);
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(config.purge_interval_secs.max(1)));
+    loop {
+        interval.tick().await;
+        let cutoff = history::now() - max_age.as_secs() as i64;
+        match history.delete_older_than(cutoff).await {
+            Ok(0) => {}
+            Ok(count) => info!(
+                "Purged {} generations older than {} days",
+                count, config.max_age_days
+            ),
+            Err(e) => warn!("Failed to purge expired generations: {:?}", e),
+        }
+    }
+}
+
+/// Deletes everything stored about a user: the settings, preferences, prompt variables and
+/// quarantined dialogue states of their private chat with the bot, their prompt snippets and
+/// styles, their generation history, and their A/B tests and votes.
+pub(crate) async fn forget_user(
+    storage: DialogueStorage,
+    history: &HistoryStore,
+    user_id: UserId,
+) -> anyhow::Result<()> {
+    // Telegram uses the user's id as the id of their private chat with the bot.
+    let chat_id = user_id.into();
+    if storage
+        .clone()
+        .get_dialogue(chat_id)
+        .await
+        .map_err(|e| anyhow!(e))?
+        .is_some()
+    {
+        storage
+            .remove_dialogue(chat_id)
+            .await
+            .map_err(|e| anyhow!(e))?;
+    }
+    history.delete_chat_preferences(chat_id).await?;
+    history.delete_snippets(user_id).await?;
+    history.delete_styles(user_id).await?;
+    history.set_user_language(user_id, None).await?;
+    settings_store::reset(history, user_id).await?;
+    let count = history.delete_user(user_id).await?;
+    info!("Forgot user, deleting {} generations", count);
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use teloxide::{
+    payloads::setters::*,
+    prelude::*,
+    types::{ChatAction, InputFile, InputMedia, MessageKind},
+};
+
+/// Where replies to a message are sent: its chat and, in forum supergroups, its topic.
+/// Messages sent to a forum without a topic land in its General topic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct SendContext {
+    pub chat_id: ChatId,
+    pub thread_id: Option<i32>,
+}
+
+impl SendContext {
+    /// Returns the context for replying to `msg`.
+    pub fn of(msg: &Message) -> Self {
+        // Replies in ordinary supergroups have a thread id too, but sending to it fails.
+        let thread_id = match &msg.kind {
+            MessageKind::Common(common) if common.is_topic_message => msg.thread_id,
+            _ => None,
+        };
+        Self {
+            chat_id: msg.chat.id,
+            thread_id,
+        }
+    }
+
+    pub fn send_message<T>(&self, bot: &Bot, text: T) -> <Bot as Requester>::SendMessage
+    where
+        T: Into<String>,
+    {
+        let request = bot.send_message(self.chat_id, text);
+        match self.thread_id {
+            Some(thread_id) => request.message_thread_id(thread_id),
+            None => request,
+        }
+    }
+
+    pub fn send_photo(&self, bot: &Bot, photo: InputFile) -> <Bot as Requester>::SendPhoto {
+        let request = bot.send_photo(self.chat_id, photo);
+        match self.thread_id {
+            Some(thread_id) => request.message_thread_id(thread_id),
+            None => request,
+        }
+    }
+
+    pub fn send_media_group<M>(&self, bot: &Bot, media: M) -> <Bot as Requester>::SendMediaGroup
+    where
+        M: IntoIterator<Item = InputMedia>,
+    {
+        let request = bot.send_media_group(self.chat_id, media);
+        match self.thread_id {
+            Some(thread_id) => request.message_thread_id(thread_id),
+            None => request,
+        }
+    }
+
+    pub fn send_chat_action(
+        &self,
+        bot: &Bot,
+        action: ChatAction,
+    ) -> <Bot as Requester>::SendChatAction {
+        let request = bot.send_chat_action(self.chat_id, action);
+        match self.thread_id {
+            Some(thread_id) => request.message_thread_id(thread_id),
+            None => request,
+        }
+    }
+
+    pub fn send_document(
+        &self,
+        bot: &Bot,
+        document: InputFile,
+    ) -> <Bot as Requester>::SendDocument {
+        let request = bot.send_document(self.chat_id, document);
+        match self.thread_id {
+            Some(thread_id) => request.message_thread_id(thread_id),
+            None => request,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(chat: serde_json::Value, topic: bool) -> Message {
+        serde_json::from_value(serde_json::json!({
+            "message_id": 5,
+            "message_thread_id": 4,
+            "is_topic_message": topic,
+            "date": 1675229140,
+            "chat": chat,
+            "from": {"id": 1, "is_bot": false, "first_name": "User"},
+            "text": "a cat",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_send_context_of() {
+        let forum = serde_json::json!({
+            "id": -100, "type": "supergroup", "title": "Forum", "is_forum": true
+        });
+        assert_eq!(
+            SendContext::of(&message(forum, true)),
+            SendContext {
+                chat_id: ChatId(-100),
+                thread_id: Some(4),
+            }
+        );
+
+        let group = serde_json::json!({"id": -200, "type": "supergroup", "title": "Group"});
+        assert_eq!(SendContext::of(&message(group, false)).thread_id, None);
+    }
+}
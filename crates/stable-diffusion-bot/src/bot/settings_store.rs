@@ -0,0 +1,219 @@
+use anyhow::Context;
+use sal_e_api::GenParams;
+use teloxide::types::{ChatId, UserId};
+use tracing::{debug, warn};
+
+use super::{config::BackendHandles, handlers::apply_setting, history::HistoryStore, State};
+
+/// Which parameters a saved setting applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SettingsKind {
+    Txt2Img,
+    Img2Img,
+}
+
+impl SettingsKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Txt2Img => "txt2img",
+            Self::Img2Img => "img2img",
+        }
+    }
+}
+
+/// Returns the settings `user_id` saved for `kind` as `(setting, value)` pairs, in the order
+/// they were first saved.
+pub(crate) async fn load(
+    history: &HistoryStore,
+    user_id: UserId,
+    kind: SettingsKind,
+) -> anyhow::Result<Vec<(String, String)>> {
+    sqlx::query_as(
+        "SELECT setting, value FROM user_settings WHERE user_id = ? AND kind = ? ORDER BY rowid",
+    )
+    .bind(user_id.0 as i64)
+    .bind(kind.as_str())
+    .fetch_all(history.pool())
+    .await
+    .context("Failed to get user settings")
+}
+
+/// Saves the value of a setting `user_id` changed, replacing any earlier value.
+pub(crate) async fn save(
+    history: &HistoryStore,
+    user_id: UserId,
+    kind: SettingsKind,
+    setting: &str,
+    value: &str,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        "INSERT INTO user_settings (user_id, kind, setting, value) VALUES (?, ?, ?, ?)
+        ON CONFLICT (user_id, kind, setting) DO UPDATE SET value = excluded.value",
+    )
+    .bind(user_id.0 as i64)
+    .bind(kind.as_str())
+    .bind(setting)
+    .bind(value)
+    .execute(history.pool())
+    .await
+    .context("Failed to save user setting")?;
+    Ok(())
+}
+
+/// Deletes all of the settings `user_id` saved, returning whether there were any.
+pub(crate) async fn reset(history: &HistoryStore, user_id: UserId) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM user_settings WHERE user_id = ?")
+        .bind(user_id.0 as i64)
+        .execute(history.pool())
+        .await
+        .context("Failed to delete user settings")?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Returns the user a private chat is with. Telegram uses the user's id as the chat's id.
+fn private_chat_user(chat_id: ChatId) -> Option<UserId> {
+    chat_id.is_user().then_some(UserId(chat_id.0 as u64))
+}
+
+/// Saves a setting changed in `chat_id` if it's a private chat. Settings of group chats are
+/// shared by their members, so they only live in the chat's dialogue.
+pub(crate) async fn save_for_chat(
+    history: &HistoryStore,
+    chat_id: ChatId,
+    kind: SettingsKind,
+    setting: &str,
+    value: &str,
+) {
+    let Some(user_id) = private_chat_user(chat_id) else {
+        return;
+    };
+    if let Err(e) = save(history, user_id, kind, setting, value).await {
+        warn!("Failed to save user setting: {:?}", e);
+    }
+}
+
+/// Applies the settings `user_id` saved for `kind` to `params`. Settings the parameters don't
+/// support, e.g. after switching backends, are skipped.
+async fn merge(
+    history: &HistoryStore,
+    user_id: UserId,
+    kind: SettingsKind,
+    params: &mut dyn GenParams,
+) -> anyhow::Result<()> {
+    for (setting, value) in load(history, user_id, kind).await? {
+        if let Err(e) = apply_setting(kind, params, &setting, &value) {
+            debug!("Skipping saved setting {}: {:?}", setting, e);
+        }
+    }
+    Ok(())
+}
+
+/// Returns `params` with the settings `user_id` saved for `kind` applied, for generating with
+/// them in any chat. The chat's own settings are left alone, so the saved settings of one member
+/// don't leak into those of a group.
+pub(crate) async fn with_user_settings<'a>(
+    history: &HistoryStore,
+    user_id: Option<UserId>,
+    kind: SettingsKind,
+    params: &(dyn GenParams + 'a),
+) -> Box<dyn GenParams + 'a> {
+    let mut params = dyn_clone::clone_box(params);
+    if let Some(user_id) = user_id {
+        if let Err(e) = merge(history, user_id, kind, params.as_mut()).await {
+            warn!("Failed to apply user settings: {:?}", e);
+        }
+    }
+    params
+}
+
+/// Returns the state a chat starts in when it has no dialogue state: the defaults, with the
+/// settings saved by the user of a private chat applied, so they survive dialogue resets.
+pub(crate) async fn default_state(
+    backends: &BackendHandles,
+    history: &HistoryStore,
+    chat_id: ChatId,
+) -> State {
+    let mut state = backends.default_state(chat_id);
+    let (
+        Some(user_id),
+        State::Ready {
+            txt2img, img2img, ..
+        },
+    ) = (private_chat_user(chat_id), &mut state)
+    else {
+        return state;
+    };
+    let result = async {
+        merge(history, user_id, SettingsKind::Txt2Img, txt2img.as_mut()).await?;
+        merge(history, user_id, SettingsKind::Img2Img, img2img.as_mut()).await
+    };
+    if let Err(e) = result.await {
+        warn!("Failed to apply user settings: {:?}", e);
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use sal_e_api::Txt2ImgParams;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_save_and_merge() {
+        let history = HistoryStore::open(None).await.unwrap();
+        let user = UserId(1);
+        save(&history, user, SettingsKind::Txt2Img, "steps", "20")
+            .await
+            .unwrap();
+        save(&history, user, SettingsKind::Txt2Img, "steps", "30")
+            .await
+            .unwrap();
+        save(
+            &history,
+            user,
+            SettingsKind::Txt2Img,
+            "restore_faces",
+            "true",
+        )
+        .await
+        .unwrap();
+        save(&history, user, SettingsKind::Txt2Img, "hr_steps", "many")
+            .await
+            .unwrap();
+        assert_eq!(
+            load(&history, user, SettingsKind::Img2Img).await.unwrap(),
+            vec![]
+        );
+
+        let mut params = Txt2ImgParams::default();
+        merge(&history, user, SettingsKind::Txt2Img, &mut params)
+            .await
+            .unwrap();
+        assert_eq!(params.steps(), Some(30));
+        assert_eq!(params.restore_faces(), Some(true));
+
+        assert!(reset(&history, user).await.unwrap());
+        assert!(!reset(&history, user).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_with_user_settings() {
+        let history = HistoryStore::open(None).await.unwrap();
+        save(&history, UserId(1), SettingsKind::Txt2Img, "steps", "30")
+            .await
+            .unwrap();
+        let mut chat = Txt2ImgParams::default();
+        chat.set_steps(10);
+
+        let params =
+            with_user_settings(&history, Some(UserId(1)), SettingsKind::Txt2Img, &chat).await;
+        assert_eq!(params.steps(), Some(30));
+        assert_eq!(chat.steps(), Some(10));
+        let params =
+            with_user_settings(&history, Some(UserId(2)), SettingsKind::Txt2Img, &chat).await;
+        assert_eq!(params.steps(), Some(10));
+        let params = with_user_settings(&history, None, SettingsKind::Txt2Img, &chat).await;
+        assert_eq!(params.steps(), Some(10));
+    }
+}
@@ -0,0 +1,55 @@
+/// Number of pixels the size presets may have unless configured otherwise, that of a 1024×1024
+/// image.
+pub(crate) const DEFAULT_MAX_PIXELS: u32 = 1024 * 1024;
+
+/// Common image sizes offered by the size keyboard, as aspect ratio, width and height. Sides are
+/// multiples of 64 and at most 1024, so they're valid for both txt2img and img2img.
+pub(crate) const PRESETS: [(&str, u32, u32); 9] = [
+    ("1:1", 512, 512),
+    ("1:1", 768, 768),
+    ("1:1", 1024, 1024),
+    ("2:3", 512, 768),
+    ("3:2", 768, 512),
+    ("3:4", 768, 1024),
+    ("4:3", 1024, 768),
+    ("9:16", 576, 1024),
+    ("16:9", 1024, 576),
+];
+
+/// Returns the presets with at most `max_pixels` pixels, or `DEFAULT_MAX_PIXELS` if unset.
+pub(crate) fn available(max_pixels: Option<u32>) -> impl Iterator<Item = (&'static str, u32, u32)> {
+    let max_pixels = max_pixels.unwrap_or(DEFAULT_MAX_PIXELS);
+    PRESETS
+        .into_iter()
+        .filter(move |(_, width, height)| width * height <= max_pixels)
+}
+
+/// Parses the size in the callback data of a preset button, e.g. `768x512`. Only sizes that are
+/// still offered are accepted.
+pub(crate) fn parse(value: &str, max_pixels: Option<u32>) -> Option<(u32, u32)> {
+    let (width, height) = value.split_once('x')?;
+    let size = (width.parse().ok()?, height.parse().ok()?);
+    available(max_pixels)
+        .any(|(_, width, height)| (width, height) == size)
+        .then_some(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_available() {
+        assert_eq!(available(None).count(), PRESETS.len());
+        assert!(available(Some(512 * 768)).all(|(_, width, height)| width * height <= 512 * 768));
+        assert_eq!(available(Some(512 * 768)).count(), 3);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("768x512", None), Some((768, 512)));
+        assert_eq!(parse("1024x1024", Some(768 * 768)), None);
+        assert_eq!(parse("700x500", None), None);
+        assert_eq!(parse("wide", None), None);
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+/// Longest allowed snippet name, in characters.
+pub(crate) const MAX_NAME_LENGTH: usize = 32;
+/// Longest allowed snippet text, in characters.
+pub(crate) const MAX_TEXT_LENGTH: usize = 1000;
+/// Maximum number of snippets a user can save.
+pub(crate) const MAX_SNIPPETS: usize = 50;
+
+lazy_static! {
+    /// Matches a snippet reference such as `{{lighting}}`.
+    static ref REFERENCE_RE: Regex = Regex::new(r"\{\{\s*([\w-]+)\s*\}\}").unwrap();
+}
+
+/// Checks whether `name` can be used as a snippet name.
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().count() <= MAX_NAME_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Checks whether `prompt` references any snippets.
+pub(crate) fn has_references(prompt: &str) -> bool {
+    REFERENCE_RE.is_match(prompt)
+}
+
+/// Replaces each `{{name}}` in `prompt` with the text of the snippet called `name`. Snippet
+/// text is inserted as is, so snippets can't reference each other. Returns the name of the
+/// first unknown snippet if any reference can't be resolved.
+pub(crate) fn expand(prompt: &str, snippets: &HashMap<String, String>) -> Result<String, String> {
+    if let Some(unknown) = REFERENCE_RE
+        .captures_iter(prompt)
+        .map(|captures| captures[1].to_owned())
+        .find(|name| !snippets.contains_key(name))
+    {
+        return Err(unknown);
+    }
+    Ok(REFERENCE_RE
+        .replace_all(prompt, |captures: &Captures| snippets[&captures[1]].clone())
+        .into_owned())
+}
+
+/// An action requested with the `/snippet` command.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum SnippetAction<'a> {
+    Add { name: &'a str, text: &'a str },
+    List,
+    Delete { name: &'a str },
+}
+
+/// Strips a pair of matching quotes from around `text`. Telegram clients may replace straight
+/// quotes with curly ones, so both are accepted.
+pub(crate) fn unquote(text: &str) -> &str {
+    [('"', '"'), ('“', '”')]
+        .into_iter()
+        .find_map(|(open, close)| text.strip_prefix(open)?.strip_suffix(close))
+        .unwrap_or(text)
+}
+
+/// Parses the arguments of the `/snippet` command.
+pub(crate) fn parse_action(args: &str) -> Option<SnippetAction<'_>> {
+    let args = args.trim();
+    let (action, rest) = args
+        .split_once(char::is_whitespace)
+        .map_or((args, ""), |(action, rest)| (action, rest.trim_start()));
+    match action {
+        "add" => {
+            let (name, text) = rest.split_once(char::is_whitespace)?;
+            let text = unquote(text.trim()).trim();
+            (!text.is_empty()).then_some(SnippetAction::Add { name, text })
+        }
+        "list" | "" if rest.is_empty() => Some(SnippetAction::List),
+        "delete" | "remove" if !rest.is_empty() && !rest.contains(char::is_whitespace) => {
+            Some(SnippetAction::Delete { name: rest })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let snippets = HashMap::from([
+            (
+                "lighting".to_owned(),
+                "volumetric light, god rays".to_owned(),
+            ),
+            ("nested".to_owned(), "{{lighting}}".to_owned()),
+        ]);
+        assert_eq!(
+            expand("a castle, {{lighting}}, {{ lighting }}", &snippets),
+            Ok("a castle, volumetric light, god rays, volumetric light, god rays".to_owned())
+        );
+        assert_eq!(
+            expand("{{nested}}", &snippets),
+            Ok("{{lighting}}".to_owned())
+        );
+        assert_eq!(expand("{{missing}}", &snippets), Err("missing".to_owned()));
+        assert_eq!(
+            expand("{not a reference}", &snippets),
+            Ok("{not a reference}".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_parse_action() {
+        assert_eq!(
+            parse_action(r#"add lighting "volumetric light, god rays""#),
+            Some(SnippetAction::Add {
+                name: "lighting",
+                text: "volumetric light, god rays"
+            })
+        );
+        assert_eq!(
+            parse_action("add style “oil painting”"),
+            Some(SnippetAction::Add {
+                name: "style",
+                text: "oil painting"
+            })
+        );
+        assert_eq!(parse_action("add lighting"), None);
+        assert_eq!(parse_action(""), Some(SnippetAction::List));
+        assert_eq!(parse_action("list"), Some(SnippetAction::List));
+        assert_eq!(
+            parse_action("delete lighting"),
+            Some(SnippetAction::Delete { name: "lighting" })
+        );
+        assert_eq!(parse_action("delete"), None);
+        assert_eq!(parse_action("rename a b"), None);
+    }
+
+    #[test]
+    fn test_is_valid_name() {
+        assert!(is_valid_name("soft-light_2"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("two words"));
+        assert!(!is_valid_name(&"a".repeat(MAX_NAME_LENGTH + 1)));
+    }
+}
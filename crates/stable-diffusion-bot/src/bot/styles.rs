@@ -0,0 +1,345 @@
+use itertools::Itertools as _;
+use sal_e_api::GenParams;
+use serde::{Deserialize, Serialize};
+use teloxide::types::{ChatId, UserId};
+use tracing::warn;
+
+use super::{
+    history::HistoryStore,
+    i18n::Translator,
+    policy::{PolicyViolation, PromptKind, PromptPolicy},
+};
+
+/// Longest allowed style name, in characters.
+pub(crate) const MAX_NAME_LENGTH: usize = 32;
+/// Longest allowed text of each part of a style, in characters.
+pub(crate) const MAX_TEXT_LENGTH: usize = 1000;
+/// Maximum number of styles a user can save.
+pub(crate) const MAX_STYLES: usize = 20;
+
+/// Struct that represents a named set of prompt additions and settings that users can select
+/// with the `/style` command.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Style {
+    /// Name used to select the style. Selections are stored by name, so renaming a style
+    /// deselects it in every chat.
+    pub name: String,
+    /// Text put before the user's prompt.
+    #[serde(default)]
+    pub prompt_prefix: Option<String>,
+    /// Text put after the user's prompt.
+    #[serde(default)]
+    pub prompt_suffix: Option<String>,
+    /// Text appended to the user's negative prompt.
+    #[serde(default)]
+    pub negative_prompt: Option<String>,
+    /// CFG scale that replaces the user's.
+    #[serde(default)]
+    pub cfg: Option<f32>,
+    /// Number of steps that replaces the user's.
+    #[serde(default)]
+    pub steps: Option<u32>,
+}
+
+impl Style {
+    /// Applies the style to `params`.
+    fn apply(&self, params: &mut dyn GenParams) {
+        let prompt = params.prompt();
+        params.set_prompt(join_prompt(&[
+            self.prompt_prefix.as_deref(),
+            prompt.as_deref(),
+            self.prompt_suffix.as_deref(),
+        ]));
+        if self.negative_prompt.is_some() {
+            let negative_prompt = params.negative_prompt();
+            params.set_negative_prompt(join_prompt(&[
+                negative_prompt.as_deref(),
+                self.negative_prompt.as_deref(),
+            ]));
+        }
+        if let Some(cfg) = self.cfg {
+            params.set_cfg(cfg);
+        }
+        if let Some(steps) = self.steps {
+            params.set_steps(steps);
+        }
+    }
+
+    /// Checks the texts of the style against `policy`, so saved styles can't add banned words
+    /// to prompts.
+    pub(crate) fn check_policy(&self, policy: &PromptPolicy) -> Result<(), PolicyViolation> {
+        for text in [&self.prompt_prefix, &self.prompt_suffix]
+            .into_iter()
+            .flatten()
+        {
+            policy.check(PromptKind::Prompt, text)?;
+        }
+        if let Some(negative_prompt) = &self.negative_prompt {
+            policy.check(PromptKind::NegativePrompt, negative_prompt)?;
+        }
+        Ok(())
+    }
+
+    /// Returns a description of what the style changes, one part per line.
+    pub(crate) fn summary(&self, translator: &Translator) -> String {
+        let line = |text: &str, value: &str| translator.format(text, &[("value", value)]);
+        [
+            self.prompt_prefix
+                .as_ref()
+                .map(|prefix| line("Prefix: {value}", prefix)),
+            self.prompt_suffix
+                .as_ref()
+                .map(|suffix| line("Suffix: {value}", suffix)),
+            self.negative_prompt
+                .as_ref()
+                .map(|negative| line("Negative: {value}", negative)),
+            self.cfg.map(|cfg| line("CFG: {value}", &cfg.to_string())),
+            self.steps
+                .map(|steps| line("Steps: {value}", &steps.to_string())),
+        ]
+        .into_iter()
+        .flatten()
+        .join("\n")
+    }
+}
+
+/// Joins the parts of a prompt, skipping empty ones.
+fn join_prompt(parts: &[Option<&str>]) -> String {
+    parts
+        .iter()
+        .flatten()
+        .map(|part| part.trim())
+        .filter(|part| !part.is_empty())
+        .join(", ")
+}
+
+/// Returns a copy of `params` with `style` applied, leaving the user's own settings unchanged.
+pub(crate) fn with_style<'a>(
+    params: &(dyn GenParams + 'a),
+    style: Option<&Style>,
+) -> Box<dyn GenParams + 'a> {
+    let mut params = dyn_clone::clone_box(params);
+    if let Some(style) = style {
+        style.apply(params.as_mut());
+    }
+    params
+}
+
+/// Returns the styles `user_id` can select: the configured ones followed by their own. Failures
+/// are logged and treated as the user having no styles of their own.
+pub(crate) async fn available_styles(
+    styles: &[Style],
+    history: &HistoryStore,
+    user_id: Option<UserId>,
+) -> Vec<Style> {
+    let mut available = styles.to_vec();
+    if let Some(user_id) = user_id {
+        match history.styles(user_id).await {
+            Ok(own) => available.extend(own),
+            Err(e) => warn!("Failed to get styles: {:?}", e),
+        }
+    }
+    available
+}
+
+/// Returns the style selected in a chat, looked up among the styles `user_id` can select.
+/// Failures are logged and treated as no style being selected, so generation can continue.
+pub(crate) async fn selected_style(
+    styles: &[Style],
+    history: &HistoryStore,
+    chat_id: ChatId,
+    user_id: Option<UserId>,
+) -> Option<Style> {
+    let name = history.chat_style(chat_id).await.unwrap_or_else(|e| {
+        warn!("Failed to get the selected style: {:?}", e);
+        None
+    })?;
+    available_styles(styles, history, user_id)
+        .await
+        .into_iter()
+        .find(|style| style.name == name)
+}
+
+/// Checks whether `name` can be used as a style name.
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().count() <= MAX_NAME_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+/// An action requested with the `/style` command.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum StyleAction<'a> {
+    Menu,
+    Select { name: &'a str },
+    Off,
+    Add(Style),
+    List,
+    Delete { name: &'a str },
+}
+
+/// Splits the value at the start of `text` from the rest. Values are either quoted, with
+/// straight or curly quotes, or end at the next whitespace.
+fn split_value(text: &str) -> Option<(&str, &str)> {
+    for (open, close) in [('"', '"'), ('“', '”')] {
+        if let Some(quoted) = text.strip_prefix(open) {
+            let (value, rest) = quoted.split_once(close)?;
+            return Some((value, rest));
+        }
+    }
+    Some(text.split_once(char::is_whitespace).unwrap_or((text, "")))
+}
+
+/// Parses the `key=value` pairs of a style called `name`.
+fn parse_style(name: &str, mut args: &str) -> Option<Style> {
+    let empty = Style {
+        name: name.to_owned(),
+        ..Default::default()
+    };
+    let mut style = empty.clone();
+    loop {
+        args = args.trim_start();
+        if args.is_empty() {
+            break;
+        }
+        let (key, rest) = args.split_once('=')?;
+        let (value, rest) = split_value(rest)?;
+        let text = Some(value.trim().to_owned()).filter(|value| !value.is_empty());
+        match key {
+            "prefix" => style.prompt_prefix = text,
+            "suffix" => style.prompt_suffix = text,
+            "negative" => style.negative_prompt = text,
+            "cfg" => style.cfg = Some(value.parse().ok()?),
+            "steps" => style.steps = Some(value.parse().ok()?),
+            _ => return None,
+        }
+        args = rest;
+    }
+    (style != empty).then_some(style)
+}
+
+/// Parses the arguments of the `/style` command.
+pub(crate) fn parse_action(args: &str) -> Option<StyleAction<'_>> {
+    let args = args.trim();
+    let (action, rest) = args
+        .split_once(char::is_whitespace)
+        .map_or((args, ""), |(action, rest)| (action, rest.trim_start()));
+    match action {
+        "" => Some(StyleAction::Menu),
+        "off" | "none" if rest.is_empty() => Some(StyleAction::Off),
+        "list" if rest.is_empty() => Some(StyleAction::List),
+        "add" => {
+            let (name, args) = rest.split_once(char::is_whitespace)?;
+            parse_style(name, args).map(StyleAction::Add)
+        }
+        "delete" | "remove" if !rest.is_empty() && !rest.contains(char::is_whitespace) => {
+            Some(StyleAction::Delete { name: rest })
+        }
+        name if rest.is_empty() => Some(StyleAction::Select { name }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sal_e_api::Txt2ImgParams;
+
+    use super::*;
+    use crate::bot::policy::BannedWord;
+
+    #[test]
+    fn test_with_style() {
+        let mut params = Txt2ImgParams::default();
+        params.set_prompt("a castle".to_owned());
+        params.set_negative_prompt("blurry".to_owned());
+        params.set_steps(20);
+        let style = Style {
+            name: "painting".to_owned(),
+            prompt_prefix: Some("oil painting of".to_owned()),
+            prompt_suffix: Some("impasto".to_owned()),
+            negative_prompt: Some("photo".to_owned()),
+            cfg: Some(9.0),
+            steps: None,
+        };
+
+        let styled = with_style(&params, Some(&style));
+        assert_eq!(
+            styled.prompt().as_deref(),
+            Some("oil painting of, a castle, impasto")
+        );
+        assert_eq!(styled.negative_prompt().as_deref(), Some("blurry, photo"));
+        assert_eq!(styled.cfg(), Some(9.0));
+        assert_eq!(styled.steps(), Some(20));
+        assert_eq!(params.prompt().as_deref(), Some("a castle"));
+
+        let unstyled = with_style(&params, None);
+        assert_eq!(unstyled.prompt().as_deref(), Some("a castle"));
+    }
+
+    #[test]
+    fn test_style_policy() {
+        let policy = PromptPolicy {
+            max_prompt_length: None,
+            banned_words: vec![BannedWord::Word("gore".to_owned())],
+        };
+        let style = Style {
+            name: "horror".to_owned(),
+            prompt_suffix: Some("lots of gore".to_owned()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            style.check_policy(&policy),
+            Err(PolicyViolation::BannedWord {
+                kind: PromptKind::Prompt,
+                ..
+            })
+        ));
+
+        let mut params = Txt2ImgParams::default();
+        params.set_prompt("a castle".to_owned());
+        assert!(policy.check_params(&params).is_ok());
+        assert!(policy
+            .check_params(with_style(&params, Some(&style)).as_ref())
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_action() {
+        assert_eq!(parse_action(""), Some(StyleAction::Menu));
+        assert_eq!(
+            parse_action("anime"),
+            Some(StyleAction::Select { name: "anime" })
+        );
+        assert_eq!(parse_action("off"), Some(StyleAction::Off));
+        assert_eq!(parse_action("list"), Some(StyleAction::List));
+        assert_eq!(
+            parse_action(r#"add noir prefix="film noir, black and white" negative=color steps=30"#),
+            Some(StyleAction::Add(Style {
+                name: "noir".to_owned(),
+                prompt_prefix: Some("film noir, black and white".to_owned()),
+                negative_prompt: Some("color".to_owned()),
+                steps: Some(30),
+                ..Default::default()
+            }))
+        );
+        assert_eq!(
+            parse_action("add noir suffix=“grainy film”"),
+            Some(StyleAction::Add(Style {
+                name: "noir".to_owned(),
+                prompt_suffix: Some("grainy film".to_owned()),
+                ..Default::default()
+            }))
+        );
+        assert_eq!(parse_action("add noir"), None);
+        assert_eq!(parse_action("add noir cfg=high"), None);
+        assert_eq!(parse_action("add noir color=none"), None);
+        assert_eq!(parse_action(r#"add noir prefix="unclosed"#), None);
+        assert_eq!(
+            parse_action("delete noir"),
+            Some(StyleAction::Delete { name: "noir" })
+        );
+        assert_eq!(parse_action("two words"), None);
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    any::Any,
+    collections::VecDeque,
+    future::Future,
+    ops::ControlFlow,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+use futures::FutureExt as _;
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    dispatching::{DpHandlerDescription, ShutdownToken, UpdateHandler},
+    dptree::{di::DependencySupplier, HandlerDescription},
+    prelude::*,
+    types::UpdateKind,
+};
+use tracing::{error, info, warn};
+
+use super::{config::UiConfig, history::HistoryStore, i18n, metrics::Metrics, send::SendContext};
+
+/// Delay before restarting the dispatcher after a panic.
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Struct that represents the configuration for restarting the dispatcher after a panic.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SupervisorConfig {
+    /// Maximum number of restarts allowed within `restart_window_secs` before the bot exits.
+    #[serde(default = "default_max_restarts")]
+    pub max_restarts: usize,
+    /// Length of the window, in seconds, in which restarts are counted.
+    #[serde(default = "default_restart_window_secs")]
+    pub restart_window_secs: u64,
+}
+
+fn default_max_restarts() -> usize {
+    5
+}
+
+fn default_restart_window_secs() -> u64 {
+    300
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            max_restarts: default_max_restarts(),
+            restart_window_secs: default_restart_window_secs(),
+        }
+    }
+}
+
+/// Circuit breaker tracking restarts within a sliding window.
+#[derive(Debug)]
+struct RestartBudget {
+    max_restarts: usize,
+    window: Duration,
+    restarts: VecDeque<Instant>,
+}
+
+impl RestartBudget {
+    fn new(config: &SupervisorConfig) -> Self {
+        Self {
+            max_restarts: config.max_restarts,
+            window: Duration::from_secs(config.restart_window_secs),
+            restarts: VecDeque::new(),
+        }
+    }
+
+    /// Records a restart at `now`, returning whether it is within the budget.
+    fn record(&mut self, now: Instant) -> bool {
+        while self
+            .restarts
+            .front()
+            .is_some_and(|restart| now.duration_since(*restart) > self.window)
+        {
+            self.restarts.pop_front();
+        }
+        self.restarts.push_back(now);
+        self.restarts.len() <= self.max_restarts
+    }
+}
+
+/// Runs the task produced by `run` until it completes, restarting it whenever it panics.
+///
+/// # Errors
+///
+/// Returns an error if the task restarts more than `max_restarts` times within the configured
+/// window, or if it is cancelled.
+pub(crate) async fn supervise<F, Fut>(
+    config: &SupervisorConfig,
+    metrics: &Metrics,
+    mut run: F,
+) -> anyhow::Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut budget = RestartBudget::new(config);
+    loop {
+        match tokio::spawn(run()).await {
+            Ok(()) => return Ok(()),
+            Err(e) if e.is_panic() => {
+                metrics.dispatcher_restarts.fetch_add(1, Ordering::Relaxed);
+                error!("Dispatcher panicked: {:?}", e);
+                if !budget.record(Instant::now()) {
+                    return Err(anyhow!(
+                        "Dispatcher restarted more than {} times within {} seconds, giving up",
+                        config.max_restarts,
+                        config.restart_window_secs
+                    ));
+                }
+                warn!("Restarting dispatcher in {:?}", RESTART_DELAY);
+                tokio::time::sleep(RESTART_DELAY).await;
+            }
+            Err(e) => return Err(e).context("Dispatcher task was cancelled"),
+        }
+    }
+}
+
+/// Returns the message a panic was raised with, if it has one.
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Tells the user whose update a handler panicked on that something went wrong.
+async fn reply_to_panicked(deps: &DependencyMap) -> anyhow::Result<()> {
+    let bot: Arc<Bot> = deps.get();
+    let update: Arc<Update> = deps.get();
+    let ui: Arc<Arc<UiConfig>> = deps.get();
+    let history: Arc<HistoryStore> = deps.get();
+    let translator = i18n::translator_for(&ui, &history, update.user().map(|user| user.id)).await;
+    let text = translator.text("Sorry, something went wrong.");
+    match &update.kind {
+        UpdateKind::Message(msg) => {
+            SendContext::of(msg)
+                .send_message(&bot, text)
+                .reply_to_message_id(msg.id)
+                .await?;
+        }
+        UpdateKind::CallbackQuery(q) => {
+            bot.answer_callback_query(&q.id).text(text).await?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Wraps `handler` so that a panic while handling an update is caught and returned as an error,
+/// after telling the user that something went wrong.
+///
+/// The dispatcher handles each chat's updates in a worker task of its own. Without this, a panic
+/// kills the worker, and the dispatcher panics when the next update for the chat arrives, losing
+/// that update too.
+pub(crate) fn catch_panics(handler: UpdateHandler<anyhow::Error>) -> UpdateHandler<anyhow::Error> {
+    let description = DpHandlerDescription::entry().merge_chain(handler.description());
+    dptree::from_fn_with_description(description, move |deps: DependencyMap, cont| {
+        let handler = handler.clone();
+        async move {
+            let result = AssertUnwindSafe(handler.execute(deps.clone(), cont))
+                .catch_unwind()
+                .await;
+            match result {
+                Ok(flow) => flow,
+                Err(panic) => {
+                    let message = panic_message(panic.as_ref()).to_owned();
+                    if let Err(e) = reply_to_panicked(&deps).await {
+                        warn!("Failed to reply after handler panicked: {:?}", e);
+                    }
+                    ControlFlow::Break(Err(anyhow!("Handler panicked: {message}")))
+                }
+            }
+        }
+    })
+}
+
+/// Shuts the running dispatcher down on ^C. It is installed once and follows the dispatcher
+/// across restarts, as a dispatcher's own handler would be installed again on every restart.
+#[derive(Clone, Default)]
+pub(crate) struct CtrlCHandler {
+    /// Shutdown token of the running dispatcher.
+    token: Arc<Mutex<Option<ShutdownToken>>>,
+    /// Whether ^C was received, after which no dispatcher is started.
+    stopping: Arc<AtomicBool>,
+}
+
+impl CtrlCHandler {
+    /// Installs the handler.
+    pub fn install() -> Self {
+        let handler = Self::default();
+        tokio::spawn({
+            let handler = handler.clone();
+            async move {
+                loop {
+                    if let Err(e) = tokio::signal::ctrl_c().await {
+                        error!("Failed to listen for ^C: {:?}", e);
+                        return;
+                    }
+                    handler.stopping.store(true, Ordering::SeqCst);
+                    let Some(token) = handler.token.lock().unwrap().clone() else {
+                        continue;
+                    };
+                    let shutdown = token.shutdown();
+                    match shutdown {
+                        Ok(shutdown) => {
+                            info!("^C received, shutting down the dispatcher");
+                            shutdown.await;
+                        }
+                        Err(_) => info!("^C received while the dispatcher isn't running"),
+                    }
+                }
+            }
+        });
+        handler
+    }
+
+    /// Makes ^C shut down the dispatcher with `token`. Returns whether it should run, which it
+    /// shouldn't if ^C was already received.
+    pub fn watch(&self, token: ShutdownToken) -> bool {
+        *self.token.lock().unwrap() = Some(token);
+        !self.stopping.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_restart_budget() {
+        let mut budget = RestartBudget::new(&SupervisorConfig {
+            max_restarts: 2,
+            restart_window_secs: 60,
+        });
+        let start = Instant::now();
+        assert!(budget.record(start));
+        assert!(budget.record(start + Duration::from_secs(10)));
+        assert!(!budget.record(start + Duration::from_secs(20)));
+        // The first two restarts have left the window.
+        assert!(budget.record(start + Duration::from_secs(75)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_supervise_restarts_after_panic() {
+        let metrics = Metrics::default();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let result = supervise(&SupervisorConfig::default(), &metrics, || {
+            let runs = runs.clone();
+            async move {
+                if runs.fetch_add(1, Ordering::SeqCst) < 2 {
+                    panic!("handler panicked");
+                }
+            }
+        })
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        assert_eq!(metrics.dispatcher_restarts.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_supervise_circuit_breaker() {
+        let metrics = Metrics::default();
+        let config = SupervisorConfig {
+            max_restarts: 1,
+            restart_window_secs: 60,
+        };
+        let result = supervise(&config, &metrics, || async { panic!("handler panicked") }).await;
+        assert!(result.is_err());
+        assert_eq!(metrics.dispatcher_restarts.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_catch_panics() {
+        let handler: UpdateHandler<anyhow::Error> = catch_panics(
+            Update::filter_message().endpoint(|| async { panic!("handler panicked") }),
+        );
+        let msg = serde_json::from_value(serde_json::json!({
+            "message_id": 5,
+            "date": 1675229140,
+            "chat": {"id": 1, "type": "private", "first_name": "User"},
+            "from": {"id": 1, "is_bot": false, "first_name": "User"},
+            "text": "a cat",
+        }))
+        .unwrap();
+        let update = Update {
+            id: 1,
+            kind: UpdateKind::Message(msg),
+        };
+        // Nothing listens on the port, so the reply fails without reaching Telegram.
+        let bot = Bot::new("token").set_api_url("http://127.0.0.1:9".parse().unwrap());
+        let history = HistoryStore::open(None).await.unwrap();
+        let ui = Arc::new(UiConfig::default());
+
+        let result = handler
+            .dispatch(dptree::deps![bot, update, ui, history])
+            .await;
+        match result {
+            ControlFlow::Break(Err(e)) => assert!(e.to_string().contains("handler panicked")),
+            _ => panic!("expected the panic to be returned as an error"),
+        }
+    }
+}
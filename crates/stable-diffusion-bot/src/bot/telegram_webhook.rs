@@ -0,0 +1,224 @@
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+use anyhow::{anyhow, Context};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::post,
+    Router,
+};
+use futures::{stream, Stream, StreamExt};
+use rand::{distributions::Alphanumeric, Rng};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    prelude::*,
+    stop::{mk_stop_token, StopFlag, StopToken},
+    types::Update,
+    update_listeners::{StatefulListener, UpdateListener},
+};
+use tokio::sync::{
+    mpsc::{self, UnboundedReceiver, UnboundedSender},
+    Mutex, OwnedMutexGuard,
+};
+use tracing::{error, info, warn};
+
+/// Header Telegram sends the secret token in.
+const SECRET_TOKEN_HEADER: &str = "x-telegram-bot-api-secret-token";
+
+/// Length of generated secret tokens.
+const SECRET_TOKEN_LENGTH: usize = 32;
+
+/// Struct that represents the configuration for receiving updates from Telegram via a webhook
+/// instead of polling.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TelegramWebhookConfig {
+    /// Address the webhook server listens on, e.g. `127.0.0.1:8443`.
+    pub listen_address: SocketAddr,
+    /// Public HTTPS URL Telegram sends updates to, e.g. `https://bot.example.com/telegram`.
+    pub url: String,
+    /// Path the server accepts updates on. Defaults to the path of `url`; set it if a reverse
+    /// proxy rewrites the path.
+    pub path: Option<String>,
+    /// Secret token Telegram sends with every update. If unset, a random token is generated on
+    /// every start.
+    pub secret_token: Option<String>,
+}
+
+/// Server receiving updates from Telegram via a webhook.
+#[derive(Debug, Clone)]
+pub(crate) struct TelegramWebhook {
+    listen_address: SocketAddr,
+    url: Url,
+    path: String,
+    secret_token: String,
+}
+
+impl TelegramWebhook {
+    /// Constructs a new `TelegramWebhook` from its configuration.
+    pub fn new(config: &TelegramWebhookConfig) -> anyhow::Result<Self> {
+        let url = Url::parse(&config.url).context("Invalid Telegram webhook URL")?;
+        let path = config.path.clone().unwrap_or_else(|| url.path().to_owned());
+        if !path.starts_with('/') {
+            return Err(anyhow!("Telegram webhook path must start with /: {}", path));
+        }
+        let secret_token = config.secret_token.clone().unwrap_or_else(|| {
+            rand::thread_rng()
+                .sample_iter(Alphanumeric)
+                .take(SECRET_TOKEN_LENGTH)
+                .map(char::from)
+                .collect()
+        });
+        Ok(Self {
+            listen_address: config.listen_address,
+            url,
+            path,
+            secret_token,
+        })
+    }
+
+    /// Starts the webhook server and registers the webhook with Telegram, returning the updates
+    /// it receives.
+    pub async fn start(self, bot: &Bot) -> anyhow::Result<WebhookUpdates> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let router = Router::new()
+            .route(&self.path, post(receive_update))
+            .with_state(Arc::new(WebhookState {
+                secret_token: self.secret_token.clone(),
+                sender,
+            }));
+        let server = axum::Server::try_bind(&self.listen_address)
+            .context("Failed to bind Telegram webhook server")?
+            .serve(router.into_make_service());
+        info!(
+            "Telegram webhook server listening on {}{}",
+            self.listen_address, self.path
+        );
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                error!("Telegram webhook server failed: {:?}", e);
+            }
+        });
+
+        bot.set_webhook(self.url)
+            .secret_token(self.secret_token)
+            .await
+            .context("Failed to set Telegram webhook")?;
+
+        Ok(WebhookUpdates {
+            receiver: Arc::new(Mutex::new(receiver)),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct WebhookState {
+    secret_token: String,
+    sender: UnboundedSender<Update>,
+}
+
+/// Checks whether a request carries the secret token the webhook was registered with.
+fn is_authorized(headers: &HeaderMap, secret_token: &str) -> bool {
+    headers
+        .get(SECRET_TOKEN_HEADER)
+        .is_some_and(|value| value.as_bytes() == secret_token.as_bytes())
+}
+
+async fn receive_update(
+    State(state): State<Arc<WebhookState>>,
+    headers: HeaderMap,
+    body: String,
+) -> StatusCode {
+    if !is_authorized(&headers, &state.secret_token) {
+        warn!("Rejected Telegram webhook request without a valid secret token");
+        return StatusCode::UNAUTHORIZED;
+    }
+    // Telegram retries updates it gets an error for, which wouldn't help with one that can't be
+    // parsed, so it's logged and acknowledged.
+    match serde_json::from_str::<Update>(&body) {
+        Ok(update) => {
+            if state.sender.send(update).is_err() {
+                return StatusCode::SERVICE_UNAVAILABLE;
+            }
+        }
+        Err(e) => error!("Failed to parse Telegram update: {:?}\n{}", e, body),
+    }
+    StatusCode::OK
+}
+
+/// Updates received by the webhook server. They're queued while the dispatcher restarts.
+#[derive(Clone)]
+pub(crate) struct WebhookUpdates {
+    receiver: Arc<Mutex<UnboundedReceiver<Update>>>,
+}
+
+struct ListenerState {
+    receiver: OwnedMutexGuard<UnboundedReceiver<Update>>,
+    stop_token: StopToken,
+    stop_flag: StopFlag,
+}
+
+fn update_stream(
+    state: &mut ListenerState,
+) -> impl Stream<Item = Result<Update, Infallible>> + Send + '_ {
+    let stop_flag = state.stop_flag.clone();
+    let receiver = &mut state.receiver;
+    stream::poll_fn(move |cx| receiver.poll_recv(cx))
+        .map(Ok)
+        .take_until(stop_flag)
+}
+
+impl WebhookUpdates {
+    /// Returns an `UpdateListener` for a dispatcher. Only one listener receives updates at a
+    /// time; this waits until the previous one is dropped.
+    pub async fn listener(&self) -> impl UpdateListener<Err = Infallible> {
+        let (stop_token, stop_flag) = mk_stop_token();
+        let state = ListenerState {
+            receiver: self.receiver.clone().lock_owned().await,
+            stop_token,
+            stop_flag,
+        };
+        StatefulListener::new(state, update_stream, |state: &mut ListenerState| {
+            state.stop_token.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(url: &str, path: Option<&str>) -> TelegramWebhookConfig {
+        TelegramWebhookConfig {
+            listen_address: "127.0.0.1:8443".parse().unwrap(),
+            url: url.to_owned(),
+            path: path.map(str::to_owned),
+            secret_token: None,
+        }
+    }
+
+    #[test]
+    fn test_telegram_webhook_path() {
+        let webhook = TelegramWebhook::new(&config("https://example.com/telegram", None)).unwrap();
+        assert_eq!(webhook.path, "/telegram");
+        assert_eq!(webhook.secret_token.len(), SECRET_TOKEN_LENGTH);
+
+        let webhook =
+            TelegramWebhook::new(&config("https://example.com/bot/telegram", Some("/hook")))
+                .unwrap();
+        assert_eq!(webhook.path, "/hook");
+
+        assert!(TelegramWebhook::new(&config("https://example.com/", Some("hook"))).is_err());
+        assert!(TelegramWebhook::new(&config("not a url", None)).is_err());
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        let mut headers = HeaderMap::new();
+        assert!(!is_authorized(&headers, "secret"));
+        headers.insert(SECRET_TOKEN_HEADER, "wrong".parse().unwrap());
+        assert!(!is_authorized(&headers, "secret"));
+        headers.insert(SECRET_TOKEN_HEADER, "secret".parse().unwrap());
+        assert!(is_authorized(&headers, "secret"));
+    }
+}
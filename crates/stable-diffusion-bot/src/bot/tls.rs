@@ -0,0 +1,59 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// Enum representing the TLS implementation used for HTTPS connections to Telegram, the
+/// backends, the vision API and webhooks.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TlsBackend {
+    /// The platform's TLS library: OpenSSL on Linux, and the system's trust store. Only
+    /// available when built with the `native-tls` feature.
+    Native,
+    /// rustls with the bundled Mozilla root certificates, which doesn't depend on the system's
+    /// TLS library or certificates being installed, e.g. in minimal containers.
+    #[default]
+    Rustls,
+}
+
+impl TlsBackend {
+    /// Configures `builder` to use this TLS implementation, failing if it isn't compiled in.
+    pub(crate) fn configure(
+        self,
+        builder: reqwest::ClientBuilder,
+    ) -> anyhow::Result<reqwest::ClientBuilder> {
+        match self {
+            #[cfg(feature = "native-tls")]
+            TlsBackend::Native => Ok(builder.use_native_tls()),
+            #[cfg(not(feature = "native-tls"))]
+            TlsBackend::Native => Err(anyhow::anyhow!(
+                "The native TLS backend requires building with the native-tls feature"
+            )),
+            TlsBackend::Rustls => Ok(builder.use_rustls_tls()),
+        }
+    }
+
+    /// Returns a client with default settings that uses this TLS implementation.
+    pub(crate) fn client(self) -> anyhow::Result<reqwest::Client> {
+        self.configure(reqwest::Client::builder())?
+            .build()
+            .with_context(|| format!("Failed to create HTTP client with {self:?} TLS"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client() {
+        assert_eq!(
+            TlsBackend::Native.client().is_ok(),
+            cfg!(feature = "native-tls")
+        );
+        assert!(TlsBackend::Rustls.client().is_ok());
+        assert_eq!(
+            serde_json::from_str::<TlsBackend>(r#""rustls""#).unwrap(),
+            TlsBackend::Rustls
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+
+use super::i18n::Translator;
+
+/// How the bot responds to commands it doesn't know.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UnknownCommandMode {
+    /// Ignore unknown commands, only logging them.
+    #[default]
+    Silent,
+    /// Reply that the command is unknown and point to `/help`.
+    Reply,
+    /// Reply with the closest known command, falling back to pointing to `/help`.
+    Suggest,
+}
+
+impl UnknownCommandMode {
+    /// Returns the reply to the unknown command `name`, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the command, without the leading `/`.
+    /// * `commands` - The names of the commands the bot knows.
+    /// * `translator` - Translates the reply into the user's language.
+    pub(crate) fn reply<'a>(
+        &self,
+        name: &str,
+        commands: impl IntoIterator<Item = &'a str>,
+        translator: &Translator,
+    ) -> Option<String> {
+        let suggestion = match self {
+            UnknownCommandMode::Silent => return None,
+            UnknownCommandMode::Reply => None,
+            UnknownCommandMode::Suggest => closest_command(name, commands),
+        };
+        Some(match suggestion {
+            Some(suggestion) => translator.format(
+                "Unknown command /{name}. Did you mean /{suggestion}?",
+                &[("name", name), ("suggestion", suggestion)],
+            ),
+            None => translator.format(
+                "Unknown command /{name}. Send /help to see the available commands.",
+                &[("name", name)],
+            ),
+        })
+    }
+}
+
+/// Returns the name of the command at the start of `text`, if it's addressed to the bot.
+///
+/// Commands addressed to another bot with `/command@other_bot` are skipped. If
+/// `require_mention` is set, as in group chats where other bots may be listening, only commands
+/// addressed to the bot by name are returned.
+pub(crate) fn command_name<'a>(
+    text: &'a str,
+    bot_name: &str,
+    require_mention: bool,
+) -> Option<&'a str> {
+    let command = text.split_whitespace().next()?.strip_prefix('/')?;
+    let (name, mention) = match command.split_once('@') {
+        Some((name, mention)) => (name, Some(mention)),
+        None => (command, None),
+    };
+    match mention {
+        Some(mention) if !mention.eq_ignore_ascii_case(bot_name) => None,
+        None if require_mention => None,
+        _ => (!name.is_empty()).then_some(name),
+    }
+}
+
+/// Returns the command in `commands` that's closest to `name`, if any is close enough to be a
+/// likely typo.
+fn closest_command<'a>(name: &str, commands: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let name = name.to_lowercase();
+    let max_distance = (name.chars().count() / 3).max(1);
+    commands
+        .into_iter()
+        .map(|command| (edit_distance(&name, command), command))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, command)| command)
+}
+
+/// Returns the Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMANDS: [&str; 4] = ["help", "settings", "snippet", "gen"];
+
+    #[test]
+    fn test_command_name() {
+        assert_eq!(command_name("/sttings", "sdbot", false), Some("sttings"));
+        assert_eq!(command_name("/foo@SDBot bar", "sdbot", true), Some("foo"));
+        assert_eq!(command_name("/foo@otherbot", "sdbot", false), None);
+        assert_eq!(command_name("/foo", "sdbot", true), None);
+        assert_eq!(command_name("/ foo", "sdbot", false), None);
+        assert_eq!(command_name("foo", "sdbot", false), None);
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("settings", "settings"), 0);
+        assert_eq!(edit_distance("sttings", "settings"), 1);
+        assert_eq!(edit_distance("gne", "gen"), 2);
+        assert_eq!(edit_distance("", "gen"), 3);
+    }
+
+    #[test]
+    fn test_reply() {
+        let translator = Translator::default();
+        assert_eq!(
+            UnknownCommandMode::Silent.reply("sttings", COMMANDS, &translator),
+            None
+        );
+        assert_eq!(
+            UnknownCommandMode::Reply
+                .reply("sttings", COMMANDS, &translator)
+                .unwrap(),
+            "Unknown command /sttings. Send /help to see the available commands."
+        );
+        assert_eq!(
+            UnknownCommandMode::Suggest
+                .reply("Sttings", COMMANDS, &translator)
+                .unwrap(),
+            "Unknown command /Sttings. Did you mean /settings?"
+        );
+        assert_eq!(
+            UnknownCommandMode::Suggest
+                .reply("frobnicate", COMMANDS, &translator)
+                .unwrap(),
+            "Unknown command /frobnicate. Send /help to see the available commands."
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use sal_e_api::UpscaleApi;
+use serde::{Deserialize, Serialize};
+
+/// Struct that represents the configuration for the upscale buttons on generated images. Only
+/// the Stable Diffusion WebUI backend supports upscaling.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UpscaleConfig {
+    /// Name of the upscaler model, as listed in the WebUI's extras tab.
+    #[serde(default = "default_upscaler")]
+    pub upscaler: String,
+    /// Factors offered as buttons. No buttons are shown if empty.
+    #[serde(default = "default_scales")]
+    pub scales: Vec<u32>,
+}
+
+fn default_upscaler() -> String {
+    "R-ESRGAN 4x+".to_owned()
+}
+
+fn default_scales() -> Vec<u32> {
+    vec![2, 4]
+}
+
+impl Default for UpscaleConfig {
+    fn default() -> Self {
+        Self {
+            upscaler: default_upscaler(),
+            scales: default_scales(),
+        }
+    }
+}
+
+/// Upscales images that users pick from the replies.
+#[derive(Debug, Clone)]
+pub(crate) struct ImageUpscaler {
+    api: Box<dyn UpscaleApi>,
+    config: UpscaleConfig,
+}
+
+impl ImageUpscaler {
+    /// Constructs a new `ImageUpscaler`, or returns `None` if no scales are configured.
+    pub fn new(api: Box<dyn UpscaleApi>, config: UpscaleConfig) -> Option<Self> {
+        (!config.scales.is_empty()).then_some(Self { api, config })
+    }
+
+    /// Returns the factors offered as buttons.
+    pub fn scales(&self) -> &[u32] {
+        &self.config.scales
+    }
+
+    /// Upscales an encoded image by `scale`, which must be one of the configured factors.
+    pub async fn upscale(&self, image: &[u8], scale: u32) -> anyhow::Result<Vec<u8>> {
+        anyhow::ensure!(
+            self.config.scales.contains(&scale),
+            "Scale {scale} is not enabled"
+        );
+        Ok(self
+            .api
+            .upscale(image, scale as f32, &self.config.upscaler)
+            .await?)
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+use super::snippets::unquote;
+
+/// Longest allowed variable name, in characters.
+pub(crate) const MAX_NAME_LENGTH: usize = 32;
+/// Longest allowed variable value, in characters.
+pub(crate) const MAX_VALUE_LENGTH: usize = 500;
+/// Maximum number of variables a chat can set.
+pub(crate) const MAX_VARIABLES: usize = 50;
+
+lazy_static! {
+    /// Matches a variable reference such as `$character`.
+    static ref REFERENCE_RE: Regex = Regex::new(r"\$([A-Za-z_][A-Za-z0-9_]*)").unwrap();
+}
+
+/// Checks whether `name` can be used as a variable name.
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    name.chars().count() <= MAX_NAME_LENGTH
+        && REFERENCE_RE
+            .find(&format!("${name}"))
+            .is_some_and(|m| m.len() == name.len() + 1)
+}
+
+/// Replaces each `$name` in `prompt` with the value of the variable called `name`. References
+/// to variables that aren't set are left as they are, since prompts may contain a literal `$`.
+pub(crate) fn expand(prompt: &str, variables: &HashMap<String, String>) -> String {
+    REFERENCE_RE
+        .replace_all(prompt, |captures: &Captures| {
+            variables
+                .get(&captures[1])
+                .cloned()
+                .unwrap_or_else(|| captures[0].to_owned())
+        })
+        .into_owned()
+}
+
+/// An action requested with the `/var` command.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum VarAction<'a> {
+    Set { name: &'a str, value: &'a str },
+    List,
+    Unset { name: &'a str },
+}
+
+/// Parses the arguments of the `/var` command.
+pub(crate) fn parse_action(args: &str) -> Option<VarAction<'_>> {
+    let args = args.trim();
+    let (action, rest) = args
+        .split_once(char::is_whitespace)
+        .map_or((args, ""), |(action, rest)| (action, rest.trim_start()));
+    match action {
+        "set" => {
+            let (name, value) = rest.split_once(char::is_whitespace)?;
+            let value = unquote(value.trim()).trim();
+            (!value.is_empty()).then_some(VarAction::Set {
+                name: name.trim_start_matches('$'),
+                value,
+            })
+        }
+        "list" | "" if rest.is_empty() => Some(VarAction::List),
+        "unset" | "delete" if !rest.is_empty() && !rest.contains(char::is_whitespace) => {
+            Some(VarAction::Unset {
+                name: rest.trim_start_matches('$'),
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let variables = HashMap::from([
+            ("character".to_owned(), "a red-haired knight".to_owned()),
+            ("place".to_owned(), "$character's castle".to_owned()),
+        ]);
+        assert_eq!(
+            expand("$character riding to $place", &variables),
+            "a red-haired knight riding to $character's castle"
+        );
+        assert_eq!(
+            expand("a $5 coin, $unknown", &variables),
+            "a $5 coin, $unknown"
+        );
+    }
+
+    #[test]
+    fn test_parse_action() {
+        assert_eq!(
+            parse_action(r#"set character "a red-haired knight""#),
+            Some(VarAction::Set {
+                name: "character",
+                value: "a red-haired knight"
+            })
+        );
+        assert_eq!(parse_action("set character"), None);
+        assert_eq!(parse_action("list"), Some(VarAction::List));
+        assert_eq!(
+            parse_action("unset $character"),
+            Some(VarAction::Unset { name: "character" })
+        );
+        assert_eq!(parse_action("unset"), None);
+    }
+
+    #[test]
+    fn test_is_valid_name() {
+        assert!(is_valid_name("character_2"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("2nd"));
+        assert!(!is_valid_name("red-knight"));
+        assert!(!is_valid_name(&"a".repeat(MAX_NAME_LENGTH + 1)));
+    }
+}
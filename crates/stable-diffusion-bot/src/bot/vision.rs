@@ -0,0 +1,237 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+use base64::{engine::general_purpose, Engine as _};
+use reqwest::Url;
+use sal_e_api::GeneratedImage;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{instrument, warn};
+
+/// Struct that represents the configuration for describing generated images with a
+/// vision-capable model served by an OpenAI-compatible API, such as the llama.cpp server.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VisionConfig {
+    /// Base URL of the API, e.g. `http://localhost:8080/v1`.
+    pub url: String,
+    /// Name of the model to request. Some servers ignore this.
+    pub model: Option<String>,
+    /// API key sent as a bearer token.
+    pub api_key: Option<String>,
+    /// Instruction sent along with the image.
+    #[serde(default = "default_prompt")]
+    pub prompt: String,
+    /// Maximum number of tokens in a description.
+    #[serde(default = "default_max_tokens")]
+    pub max_tokens: u32,
+    /// Maximum time to wait for a description, in seconds. Images are sent without a
+    /// description if it takes longer.
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Number of descriptions to keep in memory, so reruns of cached prompts don't request
+    /// them again.
+    #[serde(default = "default_cache_size")]
+    pub cache_size: usize,
+}
+
+fn default_prompt() -> String {
+    "Describe this image in one short sentence for use as alt text.".to_owned()
+}
+
+fn default_max_tokens() -> u32 {
+    60
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+fn default_cache_size() -> usize {
+    256
+}
+
+/// Descriptions keyed by a hash of the image data, evicted in insertion order.
+#[derive(Debug, Default)]
+struct DescriptionCache {
+    capacity: usize,
+    entries: HashMap<u64, String>,
+    order: VecDeque<u64>,
+}
+
+impl DescriptionCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    fn get(&self, key: u64) -> Option<String> {
+        self.entries.get(&key).cloned()
+    }
+
+    fn insert(&mut self, key: u64, description: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.insert(key, description).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// Client that requests short descriptions of generated images.
+#[derive(Debug, Clone)]
+pub(crate) struct ImageDescriber {
+    client: reqwest::Client,
+    endpoint: Url,
+    config: Arc<VisionConfig>,
+    cache: Arc<Mutex<DescriptionCache>>,
+}
+
+impl ImageDescriber {
+    /// Constructs a new `ImageDescriber` from its configuration, sending requests with `client`.
+    pub fn new(config: VisionConfig, client: reqwest::Client) -> anyhow::Result<Self> {
+        let endpoint = Url::parse(&format!(
+            "{}/chat/completions",
+            config.url.trim_end_matches('/')
+        ))
+        .context("Invalid vision API URL")?;
+        Ok(Self {
+            client,
+            endpoint,
+            cache: Arc::new(Mutex::new(DescriptionCache::new(config.cache_size))),
+            config: Arc::new(config),
+        })
+    }
+
+    /// Returns a short description of `image`, or `None` if it couldn't be described in time.
+    #[instrument(skip_all, fields(backend = "vision"))]
+    pub async fn describe(&self, image: &GeneratedImage) -> Option<String> {
+        let mut hasher = DefaultHasher::new();
+        image.data.hash(&mut hasher);
+        let key = hasher.finish();
+
+        if let Some(description) = self.cache.lock().unwrap().get(key) {
+            return Some(description);
+        }
+
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+        let description = match tokio::time::timeout(timeout, self.request(image)).await {
+            Ok(Ok(description)) => description,
+            Ok(Err(e)) => {
+                warn!("Failed to describe image: {:?}", e);
+                return None;
+            }
+            Err(_) => {
+                warn!("Timed out describing image after {:?}", timeout);
+                return None;
+            }
+        };
+
+        self.cache.lock().unwrap().insert(key, description.clone());
+        Some(description)
+    }
+
+    async fn request(&self, image: &GeneratedImage) -> anyhow::Result<String> {
+        let image_url = format!(
+            "data:{};base64,{}",
+            image.mime_type.unwrap_or("image/png"),
+            general_purpose::STANDARD.encode(&image.data)
+        );
+        let mut body = json!({
+            "max_tokens": self.config.max_tokens,
+            "messages": [{
+                "role": "user",
+                "content": [
+                    { "type": "text", "text": self.config.prompt },
+                    { "type": "image_url", "image_url": { "url": image_url } },
+                ],
+            }],
+        });
+        if let Some(model) = &self.config.model {
+            body["model"] = json!(model);
+        }
+
+        let mut request = self.client.post(self.endpoint.clone()).json(&body);
+        if let Some(api_key) = &self.config.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response: ChatCompletion = request
+            .send()
+            .await
+            .context("Failed to send request")?
+            .error_for_status()
+            .context("Request failed")?
+            .json()
+            .await
+            .context("Failed to parse response")?;
+
+        response
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message.content.trim().to_owned())
+            .filter(|content| !content.is_empty())
+            .ok_or_else(|| anyhow!("Response contained no description"))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatCompletion {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatChoice {
+    message: ChatMessage,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatMessage {
+    content: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_description_cache_eviction() {
+        let mut cache = DescriptionCache::new(2);
+        cache.insert(1, "a".to_owned());
+        cache.insert(2, "b".to_owned());
+        cache.insert(1, "c".to_owned());
+        assert_eq!(cache.get(1).as_deref(), Some("c"));
+        cache.insert(3, "d".to_owned());
+        assert_eq!(cache.get(1), None);
+        assert_eq!(cache.get(2).as_deref(), Some("b"));
+        assert_eq!(cache.get(3).as_deref(), Some("d"));
+    }
+
+    #[test]
+    fn test_description_cache_disabled() {
+        let mut cache = DescriptionCache::new(0);
+        cache.insert(1, "a".to_owned());
+        assert_eq!(cache.get(1), None);
+    }
+
+    #[test]
+    fn test_parse_chat_completion() {
+        let response: ChatCompletion = serde_json::from_str(
+            r#"{"id": "1", "choices": [{"index": 0, "message": {"role": "assistant", "content": " A cat. "}}]}"#,
+        )
+        .unwrap();
+        assert_eq!(response.choices[0].message.content, " A cat. ");
+    }
+}
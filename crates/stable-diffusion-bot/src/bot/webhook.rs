@@ -0,0 +1,201 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::Context as _;
+use base64::{engine::general_purpose, Engine as _};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use sal_e_api::Response;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use teloxide::types::{Message, MessageId};
+use tracing::{warn, Instrument as _};
+
+use super::{archive, TlsBackend};
+
+/// How long to wait for a webhook to respond.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Struct that represents a webhook that receives the results of generations in a chat, e.g.
+/// to feed them into a pipeline that posts them to a website.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WebhookConfig {
+    /// The chat whose results are posted.
+    pub chat_id: i64,
+    /// The URL the results are posted to, as JSON.
+    pub url: String,
+    /// Whether to include the images, base64 encoded. Otherwise only their metadata is posted.
+    #[serde(default)]
+    pub include_images: bool,
+    /// Headers sent with each request, e.g. for authentication.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+struct Webhook {
+    url: String,
+    include_images: bool,
+    headers: HeaderMap,
+}
+
+/// Posts the results of generations to the webhooks configured for their chats.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Webhooks {
+    client: reqwest::Client,
+    hooks: BTreeMap<i64, Vec<Webhook>>,
+}
+
+impl Webhooks {
+    pub fn new(configs: Vec<WebhookConfig>, tls: TlsBackend) -> anyhow::Result<Self> {
+        let mut hooks: BTreeMap<i64, Vec<Webhook>> = BTreeMap::new();
+        for config in configs {
+            let headers = config
+                .headers
+                .iter()
+                .map(|(name, value)| {
+                    let name = HeaderName::try_from(name.as_str())
+                        .with_context(|| format!("Invalid webhook header name: {name}"))?;
+                    let mut value = HeaderValue::try_from(value.as_str())
+                        .with_context(|| format!("Invalid value for webhook header {name}"))?;
+                    value.set_sensitive(true);
+                    Ok((name, value))
+                })
+                .collect::<anyhow::Result<_>>()?;
+            hooks.entry(config.chat_id).or_default().push(Webhook {
+                url: config.url,
+                include_images: config.include_images,
+                headers,
+            });
+        }
+        let client = tls
+            .configure(reqwest::Client::builder())?
+            .timeout(TIMEOUT)
+            .build()
+            .context("Failed to create webhook client")?;
+        Ok(Self { client, hooks })
+    }
+
+    /// Posts the result of a generation prompted by `msg` to the chat's webhooks, in the
+    /// background. Failures are logged, since the images have already been sent.
+    pub fn post(&self, msg: &Message, reply_message_ids: &[MessageId], resp: &Response) {
+        let Some(hooks) = self.hooks.get(&msg.chat.id.0) else {
+            return;
+        };
+        for hook in hooks {
+            let payload = payload(msg, reply_message_ids, resp, hook.include_images);
+            let request = self
+                .client
+                .post(&hook.url)
+                .headers(hook.headers.clone())
+                .json(&payload);
+            tokio::spawn(
+                async move {
+                    if let Err(e) = request
+                        .send()
+                        .await
+                        .and_then(|response| response.error_for_status())
+                    {
+                        warn!("Failed to post generation to webhook: {}", e);
+                    }
+                }
+                .in_current_span(),
+            );
+        }
+    }
+}
+
+/// Returns the JSON posted to webhooks for a generation.
+fn payload(
+    msg: &Message,
+    reply_message_ids: &[MessageId],
+    resp: &Response,
+    include_images: bool,
+) -> Value {
+    let mut payload = archive::params_json(resp);
+    payload.insert("chat_id".to_owned(), json!(msg.chat.id.0));
+    payload.insert(
+        "user_id".to_owned(),
+        json!(msg.from().map(|user| user.id.0)),
+    );
+    payload.insert("message_id".to_owned(), json!(msg.id.0));
+    payload.insert(
+        "reply_message_ids".to_owned(),
+        reply_message_ids.iter().map(|id| id.0).collect(),
+    );
+    payload.insert(
+        "images".to_owned(),
+        resp.images
+            .iter()
+            .map(|image| {
+                let size = image.dimensions();
+                let mut json = json!({
+                    "seed": image.seed,
+                    "width": size.map(|(width, _)| width),
+                    "height": size.map(|(_, height)| height),
+                    "mime_type": image.mime_type,
+                });
+                if include_images {
+                    json["data"] = general_purpose::STANDARD.encode(&image.data).into();
+                }
+                json
+            })
+            .collect(),
+    );
+    Value::Object(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use sal_e_api::{GeneratedImage, Txt2ImgParams};
+    use stable_diffusion_api::ImgInfo;
+
+    use super::*;
+
+    fn message() -> Message {
+        serde_json::from_value(json!({
+            "message_id": 5,
+            "date": 1675229140,
+            "chat": {"id": -100, "type": "supergroup", "title": "Group"},
+            "from": {"id": 1, "is_bot": false, "first_name": "User"},
+            "text": "a cat",
+        }))
+        .unwrap()
+    }
+
+    fn response() -> Response {
+        Response {
+            images: vec![GeneratedImage::new(vec![1, 2, 3]).with_seed(Some(42))],
+            params: Box::new(ImgInfo {
+                prompt: Some("a cat".to_owned()),
+                ..Default::default()
+            }),
+            gen_params: Box::<Txt2ImgParams>::default(),
+        }
+    }
+
+    #[test]
+    fn test_payload() {
+        let msg = message();
+        let json = payload(&msg, &[MessageId(6)], &response(), false);
+        assert_eq!(json["chat_id"], -100);
+        assert_eq!(json["user_id"], 1);
+        assert_eq!(json["message_id"], 5);
+        assert_eq!(json["reply_message_ids"], json!([6]));
+        assert_eq!(json["prompt"], "a cat");
+        assert_eq!(json["images"][0]["seed"], 42);
+        assert!(json["images"][0].get("data").is_none());
+
+        let json = payload(&msg, &[], &response(), true);
+        assert_eq!(json["images"][0]["data"], "AQID");
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let config = WebhookConfig {
+            chat_id: 1,
+            url: "http://localhost".to_owned(),
+            include_images: false,
+            headers: BTreeMap::from([("Bad Header".to_owned(), "value".to_owned())]),
+        };
+        assert!(Webhooks::new(vec![config], TlsBackend::default()).is_err());
+    }
+}
@@ -5,12 +5,19 @@ use figment::{
     Figment,
 };
 use serde::{Deserialize, Serialize};
-use stable_diffusion_api::{Img2ImgRequest, Txt2ImgRequest};
-use stable_diffusion_bot::{ApiType, ComfyUIConfig, StableDiffusionBotBuilder};
-use tracing::metadata::LevelFilter;
+use stable_diffusion_api::{Auth, Img2ImgRequest, Txt2ImgRequest};
+use stable_diffusion_bot::{
+    ApiType, BackendConfig, BlankCheckConfig, CaptionMode, CaptionTemplate, ComfyUIConfig,
+    DefaultSettings, GroupMode, GuestConfig, HttpConfig, InlineConfig, NegativePreset, PoolConfig,
+    ProgressConfig, PromptCleaningConfig, PromptPolicy, QuietHoursConfig, QuotaConfig,
+    RandomConfig, ReplyKeyboardConfig, RequestConfig, RetentionConfig, StableDiffusionBotBuilder,
+    Style, SupervisorConfig, TelegramWebhookConfig, TlsBackend, UnknownCommandMode, UpscaleConfig,
+    VisionConfig, WaitForBackendConfig, WebhookConfig, ZipConfig,
+};
+use tracing::{info, metadata::LevelFilter, warn};
 use tracing_subscriber::{prelude::*, EnvFilter};
 
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf};
 
 #[cfg(not(target_os = "linux"))]
 use anyhow::anyhow;
@@ -27,22 +34,133 @@ struct Args {
         default_value = "config.toml"
     )]
     config: Vec<PathBuf>,
+    /// Name of the configuration profile to apply on top of the base configuration
+    #[arg(short, long, env = "SD_TELEGRAM_PROFILE")]
+    profile: Option<String>,
     /// Output logs directly to systemd
     #[arg(long, default_value = "false")]
     log_to_systemd: bool,
+    /// Start even if another instance appears to be using the database
+    #[arg(long, default_value = "false")]
+    force: bool,
 }
 
 #[derive(Serialize, Deserialize, Default, Debug)]
 struct Config {
     api_key: String,
     allowed_users: Vec<i64>,
+    allowed_chats: Option<Vec<i64>>,
+    banned_users: Option<Vec<i64>>,
+    admin_users: Option<Vec<i64>>,
+    accounts: Option<BTreeMap<String, Vec<u64>>>,
     db_path: Option<String>,
+    compress_dialogues: Option<bool>,
     sd_api_url: String,
+    sd_api_user: Option<String>,
+    sd_api_password: Option<String>,
+    sd_api_token: Option<String>,
     api_type: Option<ApiType>,
     txt2img: Option<Txt2ImgRequest>,
     img2img: Option<Img2ImgRequest>,
+    users: Option<BTreeMap<String, DefaultSettings>>,
+    chats: Option<BTreeMap<String, DefaultSettings>>,
     allow_all_users: Option<bool>,
     comfyui: Option<ComfyUIConfig>,
+    http: Option<HttpConfig>,
+    telegram_webhook: Option<TelegramWebhookConfig>,
+    prompt_policy: Option<PromptPolicy>,
+    negative_presets: Option<Vec<NegativePreset>>,
+    styles: Option<Vec<Style>>,
+    telegram_api_url: Option<String>,
+    tls: Option<TlsBackend>,
+    requests: Option<RequestConfig>,
+    supervisor: Option<SupervisorConfig>,
+    vision: Option<VisionConfig>,
+    retention: Option<RetentionConfig>,
+    wait_for_backend: Option<WaitForBackendConfig>,
+    blank_check: Option<BlankCheckConfig>,
+    progress: Option<ProgressConfig>,
+    upscale: Option<UpscaleConfig>,
+    pool: Option<PoolConfig>,
+    unknown_commands: Option<UnknownCommandMode>,
+    captions: Option<CaptionMode>,
+    denoising_presets: Option<bool>,
+    size_presets_max_pixels: Option<u32>,
+    caption_template: Option<CaptionTemplate>,
+    group_mode: Option<GroupMode>,
+    prompt_cleaning: Option<PromptCleaningConfig>,
+    language: Option<String>,
+    translations_dir: Option<PathBuf>,
+    inline: Option<InlineConfig>,
+    zip: Option<ZipConfig>,
+    quiet_hours: Option<QuietHoursConfig>,
+    webhooks: Option<Vec<WebhookConfig>>,
+    quota: Option<QuotaConfig>,
+    reply_keyboard: Option<ReplyKeyboardConfig>,
+    random: Option<RandomConfig>,
+    guest: Option<GuestConfig>,
+    backends: Option<Vec<BackendConfig>>,
+}
+
+/// Loads the configuration from `paths`, applying the `[profile.<name>]` table on top of the
+/// base keys if a profile is selected. Environment variables override both.
+fn load_config(paths: &[PathBuf], profile: Option<&str>) -> anyhow::Result<Config> {
+    let mut figment = paths
+        .iter()
+        .fold(Figment::new(), |f, path| f.admerge(Toml::file(path)));
+
+    if let Some(profile) = profile {
+        let key = format!("profile.{profile}");
+        figment
+            .find_value(&key)
+            .with_context(|| format!("Profile {profile} not found in configuration"))?;
+        figment = figment.clone().merge(figment.focus(&key));
+    }
+
+    figment
+        .admerge(Env::prefixed("SD_TELEGRAM_").ignore(&["profile"]))
+        .extract()
+        .context("Invalid configuration")
+}
+
+/// Returns the credentials for the backend from either `sd_api_user` and `sd_api_password`, or
+/// `sd_api_token`.
+fn backend_auth(config: &Config) -> anyhow::Result<Option<Auth>> {
+    match (
+        &config.sd_api_user,
+        &config.sd_api_password,
+        &config.sd_api_token,
+    ) {
+        (None, None, None) => Ok(None),
+        (Some(username), Some(password), None) => Ok(Some(Auth::Basic {
+            username: username.clone(),
+            password: password.clone(),
+        })),
+        (None, None, Some(token)) => Ok(Some(Auth::Bearer {
+            token: token.clone(),
+        })),
+        _ => Err(anyhow::anyhow!(
+            "Set either both sd_api_user and sd_api_password, or only sd_api_token"
+        )),
+    }
+}
+
+/// Parses the ids that key the `[users.<id>]` or `[chats.<id>]` tables in `section`. TOML keys
+/// are always strings, so they can't be deserialized as ids directly.
+fn parse_ids(
+    section: &str,
+    defaults: Option<BTreeMap<String, DefaultSettings>>,
+) -> anyhow::Result<BTreeMap<i64, DefaultSettings>> {
+    defaults
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(id, settings)| {
+            let id = id
+                .parse()
+                .with_context(|| format!("Invalid id in [{section}.{id}]"))?;
+            Ok((id, settings))
+        })
+        .collect()
 }
 
 #[tokio::main]
@@ -50,23 +168,34 @@ async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
     let registry = tracing_subscriber::registry();
-    let layer = {
+    // Falls back to stdout if journald isn't running, e.g. in a container, so the same
+    // command line works everywhere. The reason is logged once logging is set up.
+    let (layer, journald_unavailable) = {
         #[cfg(target_os = "linux")]
-        if args.log_to_systemd && daemon::booted() {
-            tracing_journald::layer()
-                .context("tracing_journald layer")?
-                .boxed()
-        } else {
-            tracing_subscriber::fmt::layer()
-                .pretty()
-                .with_target(true)
-                .boxed()
+        match args.log_to_systemd.then(|| {
+            if daemon::booted() {
+                tracing_journald::layer().map_err(|e| e.to_string())
+            } else {
+                Err("systemd is not running".to_owned())
+            }
+        }) {
+            Some(Ok(layer)) => (layer.boxed(), None),
+            unavailable => (
+                tracing_subscriber::fmt::layer()
+                    .pretty()
+                    .with_target(true)
+                    .boxed(),
+                unavailable.and_then(Result::err),
+            ),
         }
         #[cfg(not(target_os = "linux"))]
         if args.log_to_systemd {
             return Err(anyhow!("Systemd logging is not supported on this platform"));
         } else {
-            tracing_subscriber::fmt::layer().pretty().with_target(true)
+            (
+                tracing_subscriber::fmt::layer().pretty().with_target(true),
+                None::<String>,
+            )
         }
     };
 
@@ -86,13 +215,19 @@ async fn main() -> anyhow::Result<()> {
         .with(layer)
         .init();
 
-    let config: Config = args
-        .config
-        .iter()
-        .fold(Figment::new(), |f, path| f.admerge(Toml::file(path)))
-        .admerge(Env::prefixed("SD_TELEGRAM_"))
-        .extract()
-        .context("Invalid configuration")?;
+    if let Some(reason) = journald_unavailable {
+        warn!("Logging to stdout instead of journald: {}", reason);
+    }
+    info!(
+        "Running on {}-{}",
+        std::env::consts::ARCH,
+        std::env::consts::OS
+    );
+
+    let config = load_config(&args.config, args.profile.as_deref())?;
+    let backend_auth = backend_auth(&config)?;
+    let user_defaults = parse_ids("users", config.users)?;
+    let chat_defaults = parse_ids("chats", config.chats)?;
 
     StableDiffusionBotBuilder::new(
         config.api_key,
@@ -101,10 +236,53 @@ async fn main() -> anyhow::Result<()> {
         config.api_type.unwrap_or_default(),
         config.allow_all_users.unwrap_or_default(),
     )
+    .allowed_chats(config.allowed_chats.unwrap_or_default())
+    .banned_users(config.banned_users.unwrap_or_default())
+    .admin_users(config.admin_users.unwrap_or_default())
+    .shared_accounts(config.accounts.unwrap_or_default())
     .db_path(config.db_path)
+    .force_db_lock(args.force)
+    .compress_dialogues(config.compress_dialogues.unwrap_or_default())
     .txt2img_defaults(config.txt2img.unwrap_or_default())
     .img2img_defaults(config.img2img.unwrap_or_default())
+    .user_defaults(user_defaults)
+    .chat_defaults(chat_defaults)
     .comfyui_config(config.comfyui.unwrap_or_default())
+    .http_config(config.http)
+    .telegram_webhook_config(config.telegram_webhook)
+    .prompt_policy(config.prompt_policy.unwrap_or_default())
+    .negative_presets(config.negative_presets.unwrap_or_default())
+    .styles(config.styles.unwrap_or_default())
+    .telegram_api_url(config.telegram_api_url)
+    .backend_auth(backend_auth)
+    .request_config(config.requests.unwrap_or_default())
+    .tls_backend(config.tls.unwrap_or_default())
+    .supervisor_config(config.supervisor.unwrap_or_default())
+    .vision_config(config.vision)
+    .retention_config(config.retention)
+    .wait_for_backend_config(config.wait_for_backend)
+    .blank_check_config(config.blank_check)
+    .progress_config(config.progress.unwrap_or_default())
+    .upscale_config(config.upscale.unwrap_or_default())
+    .pool_config(config.pool)
+    .unknown_command_mode(config.unknown_commands.unwrap_or_default())
+    .caption_mode(config.captions.unwrap_or_default())
+    .denoising_presets(config.denoising_presets.unwrap_or_default())
+    .size_presets_max_pixels(config.size_presets_max_pixels)
+    .caption_template(config.caption_template.unwrap_or_default())
+    .group_mode(config.group_mode.unwrap_or_default())
+    .prompt_cleaning_config(config.prompt_cleaning.unwrap_or_default())
+    .language(config.language)
+    .translations_dir(config.translations_dir)
+    .inline_config(config.inline)
+    .zip_config(config.zip)
+    .quiet_hours_config(config.quiet_hours)
+    .webhooks(config.webhooks.unwrap_or_default())
+    .quota_config(config.quota)
+    .reply_keyboard_config(config.reply_keyboard)
+    .random_config(config.random.unwrap_or_default())
+    .guest_config(config.guest)
+    .backends(config.backends.unwrap_or_default())
     .build()
     .await
     .context("Failed to build Stable Diffusion Bot")?